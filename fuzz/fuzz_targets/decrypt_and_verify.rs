@@ -0,0 +1,103 @@
+//! Feeds authentic ciphertexts of malformed (not necessarily padded) plaintexts,
+//! tampered ciphertexts, and arbitrary bytes to `decrypt_and_verify()`.
+//!
+//! Run with `cargo +nightly fuzz run decrypt_and_verify` from the repository root.
+
+#![no_main]
+
+use chrono::DateTime;
+use block_padding::{RawPadding, Iso7816};
+use libfuzzer_sys::{fuzz_target, arbitrary::{self, Arbitrary}};
+use steelsafe::crypto::{EncryptionInput, DecryptionInput, KdfParams, CipherId};
+use steelsafe::fields::{Label, AccountName, PlaintextSecret, MasterPassword};
+use steelsafe::error::Error;
+
+
+/// The KDF is not what's being fuzzed, so it's made as cheap as possible.
+const FAST_KDF: KdfParams = KdfParams::Argon2id { version: 0x13, m_cost: 8, t_cost: 1, p_cost: 1 };
+
+const PASSWORD: &str = "fuzz";
+
+#[derive(Debug, Arbitrary)]
+struct Input<'a> {
+    /// Encrypted as-is, so the padding is usually malformed.
+    plaintext: &'a [u8],
+    label: &'a str,
+    account: Option<&'a str>,
+    timestamp: i64,
+    nanos: u32,
+    tampering: Tampering<'a>,
+}
+
+#[derive(Debug, Arbitrary)]
+enum Tampering<'a> {
+    None,
+    /// Flips the bit at this index (modulo the length of the ciphertext).
+    FlipBit(usize),
+    /// Truncates the ciphertext to this length (modulo its length).
+    Truncate(usize),
+    /// Replaces the ciphertext with arbitrary bytes.
+    Replace(&'a [u8]),
+}
+
+fuzz_target!(|input: Input<'_>| {
+    let Some(last_modified_at) = DateTime::from_timestamp(input.timestamp, input.nanos) else {
+        return;
+    };
+    let plaintext_secret = PlaintextSecret::new(input.plaintext);
+    let label = Label::verbatim(input.label);
+    let account = input.account.map(AccountName::verbatim);
+    let password = MasterPassword::new(PASSWORD).expect("not empty");
+    let encryption_input = EncryptionInput {
+        plaintext_secret: &plaintext_secret,
+        label: &label,
+        account: account.as_ref(),
+        last_modified_at,
+        pepper: None,
+    };
+    let output = encryption_input
+        .encrypt_without_padding(&password, FAST_KDF, CipherId::CURRENT)
+        .expect("encryption failed");
+
+    let mut encrypted_secret = output.encrypted_secret.clone();
+    let tampered = match input.tampering {
+        Tampering::None => false,
+        Tampering::FlipBit(index) => {
+            let index = index % (encrypted_secret.len() * 8);
+            encrypted_secret[index / 8] ^= 1 << (index % 8);
+            true
+        }
+        Tampering::Truncate(len) => {
+            encrypted_secret.truncate(len % encrypted_secret.len());
+            true
+        }
+        Tampering::Replace(bytes) => {
+            let tampered = bytes != encrypted_secret.as_slice();
+            encrypted_secret = bytes.to_vec();
+            tampered
+        }
+    };
+
+    let decryption_input = DecryptionInput {
+        encrypted_secret: &encrypted_secret,
+        kdf_salt: output.kdf_salt,
+        auth_nonce: output.auth_nonce,
+        label: input.label,
+        account: input.account,
+        last_modified_at,
+        peppered: output.peppered,
+        kdf: output.kdf,
+        cipher: output.cipher,
+        pepper: None,
+    };
+    let result = decryption_input.decrypt_and_verify(PASSWORD.as_bytes());
+
+    if tampered {
+        assert!(matches!(result, Err(Error::XChaCha20Poly1305(_))), "{result:?}");
+    } else {
+        match Iso7816::raw_unpad(input.plaintext) {
+            Ok(unpadded) => assert_eq!(result.expect("decryption failed").as_slice(), unpadded),
+            Err(_) => assert!(matches!(result, Err(Error::Unpad(_))), "{result:?}"),
+        }
+    }
+});
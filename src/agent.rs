@@ -0,0 +1,356 @@
+//! A background process that keeps an encryption password in memory for a
+//! limited time, and decrypts items on behalf of the CLI and the TUI, so that
+//! subsequent invocations don't each need to prompt for the password.
+//!
+//! The agent listens on a Unix domain socket that only its owner can access.
+//! Clients send a single JSON request per connection, terminated by a newline,
+//! and the agent answers with a single JSON response. The password is zeroized
+//! when it expires, upon an explicit `steelsafe lock`, and when the agent exits.
+
+use std::{fs, mem};
+use std::io::{BufRead, BufReader, Read, Write, ErrorKind};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+use serde::{Serialize, Deserialize};
+use zeroize::{Zeroize, Zeroizing};
+use crate::{
+    crypto::Pepper,
+    db::Database,
+    memlock::SecretBytes,
+    perms,
+    error::{Error, Result, ResultExt},
+};
+
+
+/// How long the password is kept if neither the config nor the client specifies it.
+pub const DEFAULT_TTL: Duration = Duration::from_secs(15 * 60);
+
+/// Longer lifetimes requested by clients are shortened to this. A password
+/// kept for more than a week is hardly different from one that's never
+/// forgotten, and this keeps the expiry instant representable.
+pub const MAX_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// How often the agent checks for new connections and for the expiry of the password.
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Clients that don't send a complete request in time are disconnected,
+/// so that they can't block the agent indefinitely.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Requests are tiny (a label or a password), so a longer line is either
+/// garbage or an attempt at making the agent allocate unbounded memory.
+const MAX_REQUEST_BYTES: u64 = 64 * 1024;
+
+/// A message sent by a client to the agent.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Request {
+    /// Store the password for `ttl_secs` seconds, replacing any previous one.
+    Unlock { password: String, ttl_secs: u64 },
+    /// Forget the password immediately.
+    Lock,
+    /// Decrypt the secret of the item with the given label.
+    Decrypt { label: String },
+}
+
+impl Drop for Request {
+    fn drop(&mut self) {
+        if let Request::Unlock { password, .. } = self {
+            password.zeroize();
+        }
+    }
+}
+
+/// The answer of the agent to a request.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum Response {
+    Ok,
+    Secret { secret: String },
+    Locked,
+    Error { message: String },
+}
+
+impl Drop for Response {
+    fn drop(&mut self) {
+        if let Response::Secret { secret } = self {
+            secret.zeroize();
+        }
+    }
+}
+
+/// The agent process' state.
+struct Agent<'a> {
+    db: &'a Database,
+    pepper: Option<Pepper>,
+    password: Option<(SecretBytes, Instant)>,
+}
+
+impl Agent<'_> {
+    fn handle(&mut self, request: &Request) -> Response {
+        self.expire(Instant::now());
+
+        match request {
+            Request::Unlock { password, ttl_secs } => {
+                let ttl = Duration::from_secs(*ttl_secs).min(MAX_TTL);
+                let Some(expiry) = Instant::now().checked_add(ttl) else {
+                    return Response::Error { message: format!("Can't keep the password for {ttl_secs} seconds") };
+                };
+                let mut buf = SecretBytes::with_capacity(password.len());
+                buf.as_mut_vec().extend_from_slice(password.as_bytes());
+                self.password = Some((buf, expiry));
+                Response::Ok
+            }
+            Request::Lock => {
+                self.password = None;
+                Response::Ok
+            }
+            Request::Decrypt { label } => match self.decrypt(label) {
+                Ok(Some(secret)) => Response::Secret { secret },
+                Ok(None) => Response::Locked,
+                Err(error) => Response::Error { message: error.to_string() },
+            },
+        }
+    }
+
+    fn decrypt(&self, label: &str) -> Result<Option<String>> {
+        let Some((password, _)) = self.password.as_ref() else {
+            return Ok(None);
+        };
+        let item = self.db.item_by_label(label)?.ok_or_else(|| Error::ItemNotFound(label.into()))?;
+
+        // the password of such items must be typed every time, as if the agent were locked
+        if item.reprompt {
+            return Ok(None);
+        }
+
+        let secret = item.decryption_input(self.pepper.as_ref()).decrypt_and_verify(password)?;
+
+        Ok(Some(std::str::from_utf8(&secret)?.to_owned()))
+    }
+
+    /// Drops (and thus zeroizes) the password if it has expired.
+    fn expire(&mut self, now: Instant) {
+        if self.password.as_ref().is_some_and(|&(_, expires_at)| expires_at <= now) {
+            self.password = None;
+        }
+    }
+}
+
+/// Runs the agent in the foreground until it is killed.
+pub fn serve(db: &Database, pepper: Option<Pepper>, socket_path: &Path) -> Result<()> {
+    // A stale socket of a previous agent would make binding fail. If another agent
+    // is still running, then refuse to start, instead of stealing its socket.
+    if UnixStream::connect(socket_path).is_ok() {
+        return Err(Error::AgentAlreadyRunning);
+    }
+    match fs::remove_file(socket_path) {
+        Ok(()) => {}
+        Err(error) if error.kind() == ErrorKind::NotFound => {}
+        Err(error) => return Err(Error::context(error, "Can't remove stale agent socket")),
+    }
+
+    let listener = bind_private(socket_path)?;
+    listener.set_nonblocking(true)?;
+
+    let mut agent = Agent { db, pepper, password: None };
+
+    loop {
+        agent.expire(Instant::now());
+
+        match listener.accept() {
+            Ok((stream, _addr)) => {
+                // a misbehaving client must not bring down the agent
+                let _ = serve_client(&mut agent, stream);
+            }
+            Err(error) if error.kind() == ErrorKind::WouldBlock => {
+                thread::sleep(POLL_INTERVAL);
+            }
+            Err(error) => return Err(error.into()),
+        }
+    }
+}
+
+/// Binds the socket in a fresh directory that only the owner can enter, restricts
+/// its permissions there, and only then moves it to `socket_path`. Binding it at its
+/// final path directly would leave it accessible to others (according to the umask)
+/// until its permissions are changed.
+fn bind_private(socket_path: &Path) -> Result<UnixListener> {
+    let parent = match socket_path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let staging_dir = perms::create_temp_dir_in(parent, ".steelsafe-agent")
+        .context("Can't create agent socket")?;
+    let staging_path = staging_dir.join("agent.sock");
+
+    let result = UnixListener::bind(&staging_path)
+        .context("Can't create agent socket")
+        .and_then(|listener| {
+            fs::set_permissions(&staging_path, fs::Permissions::from_mode(0o600))?;
+            fs::rename(&staging_path, socket_path).context("Can't create agent socket")?;
+            Ok(listener)
+        });
+
+    // the socket has been moved out on success, and is useless on failure
+    let _ = fs::remove_dir_all(&staging_dir);
+
+    result
+}
+
+fn serve_client(agent: &mut Agent<'_>, stream: UnixStream) -> Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+
+    let mut line = Zeroizing::new(String::new());
+    BufReader::new((&stream).take(MAX_REQUEST_BYTES)).read_line(&mut line)?;
+
+    let response = match serde_json::from_str::<Request>(&line) {
+        Ok(request) => agent.handle(&request),
+        Err(error) => Response::Error { message: error.to_string() },
+    };
+
+    send(&stream, &response)
+}
+
+/// Sends a request to the agent, and waits for its response.
+pub fn request(socket_path: &Path, request: &Request) -> Result<Response> {
+    let stream = UnixStream::connect(socket_path).map_err(|error| {
+        if [ErrorKind::NotFound, ErrorKind::ConnectionRefused].contains(&error.kind()) {
+            Error::AgentNotRunning
+        } else {
+            Error::context(error, "Can't connect to agent")
+        }
+    })?;
+    send(&stream, request)?;
+
+    let mut line = Zeroizing::new(String::new());
+    BufReader::new(&stream).read_line(&mut line)?;
+
+    serde_json::from_str(&line).map_err(Into::into)
+}
+
+/// Asks the agent to decrypt the secret of an item. Returns `None` if the agent
+/// isn't running, if it's locked, if the item is marked for always asking for
+/// the password, or if it can't decrypt the item (e.g., because the item has a
+/// different password), in which case the caller should fall back to asking for
+/// the password.
+pub fn try_decrypt(socket_path: &Path, label: &str) -> Option<Zeroizing<String>> {
+    let request = Request::Decrypt { label: label.into() };
+    let mut response = self::request(socket_path, &request).ok()?;
+
+    match &mut response {
+        Response::Secret { secret } => Some(Zeroizing::new(mem::take(secret))),
+        _ => None,
+    }
+}
+
+fn send<T: Serialize>(mut stream: &UnixStream, message: &T) -> Result<()> {
+    let mut bytes = Zeroizing::new(serde_json::to_vec(message)?);
+    bytes.push(b'\n');
+    stream.write_all(&bytes)?;
+    stream.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::net::UnixStream;
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use crate::config::DatabaseOptions;
+    use crate::db::Database;
+    use crate::error::Result;
+    use crate::perms;
+    use super::{Agent, Request, Response, MAX_REQUEST_BYTES, MAX_TTL, bind_private, serve_client};
+
+
+    #[test]
+    fn password_expires_and_can_be_locked() -> Result<()> {
+        let db = Database::open(":memory:", &DatabaseOptions::default())?;
+        let mut agent = Agent { db: &db, pepper: None, password: None };
+        let decrypt = Request::Decrypt { label: "anything".into() };
+
+        assert!(matches!(agent.handle(&decrypt), Response::Locked));
+
+        let unlock = Request::Unlock { password: "hunter2".into(), ttl_secs: 60 };
+        assert!(matches!(agent.handle(&unlock), Response::Ok));
+        assert!(agent.password.is_some());
+
+        agent.expire(Instant::now() + Duration::from_secs(61));
+        assert!(agent.password.is_none());
+        assert!(matches!(agent.handle(&decrypt), Response::Locked));
+
+        agent.handle(&unlock);
+        assert!(matches!(agent.handle(&Request::Lock), Response::Ok));
+        assert!(agent.password.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn overlong_ttl_is_clamped() -> Result<()> {
+        let db = Database::open(":memory:", &DatabaseOptions::default())?;
+        let mut agent = Agent { db: &db, pepper: None, password: None };
+
+        let unlock = Request::Unlock { password: "hunter2".into(), ttl_secs: u64::MAX };
+        assert!(matches!(agent.handle(&unlock), Response::Ok));
+
+        agent.expire(Instant::now() + MAX_TTL - Duration::from_secs(60));
+        assert!(agent.password.is_some());
+
+        agent.expire(Instant::now() + MAX_TTL + Duration::from_secs(1));
+        assert!(agent.password.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn socket_is_private_from_the_start() -> Result<()> {
+        let dir = perms::create_temp_dir("steelsafe-agent-test")?;
+        let socket_path = dir.join("agent.sock");
+
+        let _listener = bind_private(&socket_path)?;
+        let mode = fs::metadata(&socket_path)?.permissions().mode() & 0o777;
+        assert_eq!(mode, 0o600);
+
+        // the staging directory is gone, only the socket is left
+        let entries = fs::read_dir(&dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(entries, [socket_path]);
+
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn oversized_request_is_rejected() -> Result<()> {
+        let db = Database::open(":memory:", &DatabaseOptions::default())?;
+        let mut agent = Agent { db: &db, pepper: None, password: None };
+        let (client, server) = UnixStream::pair()?;
+
+        let writer = thread::spawn({
+            let mut client = client.try_clone()?;
+            move || {
+                // the agent stops reading after the limit, so the rest can't be written
+                let _ = client.write_all(&vec![b'x'; 2 * MAX_REQUEST_BYTES as usize]);
+            }
+        });
+
+        serve_client(&mut agent, server)?;
+
+        let mut line = String::new();
+        BufReader::new(&client).read_line(&mut line)?;
+        assert!(matches!(serde_json::from_str(&line)?, Response::Error { .. }));
+
+        writer.join().unwrap();
+        Ok(())
+    }
+}
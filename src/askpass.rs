@@ -0,0 +1,136 @@
+//! Asking for the encryption (master) password.
+//!
+//! By default, the password is read from the terminal without echo. If
+//! `password_command` is configured, its standard output is used instead,
+//! which allows integrating with e.g. `pinentry`, a hardware token helper,
+//! or the keyring of the operating system.
+
+use std::io::Read;
+use std::process::{Command, Stdio};
+use serde::Deserialize;
+use zeroize::Zeroizing;
+use crate::interrupt;
+use crate::error::{Error, Result, ResultExt};
+
+
+/// An external program printing the password to its standard output.
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize)]
+pub struct PasswordCommand {
+    /// The program and its arguments, e.g. `["secret-tool", "lookup", "app", "steelsafe"]`.
+    pub command: Vec<String>,
+    /// Whether the TUI runs the command instead of showing the password entry
+    /// panel. The command has no access to the terminal in this case, so it
+    /// should be a graphical prompt or require no interaction at all.
+    #[serde(default)]
+    pub tui: bool,
+}
+
+impl PasswordCommand {
+    /// Runs the command, and returns its output without the trailing newline.
+    ///
+    /// `prompt` and `label` (the label of the item being decrypted, if any) are
+    /// passed in the `STEELSAFE_PROMPT` and `STEELSAFE_ITEM` environment variables.
+    /// If `interactive`, the command can use the terminal through its standard input
+    /// and standard error; otherwise, these are redirected to the null device. Even
+    /// if our own standard input is a pipe, the command reads the controlling terminal.
+    pub fn run(
+        &self,
+        prompt: &str,
+        label: Option<&str>,
+        interactive: bool,
+    ) -> Result<Zeroizing<String>> {
+        let (program, args) = self.command
+            .split_first()
+            .ok_or_else(|| Error::PasswordCommand("`password_command.command` is empty".into()))?;
+
+        let mut command = Command::new(program);
+
+        command
+            .args(args)
+            .env("STEELSAFE_PROMPT", prompt)
+            .stdin(if interactive { terminal_input() } else { Stdio::null() })
+            .stdout(Stdio::piped())
+            .stderr(if interactive { Stdio::inherit() } else { Stdio::null() });
+
+        if let Some(label) = label {
+            command.env("STEELSAFE_ITEM", label);
+        }
+
+        let mut child = command.spawn().context(format!("Can't run password command {program:?}"))?;
+        let mut output = Zeroizing::new(Vec::new());
+
+        if let Some(mut stdout) = child.stdout.take() {
+            stdout.read_to_end(&mut output)?;
+        }
+
+        let status = child.wait()?;
+
+        if !status.success() {
+            return Err(Error::PasswordCommand(format!("{program:?} exited unsuccessfully ({status})")));
+        }
+
+        parse_output(&output)
+    }
+}
+
+/// Asks for a password on the terminal, or runs `command` if it's configured.
+pub fn ask(
+    command: Option<&PasswordCommand>,
+    prompt: &str,
+    label: Option<&str>,
+) -> Result<Zeroizing<String>> {
+    let password = match command {
+        Some(command) => command.run(prompt, label, true)?,
+        None => Zeroizing::new(rpassword::prompt_password(prompt)?),
+    };
+
+    // Ctrl+C doesn't interrupt the prompt itself, but it does cancel the command
+    interrupt::check()?;
+
+    Ok(password)
+}
+
+/// The controlling terminal, falling back to our own standard input if there is none.
+#[cfg(unix)]
+fn terminal_input() -> Stdio {
+    std::fs::File::open("/dev/tty").map_or_else(|_| Stdio::inherit(), Stdio::from)
+}
+
+#[cfg(not(unix))]
+fn terminal_input() -> Stdio {
+    Stdio::inherit()
+}
+
+/// Removes a single trailing line break, which most programs print, but which
+/// is never part of the password. Other whitespace is kept.
+fn parse_output(output: &[u8]) -> Result<Zeroizing<String>> {
+    let output = output.strip_suffix(b"\n").unwrap_or(output);
+    let output = output.strip_suffix(b"\r").unwrap_or(output);
+
+    if output.is_empty() {
+        return Err(Error::EncryptionPasswordRequired);
+    }
+
+    Ok(Zeroizing::new(std::str::from_utf8(output)?.to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Result;
+    use super::parse_output;
+
+
+    #[test]
+    fn trailing_newline_is_removed() -> Result<()> {
+        assert_eq!(parse_output(b"hunter2\n")?.as_str(), "hunter2");
+        assert_eq!(parse_output(b"hunter2\r\n")?.as_str(), "hunter2");
+        assert_eq!(parse_output(b"hunter2")?.as_str(), "hunter2");
+        assert_eq!(parse_output(b" two lines \n\n")?.as_str(), " two lines \n");
+
+        assert!(parse_output(b"\n").is_err());
+        assert!(parse_output(b"").is_err());
+        assert!(parse_output(b"\xff\n").is_err());
+
+        Ok(())
+    }
+}
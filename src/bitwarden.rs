@@ -0,0 +1,332 @@
+//! Converting the unencrypted JSON export of Bitwarden into items.
+//!
+//! Steelsafe items only have a label, an account name and a secret, so a
+//! Bitwarden login may become up to three items:
+//!
+//! * the password, labelled with the name of the login, with its user name
+//!   as the account, and its first URI as the URL;
+//! * the TOTP seed, labelled `<name> (TOTP)`, as an `otpauth://` URI (a bare
+//!   Base32 seed is turned into one), which the TUI recognizes;
+//! * the notes and the URIs, labelled `<name> (notes)`, tagged `note`.
+//!
+//! Secure notes become notes, and the folder of an item becomes a tag. Cards
+//! and identities are not supported; such records are skipped and reported,
+//! along with any other record that can't be converted, without failing the
+//! rest of the import.
+
+use serde::Deserialize;
+use zeroize::{Zeroize, Zeroizing};
+use crate::{
+    db::NOTE_TAG,
+    otp::{self, OtpAuthUri},
+    percent,
+    migrate::{ItemKind, ConvertedItem, SkippedRecord, Conversion},
+    error::{Error, Result},
+};
+
+
+/// Bitwarden's numeric item types.
+const TYPE_LOGIN: u8 = 1;
+const TYPE_SECURE_NOTE: u8 = 2;
+const TYPE_CARD: u8 = 3;
+const TYPE_IDENTITY: u8 = 4;
+
+/// The top level of an export file.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Export {
+    #[serde(default)]
+    encrypted: bool,
+    #[serde(default)]
+    folders: Vec<Folder>,
+    #[serde(default)]
+    items: Vec<Record>,
+}
+
+#[derive(Deserialize)]
+struct Folder {
+    id: String,
+    name: String,
+}
+
+/// An item of the export, named so to avoid confusion with steelsafe items.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Record {
+    #[serde(rename = "type")]
+    kind: u8,
+    name: String,
+    #[serde(default)]
+    notes: Option<String>,
+    #[serde(default)]
+    folder_id: Option<String>,
+    #[serde(default)]
+    login: Option<Login>,
+}
+
+impl Drop for Record {
+    fn drop(&mut self) {
+        if let Some(notes) = self.notes.as_mut() {
+            notes.zeroize();
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Login {
+    #[serde(default)]
+    username: Option<String>,
+    #[serde(default)]
+    password: Option<String>,
+    #[serde(default)]
+    totp: Option<String>,
+    #[serde(default)]
+    uris: Option<Vec<LoginUri>>,
+}
+
+impl Drop for Login {
+    fn drop(&mut self) {
+        if let Some(password) = self.password.as_mut() {
+            password.zeroize();
+        }
+        if let Some(totp) = self.totp.as_mut() {
+            totp.zeroize();
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct LoginUri {
+    #[serde(default)]
+    uri: Option<String>,
+}
+
+/// Converts the records of an unencrypted Bitwarden JSON export. Only a
+/// malformed or encrypted file is an error; records that can't be converted
+/// are collected in `Conversion::skipped`.
+pub fn convert(text: &str) -> Result<Conversion> {
+    // a struct would also be deserialized from an array, but an export is always an object
+    let export: Export = serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(text)
+        .and_then(|object| serde_json::from_value(object.into()))
+        .map_err(|error| Error::InvalidBitwardenExport(error.to_string()))?;
+
+    if export.encrypted {
+        return Err(Error::InvalidBitwardenExport(String::from(
+            "encrypted exports are not supported; export as unencrypted JSON",
+        )));
+    }
+
+    let mut conversion = Conversion::default();
+
+    for record in &export.items {
+        let folder = record
+            .folder_id
+            .as_ref()
+            .and_then(|id| export.folders.iter().find(|folder| &folder.id == id))
+            .map(|folder| folder.name.trim())
+            .filter(|name| !name.is_empty() && !name.contains('\n'));
+
+        match convert_record(record, folder) {
+            Ok(items) => conversion.items.extend(items),
+            Err(reason) => conversion.skipped.push(SkippedRecord {
+                name: record.name.clone(),
+                reason,
+            }),
+        }
+    }
+
+    Ok(conversion)
+}
+
+fn convert_record(record: &Record, folder: Option<&str>) -> Result<Vec<ConvertedItem>, String> {
+    let name = record.name.trim();
+    let notes = record.notes.as_deref().map(str::trim).filter(|notes| !notes.is_empty());
+    let tags: Vec<String> = folder.into_iter().map(String::from).collect();
+    let note_tags = || {
+        let mut tags = tags.clone();
+        tags.push(NOTE_TAG.into());
+        tags
+    };
+
+    if name.is_empty() {
+        return Err(String::from("it has no name"));
+    }
+
+    match record.kind {
+        TYPE_LOGIN => {}
+        TYPE_SECURE_NOTE => {
+            let text = notes.ok_or_else(|| String::from("the note is empty"))?;
+
+            return Ok(vec![ConvertedItem {
+                kind: ItemKind::Note,
+                label: name.into(),
+                account: None,
+                secret: Zeroizing::new(text.into()),
+                tags: note_tags(),
+                url: None,
+            }]);
+        }
+        TYPE_CARD => return Err(String::from("cards are not supported")),
+        TYPE_IDENTITY => return Err(String::from("identities are not supported")),
+        kind => return Err(format!("unknown item type {kind}")),
+    }
+
+    let login = record.login.as_ref().ok_or_else(|| String::from("the login has no data"))?;
+    let username = login.username.as_deref().map(str::trim).filter(|name| !name.is_empty());
+    let password = login.password.as_deref().filter(|password| !password.is_empty());
+    let totp = login.totp.as_deref().map(str::trim).filter(|totp| !totp.is_empty());
+    let uris: Vec<&str> = login
+        .uris
+        .iter()
+        .flatten()
+        .filter_map(|uri| uri.uri.as_deref().map(str::trim))
+        .filter(|uri| !uri.is_empty())
+        .collect();
+
+    if password.is_none() && totp.is_none() {
+        return Err(String::from("the login has neither a password nor a TOTP seed"));
+    }
+
+    let mut items = Vec::new();
+
+    if let Some(password) = password {
+        items.push(ConvertedItem {
+            kind: ItemKind::Password,
+            label: name.into(),
+            account: username.map(String::from),
+            secret: Zeroizing::new(password.into()),
+            tags: tags.clone(),
+            url: uris.first().map(|uri| String::from(*uri)),
+        });
+    }
+
+    if let Some(totp) = totp {
+        let uri = totp_uri(totp, name, username).map_err(|error| error.to_string())?;
+
+        items.push(ConvertedItem {
+            kind: ItemKind::Totp,
+            label: format!("{name} (TOTP)"),
+            account: username.map(String::from),
+            secret: uri,
+            tags: tags.clone(),
+            url: None,
+        });
+    }
+
+    if notes.is_some() || !uris.is_empty() {
+        let mut text = Zeroizing::new(String::new());
+
+        for uri in &uris {
+            text.push_str(uri);
+            text.push('\n');
+        }
+        if let Some(notes) = notes {
+            if !uris.is_empty() {
+                text.push('\n');
+            }
+            text.push_str(notes);
+        }
+
+        items.push(ConvertedItem {
+            kind: ItemKind::Note,
+            label: format!("{name} (notes)"),
+            account: None,
+            secret: Zeroizing::new(text.trim_end().into()),
+            tags: note_tags(),
+            url: None,
+        });
+    }
+
+    Ok(items)
+}
+
+/// Bitwarden stores either a complete `otpauth://` URI or just the Base32 seed,
+/// in which case the URI is made up with the default parameters.
+fn totp_uri(totp: &str, name: &str, username: Option<&str>) -> Result<Zeroizing<String>> {
+    let uri = if OtpAuthUri::is_otpauth_uri(totp) {
+        Zeroizing::new(totp.to_owned())
+    } else if totp.contains("://") {
+        return Err(Error::InvalidOtpAuthUri(String::from("only otpauth:// URIs are supported")));
+    } else {
+        let account = username.unwrap_or(name);
+        Zeroizing::new(format!(
+            "{}totp/{}:{}?secret={}&issuer={}",
+            otp::SCHEME,
+            percent::encode(name),
+            percent::encode(account),
+            percent::encode(totp),
+            percent::encode(name),
+        ))
+    };
+
+    // only validate it; the URI is stored as it is
+    OtpAuthUri::parse(&uri)?;
+
+    Ok(uri)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::otp::OtpAuthUri;
+    use crate::migrate::ItemKind;
+    use crate::error::{Error, Result};
+    use super::convert;
+
+
+    const EXPORT: &str = r#"{
+        "encrypted": false,
+        "folders": [{ "id": "f1", "name": "Work" }],
+        "items": [
+            {
+                "type": 1, "name": "GitHub", "folderId": "f1", "notes": "Recovery email: alt@example.com",
+                "login": {
+                    "username": "octocat", "password": "hunter2", "totp": "JBSW Y3DP EHPK 3PXP",
+                    "uris": [{ "match": null, "uri": "https://github.com/login" }]
+                }
+            },
+            {
+                "type": 1, "name": "Bank", "folderId": null, "notes": null,
+                "login": { "username": null, "password": "s3cr3t", "totp": null, "uris": [] }
+            },
+            { "type": 2, "name": "Wifi", "notes": "password: correct horse", "secureNote": { "type": 0 } },
+            { "type": 3, "name": "Visa", "card": { "number": "4111111111111111" } },
+            { "type": 1, "name": "Empty", "login": { "username": "nobody", "uris": [] } }
+        ]
+    }"#;
+
+    #[test]
+    fn logins_become_items_and_unsupported_records_are_reported() -> Result<()> {
+        let conversion = convert(EXPORT)?;
+        let labels: Vec<&str> = conversion.items.iter().map(|item| item.label.as_str()).collect();
+        let kinds: Vec<ItemKind> = conversion.items.iter().map(|item| item.kind).collect();
+
+        assert_eq!(labels, ["GitHub", "GitHub (TOTP)", "GitHub (notes)", "Bank", "Wifi"]);
+        assert_eq!(kinds, [ItemKind::Password, ItemKind::Totp, ItemKind::Note, ItemKind::Password, ItemKind::Note]);
+
+        let github = &conversion.items[0];
+        assert_eq!(github.account.as_deref(), Some("octocat"));
+        assert_eq!(github.secret.as_str(), "hunter2");
+        assert_eq!(github.tags, ["Work"]);
+
+        let totp = OtpAuthUri::parse(&conversion.items[1].secret)?;
+        assert_eq!(totp.secret, "JBSWY3DPEHPK3PXP");
+        assert_eq!(totp.issuer.as_deref(), Some("GitHub"));
+        assert_eq!(totp.account, "octocat");
+
+        let notes = &conversion.items[2];
+        assert_eq!(notes.secret.as_str(), "https://github.com/login\n\nRecovery email: alt@example.com");
+        assert_eq!(notes.tags, ["Work", "note"]);
+
+        let skipped: Vec<&str> = conversion.skipped.iter().map(|record| record.name.as_str()).collect();
+        assert_eq!(skipped, ["Visa", "Empty"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn encrypted_exports_are_rejected() {
+        let result = convert(r#"{ "encrypted": true, "encKeyValidation_DO_NOT_EDIT": "..." }"#);
+        assert!(matches!(result, Err(Error::InvalidBitwardenExport(_))));
+        assert!(matches!(convert("[]"), Err(Error::InvalidBitwardenExport(_))));
+    }
+}
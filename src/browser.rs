@@ -0,0 +1,262 @@
+//! Importing the passwords saved in web browsers, as exported into CSV by
+//! Chrome (and other Chromium-based browsers), Firefox, and Safari.
+//!
+//! Columns are found by their names in the header line, in any order: `url`,
+//! `username` and `password` are required, while `note` (or `notes`) and
+//! `otpauth` (Safari) are used if present. Other columns, e.g. the `name` of
+//! Chrome, or the `httpRealm` of Firefox, are ignored.
+//!
+//! Browser exports are messy, so instead of the name the browser made up, the
+//! label is the registrable domain of the URL (e.g. `google.com` for
+//! `https://accounts.google.com/signin`), and the full URL is stored in the
+//! URL field of the item. If another account of the same site was already
+//! imported, the user name is appended to the label. Rows with the same user
+//! name and origin (scheme, host and port) are merged if their passwords are
+//! the same, and reported otherwise.
+//!
+//! Without a copy of the Public Suffix List, the registrable domain is only
+//! approximated: it's the last two labels of the host name, or the last three
+//! if the second to last one is a common second-level domain of a country
+//! (e.g. `co.uk` or `com.au`).
+
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use zeroize::Zeroizing;
+use crate::{
+    inventory,
+    otp::OtpAuthUri,
+    migrate::{ItemKind, ConvertedItem, SkippedRecord, Conversion},
+    error::{Error, Result},
+};
+
+
+/// Second-level domains under which countries register domains, e.g. `co.uk`.
+const SECOND_LEVEL_DOMAINS: [&str; 12] = [
+    "ac", "co", "com", "edu", "go", "gob", "gov", "mil", "ne", "net", "or", "org",
+];
+
+/// The parts of a URL needed for labelling and deduplication.
+#[derive(Clone, PartialEq, Eq, Debug)]
+struct Site {
+    /// The scheme, host and port, in lower case, e.g. `https://example.com:8443`.
+    origin: String,
+    /// The host name in lower case, without a trailing dot, or the IP address.
+    host: String,
+    /// `true` for web pages, `false` e.g. for Android apps (`android://...`).
+    is_web: bool,
+}
+
+impl Site {
+    fn parse(url: &str) -> Option<Self> {
+        let (scheme, rest) = url.split_once("://")?;
+        let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+        let host_port = authority.rsplit_once('@').map_or(authority, |(_, host_port)| host_port);
+        let host = match host_port.strip_prefix('[') {
+            Some(ipv6) => ipv6.split(']').next().unwrap_or_default(),
+            None => host_port.split(':').next().unwrap_or_default(),
+        };
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+
+        if scheme.is_empty() || host.is_empty() {
+            return None;
+        }
+
+        let scheme = scheme.to_ascii_lowercase();
+
+        Some(Site {
+            origin: format!("{scheme}://{}", host_port.to_ascii_lowercase()),
+            is_web: scheme == "http" || scheme == "https",
+            host,
+        })
+    }
+
+    /// The registrable domain (approximately eTLD+1) of web pages, and the
+    /// whole host (e.g. the package name of an Android app) otherwise.
+    fn label(&self) -> String {
+        let host = self.host.strip_prefix("www.").unwrap_or(&self.host);
+
+        if !self.is_web || host.parse::<IpAddr>().is_ok() {
+            return host.into();
+        }
+
+        let labels: Vec<&str> = host.split('.').collect();
+        let count = match labels.as_slice() {
+            [.., second, top] if top.len() == 2 && SECOND_LEVEL_DOMAINS.contains(second) => 3,
+            _ => 2,
+        };
+
+        labels[labels.len().saturating_sub(count)..].join(".")
+    }
+}
+
+/// Converts the rows of a CSV export of saved passwords. Only a malformed file
+/// is an error; rows that can't be converted are collected in `Conversion::skipped`.
+pub fn convert(text: &str) -> Result<Conversion> {
+    let records = Zeroizing::new(inventory::parse_records(text).map_err(|error| match error {
+        Error::InvalidInventory(reason) => Error::InvalidBrowserExport(reason),
+        error => error,
+    })?);
+    let (header, rows) = records.split_first().map_or((&[][..], &[][..]), |(header, rows)| (&header[..], rows));
+    let column = |names: &[&str]| {
+        header.iter().position(|title| names.iter().any(|name| title.trim().eq_ignore_ascii_case(name)))
+    };
+    let (Some(url_column), Some(username_column), Some(password_column)) = (
+        column(&["url"]),
+        column(&["username"]),
+        column(&["password"]),
+    ) else {
+        return Err(Error::InvalidBrowserExport(String::from(
+            "the first line must name the `url`, `username` and `password` columns",
+        )));
+    };
+    let note_column = column(&["note", "notes"]);
+    let otp_column = column(&["otpauth"]);
+
+    let mut conversion = Conversion::default();
+    // maps the origin and user name to the index of the password item
+    let mut logins = HashMap::<(String, String), usize>::new();
+    let mut labels = HashSet::new();
+
+    for (index, row) in rows.iter().enumerate() {
+        let field = |column: Option<usize>| column.and_then(|column| row.get(column)).map_or("", |field| field.trim());
+        let url = field(Some(url_column));
+        let username = field(Some(username_column));
+        let password = row.get(password_column).map_or("", String::as_str);
+        let skipped = |reason: &str| SkippedRecord {
+            name: if url.is_empty() { format!("line {}", index + 2) } else { url.into() },
+            reason: reason.into(),
+        };
+
+        if password.is_empty() {
+            conversion.skipped.push(skipped("it has no password"));
+            continue;
+        }
+        let Some(site) = Site::parse(url) else {
+            conversion.skipped.push(skipped("its URL is missing or invalid"));
+            continue;
+        };
+
+        let key = (site.origin.clone(), username.to_owned());
+
+        if let Some(&existing) = logins.get(&key) {
+            if conversion.items[existing].secret.as_str() == password {
+                conversion.merged += 1;
+            } else {
+                conversion.skipped.push(skipped(
+                    "an earlier row has the same user name and site, but a different password",
+                ));
+            }
+            continue;
+        }
+
+        let mut label = site.label();
+
+        if labels.contains(&label) && !username.is_empty() {
+            label = format!("{label} ({username})");
+        }
+        if !labels.insert(label.clone()) {
+            conversion.skipped.push(skipped("an earlier row of the same site has the same user name"));
+            continue;
+        }
+
+        let otp = field(otp_column);
+        let note = field(note_column);
+        let account = Some(username).filter(|name| !name.is_empty()).map(String::from);
+
+        if !otp.is_empty() {
+            if let Err(error) = OtpAuthUri::parse(otp) {
+                conversion.skipped.push(skipped(&error.to_string()));
+                continue;
+            }
+        }
+
+        logins.insert(key, conversion.items.len());
+        conversion.items.push(ConvertedItem {
+            kind: ItemKind::Password,
+            label: label.clone(),
+            account: account.clone(),
+            secret: Zeroizing::new(password.into()),
+            tags: Vec::new(),
+            url: Some(url.into()),
+        });
+
+        if !otp.is_empty() {
+            conversion.items.push(ConvertedItem {
+                kind: ItemKind::Totp,
+                label: format!("{label} (TOTP)"),
+                account,
+                secret: Zeroizing::new(otp.into()),
+                tags: Vec::new(),
+                url: None,
+            });
+        }
+
+        if !note.is_empty() {
+            conversion.items.push(ConvertedItem {
+                kind: ItemKind::Note,
+                label: format!("{label} (notes)"),
+                account: None,
+                secret: Zeroizing::new(note.into()),
+                tags: vec![crate::db::NOTE_TAG.into()],
+                url: None,
+            });
+        }
+    }
+
+    Ok(conversion)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::migrate::ItemKind;
+    use crate::error::{Error, Result};
+    use super::{Site, convert};
+
+
+    fn label(url: &str) -> String {
+        Site::parse(url).map(|site| site.label()).unwrap_or_default()
+    }
+
+    #[test]
+    fn labels_are_registrable_domains() {
+        assert_eq!(label("https://accounts.google.com/signin?continue=x"), "google.com");
+        assert_eq!(label("https://www.amazon.co.uk/ap/signin"), "amazon.co.uk");
+        assert_eq!(label("https://login.bank.com.au:8443/"), "bank.com.au");
+        assert_eq!(label("http://user@Example.ORG."), "example.org");
+        assert_eq!(label("http://192.168.1.1/admin"), "192.168.1.1");
+        assert_eq!(label("https://[::1]:8080/"), "::1");
+        assert_eq!(label("android://hash@com.example.app/"), "com.example.app");
+        assert_eq!(label("not a url"), "");
+    }
+
+    #[test]
+    fn rows_are_deduplicated_and_labelled() -> Result<()> {
+        // the column layout of Chrome
+        let csv = "name,url,username,password,note\n\
+                   accounts.google.com,https://accounts.google.com/signin,alice,pw1,\n\
+                   accounts.google.com,https://accounts.google.com/v3/signin,alice,pw1,\n\
+                   mail.google.com,https://mail.google.com/,bob,pw2,shared with Bob\n\
+                   accounts.google.com,https://accounts.google.com/,alice,changed,\n\
+                   example.com,https://example.com/,carol,,\n";
+        let conversion = convert(csv)?;
+        let labels: Vec<&str> = conversion.items.iter().map(|item| item.label.as_str()).collect();
+
+        assert_eq!(labels, ["google.com", "google.com (bob)", "google.com (bob) (notes)"]);
+        assert_eq!(conversion.items[0].account.as_deref(), Some("alice"));
+        assert_eq!(conversion.items[0].url.as_deref(), Some("https://accounts.google.com/signin"));
+        assert_eq!(conversion.items[2].kind, ItemKind::Note);
+        assert_eq!(conversion.merged, 1);
+        assert_eq!(conversion.skipped.len(), 2);
+
+        // the column layout of Firefox, which has no name and no notes
+        let csv = "\"url\",\"username\",\"password\",\"httpRealm\",\"formActionOrigin\"\n\
+                   \"https://github.com\",\"octocat\",\"hunter2\",,\"https://github.com\"\n";
+        let conversion = convert(csv)?;
+        assert_eq!(conversion.items.len(), 1);
+        assert_eq!(conversion.items[0].label, "github.com");
+
+        assert!(matches!(convert("label,account\nx,y\n"), Err(Error::InvalidBrowserExport(_))));
+
+        Ok(())
+    }
+}
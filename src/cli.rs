@@ -0,0 +1,2802 @@
+//! Command-line arguments and non-interactive subcommands.
+
+use std::fs::File;
+use std::collections::HashSet;
+use std::io::{self, Read, Write, Stdout, BufReader, BufWriter, IsTerminal};
+use std::path::{Path, PathBuf};
+#[cfg(feature = "serve")]
+use std::net::SocketAddr;
+use chrono::{DateTime, Utc, Local, NaiveDate, NaiveTime};
+use serde::{Serialize, de::DeserializeOwned};
+use clap::{Parser, Subcommand, ValueEnum, CommandFactory};
+use clap_complete::Shell;
+use zeroize::Zeroizing;
+use ratatui::{
+    Terminal, TerminalOptions, Viewport,
+    backend::CrosstermBackend,
+    style::{Style, Color},
+    widgets::Gauge,
+};
+#[cfg(unix)]
+use crate::agent;
+#[cfg(feature = "serve")]
+use crate::serve;
+use crate::{
+    config::{Config, SizeLimits, DEFAULT_RC_FILE},
+    crypto::{Pepper, EncryptionInput, SigningKeyParams},
+    fields::{Label, AccountName, PlaintextSecret, MasterPassword},
+    clipboard::Clipboard,
+    db::{
+        Database, Item, DisplayItem, AddItemInput, UpdateEncryptionInput, VaultSetting,
+        Recipient, ItemEnvelope, ItemRotation, SmartFilter, NOTE_TAG, PLACEHOLDER_TAG,
+    },
+    query::Query,
+    expiry::ExpiryReport,
+    sync::GitSync,
+    oplog,
+    ssitem,
+    net::Network,
+    share,
+    shamir::{self, KeyShare},
+    org,
+    report::{self, Report, ReportItem, Flag},
+    askpass,
+    interrupt,
+    wordlist,
+    policy::{Policy, ItemType},
+    kit::{self, EmergencyKit, KitSecret},
+    inventory::{self, InventoryItem},
+    migrate::{ItemKind, SkippedRecord, Conversion},
+    bitwarden,
+    pass,
+    browser,
+    perms,
+    format::{Bundle, ExportedItem},
+    conflict::{self, Conflict, ConflictDialog, Resolution},
+    screen::ScreenGuard,
+    input::TerminalEvents,
+    tui::format_file_size,
+    vault::Vault,
+    error::{Error, Result, ResultExt},
+};
+
+
+/// Prints a message meant for humans, as opposed to the output of the command.
+/// If the standard output is redirected (e.g. piped into another program),
+/// the message goes to the standard error instead, so that it doesn't end up
+/// mixed with the output.
+macro_rules! note {
+    ($($arg:tt)*) => {
+        if io::stdout().is_terminal() {
+            println!($($arg)*);
+        } else {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+/// Simple, personal TUI password manager.
+///
+/// When started without a subcommand, it opens the interactive user interface.
+#[derive(Clone, Debug, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// Instead of the full-screen interface, use line-oriented prompts and plain
+    /// text output, without colors (e.g. for use with a screen reader).
+    #[arg(long)]
+    pub no_tui: bool,
+    /// Make the database, its journal, and its directory accessible to their
+    /// owner only, then exit.
+    #[arg(long, conflicts_with = "remote")]
+    pub fix_perms: bool,
+    /// Open the database of another machine over SSH, e.g.
+    /// `user@host:/path/secrets.sqlite3`, instead of the local one. It's
+    /// downloaded to a temporary file, and opened read-only.
+    #[arg(long, value_name = "[USER@]HOST:PATH")]
+    pub remote: Option<String>,
+    /// With `--remote`, allow changes, and upload the database when exiting.
+    #[arg(long, requires = "remote")]
+    pub writable: bool,
+    /// Print errors as JSON objects with `status`, `kind` and `message` fields,
+    /// on a single line of the standard error.
+    #[arg(long, global = true)]
+    pub json_errors: bool,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Operations that can be performed without the interactive user interface.
+#[derive(Clone, Debug, Subcommand)]
+pub enum Command {
+    /// List the labels and account names of all items, or of the matching ones.
+    List {
+        /// Only list items matching this query, e.g. `bank tag:work modified:>2024-01-01`.
+        search: Option<String>,
+        /// The output format. Defaults to `table` on a terminal, and `plain` otherwise.
+        #[arg(long, value_enum)]
+        format: Option<OutputFormat>,
+    },
+    /// Print the decrypted secret of an item.
+    Get {
+        /// The label of the item.
+        label: String,
+        /// The output format. `plain` prints the secret only.
+        #[arg(long, value_enum, default_value_t = OutputFormat::Plain)]
+        format: OutputFormat,
+    },
+    /// Quickly store a note, e.g. recovery codes that are shown only once.
+    ///
+    /// The item is labelled with the current date and time, and tagged `note`.
+    /// You will be asked for its encryption password twice.
+    Note {
+        /// The text of the note. If omitted, it's read from the standard input,
+        /// which keeps it out of the shell history.
+        text: Option<String>,
+    },
+    /// Attach tags to an item.
+    Tag {
+        /// The label of the item.
+        label: String,
+        /// The tags to attach.
+        #[arg(required = true)]
+        tags: Vec<String>,
+    },
+    /// Remove tags from an item.
+    Untag {
+        /// The label of the item.
+        label: String,
+        /// The tags to remove.
+        #[arg(required = true)]
+        tags: Vec<String>,
+    },
+    /// List every tag in use, along with the number of items having it.
+    Tags,
+    /// Set or remove the expiry date of an item.
+    ///
+    /// Expired items, and items expiring within `expiry.warning_days`,
+    /// are reported by `steelsafe status` and upon starting the TUI.
+    Expire {
+        /// The label of the item.
+        label: String,
+        /// The expiry date, e.g. `2024-12-31`, or `never` to remove it.
+        date: String,
+    },
+    /// Set how often the secret of an item should be changed, or remove the
+    /// rotation policy.
+    ///
+    /// Items whose secret hasn't been changed for that long are reported by
+    /// `steelsafe status`, and listed by the "Overdue for rotation" filter of
+    /// the TUI. Changing the secret, e.g. by `steelsafe rotate`, resets the clock.
+    Rotation {
+        /// The label of the item.
+        label: String,
+        /// The interval in days, e.g. `90`, or `never` to remove the policy.
+        interval: String,
+    },
+    /// Always ask for the password of an item, even if the agent is unlocked.
+    ///
+    /// Meant for the most sensitive items, e.g. the online bank or the root
+    /// password. Turning it off again requires the password of the item.
+    Reprompt {
+        /// The label of the item.
+        label: String,
+        /// Let the agent decrypt the item again.
+        #[arg(long)]
+        off: bool,
+    },
+    /// Delete an item, along with its tags, expiry date and access log.
+    ///
+    /// With git synchronization, the item comes back from other devices upon
+    /// the next sync, unless it's deleted there, too.
+    Delete {
+        /// The label of the item.
+        label: String,
+    },
+    /// Report the items that have expired, or will expire soon.
+    Status,
+    /// Write all items to an export bundle. Secrets remain encrypted.
+    Export {
+        /// The path of the bundle file to create. Must not exist yet.
+        path: PathBuf,
+        /// Also encrypt labels and account names with a separate bundle password.
+        #[arg(long)]
+        encrypt: bool,
+    },
+    /// Add all items of an export bundle to the database.
+    ///
+    /// Items whose label already exists are shown next to the existing ones,
+    /// so that you can keep either of them, or both. Either all items are
+    /// imported as decided, or none of them are.
+    Import {
+        /// The path of the bundle file to read.
+        path: PathBuf,
+        /// Resolve every conflicting label this way, instead of asking.
+        #[arg(long, value_enum)]
+        on_conflict: Option<Resolution>,
+    },
+    /// Write a single item into a `.ssitem` file. Its secret remains encrypted.
+    ExportItem {
+        /// The title of the item.
+        label: String,
+        /// The path of the file to create. It must not exist yet.
+        path: PathBuf,
+    },
+    /// Add the item of a `.ssitem` file to the database, after confirming twice.
+    ///
+    /// The secret remains encrypted with the password it had in the other vault.
+    ImportItem {
+        /// The path of the `.ssitem` file to read.
+        path: PathBuf,
+    },
+    /// Write the titles, account names, and tags of all items into a file,
+    /// without their secrets, e.g. for taking inventory in a spreadsheet.
+    ///
+    /// The file isn't encrypted, so you will be asked to confirm.
+    ExportInventory {
+        /// The path of the file to create. Must not exist yet.
+        path: PathBuf,
+        /// CSV by default if the path ends in `.csv`, otherwise JSON.
+        #[arg(long, value_enum)]
+        format: Option<InventoryFormat>,
+    },
+    /// Add the items listed in an inventory as placeholders, with an empty
+    /// secret and the `placeholder` tag.
+    ///
+    /// You will be asked for their encryption password twice. Fill in their
+    /// secrets later with `steelsafe fill`.
+    ImportInventory {
+        /// The path of the inventory file to read.
+        path: PathBuf,
+        /// CSV by default if the path ends in `.csv`, otherwise JSON.
+        #[arg(long, value_enum)]
+        format: Option<InventoryFormat>,
+    },
+    /// Add the logins and secure notes of an unencrypted Bitwarden JSON export.
+    ///
+    /// A login becomes up to three items: its password, its TOTP seed, and its
+    /// notes along with its URIs. Records that can't be imported (e.g. cards, or
+    /// logins whose title is already taken) are listed and skipped. You will be
+    /// asked for the encryption password of the new items twice.
+    ImportBitwarden {
+        /// The path of the export file to read.
+        path: PathBuf,
+        /// Only list what would be imported, without changing the vault.
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Add the passwords saved in a web browser, exported as CSV.
+    ///
+    /// Chrome, Firefox and Safari exports are supported. Items are labelled by
+    /// the domain of their website, and remember its full URL. Repeated rows of
+    /// the same account and site are merged. You will be asked for the
+    /// encryption password of the new items twice.
+    ImportBrowser {
+        /// The path of the CSV file to read.
+        path: PathBuf,
+        /// Only list what would be imported, without changing the vault.
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Add the entries of a `pass` password store, decrypting them with gpg.
+    ///
+    /// The path of an entry becomes its title, and its directories become tags.
+    /// The first line is the password, a `login:` line the account, and an
+    /// `otpauth://` line a TOTP seed; other lines are added as a separate note.
+    /// You will be asked for the encryption password of the new items twice.
+    ImportPass {
+        /// The store to read. Defaults to `$PASSWORD_STORE_DIR` or `~/.password-store`.
+        store: Option<PathBuf>,
+        /// Only list what would be imported, without changing the vault.
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Write every item into a `pass` password store, encrypted with gpg.
+    ///
+    /// You will be asked for the encryption password. Items with a different
+    /// password, and items whose entry already exists, are skipped.
+    ExportPass {
+        /// The store to write. Defaults to `$PASSWORD_STORE_DIR` or `~/.password-store`.
+        store: Option<PathBuf>,
+        /// The key to encrypt the entries for, if the store has no `.gpg-id` yet.
+        #[arg(long = "recipient", value_name = "KEY_ID")]
+        recipients: Vec<String>,
+    },
+    /// Fill in the secret of a placeholder imported from an inventory.
+    ///
+    /// The secret is read from the standard input if it's not a terminal. You
+    /// will be asked for the password the placeholder was encrypted with.
+    Fill {
+        /// The label of the placeholder.
+        label: String,
+    },
+    /// Write the secret of an item into a file that can be sent to someone else.
+    ///
+    /// The file is encrypted with a random passphrase, which is printed only once.
+    /// Tell it to the recipient over a different channel than the file itself.
+    Share {
+        /// The label of the item.
+        label: String,
+        /// The path of the share file to create. Must not exist yet.
+        path: PathBuf,
+    },
+    /// Write a printable emergency kit: where the vault is, how to recover it,
+    /// and optionally the secrets of selected items, also as QR codes.
+    ///
+    /// Print it, keep it in a physical safe, and delete the file. You will be
+    /// asked to confirm twice, and for the password of each selected item.
+    EmergencyKit {
+        /// The path of the file to create. Must not exist yet.
+        path: PathBuf,
+        /// The label of an item whose secret to include. Can be repeated.
+        #[arg(long = "secret", value_name = "LABEL")]
+        secrets: Vec<String>,
+        /// HTML by default if the path ends in `.html` or `.htm`, otherwise plain text.
+        #[arg(long, value_enum)]
+        format: Option<KitFormat>,
+    },
+    /// Print the secret contained in a share file.
+    Reveal {
+        /// The path of the share file.
+        path: PathBuf,
+        /// Overwrite and delete the file once the secret was revealed.
+        #[arg(long)]
+        shred: bool,
+    },
+    /// Split the encryption password into key shares, any threshold of which
+    /// restore it, or restore it from such shares.
+    Shares {
+        #[command(subcommand)]
+        action: SharesAction,
+    },
+    /// Share items with the members of a team, each of whom decrypts them with
+    /// their own age key (set as `identity_file`), instead of the item password.
+    Team {
+        #[command(subcommand)]
+        action: TeamAction,
+    },
+    /// Synchronize the vault with the git repository configured as `git_sync`.
+    Sync,
+    /// Exchange changes with another vault, e.g. one on a USB stick, or that of
+    /// another device, so that both end up with the same items.
+    ///
+    /// The other vault is created if it doesn't exist. Don't copy a database
+    /// file to set up another device; merge into a new, empty vault instead.
+    /// Items of the other vault with the label of a different local item are
+    /// shown next to the local ones, so that you can keep either, or both.
+    Merge {
+        /// The path of the other database.
+        path: PathBuf,
+        /// Resolve every conflicting label this way, instead of asking.
+        #[arg(long, value_enum)]
+        on_conflict: Option<Resolution>,
+    },
+    /// Move the items with a tag into another vault, e.g. to keep work secrets
+    /// apart from personal ones. Secrets remain encrypted as they are.
+    ///
+    /// The other vault is created if it doesn't exist. Tags, expiry dates, URLs
+    /// and recovery code counts are moved along. Either all matching items are
+    /// moved, or none of them are (e.g., if any of the labels already exists).
+    Split {
+        /// Move the items with this tag.
+        #[arg(long)]
+        tag: String,
+        /// The path of the other database.
+        #[arg(long)]
+        out: PathBuf,
+        /// Keep the items in this vault, too.
+        #[arg(long)]
+        copy: bool,
+    },
+    /// Move the items of another vault (e.g. one made by `split`) into this one.
+    ///
+    /// Either all items are moved, or none of them are (e.g., if any of the
+    /// labels already exists).
+    Join {
+        /// The path of the other database.
+        path: PathBuf,
+        /// Only move the items with this tag.
+        #[arg(long)]
+        tag: Option<String>,
+        /// Keep the items in the other vault, too.
+        #[arg(long)]
+        copy: bool,
+    },
+    /// Re-encrypt the items that use outdated key derivation settings or ciphers
+    /// with the currently recommended ones.
+    ///
+    /// You will be asked for the encryption password once. Items with a different
+    /// password are left unchanged; run the command again with their password.
+    UpgradeCrypto,
+    /// Replace the passwords of the matching items with newly generated ones,
+    /// one after the other.
+    ///
+    /// For each item, the current password is copied to the clipboard, then the
+    /// new one, which is only stored once you confirm that you have changed it
+    /// on the website (or wherever it's used). You will be asked for the
+    /// encryption password once; items with a different password are skipped.
+    Rotate {
+        /// Which items to rotate, e.g. `tag:work` or `label:"My Bank"`.
+        query: String,
+    },
+    /// Rebuild the database file without the free space left behind by replaced
+    /// secrets and deleted rows, which may still contain their old contents.
+    Compact,
+    /// Report the items whose secret occurs in a list of common or leaked passwords.
+    ///
+    /// The check is done offline, against a text file with one password per
+    /// line. You will be asked for the encryption password once. Items with a
+    /// different password are skipped; run the command again with their password.
+    Audit {
+        /// The path of the password list, e.g. the most common million passwords.
+        #[arg(long, value_name = "FILE")]
+        wordlist: PathBuf,
+    },
+    /// Write a signed audit report: the label, account, tags, dates, and audit
+    /// flags of every item, but no secrets, as JSON.
+    ///
+    /// The report is signed with a key derived from the encryption password,
+    /// which you will be asked for, so its public key is the same for every
+    /// report of the vault. Give it to the auditors along with the first report.
+    AuditReport {
+        /// The path of the report file to create. Must not exist yet.
+        path: PathBuf,
+    },
+    /// Check that an audit report hasn't been altered since it was signed.
+    VerifyReport {
+        /// The path of the report file.
+        path: PathBuf,
+        /// The public key of the vault, as printed by `audit-report`. Without
+        /// it, the report is only checked against the key it contains.
+        #[arg(long)]
+        public_key: Option<String>,
+    },
+    /// Find the items whose secret can't be decrypted, because their data fails
+    /// authentication, and offer to move them into quarantine.
+    ///
+    /// The label, account name and modification date are authenticated along
+    /// with the secret, so changing any of them outside of steelsafe (or tampering
+    /// with the database otherwise) makes the item undecryptable. You will be asked
+    /// for the encryption password once; items with a different password fail, too.
+    /// Quarantined items are kept in a separate table of the database.
+    Verify,
+    /// Show or change a setting stored in the vault itself.
+    ///
+    /// Such settings travel with the database, and take precedence over the
+    /// `.steelsaferc`. Without a value, the value stored in the vault is printed.
+    VaultSetting {
+        /// The setting, named as in the `.steelsaferc`.
+        name: VaultSettingName,
+        /// The new value, as JSON, e.g. `true` or `30`.
+        value: Option<String>,
+        /// Remove the setting from the vault, so that the `.steelsaferc` applies again.
+        #[arg(long, conflicts_with = "value")]
+        unset: bool,
+    },
+    /// Keep an encryption password in memory for a limited time, and decrypt
+    /// items with it on behalf of other invocations. Runs in the foreground.
+    #[cfg(unix)]
+    Agent,
+    /// Give the encryption password to the running agent.
+    #[cfg(unix)]
+    Unlock {
+        /// How long the agent should keep the password, in seconds.
+        /// Defaults to `agent.ttl_secs` in the config, or 15 minutes.
+        /// At most a week; longer durations are shortened to that.
+        #[arg(long)]
+        ttl: Option<u64>,
+    },
+    /// Make the running agent forget the password immediately.
+    #[cfg(unix)]
+    Lock,
+    /// Check the configuration file, and report every mistake in it.
+    ///
+    /// Unknown keys, invalid colors and out-of-range values are reported
+    /// along with their location, e.g. `theme.border.fg`.
+    CheckConfig {
+        /// The file to check. Defaults to the `.steelsaferc` that would be used.
+        path: Option<PathBuf>,
+    },
+    /// Write a commented example `.steelsaferc` to the config directory.
+    ///
+    /// Every setting has its default value, so the file can be edited
+    /// right away. An existing file is only overwritten with `--force`.
+    InitConfig {
+        /// Overwrite an existing `.steelsaferc`.
+        #[arg(long)]
+        force: bool,
+    },
+    /// Print a shell completion script to the standard output.
+    Completions {
+        /// The shell to generate the script for.
+        shell: Shell,
+    },
+    /// Upload an export bundle to the target configured as `remote_backup`.
+    ///
+    /// You will be asked for the password of the item holding the credentials.
+    #[cfg(feature = "remote")]
+    Backup {
+        /// Only make a backup if the last one is older than `remote_backup.interval_days`.
+        #[arg(long)]
+        if_due: bool,
+        /// Also encrypt labels and account names with a separate bundle password.
+        #[arg(long)]
+        encrypt: bool,
+    },
+    /// Check whether a newer version of steelsafe has been published.
+    ///
+    /// The answer of crates.io is cached for a day. Without network access,
+    /// the cached answer is used, no matter how old it is.
+    #[cfg(feature = "remote")]
+    CheckUpdate,
+    /// Serve a minimal HTTP API to scripts and dashboards on this machine.
+    ///
+    /// Clients must send the access token, which is written to a file only
+    /// you can read, as `Authorization: Bearer <token>`. Runs in the foreground
+    /// until Ctrl+C is pressed.
+    #[cfg(feature = "serve")]
+    Serve {
+        /// The address to listen on; it must be a loopback address.
+        #[arg(long, default_value = serve::DEFAULT_LISTEN)]
+        listen: SocketAddr,
+    },
+}
+
+impl Command {
+    /// Performs the operation requested on the command line.
+    pub fn run(self, db: &mut Database, config: &Config) -> Result<()> {
+        match self {
+            Command::List { search, format } => list(db, search.as_deref(), format),
+            Command::Get { label, format } => get(db, config, &label, format),
+            Command::Note { text } => add_note(db, config, text.map(Zeroizing::new)),
+            Command::Tag { label, tags } => tag(db, &label, &tags, true),
+            Command::Untag { label, tags } => tag(db, &label, &tags, false),
+            Command::Delete { label } => delete(db, &label),
+            Command::Tags => list_tags(db),
+            Command::Expire { label, date } => expire(db, &label, &date),
+            Command::Rotation { label, interval } => rotation(db, &label, &interval),
+            Command::Reprompt { label, off } => reprompt(db, config, &label, !off),
+            Command::Status => status(db, config),
+            Command::Export { path, encrypt } => export(db, &path, encrypt),
+            Command::Import { path, on_conflict } => import(db, config, &path, on_conflict),
+            Command::ExportItem { label, path } => export_item(db, &label, &path),
+            Command::ImportItem { path } => import_item(db, &path),
+            Command::ExportInventory { path, format } => export_inventory(db, &path, format),
+            Command::ImportInventory { path, format } => import_inventory(db, config, &path, format),
+            Command::ImportBitwarden { path, dry_run } => import_bitwarden(db, config, &path, dry_run),
+            Command::ImportBrowser { path, dry_run } => import_browser(db, config, &path, dry_run),
+            Command::ImportPass { store, dry_run } => import_pass(db, config, store, dry_run),
+            Command::ExportPass { store, recipients } => export_pass(db, config, store, &recipients),
+            Command::Fill { label } => fill(db, config, &label),
+            Command::Share { label, path } => share_item(db, config, &label, &path),
+            Command::EmergencyKit { path, secrets, format } => {
+                emergency_kit(db, config, &path, &secrets, format)
+            }
+            Command::Reveal { path, shred } => reveal(&path, shred),
+            Command::Shares { action: SharesAction::Create { threshold, count, out } } => {
+                create_shares(db, config, threshold, count, &out)
+            }
+            Command::Shares { action: SharesAction::Restore } => restore_password(db, config),
+            Command::Team { action } => team(db, config, action),
+            Command::Sync => sync(db, config),
+            Command::Merge { path, on_conflict } => merge(db, config, &path, on_conflict),
+            Command::Split { tag, out, copy } => split(db, config, &tag, &out, copy),
+            Command::Join { path, tag, copy } => join(db, config, &path, tag.as_deref(), copy),
+            Command::UpgradeCrypto => upgrade_crypto(db, config),
+            Command::Rotate { query } => rotate(db, config, &query),
+            Command::Compact => compact(db),
+            Command::Audit { wordlist } => audit(db, config, &wordlist),
+            Command::AuditReport { path } => audit_report(db, config, &path),
+            Command::VerifyReport { path, public_key } => verify_report(config, &path, public_key.as_deref()),
+            Command::Verify => verify(db, config),
+            Command::VaultSetting { name, value, unset } => {
+                let value = if unset { None } else { value.as_deref().map(Some) };
+                match name {
+                    VaultSettingName::AccessLog => {
+                        vault_setting(db, VaultSetting::ACCESS_LOG, value)
+                    }
+                    VaultSettingName::PersistFailedAttempts => {
+                        vault_setting(db, VaultSetting::PERSIST_FAILED_ATTEMPTS, value)
+                    }
+                    VaultSettingName::ExpiryWarningDays => {
+                        vault_setting(db, VaultSetting::EXPIRY_WARNING_DAYS, value)
+                    }
+                }
+            }
+            #[cfg(unix)]
+            Command::Agent => run_agent(db, config),
+            #[cfg(unix)]
+            Command::Unlock { ttl } => unlock_agent(config, ttl),
+            #[cfg(unix)]
+            Command::Lock => agent_request(config, &agent::Request::Lock),
+            Command::CheckConfig { path } => check_config(path.as_deref()),
+            Command::InitConfig { force } => init_config(force),
+            Command::Completions { shell } => {
+                print_completions(shell);
+                Ok(())
+            }
+            #[cfg(feature = "remote")]
+            Command::Backup { if_due, encrypt } => backup(db, config, if_due, encrypt),
+            #[cfg(feature = "remote")]
+            Command::CheckUpdate => check_update(config),
+            #[cfg(feature = "serve")]
+            Command::Serve { listen } => run_server(db, config, listen),
+        }
+    }
+}
+
+/// What to do with key shares.
+#[derive(Clone, Debug, Subcommand)]
+pub enum SharesAction {
+    /// Split the encryption password into `--count` shares, any `--threshold`
+    /// of which restore it, while fewer reveal nothing about it.
+    ///
+    /// Each share is written to a printable file with a QR code, named
+    /// `steelsafe-share-N-of-M.txt`. Give them to different people, or keep
+    /// them in different places, and delete the files.
+    Create {
+        /// The number of shares needed to restore the password, at least 2.
+        #[arg(long)]
+        threshold: u8,
+        /// The number of shares to create.
+        #[arg(long)]
+        count: u8,
+        /// The directory in which to create the share files.
+        #[arg(long, default_value = ".")]
+        out: PathBuf,
+    },
+    /// Restore the encryption password from shares, typed in one by one, or
+    /// read from the standard input, one per line.
+    Restore,
+}
+
+/// Managing the members of the team, and the items shared with them.
+#[derive(Clone, Debug, Subcommand)]
+pub enum TeamAction {
+    /// List the members of the team.
+    Members,
+    /// Add a member, and re-encrypt the shared items for them. Needs your
+    /// `identity_file` if items are already shared.
+    AddMember {
+        /// The name of the member, e.g. their email address.
+        name: String,
+        /// The age X25519 public key of the member (`age1...`).
+        public_key: String,
+    },
+    /// Remove a member, and re-encrypt the shared items for the others. Needs
+    /// your `identity_file` if items are shared. Rotate the secrets they knew.
+    RemoveMember {
+        /// The name of the member.
+        name: String,
+    },
+    /// Share an item with every member. Asks for the password of the item.
+    Share {
+        /// The label of the item.
+        label: String,
+    },
+    /// Stop sharing an item with the team.
+    Unshare {
+        /// The label of the item.
+        label: String,
+    },
+    /// Print the secret of a shared item, decrypted with your `identity_file`.
+    Get {
+        /// The label of the item.
+        label: String,
+    },
+}
+
+/// The format of the emergency kit.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, ValueEnum)]
+pub enum KitFormat {
+    /// Plain text; QR codes are drawn with Unicode block characters.
+    Text,
+    /// A standalone HTML page; QR codes are inline SVG images.
+    Html,
+}
+
+impl KitFormat {
+    fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("html") || ext.eq_ignore_ascii_case("htm") => {
+                KitFormat::Html
+            }
+            _ => KitFormat::Text,
+        }
+    }
+}
+
+/// The format of inventory files.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, ValueEnum)]
+pub enum InventoryFormat {
+    /// A JSON array of objects with `label`, `account`, and `tags` fields.
+    Json,
+    /// Comma-separated values with a header line; tags are separated by line breaks.
+    Csv,
+}
+
+impl InventoryFormat {
+    fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("csv") => InventoryFormat::Csv,
+            _ => InventoryFormat::Json,
+        }
+    }
+}
+
+/// The settings that can be stored in the vault (see `Command::VaultSetting`).
+#[derive(Clone, Copy, PartialEq, Eq, Debug, ValueEnum)]
+pub enum VaultSettingName {
+    #[value(name = "access_log")]
+    AccessLog,
+    #[value(name = "persist_failed_attempts")]
+    PersistFailedAttempts,
+    #[value(name = "expiry.warning_days")]
+    ExpiryWarningDays,
+}
+
+/// How `list` and `get` print their results.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, ValueEnum)]
+pub enum OutputFormat {
+    /// Tab-separated fields, one item per line, without a header.
+    Plain,
+    /// A JSON array of items (`list`), or a single JSON object (`get`).
+    Json,
+    /// Aligned columns with a header, for humans.
+    Table,
+}
+
+/// An item as printed by `list` and `get`.
+#[derive(Debug, Serialize)]
+pub struct ListedItem<'a> {
+    label: &'a str,
+    account: Option<&'a str>,
+    last_modified_at: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    secret: Option<&'a str>,
+}
+
+impl<'a> ListedItem<'a> {
+    pub fn new(item: &'a DisplayItem) -> Self {
+        ListedItem {
+            label: &item.label,
+            account: item.account.as_deref(),
+            last_modified_at: item.last_modified_at,
+            secret: None,
+        }
+    }
+
+    fn fields(&self) -> Vec<String> {
+        let mut fields = vec![
+            self.label.to_owned(),
+            self.account.unwrap_or_default().to_owned(),
+            self.last_modified_at.format("%F %T").to_string(),
+        ];
+        fields.extend(self.secret.map(str::to_owned));
+        fields
+    }
+}
+
+fn list(db: &Database, search: Option<&str>, format: Option<OutputFormat>) -> Result<()> {
+    let format = format.unwrap_or_else(|| {
+        if io::stdout().is_terminal() { OutputFormat::Table } else { OutputFormat::Plain }
+    });
+    let query = Query::parse(search.unwrap_or_default())?;
+    let items = query.find_items(db)?;
+    let listed: Vec<_> = items.iter().map(ListedItem::new).collect();
+
+    match format {
+        OutputFormat::Plain => {
+            for item in &listed {
+                println!("{}", item.fields().join("\t"));
+            }
+        }
+        OutputFormat::Json => {
+            println!("{}", serde_json::to_string_pretty(&listed)?);
+        }
+        OutputFormat::Table => {
+            let rows: Vec<_> = listed.iter().map(ListedItem::fields).collect();
+            print_table(&["LABEL", "ACCOUNT", "LAST MODIFIED"], &rows);
+        }
+    }
+
+    Ok(())
+}
+
+fn get(db: &Database, config: &Config, label: &str, format: OutputFormat) -> Result<()> {
+    let item = db.item_by_label(label)?.ok_or_else(|| Error::ItemNotFound(label.into()))?;
+    let secret = decrypt_item(&item, config)?;
+
+    if config.access_log {
+        db.log_access(item.uid)?;
+    }
+
+    let listed = ListedItem {
+        label: &item.label,
+        account: item.account.as_deref(),
+        last_modified_at: item.last_modified_at,
+        secret: Some(secret.as_str()),
+    };
+
+    match format {
+        OutputFormat::Plain => {
+            println!("{}", secret.as_str());
+        }
+        OutputFormat::Json => {
+            let json = Zeroizing::new(serde_json::to_string_pretty(&listed)?);
+            println!("{}", json.as_str());
+        }
+        OutputFormat::Table => {
+            let row = Zeroizing::new(listed.fields());
+            print_table(&["LABEL", "ACCOUNT", "LAST MODIFIED", "SECRET"], std::slice::from_ref(&*row));
+        }
+    }
+
+    Ok(())
+}
+
+/// Stores `text`, or the standard input, as a new item tagged `note`.
+fn add_note(db: &Database, config: &Config, text: Option<Zeroizing<String>>) -> Result<()> {
+    let text = match text {
+        Some(text) => text,
+        None => {
+            if io::stdin().is_terminal() {
+                note!("Type the note, then press Ctrl+D on an empty line");
+            }
+            read_stdin_secret(&config.limits)?
+        }
+    };
+    let text = text.trim_end();
+
+    if text.is_empty() {
+        return Err(Error::SecretRequired);
+    }
+
+    config.limits.check_secret_len(text.len())?;
+
+    let password = new_encryption_password(config)?;
+    let pepper = config.pepper_file.as_deref().map(Pepper::load_or_create).transpose()?;
+    let label = Label::new(&db.unused_note_label(Local::now().naive_local())?)?;
+    let input = EncryptionInput {
+        plaintext_secret: &PlaintextSecret::new(text.as_bytes()),
+        label: &label,
+        account: None,
+        last_modified_at: Utc::now(),
+        pepper: pepper.as_ref(),
+    };
+    let output = input.encrypt_and_authenticate(&password)?;
+    let item = db.add_item(AddItemInput::new(&label, None, input.last_modified_at, &output))?;
+
+    db.tag_item(item.uid, NOTE_TAG)?;
+    note!("Added {label:?}");
+
+    Ok(())
+}
+
+/// Reads a secret from the standard input, but not much more than the size limit.
+fn read_stdin_secret(limits: &SizeLimits) -> Result<Zeroizing<String>> {
+    // reading one more byte than allowed is enough to tell that it's too long
+    let limit = limits.max_secret_bytes as u64 + 1;
+    let mut text = Zeroizing::new(String::new());
+    io::stdin().take(limit).read_to_string(&mut text)?;
+
+    Ok(text)
+}
+
+/// Asks for the encryption password of new items, and for its confirmation.
+fn new_encryption_password(config: &Config) -> Result<MasterPassword> {
+    let password = askpass::ask(config.password_command.as_ref(), "Encryption password: ", None)?;
+    let master_password = MasterPassword::new(&password)?;
+
+    // a password command can't make typos
+    if config.password_command.is_none() {
+        let confirmation = Zeroizing::new(rpassword::prompt_password("Confirm encryption password: ")?);
+
+        if password != confirmation {
+            return Err(Error::ConfirmPasswordMismatch);
+        }
+    }
+
+    Ok(master_password)
+}
+
+/// Attaches the tags to, or removes them from, the item.
+fn tag(db: &Database, label: &str, tags: &[String], attach: bool) -> Result<()> {
+    let item = db.item_by_label(label)?.ok_or_else(|| Error::ItemNotFound(label.into()))?;
+
+    for tag in tags {
+        if attach {
+            db.tag_item(item.uid, tag)?;
+        } else {
+            db.untag_item(item.uid, tag)?;
+        }
+    }
+
+    note!("{}: {}", item.label, db.item_tags(item.uid)?.join(", "));
+
+    Ok(())
+}
+
+fn list_tags(db: &Database) -> Result<()> {
+    let rows: Vec<_> = db
+        .tag_counts()?
+        .into_iter()
+        .map(|tag_count| vec![tag_count.tag, tag_count.item_count.to_string()])
+        .collect();
+
+    print_table(&["TAG", "ITEMS"], &rows);
+
+    Ok(())
+}
+
+fn expire(db: &Database, label: &str, date: &str) -> Result<()> {
+    let item = db.item_by_label(label)?.ok_or_else(|| Error::ItemNotFound(label.into()))?;
+
+    if date.eq_ignore_ascii_case("never") {
+        db.set_expiry(item.uid, None)?;
+        note!("{} no longer expires.", item.label);
+    } else {
+        let date = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .map_err(|_| Error::InvalidDate(date.into()))?;
+
+        db.set_expiry(item.uid, Some(date.and_time(NaiveTime::MIN).and_utc()))?;
+        note!("{} expires on {}.", item.label, date);
+    }
+
+    Ok(())
+}
+
+fn delete(db: &mut Database, label: &str) -> Result<()> {
+    let item = db.item_by_label(label)?.ok_or_else(|| Error::ItemNotFound(label.into()))?;
+    let question = format!("Delete {:?} for good? [y/N] ", item.label);
+
+    if !confirm(&question, &["y", "yes"])? {
+        return Ok(());
+    }
+
+    db.delete_item(item.uid)?;
+    note!("Deleted {}.", item.label);
+
+    Ok(())
+}
+
+fn reprompt(db: &Database, config: &Config, label: &str, reprompt: bool) -> Result<()> {
+    let item = db.item_by_label(label)?.ok_or_else(|| Error::ItemNotFound(label.into()))?;
+
+    if reprompt {
+        db.set_reprompt(item.uid, true)?;
+        note!("The password of {} will be asked for every time.", item.label);
+    } else {
+        // otherwise, anyone at the keyboard could turn it off while the agent is unlocked
+        if item.reprompt {
+            decrypt_item(&item, config)?;
+        }
+        db.set_reprompt(item.uid, false)?;
+        note!("{} can be decrypted by the agent again.", item.label);
+    }
+
+    Ok(())
+}
+
+/// Prints the setting if `value` is `None`, stores it if `value` is `Some(Some(_))`,
+/// and removes it from the vault if `value` is `Some(None)`.
+fn vault_setting<T>(db: &Database, setting: VaultSetting<T>, value: Option<Option<&str>>) -> Result<()>
+where
+    T: Serialize + DeserializeOwned,
+{
+    let name = setting.name();
+
+    match value {
+        None => match db.setting(setting)? {
+            Some(value) => println!("{}", serde_json::to_string(&value)?),
+            None => note!("{name} is not set in the vault; the .steelsaferc applies"),
+        },
+        Some(Some(json)) => {
+            let value: T = serde_json::from_str(json).context(format!("Invalid value for {name}"))?;
+            db.set_setting(setting, Some(&value))?;
+            note!("Set {name} to {json} in the vault");
+        }
+        Some(None) => {
+            db.set_setting(setting, None)?;
+            note!("Removed {name} from the vault; the .steelsaferc applies");
+        }
+    }
+
+    Ok(())
+}
+
+fn rotation(db: &Database, label: &str, interval: &str) -> Result<()> {
+    let item = db.item_by_label(label)?.ok_or_else(|| Error::ItemNotFound(label.into()))?;
+
+    if interval.eq_ignore_ascii_case("never") {
+        db.set_rotation(item.uid, None)?;
+        note!("{} no longer needs to be rotated.", item.label);
+    } else {
+        let days: u32 = interval
+            .parse()
+            .ok()
+            .filter(|&days| days > 0)
+            .ok_or_else(|| Error::InvalidRotationInterval(interval.into()))?;
+
+        db.set_rotation(item.uid, Some(days))?;
+
+        let due_at = db.rotations()?.get(&item.uid).map(ItemRotation::due_at);
+
+        if let Some(due_at) = due_at {
+            note!("{} should be rotated every {} days; next by {}.", item.label, days, due_at.format("%F"));
+        }
+    }
+
+    Ok(())
+}
+
+fn status(db: &Database, config: &Config) -> Result<()> {
+    let now = Utc::now();
+    let warning_days = config.expiry.warning_days;
+    let report = ExpiryReport::new(db, now, warning_days)?;
+    let rotations = db.rotations()?;
+    let overdue = db.items_by_filter(SmartFilter::RotationOverdue, now)?;
+
+    if report.is_empty() && overdue.is_empty() {
+        note!("No items have expired, expire within {warning_days} days, or are overdue for rotation.");
+        return Ok(());
+    }
+
+    let expired = report.expired.iter().map(|item| (&item.label, &item.account, item.expires_at, "expired"));
+    let expiring = report.expiring.iter().map(|item| (&item.label, &item.account, item.expires_at, "expiring"));
+    let overdue = overdue.iter().filter_map(|item| {
+        let rotation = rotations.get(&item.uid)?;
+        Some((&item.label, &item.account, rotation.due_at(), "rotation overdue"))
+    });
+    let rows: Vec<_> = expired
+        .chain(expiring)
+        .chain(overdue)
+        .map(|(label, account, date, status)| vec![
+            label.clone(),
+            account.clone().unwrap_or_default(),
+            date.format("%F").to_string(),
+            status.to_owned(),
+        ])
+        .collect();
+
+    print_table(&["LABEL", "ACCOUNT", "DUE", "STATUS"], &rows);
+
+    Ok(())
+}
+
+/// Prints rows in left-aligned columns, separated by two spaces.
+fn print_table(header: &[&str], rows: &[Vec<String>]) {
+    let mut widths: Vec<usize> = header.iter().map(|title| title.chars().count()).collect();
+
+    for row in rows {
+        for (width, field) in widths.iter_mut().zip(row) {
+            *width = (*width).max(field.chars().count());
+        }
+    }
+
+    let header: Vec<String> = header.iter().map(|&title| title.to_owned()).collect();
+
+    for row in std::iter::once(&header).chain(rows) {
+        let line: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(field, &width)| format!("{field:<width$}"))
+            .collect();
+
+        println!("{}", line.join("  ").trim_end());
+    }
+}
+
+/// Prints a completion script for `shell` to the standard output.
+/// Reads the configuration file the same way as upon startup. The errors,
+/// including the list of mistakes, are reported by `main()`.
+pub fn check_config(path: Option<&Path>) -> Result<()> {
+    let found = match path {
+        Some(path) => {
+            let file = File::open(path).context(format!("Can't open {}", path.display()))?;
+            Some((path.to_owned(), file))
+        }
+        None => Config::find_rc_file()?,
+    };
+    let Some((path, file)) = found else {
+        note!("No .steelsaferc found; the default settings are used");
+        return Ok(());
+    };
+
+    Config::from_file(file)?;
+    note!("{}: OK", path.display());
+
+    Ok(())
+}
+
+pub fn init_config(force: bool) -> Result<()> {
+    let path = Config::rc_file_path()?;
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    let file = if force { File::create(&path) } else { File::create_new(&path) };
+    let mut file = file.context(format!(
+        "Can't create {} (use --force to overwrite an existing one)",
+        path.display(),
+    ))?;
+
+    file.write_all(DEFAULT_RC_FILE.as_bytes())?;
+    note!("Wrote {}", path.display());
+
+    Ok(())
+}
+
+/// Revokes the access of other users to the database and its journal.
+pub fn fix_perms(db_path: &Path) -> Result<()> {
+    let fixed = perms::fix(db_path)?;
+
+    if fixed.is_empty() {
+        note!("{} is already private", db_path.display());
+    }
+    for path in fixed {
+        note!("Made {} private", path.display());
+    }
+
+    Ok(())
+}
+
+pub fn print_completions(shell: Shell) {
+    clap_complete::generate(shell, &mut Cli::command(), "steelsafe", &mut io::stdout());
+}
+
+fn export(db: &Database, path: &Path, encrypt: bool) -> Result<()> {
+    let items: Vec<ExportedItem> = db.all_items()?.into_iter().map(Into::into).collect();
+    let bundle = Bundle::new(items);
+    let password = if encrypt { Some(new_bundle_password()?) } else { None };
+
+    // never silently overwrite an existing file (e.g., a previous backup)
+    let file = File::create_new(path).context("Can't create export bundle")?;
+    bundle.write_to(BufWriter::new(file), password.as_ref().map(|p| p.as_bytes()))?;
+
+    note!("Exported {} items to {}", bundle.items.len(), path.display());
+
+    Ok(())
+}
+
+fn import(db: &mut Database, config: &Config, path: &Path, on_conflict: Option<Resolution>) -> Result<()> {
+    let limits = &config.limits;
+    let file = File::open(path).context("Can't open export bundle")?;
+    let size = file.metadata()?.len();
+
+    if size > limits.max_import_bytes {
+        return Err(Error::ImportTooLarge { size, limit: limits.max_import_bytes });
+    }
+
+    // The file is read in chunks, and never beyond the limit, even if it's
+    // still growing or it isn't a regular file (e.g. a named pipe).
+    let reader = BufReader::new(file.take(limits.max_import_bytes));
+    let bundle = Bundle::read_from(reader, bundle_password)?;
+    let (items, conflicts) = conflict::find_conflicts(db, bundle.items)?;
+    let conflicts = resolve_conflicts(conflicts, config, on_conflict)?;
+    let count = db.import_items(&items, &conflicts)?;
+
+    note!("Imported {} items from {}", count, path.display());
+
+    Ok(())
+}
+
+/// Decides what happens to each incoming item whose label belongs to a local
+/// item: what `--on-conflict` says, or else what the user picks in a dialog.
+/// Items kept under a new label are re-encrypted, which needs their passwords.
+fn resolve_conflicts(
+    mut conflicts: Vec<Conflict>,
+    config: &Config,
+    on_conflict: Option<Resolution>,
+) -> Result<Vec<Conflict>> {
+    if conflicts.is_empty() {
+        return Ok(conflicts);
+    }
+
+    if let Some(resolution) = on_conflict {
+        for conflict in &mut conflicts {
+            conflict.resolution = resolution;
+        }
+    } else if io::stdin().is_terminal() && io::stdout().is_terminal() {
+        // the screen is restored before asking for passwords
+        let mut screen = ScreenGuard::open()?;
+        let dialog = ConflictDialog::new(conflicts, config.theme.clone());
+        conflicts = dialog.run(&mut *screen, &mut TerminalEvents)?;
+    } else {
+        return Err(Error::UnresolvedConflicts(conflicts.len()));
+    }
+
+    let pepper = config.pepper_file.as_deref().map(Pepper::load_or_create).transpose()?;
+
+    for conflict in conflicts.iter_mut().filter(|conflict| conflict.resolution == Resolution::KeepBoth) {
+        let label = conflict.incoming.label.to_string();
+        let prompt = format!("Password for the incoming {label:?}: ");
+        let password = askpass::ask(config.password_command.as_ref(), &prompt, Some(&label))?;
+
+        conflict.rename_incoming(&MasterPassword::new(&password)?, pepper.as_ref())?;
+        note!("The incoming {:?} is kept as {:?}", label, conflict.renamed.as_str());
+    }
+
+    Ok(conflicts)
+}
+
+fn export_item(db: &Database, label: &str, path: &Path) -> Result<()> {
+    let item = db.item_by_label(label)?.ok_or_else(|| Error::ItemNotFound(label.into()))?;
+
+    ssitem::write_item_file(path, &item.into())?;
+    note!("Exported {} to {}", label, path.display());
+
+    Ok(())
+}
+
+/// Asks twice: the secret can't be checked without its password, so adding
+/// the item is only worth it if the user knows that password.
+fn import_item(db: &mut Database, path: &Path) -> Result<()> {
+    let item = ssitem::read_item_file(path)?;
+
+    if db.label_exists(&item.label)? {
+        return Err(Error::DuplicateLabel);
+    }
+
+    match item.account.as_deref() {
+        Some(account) => note!("{} ({}), last modified at {}", item.label, account, item.last_modified_at),
+        None => note!("{}, last modified at {}", item.label, item.last_modified_at),
+    }
+
+    if !confirm("Add this item to the vault? [y/N] ", &["y", "yes"])? {
+        return Err(Error::Cancelled);
+    }
+
+    note!("The secret stays encrypted with the password it had in the vault it came from.");
+
+    if item.peppered {
+        note!("It can also only be decrypted with the pepper file of that vault.");
+    }
+
+    if !confirm("Do you know that password? [y/N] ", &["y", "yes"])? {
+        return Err(Error::Cancelled);
+    }
+
+    db.add_item(item.as_add_item_input())?;
+    note!("Imported {} from {}", item.label, path.display());
+
+    Ok(())
+}
+
+fn export_inventory(db: &Database, path: &Path, format: Option<InventoryFormat>) -> Result<()> {
+    let mut tags = db.tags_by_item()?;
+    let items: Vec<InventoryItem> = db
+        .list_items_for_display(None)?
+        .into_iter()
+        .map(|item| InventoryItem {
+            tags: tags.remove(&item.uid).unwrap_or_default(),
+            label: item.label,
+            account: item.account,
+        })
+        .collect();
+
+    if path.try_exists()? {
+        let error = io::Error::from(io::ErrorKind::AlreadyExists);
+        return Err(Error::context(error, "Can't create inventory"));
+    }
+
+    note!("The inventory will list the titles, accounts, and tags of {} items, unencrypted.", items.len());
+
+    if !confirm("Continue? [y/N] ", &["y", "yes"])? {
+        return Err(Error::Cancelled);
+    }
+
+    let contents = match format.unwrap_or_else(|| InventoryFormat::from_path(path)) {
+        InventoryFormat::Json => inventory::to_json(&items)?,
+        InventoryFormat::Csv => inventory::to_csv(&items),
+    };
+    let mut file = File::create_new(path).context("Can't create inventory")?;
+    file.write_all(contents.as_bytes())?;
+
+    note!("Exported {} items to {}", items.len(), path.display());
+
+    Ok(())
+}
+
+fn import_inventory(
+    db: &mut Database,
+    config: &Config,
+    path: &Path,
+    format: Option<InventoryFormat>,
+) -> Result<()> {
+    let text = read_import_file(path, &config.limits, "Can't open inventory")?;
+    let items = match format.unwrap_or_else(|| InventoryFormat::from_path(path)) {
+        InventoryFormat::Json => inventory::from_json(&text)?,
+        InventoryFormat::Csv => inventory::from_csv(&text)?,
+    };
+
+    let fields = items
+        .iter()
+        .map(|item| {
+            let label = Label::new(&item.label)?;
+            let account = item.account.as_deref().map(AccountName::optional).transpose()?.flatten();
+            Ok((label, account))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    note!("{} placeholders will be added", items.len());
+
+    let password = new_encryption_password(config)?;
+    let pepper = config.pepper_file.as_deref().map(Pepper::load_or_create).transpose()?;
+    let mut progress = if io::stdout().is_terminal() { Some(ProgressBar::new()?) } else { None };
+    let mut outputs = Vec::with_capacity(items.len());
+    let last_modified_at = Utc::now();
+    let placeholder = PlaintextSecret::new(b"");
+
+    for (index, (label, account)) in fields.iter().enumerate() {
+        // nothing has been written yet, so stopping here leaves no partial changes
+        interrupt::check()?;
+
+        if let Some(progress) = progress.as_mut() {
+            progress.update(index, items.len(), label)?;
+        }
+
+        let input = EncryptionInput {
+            plaintext_secret: &placeholder,
+            label,
+            account: account.as_ref(),
+            last_modified_at,
+            pepper: pepper.as_ref(),
+        };
+        outputs.push(input.encrypt_and_authenticate(&password)?);
+    }
+
+    if let Some(mut progress) = progress {
+        progress.update(items.len(), items.len(), "done")?;
+        println!();
+    }
+
+    let inputs = fields.iter().zip(&outputs).map(|((label, account), output)| {
+        AddItemInput::new(label, account.as_ref(), last_modified_at, output)
+    });
+    let added = db.add_items(inputs)?;
+
+    for (item, added) in items.iter().zip(&added) {
+        db.tag_item(added.uid, PLACEHOLDER_TAG)?;
+
+        for tag in &item.tags {
+            db.tag_item(added.uid, tag)?;
+        }
+    }
+
+    note!("Imported {} placeholders from {}", added.len(), path.display());
+    note!("Fill in their secrets with `steelsafe fill <LABEL>`");
+
+    Ok(())
+}
+
+fn import_bitwarden(db: &mut Database, config: &Config, path: &Path, dry_run: bool) -> Result<()> {
+    let text = read_import_file(path, &config.limits, "Can't open Bitwarden export")?;
+    let conversion = bitwarden::convert(&text)?;
+
+    import_converted(db, config, path, conversion, dry_run)
+}
+
+fn import_browser(db: &mut Database, config: &Config, path: &Path, dry_run: bool) -> Result<()> {
+    let text = read_import_file(path, &config.limits, "Can't open browser export")?;
+    let conversion = browser::convert(&text)?;
+
+    import_converted(db, config, path, conversion, dry_run)
+}
+
+fn import_pass(db: &mut Database, config: &Config, store: Option<PathBuf>, dry_run: bool) -> Result<()> {
+    let store = store.or_else(pass::default_store_dir).ok_or(Error::PassStoreRequired)?;
+
+    note!("Decrypting the entries of {} with gpg", store.display());
+
+    let conversion = pass::import(&store)?;
+
+    import_converted(db, config, &store, conversion, dry_run)
+}
+
+/// Encrypts and adds the items converted from another password manager,
+/// after listing those that can't be added. With `dry_run`, only lists them.
+fn import_converted(
+    db: &mut Database,
+    config: &Config,
+    source: &Path,
+    conversion: Conversion,
+    dry_run: bool,
+) -> Result<()> {
+    let mut skipped = conversion.skipped;
+    let mut items = Vec::with_capacity(conversion.items.len());
+    let mut labels = HashSet::new();
+
+    // titles must be unique, both within the export and in the vault
+    for item in conversion.items {
+        let fields = Label::new(&item.label).and_then(|label| {
+            let account = item.account.as_deref().map(AccountName::optional).transpose()?.flatten();
+            config.limits.check_secret_len(item.secret.len())?;
+            Ok((label, account))
+        });
+        let is_duplicate = match &fields {
+            Ok((label, _)) => db.label_exists(label.as_str())? || !labels.insert(label.as_str().to_owned()),
+            Err(_) => false,
+        };
+
+        match fields.and_then(|fields| if is_duplicate { Err(Error::DuplicateLabel) } else { Ok(fields) }) {
+            Ok((label, account)) => items.push((item, label, account)),
+            Err(error) => skipped.push(SkippedRecord {
+                name: item.label.clone(),
+                reason: error.to_string(),
+            }),
+        }
+    }
+
+    let count = |kind| items.iter().filter(|(item, ..)| item.kind == kind).count();
+
+    note!(
+        "{} items will be added: {} passwords, {} TOTP seeds, {} notes",
+        items.len(),
+        count(ItemKind::Password),
+        count(ItemKind::Totp),
+        count(ItemKind::Note),
+    );
+
+    if conversion.merged > 0 {
+        note!("{} repeated records were merged", conversion.merged);
+    }
+
+    for record in &skipped {
+        note!("Skipping {:?}: {}", record.name, record.reason);
+    }
+
+    if dry_run || items.is_empty() {
+        return Ok(());
+    }
+
+    let password = new_encryption_password(config)?;
+    let pepper = config.pepper_file.as_deref().map(Pepper::load_or_create).transpose()?;
+    let mut progress = if io::stdout().is_terminal() { Some(ProgressBar::new()?) } else { None };
+    let mut outputs = Vec::with_capacity(items.len());
+    let last_modified_at = Utc::now();
+
+    for (index, (item, label, account)) in items.iter().enumerate() {
+        // nothing has been written yet, so stopping here leaves no partial changes
+        interrupt::check()?;
+
+        if let Some(progress) = progress.as_mut() {
+            progress.update(index, items.len(), label)?;
+        }
+
+        let input = EncryptionInput {
+            plaintext_secret: &PlaintextSecret::new(item.secret.as_bytes()),
+            label,
+            account: account.as_ref(),
+            last_modified_at,
+            pepper: pepper.as_ref(),
+        };
+        outputs.push(input.encrypt_and_authenticate(&password)?);
+    }
+
+    if let Some(mut progress) = progress {
+        progress.update(items.len(), items.len(), "done")?;
+        println!();
+    }
+
+    let inputs = items.iter().zip(&outputs).map(|((_, label, account), output)| {
+        AddItemInput::new(label, account.as_ref(), last_modified_at, output)
+    });
+    let added = db.add_items(inputs)?;
+
+    for ((item, ..), added) in items.iter().zip(&added) {
+        for tag in &item.tags {
+            db.tag_item(added.uid, tag)?;
+        }
+        if let Some(url) = item.url.as_deref() {
+            db.set_url(added.uid, Some(url))?;
+        }
+    }
+
+    note!("Imported {} items from {}", added.len(), source.display());
+
+    if !skipped.is_empty() {
+        note!("{} records were skipped; add them by hand", skipped.len());
+    }
+
+    Ok(())
+}
+
+fn export_pass(db: &Database, config: &Config, store: Option<PathBuf>, recipients: &[String]) -> Result<()> {
+    let store = store.or_else(pass::default_store_dir).ok_or(Error::PassStoreRequired)?;
+    let items = db.all_items()?;
+
+    if items.is_empty() {
+        note!("There are no items to export");
+        return Ok(());
+    }
+
+    let recipients = pass::recipients(&store, recipients)?;
+    let password = askpass::ask(config.password_command.as_ref(), "Encryption password: ", None)?;
+
+    if password.is_empty() {
+        return Err(Error::EncryptionPasswordRequired);
+    }
+
+    let pepper = config.pepper_file.as_deref().map(Pepper::load_or_create).transpose()?;
+    let mut exported = 0;
+    let mut skipped = Vec::new();
+
+    for item in &items {
+        interrupt::check()?;
+
+        let path = pass::entry_path(&store, &item.label);
+
+        if path.exists() {
+            skipped.push((item, String::from("the entry already exists")));
+            continue;
+        }
+
+        let written = item
+            .decryption_input(pepper.as_ref())
+            .decrypt_and_verify(password.as_bytes())
+            .and_then(|secret| {
+                let secret = std::str::from_utf8(&secret)?;
+                pass::write_entry(&path, &recipients, secret, item.account.as_deref())
+            });
+
+        match written {
+            Ok(()) => exported += 1,
+            Err(error) => skipped.push((item, error.to_string())),
+        }
+    }
+
+    for (item, reason) in &skipped {
+        eprintln!("Skipped {:?}: {}", item.label, reason);
+    }
+
+    note!("Exported {exported} items into {}", store.display());
+
+    Ok(())
+}
+
+/// Reads a file to be imported, refusing it if it exceeds `limits.max_import_bytes`.
+fn read_import_file(path: &Path, limits: &SizeLimits, context: &str) -> Result<Zeroizing<String>> {
+    let file = File::open(path).context(context)?;
+    let size = file.metadata()?.len();
+
+    if size > limits.max_import_bytes {
+        return Err(Error::ImportTooLarge { size, limit: limits.max_import_bytes });
+    }
+
+    let mut text = Zeroizing::new(String::new());
+    file.take(limits.max_import_bytes).read_to_string(&mut text)?;
+
+    Ok(text)
+}
+
+fn fill(db: &mut Database, config: &Config, label: &str) -> Result<()> {
+    let item = db.item_by_label(label)?.ok_or_else(|| Error::ItemNotFound(label.into()))?;
+
+    if !db.item_tags(item.uid)?.iter().any(|tag| tag == PLACEHOLDER_TAG) {
+        return Err(Error::NotPlaceholder(item.label));
+    }
+
+    let secret = if io::stdin().is_terminal() {
+        let secret = Zeroizing::new(rpassword::prompt_password("Secret: ")?);
+        let confirmation = Zeroizing::new(rpassword::prompt_password("Confirm secret: ")?);
+
+        if secret != confirmation {
+            return Err(Error::ConfirmPasswordMismatch);
+        }
+        secret
+    } else {
+        read_stdin_secret(&config.limits)?
+    };
+    let secret = secret.trim_end_matches(['\r', '\n']);
+
+    if secret.is_empty() {
+        return Err(Error::SecretRequired);
+    }
+
+    config.limits.check_secret_len(secret.len())?;
+
+    // decrypting the empty secret proves that the password is the right one
+    let pepper = config.pepper_file.as_deref().map(Pepper::load_or_create).transpose()?;
+    let prompt = format!("Password for {:?}: ", item.label);
+    let password = askpass::ask(config.password_command.as_ref(), &prompt, Some(&item.label))?;
+    item.decryption_input(pepper.as_ref()).decrypt_and_verify(password.as_bytes())?;
+
+    let secret = PlaintextSecret::new(secret.as_bytes());
+    let output = item.encrypt_replacement(&secret, &MasterPassword::new(&password)?, pepper.as_ref())?;
+    db.update_encryption([UpdateEncryptionInput::new(item.uid, &output)])?;
+    db.untag_item(item.uid, PLACEHOLDER_TAG)?;
+
+    note!("Filled in the secret of {:?}", item.label);
+
+    Ok(())
+}
+
+fn share_item(db: &Database, config: &Config, label: &str, path: &Path) -> Result<()> {
+    let item = db.item_by_label(label)?.ok_or_else(|| Error::ItemNotFound(label.into()))?;
+    let secret = decrypt_item(&item, config)?;
+    let passphrase = share::write_share_file(
+        path,
+        &Label::verbatim(&item.label),
+        item.account.as_deref().map(AccountName::verbatim).as_ref(),
+        &PlaintextSecret::new(secret.as_bytes()),
+    )?;
+
+    note!("Shared {:?} in {}", item.label, path.display());
+    println!("Passphrase (shown only once): {}", passphrase.as_str());
+
+    Ok(())
+}
+
+fn emergency_kit(
+    db: &Database,
+    config: &Config,
+    path: &Path,
+    labels: &[String],
+    format: Option<KitFormat>,
+) -> Result<()> {
+    let items: Vec<Item> = labels
+        .iter()
+        .map(|label| db.item_by_label(label)?.ok_or_else(|| Error::ItemNotFound(label.into())))
+        .collect::<Result<_>>()?;
+
+    if path.try_exists()? {
+        let error = io::Error::from(io::ErrorKind::AlreadyExists);
+        return Err(Error::context(error, "Can't create emergency kit"));
+    }
+
+    note!("The emergency kit will contain {} secrets in plain text.", items.len());
+
+    if !confirm("Anyone who reads it can use them. Continue? [y/N] ", &["y", "yes"])?
+        || !confirm("Are you sure? Type `yes` to confirm: ", &["yes"])?
+    {
+        return Err(Error::Cancelled);
+    }
+
+    let mut secrets = Vec::with_capacity(items.len());
+
+    for item in items {
+        let secret = decrypt_item(&item, config)?;
+
+        if config.access_log {
+            db.log_access(item.uid)?;
+        }
+
+        secrets.push(KitSecret { label: item.label, account: item.account, secret });
+    }
+
+    let mut locations = vec![("Database", config.db_file_path()?.display().to_string())];
+
+    if let Some((rc_path, _)) = Config::find_rc_file()? {
+        locations.push(("Configuration", rc_path.display().to_string()));
+    }
+    if let Some(pepper_file) = config.pepper_file.as_ref() {
+        locations.push(("Pepper file", pepper_file.display().to_string()));
+    }
+    if let Some(git_sync) = config.git_sync.as_ref() {
+        locations.push(("Git synchronization", git_sync.repo.display().to_string()));
+    }
+    if let Some(remote_backup) = config.remote_backup.as_ref() {
+        locations.push(("Remote backups", remote_backup.target.to_string()));
+    }
+
+    let kit = EmergencyKit {
+        created_at: Utc::now(),
+        locations,
+        item_count: db.stats(Utc::now())?.item_count,
+        secrets,
+    };
+    let contents = match format.unwrap_or_else(|| KitFormat::from_path(path)) {
+        KitFormat::Text => kit.to_text()?,
+        KitFormat::Html => kit.to_html()?,
+    };
+
+    kit::write_kit_file(path, &contents)?;
+    note!("Wrote the emergency kit to {}. Print it, then delete the file.", path.display());
+
+    Ok(())
+}
+
+/// Asks a question on the terminal, and returns whether the answer is one of
+/// `accepted` (compared case-insensitively).
+fn confirm(question: &str, accepted: &[&str]) -> Result<bool> {
+    let mut answer = String::new();
+
+    eprint!("{question}");
+    io::stderr().flush()?;
+    io::stdin().read_line(&mut answer)?;
+
+    let answer = answer.trim();
+
+    Ok(accepted.iter().any(|accepted| answer.eq_ignore_ascii_case(accepted)))
+}
+
+fn reveal(path: &Path, shred: bool) -> Result<()> {
+    let passphrase = Zeroizing::new(rpassword::prompt_password("Passphrase: ")?);
+    let (item, secret) = share::read_share_file(path, &passphrase)?;
+
+    match item.account.as_deref() {
+        Some(account) => note!("{} ({})", item.label, account),
+        None => note!("{}", item.label),
+    }
+    println!("{}", std::str::from_utf8(&secret)?);
+
+    if shred {
+        share::shred(path)?;
+    }
+
+    Ok(())
+}
+
+fn create_shares(db: &Database, config: &Config, threshold: u8, count: u8, out: &Path) -> Result<()> {
+    let password = askpass::ask(config.password_command.as_ref(), "Encryption password: ", None)?;
+
+    if password.is_empty() {
+        return Err(Error::EncryptionPasswordRequired);
+    }
+    if item_decrypted_by(db, config, &password)?.is_none() {
+        note!("The vault is empty, so the password can't be checked");
+    }
+
+    let shares = shamir::split(&password, threshold, count)?;
+    let created_at = Utc::now();
+
+    for share in &shares {
+        let path = out.join(format!("steelsafe-share-{}-of-{}.txt", share.number(), count));
+        let contents = shamir::sheet(share, count, created_at)?;
+
+        shamir::write_sheet_file(&path, &contents)?;
+        note!("Wrote share {} to {}", share.number(), path.display());
+    }
+
+    note!(
+        "Any {} of the {} shares restore the password. Print them, hand them out, then delete the files.",
+        threshold,
+        count,
+    );
+
+    Ok(())
+}
+
+fn restore_password(db: &Database, config: &Config) -> Result<()> {
+    let mut shares = Vec::new();
+
+    if io::stdin().is_terminal() {
+        // the first share tells how many are needed
+        while shares.first().is_none_or(|first: &KeyShare| shares.len() < usize::from(first.threshold())) {
+            let text = Zeroizing::new(rpassword::prompt_password(format!("Share {}: ", shares.len() + 1))?);
+
+            match KeyShare::parse(&text) {
+                Ok(share) => shares.push(share),
+                Err(error) => eprintln!("{error}; try again"),
+            }
+        }
+    } else {
+        let text = read_stdin_secret(&config.limits)?;
+
+        for line in text.lines().filter(|line| !line.trim().is_empty()) {
+            shares.push(KeyShare::parse(line)?);
+        }
+    }
+
+    let password = shamir::combine(&shares)?;
+
+    if let Some(label) = item_decrypted_by(db, config, &password)? {
+        note!("The password decrypts {label:?}");
+    }
+    println!("{}", password.as_str());
+
+    Ok(())
+}
+
+/// Returns the label of the first item that the password decrypts, `None` if
+/// the vault is empty, and an error if it doesn't decrypt any of the items.
+fn item_decrypted_by(db: &Database, config: &Config, password: &str) -> Result<Option<String>> {
+    let items = db.all_items()?;
+    let pepper = config.pepper_file.as_deref().map(Pepper::load_or_create).transpose()?;
+
+    for item in &items {
+        match item.decryption_input(pepper.as_ref()).decrypt_and_verify(password.as_bytes()) {
+            Ok(_) => return Ok(Some(item.label.clone())),
+            Err(Error::XChaCha20Poly1305(_)) => {}
+            Err(error) => return Err(error),
+        }
+    }
+
+    if items.is_empty() { Ok(None) } else { Err(Error::NoItemDecrypted) }
+}
+
+fn team(db: &mut Database, config: &Config, action: TeamAction) -> Result<()> {
+    match action {
+        TeamAction::Members => {
+            let rows: Vec<Vec<String>> = db
+                .recipients()?
+                .into_iter()
+                .map(|member| {
+                    vec![member.name, member.public_key, member.added_at.format("%Y-%m-%d").to_string()]
+                })
+                .collect();
+
+            print_table(&["NAME", "PUBLIC KEY", "ADDED"], &rows);
+            note!("{} items are shared with the team", db.envelopes()?.len());
+        }
+        TeamAction::AddMember { name, public_key } => {
+            let public_key = org::parse_recipient(&public_key)?.to_string();
+            let mut members = db.recipients()?;
+
+            if members.iter().any(|member| member.name == name || member.public_key == public_key) {
+                return Err(Error::DuplicateRecipient(name));
+            }
+
+            let member = Recipient { name, public_key, added_at: Utc::now() };
+            members.push(member.clone());
+
+            let envelopes = rewrap_envelopes(db, config, &members)?;
+            let count = envelopes.len();
+            let name = member.name.clone();
+
+            db.add_recipient(member, envelopes)?;
+            note!("Added {:?}; re-encrypted {} shared items", name, count);
+        }
+        TeamAction::RemoveMember { name } => {
+            let mut members = db.recipients()?;
+            let index = members
+                .iter()
+                .position(|member| member.name == name)
+                .ok_or_else(|| Error::RecipientNotFound(name.clone()))?;
+
+            members.remove(index);
+
+            if members.is_empty() && !db.envelopes()?.is_empty() {
+                return Err(Error::LastRecipient);
+            }
+
+            let envelopes = rewrap_envelopes(db, config, &members)?;
+            let count = envelopes.len();
+
+            db.remove_recipient(&name, envelopes)?;
+            note!("Removed {:?}; re-encrypted {} shared items", name, count);
+        }
+        TeamAction::Share { label } => {
+            let item = db.item_by_label(&label)?.ok_or_else(|| Error::ItemNotFound(label.clone()))?;
+            let public_keys = db
+                .recipients()?
+                .iter()
+                .map(|member| org::parse_recipient(&member.public_key))
+                .collect::<Result<Vec<_>>>()?;
+
+            if public_keys.is_empty() {
+                return Err(Error::NoRecipients);
+            }
+
+            let secret = decrypt_item(&item, config)?;
+
+            if config.access_log {
+                db.log_access(item.uid)?;
+            }
+
+            db.set_envelope(item.uid, Some(org::seal(secret.as_bytes(), &public_keys)?))?;
+            note!("Shared {:?} with {} members", label, public_keys.len());
+        }
+        TeamAction::Unshare { label } => {
+            let item = db.item_by_label(&label)?.ok_or_else(|| Error::ItemNotFound(label.clone()))?;
+
+            if db.envelope(item.uid)?.is_none() {
+                return Err(Error::NotShared(label));
+            }
+
+            db.set_envelope(item.uid, None)?;
+            note!("{label:?} is no longer shared with the team");
+        }
+        TeamAction::Get { label } => {
+            let item = db.item_by_label(&label)?.ok_or_else(|| Error::ItemNotFound(label.clone()))?;
+            let envelope = db.envelope(item.uid)?.ok_or_else(|| Error::NotShared(label))?;
+            let identity_file = config.identity_file.as_deref().ok_or(Error::IdentityRequired)?;
+            let secret = org::open(&envelope.envelope, &org::load_identity(identity_file)?)?;
+
+            if config.access_log {
+                db.log_access(item.uid)?;
+            }
+
+            println!("{}", std::str::from_utf8(&secret)?);
+        }
+    }
+
+    Ok(())
+}
+
+/// Opens the envelope of every shared item with the identity of the user, and
+/// encrypts the secrets again for `members`. Without shared items, no identity
+/// is needed.
+fn rewrap_envelopes(db: &Database, config: &Config, members: &[Recipient]) -> Result<Vec<ItemEnvelope>> {
+    let envelopes = db.envelopes()?;
+
+    if envelopes.is_empty() {
+        return Ok(envelopes);
+    }
+
+    let identity_file = config.identity_file.as_deref().ok_or(Error::IdentityRequired)?;
+    let identity = org::load_identity(identity_file)?;
+    let public_keys = members
+        .iter()
+        .map(|member| org::parse_recipient(&member.public_key))
+        .collect::<Result<Vec<_>>>()?;
+
+    envelopes
+        .into_iter()
+        .map(|ItemEnvelope { item_uid, envelope }| {
+            let secret = org::open(&envelope, &identity)?;
+            Ok(ItemEnvelope { item_uid, envelope: org::seal(&secret, &public_keys)? })
+        })
+        .collect()
+}
+
+fn sync(db: &mut Database, config: &Config) -> Result<()> {
+    let sync_config = config.git_sync.as_ref().ok_or(Error::SyncNotConfigured)?;
+    let git_sync = GitSync::new(sync_config, Network::new(config));
+    let password = git_sync.ask_password()?;
+    let report = git_sync.sync(db, password.as_bytes())?;
+
+    note!(
+        "Synchronized with {}; received {} and sent {} changes",
+        sync_config.repo.display(),
+        report.received,
+        report.sent,
+    );
+
+    for label in &report.conflicts {
+        eprintln!("Warning: {label:?} is the label of different items on another device; rename one of them");
+    }
+
+    Ok(())
+}
+
+fn merge(db: &mut Database, config: &Config, path: &Path, on_conflict: Option<Resolution>) -> Result<()> {
+    perms::create_private_file(path)?;
+
+    let mut other = Database::open(path, &config.db_options)?;
+    let conflicts = resolve_conflicts(oplog::conflicts(db, &other)?, config, on_conflict)?;
+
+    db.resolve_conflicts(&conflicts)?;
+
+    let report = oplog::sync(db, &mut other)?;
+
+    note!(
+        "Merged with {}; received {} and sent {} changes",
+        path.display(),
+        report.received,
+        report.sent,
+    );
+
+    for label in &report.conflicts {
+        eprintln!("Warning: {label:?} is the label of different items in the two vaults; rename one of them");
+    }
+
+    Ok(())
+}
+
+fn split(db: &mut Database, config: &Config, tag: &str, out: &Path, copy: bool) -> Result<()> {
+    perms::create_private_file(out)?;
+
+    let mut other = Database::open(out, &config.db_options)?;
+    let count = transfer_items(db, &mut other, Some(tag), copy)?;
+    let verb = if copy { "Copied" } else { "Moved" };
+
+    note!("{} {} items tagged {:?} to {}", verb, count, tag, out.display());
+
+    Ok(())
+}
+
+fn join(db: &mut Database, config: &Config, path: &Path, tag: Option<&str>, copy: bool) -> Result<()> {
+    // opening a database that doesn't exist would create it
+    File::open(path).context("Can't open the other vault")?;
+
+    let mut other = Database::open(path, &config.db_options)?;
+    let count = transfer_items(&mut other, db, tag, copy)?;
+    let verb = if copy { "Copied" } else { "Moved" };
+
+    note!("{} {} items from {}", verb, count, path.display());
+
+    Ok(())
+}
+
+/// Copies the items of `source` (only those with `tag`, if given) into `target`,
+/// then deletes them from `source`, unless `copy` is set. Returns their number.
+///
+/// The copies are new items of `target` as far as replication is concerned, and
+/// the originals are deleted from `source` in a separate transaction, after the
+/// copies have been committed; if that fails, the items are in both vaults, and
+/// the error lists their labels. Attaching one database to the other wouldn't
+/// help: in WAL mode, SQLite only commits atomically within each database file,
+/// not across them.
+fn transfer_items(source: &mut Database, target: &mut Database, tag: Option<&str>, copy: bool) -> Result<usize> {
+    if source.replica_id()? == target.replica_id()? {
+        return Err(Error::SameReplica);
+    }
+
+    let tags = source.tags_by_item()?;
+    let items: Vec<Item> = source
+        .all_items()?
+        .into_iter()
+        .filter(|item| {
+            tag.is_none_or(|tag| {
+                tags.get(&item.uid).is_some_and(|item_tags| item_tags.iter().any(|t| t == tag.trim()))
+            })
+        })
+        .collect();
+    let uids: Vec<u64> = items.iter().map(|item| item.uid).collect();
+    let labels: Vec<String> = items.iter().map(|item| item.label.clone()).collect();
+
+    if items.iter().any(|item| item.peppered) {
+        eprintln!("Warning: peppered items can only be decrypted with the same pepper file in the other vault");
+    }
+
+    target.copy_items_from(source, items)?;
+
+    if !copy {
+        source.delete_items(uids.iter().copied()).map_err(|error| Error::TransferIncomplete {
+            labels,
+            source: Box::new(error),
+        })?;
+    }
+
+    Ok(uids.len())
+}
+
+/// Re-encrypts every item using outdated algorithms with a single password.
+/// The database is only updated once all items have been processed, within a
+/// single transaction, so an interrupted upgrade doesn't leave partial changes.
+fn upgrade_crypto(db: &mut Database, config: &Config) -> Result<()> {
+    let items: Vec<Item> = db
+        .all_items()?
+        .into_iter()
+        .filter(Item::needs_crypto_upgrade)
+        .collect();
+
+    if items.is_empty() {
+        note!("Every item already uses the current algorithms");
+        return Ok(());
+    }
+
+    note!("{} items use outdated algorithms", items.len());
+
+    let password = askpass::ask(config.password_command.as_ref(), "Encryption password: ", None)?;
+    let password = MasterPassword::new(&password)?;
+
+    let pepper = config.pepper_file.as_deref().map(Pepper::load_or_create).transpose()?;
+    let mut progress = if io::stdout().is_terminal() { Some(ProgressBar::new()?) } else { None };
+    let mut upgraded = Vec::with_capacity(items.len());
+    let mut skipped = Vec::new();
+
+    for (index, item) in items.iter().enumerate() {
+        // nothing has been written yet, so stopping here leaves no partial changes
+        interrupt::check()?;
+
+        if let Some(progress) = progress.as_mut() {
+            progress.update(index, items.len(), &item.label)?;
+        }
+
+        match item.reencrypt(&password, pepper.as_ref()) {
+            Ok(output) => upgraded.push((item, output)),
+            Err(error) => skipped.push((item, error)),
+        }
+    }
+
+    if let Some(mut progress) = progress {
+        progress.update(items.len(), items.len(), "done")?;
+        println!();
+    }
+
+    let updates = upgraded.iter().map(|(item, output)| UpdateEncryptionInput::new(item.uid, output));
+    db.update_encryption(updates)?;
+
+    for (item, error) in &skipped {
+        eprintln!("Skipped {:?}: {}", item.label, error);
+    }
+
+    note!("Upgraded {} items, skipped {}", upgraded.len(), skipped.len());
+
+    Ok(())
+}
+
+fn compact(db: &Database) -> Result<()> {
+    let compaction = db.compact()?;
+
+    if let (Some(before), Some(after)) = (compaction.size_before, compaction.size_after) {
+        note!("Compacted the database from {} to {}", format_file_size(before), format_file_size(after));
+    } else {
+        note!("Compacted the database");
+    }
+
+    Ok(())
+}
+
+fn audit(db: &Database, config: &Config, wordlist: &Path) -> Result<()> {
+    // check the list before asking for the password
+    let list = File::open(wordlist).context("Can't open password list")?;
+    let items = db.all_items()?;
+
+    if items.is_empty() {
+        note!("There are no items to check");
+        return Ok(());
+    }
+
+    let password = askpass::ask(config.password_command.as_ref(), "Encryption password: ", None)?;
+
+    if password.is_empty() {
+        return Err(Error::EncryptionPasswordRequired);
+    }
+
+    let pepper = config.pepper_file.as_deref().map(Pepper::load_or_create).transpose()?;
+    let mut progress = if io::stdout().is_terminal() { Some(ProgressBar::new()?) } else { None };
+    let mut checked = Vec::with_capacity(items.len());
+    let mut skipped = Vec::new();
+
+    for (index, item) in items.iter().enumerate() {
+        interrupt::check()?;
+
+        if let Some(progress) = progress.as_mut() {
+            progress.update(index, items.len(), &item.label)?;
+        }
+
+        let decrypted = item
+            .decryption_input(pepper.as_ref())
+            .decrypt_and_verify(password.as_bytes())
+            .and_then(|secret| Ok(Zeroizing::new(std::str::from_utf8(&secret)?.to_owned())));
+
+        match decrypted {
+            Ok(secret) => checked.push((item, secret)),
+            Err(error) => skipped.push((item, error)),
+        }
+    }
+
+    if let Some(mut progress) = progress {
+        progress.update(items.len(), items.len(), "done")?;
+        println!();
+    }
+
+    let secrets: Vec<&str> = checked.iter().map(|(_, secret)| secret.as_str()).collect();
+    let listed = wordlist::find_listed(BufReader::new(list), &secrets)?;
+
+    for &index in &listed {
+        let (item, _) = &checked[index];
+
+        match item.account.as_deref() {
+            Some(account) => println!("{}\t{}", item.label, account),
+            None => println!("{}", item.label),
+        }
+    }
+
+    for (item, error) in &skipped {
+        eprintln!("Skipped {:?}: {}", item.label, error);
+    }
+
+    note!(
+        "{} of {} checked items have a secret found in {}, skipped {}",
+        listed.len(),
+        checked.len(),
+        wordlist.display(),
+        skipped.len(),
+    );
+
+    Ok(())
+}
+
+fn audit_report(db: &Database, config: &Config, path: &Path) -> Result<()> {
+    if path.try_exists()? {
+        let error = io::Error::from(io::ErrorKind::AlreadyExists);
+        return Err(Error::context(error, "Can't create audit report"));
+    }
+
+    let password = askpass::ask(config.password_command.as_ref(), "Encryption password: ", None)?;
+
+    if password.is_empty() {
+        return Err(Error::EncryptionPasswordRequired);
+    }
+
+    // don't sign with a key derived from a mistyped password
+    item_decrypted_by(db, config, &password)?;
+
+    let now = Utc::now();
+    let mut tags = db.tags_by_item()?;
+    let expiries = db.expiries()?;
+    let recovery_codes = db.recovery_codes()?;
+    let shared: HashSet<u64> = db.envelopes()?.into_iter().map(|envelope| envelope.item_uid).collect();
+    let items: Vec<ReportItem> = db
+        .all_items()?
+        .into_iter()
+        .map(|item| {
+            let tags = tags.remove(&item.uid).unwrap_or_default();
+            let expires_at = expiries.get(&item.uid).copied();
+            let flags = [
+                (Flag::Expired, expires_at.is_some_and(|expires_at| expires_at <= now)),
+                (Flag::OutdatedEncryption, item.needs_crypto_upgrade()),
+                (Flag::Peppered, item.peppered),
+                (Flag::Reprompt, item.reprompt),
+                (Flag::Placeholder, tags.iter().any(|tag| tag == PLACEHOLDER_TAG)),
+                (
+                    Flag::NoRecoveryCodesLeft,
+                    recovery_codes.get(&item.uid).is_some_and(|codes| codes.remaining == 0),
+                ),
+                (Flag::Shared, shared.contains(&item.uid)),
+            ];
+
+            ReportItem {
+                label: item.label,
+                account: item.account,
+                last_modified_at: item.last_modified_at,
+                expires_at,
+                flags: flags.into_iter().filter_map(|(flag, set)| set.then_some(flag)).collect(),
+                tags,
+            }
+        })
+        .collect();
+    let count = items.len();
+    let report = Report {
+        version: report::VERSION,
+        created_at: now,
+        vault: db.replica_id()?,
+        items,
+    };
+    let pepper = config.pepper_file.as_deref().map(Pepper::load_or_create).transpose()?;
+    let params = db.signing_key_params(SigningKeyParams::generate(pepper.as_ref()))?;
+    let key = params.derive_key(password.as_bytes(), pepper.as_ref())?;
+    let contents = report.sign(&key)?;
+    let mut file = File::create_new(path).context("Can't create audit report")?;
+    file.write_all(contents.as_bytes())?;
+
+    note!("Wrote the audit report of {} items to {}", count, path.display());
+    note!("Public key of the vault: {}", report::encode_public_key(&key.verifying_key()));
+
+    Ok(())
+}
+
+fn verify_report(config: &Config, path: &Path, public_key: Option<&str>) -> Result<()> {
+    let text = read_import_file(path, &config.limits, "Can't open audit report")?;
+    let (report, signed_with) = report::verify(&text)?;
+
+    match public_key {
+        Some(public_key) => {
+            if report::decode_public_key(public_key)? != signed_with {
+                return Err(Error::ReportKeyMismatch);
+            }
+        }
+        None => {
+            note!(
+                "Signed with {}; pass `--public-key` to check that it's the key of the vault",
+                report::encode_public_key(&signed_with),
+            );
+        }
+    }
+
+    note!(
+        "The report of {} items, made at {}, hasn't been altered",
+        report.items.len(),
+        report.created_at.format("%Y-%m-%d %H:%M:%S UTC"),
+    );
+
+    Ok(())
+}
+
+fn verify(db: &mut Database, config: &Config) -> Result<()> {
+    let items = db.all_items()?;
+    let quarantined = db.quarantined_items()?.len();
+
+    if quarantined > 0 {
+        note!("{} items are already in quarantine", quarantined);
+    }
+    if items.is_empty() {
+        note!("There are no items to verify");
+        return Ok(());
+    }
+
+    let password = askpass::ask(config.password_command.as_ref(), "Encryption password: ", None)?;
+
+    if password.is_empty() {
+        return Err(Error::EncryptionPasswordRequired);
+    }
+
+    let pepper = config.pepper_file.as_deref().map(Pepper::load_or_create).transpose()?;
+    let mut progress = if io::stdout().is_terminal() { Some(ProgressBar::new()?) } else { None };
+    let mut failed = Vec::new();
+    let mut skipped = Vec::new();
+
+    for (index, item) in items.iter().enumerate() {
+        interrupt::check()?;
+
+        if let Some(progress) = progress.as_mut() {
+            progress.update(index, items.len(), &item.label)?;
+        }
+
+        match item.decryption_input(pepper.as_ref()).decrypt_and_verify(password.as_bytes()) {
+            Ok(_) => {}
+            Err(Error::XChaCha20Poly1305(_)) => failed.push(item),
+            Err(error) => skipped.push((item, error)),
+        }
+    }
+
+    if let Some(mut progress) = progress {
+        progress.update(items.len(), items.len(), "done")?;
+        println!();
+    }
+
+    for item in &failed {
+        match item.account.as_deref() {
+            Some(account) => println!("{}\t{}", item.label, account),
+            None => println!("{}", item.label),
+        }
+    }
+
+    for (item, error) in &skipped {
+        eprintln!("Skipped {:?}: {}", item.label, error);
+    }
+
+    note!(
+        "{} of {} items failed authentication, skipped {}",
+        failed.len(),
+        items.len(),
+        skipped.len(),
+    );
+
+    if failed.is_empty() {
+        return Ok(());
+    }
+
+    note!("Items encrypted with a different password fail, too; don't quarantine those");
+
+    if !confirm("Move them to quarantine? [y/N] ", &["y", "yes"])? {
+        return Ok(());
+    }
+
+    let count = db.quarantine_items(failed.iter().map(|item| item.uid), Utc::now())?;
+
+    note!("Moved {} items to quarantine", count);
+
+    Ok(())
+}
+
+/// The answer to a question asked while rotating passwords.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum RotationStep {
+    Proceed,
+    Skip,
+    Quit,
+}
+
+impl RotationStep {
+    /// Asks the question on the terminal. The end of the input means quitting.
+    fn ask(question: &str) -> Result<Self> {
+        loop {
+            let mut answer = String::new();
+
+            eprint!("{question}");
+            io::stderr().flush()?;
+
+            if io::stdin().read_line(&mut answer)? == 0 {
+                return Ok(RotationStep::Quit);
+            }
+
+            match answer.trim().to_ascii_lowercase().as_str() {
+                "" => return Ok(RotationStep::Proceed),
+                "s" | "skip" => return Ok(RotationStep::Skip),
+                "q" | "quit" => return Ok(RotationStep::Quit),
+                _ => eprintln!("Press Enter, or type `skip` or `quit`."),
+            }
+        }
+    }
+}
+
+fn rotate(db: &mut Database, config: &Config, query: &str) -> Result<()> {
+    let recovery_codes = db.recovery_codes()?;
+    let items: Vec<Item> = Query::parse(query)?
+        .find_items(db)?
+        .into_iter()
+        // a password would replace the codes, instead of ticking one off
+        .filter(|item| !recovery_codes.contains_key(&item.uid))
+        .map(|item| db.item_by_id(item.uid))
+        .collect::<Result<_>>()?;
+
+    if items.is_empty() {
+        note!("No items to rotate");
+        return Ok(());
+    }
+
+    note!("{} items will be rotated:", items.len());
+
+    for item in &items {
+        note!("  {}", item.label);
+    }
+
+    note!("At each step, type `skip` to leave the item unchanged, or `quit` to stop.");
+
+    let password = askpass::ask(config.password_command.as_ref(), "Encryption password: ", None)?;
+    let password = MasterPassword::new(&password)?;
+
+    let pepper = config.pepper_file.as_deref().map(Pepper::load_or_create).transpose()?;
+    let mut clipboard = Clipboard::new(&config.clipboard);
+    let mut rotated = 0;
+    let mut not_rotated = Vec::new();
+    let mut remaining = items.iter();
+
+    for item in remaining.by_ref() {
+        interrupt::check()?;
+        note!("[{}/{}] {}", rotated + not_rotated.len() + 1, items.len(), item.label);
+
+        // the password is checked first, so that a new one is never made in vain
+        let current = match item.decryption_input(pepper.as_ref()).decrypt_and_verify(password.as_bytes()) {
+            Ok(current) => current,
+            Err(error) => {
+                note!("  Skipped: {error}");
+                not_rotated.push(item);
+                continue;
+            }
+        };
+
+        if config.access_log {
+            db.log_access(item.uid)?;
+        }
+
+        clipboard.set_text(std::str::from_utf8(&current)?, config.copy_to)?;
+
+        match RotationStep::ask("  Copied the current password. Press Enter to copy the new one: ")? {
+            RotationStep::Proceed => {}
+            RotationStep::Skip => {
+                not_rotated.push(item);
+                continue;
+            }
+            RotationStep::Quit => {
+                not_rotated.push(item);
+                break;
+            }
+        }
+
+        let tags = db.item_tags(item.uid)?;
+        let item_type = if tags.iter().any(|tag| tag == NOTE_TAG) { ItemType::Note } else { ItemType::Secret };
+        let new_password = Policy::for_item(&config.policies, item_type, &tags).generate_password();
+        clipboard.set_text(&new_password, config.copy_to)?;
+
+        match RotationStep::ask("  Copied the new password. Change it, then press Enter to store it: ")? {
+            RotationStep::Proceed => {}
+            RotationStep::Skip => {
+                not_rotated.push(item);
+                continue;
+            }
+            RotationStep::Quit => {
+                note!("  The new password was not stored");
+                not_rotated.push(item);
+                break;
+            }
+        }
+
+        let modified_at = Utc::now();
+        let output = item.encrypt_new_secret(
+            &PlaintextSecret::new(new_password.as_bytes()),
+            &password,
+            pepper.as_ref(),
+            modified_at,
+        )?;
+        db.replace_secret(item.uid, modified_at, &output)?;
+        rotated += 1;
+    }
+
+    not_rotated.extend(remaining);
+
+    for item in &not_rotated {
+        eprintln!("Not rotated: {:?}", item.label);
+    }
+
+    note!("Rotated {} of {} items", rotated, items.len());
+
+    Ok(())
+}
+
+/// A single-line progress bar, drawn in place below the cursor.
+struct ProgressBar {
+    terminal: Terminal<CrosstermBackend<Stdout>>,
+}
+
+impl ProgressBar {
+    fn new() -> Result<Self> {
+        let options = TerminalOptions { viewport: Viewport::Inline(1) };
+        let terminal = Terminal::with_options(CrosstermBackend::new(io::stdout()), options)?;
+
+        Ok(ProgressBar { terminal })
+    }
+
+    fn update(&mut self, done: usize, total: usize, current: &str) -> Result<()> {
+        let ratio = if total == 0 { 1.0 } else { done as f64 / total as f64 };
+        let gauge = Gauge::default()
+            .ratio(ratio)
+            .label(format!("{done}/{total}: {current}"))
+            .gauge_style(Style::default().fg(Color::Green).bg(Color::DarkGray))
+            .use_unicode(true);
+
+        self.terminal.draw(|frame| frame.render_widget(gauge, frame.area()))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+fn run_agent(db: &Database, config: &Config) -> Result<()> {
+    let socket_path = config.agent_socket_path()?;
+    let pepper = config.pepper_file.as_deref().map(Pepper::load_or_create).transpose()?;
+
+    note!("Agent listening on {}", socket_path.display());
+
+    agent::serve(db, pepper, &socket_path)
+}
+
+#[cfg(feature = "serve")]
+fn run_server(db: &Database, config: &Config, listen: SocketAddr) -> Result<()> {
+    let pepper = config.pepper_file.as_deref().map(Pepper::load_or_create).transpose()?;
+    let listener = serve::bind(listen)?;
+    let token_path = config.serve_token_path()?;
+    let token = serve::create_token(&token_path)?;
+
+    note!("Serving the API on http://{listen}/");
+    note!("The access token is in {}", token_path.display());
+
+    let result = serve::Server::new(db, config, pepper, token)?.run(&listener);
+    let _ = std::fs::remove_file(&token_path);
+
+    result
+}
+
+#[cfg(unix)]
+fn unlock_agent(config: &Config, ttl: Option<u64>) -> Result<()> {
+    let password = askpass::ask(config.password_command.as_ref(), "Encryption password: ", None)?;
+
+    if password.is_empty() {
+        return Err(Error::EncryptionPasswordRequired);
+    }
+
+    let ttl_secs = ttl
+        .or(config.agent.ttl_secs)
+        .unwrap_or(agent::DEFAULT_TTL.as_secs());
+    let request = agent::Request::Unlock { password: password.as_str().into(), ttl_secs };
+
+    agent_request(config, &request)
+}
+
+#[cfg(unix)]
+fn agent_request(config: &Config, request: &agent::Request) -> Result<()> {
+    match agent::request(&config.agent_socket_path()?, request)? {
+        agent::Response::Error { ref message } => Err(Error::Agent(message.clone())),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(feature = "remote")]
+fn check_update(config: &Config) -> Result<()> {
+    use std::time::Duration;
+    use serde::Deserialize;
+
+    #[derive(Deserialize)]
+    struct CrateResponse {
+        #[serde(rename = "crate")]
+        krate: CrateInfo,
+    }
+
+    #[derive(Deserialize)]
+    struct CrateInfo {
+        max_stable_version: String,
+    }
+
+    let url = "https://crates.io/api/v1/crates/steelsafe";
+    let response = Network::new(config).get_cached(url, Duration::from_secs(24 * 60 * 60))?;
+    let latest = serde_json::from_slice::<CrateResponse>(&response.body)?.krate.max_stable_version;
+    let current = env!("CARGO_PKG_VERSION");
+
+    if response.stale {
+        note!("Can't reach crates.io; using the answer from {}", response.fetched_at);
+    }
+
+    // versions compare numerically, component by component
+    let parse = |version: &str| -> Vec<u64> {
+        version.split('.').map(|part| part.parse().unwrap_or(0)).collect()
+    };
+
+    if parse(&latest) > parse(current) {
+        println!("steelsafe {latest} is available (installed: {current})");
+    } else {
+        println!("steelsafe {current} is up to date");
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "remote")]
+fn backup(db: &Database, config: &Config, if_due: bool, encrypt: bool) -> Result<()> {
+    use chrono::{Utc, Days};
+    use crate::remote::{self, Credentials};
+
+    let backup_config = config.remote_backup.as_ref().ok_or(Error::BackupNotConfigured)?;
+
+    if if_due {
+        let interval = Days::new(backup_config.interval_days.unwrap_or(1).into());
+
+        if let Some(last) = db.last_remote_backup()? {
+            if last.checked_add_days(interval).is_some_and(|due| Utc::now() < due) {
+                note!("Last backup was made at {last}, next one is not due yet");
+                return Ok(());
+            }
+        }
+    }
+
+    let label = &backup_config.credentials_item;
+    let item = db.item_by_label(label)?.ok_or_else(|| Error::MissingCredentials(label.clone()))?;
+    let credentials = Credentials {
+        username: item.account.clone().unwrap_or_default(),
+        password: decrypt_item(&item, config)?,
+    };
+
+    let items: Vec<ExportedItem> = db.all_items()?.into_iter().map(Into::into).collect();
+    let bundle = Bundle::new(items);
+    let password = if encrypt { Some(new_bundle_password()?) } else { None };
+    let mut bytes = Zeroizing::new(Vec::new());
+    bundle.write_to(&mut *bytes, password.as_ref().map(|p| p.as_bytes()))?;
+
+    let url = remote::upload(&Network::new(config), &backup_config.target, &credentials, &bytes)?;
+    db.set_last_remote_backup(Utc::now())?;
+
+    note!("Uploaded {} items to {}", bundle.items.len(), url);
+
+    Ok(())
+}
+
+/// Decrypts the secret of an item using the agent if possible, and otherwise
+/// (e.g. if the item always asks for its password) by asking for its password
+/// on the terminal.
+pub fn decrypt_item(item: &Item, config: &Config) -> Result<Zeroizing<String>> {
+    // the agent refuses such items anyway, but it may be an older version
+    #[cfg(unix)]
+    if !item.reprompt {
+        if let Some(secret) = agent::try_decrypt(&config.agent_socket_path()?, &item.label) {
+            return Ok(secret);
+        }
+    }
+
+    let pepper = config.pepper_file.as_deref().map(Pepper::load_or_create).transpose()?;
+    let prompt = format!("Password for {:?}: ", item.label);
+    let enc_pass = askpass::ask(config.password_command.as_ref(), &prompt, Some(&item.label))?;
+    let secret = item.decryption_input(pepper.as_ref()).decrypt_and_verify(enc_pass.as_bytes())?;
+
+    Ok(Zeroizing::new(std::str::from_utf8(&secret)?.to_owned()))
+}
+
+/// Asks for the password of an encrypted bundle on the terminal.
+fn bundle_password() -> Result<Zeroizing<String>> {
+    let password = Zeroizing::new(rpassword::prompt_password("Bundle password: ")?);
+
+    if password.is_empty() {
+        return Err(Error::EncryptionPasswordRequired);
+    }
+
+    Ok(password)
+}
+
+/// Asks for a new bundle password twice, so that typos don't make the export unreadable.
+fn new_bundle_password() -> Result<Zeroizing<String>> {
+    let password = bundle_password()?;
+    let confirmation = Zeroizing::new(rpassword::prompt_password("Confirm bundle password: ")?);
+
+    if password != confirmation {
+        return Err(Error::ConfirmPasswordMismatch);
+    }
+
+    Ok(password)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use chrono::Utc;
+    use nanosql::Null;
+    use crate::config::{Config, DatabaseOptions};
+    use crate::crypto::{KdfParams, CipherId};
+    use crate::db::{Database, AddItemInput};
+    use crate::fields::Label;
+    use crate::perms;
+    use crate::error::{Error, Result};
+    use super::{split, join, transfer_items};
+
+
+    /// Adds two items tagged `work`, and one tagged `home`.
+    fn add_samples(db: &Database) -> Result<()> {
+        for (label, tag, salt) in [("VPN", "work", 1), ("Home Wi-Fi", "home", 2), ("Intranet", "work", 3)] {
+            let item = db.add_item(AddItemInput {
+                uid: Null,
+                label: &Label::new(label)?,
+                account: None,
+                last_modified_at: Utc::now(),
+                encrypted_secret: label.as_bytes(),
+                kdf_salt: [salt; 16],
+                auth_nonce: [salt; 24],
+                peppered: false,
+                kdf: KdfParams::CURRENT,
+                cipher: CipherId::CURRENT,
+                reprompt: false,
+            })?;
+            db.tag_item(item.uid, tag)?;
+        }
+
+        Ok(())
+    }
+
+    fn empty_vault() -> Result<Database> {
+        Database::open(":memory:", &DatabaseOptions::default())
+    }
+
+    fn labels(db: &Database) -> Result<Vec<String>> {
+        let mut labels: Vec<String> = db.all_items()?.into_iter().map(|item| item.label).collect();
+        labels.sort();
+        Ok(labels)
+    }
+
+    #[test]
+    fn split_copies_or_moves_tagged_items() -> Result<()> {
+        let dir = perms::create_temp_dir("steelsafe-cli-test")?;
+        let config = Config::default();
+        let mut db = empty_vault()?;
+        add_samples(&db)?;
+
+        let copied_to = dir.join("copied.sqlite3");
+        split(&mut db, &config, "work", &copied_to, true)?;
+        assert_eq!(labels(&db)?, ["Home Wi-Fi", "Intranet", "VPN"]);
+        assert_eq!(labels(&Database::open(&copied_to, &config.db_options)?)?, ["Intranet", "VPN"]);
+
+        let moved_to = dir.join("moved.sqlite3");
+        split(&mut db, &config, "work", &moved_to, false)?;
+        assert_eq!(labels(&db)?, ["Home Wi-Fi"]);
+        assert_eq!(labels(&Database::open(&moved_to, &config.db_options)?)?, ["Intranet", "VPN"]);
+
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn join_copies_or_moves_items() -> Result<()> {
+        let dir = perms::create_temp_dir("steelsafe-cli-test")?;
+        let config = Config::default();
+        let path = dir.join("other.sqlite3");
+        let other = Database::open(&path, &config.db_options)?;
+        add_samples(&other)?;
+
+        let mut db = empty_vault()?;
+        join(&mut db, &config, &path, Some("work"), true)?;
+        assert_eq!(labels(&db)?, ["Intranet", "VPN"]);
+        assert_eq!(labels(&other)?, ["Home Wi-Fi", "Intranet", "VPN"]);
+
+        let mut db = empty_vault()?;
+        join(&mut db, &config, &path, None, false)?;
+        assert_eq!(labels(&db)?, ["Home Wi-Fi", "Intranet", "VPN"]);
+        assert!(labels(&other)?.is_empty());
+
+        // the other vault must exist
+        assert!(join(&mut db, &config, &dir.join("missing.sqlite3"), None, false).is_err());
+        assert!(!dir.join("missing.sqlite3").exists());
+
+        drop(other);
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn items_left_in_both_vaults_are_reported() -> Result<()> {
+        let mut source = empty_vault()?;
+        let mut target = empty_vault()?;
+        add_samples(&source)?;
+        source.set_read_only()?;
+
+        let result = transfer_items(&mut source, &mut target, Some("work"), false);
+        assert!(
+            matches!(&result, Err(Error::TransferIncomplete { labels, .. }) if labels.len() == 2),
+            "unexpected result: {result:#?}",
+        );
+        assert_eq!(labels(&source)?, ["Home Wi-Fi", "Intranet", "VPN"]);
+        assert_eq!(labels(&target)?, ["Intranet", "VPN"]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,410 @@
+//! Copying secrets to the clipboard.
+//!
+//! No single mechanism works everywhere: `arboard` talks to X11 (or XWayland)
+//! directly, `wl-copy` and `xclip` are external programs, the tmux paste buffer
+//! is handy in remote sessions, and the OSC 52 escape sequence asks the terminal
+//! emulator itself to set the clipboard, which even works over SSH, provided
+//! that the terminal supports it. Therefore, backends are tried in the
+//! configured order, until one of them succeeds.
+//!
+//! On X11 and Wayland, secrets can also be copied to the primary selection
+//! (pasted with the middle mouse button), if the backend supports it.
+
+use std::env;
+use std::fmt::{self, Debug, Formatter};
+use std::io::{self, Write, IsTerminal};
+use std::process::{Command, Stdio};
+use serde::Deserialize;
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use zeroize::Zeroizing;
+use crate::error::{Error, Result, ResultExt};
+
+
+/// Where the secret is copied by the "copy" action.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CopyTarget {
+    /// The regular clipboard (Ctrl+V).
+    #[default]
+    Clipboard,
+    /// The primary selection (middle-click).
+    Primary,
+    /// Both the clipboard and the primary selection.
+    Both,
+}
+
+impl CopyTarget {
+    /// The selections to be set, in order.
+    pub const fn selections(self) -> &'static [Selection] {
+        match self {
+            CopyTarget::Clipboard => &[Selection::Clipboard],
+            CopyTarget::Primary => &[Selection::Primary],
+            CopyTarget::Both => &[Selection::Clipboard, Selection::Primary],
+        }
+    }
+}
+
+/// A single selection. Only X11 and Wayland distinguish the two.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Selection {
+    Clipboard,
+    Primary,
+}
+
+/// Identifies a clipboard backend in the config.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClipboardKind {
+    /// The `arboard` library (X11, macOS, Windows).
+    Arboard,
+    /// The `wl-copy` program of `wl-clipboard` (Wayland).
+    WlCopy,
+    /// The `xclip` program (X11).
+    Xclip,
+    /// The paste buffer of tmux, via `tmux load-buffer`.
+    Tmux,
+    /// The OSC 52 escape sequence, interpreted by the terminal emulator.
+    Osc52,
+}
+
+impl ClipboardKind {
+    /// The order in which backends are tried if none are configured. Backends
+    /// that can't possibly work in the current session are left out, and OSC 52
+    /// comes last, because it can't detect whether the terminal supports it.
+    fn default_order(wayland: bool, x11: bool, tmux: bool) -> Vec<Self> {
+        let mut kinds = Vec::new();
+
+        if wayland {
+            kinds.push(ClipboardKind::WlCopy);
+        }
+
+        kinds.push(ClipboardKind::Arboard);
+
+        if x11 {
+            kinds.push(ClipboardKind::Xclip);
+        }
+        if tmux {
+            kinds.push(ClipboardKind::Tmux);
+        }
+
+        kinds.push(ClipboardKind::Osc52);
+        kinds
+    }
+
+    /// The `configured` backends, or if there are none, the default order.
+    fn configured_or_default(configured: &[Self]) -> Vec<Self> {
+        if configured.is_empty() {
+            ClipboardKind::default_order(
+                env::var_os("WAYLAND_DISPLAY").is_some(),
+                env::var_os("DISPLAY").is_some(),
+                env::var_os("TMUX").is_some(),
+            )
+        } else {
+            configured.to_vec()
+        }
+    }
+
+    /// Whether the backend has a chance of working, i.e. the program is installed,
+    /// and the display server or terminal it talks to is present. Nothing is copied.
+    fn is_probably_usable(self) -> bool {
+        let x11 = env::var_os("DISPLAY").is_some();
+        let wayland = env::var_os("WAYLAND_DISPLAY").is_some();
+
+        match self {
+            ClipboardKind::Arboard => {
+                cfg!(any(target_os = "macos", windows)) || x11 || wayland
+            }
+            ClipboardKind::WlCopy => wayland && is_installed("wl-copy"),
+            ClipboardKind::Xclip => x11 && is_installed("xclip"),
+            ClipboardKind::Tmux => env::var_os("TMUX").is_some() && is_installed("tmux"),
+            ClipboardKind::Osc52 => {
+                // the Linux console and dumb terminals ignore the sequence
+                let term = env::var("TERM").unwrap_or_default();
+                io::stdout().is_terminal() && term != "linux" && term != "dumb"
+            }
+        }
+    }
+
+    /// The name of the backend in the config and in error messages.
+    const fn name(self) -> &'static str {
+        match self {
+            ClipboardKind::Arboard => "arboard",
+            ClipboardKind::WlCopy => "wl_copy",
+            ClipboardKind::Xclip => "xclip",
+            ClipboardKind::Tmux => "tmux",
+            ClipboardKind::Osc52 => "osc52",
+        }
+    }
+
+    fn backend(self) -> Box<dyn ClipboardBackend> {
+        match self {
+            ClipboardKind::Arboard => Box::new(Arboard(None)),
+            ClipboardKind::WlCopy => Box::new(Program {
+                program: "wl-copy",
+                clipboard_args: &[],
+                primary_args: Some(&["--primary"]),
+            }),
+            ClipboardKind::Xclip => Box::new(Program {
+                program: "xclip",
+                clipboard_args: &["-selection", "clipboard"],
+                primary_args: Some(&["-selection", "primary"]),
+            }),
+            ClipboardKind::Tmux => Box::new(Program {
+                program: "tmux",
+                clipboard_args: &["load-buffer", "-w", "-"],
+                primary_args: None,
+            }),
+            ClipboardKind::Osc52 => Box::new(Osc52),
+        }
+    }
+}
+
+/// Whether any of the backends that `Clipboard::new()` would try can possibly
+/// work in this session, for warning about it before a secret is to be copied.
+pub fn is_probably_available(configured: &[ClipboardKind]) -> bool {
+    ClipboardKind::configured_or_default(configured)
+        .into_iter()
+        .any(ClipboardKind::is_probably_usable)
+}
+
+/// Whether `program` is found in one of the directories of `PATH`.
+fn is_installed(program: &str) -> bool {
+    let Some(path) = env::var_os("PATH") else {
+        return false;
+    };
+
+    env::split_paths(&path).any(|dir| {
+        dir.join(program).is_file() || dir.join(format!("{program}.exe")).is_file()
+    })
+}
+
+/// A mechanism for putting text onto the clipboard.
+pub trait ClipboardBackend {
+    /// Replaces the contents of `selection` with `text`. Returns
+    /// `Error::PrimarySelectionUnsupported` if the backend has no primary selection.
+    fn set_text(&mut self, text: &str, selection: Selection) -> Result<()>;
+}
+
+/// Tries each backend in order, until one of them succeeds.
+pub struct Clipboard {
+    /// The name of each backend, for error messages, and the backend itself.
+    backends: Vec<(&'static str, Box<dyn ClipboardBackend>)>,
+}
+
+impl Clipboard {
+    /// Uses the `configured` backends, or if there are none,
+    /// a default order based on the environment.
+    pub fn new(configured: &[ClipboardKind]) -> Self {
+        let kinds = ClipboardKind::configured_or_default(configured);
+
+        Clipboard {
+            backends: kinds.into_iter().map(|kind| (kind.name(), kind.backend())).collect(),
+        }
+    }
+
+    /// Copies `text` to each selection of `target`. If every backend fails
+    /// for a selection, the error contains the reason for each of them.
+    pub fn set_text(&mut self, text: &str, target: CopyTarget) -> Result<()> {
+        for &selection in target.selections() {
+            self.set_selection(text, selection)?;
+        }
+
+        Ok(())
+    }
+
+    fn set_selection(&mut self, text: &str, selection: Selection) -> Result<()> {
+        let mut failures = Vec::new();
+
+        for (name, backend) in &mut self.backends {
+            match backend.set_text(text, selection) {
+                Ok(()) => return Ok(()),
+                Err(error) => failures.push(format!("{name}: {error}")),
+            }
+        }
+
+        Err(Error::ClipboardUnavailable(failures.join("; ")))
+    }
+}
+
+#[cfg(test)]
+impl Clipboard {
+    /// Uses only the given backend, e.g. a `RecordingBackend`.
+    pub fn with_backend<B: ClipboardBackend + 'static>(name: &'static str, backend: B) -> Self {
+        Clipboard {
+            backends: vec![(name, Box::new(backend))],
+        }
+    }
+}
+
+impl Debug for Clipboard {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        formatter
+            .debug_list()
+            .entries(self.backends.iter().map(|(name, _)| name))
+            .finish()
+    }
+}
+
+/// The `arboard` library. The clipboard object is only created upon first use,
+/// so that its absence doesn't prevent other backends from being tried. It's
+/// kept alive afterwards, because on X11, the contents are served by its thread.
+struct Arboard(Option<arboard::Clipboard>);
+
+impl ClipboardBackend for Arboard {
+    fn set_text(&mut self, text: &str, selection: Selection) -> Result<()> {
+        let clipboard = match &mut self.0 {
+            Some(clipboard) => clipboard,
+            slot @ None => slot.insert(arboard::Clipboard::new()?),
+        };
+
+        match selection {
+            Selection::Clipboard => clipboard.set_text(text)?,
+            Selection::Primary => Self::set_primary(clipboard, text)?,
+        }
+
+        Ok(())
+    }
+}
+
+impl Arboard {
+    #[cfg(all(unix, not(any(target_os = "macos", target_os = "android", target_os = "emscripten"))))]
+    fn set_primary(clipboard: &mut arboard::Clipboard, text: &str) -> Result<()> {
+        use arboard::{SetExtLinux, LinuxClipboardKind};
+
+        clipboard.set().clipboard(LinuxClipboardKind::Primary).text(text)?;
+
+        Ok(())
+    }
+
+    #[cfg(not(all(unix, not(any(target_os = "macos", target_os = "android", target_os = "emscripten")))))]
+    fn set_primary(_clipboard: &mut arboard::Clipboard, _text: &str) -> Result<()> {
+        Err(Error::PrimarySelectionUnsupported)
+    }
+}
+
+/// An external program that reads the text from its standard input.
+struct Program {
+    program: &'static str,
+    clipboard_args: &'static [&'static str],
+    /// `None` if the program can't set the primary selection.
+    primary_args: Option<&'static [&'static str]>,
+}
+
+impl ClipboardBackend for Program {
+    fn set_text(&mut self, text: &str, selection: Selection) -> Result<()> {
+        let args = match selection {
+            Selection::Clipboard => self.clipboard_args,
+            Selection::Primary => self.primary_args.ok_or(Error::PrimarySelectionUnsupported)?,
+        };
+
+        // The output is not captured: these programs may fork, and keep serving
+        // the clipboard in the background, so the output would never end.
+        let mut child = Command::new(self.program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .context(format!("Can't run {}", self.program))?;
+
+        // Dropping stdin closes the pipe, signalling the end of the text.
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(text.as_bytes())?;
+        }
+
+        let status = child.wait()?;
+
+        if status.success() {
+            Ok(())
+        } else {
+            Err(Error::ClipboardProgram {
+                program: self.program.into(),
+                status: status.to_string(),
+            })
+        }
+    }
+}
+
+/// Writes the OSC 52 escape sequence to the terminal.
+struct Osc52;
+
+impl ClipboardBackend for Osc52 {
+    fn set_text(&mut self, text: &str, selection: Selection) -> Result<()> {
+        let mut stdout = io::stdout().lock();
+
+        // Otherwise, the escape sequence would end up in a file or a pipe.
+        if !stdout.is_terminal() {
+            return Err(Error::context(
+                io::Error::from(io::ErrorKind::Unsupported),
+                "Standard output is not a terminal",
+            ));
+        }
+
+        let sequence = osc52_sequence(text, selection, env::var_os("TMUX").is_some());
+        stdout.write_all(sequence.as_bytes())?;
+        stdout.flush()?;
+
+        Ok(())
+    }
+}
+
+/// `ESC ] 52 ; c ; <base64 text> BEL`, or `p` instead of `c` for the primary
+/// selection (which many terminals ignore). Within tmux, the sequence is wrapped
+/// in a passthrough sequence (and its escape characters are doubled), so that
+/// tmux forwards it to the outer terminal; this requires `allow-passthrough`.
+fn osc52_sequence(text: &str, selection: Selection, tmux: bool) -> Zeroizing<String> {
+    let parameter = match selection {
+        Selection::Clipboard => 'c',
+        Selection::Primary => 'p',
+    };
+    let encoded = Zeroizing::new(BASE64.encode(text));
+    let sequence = Zeroizing::new(format!("\x1b]52;{};{}\x07", parameter, encoded.as_str()));
+
+    if tmux {
+        Zeroizing::new(format!("\x1bPtmux;{}\x1b\\", sequence.replace('\x1b', "\x1b\x1b")))
+    } else {
+        sequence
+    }
+}
+
+/// Remembers the copied text instead of copying it anywhere, for testing.
+/// Clones share the same record.
+#[cfg(test)]
+#[derive(Clone, Default, Debug)]
+pub struct RecordingBackend(pub std::sync::Arc<std::sync::Mutex<Vec<(String, Selection)>>>);
+
+#[cfg(test)]
+impl ClipboardBackend for RecordingBackend {
+    fn set_text(&mut self, text: &str, selection: Selection) -> Result<()> {
+        self.0.lock().expect("clipboard record poisoned").push((text.to_owned(), selection));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ClipboardKind::*, Selection, osc52_sequence};
+
+
+    #[test]
+    fn default_order_and_osc52_encoding() {
+        assert_eq!(super::ClipboardKind::default_order(false, false, false), [Arboard, Osc52]);
+        assert_eq!(
+            super::ClipboardKind::default_order(true, true, true),
+            [WlCopy, Arboard, Xclip, Tmux, Osc52],
+        );
+
+        assert_eq!(
+            osc52_sequence("hunter2", Selection::Clipboard, false).as_str(),
+            "\x1b]52;c;aHVudGVyMg==\x07",
+        );
+        assert_eq!(
+            osc52_sequence("hunter2", Selection::Primary, false).as_str(),
+            "\x1b]52;p;aHVudGVyMg==\x07",
+        );
+        assert_eq!(
+            osc52_sequence("hunter2", Selection::Clipboard, true).as_str(),
+            "\x1bPtmux;\x1b\x1b]52;c;aHVudGVyMg==\x07\x1b\\",
+        );
+    }
+}
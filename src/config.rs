@@ -2,14 +2,36 @@
 
 use std::io::ErrorKind;
 use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::fmt::{self, Display, Formatter};
 use std::fs::File;
+use std::io::Read;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 use serde::Deserialize;
+use serde_json::Value;
 use directories::{UserDirs, ProjectDirs};
-use ratatui::style::{Style, Color};
-use crate::error::{Error, Result, ResultExt};
+use ratatui::{
+    layout::Constraint,
+    style::{Style, Color},
+};
+use crate::{
+    i18n::Locale,
+    clipboard::{ClipboardKind, CopyTarget},
+    askpass::PasswordCommand,
+    policy::PasswordPolicy,
+    timestamp::{DEFAULT_TIME_FORMAT, validate_time_format},
+    schema::{self, Diagnostics},
+    db::{Database, VaultSetting, DisplayItem},
+    perms,
+    error::{Error, Result, ResultExt},
+};
 
 
+/// The commented config that `steelsafe init-config` writes. Every setting
+/// has its default value, or is commented out if it's unset by default.
+pub const DEFAULT_RC_FILE: &str = include_str!("default.steelsaferc");
+
 /// Configures the environment of the application.
 #[derive(Clone, Default, Debug, Deserialize)]
 pub struct Config {
@@ -19,6 +41,84 @@ pub struct Config {
     /// The path to the password storage directory, where an SQLite database will be created.
     #[serde(default)]
     pub database: Option<PathBuf>,
+    /// Tuning parameters for the SQLite connection.
+    #[serde(default)]
+    pub db_options: DatabaseOptions,
+    /// The path to a machine-local pepper file, which will be created if it doesn't
+    /// exist. If specified, the pepper is mixed into the key derivation of new items.
+    #[serde(default)]
+    pub pepper_file: Option<PathBuf>,
+    /// The file holding the age secret key of this member of the team, for
+    /// decrypting the items shared with the team (see `org`).
+    #[serde(default)]
+    pub identity_file: Option<PathBuf>,
+    /// Whether to store the number of consecutive failed decryption attempts in the
+    /// database, so that restarting the application does not reset the cooldown.
+    #[serde(default)]
+    pub persist_failed_attempts: bool,
+    /// A program that prints the encryption password, used instead of asking for it.
+    #[serde(default)]
+    pub password_command: Option<PasswordCommand>,
+    /// Whether to record every successful decryption of an item in the database.
+    #[serde(default)]
+    pub access_log: bool,
+    /// Synchronizes the vault with other devices through a git repository, if specified.
+    #[serde(default)]
+    pub git_sync: Option<GitSyncConfig>,
+    /// Where `steelsafe backup` uploads the export bundle, if specified.
+    #[serde(default)]
+    pub remote_backup: Option<RemoteBackupConfig>,
+    /// Settings of the password-caching agent (`steelsafe agent`).
+    #[serde(default)]
+    pub agent: AgentConfig,
+    /// How dates and times are displayed in the TUI.
+    #[serde(default)]
+    pub timestamps: TimestampConfig,
+    /// The columns of the main table, in order. Columns that are not
+    /// listed are hidden initially, but they can be shown at runtime.
+    #[serde(default = "TableColumn::defaults")]
+    pub columns: Vec<TableColumn>,
+    /// The order of the items in the main table.
+    #[serde(default)]
+    pub sort: SortOrder,
+    /// Where the panel of the search term and the password entry is shown.
+    #[serde(default)]
+    pub panel: PanelConfig,
+    /// Upper bounds on the size of secrets and imported files.
+    #[serde(default)]
+    pub limits: SizeLimits,
+    /// Reminders about expiring items.
+    #[serde(default)]
+    pub expiry: ExpiryConfig,
+    /// The language of the user interface. If not specified, it is
+    /// determined by the `LC_ALL`, `LC_MESSAGES`, or `LANG` variables.
+    #[serde(default)]
+    pub locale: Option<Locale>,
+    /// The clipboard backends to try, in order. If empty, the order
+    /// is determined by the environment (Wayland, X11, tmux).
+    #[serde(default)]
+    pub clipboard: Vec<ClipboardKind>,
+    /// Where the "copy" action puts secrets: the clipboard, the primary
+    /// selection, or both. The "copy to primary" action is not affected.
+    #[serde(default)]
+    pub copy_to: CopyTarget,
+    /// Whether steelsafe may access the network at all: remote backups, git
+    /// synchronization, and update checks. Only `false` has an effect.
+    #[serde(default)]
+    pub network: Option<bool>,
+    /// Whether the TUI hides everything when the terminal loses focus, and
+    /// what it takes to show it again.
+    #[serde(default)]
+    pub lock_on_focus_loss: FocusLock,
+    /// How often the TUI checks for input while in use and while idle.
+    #[serde(default)]
+    pub poll: PollConfig,
+    /// The passphrase generators offered in the New item dialog.
+    #[serde(default)]
+    pub generator: GeneratorConfig,
+    /// Requirements on the secrets of items with certain tags or of certain types.
+    #[serde(default)]
+    pub policies: Vec<PasswordPolicy>,
 }
 
 impl Config {
@@ -30,12 +130,19 @@ impl Config {
     ///
     /// If the file exists but it contains syntax errors, an error is returned.
     pub fn from_rc_file() -> Result<Self> {
+        match Self::find_rc_file()? {
+            Some((_path, config_file)) => Self::from_file(config_file),
+            // not found anywhere, return the built-in default config
+            None => Ok(Config::default()),
+        }
+    }
+
+    /// Opens the `.steelsaferc` file that `from_rc_file()` reads, if there is one.
+    pub fn find_rc_file() -> Result<Option<(PathBuf, File)>> {
         // First, search in the config directory
-        if let Ok(project_dirs) = Self::project_dirs() {
-            let config_path = project_dirs.config_dir().join(".steelsaferc");
+        if let Ok(config_path) = Self::rc_file_path() {
             if let Some(config_file) = Self::open_file_if_exists(&config_path)? {
-                // do NOT silently ignore JSON syntax/semantic errors!
-                return serde_json::from_reader(config_file).context("Invalid .steelsaferc");
+                return Ok(Some((config_path, config_file)));
             }
         }
 
@@ -43,12 +150,64 @@ impl Config {
         if let Some(user_dirs) = UserDirs::new() {
             let config_path = user_dirs.home_dir().join(".steelsaferc");
             if let Some(config_file) = Self::open_file_if_exists(&config_path)? {
-                return serde_json::from_reader(config_file).context("Invalid .steelsaferc");
+                return Ok(Some((config_path, config_file)));
             }
         }
 
-        // not found anywhere, return the built-in default config
-        Ok(Config::default())
+        Ok(None)
+    }
+
+    /// Where `steelsafe init-config` writes the config: the `.steelsaferc`
+    /// file in the config directory. It doesn't necessarily exist yet.
+    pub fn rc_file_path() -> Result<PathBuf> {
+        Ok(Self::project_dirs()?.config_dir().join(".steelsaferc"))
+    }
+
+    /// Reads and validates the config. Every unknown key and invalid value
+    /// is reported at once, rather than just the first one.
+    pub fn from_file(mut file: File) -> Result<Self> {
+        let mut text = String::new();
+        file.read_to_string(&mut text).context("Can't read .steelsaferc")?;
+        Self::from_text(&text)
+    }
+
+    /// Parses and validates the contents of a config file.
+    fn from_text(text: &str) -> Result<Self> {
+        // do NOT silently ignore JSON syntax/semantic errors!
+        let json = strip_comments(text);
+        let value: Value = serde_json::from_str(&json).context("Invalid .steelsaferc")?;
+        let diagnostics = schema::check_config(&value);
+
+        if !diagnostics.is_empty() {
+            return Err(Error::InvalidConfig(Diagnostics(diagnostics)));
+        }
+
+        let config: Self = serde_json::from_value(value).context("Invalid .steelsaferc")?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Overrides the settings that are also stored in the vault itself.
+    ///
+    /// A setting stored in the vault takes precedence over the `.steelsaferc`,
+    /// which in turn takes precedence over the built-in default.
+    pub fn apply_vault_settings(&mut self, db: &Database) -> Result<()> {
+        if let Some(access_log) = db.setting(VaultSetting::ACCESS_LOG)? {
+            self.access_log = access_log;
+        }
+        if let Some(persist) = db.setting(VaultSetting::PERSIST_FAILED_ATTEMPTS)? {
+            self.persist_failed_attempts = persist;
+        }
+        if let Some(warning_days) = db.setting(VaultSetting::EXPIRY_WARNING_DAYS)? {
+            self.expiry.warning_days = warning_days;
+        }
+        Ok(())
+    }
+
+    /// Checks the settings that can't be validated during deserialization,
+    /// so that mistakes are reported at startup rather than when rendering.
+    fn validate(&self) -> Result<()> {
+        validate_time_format(self.timestamps.format())
     }
 
     fn project_dirs() -> Result<ProjectDirs> {
@@ -68,23 +227,615 @@ impl Config {
         }
     }
 
-    /// Creates the directory containing the password database.
-    /// Returns its path if creating the directory succeeded.
+    /// The path of the agent's socket: the configured one, or `agent.sock`
+    /// in the runtime directory (if the platform has one), or else in the
+    /// database directory.
+    pub fn agent_socket_path(&self) -> Result<PathBuf> {
+        if let Some(path) = self.agent.socket.as_ref() {
+            return Ok(path.clone());
+        }
+
+        Ok(self.ensure_runtime_dir()?.join("agent.sock"))
+    }
+
+    /// The path of the access token of the HTTP API (`steelsafe serve`),
+    /// `serve.token` next to the agent's default socket.
+    #[cfg(feature = "serve")]
+    pub fn serve_token_path(&self) -> Result<PathBuf> {
+        Ok(self.ensure_runtime_dir()?.join("serve.token"))
+    }
+
+    /// Creates the runtime directory (if the platform has one), or else the
+    /// database directory, for files that only live as long as a process.
+    fn ensure_runtime_dir(&self) -> Result<PathBuf> {
+        let runtime_dir = Self::project_dirs()?.runtime_dir().map(Path::to_owned);
+
+        match runtime_dir {
+            Some(dir) => {
+                std::fs::create_dir_all(&dir)?;
+                Ok(dir)
+            }
+            None => Ok(self.ensure_db_dir()?.into_owned()),
+        }
+    }
+
+    /// Where downloaded data is cached, e.g. `~/.cache/steelsafe` on Linux.
+    /// It doesn't necessarily exist yet.
+    #[cfg(feature = "remote")]
+    pub fn cache_dir() -> Result<PathBuf> {
+        Ok(Self::project_dirs()?.cache_dir().to_owned())
+    }
+
+    /// The path of the password database file. Creates its directory.
+    pub fn db_file_path(&self) -> Result<PathBuf> {
+        Ok(self.ensure_db_dir()?.join("secrets.sqlite3"))
+    }
+
+    /// Creates the directory containing the password database, accessible
+    /// only by its owner. Returns its path if creating the directory succeeded.
     pub fn ensure_db_dir(&self) -> Result<Cow<'_, Path>> {
         if let Some(path) = self.database.as_ref() {
-            std::fs::create_dir_all(path)?;
+            perms::create_private_dir(path)?;
             return Ok(path.into());
         }
 
         let dirs = Self::project_dirs()?;
         let db_dir = dirs.data_dir();
 
-        std::fs::create_dir_all(db_dir)?;
+        perms::create_private_dir(db_dir)?;
 
         Ok(db_dir.to_owned().into())
     }
 }
 
+/// Blanks out `//` comments outside of strings, so that the config file can
+/// explain itself. Comments are replaced by spaces rather than removed, so
+/// that the positions reported in syntax errors remain accurate.
+fn strip_comments(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    let mut in_string = false;
+    let mut escaped = false;
+
+    while let Some(c) = chars.next() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+        } else if c == '"' {
+            in_string = true;
+        } else if c == '/' && chars.peek() == Some(&'/') {
+            output.push(' ');
+
+            while let Some(c) = chars.next_if(|&c| c != '\n') {
+                output.push(if c == '\r' { c } else { ' ' });
+            }
+            continue;
+        }
+
+        output.push(c);
+    }
+
+    output
+}
+
+/// Tuning parameters for the SQLite connection.
+#[derive(Clone, Default, Debug, Deserialize)]
+pub struct DatabaseOptions {
+    /// How long to wait for a lock held by another process (e.g. another
+    /// running instance of steelsafe) before giving up, in milliseconds.
+    #[serde(default)]
+    pub busy_timeout_ms: Option<u64>,
+}
+
+impl DatabaseOptions {
+    /// The default busy timeout is 5 seconds.
+    pub fn busy_timeout(&self) -> Duration {
+        Duration::from_millis(self.busy_timeout_ms.unwrap_or(5000))
+    }
+}
+
+/// Settings of the git-backed vault synchronization.
+#[derive(Clone, Debug, Deserialize)]
+pub struct GitSyncConfig {
+    /// The working tree of a git repository dedicated to steelsafe. It must already
+    /// exist; clone it (or run `git init`) and set up its upstream branch manually.
+    pub repo: PathBuf,
+    /// The name of the sync file within the repository (`vault.steelsafe` by default).
+    #[serde(default)]
+    pub bundle_file_name: Option<String>,
+    /// A program printing the password the sync file is encrypted with. The TUI
+    /// synchronizes by itself, so it needs this; the CLI asks on the terminal without it.
+    #[serde(default)]
+    pub password_command: Option<PasswordCommand>,
+}
+
+/// Settings of the password-caching agent.
+#[derive(Clone, Default, Debug, Deserialize)]
+pub struct AgentConfig {
+    /// The path of the Unix domain socket the agent listens on.
+    #[serde(default)]
+    pub socket: Option<PathBuf>,
+    /// How long the agent keeps the password after unlocking, in seconds.
+    /// Defaults to 15 minutes, and can't be more than a week.
+    #[serde(default)]
+    pub ttl_secs: Option<u64>,
+}
+
+/// How dates and times are displayed in the TUI.
+#[derive(Clone, Default, Debug, Deserialize)]
+pub struct TimestampConfig {
+    /// Show times in the local time zone instead of UTC.
+    #[serde(default)]
+    pub local_time: bool,
+    /// Show recent times as durations, e.g. "3 days ago".
+    #[serde(default)]
+    pub relative: bool,
+    /// A `chrono` format string for absolute dates and times; `%F %T` by default.
+    #[serde(default)]
+    pub format: Option<String>,
+}
+
+impl TimestampConfig {
+    /// The format string of absolute dates and times.
+    pub fn format(&self) -> &str {
+        self.format.as_deref().unwrap_or(DEFAULT_TIME_FORMAT)
+    }
+}
+
+/// Upper bounds on the size of data entering the vault, so that e.g. pasting
+/// a huge blob by accident doesn't hang the UI or silently bloat the vault.
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct SizeLimits {
+    /// The maximal size of a secret, in bytes; 64 KiB by default.
+    #[serde(default = "SizeLimits::default_max_secret_bytes")]
+    pub max_secret_bytes: usize,
+    /// The maximal size of an imported export bundle, in bytes; 64 MiB by default.
+    #[serde(default = "SizeLimits::default_max_import_bytes")]
+    pub max_import_bytes: u64,
+}
+
+impl SizeLimits {
+    const fn default_max_secret_bytes() -> usize {
+        64 * 1024
+    }
+
+    const fn default_max_import_bytes() -> u64 {
+        64 * 1024 * 1024
+    }
+
+    /// Returns an error if a secret of `len` bytes is too large to be stored.
+    pub fn check_secret_len(&self, len: usize) -> Result<()> {
+        if len > self.max_secret_bytes {
+            Err(Error::InputTooLarge { size: len, limit: self.max_secret_bytes })
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl Default for SizeLimits {
+    fn default() -> Self {
+        SizeLimits {
+            max_secret_bytes: Self::default_max_secret_bytes(),
+            max_import_bytes: Self::default_max_import_bytes(),
+        }
+    }
+}
+
+/// Configures the reminders about items that have expired or are about to expire.
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct ExpiryConfig {
+    /// How many days before its expiry date an item is reported; 14 by default.
+    #[serde(default = "ExpiryConfig::default_warning_days")]
+    pub warning_days: u32,
+}
+
+impl ExpiryConfig {
+    const fn default_warning_days() -> u32 {
+        14
+    }
+}
+
+impl Default for ExpiryConfig {
+    fn default() -> Self {
+        ExpiryConfig {
+            warning_days: Self::default_warning_days(),
+        }
+    }
+}
+
+/// Configures the password generators of the New item dialog (key Ctrl+G).
+/// Random characters and pronounceable passwords are always offered, and a
+/// passphrase generator is added for each wordlist that is specified.
+#[derive(Clone, Debug, Deserialize)]
+pub struct GeneratorConfig {
+    /// A diceware wordlist of long words, e.g. the EFF long wordlist.
+    #[serde(default)]
+    pub long_wordlist: Option<PathBuf>,
+    /// A diceware wordlist of short words, e.g. the EFF short wordlist.
+    #[serde(default)]
+    pub short_wordlist: Option<PathBuf>,
+    /// The number of words in a passphrase; 6 by default.
+    #[serde(default = "GeneratorConfig::default_words")]
+    pub words: usize,
+    /// What separates the words of a passphrase; `-` by default.
+    #[serde(default = "GeneratorConfig::default_separator")]
+    pub separator: String,
+    /// The shape of pronounceable passwords.
+    #[serde(default)]
+    pub pronounceable: PronounceableConfig,
+}
+
+impl GeneratorConfig {
+    const fn default_words() -> usize {
+        6
+    }
+
+    fn default_separator() -> String {
+        String::from("-")
+    }
+}
+
+impl Default for GeneratorConfig {
+    fn default() -> Self {
+        GeneratorConfig {
+            long_wordlist: None,
+            short_wordlist: None,
+            words: Self::default_words(),
+            separator: Self::default_separator(),
+            pronounceable: PronounceableConfig::default(),
+        }
+    }
+}
+
+/// Configures the pronounceable passwords of the generator.
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct PronounceableConfig {
+    /// The number of letters; 20 by default.
+    #[serde(default = "PronounceableConfig::default_length")]
+    pub length: usize,
+    /// The number of digits inserted between syllables; 2 by default.
+    #[serde(default = "PronounceableConfig::default_digits")]
+    pub digits: usize,
+    /// The number of symbols inserted between syllables; none by default.
+    #[serde(default)]
+    pub symbols: usize,
+}
+
+impl PronounceableConfig {
+    const fn default_length() -> usize {
+        20
+    }
+
+    const fn default_digits() -> usize {
+        2
+    }
+}
+
+impl Default for PronounceableConfig {
+    fn default() -> Self {
+        PronounceableConfig {
+            length: Self::default_length(),
+            digits: Self::default_digits(),
+            symbols: 0,
+        }
+    }
+}
+
+/// The placement of the panel below (or above) the table, which holds the
+/// search term or the password entry while either is open.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize)]
+pub struct PanelConfig {
+    #[serde(default)]
+    pub position: PanelPosition,
+    /// The height of the panel in rows, including its border. On a terminal
+    /// shorter than this, the panel takes up the whole screen.
+    #[serde(default = "PanelConfig::default_height")]
+    pub height: u16,
+}
+
+impl PanelConfig {
+    const fn default_height() -> u16 {
+        3
+    }
+}
+
+impl Default for PanelConfig {
+    fn default() -> Self {
+        PanelConfig {
+            position: PanelPosition::default(),
+            height: PanelConfig::default_height(),
+        }
+    }
+}
+
+/// Which edge of the screen the panel is shown at.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PanelPosition {
+    Top,
+    #[default]
+    Bottom,
+}
+
+/// What happens when the terminal window loses focus, e.g. when switching
+/// to another window. Terminals that don't report focus changes never lock.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FocusLock {
+    /// Nothing; the vault remains on the screen.
+    #[default]
+    Off,
+    /// The screen is blanked until a key is pressed.
+    Keypress,
+    /// The screen is blanked until the password of the selected item is entered.
+    Password,
+}
+
+/// How long the TUI waits for input before checking on background tasks
+/// and timers. Waiting longer while idle saves power, e.g. on laptops.
+#[derive(Clone, Copy, Default, Debug, Deserialize)]
+pub struct PollConfig {
+    /// The interval while keys are being pressed, or something is in
+    /// progress (e.g. decryption), in milliseconds.
+    #[serde(default)]
+    pub active_ms: Option<u64>,
+    /// The interval once idle, in milliseconds.
+    #[serde(default)]
+    pub idle_ms: Option<u64>,
+    /// How long after the last input the TUI becomes idle, in milliseconds.
+    #[serde(default)]
+    pub idle_after_ms: Option<u64>,
+}
+
+impl PollConfig {
+    /// 50 milliseconds by default.
+    pub fn active_interval(&self) -> Duration {
+        Duration::from_millis(self.active_ms.unwrap_or(50))
+    }
+
+    /// Half a second by default.
+    pub fn idle_interval(&self) -> Duration {
+        Duration::from_millis(self.idle_ms.unwrap_or(500))
+    }
+
+    /// 2 seconds by default.
+    pub fn idle_after(&self) -> Duration {
+        Duration::from_millis(self.idle_after_ms.unwrap_or(2000))
+    }
+}
+
+/// A column of the main table.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize)]
+pub struct TableColumn {
+    pub kind: ColumnKind,
+    /// How much space the column takes up. Each kind of column has its own default.
+    #[serde(default)]
+    pub width: Option<ColumnWidth>,
+}
+
+impl TableColumn {
+    /// The columns shown if none are configured.
+    pub fn defaults() -> Vec<Self> {
+        [ColumnKind::Label, ColumnKind::Account, ColumnKind::Modified]
+            .into_iter()
+            .map(|kind| TableColumn { kind, width: None })
+            .collect()
+    }
+
+    /// The layout constraint of the column.
+    pub fn constraint(&self) -> Constraint {
+        self.width.unwrap_or_else(|| self.kind.default_width()).into()
+    }
+}
+
+/// What a column of the main table displays.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ColumnKind {
+    Label,
+    Account,
+    Modified,
+    Tags,
+    /// The number of recovery codes left, for items holding such codes.
+    RecoveryCodes,
+    /// The expiry date, marked if it has passed or is within the warning period.
+    Expiry,
+}
+
+impl ColumnKind {
+    pub const ALL: [ColumnKind; 6] = [
+        ColumnKind::Label,
+        ColumnKind::Account,
+        ColumnKind::Modified,
+        ColumnKind::Tags,
+        ColumnKind::RecoveryCodes,
+        ColumnKind::Expiry,
+    ];
+
+    pub const fn title(self) -> &'static str {
+        match self {
+            ColumnKind::Label => "Title",
+            ColumnKind::Account => "Username or account",
+            ColumnKind::Modified => "Modified at",
+            ColumnKind::Tags => "Tags",
+            ColumnKind::RecoveryCodes => "Codes left",
+            ColumnKind::Expiry => "Expires",
+        }
+    }
+
+    const fn default_width(self) -> ColumnWidth {
+        match self {
+            ColumnKind::Label | ColumnKind::Account => ColumnWidth::Percentage(40),
+            ColumnKind::Modified => ColumnWidth::Min(24),
+            ColumnKind::Tags => ColumnWidth::Percentage(20),
+            ColumnKind::RecoveryCodes => ColumnWidth::Length(10),
+            // "2024-12-31 [abgelaufen]", the longest translation
+            ColumnKind::Expiry => ColumnWidth::Length(23),
+        }
+    }
+}
+
+/// The width of a column, e.g. `{ "percentage": 40 }` or `{ "min": 24 }`.
+/// See the layout constraints of ratatui for the exact meaning.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ColumnWidth {
+    /// Exactly this many characters.
+    Length(u16),
+    /// At least this many characters.
+    Min(u16),
+    /// At most this many characters.
+    Max(u16),
+    /// This percentage of the total width.
+    Percentage(u16),
+    /// A share of the remaining space, proportional to this weight.
+    Fill(u16),
+}
+
+impl From<ColumnWidth> for Constraint {
+    fn from(width: ColumnWidth) -> Self {
+        match width {
+            ColumnWidth::Length(n) => Constraint::Length(n),
+            ColumnWidth::Min(n) => Constraint::Min(n),
+            ColumnWidth::Max(n) => Constraint::Max(n),
+            ColumnWidth::Percentage(n) => Constraint::Percentage(n),
+            ColumnWidth::Fill(n) => Constraint::Fill(n),
+        }
+    }
+}
+
+/// The order of the items in the main table: by `by`, then by `then_by`
+/// among items that are equal according to `by`, and finally in the order
+/// they were added. Each key sorts in its natural direction (see `SortKey`).
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize)]
+pub struct SortOrder {
+    #[serde(default)]
+    pub by: SortKey,
+    #[serde(default = "SortOrder::default_then_by")]
+    pub then_by: SortKey,
+}
+
+impl SortOrder {
+    const fn default_then_by() -> SortKey {
+        SortKey::Label
+    }
+
+    /// Compares two items of the table. No two distinct items are equal, so
+    /// sorting gives the same order every time, regardless of the input order.
+    pub fn compare(&self, lhs: &DisplayItem, rhs: &DisplayItem) -> Ordering {
+        self.by
+            .compare(lhs, rhs)
+            .then_with(|| self.then_by.compare(lhs, rhs))
+            .then_with(|| lhs.uid.cmp(&rhs.uid))
+    }
+}
+
+impl Default for SortOrder {
+    fn default() -> Self {
+        SortOrder {
+            by: SortKey::default(),
+            then_by: SortOrder::default_then_by(),
+        }
+    }
+}
+
+/// What the items of the main table are sorted by.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortKey {
+    /// The order in which the items were added, oldest first.
+    #[default]
+    Added,
+    /// The label, alphabetically, ignoring case.
+    Label,
+    /// The account name, alphabetically, ignoring case. Items without one come last.
+    Account,
+    /// The time of the last modification, newest first.
+    Modified,
+}
+
+impl SortKey {
+    fn compare(self, lhs: &DisplayItem, rhs: &DisplayItem) -> Ordering {
+        match self {
+            SortKey::Added => lhs.uid.cmp(&rhs.uid),
+            SortKey::Label => compare_ignoring_case(&lhs.label, &rhs.label),
+            SortKey::Account => {
+                let lhs = lhs.account.as_deref().filter(|account| !account.is_empty());
+                let rhs = rhs.account.as_deref().filter(|account| !account.is_empty());
+
+                match (lhs, rhs) {
+                    (Some(lhs), Some(rhs)) => compare_ignoring_case(lhs, rhs),
+                    (Some(_), None) => Ordering::Less,
+                    (None, Some(_)) => Ordering::Greater,
+                    (None, None) => Ordering::Equal,
+                }
+            }
+            SortKey::Modified => rhs.last_modified_at.cmp(&lhs.last_modified_at),
+        }
+    }
+}
+
+fn compare_ignoring_case(lhs: &str, rhs: &str) -> Ordering {
+    lhs.chars()
+        .flat_map(char::to_lowercase)
+        .cmp(rhs.chars().flat_map(char::to_lowercase))
+}
+
+/// Settings of the remote backup (requires the `remote` feature).
+#[derive(Clone, Debug, Deserialize)]
+pub struct RemoteBackupConfig {
+    /// The server or bucket to upload backups to.
+    pub target: RemoteTarget,
+    /// The label of the vault item holding the credentials for the target.
+    #[cfg(feature = "remote")]
+    pub credentials_item: String,
+    /// With `steelsafe backup --if-due`, a backup is only made if the
+    /// last one is at least this many days old. Defaults to 1 day.
+    #[serde(default)]
+    pub interval_days: Option<u32>,
+}
+
+/// A remote backup target.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RemoteTarget {
+    /// A WebDAV collection; backups are `PUT` into it.
+    WebDav {
+        /// The URL of the collection (directory).
+        url: String,
+    },
+    /// An S3-compatible bucket, addressed path-style.
+    S3 {
+        /// The URL of the service, e.g. `https://s3.eu-central-1.amazonaws.com`.
+        endpoint: String,
+        /// The region used for request signing.
+        region: String,
+        /// The name of the bucket.
+        bucket: String,
+        /// Prepended to the object key of each backup, e.g. `steelsafe/`.
+        #[serde(default)]
+        prefix: Option<String>,
+    },
+}
+
+impl Display for RemoteTarget {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            RemoteTarget::WebDav { url } => formatter.write_str(url),
+            RemoteTarget::S3 { endpoint, region, bucket, prefix } => write!(
+                formatter,
+                "s3://{bucket}/{} at {endpoint} ({region})",
+                prefix.as_deref().unwrap_or_default(),
+            ),
+        }
+    }
+}
+
 /// A pair of background and foreground colors.
 #[derive(Clone, Default, Debug, Deserialize)]
 pub struct ColorPair {
@@ -147,3 +898,36 @@ impl Theme {
             .fg(self.error.fg.unwrap_or(Color::LightRed))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Config, PollConfig, SortOrder, PanelConfig, DEFAULT_RC_FILE, strip_comments};
+
+
+    #[test]
+    fn default_rc_file_is_valid() {
+        let config = Config::from_text(DEFAULT_RC_FILE).expect("invalid default config");
+
+        assert_eq!(config.db_options.busy_timeout_ms, Some(5000));
+        assert_eq!(config.poll.active_interval(), PollConfig::default().active_interval());
+        assert_eq!(config.poll.idle_interval(), PollConfig::default().idle_interval());
+        assert_eq!(config.poll.idle_after(), PollConfig::default().idle_after());
+        assert_eq!(config.columns.len(), 3);
+        assert_eq!(config.sort, SortOrder::default());
+        assert_eq!(config.panel, PanelConfig::default());
+        assert!(config.database.is_none());
+    }
+
+    #[test]
+    fn comments_are_blanked_out_except_in_strings() {
+        let text = "{ // a comment\n  \"url\": \"https://example.com/\\\"//\" // another\r\n}";
+        let json = strip_comments(text);
+
+        assert_eq!(json.len(), text.len());
+        assert_eq!(json.lines().count(), text.lines().count());
+        assert!(!json.contains("comment") && !json.contains("another"));
+
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["url"], "https://example.com/\"//");
+    }
+}
@@ -0,0 +1,532 @@
+//! Resolving the label conflicts of imported and merged items.
+//!
+//! Labels are unique, so an incoming item (of an export bundle, or of another
+//! replica of the vault) can't simply be added if a different local item has
+//! the same label. For each such conflict, the user decides whether to keep
+//! the local item and drop the incoming one, to take the incoming item instead,
+//! or to keep both, renaming the incoming one. Decisions are made in a dialog
+//! that shows the two items side by side, or for every conflict at once by the
+//! `--on-conflict` option, and they are applied within a single transaction,
+//! along with the rest of the import (see `Database::import_items()`).
+
+use std::collections::HashSet;
+use std::time::Duration;
+use chrono::{Local, Utc};
+use clap::ValueEnum;
+use ratatui::{
+    Terminal,
+    backend::Backend,
+    buffer::Buffer,
+    layout::{Constraint, Layout, Rect},
+    style::{Modifier, Style},
+    text::Line,
+    widgets::{Block, BorderType, Padding, Paragraph, Widget, Wrap},
+    crossterm::event::{Event, KeyCode, KeyEventKind, KeyModifiers},
+};
+use crate::{
+    config::Theme,
+    crypto::{Pepper, EncryptionInput},
+    db::{Database, Item},
+    fields::{Label, MasterPassword, PlaintextSecret},
+    format::ExportedItem,
+    oplog::Operation,
+    input::EventSource,
+    i18n::tr,
+    ui::hint,
+    error::{Error, Result},
+};
+
+
+/// How long the dialog waits for an event before redrawing, e.g. after a resize.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// What happens to an incoming item whose label belongs to a local item.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, ValueEnum)]
+pub enum Resolution {
+    /// The local item stays as it is, and the incoming one is dropped.
+    #[default]
+    KeepLocal,
+    /// The incoming item replaces the local one.
+    TakeIncoming,
+    /// Both items are kept, and the incoming one is renamed.
+    KeepBoth,
+}
+
+impl Resolution {
+    /// The resolution chosen by a key of the dialog, in lowercase.
+    fn from_key(key: char) -> Option<Self> {
+        match key {
+            'l' => Some(Resolution::KeepLocal),
+            'i' => Some(Resolution::TakeIncoming),
+            'b' => Some(Resolution::KeepBoth),
+            _ => None,
+        }
+    }
+}
+
+/// A local item, and an incoming item with the same label.
+#[derive(Clone, Debug)]
+pub struct Conflict {
+    /// The unique ID of the local item.
+    pub local_uid: u64,
+    pub local: ExportedItem,
+    pub incoming: ExportedItem,
+    /// The operation of the other replica that added or renamed the incoming
+    /// item if merging, or `None` if importing an export bundle.
+    pub operation: Option<Operation>,
+    /// The label the incoming item gets if both items are kept.
+    pub renamed: Label,
+    pub resolution: Resolution,
+}
+
+impl Conflict {
+    pub fn new(local: Item, incoming: ExportedItem, operation: Option<Operation>, renamed: Label) -> Self {
+        Conflict {
+            local_uid: local.uid,
+            local: local.into(),
+            incoming,
+            operation,
+            renamed,
+            resolution: Resolution::default(),
+        }
+    }
+
+    /// Gives the incoming item its new label, if both items are kept. The label
+    /// is authenticated along with the secret, so this re-encrypts the secret
+    /// with the password of the incoming item, which must be known. The pepper
+    /// is only used if the item had one.
+    pub fn rename_incoming(&mut self, password: &MasterPassword, pepper: Option<&Pepper>) -> Result<()> {
+        let item = &self.incoming;
+        let secret = item.decryption_input(pepper).decrypt_and_verify(password.as_bytes())?;
+        let modified_at = Utc::now();
+        let input = EncryptionInput {
+            plaintext_secret: &PlaintextSecret::from(secret),
+            label: &self.renamed,
+            account: item.account.as_ref(),
+            last_modified_at: modified_at,
+            pepper: if item.peppered { pepper } else { None },
+        };
+        let output = input.encrypt_and_authenticate(password)?;
+
+        self.incoming = ExportedItem {
+            label: self.renamed.clone(),
+            account: item.account.clone(),
+            last_modified_at: modified_at,
+            encrypted_secret: output.encrypted_secret,
+            kdf_salt: output.kdf_salt,
+            auth_nonce: output.auth_nonce,
+            peppered: output.peppered,
+            kdf: output.kdf,
+            cipher: output.cipher,
+        };
+
+        Ok(())
+    }
+}
+
+/// Separates the items of an export bundle that can be added as they are
+/// from those whose label belongs to a local item.
+pub fn find_conflicts(db: &Database, items: Vec<ExportedItem>) -> Result<(Vec<ExportedItem>, Vec<Conflict>)> {
+    let mut taken: HashSet<String> = items.iter().map(|item| item.label.to_string()).collect();
+    let mut fresh = Vec::new();
+    let mut conflicts = Vec::new();
+
+    for item in items {
+        if !db.label_exists(&item.label)? {
+            fresh.push(item);
+            continue;
+        }
+
+        let local = db.item_by_label(&item.label)?.ok_or(Error::DuplicateLabel)?;
+        let renamed = unused_label(db, &item.label, &mut taken)?;
+
+        conflicts.push(Conflict::new(local, item, None, renamed));
+    }
+
+    Ok((fresh, conflicts))
+}
+
+/// Returns the first of "label (2)", "label (3)", etc. that is neither the label
+/// of a local item nor in `taken` (e.g. the labels of other incoming items),
+/// and adds it to `taken`.
+pub fn unused_label(db: &Database, label: &Label, taken: &mut HashSet<String>) -> Result<Label> {
+    let candidate = first_unused_label(
+        |n| format!("{label} ({})", n + 1),
+        |candidate| Ok(taken.contains(candidate) || db.label_exists(candidate)?),
+    )?;
+
+    taken.insert(candidate.clone());
+
+    Label::new(&candidate)
+}
+
+/// Returns the first of `candidate(1)`, `candidate(2)`, etc. for which
+/// `is_taken` returns `false`, e.g. the first label that doesn't exist yet.
+pub fn first_unused_label<C, T>(mut candidate: C, mut is_taken: T) -> Result<String>
+where
+    C: FnMut(u32) -> String,
+    T: FnMut(&str) -> Result<bool>,
+{
+    for n in 1_u32.. {
+        let candidate = candidate(n);
+
+        if !is_taken(&candidate)? {
+            return Ok(candidate);
+        }
+    }
+
+    unreachable!("ran out of labels")
+}
+
+/// Lets the user pick a resolution for each conflict, showing the local and
+/// the incoming item side by side, along with the outcome of the decision.
+#[derive(Clone, Debug)]
+pub struct ConflictDialog {
+    conflicts: Vec<Conflict>,
+    selected: usize,
+    theme: Theme,
+}
+
+impl ConflictDialog {
+    /// `conflicts` must not be empty.
+    pub fn new(conflicts: Vec<Conflict>, theme: Theme) -> Self {
+        assert!(!conflicts.is_empty(), "no conflicts to resolve");
+
+        ConflictDialog { conflicts, selected: 0, theme }
+    }
+
+    /// Shows the dialog until the user applies the decisions, and returns the
+    /// conflicts with their resolutions, or `Error::Cancelled` if the user
+    /// cancels, in which case nothing must be imported.
+    pub fn run<B: Backend>(
+        mut self,
+        terminal: &mut Terminal<B>,
+        events: &mut dyn EventSource,
+    ) -> Result<Vec<Conflict>> {
+        loop {
+            terminal.draw(|frame| frame.render_widget(&self, frame.area()))?;
+
+            let Some(Event::Key(key)) = events.next_event(POLL_INTERVAL)? else {
+                continue;
+            };
+
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+
+            match key.code {
+                KeyCode::Enter => return Ok(self.conflicts),
+                KeyCode::Esc => return Err(Error::Cancelled),
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    return Err(Error::Cancelled);
+                }
+                KeyCode::Up | KeyCode::Char('k') => {
+                    self.selected = self.selected.saturating_sub(1);
+                }
+                KeyCode::Down | KeyCode::Char('j') | KeyCode::Tab => {
+                    self.selected = (self.selected + 1).min(self.conflicts.len() - 1);
+                }
+                KeyCode::Char(c) => {
+                    let Some(resolution) = Resolution::from_key(c.to_ascii_lowercase()) else {
+                        continue;
+                    };
+
+                    // uppercase applies the same decision to every conflict
+                    if c.is_ascii_uppercase() {
+                        for conflict in &mut self.conflicts {
+                            conflict.resolution = resolution;
+                        }
+                    } else {
+                        self.conflicts[self.selected].resolution = resolution;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// The fields of `item`, highlighting those that differ in `other`.
+    fn item_lines(&self, item: &ExportedItem, other: &ExportedItem) -> Vec<Line<'static>> {
+        let fields = |item: &ExportedItem| [
+            (tr("Title"), item.label.to_string()),
+            (tr("Username or account"), item.account.as_ref().map(ToString::to_string).unwrap_or_default()),
+            (tr("Modified at"), item.last_modified_at.with_timezone(&Local).format("%F %T").to_string()),
+            (tr("Encrypted with pepper"), String::from(tr(if item.peppered { "yes" } else { "no" }))),
+        ];
+        let name_style = self.theme.border_highlight().add_modifier(Modifier::BOLD);
+        let mut lines = Vec::new();
+
+        for ((name, value), (_, other_value)) in fields(item).into_iter().zip(fields(other)) {
+            let style = if value == other_value { self.theme.default() } else { self.theme.highlight() };
+
+            if !lines.is_empty() {
+                lines.push(Line::default());
+            }
+            lines.push(Line::from(name).style(name_style));
+            lines.push(Line::from(value).style(style));
+        }
+
+        lines
+    }
+
+    /// Describes what happens to the two items of the conflict.
+    fn outcome_lines(&self, conflict: &Conflict) -> Vec<Line<'static>> {
+        let title = match conflict.resolution {
+            Resolution::KeepLocal => "Keep local item",
+            Resolution::TakeIncoming => "Take incoming item",
+            Resolution::KeepBoth => "Keep both items",
+        };
+        let sentences: &[&str] = match (conflict.resolution, conflict.operation.is_some()) {
+            (Resolution::KeepLocal, false) => &[
+                "The local item stays as it is.",
+                "The incoming item is not imported.",
+            ],
+            (Resolution::KeepLocal, true) => &[
+                "The local item stays as it is.",
+                "The incoming change is undone in the other vault.",
+            ],
+            (Resolution::TakeIncoming, _) => &[
+                "The incoming item replaces the local one.",
+                "The tags, expiry date and URL of the local item are kept.",
+            ],
+            (Resolution::KeepBoth, _) => &[
+                "The incoming item is added under a new title:",
+                "",
+                "Its password is asked for once you press Enter.",
+            ],
+        };
+        let title_style = self.theme.border_highlight().add_modifier(Modifier::BOLD);
+        let mut lines = vec![Line::from(tr(title)).style(title_style), Line::default()];
+
+        for &sentence in sentences {
+            // the empty sentence stands for the new title of the incoming item
+            let line = if sentence.is_empty() {
+                Line::from(conflict.renamed.to_string()).style(self.theme.highlight())
+            } else {
+                Line::from(tr(sentence)).style(self.theme.default())
+            };
+            lines.push(line);
+        }
+
+        lines
+    }
+
+    /// A pane of the dialog, highlighted if its item is kept.
+    fn pane(&self, title: &'static str, lines: Vec<Line<'static>>, is_kept: bool) -> Paragraph<'static> {
+        let border_style: Style = if is_kept { self.theme.border_highlight() } else { self.theme.border() };
+        let block = Block::bordered()
+            .title(hint(title))
+            .border_type(BorderType::Rounded)
+            .border_style(border_style)
+            .padding(Padding::horizontal(1));
+
+        Paragraph::new(lines).block(block).wrap(Wrap { trim: false })
+    }
+}
+
+impl Widget for &ConflictDialog {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let conflict = &self.conflicts[self.selected];
+        let title = tr("Conflict {} of {}")
+            .replacen("{}", &(self.selected + 1).to_string(), 1)
+            .replacen("{}", &self.conflicts.len().to_string(), 1);
+        let block = Block::bordered()
+            .title(format!(" {title} "))
+            .title_bottom(hint("<↑↓> Select"))
+            .title_bottom(hint("[L] Keep local"))
+            .title_bottom(hint("[I] Take incoming"))
+            .title_bottom(hint("[B] Keep both"))
+            .title_bottom(hint("<Shift> For all"))
+            .title_bottom(hint("<Enter> Apply"))
+            .title_bottom(hint("<Esc> Cancel"))
+            .border_type(BorderType::Rounded)
+            .border_style(self.theme.border_highlight().add_modifier(Modifier::BOLD));
+        let inner = block.inner(area);
+        let [local_area, incoming_area, outcome_area] =
+            Layout::horizontal([Constraint::Ratio(1, 3); 3]).areas(inner);
+        let keeps_local = conflict.resolution != Resolution::TakeIncoming;
+        let keeps_incoming = conflict.resolution != Resolution::KeepLocal;
+
+        block.render(area, buf);
+        self.pane("Local item", self.item_lines(&conflict.local, &conflict.incoming), keeps_local)
+            .render(local_area, buf);
+        self.pane("Incoming item", self.item_lines(&conflict.incoming, &conflict.local), keeps_incoming)
+            .render(incoming_area, buf);
+        self.pane("Result", self.outcome_lines(conflict), true)
+            .render(outcome_area, buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use ratatui::{Terminal, backend::TestBackend, buffer::Cell, crossterm::event::KeyCode};
+    use crate::config::{Theme, DatabaseOptions};
+    use crate::crypto::{KdfParams, CipherId, EncryptionInput};
+    use crate::db::Database;
+    use crate::fields::{Label, AccountName, PlaintextSecret, MasterPassword};
+    use crate::format::ExportedItem;
+    use crate::input::ScriptedEvents;
+    use crate::error::{Error, Result};
+    use super::{Conflict, ConflictDialog, Resolution, find_conflicts};
+
+
+    fn item(label: &str, account: &str, salt: u8) -> Result<ExportedItem> {
+        Ok(ExportedItem {
+            label: Label::new(label)?,
+            account: Some(AccountName::new(account)?),
+            last_modified_at: Utc.with_ymd_and_hms(2024, 3, 1, 12, 0, 0).unwrap(),
+            encrypted_secret: b"not actually encrypted".to_vec(),
+            kdf_salt: [salt; 16],
+            auth_nonce: [salt; 24],
+            peppered: false,
+            kdf: KdfParams::CURRENT,
+            cipher: CipherId::CURRENT,
+        })
+    }
+
+    /// An item as exported from another vault, with its secret actually encrypted.
+    fn encrypted(label: &str, account: &str, password: &MasterPassword) -> Result<ExportedItem> {
+        let label = Label::new(label)?;
+        let account = AccountName::new(account)?;
+        let last_modified_at = Utc::now();
+        let output = EncryptionInput {
+            plaintext_secret: &PlaintextSecret::new(b"correct horse"),
+            label: &label,
+            account: Some(&account),
+            last_modified_at,
+            pepper: None,
+        }.encrypt_and_authenticate(password)?;
+
+        Ok(ExportedItem {
+            label,
+            account: Some(account),
+            last_modified_at,
+            encrypted_secret: output.encrypted_secret,
+            kdf_salt: output.kdf_salt,
+            auth_nonce: output.auth_nonce,
+            peppered: output.peppered,
+            kdf: output.kdf,
+            cipher: output.cipher,
+        })
+    }
+
+    fn conflicts() -> Result<Vec<Conflict>> {
+        ["My Bank", "Webmail"]
+            .into_iter()
+            .zip(1..)
+            .map(|(label, uid)| {
+                Ok(Conflict {
+                    local_uid: uid,
+                    local: item(label, "alice", uid as u8)?,
+                    incoming: item(label, "bob", 10 + uid as u8)?,
+                    operation: None,
+                    renamed: Label::new(&format!("{label} (2)"))?,
+                    resolution: Resolution::default(),
+                })
+            })
+            .collect()
+    }
+
+    fn screen(terminal: &Terminal<TestBackend>) -> String {
+        let buffer = terminal.backend().buffer();
+
+        buffer.content
+            .chunks(usize::from(buffer.area.width))
+            .map(|row| row.iter().map(Cell::symbol).collect::<String>())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn decisions_are_made_per_conflict_or_for_all() -> Result<()> {
+        let mut terminal = Terminal::new(TestBackend::new(120, 20))?;
+        let dialog = ConflictDialog::new(conflicts()?, <Theme as Default>::default());
+        let mut events = ScriptedEvents::default();
+        events.type_text("b").key(KeyCode::Down).type_text("i").key(KeyCode::Enter);
+
+        let resolved = dialog.run(&mut terminal, &mut events)?;
+        let resolutions: Vec<_> = resolved.iter().map(|conflict| conflict.resolution).collect();
+        assert_eq!(resolutions, [Resolution::KeepBoth, Resolution::TakeIncoming]);
+
+        // the last frame shows the second conflict, and the incoming item replacing the local one
+        let text = screen(&terminal);
+        assert!(text.contains("Conflict 2 of 2"), "{text}");
+        assert!(text.contains("Webmail"), "{text}");
+        assert!(text.contains("The incoming item replaces"), "{text}");
+
+        let dialog = ConflictDialog::new(conflicts()?, <Theme as Default>::default());
+        let mut events = ScriptedEvents::default();
+        events.type_text("B").key(KeyCode::Enter);
+
+        let resolved = dialog.run(&mut terminal, &mut events)?;
+        assert!(resolved.iter().all(|conflict| conflict.resolution == Resolution::KeepBoth));
+        assert!(screen(&terminal).contains("My Bank (2)"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn imported_conflicts_are_resolved_as_decided() -> Result<()> {
+        let password = MasterPassword::new("hunter2")?;
+        let incoming = [encrypted("Bank", "bob", &password)?, encrypted("Mail", "bob", &password)?];
+        let resolutions = [Resolution::KeepLocal, Resolution::TakeIncoming, Resolution::KeepBoth];
+
+        for resolution in resolutions {
+            let mut db = Database::open(":memory:", &DatabaseOptions::default())?;
+            let local = db.add_item(item("Bank", "alice", 1)?.as_add_item_input())?;
+            db.tag_item(local.uid, "work")?;
+
+            let (fresh, mut conflicts) = find_conflicts(&db, incoming.to_vec())?;
+            assert_eq!(fresh.len(), 1);
+            assert_eq!(conflicts.len(), 1);
+            assert_eq!(conflicts[0].renamed.as_str(), "Bank (2)");
+
+            conflicts[0].resolution = resolution;
+
+            if resolution == Resolution::KeepBoth {
+                conflicts[0].rename_incoming(&password, None)?;
+            }
+
+            let count = db.import_items(&fresh, &conflicts)?;
+            let bank = db.item_by_label("Bank")?.expect("an item is still called Bank");
+            assert!(db.label_exists("Mail")?);
+            // the local item keeps its tags even if its contents are replaced
+            assert_eq!(bank.uid, local.uid);
+            assert_eq!(db.item_tags(bank.uid)?, ["work"]);
+
+            match resolution {
+                Resolution::KeepLocal => {
+                    assert_eq!(count, 1);
+                    assert_eq!(bank.account.as_deref(), Some("alice"));
+                }
+                Resolution::TakeIncoming => {
+                    assert_eq!(count, 2);
+                    assert_eq!(bank.account.as_deref(), Some("bob"));
+                }
+                Resolution::KeepBoth => {
+                    assert_eq!(count, 2);
+                    assert_eq!(bank.account.as_deref(), Some("alice"));
+
+                    let copy = db.item_by_label("Bank (2)")?.expect("the incoming item was renamed");
+                    let secret = copy.decryption_input(None).decrypt_and_verify(password.as_bytes())?;
+                    assert_eq!(secret.as_slice(), b"correct horse");
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn cancelling_resolves_nothing() -> Result<()> {
+        let mut terminal = Terminal::new(TestBackend::new(120, 20))?;
+        let dialog = ConflictDialog::new(conflicts()?, <Theme as Default>::default());
+        let mut events = ScriptedEvents::default();
+        events.type_text("I").key(KeyCode::Esc);
+
+        assert!(matches!(dialog.run(&mut terminal, &mut events), Err(Error::Cancelled)));
+
+        Ok(())
+    }
+}
@@ -1,15 +1,27 @@
 //! Key derivation, encryption, and authentication.
 
 use std::iter;
-use serde::Serialize;
+use std::mem;
+use std::fmt::{self, Debug, Formatter};
+use std::fs::{File, OpenOptions};
+use std::path::Path;
+use std::io::{self, Read, Write, ErrorKind};
+use serde::{Serialize, Deserialize};
 use chrono::{DateTime, Utc};
-use rand::seq::SliceRandom;
-use zeroize::Zeroizing;
+use rand::{RngCore, seq::SliceRandom};
 use block_padding::{RawPadding, Iso7816};
 use crypto_common::typenum::Unsigned;
-use argon2::Argon2;
-use chacha20poly1305::{XChaCha20Poly1305, KeyInit, aead::{Aead, Payload, KeySizeUser}};
-use crate::Result;
+use argon2::{Argon2, Algorithm, Params, Version};
+use ed25519_dalek::{SigningKey, SECRET_KEY_LENGTH};
+use chacha20poly1305::{
+    XChaCha20Poly1305, KeyInit,
+    aead::{Aead, AeadInPlace, Payload, KeySizeUser, stream::{EncryptorBE32, DecryptorBE32}},
+};
+use nanosql::{AsSqlTy, FromSql, ToSql, SqlTy, TyPrim, ToSqlOutput, ValueRef, FromSqlResult};
+use nanosql::rusqlite::{Result as SqliteResult, Error as SqliteError, types::FromSqlError};
+use crate::error::{Error, Result, ResultExt};
+use crate::memlock::{SecretBytes, SecretString};
+use crate::fields::{Label, AccountName, PlaintextSecret, MasterPassword};
 
 
 /// The length of the per-item password salt, in bytes.
@@ -33,18 +45,258 @@ pub const PASSWORD_CHARSET: &'static [u8] =
 /// force. This should satisfy even the most stringent requirements.
 pub const PASSWORD_LEN: usize = 40;
 
+/// The length of the machine-local pepper, in bytes.
+pub const PEPPER_LEN: usize = 32;
+
+/// The length of the plaintext chunks in streaming encryption, in bytes.
+pub const STREAM_CHUNK_LEN: usize = 64 * 1024;
+
+/// The length of the random nonce prefix of the STREAM construction, in bytes.
+/// The remaining 5 bytes of the 24-byte nonce hold the big-endian 32-bit chunk
+/// counter and the flag marking the last chunk.
+pub const STREAM_NONCE_PREFIX_LEN: usize = NONCE_LEN - 5;
+
+/// The length of the authentication tag appended to each encrypted chunk, in bytes.
+const TAG_LEN: usize = 16;
+
+/// Upper limit on the chunk length of streaming decryption, so that a corrupt or
+/// malicious chunk length in the header of a bundle can't exhaust the memory.
+const MAX_STREAM_CHUNK_LEN: usize = 16 * 1024 * 1024;
+
+/// Upper limits on the Argon2 memory cost (in KiB), number of iterations and degree
+/// of parallelism. The parameters are read from items, bundles and sync files, so
+/// without a limit, a corrupt or malicious file could make deriving a key take
+/// gigabytes of memory, or practically forever.
+const MAX_ARGON2_M_COST: u32 = 1024 * 1024;
+const MAX_ARGON2_T_COST: u32 = 64;
+const MAX_ARGON2_P_COST: u32 = 64;
+
+/// Identifies the key derivation function and its parameters used for
+/// deriving the per-item encryption keys from the encryption password.
+///
+/// Together with [`CipherId`], this is the registry of supported algorithms:
+/// every item records the ones it was encrypted with, so supporting a new
+/// algorithm (or new parameters) only requires adding a variant and handling
+/// it in the `match`es below, while existing items remain decryptable.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(tag = "algorithm", rename_all = "lowercase")]
+pub enum KdfParams {
+    /// The Argon2id password hash, with memory cost in KiB, number of iterations,
+    /// and degree of parallelism, respectively.
+    Argon2id {
+        version: u32,
+        m_cost: u32,
+        t_cost: u32,
+        p_cost: u32,
+    },
+}
+
+impl KdfParams {
+    /// The KDF settings currently in use. These **must** be kept in sync with
+    /// the parameters of the `Argon2` instance created during encryption.
+    pub const CURRENT: Self = KdfParams::Argon2id {
+        version: Version::V0x13 as u32,
+        m_cost: Params::DEFAULT_M_COST,
+        t_cost: Params::DEFAULT_T_COST,
+        p_cost: Params::DEFAULT_P_COST,
+    };
+
+    /// The KDF settings of items that were created before the settings were
+    /// recorded along with each item. These **must never** change.
+    pub const LEGACY: Self = KdfParams::Argon2id {
+        version: 0x13,
+        m_cost: 19 * 1024,
+        t_cost: 2,
+        p_cost: 1,
+    };
+
+    /// Creates the KDF context described by these parameters. If a pepper
+    /// is given, it is used as the secret input of the KDF.
+    fn hasher(self, pepper: Option<&Pepper>) -> Result<Argon2<'_>> {
+        match self {
+            KdfParams::Argon2id { version, m_cost, t_cost, p_cost } => {
+                if m_cost > MAX_ARGON2_M_COST || t_cost > MAX_ARGON2_T_COST || p_cost > MAX_ARGON2_P_COST {
+                    return Err(Error::UnsupportedEncryption);
+                }
+
+                let version = Version::try_from(version)?;
+                let params = Params::new(m_cost, t_cost, p_cost, None)?;
+
+                match pepper {
+                    Some(pepper) => Argon2::new_with_secret(
+                        pepper.0.as_slice(),
+                        Algorithm::Argon2id,
+                        version,
+                        params,
+                    ).map_err(Into::into),
+                    None => Ok(Argon2::new(Algorithm::Argon2id, version, params)),
+                }
+            }
+        }
+    }
+}
+
+/// Identifies the authenticated encryption algorithm used for encrypting secrets.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum CipherId {
+    /// XChaCha20 for encryption and Poly1305 for authentication.
+    #[serde(rename = "xchacha20poly1305")]
+    XChaCha20Poly1305,
+}
+
+impl CipherId {
+    /// The cipher currently in use.
+    pub const CURRENT: Self = CipherId::XChaCha20Poly1305;
+
+    /// The cipher of items that were created before the cipher was recorded
+    /// along with each item. This **must never** change.
+    pub const LEGACY: Self = CipherId::XChaCha20Poly1305;
+
+    /// The identifier of the cipher in the database and in bundles.
+    pub const fn name(self) -> &'static str {
+        match self {
+            CipherId::XChaCha20Poly1305 => "xchacha20poly1305",
+        }
+    }
+
+    /// The inverse of `name()`. Returns `None` for unknown ciphers.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "xchacha20poly1305" => Some(CipherId::XChaCha20Poly1305),
+            _ => None,
+        }
+    }
+
+    /// The length of the key, in bytes.
+    fn key_len(self) -> usize {
+        match self {
+            CipherId::XChaCha20Poly1305 => <XChaCha20Poly1305 as KeySizeUser>::KeySize::USIZE,
+        }
+    }
+
+    /// Encrypts and authenticates `payload`, returning the ciphertext and the tag.
+    fn encrypt(self, key: &[u8], nonce: &[u8; NONCE_LEN], payload: Payload<'_, '_>) -> Result<Vec<u8>> {
+        match self {
+            CipherId::XChaCha20Poly1305 => {
+                let aead = XChaCha20Poly1305::new_from_slice(key)?;
+                aead.encrypt(<_>::from(nonce), payload).map_err(Into::into)
+            }
+        }
+    }
+
+    /// Decrypts and verifies `buffer` in place, replacing the ciphertext with the plaintext.
+    fn decrypt_in_place(
+        self,
+        key: &[u8],
+        nonce: &[u8; NONCE_LEN],
+        associated_data: &[u8],
+        buffer: &mut Vec<u8>,
+    ) -> Result<()> {
+        match self {
+            CipherId::XChaCha20Poly1305 => {
+                let aead = XChaCha20Poly1305::new_from_slice(key)?;
+                aead.decrypt_in_place(<_>::from(nonce), associated_data, buffer).map_err(Into::into)
+            }
+        }
+    }
+}
+
 /// The pieces of data that are not encrypted but still validated using the
 /// specified encryption password, for tamper detection.
 ///
 /// Fields are in alphabetical order, so that round-tripping through `Value`
 /// results in bitwise-identical JSON. (This is a precautionary measure.)
+///
+/// The algorithms are only included if they differ from the legacy ones, so
+/// that the additional data of items created before they were recorded (and
+/// of items still using the same algorithms) stays exactly the same.
 #[derive(Clone, Copy, Debug, Serialize)]
 struct AdditionalData<'a> {
     account: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cipher: Option<CipherId>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    kdf: Option<KdfParams>,
     label: &'a str,
     last_modified_at: DateTime<Utc>,
 }
 
+impl<'a> AdditionalData<'a> {
+    fn new(
+        label: &'a str,
+        account: Option<&'a str>,
+        last_modified_at: DateTime<Utc>,
+        kdf: KdfParams,
+        cipher: CipherId,
+    ) -> Self {
+        AdditionalData {
+            account,
+            cipher: Some(cipher).filter(|&cipher| cipher != CipherId::LEGACY),
+            kdf: Some(kdf).filter(|&kdf| kdf != KdfParams::LEGACY),
+            label,
+            last_modified_at,
+        }
+    }
+}
+
+/// A machine-local secret that is mixed into key derivation in addition to the
+/// per-item salt. Unlike the salt, it is **not** stored in the database, so a copy
+/// of the database alone is not enough for brute-forcing weak passwords offline.
+pub struct Pepper(SecretBytes);
+
+impl Pepper {
+    /// Reads the pepper from the file at `path`. If the file does not exist yet,
+    /// a new random pepper is generated and written to it. On Unix, the file is
+    /// created with permissions `0600` (read-write for the owner only).
+    pub fn load_or_create(path: &Path) -> Result<Self> {
+        match File::open(path) {
+            Ok(file) => {
+                // Reading one more byte than needed detects overly long files
+                // without ever growing the buffer beyond its initial capacity.
+                let mut bytes = SecretBytes::with_capacity(PEPPER_LEN + 1);
+                file.take(PEPPER_LEN as u64 + 1)
+                    .read_to_end(bytes.as_mut_vec())
+                    .context("Can't read pepper file")?;
+
+                if bytes.len() != PEPPER_LEN {
+                    return Err(Error::InvalidPepperFile);
+                }
+
+                Ok(Pepper(bytes))
+            }
+            Err(error) if error.kind() == ErrorKind::NotFound => {
+                let pepper = Pepper::generate();
+                let mut options = OpenOptions::new();
+                options.write(true).create_new(true);
+
+                #[cfg(unix)]
+                std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+                let mut file = options.open(path).context("Can't create pepper file")?;
+                file.write_all(&pepper.0)?;
+                file.sync_all()?;
+
+                Ok(pepper)
+            }
+            Err(error) => Err(Error::context(error, "Can't open pepper file")),
+        }
+    }
+
+    /// Generates a new random pepper. `thread_rng()` is a CSPRNG.
+    fn generate() -> Self {
+        let mut bytes = SecretBytes::zeroed(PEPPER_LEN);
+        rand::thread_rng().fill_bytes(bytes.as_mut_vec());
+        Pepper(bytes)
+    }
+
+}
+
+impl Debug for Pepper {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        formatter.write_str("Pepper(<redacted>)")
+    }
+}
+
 /// The result of encrypting and authenticating the secret, and authenticating
 /// the additional data, using the specified password. The salt for the Key
 /// Derivation Function and the nonce for the authentication are generated
@@ -59,40 +311,78 @@ pub struct EncryptionOutput {
     pub kdf_salt: [u8; RECOMMENDED_SALT_LEN],
     /// The randomly-generated nonce, used for initializing the AEAD hash.
     pub auth_nonce: [u8; NONCE_LEN],
+    /// Whether the pepper was mixed into the key derivation.
+    pub peppered: bool,
+    /// The key derivation function and its parameters.
+    pub kdf: KdfParams,
+    /// The cipher used for encryption and authentication.
+    pub cipher: CipherId,
 }
 
 /// The plain old data input for encryption, except for the password.
 #[derive(Clone, Copy, Debug)]
 pub struct EncryptionInput<'a> {
-    pub plaintext_secret: &'a [u8],
-    pub label: &'a str,
-    pub account: Option<&'a str>,
+    pub plaintext_secret: &'a PlaintextSecret,
+    pub label: &'a Label,
+    pub account: Option<&'a AccountName>,
     pub last_modified_at: DateTime<Utc>,
+    /// If given, the pepper is mixed into the key derivation.
+    pub pepper: Option<&'a Pepper>,
 }
 
 impl EncryptionInput<'_> {
     /// Encrypts and authenticates the secret, and authenticates the additional data,
     /// using a key derived from the `encryption_password`.
-    pub fn encrypt_and_authenticate(self, encryption_password: &[u8]) -> Result<EncryptionOutput> {
+    /// The secret is encrypted using the currently recommended algorithms.
+    pub fn encrypt_and_authenticate(self, encryption_password: &MasterPassword) -> Result<EncryptionOutput> {
+        self.encrypt_with(encryption_password, KdfParams::CURRENT, CipherId::CURRENT)
+    }
+
+    fn encrypt_with(
+        self,
+        encryption_password: &MasterPassword,
+        kdf: KdfParams,
+        cipher: CipherId,
+    ) -> Result<EncryptionOutput> {
         // Pad the secret to a multiple of the block size.
         // Directly extending the String could re-allocate, which would leave
         // the contents of the old allocation in the memory, without zeroizing it.
         // To prevent this, what we do instead is pre-allocate a buffer of the
         // required size, then copy the secret over, and perform the padding in
         // the new buffer.
-        let unpadded_secret = self.plaintext_secret;
+        let unpadded_secret = self.plaintext_secret.as_bytes();
         let total_len = (unpadded_secret.len() / PADDING_BLOCK_SIZE + 1) * PADDING_BLOCK_SIZE;
-        let mut padded_secret = Zeroizing::new(vec![0x00_u8; total_len]);
+        let mut padded_secret = SecretBytes::zeroed(total_len);
 
-        padded_secret[..unpadded_secret.len()].copy_from_slice(unpadded_secret);
-        Iso7816::raw_pad(padded_secret.as_mut_slice(), unpadded_secret.len());
+        padded_secret.as_mut_vec()[..unpadded_secret.len()].copy_from_slice(unpadded_secret);
+        Iso7816::raw_pad(padded_secret.as_mut_vec(), unpadded_secret.len());
 
+        let padded_secret = PlaintextSecret::from(padded_secret);
+
+        EncryptionInput {
+            plaintext_secret: &padded_secret,
+            ..self
+        }
+        .encrypt_without_padding(encryption_password, kdf, cipher)
+    }
+
+    /// Encrypts `plaintext_secret` as-is, which must already be padded. This is
+    /// separate from `encrypt_with()` so that the fuzz target can make
+    /// `decrypt_and_verify()` unpad malformed plaintexts.
+    pub fn encrypt_without_padding(
+        self,
+        encryption_password: &MasterPassword,
+        kdf: KdfParams,
+        cipher: CipherId,
+    ) -> Result<EncryptionOutput> {
         // Create the additional authenticated data.
-        let additional_data = AdditionalData {
-            account: self.account,
-            label: self.label,
-            last_modified_at: self.last_modified_at,
-        };
+        let additional_data = AdditionalData::new(
+            self.label.as_str(),
+            self.account.map(AccountName::as_str),
+            self.last_modified_at,
+            kdf,
+            cipher,
+        );
         let additional_data_str = serde_json::to_string(&additional_data)?;
 
         // Generate random salt and nonce. `rand::random()` uses a CSPRNG.
@@ -100,27 +390,26 @@ impl EncryptionInput<'_> {
         let auth_nonce: [u8; NONCE_LEN] = rand::random();
 
         // Create KDF context.
-        // This uses recommended parameters (19 MB memory, 2 rounds, 1 degree of parallelism).
-        let hasher = Argon2::default();
+        let hasher = kdf.hasher(self.pepper)?;
 
         // The actual encryption key is cleared (overwritten with all 0s) upon drop.
-        let mut key = Zeroizing::new([0_u8; <XChaCha20Poly1305 as KeySizeUser>::KeySize::USIZE]);
-        hasher.hash_password_into(encryption_password, &kdf_salt, &mut *key)?;
-
-        // Create encryption and authentication context.
-        let aead = XChaCha20Poly1305::new_from_slice(key.as_slice())?;
+        let mut key = SecretBytes::zeroed(cipher.key_len());
+        hasher.hash_password_into(encryption_password.as_bytes(), &kdf_salt, key.as_mut_vec())?;
 
         // Actually perform the encryption and authentication.
         let payload = Payload {
-            msg: padded_secret.as_slice(),
+            msg: self.plaintext_secret.as_bytes(),
             aad: additional_data_str.as_bytes(),
         };
-        let encrypted_secret = aead.encrypt(<_>::from(&auth_nonce), payload)?;
+        let encrypted_secret = cipher.encrypt(key.as_slice(), &auth_nonce, payload)?;
 
         Ok(EncryptionOutput {
             encrypted_secret,
             kdf_salt,
             auth_nonce,
+            peppered: self.pepper.is_some(),
+            kdf,
+            cipher,
         })
     }
 }
@@ -135,69 +424,384 @@ pub struct DecryptionInput<'a> {
     pub label: &'a str,
     pub account: Option<&'a str>,
     pub last_modified_at: DateTime<Utc>,
+    /// Whether the pepper was mixed into the key derivation during encryption.
+    pub peppered: bool,
+    /// The key derivation function and its parameters used during encryption.
+    pub kdf: KdfParams,
+    /// The cipher used during encryption.
+    pub cipher: CipherId,
+    /// The currently configured pepper, if any.
+    pub pepper: Option<&'a Pepper>,
 }
 
 impl DecryptionInput<'_> {
     /// Decrypts and verifies the secret, and verifies the additional data,
     /// using a key derived from the `decryption_password`.
-    pub fn decrypt_and_verify(self, decryption_password: &[u8]) -> Result<Zeroizing<Vec<u8>>> {
+    pub fn decrypt_and_verify(self, decryption_password: &[u8]) -> Result<SecretBytes> {
         // Re-create the additional authenticated data. This helps detect when
         // the displayed label or account have been tampered with in the database.
         // This **must** be bitwise identical to the data used during encryption.
-        let additional_data = AdditionalData {
-            account: self.account,
-            label: self.label,
-            last_modified_at: self.last_modified_at,
-        };
+        let additional_data = AdditionalData::new(
+            self.label,
+            self.account,
+            self.last_modified_at,
+            self.kdf,
+            self.cipher,
+        );
         let additional_data_str = serde_json::to_string(&additional_data)?;
 
         // Create KDF context.
-        // This MUST use the same parameters as hashing during encryption.
-        let hasher = Argon2::default();
+        // This MUST use the same parameters (and pepper) as hashing during encryption.
+        let pepper = match (self.peppered, self.pepper) {
+            (false, _) => None,
+            (true, Some(pepper)) => Some(pepper),
+            (true, None) => return Err(Error::PepperRequired),
+        };
+        let hasher = self.kdf.hasher(pepper)?;
 
         // The actual encryption key is cleared (overwritten with all 0s) upon drop.
-        let mut key = Zeroizing::new([0_u8; <XChaCha20Poly1305 as KeySizeUser>::KeySize::USIZE]);
-        hasher.hash_password_into(decryption_password, &self.kdf_salt, &mut *key)?;
-
-        // Create decryption and verification context.
-        let aead = XChaCha20Poly1305::new_from_slice(key.as_slice())?;
-
-        // Actually perform the decryption and verification.
-        let payload = Payload {
-            msg: self.encrypted_secret,
-            aad: additional_data_str.as_bytes(),
-        };
-        let plaintext_secret = aead.decrypt(<_>::from(&self.auth_nonce), payload)?;
-        let mut plaintext_secret = Zeroizing::new(plaintext_secret);
+        let mut key = SecretBytes::zeroed(self.cipher.key_len());
+        hasher.hash_password_into(decryption_password, &self.kdf_salt, key.as_mut_vec())?;
+
+        // Actually perform the decryption and verification. This happens in place,
+        // so that the plaintext is only ever written to memory that we control.
+        // Decryption only ever shrinks the buffer, so it never re-allocates.
+        let mut plaintext_secret = SecretBytes::with_capacity(self.encrypted_secret.len());
+        plaintext_secret.as_mut_vec().extend_from_slice(self.encrypted_secret);
+        self.cipher.decrypt_in_place(
+            key.as_slice(),
+            &self.auth_nonce,
+            additional_data_str.as_bytes(),
+            plaintext_secret.as_mut_vec(),
+        )?;
 
         // Un-pad the decrypted plaintext
         let unpadded_len = Iso7816::raw_unpad(plaintext_secret.as_slice())?.len();
-        plaintext_secret.truncate(unpadded_len);
+        plaintext_secret.as_mut_vec().truncate(unpadded_len);
 
         Ok(plaintext_secret)
     }
 }
 
-/// Randomly generates a cryptographically strong (unpredictable) password.
-pub fn generate_password() -> Zeroizing<String> {
+/// Encrypts and authenticates arbitrarily long data read from `reader`, and writes
+/// the ciphertext to `writer`, using a key derived from `encryption_password`.
+///
+/// Unlike [`EncryptionInput::encrypt_and_authenticate()`], this never holds more
+/// than two chunks of the plaintext in memory. It uses the STREAM construction
+/// (Hoang, Reyhanitabar, Rogaway & Vizár, 2015): every chunk is authenticated
+/// separately, and the nonce of each chunk includes its index as well as a flag
+/// marking the last chunk, so reordering, dropping, or truncating chunks is also
+/// detected. The `associated_data` is authenticated along with every chunk.
+///
+/// The output is self-contained: it starts with the freshly-generated KDF salt
+/// (16 bytes) and nonce prefix (19 bytes), followed by the encrypted chunks, each
+/// of which is `STREAM_CHUNK_LEN + 16` bytes long, except for the last one, which
+/// may be shorter (but always contains at least the 16-byte authentication tag).
+///
+/// The key is derived using [`KdfParams::CURRENT`], and the chunks are encrypted
+/// using [`CipherId::CURRENT`]; these must be passed to [`decrypt_stream()`].
+pub fn encrypt_stream<R, W>(
+    encryption_password: &[u8],
+    associated_data: &[u8],
+    reader: R,
+    writer: W,
+) -> Result<()>
+where
+    R: Read,
+    W: Write,
+{
+    encrypt_stream_with(
+        encryption_password,
+        associated_data,
+        KdfParams::CURRENT,
+        CipherId::CURRENT,
+        STREAM_CHUNK_LEN,
+        reader,
+        writer,
+    )
+}
+
+fn encrypt_stream_with<R, W>(
+    encryption_password: &[u8],
+    associated_data: &[u8],
+    kdf: KdfParams,
+    cipher: CipherId,
+    chunk_len: usize,
+    mut reader: R,
+    mut writer: W,
+) -> Result<()>
+where
+    R: Read,
+    W: Write,
+{
+    // Generate random salt and nonce prefix. `rand::random()` uses a CSPRNG.
+    let kdf_salt: [u8; RECOMMENDED_SALT_LEN] = rand::random();
+    let nonce_prefix: [u8; STREAM_NONCE_PREFIX_LEN] = rand::random();
+
+    let key = derive_stream_key(encryption_password, &kdf_salt, kdf, cipher)?;
+    let mut encryptor = match cipher {
+        CipherId::XChaCha20Poly1305 => {
+            let aead = XChaCha20Poly1305::new_from_slice(key.as_slice())?;
+            EncryptorBE32::from_aead(aead, <_>::from(&nonce_prefix))
+        }
+    };
+
+    writer.write_all(&kdf_salt)?;
+    writer.write_all(&nonce_prefix)?;
+
+    // The buffers are allocated with enough capacity for the authentication tag
+    // up front, so that in-place encryption never re-allocates.
+    let mut current = SecretBytes::with_capacity(chunk_len + TAG_LEN);
+    let mut next = SecretBytes::with_capacity(chunk_len + TAG_LEN);
+
+    // A chunk can only be encrypted once we know whether it is the last one,
+    // so we always need to read one chunk ahead.
+    read_chunk(&mut reader, current.as_mut_vec(), chunk_len)?;
+
+    loop {
+        read_chunk(&mut reader, next.as_mut_vec(), chunk_len)?;
+
+        if next.is_empty() {
+            encryptor.encrypt_last_in_place(associated_data, current.as_mut_vec())?;
+            writer.write_all(&current)?;
+            break;
+        }
+
+        encryptor.encrypt_next_in_place(associated_data, current.as_mut_vec())?;
+        writer.write_all(&current)?;
+        mem::swap(&mut current, &mut next);
+    }
+
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Decrypts and verifies data produced by [`encrypt_stream()`], and writes the
+/// plaintext to `writer`, using a key derived from `decryption_password`.
+///
+/// The `kdf`, the `cipher` and the `chunk_len` must be the ones the data was
+/// encrypted with, which may differ from the current ones if the data is old.
+/// A chunk length of 0 or of more than 16 MiB is rejected as unsupported.
+///
+/// Each chunk is verified before being written, but the end of the stream can
+/// only be verified once all preceding chunks have been written. Therefore, if
+/// this function returns an error, then the output written so far **must** be
+/// discarded, because it may be incomplete.
+pub fn decrypt_stream<R, W>(
+    decryption_password: &[u8],
+    associated_data: &[u8],
+    kdf: KdfParams,
+    cipher: CipherId,
+    chunk_len: usize,
+    mut reader: R,
+    mut writer: W,
+) -> Result<()>
+where
+    R: Read,
+    W: Write,
+{
+    if chunk_len == 0 || chunk_len > MAX_STREAM_CHUNK_LEN {
+        return Err(Error::UnsupportedEncryption);
+    }
+
+    let mut kdf_salt = [0_u8; RECOMMENDED_SALT_LEN];
+    let mut nonce_prefix = [0_u8; STREAM_NONCE_PREFIX_LEN];
+
+    reader.read_exact(&mut kdf_salt)?;
+    reader.read_exact(&mut nonce_prefix)?;
+
+    let key = derive_stream_key(decryption_password, &kdf_salt, kdf, cipher)?;
+    let mut decryptor = match cipher {
+        CipherId::XChaCha20Poly1305 => {
+            let aead = XChaCha20Poly1305::new_from_slice(key.as_slice())?;
+            DecryptorBE32::from_aead(aead, <_>::from(&nonce_prefix))
+        }
+    };
+
+    let mut current = SecretBytes::with_capacity(chunk_len + TAG_LEN);
+    let mut next = SecretBytes::with_capacity(chunk_len + TAG_LEN);
+
+    read_chunk(&mut reader, current.as_mut_vec(), chunk_len + TAG_LEN)?;
+
+    loop {
+        read_chunk(&mut reader, next.as_mut_vec(), chunk_len + TAG_LEN)?;
+
+        if next.is_empty() {
+            decryptor.decrypt_last_in_place(associated_data, current.as_mut_vec())?;
+            writer.write_all(&current)?;
+            break;
+        }
+
+        decryptor.decrypt_next_in_place(associated_data, current.as_mut_vec())?;
+        writer.write_all(&current)?;
+        mem::swap(&mut current, &mut next);
+    }
+
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Derives the key of `cipher` for streaming encryption from the password and the salt.
+fn derive_stream_key(
+    password: &[u8],
+    kdf_salt: &[u8; RECOMMENDED_SALT_LEN],
+    kdf: KdfParams,
+    cipher: CipherId,
+) -> Result<SecretBytes> {
+    // The pepper is local to the machine, but bundles are meant to be portable.
+    let hasher = kdf.hasher(None)?;
+
+    // The actual encryption key is cleared (overwritten with all 0s) upon drop.
+    let mut key = SecretBytes::zeroed(cipher.key_len());
+    hasher.hash_password_into(password, kdf_salt, key.as_mut_vec())?;
+
+    Ok(key)
+}
+
+/// Describes how the key signing the audit reports of a vault (see `report`) is
+/// derived from the encryption password. It's generated for the first report
+/// and stored in the vault, so that every report is signed with the same key,
+/// which only someone who knows the password (and has the pepper, if any) can use.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct SigningKeyParams {
+    /// The random salt of the key derivation.
+    pub salt: [u8; RECOMMENDED_SALT_LEN],
+    /// The key derivation function and its parameters.
+    pub kdf: KdfParams,
+    /// Whether the pepper is mixed into the key derivation.
+    pub peppered: bool,
+}
+
+impl SigningKeyParams {
+    /// A random salt, with the current KDF settings. The pepper is used if one is configured.
+    pub fn generate(pepper: Option<&Pepper>) -> Self {
+        SigningKeyParams {
+            // `rand::random()` uses a CSPRNG.
+            salt: rand::random(),
+            kdf: KdfParams::CURRENT,
+            peppered: pepper.is_some(),
+        }
+    }
+
+    /// Derives the signing key in the same way as the encryption keys of items,
+    /// so that the public key published with every report is no easier to
+    /// brute-force than the items themselves.
+    pub fn derive_key(&self, password: &[u8], pepper: Option<&Pepper>) -> Result<SigningKey> {
+        let pepper = match (self.peppered, pepper) {
+            (true, None) => return Err(Error::PepperRequired),
+            (true, Some(pepper)) => Some(pepper),
+            (false, _) => None,
+        };
+        let hasher = self.kdf.hasher(pepper)?;
+        let mut seed = SecretBytes::zeroed(SECRET_KEY_LENGTH);
+        hasher.hash_password_into(password, &self.salt, seed.as_mut_vec())?;
+
+        let seed: &[u8; SECRET_KEY_LENGTH] = seed
+            .as_slice()
+            .try_into()
+            .map_err(|_| Error::InvalidLength(crypto_common::InvalidLength))?;
+
+        Ok(SigningKey::from_bytes(seed))
+    }
+}
+
+/// Replaces the contents of `buf` with the next at most `chunk_len` bytes of the
+/// input. The chunk is only shorter than `chunk_len` at the end of the input.
+/// The capacity of `buf` must be at least `chunk_len`, so it is never re-allocated.
+fn read_chunk<R: Read>(reader: &mut R, buf: &mut Vec<u8>, chunk_len: usize) -> io::Result<()> {
+    debug_assert!(buf.capacity() >= chunk_len);
+
+    buf.clear();
+    buf.resize(chunk_len, 0);
+
+    let mut len = 0;
+
+    while len < chunk_len {
+        match reader.read(&mut buf[len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(error) if error.kind() == ErrorKind::Interrupted => {}
+            Err(error) => return Err(error),
+        }
+    }
+
+    buf.truncate(len);
+
+    Ok(())
+}
+
+/// Randomly generates a cryptographically strong (unpredictable) password of
+/// `len` ASCII characters taken from `charset`, typically `PASSWORD_CHARSET`
+/// and `PASSWORD_LEN`. The password is empty if the character set is.
+pub fn generate_password_from(charset: &[u8], len: usize) -> SecretString {
     // `thread_rng()` returns a CSPRNG.
     let mut rng = rand::thread_rng();
+    let mut password = SecretString::with_capacity(len);
+
+    for &c in iter::from_fn(|| charset.choose(&mut rng)).take(len) {
+        password.push(char::from(c));
+    }
+
+    password
+}
+
+/// The KDF parameters are stored as JSON, in the same
+/// representation as in the header of export bundles.
+impl AsSqlTy for KdfParams {
+    const SQL_TY: SqlTy = SqlTy::new(TyPrim::Text);
+
+    type Borrowed<'p> = Self;
+}
+
+impl ToSql for KdfParams {
+    fn to_sql(&self) -> SqliteResult<ToSqlOutput<'_>> {
+        serde_json::to_string(self)
+            .map(ToSqlOutput::from)
+            .map_err(|error| SqliteError::ToSqlConversionFailure(Box::new(error)))
+    }
+}
+
+impl FromSql for KdfParams {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        serde_json::from_str(value.as_str()?).map_err(|error| FromSqlError::Other(Box::new(error)))
+    }
+}
+
+impl AsSqlTy for CipherId {
+    const SQL_TY: SqlTy = SqlTy::new(TyPrim::Text);
+
+    type Borrowed<'p> = Self;
+}
+
+impl ToSql for CipherId {
+    fn to_sql(&self) -> SqliteResult<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.name()))
+    }
+}
 
-    iter::from_fn(|| PASSWORD_CHARSET.choose(&mut rng))
-        .copied()
-        .map(char::from)
-        .take(PASSWORD_LEN)
-        .collect::<String>()
-        .into()
+impl FromSql for CipherId {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        CipherId::from_name(value.as_str()?).ok_or(FromSqlError::InvalidType)
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use chrono::{Utc, Days};
+    use chrono::{DateTime, Utc, Days};
     use rand::{Rng, RngCore, distributions::{Standard, DistString}};
     use zxcvbn::{zxcvbn, Score};
+    use proptest::prelude::*;
+    use proptest::{collection::vec, option, sample::Index};
+    use block_padding::{RawPadding, Iso7816};
     use crate::error::{Error, Result};
-    use super::{EncryptionInput, DecryptionInput, PADDING_BLOCK_SIZE, PASSWORD_LEN};
+    use crate::memlock::SecretBytes;
+    use crate::fields::{Label, AccountName, PlaintextSecret, MasterPassword};
+    use super::{EncryptionInput, EncryptionOutput, DecryptionInput, Pepper, PADDING_BLOCK_SIZE};
+    use super::{PASSWORD_CHARSET, PASSWORD_LEN};
+    use super::{KdfParams, CipherId, SigningKeyParams};
+    use super::{encrypt_stream, encrypt_stream_with, decrypt_stream, STREAM_CHUNK_LEN, TAG_LEN};
 
 
     #[test]
@@ -216,21 +820,29 @@ mod tests {
         for payload in [p0, p1, p2, p3] {
             let password_len: usize = rng.gen_range(8..64);
             let password = Standard.sample_string(&mut rng, password_len);
+            let plaintext_secret = PlaintextSecret::new(&payload);
+            let label = Label::new("the precise label does not matter")?;
+            let account = AccountName::new("my uninteresting account name")?;
             let encryption_input = EncryptionInput {
-                plaintext_secret: payload.as_slice(),
-                label: "the precise label does not matter",
-                account: Some("my uninteresting account name"),
+                plaintext_secret: &plaintext_secret,
+                label: &label,
+                account: Some(&account),
                 last_modified_at: timestamp,
+                pepper: None,
             };
 
-            let output = encryption_input.encrypt_and_authenticate(password.as_bytes())?;
+            let output = encryption_input.encrypt_and_authenticate(&MasterPassword::new(&password)?)?;
             let decryption_input = DecryptionInput {
                 encrypted_secret: output.encrypted_secret.as_slice(),
                 kdf_salt: output.kdf_salt,
                 auth_nonce: output.auth_nonce,
                 label: encryption_input.label,
-                account: encryption_input.account,
+                account: encryption_input.account.map(AccountName::as_str),
                 last_modified_at: timestamp,
+                peppered: output.peppered,
+                kdf: output.kdf,
+                cipher: output.cipher,
+                pepper: None,
             };
             let decrypted_secret = decryption_input.decrypt_and_verify(password.as_bytes())?;
 
@@ -256,21 +868,29 @@ mod tests {
         for payload in [p0, p1, p2, p3] {
             let password_len: usize = rng.gen_range(8..64);
             let password = Standard.sample_string(&mut rng, password_len);
+            let plaintext_secret = PlaintextSecret::new(&payload);
+            let label = Label::new("the precise label does not matter")?;
+            let account = AccountName::new("my uninteresting account name")?;
             let encryption_input = EncryptionInput {
-                plaintext_secret: payload.as_slice(),
-                label: "the precise label does not matter",
-                account: Some("my uninteresting account name"),
+                plaintext_secret: &plaintext_secret,
+                label: &label,
+                account: Some(&account),
                 last_modified_at: timestamp,
+                pepper: None,
             };
 
-            let output = encryption_input.encrypt_and_authenticate(password.as_bytes())?;
+            let output = encryption_input.encrypt_and_authenticate(&MasterPassword::new(&password)?)?;
             let decryption_input = DecryptionInput {
                 encrypted_secret: output.encrypted_secret.as_slice(),
                 kdf_salt: output.kdf_salt,
                 auth_nonce: output.auth_nonce,
                 label: encryption_input.label,
-                account: encryption_input.account,
+                account: encryption_input.account.map(AccountName::as_str),
                 last_modified_at: timestamp,
+                peppered: output.peppered,
+                kdf: output.kdf,
+                cipher: output.cipher,
+                pepper: None,
             };
 
             let wrong_password = b"this is NOT the right password!";
@@ -305,14 +925,18 @@ mod tests {
         for payload in [p0, p1, p2, p3] {
             let password_len: usize = rng.gen_range(8..64);
             let password = Standard.sample_string(&mut rng, password_len);
+            let plaintext_secret = PlaintextSecret::new(&payload);
+            let label = Label::new("the precise label does not matter")?;
+            let account = AccountName::new("my uninteresting account name")?;
             let encryption_input = EncryptionInput {
-                plaintext_secret: payload.as_slice(),
-                label: "the precise label does not matter",
-                account: Some("my uninteresting account name"),
+                plaintext_secret: &plaintext_secret,
+                label: &label,
+                account: Some(&account),
                 last_modified_at: timestamp,
+                pepper: None,
             };
 
-            let output = encryption_input.encrypt_and_authenticate(password.as_bytes())?;
+            let output = encryption_input.encrypt_and_authenticate(&MasterPassword::new(&password)?)?;
 
             // Case #1: the account is altered (None instead of Some)
             {
@@ -323,6 +947,10 @@ mod tests {
                     label: encryption_input.label,
                     account: None,
                     last_modified_at: timestamp,
+                    peppered: output.peppered,
+                    kdf: output.kdf,
+                    cipher: output.cipher,
+                    pepper: None,
                 };
 
                 let result = decryption_input.decrypt_and_verify(password.as_bytes());
@@ -343,9 +971,13 @@ mod tests {
                     encrypted_secret: output.encrypted_secret.as_slice(),
                     kdf_salt: output.kdf_salt,
                     auth_nonce: output.auth_nonce,
-                    label: &encryption_input.label[1..],
-                    account: encryption_input.account,
+                    label: &encryption_input.label.as_str()[1..],
+                    account: encryption_input.account.map(AccountName::as_str),
                     last_modified_at: timestamp,
+                    peppered: output.peppered,
+                    kdf: output.kdf,
+                    cipher: output.cipher,
+                    pepper: None,
                 };
 
                 let result = decryption_input.decrypt_and_verify(password.as_bytes());
@@ -367,8 +999,12 @@ mod tests {
                     kdf_salt: output.kdf_salt,
                     auth_nonce: output.auth_nonce,
                     label: encryption_input.label,
-                    account: encryption_input.account,
+                    account: encryption_input.account.map(AccountName::as_str),
                     last_modified_at: timestamp.checked_sub_days(Days::new(1)).unwrap(),
+                    peppered: output.peppered,
+                    kdf: output.kdf,
+                    cipher: output.cipher,
+                    pepper: None,
                 };
 
                 let result = decryption_input.decrypt_and_verify(password.as_bytes());
@@ -387,10 +1023,400 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn pepper_is_required_for_decryption() -> Result<()> {
+        let timestamp = Utc::now();
+        let pepper = Pepper::generate();
+        let other_pepper = Pepper::generate();
+        let password = MasterPassword::new("the same password every time")?;
+        let plaintext_secret = PlaintextSecret::new(b"peppered secret");
+        let label = Label::new("the precise label does not matter")?;
+        let encryption_input = EncryptionInput {
+            plaintext_secret: &plaintext_secret,
+            label: &label,
+            account: None,
+            last_modified_at: timestamp,
+            pepper: Some(&pepper),
+        };
+
+        let output = encryption_input.encrypt_and_authenticate(&password)?;
+        assert!(output.peppered);
+
+        let decrypt = |pepper| {
+            let decryption_input = DecryptionInput {
+                encrypted_secret: output.encrypted_secret.as_slice(),
+                kdf_salt: output.kdf_salt,
+                auth_nonce: output.auth_nonce,
+                label: encryption_input.label,
+                account: encryption_input.account.map(AccountName::as_str),
+                last_modified_at: timestamp,
+                peppered: output.peppered,
+                kdf: output.kdf,
+                cipher: output.cipher,
+                pepper,
+            };
+            decryption_input.decrypt_and_verify(password.as_bytes())
+        };
+
+        let decrypted_secret = decrypt(Some(&pepper))?;
+        assert_eq!(decrypted_secret.as_slice(), encryption_input.plaintext_secret.as_bytes());
+
+        let result = decrypt(Some(&other_pepper));
+        assert!(
+            matches!(result, Err(Error::XChaCha20Poly1305(chacha20poly1305::Error))),
+            "unexpected result: {:#?}",
+            result,
+        );
+
+        let result = decrypt(None);
+        assert!(matches!(result, Err(Error::PepperRequired)), "unexpected result: {:#?}", result);
+
+        Ok(())
+    }
+
+    #[test]
+    fn pepper_is_required_for_signing() -> Result<()> {
+        let password = b"the same password every time";
+        let pepper = Pepper::generate();
+        let plain = SigningKeyParams::generate(None);
+        let peppered = SigningKeyParams::generate(Some(&pepper));
+        assert_eq!(peppered.kdf, KdfParams::CURRENT);
+        assert!(peppered.peppered && !plain.peppered);
+
+        // the pepper is only mixed in if the parameters say so
+        let key = plain.derive_key(password, None)?.verifying_key();
+        assert_eq!(plain.derive_key(password, Some(&pepper))?.verifying_key(), key);
+
+        let same_salt = SigningKeyParams { salt: plain.salt, ..peppered };
+        assert_ne!(same_salt.derive_key(password, Some(&pepper))?.verifying_key(), key);
+
+        let result = peppered.derive_key(password, None);
+        assert!(matches!(result, Err(Error::PepperRequired)), "unexpected result: {:#?}", result);
+
+        Ok(())
+    }
+
+    #[test]
+    fn recorded_algorithms_are_used_and_authenticated() -> Result<()> {
+        let timestamp = Utc::now();
+        let password = MasterPassword::new("the same password every time")?;
+        let cheap_kdf = KdfParams::Argon2id { version: 0x13, m_cost: 1024, t_cost: 1, p_cost: 1 };
+        let plaintext_secret = PlaintextSecret::new(b"secret with non-default KDF parameters");
+        let label = Label::new("the precise label does not matter")?;
+        let encryption_input = EncryptionInput {
+            plaintext_secret: &plaintext_secret,
+            label: &label,
+            account: None,
+            last_modified_at: timestamp,
+            pepper: None,
+        };
+
+        let output = encryption_input.encrypt_with(&password, cheap_kdf, CipherId::CURRENT)?;
+        assert_eq!(output.kdf, cheap_kdf);
+
+        let decrypt = |kdf| {
+            let decryption_input = DecryptionInput {
+                encrypted_secret: output.encrypted_secret.as_slice(),
+                kdf_salt: output.kdf_salt,
+                auth_nonce: output.auth_nonce,
+                label: encryption_input.label,
+                account: encryption_input.account.map(AccountName::as_str),
+                last_modified_at: timestamp,
+                peppered: output.peppered,
+                kdf,
+                cipher: output.cipher,
+                pepper: None,
+            };
+            decryption_input.decrypt_and_verify(password.as_bytes())
+        };
+
+        let decrypted_secret = decrypt(cheap_kdf)?;
+        assert_eq!(decrypted_secret.as_slice(), encryption_input.plaintext_secret.as_bytes());
+
+        // items without recorded algorithms are assumed to use the legacy ones
+        let result = decrypt(KdfParams::LEGACY);
+        assert!(
+            matches!(result, Err(Error::XChaCha20Poly1305(chacha20poly1305::Error))),
+            "unexpected result: {:#?}",
+            result,
+        );
+
+        Ok(())
+    }
+
+    /// KDF parameters cheap enough for running hundreds of cases per property.
+    const FAST_KDF: KdfParams = KdfParams::Argon2id { version: 0x13, m_cost: 8, t_cost: 1, p_cost: 1 };
+
+    const PASSWORD: &str = "the same password every time";
+
+    fn password() -> MasterPassword {
+        MasterPassword::new(PASSWORD).expect("not empty")
+    }
+
+    /// Any timestamp representable by `chrono`, with the extremes being more likely.
+    fn any_timestamp() -> impl Strategy<Value = DateTime<Utc>> {
+        let secs = DateTime::<Utc>::MIN_UTC.timestamp()..=DateTime::<Utc>::MAX_UTC.timestamp();
+
+        prop_oneof![
+            Just(DateTime::<Utc>::MIN_UTC),
+            Just(DateTime::<Utc>::MAX_UTC),
+            Just(DateTime::<Utc>::UNIX_EPOCH),
+            (secs, 0..1_000_000_000_u32).prop_map(|(secs, nanos)| {
+                DateTime::from_timestamp(secs, nanos).unwrap_or(DateTime::<Utc>::MAX_UTC)
+            }),
+        ]
+    }
+
+    /// Decrypts `encrypted_secret` with the additional data and algorithms of the encryption.
+    fn decrypt(
+        input: &EncryptionInput<'_>,
+        output: &EncryptionOutput,
+        encrypted_secret: &[u8],
+    ) -> Result<SecretBytes> {
+        let decryption_input = DecryptionInput {
+            encrypted_secret,
+            kdf_salt: output.kdf_salt,
+            auth_nonce: output.auth_nonce,
+            label: input.label,
+            account: input.account.map(AccountName::as_str),
+            last_modified_at: input.last_modified_at,
+            peppered: output.peppered,
+            kdf: output.kdf,
+            cipher: output.cipher,
+            pepper: None,
+        };
+        decryption_input.decrypt_and_verify(PASSWORD.as_bytes())
+    }
+
+    proptest! {
+        #[test]
+        fn round_trip_preserves_any_item(
+            payload in vec(any::<u8>(), 0..4 * PADDING_BLOCK_SIZE),
+            label in "\\PC*",
+            account in option::of("\\PC*"),
+            last_modified_at in any_timestamp(),
+        ) {
+            // any label, even one that `Label::new()` would reject, round-trips
+            let plaintext_secret = PlaintextSecret::new(&payload);
+            let label = Label::verbatim(&label);
+            let account = account.as_deref().map(AccountName::verbatim);
+            let encryption_input = EncryptionInput {
+                plaintext_secret: &plaintext_secret,
+                label: &label,
+                account: account.as_ref(),
+                last_modified_at,
+                pepper: None,
+            };
+            let output = encryption_input.encrypt_with(&password(), FAST_KDF, CipherId::CURRENT)?;
+
+            // the length of the secret is only revealed up to the padding block size
+            let padded_len = (payload.len() / PADDING_BLOCK_SIZE + 1) * PADDING_BLOCK_SIZE;
+            prop_assert_eq!(output.encrypted_secret.len(), padded_len + TAG_LEN);
+
+            let decrypted_secret = decrypt(&encryption_input, &output, &output.encrypted_secret)?;
+            prop_assert_eq!(decrypted_secret.as_slice(), payload.as_slice());
+        }
+
+        #[test]
+        fn any_flipped_bit_fails_verification(
+            payload in vec(any::<u8>(), 0..2 * PADDING_BLOCK_SIZE),
+            bit in any::<Index>(),
+        ) {
+            let plaintext_secret = PlaintextSecret::new(&payload);
+            let label = Label::new("the precise label does not matter")?;
+            let encryption_input = EncryptionInput {
+                plaintext_secret: &plaintext_secret,
+                label: &label,
+                account: None,
+                last_modified_at: Utc::now(),
+                pepper: None,
+            };
+            let output = encryption_input.encrypt_with(&password(), FAST_KDF, CipherId::CURRENT)?;
+
+            let mut tampered = output.encrypted_secret.clone();
+            let bit = bit.index(tampered.len() * 8);
+            tampered[bit / 8] ^= 1 << (bit % 8);
+
+            let result = decrypt(&encryption_input, &output, &tampered);
+            prop_assert!(
+                matches!(result, Err(Error::XChaCha20Poly1305(chacha20poly1305::Error))),
+                "unexpected result: {:#?}",
+                result,
+            );
+        }
+
+        /// Authentic ciphertexts of plaintexts that may or may not be padded
+        /// correctly, i.e. terminated by 0x80 and any number of zeros.
+        #[test]
+        fn malformed_padding_is_rejected(
+            payload in vec(any::<u8>(), 0..2 * PADDING_BLOCK_SIZE),
+            marker in prop_oneof![Just(0x80_u8), any::<u8>()],
+            zeros in 0..PADDING_BLOCK_SIZE,
+        ) {
+            let mut plaintext = payload;
+            plaintext.push(marker);
+            plaintext.resize(plaintext.len() + zeros, 0x00);
+
+            let plaintext_secret = PlaintextSecret::new(&plaintext);
+            let label = Label::new("the precise label does not matter")?;
+            let encryption_input = EncryptionInput {
+                plaintext_secret: &plaintext_secret,
+                label: &label,
+                account: None,
+                last_modified_at: Utc::now(),
+                pepper: None,
+            };
+            let output = encryption_input.encrypt_without_padding(&password(), FAST_KDF, CipherId::CURRENT)?;
+            let result = decrypt(&encryption_input, &output, &output.encrypted_secret);
+
+            match Iso7816::raw_unpad(&plaintext) {
+                Ok(unpadded) => {
+                    let decrypted = result?;
+                    prop_assert_eq!(decrypted.as_slice(), unpadded);
+                }
+                Err(_) => prop_assert!(matches!(result, Err(Error::Unpad(_))), "unexpected result: {:#?}", result),
+            }
+        }
+    }
+
+    /// Decrypts a stream encrypted by `encrypt_stream()`, i.e. using the current settings.
+    fn decrypt_current_stream(password: &[u8], aad: &[u8], input: &[u8], output: &mut Vec<u8>) -> Result<()> {
+        let (kdf, cipher) = (KdfParams::CURRENT, CipherId::CURRENT);
+        decrypt_stream(password, aad, kdf, cipher, STREAM_CHUNK_LEN, input, output)
+    }
+
+    #[test]
+    fn stream_round_trip_succeeds() -> Result<()> {
+        let mut rng = rand::thread_rng();
+        let password = b"correct horse battery staple";
+        let aad = b"associated data";
+
+        for len in [
+            0,
+            1,
+            STREAM_CHUNK_LEN - 1,
+            STREAM_CHUNK_LEN,
+            STREAM_CHUNK_LEN + 1,
+            2 * STREAM_CHUNK_LEN + 1234,
+        ] {
+            let mut plaintext = vec![0_u8; len];
+            rng.fill_bytes(&mut plaintext);
+
+            let mut ciphertext = Vec::new();
+            encrypt_stream(password, aad, plaintext.as_slice(), &mut ciphertext)?;
+
+            // an empty plaintext still has a (last) chunk
+            let chunk_count = len.div_ceil(STREAM_CHUNK_LEN).max(1);
+            assert_eq!(ciphertext.len(), 16 + 19 + len + chunk_count * TAG_LEN);
+
+            let mut decrypted = Vec::new();
+            decrypt_current_stream(password, aad, ciphertext.as_slice(), &mut decrypted)?;
+
+            assert_eq!(decrypted, plaintext);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn tampered_stream_fails_decryption() -> Result<()> {
+        let mut rng = rand::thread_rng();
+        let password = b"correct horse battery staple";
+        let aad = b"associated data";
+
+        let mut plaintext = vec![0_u8; 2 * STREAM_CHUNK_LEN + 1234];
+        rng.fill_bytes(&mut plaintext);
+
+        let mut ciphertext = Vec::new();
+        encrypt_stream(password, aad, plaintext.as_slice(), &mut ciphertext)?;
+
+        // Case #1: a single bit is flipped
+        {
+            let mut tampered = ciphertext.clone();
+            let index = rng.gen_range(16 + 19..tampered.len());
+            tampered[index] ^= 0x01;
+
+            let result = decrypt_current_stream(password, aad, tampered.as_slice(), &mut Vec::new());
+            assert!(
+                matches!(result, Err(Error::XChaCha20Poly1305(chacha20poly1305::Error))),
+                "unexpected result: {:#?}",
+                result,
+            );
+        }
+
+        // Case #2: the last chunk is dropped
+        {
+            let truncated = &ciphertext[..16 + 19 + 2 * (STREAM_CHUNK_LEN + TAG_LEN)];
+
+            let result = decrypt_current_stream(password, aad, truncated, &mut Vec::new());
+            assert!(
+                matches!(result, Err(Error::XChaCha20Poly1305(chacha20poly1305::Error))),
+                "unexpected result: {:#?}",
+                result,
+            );
+        }
+
+        // Case #3: the associated data is altered
+        {
+            let result = decrypt_current_stream(password, b"other", ciphertext.as_slice(), &mut Vec::new());
+            assert!(
+                matches!(result, Err(Error::XChaCha20Poly1305(chacha20poly1305::Error))),
+                "unexpected result: {:#?}",
+                result,
+            );
+        }
+
+        // Case #4: the password is wrong
+        {
+            let wrong_password = b"this is NOT the right password!";
+            let result = decrypt_current_stream(wrong_password, aad, ciphertext.as_slice(), &mut Vec::new());
+            assert!(
+                matches!(result, Err(Error::XChaCha20Poly1305(chacha20poly1305::Error))),
+                "unexpected result: {:#?}",
+                result,
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn stream_is_decrypted_with_its_own_settings() -> Result<()> {
+        let password = b"correct horse battery staple";
+        let aad = b"associated data";
+        let plaintext = vec![0x5a_u8; 2500];
+        let cipher = CipherId::CURRENT;
+
+        let mut ciphertext = Vec::new();
+        encrypt_stream_with(password, aad, FAST_KDF, cipher, 1000, plaintext.as_slice(), &mut ciphertext)?;
+        assert_eq!(ciphertext.len(), 16 + 19 + plaintext.len() + 3 * TAG_LEN);
+
+        let mut decrypted = Vec::new();
+        decrypt_stream(password, aad, FAST_KDF, cipher, 1000, ciphertext.as_slice(), &mut decrypted)?;
+        assert_eq!(decrypted, plaintext);
+
+        // the current settings are different
+        let result = decrypt_current_stream(password, aad, ciphertext.as_slice(), &mut Vec::new());
+        assert!(matches!(result, Err(Error::XChaCha20Poly1305(_))), "unexpected result: {result:#?}");
+
+        for chunk_len in [0, 1 << 30] {
+            let input = ciphertext.as_slice();
+            let result = decrypt_stream(password, aad, FAST_KDF, cipher, chunk_len, input, &mut Vec::new());
+            assert!(matches!(result, Err(Error::UnsupportedEncryption)), "unexpected result: {result:#?}");
+        }
+
+        // this would take 4 TiB of memory and forever, if it wasn't rejected
+        let kdf = KdfParams::Argon2id { version: 0x13, m_cost: u32::MAX, t_cost: u32::MAX, p_cost: 1 };
+        let result = decrypt_stream(password, aad, kdf, cipher, 1000, ciphertext.as_slice(), &mut Vec::new());
+        assert!(matches!(result, Err(Error::UnsupportedEncryption)), "unexpected result: {result:#?}");
+
+        Ok(())
+    }
+
     #[test]
     fn generated_password_is_strong() {
         for _ in 0..1024 {
-            let password = super::generate_password();
+            let password = super::generate_password_from(PASSWORD_CHARSET, PASSWORD_LEN);
 
             assert_eq!(password.len(), PASSWORD_LEN);
 
@@ -1,17 +1,56 @@
 //! Describes and implements the password database.
 
+use std::io::ErrorKind;
 use std::path::Path;
-use chrono::{DateTime, Utc};
+use std::marker::PhantomData;
+use std::collections::HashMap;
+use chrono::{DateTime, Utc, NaiveDateTime, TimeDelta};
+use serde::{Serialize, de::DeserializeOwned};
 use nanosql::{
-    Connection, ConnectionExt, Null, Value,
+    Connection, ConnectionExt, TransactionExt, Null, Value, Single,
     Table, Param, ResultRecord, InsertInput, AsSqlTy, FromSql, ToSql,
 };
-use crate::crypto::{RECOMMENDED_SALT_LEN, NONCE_LEN};
+use nanosql::rusqlite::{ErrorCode, Error as SqliteError};
+use crate::config::DatabaseOptions;
+use crate::recovery::RecoveryCodes;
+use crate::vault::Vault;
+use crate::oplog::{OpKind, Operation};
+use crate::format::ExportedItem;
+use crate::conflict::{self, Conflict, Resolution};
+use crate::crypto::{
+    DecryptionInput, EncryptionInput, EncryptionOutput, Pepper, KdfParams, CipherId,
+    SigningKeyParams, RECOMMENDED_SALT_LEN, NONCE_LEN,
+};
+use crate::fields::{self, Label, AccountName, PlaintextSecret, MasterPassword};
 use crate::error::{Error, Result};
 
 
 /// The current version of the database schema.
-const SCHEMA_VERSION: i64 = 1;
+///
+/// Version history:
+///
+/// * Version 1: initial version.
+/// * Version 2: added the `peppered` column to the `item` table.
+/// * Version 3: added the `kdf` and `cipher` columns to the `item` table.
+/// * Version 4: added the `reprompt` column to the `item` table.
+/// * Version 5: added the `item_identity` and `operation` tables (see `oplog`).
+const SCHEMA_VERSION: i64 = 5;
+
+/// The tag attached to quick notes, i.e. items created by `steelsafe note`
+/// or by `[M]emo` in the TUI, so that they can be found with `tag:note`.
+pub const NOTE_TAG: &str = "note";
+
+/// The tag attached to items imported from an inventory, whose secret
+/// is empty until it's filled in by `steelsafe fill`.
+pub const PLACEHOLDER_TAG: &str = "placeholder";
+
+/// The tag of Wi-Fi passwords, whose QR code is a `WIFI:` URI with the
+/// account (or the label) as the network name.
+pub const WIFI_TAG: &str = "wifi";
+
+/// The tag of bare Base32 TOTP seeds, whose QR code is an `otpauth://` URI
+/// with the label as the issuer.
+pub const TOTP_TAG: &str = "totp";
 
 /// Handle for the secrets database.
 #[derive(Debug)]
@@ -21,16 +60,30 @@ pub struct Database {
 }
 
 impl Database {
-    /// Opens the database at the specified path.
-    pub fn open<P>(path: P) -> Result<Self>
+    /// Opens the database at the specified path, and applies the connection settings.
+    pub fn open<P>(path: P, options: &DatabaseOptions) -> Result<Self>
     where
         P: AsRef<Path>
     {
         let mut connection = Connection::connect(path)?;
+        Self::configure(&connection, options)?;
         connection.create_table::<Item>()?;
         connection.create_table::<Metadata>()?;
+        connection.create_table::<AccessLogEntry>()?;
+        connection.create_table::<ItemTag>()?;
+        connection.create_table::<ItemExpiry>()?;
+        connection.create_table::<ItemRecoveryCodes>()?;
+        connection.create_table::<ItemUrl>()?;
+        connection.create_table::<ItemRotation>()?;
+        connection.create_table::<Recipient>()?;
+        connection.create_table::<ItemEnvelope>()?;
+        connection.create_table::<QuarantinedItem>()?;
+        connection.create_table::<ItemIdentity>()?;
+        connection.create_table::<OperationEntry>()?;
 
-        let schema_version = Self::schema_version(&connection)?;
+        let mut schema_version = Self::schema_version(&connection)?;
+        // the migration to version 5 logs the existing items as added by this replica
+        Self::ensure_replica_id(&connection)?;
 
         if SCHEMA_VERSION < schema_version {
             return Err(Error::SchemaVersionMismatch {
@@ -39,9 +92,90 @@ impl Database {
             });
         }
 
+        if schema_version < SCHEMA_VERSION {
+            Self::migrate(&mut connection, schema_version)?;
+            schema_version = SCHEMA_VERSION;
+        }
+
         Ok(Database { connection, schema_version })
     }
 
+    /// Upgrades the schema of a database created by an older version of steelsafe
+    /// to the current version, one version at a time. All steps are performed in a
+    /// single transaction, so the database is never left partially migrated.
+    fn migrate(connection: &mut Connection, from_version: i64) -> Result<()> {
+        let txn = connection.transaction().map_err(nanosql::Error::from)?;
+
+        for version in from_version..SCHEMA_VERSION {
+            let sql = match version {
+                1 => r#"
+                ALTER TABLE "item"
+                ADD COLUMN "peppered" INTEGER NOT NULL DEFAULT FALSE CHECK ("peppered" IN (0, 1));
+                "#,
+                // the defaults are `KdfParams::LEGACY` and `CipherId::LEGACY`
+                2 => r#"
+                ALTER TABLE "item"
+                ADD COLUMN "kdf" TEXT NOT NULL
+                DEFAULT '{"algorithm":"argon2id","version":19,"m_cost":19456,"t_cost":2,"p_cost":1}';
+                ALTER TABLE "item"
+                ADD COLUMN "cipher" TEXT NOT NULL DEFAULT 'xchacha20poly1305';
+                "#,
+                3 => r#"
+                ALTER TABLE "item"
+                ADD COLUMN "reprompt" INTEGER NOT NULL DEFAULT FALSE CHECK ("reprompt" IN (0, 1));
+                "#,
+                4 => r#"
+                INSERT INTO "item_identity" ("item_uid", "item_id")
+                SELECT "item"."uid", lower(hex(randomblob(16))) FROM "item";
+                INSERT INTO "operation" ("item_id", "kind", "clock", "replica")
+                SELECT "item_identity"."item_id", 'add', 1, (
+                    SELECT "metadata"."value" FROM "metadata" WHERE "metadata"."key" = 'replica_id'
+                )
+                FROM "item_identity";
+                "#,
+                _ => return Err(Error::SchemaVersionMismatch {
+                    expected: SCHEMA_VERSION,
+                    actual: from_version,
+                }),
+            };
+            txn.execute_batch(sql).map_err(nanosql::Error::from)?;
+        }
+
+        txn.execute(
+            r#"UPDATE "metadata" SET "value" = ?1 WHERE "key" = 'schema_version';"#,
+            [SCHEMA_VERSION],
+        ).map_err(nanosql::Error::from)?;
+        txn.commit().map_err(nanosql::Error::from)?;
+
+        Ok(())
+    }
+
+    /// Sets the journal mode, durability, and locking behavior of the connection.
+    ///
+    /// WAL mode allows readers to proceed concurrently with a writer, and with WAL,
+    /// `synchronous = NORMAL` is still safe against corruption, while it avoids an
+    /// `fsync()` upon every single commit. (`nanosql` currently applies the former
+    /// two by default, but we don't want to rely on that, because it's not part of
+    /// its semver guarantees.) The busy timeout prevents spurious `SQLITE_BUSY`
+    /// errors when another process holds a lock on the database for a short time.
+    /// `secure_delete` overwrites deleted content with zeros, so that old secrets
+    /// (e.g. the ciphertext of rotated passwords) and plain text metadata can't be
+    /// recovered from the free pages of the file.
+    fn configure(connection: &Connection, options: &DatabaseOptions) -> nanosql::Result<()> {
+        connection.pragma_update(None, "journal_mode", "WAL")?;
+        connection.pragma_update(None, "synchronous", "NORMAL")?;
+        connection.pragma_update(None, "secure_delete", true)?;
+        connection.busy_timeout(options.busy_timeout())?;
+        Ok(())
+    }
+
+    /// Makes every subsequent attempt to change the database fail. Used for
+    /// remote vaults that were not opened with `--writable`.
+    pub fn set_read_only(&self) -> Result<()> {
+        self.connection.pragma_update(None, "query_only", true).map_err(nanosql::Error::from)?;
+        Ok(())
+    }
+
     /// Retrieves the schema version of the database.
     /// If the schema version was not yet set (because the database was just created),
     /// then the schema version of the currently-running steelsafe process will be
@@ -64,6 +198,36 @@ impl Database {
         }
     }
 
+    /// Generates the ID of this replica of the vault (see `oplog`), unless it already has one.
+    fn ensure_replica_id(connection: &Connection) -> nanosql::Result<()> {
+        let Single(replica_id) = connection.compile_invoke(RandomId, ())?;
+        let metadata = Metadata {
+            key: MetadataKey::ReplicaId,
+            value: Value::Text(replica_id),
+        };
+        connection.insert_or_ignore_one(metadata)?;
+        Ok(())
+    }
+
+    /// The ID of this replica of the vault, which stamps the operations made here.
+    pub fn replica_id(&self) -> Result<String> {
+        Self::metadata_by_key(&self.connection, MetadataKey::ReplicaId).map_err(Into::into)
+    }
+
+    /// How the key signing audit reports (see `report`) is derived from the
+    /// encryption password. `new` is stored and returned if there's none yet.
+    pub fn signing_key_params(&self, new: SigningKeyParams) -> Result<SigningKeyParams> {
+        let metadata = Metadata {
+            key: MetadataKey::SigningKeyParams,
+            value: Value::Text(serde_json::to_string(&new)?),
+        };
+        self.connection.insert_or_ignore_one(metadata)?;
+
+        let json: String = Self::metadata_by_key(&self.connection, MetadataKey::SigningKeyParams)?;
+
+        serde_json::from_str(&json).map_err(Into::into)
+    }
+
     fn metadata_by_key<T: FromSql>(connection: &Connection, key: MetadataKey) -> nanosql::Result<T> {
         let Metadata { ref value, .. } = connection.select_by_key(key)?;
         let value = T::column_result(value.into())?;
@@ -79,17 +243,71 @@ impl Database {
     ///
     /// If the `search_term` is `None`, then all items are returned.
     ///
-    /// If the `search_term` is `Some(_)`, then only items matching the search term will
-    /// be returned. The search term is interpreted as an SQL `LIKE` pattern. The pattern
-    /// will be matched against the label and the account name, and entries matching either
-    /// will be returned.
-    pub fn list_items_for_display(&self, search_term: Option<&str>) -> Result<Vec<DisplayItem>> {
-        self.connection.compile_invoke(ListItemsForDisplay, search_term).map_err(Into::into)
+    /// If the `search_text` is `Some(_)`, then only items whose label or account name
+    /// contains it (ignoring ASCII case, like SQL `LIKE`) will be returned. The `LIKE`
+    /// wildcards `%` and `_` are escaped, so every character is matched literally.
+    pub fn list_items_for_display(&self, search_text: Option<&str>) -> Result<Vec<DisplayItem>> {
+        let pattern = search_text.map(|text| {
+            let escaped = text.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+            format!("%{escaped}%")
+        });
+
+        self.connection.compile_invoke(ListItemsForDisplay, pattern.as_deref()).map_err(Into::into)
+    }
+
+    /// Returns the items matching one of the predefined filters, as of `now`.
+    pub fn items_by_filter(&self, filter: SmartFilter, now: DateTime<Utc>) -> Result<Vec<DisplayItem>> {
+        let items = match filter {
+            SmartFilter::NoAccount => self.connection.compile_invoke(ItemsWithoutAccount, ())?,
+            SmartFilter::NoTags => self.connection.compile_invoke(ItemsWithoutTags, ())?,
+            SmartFilter::NotAccessedForAYear => {
+                self.connection.compile_invoke(ItemsNotAccessedSince, now - TimeDelta::days(365))?
+            }
+            SmartFilter::ChangedThisWeek => {
+                self.connection.compile_invoke(ItemsModifiedSince, now - TimeDelta::days(7))?
+            }
+            SmartFilter::RotationOverdue => {
+                let rotations = self.rotations()?;
+                let mut items = self.list_items_for_display(None)?;
+
+                items.retain(|item| {
+                    rotations.get(&item.uid).is_some_and(|rotation| rotation.is_overdue(now))
+                });
+                items
+            }
+        };
+
+        Ok(items)
     }
 
     /// Creates a new entry in the database using an already-encrypted secret.
+    /// Returns `Error::DuplicateLabel` if an item with the same label exists.
     pub fn add_item(&self, input: AddItemInput<'_>) -> Result<Item> {
-        self.connection.insert_one(input).map_err(Into::into)
+        let txn = self.connection.unchecked_transaction().map_err(nanosql::Error::from)?;
+        let item = txn.insert_one(input).map_err(map_duplicate_label)?;
+
+        log_operation(&txn, item.uid, OpKind::Add)?;
+        txn.commit().map_err(nanosql::Error::from)?;
+
+        Ok(item)
+    }
+
+    /// Returns `true` if an item with exactly this label already exists.
+    pub fn label_exists(&self, label: &str) -> Result<bool> {
+        let Single(exists) = self.connection.compile_invoke(LabelExists, label)?;
+        Ok(exists)
+    }
+
+    /// Returns the label for a quick note created at the local date and time
+    /// `created_at`, that doesn't exist yet: "Note 2024-06-30 14:03:59",
+    /// "Note 2024-06-30 14:03:59 (2)", etc.
+    pub fn unused_note_label(&self, created_at: NaiveDateTime) -> Result<String> {
+        let base = format!("Note {}", created_at.format("%F %T"));
+
+        conflict::first_unused_label(
+            |n| if n == 1 { base.clone() } else { format!("{base} ({n})") },
+            |candidate| self.label_exists(candidate),
+        )
     }
 
     /// Retrieves a full item from the database based on its unique ID (primary key).
@@ -98,187 +316,3079 @@ impl Database {
     pub fn item_by_id(&self, id: u64) -> Result<Item> {
         self.connection.select_by_key(id).map_err(Into::into)
     }
-}
 
-/// Describes a secret item.
-#[derive(Clone, PartialEq, Eq, Debug, Table, ResultRecord)]
-#[nanosql(insert_input_ty = AddItemInput<'p>)]
-pub struct Item {
-    /// Unique identifier of the item.
-    #[nanosql(pk)]
-    pub uid: u64,
-    /// Human-readable identifier of the item.
-    #[nanosql(unique)]
-    pub label: String,
-    /// Username, email address, etc. for identification. `None` if not applicable.
-    pub account: Option<String>,
-    /// Last modification date of the item. If never modified, this is the creation date.
-    pub last_modified_at: DateTime<Utc>,
-    /// The encrypted and authenticated password data.
-    /// Also contains a copy of the other fields for the purpose of tamper protection.
-    pub encrypted_secret: Vec<u8>,
-    /// The salt for the key derivation function.
-    ///
-    /// This is `UNIQUE`, acting as an additional line of defense against
-    /// salt re-use, which would result in two users with the same password
-    /// and salt getting identical encryption keys.
-    #[nanosql(unique)]
-    pub kdf_salt: [u8; RECOMMENDED_SALT_LEN],
-    /// The nonce for the authentication function.
-    ///
-    /// This is `UNIQUE`, acting as an additional line of defense against
-    /// nonce re-use, which would allow breaking encryption/authentication.
-    #[nanosql(unique)]
-    pub auth_nonce: [u8; NONCE_LEN],
-}
+    /// Retrieves a full item based on its label, or `None` if there is no such item.
+    /// Labels stored before they were normalized are matched as typed, and
+    /// the rest by their normalized form, e.g. if `label` was pasted.
+    pub fn item_by_label(&self, label: &str) -> Result<Option<Item>> {
+        if let Some(item) = self.connection.compile_invoke(ItemByLabel, label)? {
+            return Ok(Some(item));
+        }
 
-/// Used for adding an encrypted secret item to the database.
-#[derive(Clone, Param, InsertInput)]
-#[nanosql(table = Item)]
-pub struct AddItemInput<'p> {
-    /// inserting a `NULL` into an `INTEGER PRIMARY KEY` auto-generates the PK
-    pub uid: Null,
-    pub label: &'p str,
-    pub account: Option<&'p str>,
-    pub last_modified_at: DateTime<Utc>,
-    pub encrypted_secret: &'p [u8],
-    pub kdf_salt: [u8; RECOMMENDED_SALT_LEN],
-    pub auth_nonce: [u8; NONCE_LEN],
-}
+        let normalized = fields::normalize(label);
 
-/// Human-readable subset (projection) of the `Item` table.
-/// Does not contain the secret or the encryption details (salt/nonce).
-#[derive(Clone, Debug, ResultRecord)]
-pub struct DisplayItem {
-    pub uid: u64,
-    pub label: String,
-    pub account: Option<String>,
-    pub last_modified_at: DateTime<Utc>,
-}
+        if normalized == label {
+            return Ok(None);
+        }
 
-/// Internal technical bookkeeping data (e.g., database version).
-#[derive(Clone, Debug, Table, Param, ResultRecord)]
-struct Metadata {
-    #[nanosql(pk)]
-    key: MetadataKey,
-    value: Value,
-}
+        self.connection.compile_invoke(ItemByLabel, normalized.as_str()).map_err(Into::into)
+    }
 
-/// The kinds of metadata stored in the database.
-#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, AsSqlTy, ToSql, FromSql, Param, ResultRecord)]
-#[nanosql(rename_all = "lower_snake_case")]
-enum MetadataKey {
-    /// The version of the database schema that determines its format.
-    SchemaVersion,
-}
+    /// Returns every item in full, including the encrypted secrets and their
+    /// encryption details, in the order of their unique IDs.
+    pub fn all_items(&self) -> Result<Vec<Item>> {
+        self.connection.compile_invoke(ListAllItems, ()).map_err(Into::into)
+    }
 
-nanosql::define_query! {
-    /// The optional parameter is a search/filter term. It works with SQLite `LIKE` syntax.
-    /// If not provided, no filtering will be performed, and all items will be returned.
-    ListItemsForDisplay<'p>: Option<&'p str> => Vec<DisplayItem> {
-        r#"
-        SELECT
-            "item"."uid" AS "uid",
-            "item"."label" AS "label",
-            "item"."account" AS "account",
-            "item"."last_modified_at" AS "last_modified_at"
-        FROM "item"
-        WHERE ?1 IS NULL OR "item"."label" LIKE ?1 OR "item"."account" LIKE ?1
-        ORDER BY "item"."uid";
-        "#
+    /// Adds several entries with already-encrypted secrets within a single
+    /// transaction, using a single prepared statement. If any of the items
+    /// can't be added (e.g., because of a duplicate label), then none of them
+    /// will be.
+    pub fn add_items<'p, I>(&mut self, inputs: I) -> Result<Vec<Item>>
+    where
+        I: IntoIterator<Item = AddItemInput<'p>>
+    {
+        let txn = self.connection.transaction().map_err(nanosql::Error::from)?;
+        let items: Vec<Item> = txn.insert_batch(inputs).map_err(map_duplicate_label)?;
+
+        for item in &items {
+            log_operation(&txn, item.uid, OpKind::Add)?;
+        }
+
+        txn.commit().map_err(nanosql::Error::from)?;
+
+        Ok(items)
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use chrono::Utc;
-    use nanosql::{Null, Error as NanosqlError};
-    use nanosql::rusqlite::{ErrorCode, Error as SqliteError};
-    use crate::crypto::{RECOMMENDED_SALT_LEN, NONCE_LEN};
-    use crate::error::{Error, Result};
-    use super::{Database, AddItemInput};
+    /// Adds the items of an export bundle, and applies the decisions on those
+    /// whose label belongs to a local item (see `conflict`), within a single
+    /// transaction, so that either every decision takes effect, or none of them
+    /// does. `items` must not conflict with local items. Returns the number of
+    /// items added or replaced.
+    pub fn import_items(&mut self, items: &[ExportedItem], conflicts: &[Conflict]) -> Result<usize> {
+        let txn = self.connection.transaction().map_err(nanosql::Error::from)?;
+        let mut count = 0;
 
+        for item in items {
+            let added: Item = txn.insert_one(item.as_add_item_input()).map_err(map_duplicate_label)?;
+            log_operation(&txn, added.uid, OpKind::Add)?;
+            count += 1;
+        }
 
-    #[test]
-    fn salt_uniqueness_is_enforced() -> Result<()> {
-        let db = Database::open(":memory:")?;
-        let salt: [u8; RECOMMENDED_SALT_LEN] = *b"Qk2Dw5aV65Ie8y7t";
-        let nonce_1: [u8; NONCE_LEN] = *b"lMVXTMT2z2giginHeWwIajy4";
-        let nonce_2: [u8; NONCE_LEN] = *b"rZNaJw3dBHmiqGhfUxLbjL6x";
+        for conflict in conflicts {
+            count += usize::from(resolve_conflict(&txn, conflict)?);
+        }
 
-        let input_1 = AddItemInput {
-            uid: Null,
-            label: "Some label",
-            account: Some("first@account.com"),
-            last_modified_at: Utc::now(),
-            encrypted_secret: b"EncrYpt3d S3cre7!123",
-            kdf_salt: salt,
-            auth_nonce: nonce_1,
-        };
-        let input_2 = AddItemInput {
-            uid: Null,
-            label: "a completely different title",
-            account: Some("second@otherserver.org"),
-            last_modified_at: Utc::now(),
-            encrypted_secret: b"$#an0ther-c1pherteXt-of_diff3rent^LENGTH%",
-            kdf_salt: salt,
-            auth_nonce: nonce_2,
-        };
+        txn.commit().map_err(nanosql::Error::from)?;
 
-        // We should be able to add the first item sucessfully.
-        let item = db.add_item(input_1)?;
-        assert_eq!(db.item_by_id(item.uid)?, item);
+        Ok(count)
+    }
 
-        // The second item has an identical salt, so insertion must fail
-        // due to the violation of the UNIQUE constraint.
-        let error = db.add_item(input_2).expect_err("item with duplicate salt added");
-        let Error::Db(NanosqlError::Sqlite(SqliteError::SqliteFailure(error, _))) = error else {
-            panic!("unexpected error: {}", error);
-        };
+    /// Applies the decisions on the conflicts between the items of another
+    /// replica and local ones within a single transaction, before the two
+    /// vaults are synchronized. Returns the number of items added or replaced.
+    pub fn resolve_conflicts(&mut self, conflicts: &[Conflict]) -> Result<usize> {
+        self.import_items(&[], conflicts)
+    }
 
-        assert_eq!(error.code, ErrorCode::ConstraintViolation);
+    /// Adds copies of items of another vault, with their secrets still encrypted,
+    /// along with their tags, expiry dates, rotation policies, URLs, recovery code
+    /// counts and `reprompt` flags, within a single transaction. If any of the items can't
+    /// be added (e.g., because of a duplicate label), then none of them will be.
+    /// The access log is not copied. Returns the copies, in the same order.
+    pub fn copy_items_from(&mut self, source: &Database, items: Vec<Item>) -> Result<Vec<Item>> {
+        let tags = source.tags_by_item()?;
+        let expiries = source.expiries()?;
+        let rotations = source.rotations()?;
+        let urls = source.urls()?;
+        let recovery_codes = source.recovery_codes()?;
+        let originals: Vec<(u64, bool)> = items.iter().map(|item| (item.uid, item.reprompt)).collect();
+        let exported: Vec<ExportedItem> = items.into_iter().map(ExportedItem::from).collect();
+
+        let txn = self.connection.transaction().map_err(nanosql::Error::from)?;
+
+        // a copy has the salt and the nonce of the original, too, and SQLite might
+        // report the violation of their uniqueness instead of that of the label
+        for item in &exported {
+            let Single(exists) = txn.compile_invoke(LabelExists, item.label.as_str())?;
+
+            if exists {
+                return Err(Error::DuplicateLabel);
+            }
+        }
+
+        let copies: Vec<Item> = txn
+            .insert_batch(exported.iter().map(ExportedItem::as_add_item_input))
+            .map_err(map_duplicate_label)?;
+
+        for (&(item_uid, reprompt), copy) in originals.iter().zip(&copies) {
+            log_operation(&txn, copy.uid, OpKind::Add)?;
+
+            for tag in tags.get(&item_uid).into_iter().flatten() {
+                txn.compile_invoke(TagItem, (copy.uid, tag.as_str()))?;
+            }
+            if let Some(&expires_at) = expiries.get(&item_uid) {
+                txn.insert_or_replace_one(ItemExpiry { item_uid: copy.uid, expires_at })?;
+            }
+            if let Some(rotation) = rotations.get(&item_uid) {
+                txn.insert_or_replace_one(ItemRotation { item_uid: copy.uid, ..*rotation })?;
+            }
+            if let Some(url) = urls.get(&item_uid) {
+                txn.insert_or_replace_one(ItemUrl { item_uid: copy.uid, url: url.clone() })?;
+            }
+            if let Some(codes) = recovery_codes.get(&item_uid) {
+                txn.insert_or_replace_one(ItemRecoveryCodes { item_uid: copy.uid, ..*codes })?;
+            }
+            if reprompt {
+                txn.compile_invoke(SetReprompt, (copy.uid, true))?;
+            }
+        }
+
+        txn.commit().map_err(nanosql::Error::from)?;
+
+        Ok(copies)
+    }
+
+    /// Deletes several items, along with everything referring to them, within
+    /// a single transaction. Returns the number of items.
+    pub fn delete_items<I>(&mut self, item_uids: I) -> Result<usize>
+    where
+        I: IntoIterator<Item = u64>
+    {
+        let txn = self.connection.transaction().map_err(nanosql::Error::from)?;
+        let mut count = 0;
+
+        for item_uid in item_uids {
+            log_operation(&txn, item_uid, OpKind::Delete)?;
+            delete_item_rows(&txn, item_uid)?;
+            count += 1;
+        }
+
+        txn.commit().map_err(nanosql::Error::from)?;
+
+        Ok(count)
+    }
+
+    /// Replaces the encrypted secrets of several items within a single transaction.
+    /// Labels, account names, and modification dates remain unchanged, so the new
+    /// secrets must have been authenticated using the existing ones.
+    pub fn update_encryption<'p, I>(&mut self, updates: I) -> Result<()>
+    where
+        I: IntoIterator<Item = UpdateEncryptionInput<'p>>
+    {
+        let txn = self.connection.transaction().map_err(nanosql::Error::from)?;
+
+        for update in updates {
+            let item_uid = update.uid;
+            txn.compile_invoke(UpdateEncryption, update)?;
+            log_operation(&txn, item_uid, OpKind::Update)?;
+        }
+
+        txn.commit().map_err(nanosql::Error::from)?;
 
         Ok(())
     }
 
-    #[test]
-    fn nonce_uniqueness_is_enforced() -> Result<()> {
-        let db = Database::open(":memory:")?;
-        let salt_1: [u8; RECOMMENDED_SALT_LEN] = *b"NdBIIex0BLnkThWH";
-        let salt_2: [u8; RECOMMENDED_SALT_LEN] = *b"xS8HYP2XAjgSnEOJ";
-        let nonce: [u8; NONCE_LEN] = *b"vb4yngPRSgEOrBLNGw8YcGpG";
+    /// Stores a new secret of the item, encrypted by `Item::encrypt_new_secret()`,
+    /// along with the modification date it was authenticated with. The envelope
+    /// of the item (see `org`) is removed, since it holds the old secret, and
+    /// the change counts as a rotation if the item has a rotation policy.
+    pub fn replace_secret(
+        &mut self,
+        item_uid: u64,
+        modified_at: DateTime<Utc>,
+        output: &EncryptionOutput,
+    ) -> Result<()> {
+        let txn = self.connection.transaction().map_err(nanosql::Error::from)?;
 
-        let input_1 = AddItemInput {
-            uid: Null,
-            label: "Not a useful label",
-            account: Some("foo@bar.qux"),
-            last_modified_at: Utc::now(),
-            encrypted_secret: b"more stuff, I've run out of ideas",
-            kdf_salt: salt_1,
-            auth_nonce: nonce,
-        };
-        let input_2 = AddItemInput {
-            uid: Null,
-            label: "...but neither is this!",
-            account: Some("lol@wut.gov"),
-            last_modified_at: Utc::now(),
-            encrypted_secret: b"some different blob",
-            kdf_salt: salt_2,
-            auth_nonce: nonce,
-        };
+        txn.compile_invoke(UpdateEncryption, UpdateEncryptionInput::new(item_uid, output))?;
+        txn.compile_invoke(SetLastModifiedAt, (item_uid, modified_at))?;
+        txn.compile_invoke(ClearEnvelope, item_uid)?;
+        txn.compile_invoke(MarkRotated, (item_uid, modified_at))?;
+        log_operation(&txn, item_uid, OpKind::Update)?;
+        txn.commit().map_err(nanosql::Error::from)?;
 
-        // We should be able to add the first item sucessfully.
-        let item = db.add_item(input_1)?;
-        assert_eq!(db.item_by_id(item.uid)?, item);
+        Ok(())
+    }
 
-        // The second item has an identical nonce, so insertion must fail
-        // due to the violation of the UNIQUE constraint.
-        let error = db.add_item(input_2).expect_err("item with duplicate nonce added");
-        let Error::Db(NanosqlError::Sqlite(SqliteError::SqliteFailure(error, _))) = error else {
-            panic!("unexpected error: {}", error);
-        };
+    /// Renames the item, and stores its secret as re-encrypted by `Item::encrypt_renamed()`,
+    /// along with the modification date it was authenticated with, atomically.
+    pub fn rename_item(
+        &mut self,
+        item_uid: u64,
+        label: &str,
+        modified_at: DateTime<Utc>,
+        output: &EncryptionOutput,
+    ) -> Result<()> {
+        let txn = self.connection.transaction().map_err(nanosql::Error::from)?;
 
-        assert_eq!(error.code, ErrorCode::ConstraintViolation);
+        txn.compile_invoke(SetLabel, (item_uid, label)).map_err(map_duplicate_label)?;
+        txn.compile_invoke(UpdateEncryption, UpdateEncryptionInput::new(item_uid, output))?;
+        txn.compile_invoke(SetLastModifiedAt, (item_uid, modified_at))?;
+        log_operation(&txn, item_uid, OpKind::Update)?;
+        txn.commit().map_err(nanosql::Error::from)?;
+
+        Ok(())
+    }
+
+    /// Moves the items into the `quarantined_item` table within a single transaction,
+    /// e.g. because they fail authentication. Their tags, expiry dates, recovery
+    /// code counts and access log entries are deleted. Returns the number of items.
+    pub fn quarantine_items<I>(&mut self, item_uids: I, now: DateTime<Utc>) -> Result<usize>
+    where
+        I: IntoIterator<Item = u64>
+    {
+        let txn = self.connection.transaction().map_err(nanosql::Error::from)?;
+        let mut count = 0;
+
+        for item_uid in item_uids {
+            txn.compile_invoke(QuarantineItem, (item_uid, now))?;
+            log_operation(&txn, item_uid, OpKind::Delete)?;
+            delete_item_rows(&txn, item_uid)?;
+            count += 1;
+        }
+
+        txn.commit().map_err(nanosql::Error::from)?;
+
+        Ok(count)
+    }
+
+    /// Returns every quarantined item, most recently quarantined first.
+    pub fn quarantined_items(&self) -> Result<Vec<QuarantinedItem>> {
+        self.connection.compile_invoke(ListQuarantinedItems, ()).map_err(Into::into)
+    }
+
+    /// Returns the whole operation log, in the order the operations were recorded.
+    pub fn operations(&self) -> Result<Vec<OperationEntry>> {
+        self.connection.compile_invoke(AllOperations, ()).map_err(Into::into)
+    }
+
+    /// Maps the replica-independent IDs of the items to their unique IDs.
+    pub fn item_ids(&self) -> Result<HashMap<String, u64>> {
+        let identities: Vec<ItemIdentity> = self.connection.compile_invoke(AllItemIdentities, ())?;
+
+        Ok(identities.into_iter().map(|identity| (identity.item_id, identity.item_uid)).collect())
+    }
+
+    /// Applies operations received from another replica, which must be newer than
+    /// the local operations of the same items, within a single transaction. They
+    /// are recorded with their original timestamps. Operations that would give an
+    /// item a label that another item has are retried once the rest have been
+    /// applied, since those may rename or delete the other item. If they still
+    /// conflict, they are skipped, and their labels are returned.
+    pub fn apply_operations(&mut self, operations: &[Operation]) -> Result<Vec<String>> {
+        let txn = self.connection.transaction().map_err(nanosql::Error::from)?;
+        let mut pending: Vec<&Operation> = operations.iter().collect();
+
+        loop {
+            let mut skipped = Vec::new();
+
+            for &operation in &pending {
+                if !apply_operation(&txn, operation)? {
+                    skipped.push(operation);
+                }
+            }
+
+            let is_stuck = skipped.len() == pending.len();
+            pending = skipped;
+
+            if pending.is_empty() || is_stuck {
+                break;
+            }
+        }
+
+        txn.commit().map_err(nanosql::Error::from)?;
+
+        Ok(pending
+            .iter()
+            .filter_map(|operation| operation.item.as_ref())
+            .map(|item| item.label.to_string())
+            .collect())
+    }
+
+    /// Records that the secret of the item was successfully decrypted just now.
+    pub fn log_access(&self, item_uid: u64) -> Result<()> {
+        self.connection.insert_one(AccessLogEntry {
+            item_uid,
+            accessed_at: Utc::now(),
+        })?;
+        Ok(())
+    }
+
+    /// Returns the recorded accesses of the item, most recent first.
+    pub fn access_history(&self, item_uid: u64) -> Result<Vec<AccessLogEntry>> {
+        self.connection.compile_invoke(AccessHistory, item_uid).map_err(Into::into)
+    }
+
+    /// Attaches a tag to the item. Does nothing if the item already has this tag.
+    pub fn tag_item(&self, item_uid: u64, tag: &str) -> Result<()> {
+        let tag = normalize_tag(tag)?;
+        self.connection.compile_invoke(TagItem, (item_uid, tag))?;
+        Ok(())
+    }
+
+    /// Removes a tag from the item. Does nothing if the item doesn't have this tag.
+    pub fn untag_item(&self, item_uid: u64, tag: &str) -> Result<()> {
+        self.connection.compile_invoke(UntagItem, (item_uid, tag.trim()))?;
+        Ok(())
+    }
+
+    /// Returns the tags of the item in alphabetical order.
+    pub fn item_tags(&self, item_uid: u64) -> Result<Vec<String>> {
+        self.connection.compile_invoke(ItemTags, item_uid).map_err(Into::into)
+    }
+
+    /// Returns the tags of every tagged item, keyed by the unique ID of the item.
+    pub fn tags_by_item(&self) -> Result<HashMap<u64, Vec<String>>> {
+        let item_tags: Vec<ItemTag> = self.connection.compile_invoke(AllItemTags, ())?;
+
+        Ok(item_tags.into_iter().fold(HashMap::new(), |mut acc, item_tag| {
+            acc.entry(item_tag.item_uid).or_insert_with(Vec::new).push(item_tag.tag);
+            acc
+        }))
+    }
+
+    /// Returns every tag in use, along with the number of items it's attached to,
+    /// in alphabetical order.
+    pub fn tag_counts(&self) -> Result<Vec<TagCount>> {
+        self.connection.compile_invoke(TagCounts, ()).map_err(Into::into)
+    }
+
+    /// Renames a tag on every item. If there is already a tag called `new_name`,
+    /// then the two tags are merged: items that had both will only have it once.
+    pub fn rename_tag(&mut self, old_name: &str, new_name: &str) -> Result<()> {
+        let new_name = normalize_tag(new_name)?;
+        let txn = self.connection.transaction().map_err(nanosql::Error::from)?;
+
+        txn.compile_invoke(RenameTag, (old_name, new_name))?;
+        txn.compile_invoke(RemoveDuplicateTags, ())?;
+        txn.commit().map_err(nanosql::Error::from)?;
+
+        Ok(())
+    }
+
+    /// Removes a tag from every item.
+    pub fn delete_tag(&self, name: &str) -> Result<()> {
+        self.connection.compile_invoke(DeleteTag, name)?;
+        Ok(())
+    }
+
+    /// Sets whether the password of the item must always be typed, bypassing
+    /// the agent. The flag isn't part of the encrypted data, so changing it
+    /// doesn't require the password.
+    pub fn set_reprompt(&self, item_uid: u64, reprompt: bool) -> Result<()> {
+        self.connection.compile_invoke(SetReprompt, (item_uid, reprompt))?;
+        Ok(())
+    }
+
+    /// Sets the expiry date of the item, or removes it if `expires_at` is `None`.
+    pub fn set_expiry(&self, item_uid: u64, expires_at: Option<DateTime<Utc>>) -> Result<()> {
+        match expires_at {
+            Some(expires_at) => {
+                self.connection.insert_or_replace_one(ItemExpiry { item_uid, expires_at })?;
+            }
+            None => {
+                self.connection.compile_invoke(ClearExpiry, item_uid)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Maps the unique IDs of items to their expiry dates.
+    /// Items that never expire are not included.
+    pub fn expiries(&self) -> Result<HashMap<u64, DateTime<Utc>>> {
+        let expiries: Vec<ItemExpiry> = self.connection.compile_invoke(AllExpiries, ())?;
+
+        Ok(expiries.into_iter().map(|expiry| (expiry.item_uid, expiry.expires_at)).collect())
+    }
+
+    /// Records how many of the recovery codes stored in the item are left,
+    /// or marks it as an ordinary item if `codes` is `None`.
+    pub fn set_recovery_codes(&self, item_uid: u64, codes: Option<&RecoveryCodes>) -> Result<()> {
+        match codes {
+            Some(codes) => {
+                self.connection.insert_or_replace_one(ItemRecoveryCodes {
+                    item_uid,
+                    remaining: codes.remaining(),
+                    total: codes.total(),
+                })?;
+            }
+            None => {
+                self.connection.compile_invoke(ClearRecoveryCodes, item_uid)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sets how many days the secret of the item should be changed after, or
+    /// removes its rotation policy if `interval_days` is `None`. The policy
+    /// counts from the modification date of the item when it's first set.
+    pub fn set_rotation(&self, item_uid: u64, interval_days: Option<u32>) -> Result<()> {
+        match interval_days {
+            Some(interval_days) => {
+                self.connection.compile_invoke(SetRotationInterval, (item_uid, interval_days))?;
+            }
+            None => {
+                self.connection.compile_invoke(ClearRotation, item_uid)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Maps the unique IDs of items to their rotation policies. Items without one are not included.
+    pub fn rotations(&self) -> Result<HashMap<u64, ItemRotation>> {
+        let records: Vec<ItemRotation> = self.connection.compile_invoke(AllRotations, ())?;
+
+        Ok(records.into_iter().map(|record| (record.item_uid, record)).collect())
+    }
+
+    /// Sets the URL of the item, or removes it if `url` is `None`.
+    pub fn set_url(&self, item_uid: u64, url: Option<&str>) -> Result<()> {
+        match url {
+            Some(url) => {
+                self.connection.insert_or_replace_one(ItemUrl { item_uid, url: url.into() })?;
+            }
+            None => {
+                self.connection.compile_invoke(ClearUrl, item_uid)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Maps the unique IDs of items to their URLs. Items without a URL are not included.
+    pub fn urls(&self) -> Result<HashMap<u64, String>> {
+        let urls: Vec<ItemUrl> = self.connection.compile_invoke(AllUrls, ())?;
+
+        Ok(urls.into_iter().map(|record| (record.item_uid, record.url)).collect())
+    }
+
+    /// Returns the members of the team (see `org`), in the order of their names.
+    pub fn recipients(&self) -> Result<Vec<Recipient>> {
+        self.connection.compile_invoke(AllRecipients, ()).map_err(Into::into)
+    }
+
+    /// Returns the envelope of the item (see `org`), if it's shared with the team.
+    pub fn envelope(&self, item_uid: u64) -> Result<Option<ItemEnvelope>> {
+        self.connection.select_by_key_opt(item_uid).map_err(Into::into)
+    }
+
+    /// Returns the envelopes of every item shared with the team.
+    pub fn envelopes(&self) -> Result<Vec<ItemEnvelope>> {
+        self.connection.compile_invoke(AllEnvelopes, ()).map_err(Into::into)
+    }
+
+    /// Sets the envelope of the item, or stops sharing it if `envelope` is `None`.
+    pub fn set_envelope(&self, item_uid: u64, envelope: Option<Vec<u8>>) -> Result<()> {
+        match envelope {
+            Some(envelope) => {
+                self.connection.insert_or_replace_one(ItemEnvelope { item_uid, envelope })?;
+            }
+            None => {
+                self.connection.compile_invoke(ClearEnvelope, item_uid)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Adds a member to the team, and replaces the envelopes of the shared items
+    /// with ones re-wrapped for the new set of members, atomically.
+    pub fn add_recipient(&mut self, recipient: Recipient, envelopes: Vec<ItemEnvelope>) -> Result<()> {
+        let txn = self.connection.transaction().map_err(nanosql::Error::from)?;
+
+        txn.insert_one(recipient)?;
+        txn.insert_or_replace_batch(envelopes)?;
+        txn.commit().map_err(nanosql::Error::from)?;
+
+        Ok(())
+    }
+
+    /// Removes a member from the team, and replaces the envelopes of the shared
+    /// items with ones re-wrapped for the remaining members, atomically.
+    pub fn remove_recipient(&mut self, name: &str, envelopes: Vec<ItemEnvelope>) -> Result<()> {
+        let txn = self.connection.transaction().map_err(nanosql::Error::from)?;
+
+        txn.compile_invoke(DeleteRecipient, name)?;
+        txn.insert_or_replace_batch(envelopes)?;
+        txn.commit().map_err(nanosql::Error::from)?;
+
+        Ok(())
+    }
+
+    /// Maps the unique IDs of the items holding recovery codes to the number of codes.
+    pub fn recovery_codes(&self) -> Result<HashMap<u64, ItemRecoveryCodes>> {
+        let records: Vec<ItemRecoveryCodes> = self.connection.compile_invoke(AllRecoveryCodes, ())?;
+
+        Ok(records.into_iter().map(|record| (record.item_uid, record)).collect())
+    }
+
+    /// Returns the items expiring at or before `until`, including those that
+    /// have already expired, in the order of their expiry dates.
+    pub fn expiring_items(&self, until: DateTime<Utc>) -> Result<Vec<ExpiringItem>> {
+        self.connection.compile_invoke(ExpiringItems, until).map_err(Into::into)
+    }
+
+    /// Returns the persisted number of consecutive failed decryption attempts.
+    pub fn failed_attempts(&self) -> Result<u32> {
+        let record: Option<Metadata> = self.connection.select_by_key_opt(MetadataKey::FailedAttempts)?;
+        let count = match record {
+            Some(Metadata { value: Value::Integer(count), .. }) => count,
+            _ => 0,
+        };
+
+        Ok(u32::try_from(count.max(0)).unwrap_or(u32::MAX))
+    }
+
+    /// Returns when the last remote backup was made, if ever.
+    pub fn last_remote_backup(&self) -> Result<Option<DateTime<Utc>>> {
+        let record: Option<Metadata> = self.connection.select_by_key_opt(MetadataKey::LastRemoteBackup)?;
+        let timestamp = match record {
+            Some(Metadata { value: Value::Integer(timestamp), .. }) => timestamp,
+            _ => return Ok(None),
+        };
+
+        Ok(DateTime::from_timestamp(timestamp, 0))
+    }
+
+    /// Records the time of a successful remote backup.
+    #[cfg(feature = "remote")]
+    pub fn set_last_remote_backup(&self, time: DateTime<Utc>) -> Result<()> {
+        self.connection.insert_or_replace_one(Metadata {
+            key: MetadataKey::LastRemoteBackup,
+            value: Value::Integer(time.timestamp()),
+        })?;
+        Ok(())
+    }
+
+    /// Persists the number of consecutive failed decryption attempts.
+    pub fn set_failed_attempts(&self, count: u32) -> Result<()> {
+        self.connection.insert_or_replace_one(Metadata {
+            key: MetadataKey::FailedAttempts,
+            value: Value::Integer(count.into()),
+        })?;
+        Ok(())
+    }
+
+    /// Returns the value of a per-vault setting, or `None` if it's not set.
+    pub fn setting<T>(&self, setting: VaultSetting<T>) -> Result<Option<T>>
+    where
+        T: DeserializeOwned,
+    {
+        let record: Option<Metadata> = self.connection.select_by_key_opt(setting.key)?;
+        let json = match record {
+            Some(Metadata { value: Value::Text(json), .. }) => json,
+            _ => return Ok(None),
+        };
+
+        serde_json::from_str(&json).map(Some).map_err(Into::into)
+    }
+
+    /// Stores a per-vault setting, or removes it if `value` is `None`,
+    /// so that the `.steelsaferc` (or the built-in default) applies again.
+    pub fn set_setting<T>(&self, setting: VaultSetting<T>, value: Option<&T>) -> Result<()>
+    where
+        T: Serialize,
+    {
+        match value {
+            Some(value) => {
+                self.connection.insert_or_replace_one(Metadata {
+                    key: setting.key,
+                    value: Value::Text(serde_json::to_string(value)?),
+                })?;
+            }
+            None => {
+                let _: Option<Metadata> = self.connection.delete_by_key(setting.key)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Rebuilds the database file without free pages (e.g. those left behind by
+    /// replaced secrets, before `secure_delete` was turned on),
+    /// and empties the write-ahead log, which may still contain old pages.
+    pub fn compact(&self) -> Result<Compaction> {
+        let size_before = self.file_size()?;
+
+        self.connection.execute_batch("VACUUM;").map_err(nanosql::Error::from)?;
+        self.connection.pragma_update(None, "wal_checkpoint", "TRUNCATE").map_err(nanosql::Error::from)?;
+
+        Ok(Compaction {
+            size_before,
+            size_after: self.file_size()?,
+        })
+    }
+
+    /// The size of the database in bytes, if it is stored in a file: the main
+    /// file, plus the write-ahead log, which holds the most recent changes.
+    fn file_size(&self) -> Result<Option<u64>> {
+        // In-memory and temporary databases have no (or an empty) path.
+        let Some(path) = self.connection.path().filter(|path| !path.is_empty()) else {
+            return Ok(None);
+        };
+        let wal_size = match std::fs::metadata(format!("{path}-wal")) {
+            Ok(metadata) => metadata.len(),
+            Err(error) if error.kind() == ErrorKind::NotFound => 0,
+            Err(error) => return Err(error.into()),
+        };
+
+        Ok(Some(std::fs::metadata(path)?.len() + wal_size))
+    }
+
+    /// Computes summary statistics about the contents and the storage of the
+    /// database. Expiry and rotation are checked as of `now`.
+    pub fn stats(&self, now: DateTime<Utc>) -> Result<VaultStats> {
+        let Single(item_stats) = self.connection.compile_invoke(ItemStats, ())?;
+        let expired_count = self.expiring_items(now)?.len();
+        let rotation_overdue_count = self.items_by_filter(SmartFilter::RotationOverdue, now)?.len();
+
+        Ok(VaultStats {
+            schema_version: self.schema_version,
+            file_size: self.file_size()?,
+            item_count: item_stats.item_count,
+            items_without_account: item_stats.items_without_account,
+            expired_count: expired_count as u64,
+            rotation_overdue_count: rotation_overdue_count as u64,
+            tags: self.tag_counts()?,
+            oldest_modified_at: item_stats.oldest_modified_at,
+            newest_modified_at: item_stats.newest_modified_at,
+        })
+    }
+}
+
+impl Vault for Database {
+    fn list_items_for_display(&self, search_text: Option<&str>) -> Result<Vec<DisplayItem>> {
+        Database::list_items_for_display(self, search_text)
+    }
+
+    fn items_by_filter(&self, filter: SmartFilter, now: DateTime<Utc>) -> Result<Vec<DisplayItem>> {
+        Database::items_by_filter(self, filter, now)
+    }
+
+    fn item_by_id(&self, id: u64) -> Result<Item> {
+        Database::item_by_id(self, id)
+    }
+
+    fn all_items(&self) -> Result<Vec<Item>> {
+        Database::all_items(self)
+    }
+
+    fn label_exists(&self, label: &str) -> Result<bool> {
+        Database::label_exists(self, label)
+    }
+
+    fn unused_note_label(&self, created_at: NaiveDateTime) -> Result<String> {
+        Database::unused_note_label(self, created_at)
+    }
+
+    fn add_item(&self, input: AddItemInput<'_>) -> Result<Item> {
+        Database::add_item(self, input)
+    }
+
+    fn update_encryption(&mut self, updates: Vec<UpdateEncryptionInput<'_>>) -> Result<()> {
+        Database::update_encryption(self, updates)
+    }
+
+    fn rename_item(
+        &mut self,
+        item_uid: u64,
+        label: &str,
+        modified_at: DateTime<Utc>,
+        output: &EncryptionOutput,
+    ) -> Result<()> {
+        Database::rename_item(self, item_uid, label, modified_at, output)
+    }
+
+    fn delete_item(&mut self, item_uid: u64) -> Result<()> {
+        let txn = self.connection.transaction().map_err(nanosql::Error::from)?;
+
+        log_operation(&txn, item_uid, OpKind::Delete)?;
+        delete_item_rows(&txn, item_uid)?;
+        txn.commit().map_err(nanosql::Error::from)?;
+
+        Ok(())
+    }
+
+    fn set_reprompt(&self, item_uid: u64, reprompt: bool) -> Result<()> {
+        Database::set_reprompt(self, item_uid, reprompt)
+    }
+
+    fn tags_by_item(&self) -> Result<HashMap<u64, Vec<String>>> {
+        Database::tags_by_item(self)
+    }
+
+    fn tag_counts(&self) -> Result<Vec<TagCount>> {
+        Database::tag_counts(self)
+    }
+
+    fn tag_item(&self, item_uid: u64, tag: &str) -> Result<()> {
+        Database::tag_item(self, item_uid, tag)
+    }
+
+    fn rename_tag(&mut self, old_name: &str, new_name: &str) -> Result<()> {
+        Database::rename_tag(self, old_name, new_name)
+    }
+
+    fn delete_tag(&self, name: &str) -> Result<()> {
+        Database::delete_tag(self, name)
+    }
+
+    fn expiries(&self) -> Result<HashMap<u64, DateTime<Utc>>> {
+        Database::expiries(self)
+    }
+
+    fn expiring_items(&self, until: DateTime<Utc>) -> Result<Vec<ExpiringItem>> {
+        Database::expiring_items(self, until)
+    }
+
+    fn recovery_codes(&self) -> Result<HashMap<u64, ItemRecoveryCodes>> {
+        Database::recovery_codes(self)
+    }
+
+    fn set_recovery_codes(&self, item_uid: u64, codes: Option<&RecoveryCodes>) -> Result<()> {
+        Database::set_recovery_codes(self, item_uid, codes)
+    }
+
+    fn urls(&self) -> Result<HashMap<u64, String>> {
+        Database::urls(self)
+    }
+
+    fn log_access(&self, item_uid: u64) -> Result<()> {
+        Database::log_access(self, item_uid)
+    }
+
+    fn access_history(&self, item_uid: u64) -> Result<Vec<AccessLogEntry>> {
+        Database::access_history(self, item_uid)
+    }
+
+    fn failed_attempts(&self) -> Result<u32> {
+        Database::failed_attempts(self)
+    }
+
+    fn set_failed_attempts(&self, count: u32) -> Result<()> {
+        Database::set_failed_attempts(self, count)
+    }
+
+    fn operations(&self) -> Result<Vec<OperationEntry>> {
+        Database::operations(self)
+    }
+
+    fn item_ids(&self) -> Result<HashMap<String, u64>> {
+        Database::item_ids(self)
+    }
+
+    fn apply_operations(&mut self, operations: &[Operation]) -> Result<Vec<String>> {
+        Database::apply_operations(self, operations)
+    }
+
+    fn stats(&self, now: DateTime<Utc>) -> Result<VaultStats> {
+        Database::stats(self, now)
+    }
+
+    fn compact(&self) -> Result<Compaction> {
+        Database::compact(self)
+    }
+}
+
+/// Deletes the item and everything referring to it. Must be called within a
+/// transaction, so that the item isn't left behind half-deleted upon an error.
+fn delete_item_rows(connection: &Connection, item_uid: u64) -> Result<()> {
+    connection.compile_invoke(ClearTags, item_uid)?;
+    connection.compile_invoke(ClearExpiry, item_uid)?;
+    connection.compile_invoke(ClearRecoveryCodes, item_uid)?;
+    connection.compile_invoke(ClearUrl, item_uid)?;
+    connection.compile_invoke(ClearRotation, item_uid)?;
+    connection.compile_invoke(ClearEnvelope, item_uid)?;
+    connection.compile_invoke(ClearAccessLog, item_uid)?;
+    connection.compile_invoke(ClearItemIdentity, item_uid)?;
+    connection.compile_invoke(DeleteItem, item_uid)?;
+
+    Ok(())
+}
+
+/// Applies a single operation received from another replica, and records it.
+/// Returns `false` without changing anything if the operation would give the
+/// item a label that another item has.
+fn apply_operation(connection: &Connection, operation: &Operation) -> Result<bool> {
+    let item_uid = connection.compile_invoke(ItemUidById, operation.item_id.as_str())?;
+
+    if operation.kind == OpKind::Delete {
+        if let Some(item_uid) = item_uid {
+            delete_item_rows(connection, item_uid)?;
+        }
+    } else {
+        let item = operation.item.as_ref().ok_or(Error::InvalidBundle)?;
+        let owner: Option<Item> = connection.compile_invoke(ItemByLabel, item.label.as_str())?;
+
+        if owner.is_some_and(|owner| Some(owner.uid) != item_uid) {
+            return Ok(false);
+        }
+
+        if let Some(item_uid) = item_uid {
+            overwrite_item(connection, item_uid, item)?;
+        } else {
+            let added = connection.insert_one(item.as_add_item_input())?;
+            connection.insert_one(ItemIdentity {
+                item_uid: added.uid,
+                item_id: operation.item_id.clone(),
+            })?;
+        }
+    }
+
+    record_operation(connection, operation)?;
+
+    Ok(true)
+}
+
+/// Applies the decision on the conflict between a local and an incoming item.
+/// Returns `true` if an item was added or replaced.
+fn resolve_conflict(connection: &Connection, conflict: &Conflict) -> Result<bool> {
+    match conflict.operation.as_ref() {
+        None => resolve_import_conflict(connection, conflict),
+        Some(operation) => resolve_merge_conflict(connection, conflict, operation),
+    }
+}
+
+/// The incoming item is one of an export bundle.
+fn resolve_import_conflict(connection: &Connection, conflict: &Conflict) -> Result<bool> {
+    match conflict.resolution {
+        Resolution::KeepLocal => return Ok(false),
+        Resolution::TakeIncoming => {
+            // the tags, expiry date, etc. of the local item are kept
+            overwrite_item(connection, conflict.local_uid, &conflict.incoming)?;
+            connection.compile_invoke(ClearEnvelope, conflict.local_uid)?;
+            log_operation(connection, conflict.local_uid, OpKind::Update)?;
+        }
+        Resolution::KeepBoth => {
+            // the incoming item has been renamed by `Conflict::rename_incoming()`
+            let added: Item = connection
+                .insert_one(conflict.incoming.as_add_item_input())
+                .map_err(map_duplicate_label)?;
+            log_operation(connection, added.uid, OpKind::Add)?;
+        }
+    }
+
+    Ok(true)
+}
+
+/// The incoming item was added or renamed by `operation` of another replica.
+/// The operation is recorded, along with a newer local operation where the
+/// decision overrides it, so that the decision prevails in both vaults once
+/// they are synchronized.
+fn resolve_merge_conflict(
+    connection: &Connection,
+    conflict: &Conflict,
+    operation: &Operation,
+) -> Result<bool> {
+    let incoming = &conflict.incoming;
+    // the incoming item may be a local one that the other replica renamed
+    let existing = connection.compile_invoke(ItemUidById, operation.item_id.as_str())?;
+    record_operation(connection, operation)?;
+
+    match conflict.resolution {
+        Resolution::KeepLocal => {
+            // undo the rename, or delete the new item from the other vault
+            match existing {
+                Some(item_uid) => log_operation(connection, item_uid, OpKind::Update)?,
+                None => {
+                    let item_id = operation.item_id.as_str();
+                    connection.compile_invoke(LogOperationOnId, (item_id, OpKind::Delete))?;
+                }
+            }
+
+            return Ok(false);
+        }
+        Resolution::TakeIncoming => {
+            // the local item is deleted everywhere, but if the incoming item is new,
+            // the local item takes its identity, keeping its tags, expiry date, etc.
+            log_operation(connection, conflict.local_uid, OpKind::Delete)?;
+
+            let item_uid = match existing {
+                Some(item_uid) => {
+                    delete_item_rows(connection, conflict.local_uid)?;
+                    item_uid
+                }
+                None => {
+                    connection.insert_or_replace_one(ItemIdentity {
+                        item_uid: conflict.local_uid,
+                        item_id: operation.item_id.clone(),
+                    })?;
+                    conflict.local_uid
+                }
+            };
+
+            overwrite_item(connection, item_uid, incoming)?;
+            connection.compile_invoke(ClearEnvelope, item_uid)?;
+        }
+        Resolution::KeepBoth => {
+            let item_uid = match existing {
+                Some(item_uid) => {
+                    overwrite_item(connection, item_uid, incoming)?;
+                    connection.compile_invoke(ClearEnvelope, item_uid)?;
+                    item_uid
+                }
+                None => {
+                    let added: Item = connection
+                        .insert_one(incoming.as_add_item_input())
+                        .map_err(map_duplicate_label)?;
+                    connection.insert_one(ItemIdentity {
+                        item_uid: added.uid,
+                        item_id: operation.item_id.clone(),
+                    })?;
+                    added.uid
+                }
+            };
+
+            // the new label is sent back to the other replica
+            log_operation(connection, item_uid, OpKind::Update)?;
+        }
+    }
+
+    Ok(true)
+}
+
+/// Overwrites the replicated fields of the item with those of `item`.
+fn overwrite_item(connection: &Connection, item_uid: u64, item: &ExportedItem) -> Result<()> {
+    connection.compile_invoke(SetLabel, (item_uid, item.label.as_str())).map_err(map_duplicate_label)?;
+    connection.compile_invoke(SetAccount, (item_uid, item.account.as_deref()))?;
+    connection.compile_invoke(SetLastModifiedAt, (item_uid, item.last_modified_at))?;
+    connection.compile_invoke(UpdateEncryption, UpdateEncryptionInput {
+        uid: item_uid,
+        encrypted_secret: &item.encrypted_secret,
+        kdf_salt: item.kdf_salt,
+        auth_nonce: item.auth_nonce,
+        peppered: item.peppered,
+        kdf: item.kdf,
+        cipher: item.cipher,
+    })?;
+
+    Ok(())
+}
+
+/// Records an operation received from another replica, with its original timestamp.
+fn record_operation(connection: &Connection, operation: &Operation) -> nanosql::Result<()> {
+    connection.insert_one(AddOperation {
+        item_id: &operation.item_id,
+        kind: operation.kind,
+        clock: operation.stamp.clock,
+        replica: &operation.stamp.replica,
+    })?;
+
+    Ok(())
+}
+
+/// Appends an operation on the item, made by this replica, to the operation log,
+/// stamped with the next Lamport timestamp. Must be called within the transaction
+/// making the change, after adding the item, or before deleting it.
+fn log_operation(connection: &Connection, item_uid: u64, kind: OpKind) -> nanosql::Result<()> {
+    if kind == OpKind::Add {
+        connection.compile_invoke(AssignItemId, item_uid)?;
+    }
+
+    connection.compile_invoke(LogOperation, (item_uid, kind))
+}
+
+/// Turns the violation of the `UNIQUE` constraint on the label of an item into a
+/// dedicated, user-friendly error. Other errors (including the violation of other
+/// constraints, e.g. on the salt or the nonce) are passed through unchanged.
+fn map_duplicate_label(error: nanosql::Error) -> Error {
+    if let nanosql::Error::Sqlite(SqliteError::SqliteFailure(ref failure, Some(ref message))) = error {
+        // SQLite reports the violated constraint as "UNIQUE constraint failed: Table.column"
+        if failure.code == ErrorCode::ConstraintViolation && message.ends_with(".label") {
+            return Error::DuplicateLabel;
+        }
+    }
+
+    error.into()
+}
+
+/// Describes a secret item.
+#[derive(Clone, PartialEq, Eq, Debug, Table, ResultRecord)]
+#[nanosql(insert_input_ty = AddItemInput<'p>)]
+pub struct Item {
+    /// Unique identifier of the item.
+    #[nanosql(pk)]
+    pub uid: u64,
+    /// Human-readable identifier of the item.
+    #[nanosql(unique)]
+    pub label: String,
+    /// Username, email address, etc. for identification. `None` if not applicable.
+    pub account: Option<String>,
+    /// Last modification date of the item. If never modified, this is the creation date.
+    pub last_modified_at: DateTime<Utc>,
+    /// The encrypted and authenticated password data.
+    /// Also contains a copy of the other fields for the purpose of tamper protection.
+    pub encrypted_secret: Vec<u8>,
+    /// The salt for the key derivation function.
+    ///
+    /// This is `UNIQUE`, acting as an additional line of defense against
+    /// salt re-use, which would result in two users with the same password
+    /// and salt getting identical encryption keys.
+    #[nanosql(unique)]
+    pub kdf_salt: [u8; RECOMMENDED_SALT_LEN],
+    /// The nonce for the authentication function.
+    ///
+    /// This is `UNIQUE`, acting as an additional line of defense against
+    /// nonce re-use, which would allow breaking encryption/authentication.
+    #[nanosql(unique)]
+    pub auth_nonce: [u8; NONCE_LEN],
+    /// Whether the machine-local pepper was mixed into the key derivation.
+    #[nanosql(default = "FALSE")]
+    pub peppered: bool,
+    /// The key derivation function and its parameters, as JSON.
+    /// Items created before schema version 3 use the legacy settings.
+    #[nanosql(default = r#"'{"algorithm":"argon2id","version":19,"m_cost":19456,"t_cost":2,"p_cost":1}'"#)]
+    pub kdf: KdfParams,
+    /// The name of the cipher used for encryption and authentication.
+    #[nanosql(default = "'xchacha20poly1305'")]
+    pub cipher: CipherId,
+    /// Whether the password must be typed every time the secret is decrypted,
+    /// even if the agent is unlocked.
+    #[nanosql(default = "FALSE")]
+    pub reprompt: bool,
+}
+
+impl Item {
+    /// Returns `true` if the secret was encrypted using algorithms or parameters
+    /// other than the currently recommended ones, i.e., it should be re-encrypted.
+    pub fn needs_crypto_upgrade(&self) -> bool {
+        self.kdf != KdfParams::CURRENT || self.cipher != CipherId::CURRENT
+    }
+
+    /// Everything required for decrypting the secret, except for the password.
+    pub fn decryption_input<'a>(&'a self, pepper: Option<&'a Pepper>) -> DecryptionInput<'a> {
+        DecryptionInput {
+            encrypted_secret: &self.encrypted_secret,
+            kdf_salt: self.kdf_salt,
+            auth_nonce: self.auth_nonce,
+            label: self.label.as_str(),
+            account: self.account.as_deref(),
+            last_modified_at: self.last_modified_at,
+            peppered: self.peppered,
+            kdf: self.kdf,
+            cipher: self.cipher,
+            pepper,
+        }
+    }
+
+    /// Decrypts the secret, then encrypts it again using the currently recommended
+    /// algorithms and the same password, with fresh salt and nonce. The pepper is
+    /// only used if the item had one. The result is meant to be passed to
+    /// `Database::update_encryption()`.
+    pub fn reencrypt(&self, password: &MasterPassword, pepper: Option<&Pepper>) -> Result<EncryptionOutput> {
+        let secret = self.decryption_input(pepper).decrypt_and_verify(password.as_bytes())?;
+        self.encrypt_replacement(&PlaintextSecret::from(secret), password, pepper)
+    }
+
+    /// The label and the account name, exactly as they were authenticated.
+    fn authenticated_fields(&self) -> (Label, Option<AccountName>) {
+        (Label::verbatim(&self.label), self.account.as_deref().map(AccountName::verbatim))
+    }
+
+    /// Encrypts `secret` in the same way as `reencrypt()` does, so that it can
+    /// replace the secret of the item (e.g. after ticking off a recovery code),
+    /// while the rest of the item stays the same.
+    pub fn encrypt_replacement(
+        &self,
+        secret: &PlaintextSecret,
+        password: &MasterPassword,
+        pepper: Option<&Pepper>,
+    ) -> Result<EncryptionOutput> {
+        let (label, account) = self.authenticated_fields();
+        let input = EncryptionInput {
+            plaintext_secret: secret,
+            label: &label,
+            account: account.as_ref(),
+            last_modified_at: self.last_modified_at,
+            pepper: if self.peppered { pepper } else { None },
+        };
+
+        input.encrypt_and_authenticate(password)
+    }
+
+    /// Encrypts a new secret for the item, e.g. after rotating its password.
+    /// Unlike `encrypt_replacement()`, the modification date becomes `modified_at`,
+    /// so it must be stored along with the result by `Database::replace_secret()`.
+    pub fn encrypt_new_secret(
+        &self,
+        secret: &PlaintextSecret,
+        password: &MasterPassword,
+        pepper: Option<&Pepper>,
+        modified_at: DateTime<Utc>,
+    ) -> Result<EncryptionOutput> {
+        let (label, account) = self.authenticated_fields();
+        let input = EncryptionInput {
+            plaintext_secret: secret,
+            label: &label,
+            account: account.as_ref(),
+            last_modified_at: modified_at,
+            pepper: if self.peppered { pepper } else { None },
+        };
+
+        input.encrypt_and_authenticate(password)
+    }
+
+    /// Encrypts the secret for a new label. The label is authenticated along with
+    /// the secret, so renaming requires re-encrypting it. The result must be stored
+    /// by `Database::rename_item()`, along with the label and `modified_at`.
+    pub fn encrypt_renamed(
+        &self,
+        secret: &PlaintextSecret,
+        password: &MasterPassword,
+        pepper: Option<&Pepper>,
+        label: &Label,
+        modified_at: DateTime<Utc>,
+    ) -> Result<EncryptionOutput> {
+        let (_, account) = self.authenticated_fields();
+        let input = EncryptionInput {
+            plaintext_secret: secret,
+            label,
+            account: account.as_ref(),
+            last_modified_at: modified_at,
+            pepper: if self.peppered { pepper } else { None },
+        };
+
+        input.encrypt_and_authenticate(password)
+    }
+}
+
+/// Used for adding an encrypted secret item to the database.
+#[derive(Clone, Param, InsertInput)]
+#[nanosql(table = Item)]
+pub struct AddItemInput<'p> {
+    /// inserting a `NULL` into an `INTEGER PRIMARY KEY` auto-generates the PK
+    pub uid: Null,
+    pub label: &'p Label,
+    pub account: Option<&'p AccountName>,
+    pub last_modified_at: DateTime<Utc>,
+    pub encrypted_secret: &'p [u8],
+    pub kdf_salt: [u8; RECOMMENDED_SALT_LEN],
+    pub auth_nonce: [u8; NONCE_LEN],
+    pub peppered: bool,
+    pub kdf: KdfParams,
+    pub cipher: CipherId,
+    pub reprompt: bool,
+}
+
+impl<'p> AddItemInput<'p> {
+    /// Adds a new item with the result of encrypting its secret, which must
+    /// have been authenticated along with these fields.
+    pub fn new(
+        label: &'p Label,
+        account: Option<&'p AccountName>,
+        last_modified_at: DateTime<Utc>,
+        output: &'p EncryptionOutput,
+    ) -> Self {
+        AddItemInput {
+            uid: Null, // generate fresh unique ID
+            label,
+            account,
+            last_modified_at,
+            encrypted_secret: &output.encrypted_secret,
+            kdf_salt: output.kdf_salt,
+            auth_nonce: output.auth_nonce,
+            peppered: output.peppered,
+            kdf: output.kdf,
+            cipher: output.cipher,
+            reprompt: false,
+        }
+    }
+}
+
+/// Used for replacing the encrypted secret of an existing item, e.g. after
+/// re-encrypting it using the currently recommended algorithms.
+#[derive(Clone, Debug, Param)]
+pub struct UpdateEncryptionInput<'p> {
+    pub uid: u64,
+    pub encrypted_secret: &'p [u8],
+    pub kdf_salt: [u8; RECOMMENDED_SALT_LEN],
+    pub auth_nonce: [u8; NONCE_LEN],
+    pub peppered: bool,
+    pub kdf: KdfParams,
+    pub cipher: CipherId,
+}
+
+impl<'p> UpdateEncryptionInput<'p> {
+    /// Stores the result of re-encrypting the secret of the item `uid`.
+    pub fn new(uid: u64, output: &'p EncryptionOutput) -> Self {
+        UpdateEncryptionInput {
+            uid,
+            encrypted_secret: &output.encrypted_secret,
+            kdf_salt: output.kdf_salt,
+            auth_nonce: output.auth_nonce,
+            peppered: output.peppered,
+            kdf: output.kdf,
+            cipher: output.cipher,
+        }
+    }
+}
+
+/// Human-readable subset (projection) of the `Item` table.
+/// Does not contain the secret or the encryption details (salt/nonce).
+#[derive(Clone, Debug, ResultRecord)]
+pub struct DisplayItem {
+    pub uid: u64,
+    pub label: String,
+    pub account: Option<String>,
+    pub last_modified_at: DateTime<Utc>,
+    pub reprompt: bool,
+}
+
+/// A predefined filter of the table, for finding items that need tidying up.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SmartFilter {
+    /// No account name is set.
+    NoAccount,
+    /// The item has no tags.
+    NoTags,
+    /// The secret hasn't been decrypted for 365 days, according to the access
+    /// log. If the access log is disabled, this matches every item.
+    NotAccessedForAYear,
+    /// Created or last changed within the past 7 days. (The creation date
+    /// itself is not stored.)
+    ChangedThisWeek,
+    /// The secret is due to be changed according to the rotation policy.
+    RotationOverdue,
+}
+
+impl SmartFilter {
+    /// Every filter, in the order they are offered.
+    pub const ALL: [SmartFilter; 5] = [
+        SmartFilter::NoAccount,
+        SmartFilter::NoTags,
+        SmartFilter::NotAccessedForAYear,
+        SmartFilter::ChangedThisWeek,
+        SmartFilter::RotationOverdue,
+    ];
+
+    pub const fn title(self) -> &'static str {
+        match self {
+            SmartFilter::NoAccount => "No account",
+            SmartFilter::NoTags => "No tags",
+            SmartFilter::NotAccessedForAYear => "Not used for a year",
+            SmartFilter::ChangedThisWeek => "Added or changed this week",
+            SmartFilter::RotationOverdue => "Overdue for rotation",
+        }
+    }
+}
+
+/// A successful decryption of an item. Only recorded if the access log is enabled.
+#[derive(Clone, Debug, Table, Param, ResultRecord)]
+#[nanosql(rename = access_log)]
+#[nanosql(index(columns(item_uid, accessed_at = desc)))]
+pub struct AccessLogEntry {
+    /// The unique ID of the item that was accessed.
+    #[nanosql(foreign_key = Item::uid)]
+    pub item_uid: u64,
+    /// The date of the access.
+    pub accessed_at: DateTime<Utc>,
+}
+
+/// The attachment of a tag to an item. Tags have no table of their own: a tag
+/// exists as long as at least one item has it, so renaming, merging, or deleting
+/// a tag only ever needs to touch this table.
+#[derive(Clone, Debug, Table, Param, ResultRecord)]
+#[nanosql(rename = item_tag)]
+#[nanosql(index(columns(item_uid, tag)))]
+#[nanosql(index(columns(tag)))]
+// two indexes sharing a column are not a duplicated attribute
+#[allow(clippy::duplicated_attributes)]
+pub struct ItemTag {
+    /// Unique identifier of the attachment.
+    #[nanosql(pk)]
+    pub uid: u64,
+    /// The unique ID of the tagged item.
+    #[nanosql(foreign_key = Item::uid)]
+    pub item_uid: u64,
+    /// The name of the tag.
+    pub tag: String,
+}
+
+/// The date after which an item should no longer be used, e.g. because the
+/// password must be changed periodically. Like tags, it's not part of the
+/// authenticated data of the item, so it can be changed without the password.
+#[derive(Clone, Debug, Table, Param, ResultRecord)]
+#[nanosql(rename = item_expiry)]
+pub struct ItemExpiry {
+    /// The unique ID of the item. Each item has at most one expiry date.
+    #[nanosql(pk)]
+    #[nanosql(foreign_key = Item::uid)]
+    pub item_uid: u64,
+    /// The date of the expiry.
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Marks an item as holding recovery codes (see the `recovery` module), and
+/// counts them, so that the number of codes left is known without decrypting
+/// the item. Like the expiry date, it's not part of the authenticated data.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Table, Param, ResultRecord)]
+#[nanosql(rename = item_recovery_codes)]
+pub struct ItemRecoveryCodes {
+    /// The unique ID of the item.
+    #[nanosql(pk)]
+    #[nanosql(foreign_key = Item::uid)]
+    pub item_uid: u64,
+    /// The number of codes that haven't been used yet.
+    pub remaining: u32,
+    /// The number of codes, including the used ones.
+    pub total: u32,
+}
+
+/// The rotation policy of an item: its secret should be changed every so many
+/// days. Like the expiry date, it's not part of the authenticated data.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Table, Param, ResultRecord)]
+#[nanosql(rename = item_rotation)]
+pub struct ItemRotation {
+    /// The unique ID of the item.
+    #[nanosql(pk)]
+    #[nanosql(foreign_key = Item::uid)]
+    pub item_uid: u64,
+    /// The number of days after which the secret should be changed.
+    pub interval_days: u32,
+    /// When the secret was last changed, as far as the policy is concerned.
+    pub last_rotated_at: DateTime<Utc>,
+}
+
+impl ItemRotation {
+    /// When the secret should be changed next.
+    pub fn due_at(&self) -> DateTime<Utc> {
+        self.last_rotated_at + TimeDelta::days(self.interval_days.into())
+    }
+
+    /// Whether the secret should have been changed by `now`.
+    pub fn is_overdue(&self, now: DateTime<Utc>) -> bool {
+        self.due_at() <= now
+    }
+}
+
+/// The address of the website or service an item belongs to, e.g. as imported
+/// from a browser. Like the expiry date, it's not part of the authenticated data.
+#[derive(Clone, Debug, Table, Param, ResultRecord)]
+#[nanosql(rename = item_url)]
+pub struct ItemUrl {
+    /// The unique ID of the item. Each item has at most one URL.
+    #[nanosql(pk)]
+    #[nanosql(foreign_key = Item::uid)]
+    pub item_uid: u64,
+    /// The URL, as it was given.
+    pub url: String,
+}
+
+/// A member of the team sharing the vault (see `org`), under the name given
+/// when adding them, with the public key that envelopes are encrypted to.
+#[derive(Clone, PartialEq, Eq, Debug, Table, Param, ResultRecord)]
+#[nanosql(rename = recipient)]
+pub struct Recipient {
+    #[nanosql(pk)]
+    pub name: String,
+    /// The age X25519 public key, in its `age1...` form.
+    #[nanosql(unique)]
+    pub public_key: String,
+    pub added_at: DateTime<Utc>,
+}
+
+/// The secret of an item shared with the team, encrypted with age to the
+/// public key of every member (see `org`). Like the URL, it's not part of
+/// the authenticated data of the item.
+#[derive(Clone, Debug, Table, Param, ResultRecord)]
+#[nanosql(rename = item_envelope)]
+pub struct ItemEnvelope {
+    /// The unique ID of the item.
+    #[nanosql(pk)]
+    #[nanosql(foreign_key = Item::uid)]
+    pub item_uid: u64,
+    /// The binary age file.
+    pub envelope: Vec<u8>,
+}
+
+/// The ID of an item that is the same in every replica of the vault (see `oplog`),
+/// unlike its unique ID, which is only meaningful within one database.
+#[derive(Clone, Debug, Table, Param, ResultRecord)]
+#[nanosql(rename = item_identity)]
+pub struct ItemIdentity {
+    /// The unique ID of the item.
+    #[nanosql(pk)]
+    #[nanosql(foreign_key = Item::uid)]
+    pub item_uid: u64,
+    /// The random, replica-independent ID of the item.
+    #[nanosql(unique)]
+    pub item_id: String,
+}
+
+/// An entry of the append-only operation log (see `oplog`). It outlives the
+/// item it refers to, so that deletions can be replicated, too.
+#[derive(Clone, Debug, Table, ResultRecord)]
+#[nanosql(rename = operation, insert_input_ty = AddOperation<'p>)]
+#[nanosql(index(columns(item_id, clock)))]
+pub struct OperationEntry {
+    /// The replica-independent ID of the item.
+    pub item_id: String,
+    pub kind: OpKind,
+    /// The counter of the Lamport timestamp of the operation.
+    pub clock: u64,
+    /// The ID of the replica where the operation was made.
+    pub replica: String,
+}
+
+/// An item moved out of the `item` table by `Database::quarantine_items()`, e.g.
+/// because it failed authentication (its label, account, or date was changed
+/// outside of steelsafe, or its data was tampered with). It's kept, rather than
+/// deleted, so that nothing is lost if the secret can be recovered after all.
+#[derive(Clone, PartialEq, Eq, Debug, Table, Param, ResultRecord)]
+#[nanosql(rename = quarantined_item)]
+pub struct QuarantinedItem {
+    /// Unique identifier of the quarantined item.
+    #[nanosql(pk)]
+    pub uid: u64,
+    /// The unique ID the item had in the `item` table. It may have been reused since.
+    pub item_uid: u64,
+    pub label: String,
+    pub account: Option<String>,
+    pub last_modified_at: DateTime<Utc>,
+    pub encrypted_secret: Vec<u8>,
+    pub kdf_salt: [u8; RECOMMENDED_SALT_LEN],
+    pub auth_nonce: [u8; NONCE_LEN],
+    pub peppered: bool,
+    pub kdf: KdfParams,
+    pub cipher: CipherId,
+    /// When the item was moved into quarantine.
+    pub quarantined_at: DateTime<Utc>,
+}
+
+/// An item with an expiry date, as returned by `Database::expiring_items()`.
+#[derive(Clone, PartialEq, Eq, Debug, ResultRecord)]
+pub struct ExpiringItem {
+    pub uid: u64,
+    pub label: String,
+    pub account: Option<String>,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// A tag and the number of items that have it.
+#[derive(Clone, PartialEq, Eq, Debug, ResultRecord)]
+pub struct TagCount {
+    pub tag: String,
+    pub item_count: u64,
+}
+
+/// Used for recording an operation received from another replica, along with
+/// its original timestamp. Local operations are recorded by `LogOperation`.
+#[derive(Clone, Copy, Param, InsertInput)]
+#[nanosql(table = OperationEntry)]
+pub struct AddOperation<'p> {
+    item_id: &'p str,
+    kind: OpKind,
+    clock: u64,
+    replica: &'p str,
+}
+
+/// Summary statistics of the database, for the purpose of vault hygiene.
+#[derive(Clone, Debug)]
+pub struct VaultStats {
+    /// The version of the schema the database was created with.
+    pub schema_version: i64,
+    /// The size of the database file and its write-ahead log in bytes,
+    /// if it is stored in a file.
+    pub file_size: Option<u64>,
+    /// The total number of items.
+    pub item_count: u64,
+    /// The number of items with no account name.
+    pub items_without_account: u64,
+    /// The number of items whose expiry date has passed.
+    pub expired_count: u64,
+    /// The number of items whose secret is due to be changed according to
+    /// the rotation policy.
+    pub rotation_overdue_count: u64,
+    /// The number of items with each tag, in alphabetical order of the tags.
+    pub tags: Vec<TagCount>,
+    /// The last modification date of the least recently modified item.
+    pub oldest_modified_at: Option<DateTime<Utc>>,
+    /// The last modification date of the most recently modified item.
+    pub newest_modified_at: Option<DateTime<Utc>>,
+}
+
+/// The size of the database file and its write-ahead log before and after
+/// `Database::compact()`.
+#[derive(Clone, Copy, Debug)]
+pub struct Compaction {
+    pub size_before: Option<u64>,
+    pub size_after: Option<u64>,
+}
+
+/// The part of `VaultStats` that is computed by aggregating the `Item` table.
+#[derive(Clone, Debug, ResultRecord)]
+struct ItemStatsRecord {
+    item_count: u64,
+    items_without_account: u64,
+    oldest_modified_at: Option<DateTime<Utc>>,
+    newest_modified_at: Option<DateTime<Utc>>,
+}
+
+/// Internal technical bookkeeping data (e.g., database version).
+#[derive(Clone, Debug, Table, Param, ResultRecord)]
+struct Metadata {
+    #[nanosql(pk)]
+    key: MetadataKey,
+    value: Value,
+}
+
+/// The kinds of metadata stored in the database.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, AsSqlTy, ToSql, FromSql, Param, ResultRecord)]
+#[nanosql(rename_all = "lower_snake_case")]
+enum MetadataKey {
+    /// The version of the database schema that determines its format.
+    SchemaVersion,
+    /// The number of consecutive failed decryption attempts, if persisted.
+    FailedAttempts,
+    /// The time of the last successful upload to the remote backup target.
+    LastRemoteBackup,
+    /// Per-vault setting overriding `access_log` of the config.
+    AccessLog,
+    /// Per-vault setting overriding `persist_failed_attempts` of the config.
+    PersistFailedAttempts,
+    /// Per-vault setting overriding `expiry.warning_days` of the config.
+    ExpiryWarningDays,
+    /// The random ID of this replica of the vault, for stamping operations.
+    ReplicaId,
+    /// How the key signing audit reports is derived.
+    SigningKeyParams,
+}
+
+/// A setting stored in the metadata of the vault, so that it travels with
+/// the database file (e.g. when it's copied to another machine)
+/// rather than with the `.steelsaferc` of one particular machine.
+///
+/// The type parameter is the type of the value, which is stored as JSON.
+#[derive(Debug)]
+pub struct VaultSetting<T> {
+    key: MetadataKey,
+    name: &'static str,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> VaultSetting<T> {
+    const fn new(key: MetadataKey, name: &'static str) -> Self {
+        VaultSetting { key, name, marker: PhantomData }
+    }
+
+    /// The name of the setting, which is also its path in the config.
+    pub const fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+impl<T> Clone for VaultSetting<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for VaultSetting<T> {}
+
+impl VaultSetting<bool> {
+    pub const ACCESS_LOG: Self = VaultSetting::new(MetadataKey::AccessLog, "access_log");
+    pub const PERSIST_FAILED_ATTEMPTS: Self = VaultSetting::new(
+        MetadataKey::PersistFailedAttempts,
+        "persist_failed_attempts",
+    );
+}
+
+impl VaultSetting<u32> {
+    pub const EXPIRY_WARNING_DAYS: Self = VaultSetting::new(
+        MetadataKey::ExpiryWarningDays,
+        "expiry.warning_days",
+    );
+}
+
+nanosql::define_query! {
+    /// The optional parameter is a search/filter term. It works with SQLite `LIKE` syntax,
+    /// with `\` as the escape character. If not provided, all items will be returned.
+    ListItemsForDisplay<'p>: Option<&'p str> => Vec<DisplayItem> {
+        r#"
+        SELECT
+            "item"."uid" AS "uid",
+            "item"."label" AS "label",
+            "item"."account" AS "account",
+            "item"."last_modified_at" AS "last_modified_at",
+            "item"."reprompt" AS "reprompt"
+        FROM "item"
+        WHERE ?1 IS NULL
+           OR "item"."label" LIKE ?1 ESCAPE '\'
+           OR "item"."account" LIKE ?1 ESCAPE '\'
+        ORDER BY "item"."uid";
+        "#
+    }
+
+    /// Items whose account name is missing or empty.
+    ItemsWithoutAccount<'p>: () => Vec<DisplayItem> {
+        r#"
+        SELECT
+            "item"."uid" AS "uid",
+            "item"."label" AS "label",
+            "item"."account" AS "account",
+            "item"."last_modified_at" AS "last_modified_at",
+            "item"."reprompt" AS "reprompt"
+        FROM "item"
+        WHERE "item"."account" IS NULL OR "item"."account" = ''
+        ORDER BY "item"."uid";
+        "#
+    }
+
+    /// Items without any tags.
+    ItemsWithoutTags<'p>: () => Vec<DisplayItem> {
+        r#"
+        SELECT
+            "item"."uid" AS "uid",
+            "item"."label" AS "label",
+            "item"."account" AS "account",
+            "item"."last_modified_at" AS "last_modified_at",
+            "item"."reprompt" AS "reprompt"
+        FROM "item"
+        WHERE NOT EXISTS(
+            SELECT 1 FROM "item_tag" WHERE "item_tag"."item_uid" = "item"."uid"
+        )
+        ORDER BY "item"."uid";
+        "#
+    }
+
+    /// The parameter is the cutoff date: items with no access recorded at
+    /// or after it are returned. Timestamps are compared as RFC 3339 strings.
+    ItemsNotAccessedSince<'p>: DateTime<Utc> => Vec<DisplayItem> {
+        r#"
+        SELECT
+            "item"."uid" AS "uid",
+            "item"."label" AS "label",
+            "item"."account" AS "account",
+            "item"."last_modified_at" AS "last_modified_at",
+            "item"."reprompt" AS "reprompt"
+        FROM "item"
+        WHERE NOT EXISTS(
+            SELECT 1 FROM "access_log"
+            WHERE "access_log"."item_uid" = "item"."uid" AND "access_log"."accessed_at" >= ?1
+        )
+        ORDER BY "item"."uid";
+        "#
+    }
+
+    /// The parameter is the earliest modification date to be included.
+    ItemsModifiedSince<'p>: DateTime<Utc> => Vec<DisplayItem> {
+        r#"
+        SELECT
+            "item"."uid" AS "uid",
+            "item"."label" AS "label",
+            "item"."account" AS "account",
+            "item"."last_modified_at" AS "last_modified_at",
+            "item"."reprompt" AS "reprompt"
+        FROM "item"
+        WHERE "item"."last_modified_at" >= ?1
+        ORDER BY "item"."uid";
+        "#
+    }
+
+    /// The parameter is the label to be looked up. Labels are compared exactly,
+    /// in the same way as the `UNIQUE` constraint on the column does.
+    LabelExists<'p>: &'p str => Single<bool> {
+        r#"
+        SELECT EXISTS(
+            SELECT 1 FROM "item" WHERE "item"."label" = ?1
+        ) AS "exists";
+        "#
+    }
+
+    /// Retrieves all columns of the item with the given label, if any.
+    ItemByLabel<'p>: &'p str => Option<Item> {
+        r#"
+        SELECT
+            "item"."uid" AS "uid",
+            "item"."label" AS "label",
+            "item"."account" AS "account",
+            "item"."last_modified_at" AS "last_modified_at",
+            "item"."encrypted_secret" AS "encrypted_secret",
+            "item"."kdf_salt" AS "kdf_salt",
+            "item"."auth_nonce" AS "auth_nonce",
+            "item"."peppered" AS "peppered",
+            "item"."kdf" AS "kdf",
+            "item"."cipher" AS "cipher",
+            "item"."reprompt" AS "reprompt"
+        FROM "item"
+        WHERE "item"."label" = ?1;
+        "#
+    }
+
+    /// Replaces the encrypted secret of the item, along with the data
+    /// required for decrypting it.
+    UpdateEncryption<'p>: UpdateEncryptionInput<'p> => () {
+        r#"
+        UPDATE "item"
+        SET
+            "encrypted_secret" = $encrypted_secret,
+            "kdf_salt" = $kdf_salt,
+            "auth_nonce" = $auth_nonce,
+            "peppered" = $peppered,
+            "kdf" = $kdf,
+            "cipher" = $cipher
+        WHERE "item"."uid" = $uid;
+        "#
+    }
+
+    /// The parameters are the unique ID of the item and its new label.
+    SetLabel<'p>: (u64, &'p str) => () {
+        r#"
+        UPDATE "item" SET "label" = ?2 WHERE "item"."uid" = ?1;
+        "#
+    }
+
+    /// The parameters are the unique ID of the item and its new account name.
+    SetAccount<'p>: (u64, Option<&'p str>) => () {
+        r#"
+        UPDATE "item" SET "account" = ?2 WHERE "item"."uid" = ?1;
+        "#
+    }
+
+    /// The parameters are the unique ID of the item and its new modification date.
+    SetLastModifiedAt<'p>: (u64, DateTime<Utc>) => () {
+        r#"
+        UPDATE "item" SET "last_modified_at" = ?2 WHERE "item"."uid" = ?1;
+        "#
+    }
+
+    /// The parameters are the unique ID of the item and the new value of the flag.
+    SetReprompt<'p>: (u64, bool) => () {
+        r#"
+        UPDATE "item" SET "reprompt" = ?2 WHERE "item"."uid" = ?1;
+        "#
+    }
+
+    /// Retrieves all columns of all items, e.g. for exporting them.
+    ListAllItems<'p>: () => Vec<Item> {
+        r#"
+        SELECT
+            "item"."uid" AS "uid",
+            "item"."label" AS "label",
+            "item"."account" AS "account",
+            "item"."last_modified_at" AS "last_modified_at",
+            "item"."encrypted_secret" AS "encrypted_secret",
+            "item"."kdf_salt" AS "kdf_salt",
+            "item"."auth_nonce" AS "auth_nonce",
+            "item"."peppered" AS "peppered",
+            "item"."kdf" AS "kdf",
+            "item"."cipher" AS "cipher",
+            "item"."reprompt" AS "reprompt"
+        FROM "item"
+        ORDER BY "item"."uid";
+        "#
+    }
+
+    /// The parameter is the unique ID of the item. Most recent accesses come first.
+    AccessHistory<'p>: u64 => Vec<AccessLogEntry> {
+        r#"
+        SELECT
+            "access_log"."item_uid" AS "item_uid",
+            "access_log"."accessed_at" AS "accessed_at"
+        FROM "access_log"
+        WHERE "access_log"."item_uid" = ?1
+        ORDER BY "access_log"."accessed_at" DESC, "access_log"."rowid" DESC;
+        "#
+    }
+
+    /// The parameters are the unique ID of the item and the name of the tag.
+    /// Inserts nothing if the item already has the tag.
+    TagItem<'p>: (u64, &'p str) => () {
+        r#"
+        INSERT INTO "item_tag"("item_uid", "tag")
+        SELECT ?1, ?2
+        WHERE NOT EXISTS(
+            SELECT 1 FROM "item_tag" WHERE "item_tag"."item_uid" = ?1 AND "item_tag"."tag" = ?2
+        );
+        "#
+    }
+
+    /// The parameters are the unique ID of the item and the name of the tag.
+    UntagItem<'p>: (u64, &'p str) => () {
+        r#"
+        DELETE FROM "item_tag" WHERE "item_tag"."item_uid" = ?1 AND "item_tag"."tag" = ?2;
+        "#
+    }
+
+    /// The parameter is the unique ID of the item.
+    ItemTags<'p>: u64 => Vec<String> {
+        r#"
+        SELECT "item_tag"."tag" AS "tag"
+        FROM "item_tag"
+        WHERE "item_tag"."item_uid" = ?1
+        ORDER BY "item_tag"."tag";
+        "#
+    }
+
+    /// Every attachment of a tag to an item.
+    AllItemTags<'p>: () => Vec<ItemTag> {
+        r#"
+        SELECT
+            "item_tag"."uid" AS "uid",
+            "item_tag"."item_uid" AS "item_uid",
+            "item_tag"."tag" AS "tag"
+        FROM "item_tag"
+        ORDER BY "item_tag"."item_uid", "item_tag"."tag";
+        "#
+    }
+
+    /// Every tag in use, with the number of items having it.
+    TagCounts<'p>: () => Vec<TagCount> {
+        r#"
+        SELECT
+            "item_tag"."tag" AS "tag",
+            COUNT(*) AS "item_count"
+        FROM "item_tag"
+        GROUP BY "item_tag"."tag"
+        ORDER BY "item_tag"."tag";
+        "#
+    }
+
+    /// The parameters are the old and the new name of the tag.
+    RenameTag<'p>: (&'p str, &'p str) => () {
+        r#"
+        UPDATE "item_tag" SET "tag" = ?2 WHERE "item_tag"."tag" = ?1;
+        "#
+    }
+
+    /// After merging two tags, items that had both of them have the tag twice.
+    RemoveDuplicateTags<'p>: () => () {
+        r#"
+        DELETE FROM "item_tag"
+        WHERE "item_tag"."uid" NOT IN (
+            SELECT MIN("uid") FROM "item_tag" GROUP BY "item_uid", "tag"
+        );
+        "#
+    }
+
+    /// The parameter is the name of the tag.
+    DeleteTag<'p>: &'p str => () {
+        r#"
+        DELETE FROM "item_tag" WHERE "item_tag"."tag" = ?1;
+        "#
+    }
+
+    /// The parameter is the unique ID of the item.
+    ClearTags<'p>: u64 => () {
+        r#"
+        DELETE FROM "item_tag" WHERE "item_tag"."item_uid" = ?1;
+        "#
+    }
+
+    /// The parameter is the unique ID of the item.
+    ClearAccessLog<'p>: u64 => () {
+        r#"
+        DELETE FROM "access_log" WHERE "access_log"."item_uid" = ?1;
+        "#
+    }
+
+    /// The parameters are the unique ID of the item and the date of the quarantine.
+    /// The item itself must be deleted afterwards using `DeleteItem`.
+    QuarantineItem<'p>: (u64, DateTime<Utc>) => () {
+        r#"
+        INSERT INTO "quarantined_item" (
+            "item_uid", "label", "account", "last_modified_at", "encrypted_secret",
+            "kdf_salt", "auth_nonce", "peppered", "kdf", "cipher", "quarantined_at"
+        )
+        SELECT
+            "item"."uid", "item"."label", "item"."account", "item"."last_modified_at",
+            "item"."encrypted_secret", "item"."kdf_salt", "item"."auth_nonce",
+            "item"."peppered", "item"."kdf", "item"."cipher", ?2
+        FROM "item"
+        WHERE "item"."uid" = ?1;
+        "#
+    }
+
+    /// The parameter is the unique ID of the item.
+    ClearItemIdentity<'p>: u64 => () {
+        r#"
+        DELETE FROM "item_identity" WHERE "item_identity"."item_uid" = ?1;
+        "#
+    }
+
+    /// The parameter is the unique ID of the item. Everything
+    /// referring to the item must have been deleted before.
+    DeleteItem<'p>: u64 => () {
+        r#"
+        DELETE FROM "item" WHERE "item"."uid" = ?1;
+        "#
+    }
+
+    /// Retrieves every quarantined item, most recently quarantined first.
+    ListQuarantinedItems<'p>: () => Vec<QuarantinedItem> {
+        r#"
+        SELECT
+            "quarantined_item"."uid" AS "uid",
+            "quarantined_item"."item_uid" AS "item_uid",
+            "quarantined_item"."label" AS "label",
+            "quarantined_item"."account" AS "account",
+            "quarantined_item"."last_modified_at" AS "last_modified_at",
+            "quarantined_item"."encrypted_secret" AS "encrypted_secret",
+            "quarantined_item"."kdf_salt" AS "kdf_salt",
+            "quarantined_item"."auth_nonce" AS "auth_nonce",
+            "quarantined_item"."peppered" AS "peppered",
+            "quarantined_item"."kdf" AS "kdf",
+            "quarantined_item"."cipher" AS "cipher",
+            "quarantined_item"."quarantined_at" AS "quarantined_at"
+        FROM "quarantined_item"
+        ORDER BY "quarantined_item"."quarantined_at" DESC, "quarantined_item"."uid" DESC;
+        "#
+    }
+
+    /// The parameter is the unique ID of the item.
+    ClearExpiry<'p>: u64 => () {
+        r#"
+        DELETE FROM "item_expiry" WHERE "item_expiry"."item_uid" = ?1;
+        "#
+    }
+
+    /// The parameter is the unique ID of the item.
+    ClearRecoveryCodes<'p>: u64 => () {
+        r#"
+        DELETE FROM "item_recovery_codes" WHERE "item_recovery_codes"."item_uid" = ?1;
+        "#
+    }
+
+    /// The parameter is the unique ID of the item.
+    ClearUrl<'p>: u64 => () {
+        r#"
+        DELETE FROM "item_url" WHERE "item_url"."item_uid" = ?1;
+        "#
+    }
+
+    /// The parameters are the unique ID of the item and the interval in days.
+    /// An existing policy keeps the date of the last rotation.
+    SetRotationInterval<'p>: (u64, u32) => () {
+        r#"
+        INSERT INTO "item_rotation" ("item_uid", "interval_days", "last_rotated_at")
+        SELECT "item"."uid", ?2, "item"."last_modified_at"
+        FROM "item"
+        WHERE "item"."uid" = ?1
+        ON CONFLICT ("item_uid") DO UPDATE SET "interval_days" = "excluded"."interval_days";
+        "#
+    }
+
+    /// The parameters are the unique ID of the item and the time of the change.
+    MarkRotated<'p>: (u64, DateTime<Utc>) => () {
+        r#"
+        UPDATE "item_rotation" SET "last_rotated_at" = ?2 WHERE "item_rotation"."item_uid" = ?1;
+        "#
+    }
+
+    /// The parameter is the unique ID of the item.
+    ClearRotation<'p>: u64 => () {
+        r#"
+        DELETE FROM "item_rotation" WHERE "item_rotation"."item_uid" = ?1;
+        "#
+    }
+
+    /// Every rotation policy.
+    AllRotations<'p>: () => Vec<ItemRotation> {
+        r#"
+        SELECT
+            "item_rotation"."item_uid" AS "item_uid",
+            "item_rotation"."interval_days" AS "interval_days",
+            "item_rotation"."last_rotated_at" AS "last_rotated_at"
+        FROM "item_rotation"
+        ORDER BY "item_rotation"."item_uid";
+        "#
+    }
+
+    /// Every URL.
+    AllUrls<'p>: () => Vec<ItemUrl> {
+        r#"
+        SELECT
+            "item_url"."item_uid" AS "item_uid",
+            "item_url"."url" AS "url"
+        FROM "item_url"
+        ORDER BY "item_url"."item_uid";
+        "#
+    }
+
+    /// The parameter is the unique ID of the item.
+    ClearEnvelope<'p>: u64 => () {
+        r#"
+        DELETE FROM "item_envelope" WHERE "item_envelope"."item_uid" = ?1;
+        "#
+    }
+
+    /// Every envelope.
+    AllEnvelopes<'p>: () => Vec<ItemEnvelope> {
+        r#"
+        SELECT
+            "item_envelope"."item_uid" AS "item_uid",
+            "item_envelope"."envelope" AS "envelope"
+        FROM "item_envelope"
+        ORDER BY "item_envelope"."item_uid";
+        "#
+    }
+
+    /// Every member of the team.
+    AllRecipients<'p>: () => Vec<Recipient> {
+        r#"
+        SELECT
+            "recipient"."name" AS "name",
+            "recipient"."public_key" AS "public_key",
+            "recipient"."added_at" AS "added_at"
+        FROM "recipient"
+        ORDER BY "recipient"."name";
+        "#
+    }
+
+    /// The parameter is the name of the member.
+    DeleteRecipient<'p>: &'p str => () {
+        r#"
+        DELETE FROM "recipient" WHERE "recipient"."name" = ?1;
+        "#
+    }
+
+    /// The number of codes in every item holding recovery codes.
+    AllRecoveryCodes<'p>: () => Vec<ItemRecoveryCodes> {
+        r#"
+        SELECT
+            "item_recovery_codes"."item_uid" AS "item_uid",
+            "item_recovery_codes"."remaining" AS "remaining",
+            "item_recovery_codes"."total" AS "total"
+        FROM "item_recovery_codes"
+        ORDER BY "item_recovery_codes"."item_uid";
+        "#
+    }
+
+    /// Every expiry date.
+    AllExpiries<'p>: () => Vec<ItemExpiry> {
+        r#"
+        SELECT
+            "item_expiry"."item_uid" AS "item_uid",
+            "item_expiry"."expires_at" AS "expires_at"
+        FROM "item_expiry"
+        ORDER BY "item_expiry"."item_uid";
+        "#
+    }
+
+    /// The parameter is the latest expiry date to be included. Timestamps
+    /// are stored as RFC 3339 strings in UTC, so they can be compared as text.
+    ExpiringItems<'p>: DateTime<Utc> => Vec<ExpiringItem> {
+        r#"
+        SELECT
+            "item"."uid" AS "uid",
+            "item"."label" AS "label",
+            "item"."account" AS "account",
+            "item_expiry"."expires_at" AS "expires_at"
+        FROM "item_expiry"
+        INNER JOIN "item" ON "item"."uid" = "item_expiry"."item_uid"
+        WHERE "item_expiry"."expires_at" <= ?1
+        ORDER BY "item_expiry"."expires_at", "item"."uid";
+        "#
+    }
+
+    /// Aggregates over all items. Timestamps are stored as RFC 3339 strings,
+    /// all in UTC, so their lexicographic order is the same as chronological.
+    ItemStats<'p>: () => Single<ItemStatsRecord> {
+        r#"
+        SELECT
+            COUNT(*) AS "item_count",
+            COUNT(*) - COUNT("item"."account") AS "items_without_account",
+            MIN("item"."last_modified_at") AS "oldest_modified_at",
+            MAX("item"."last_modified_at") AS "newest_modified_at"
+        FROM "item";
+        "#
+    }
+
+    /// A random 128-bit ID in hexadecimal, for replicas and items.
+    RandomId<'p>: () => Single<String> {
+        r#"
+        SELECT lower(hex(randomblob(16)));
+        "#
+    }
+
+    /// The parameter is the unique ID of a newly-added item.
+    AssignItemId<'p>: u64 => () {
+        r#"
+        INSERT INTO "item_identity" ("item_uid", "item_id")
+        VALUES (?1, lower(hex(randomblob(16))));
+        "#
+    }
+
+    /// The parameters are the unique ID of the item and the kind of the operation.
+    /// The clock of the timestamp is one greater than any other in the log.
+    LogOperation<'p>: (u64, OpKind) => () {
+        r#"
+        INSERT INTO "operation" ("item_id", "kind", "clock", "replica")
+        SELECT
+            "item_identity"."item_id",
+            ?2,
+            (SELECT COALESCE(MAX("operation"."clock"), 0) + 1 FROM "operation"),
+            (SELECT "metadata"."value" FROM "metadata" WHERE "metadata"."key" = 'replica_id')
+        FROM "item_identity"
+        WHERE "item_identity"."item_uid" = ?1;
+        "#
+    }
+
+    /// The parameters are the replica-independent ID of an item, which needn't
+    /// exist locally, and the kind of the operation. Stamped like `LogOperation`.
+    LogOperationOnId<'p>: (&'p str, OpKind) => () {
+        r#"
+        INSERT INTO "operation" ("item_id", "kind", "clock", "replica")
+        VALUES (
+            ?1,
+            ?2,
+            (SELECT COALESCE(MAX("operation"."clock"), 0) + 1 FROM "operation"),
+            (SELECT "metadata"."value" FROM "metadata" WHERE "metadata"."key" = 'replica_id')
+        );
+        "#
+    }
+
+    /// Retrieves the whole operation log, in the order of recording.
+    AllOperations<'p>: () => Vec<OperationEntry> {
+        r#"
+        SELECT
+            "operation"."item_id" AS "item_id",
+            "operation"."kind" AS "kind",
+            "operation"."clock" AS "clock",
+            "operation"."replica" AS "replica"
+        FROM "operation"
+        ORDER BY "operation"."rowid";
+        "#
+    }
+
+    /// Retrieves the replica-independent ID of every item.
+    AllItemIdentities<'p>: () => Vec<ItemIdentity> {
+        r#"
+        SELECT
+            "item_identity"."item_uid" AS "item_uid",
+            "item_identity"."item_id" AS "item_id"
+        FROM "item_identity";
+        "#
+    }
+
+    /// The parameter is the replica-independent ID of the item.
+    ItemUidById<'p>: &'p str => Option<u64> {
+        r#"
+        SELECT "item_identity"."item_uid" AS "item_uid"
+        FROM "item_identity"
+        WHERE "item_identity"."item_id" = ?1;
+        "#
+    }
+}
+
+/// Tags are single-line, and surrounding whitespace is insignificant.
+pub fn normalize_tag(tag: &str) -> Result<&str> {
+    let tag = tag.trim();
+
+    if tag.is_empty() || tag.contains('\n') {
+        Err(Error::TagRequired)
+    } else {
+        Ok(tag)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Utc, Days, NaiveDate};
+    use nanosql::{Null, Error as NanosqlError};
+    use nanosql::rusqlite::{ErrorCode, Error as SqliteError};
+    use crate::config::DatabaseOptions;
+    use crate::crypto::{
+        KdfParams, CipherId, EncryptionInput, EncryptionOutput, RECOMMENDED_SALT_LEN, NONCE_LEN,
+    };
+    use crate::fields::{Label, AccountName, PlaintextSecret, MasterPassword};
+    use crate::vault::Vault;
+    use crate::error::{Error, Result};
+    use super::{Database, AddItemInput, UpdateEncryptionInput, VaultSetting, SmartFilter, TagCount};
+
+
+    #[test]
+    fn salt_uniqueness_is_enforced() -> Result<()> {
+        let db = Database::open(":memory:", &DatabaseOptions::default())?;
+        let salt: [u8; RECOMMENDED_SALT_LEN] = *b"Qk2Dw5aV65Ie8y7t";
+        let nonce_1: [u8; NONCE_LEN] = *b"lMVXTMT2z2giginHeWwIajy4";
+        let nonce_2: [u8; NONCE_LEN] = *b"rZNaJw3dBHmiqGhfUxLbjL6x";
+        let account_1 = AccountName::new("first@account.com")?;
+        let account_2 = AccountName::new("second@otherserver.org")?;
+
+        let input_1 = AddItemInput {
+            uid: Null,
+            label: &Label::new("Some label")?,
+            account: Some(&account_1),
+            last_modified_at: Utc::now(),
+            encrypted_secret: b"EncrYpt3d S3cre7!123",
+            kdf_salt: salt,
+            auth_nonce: nonce_1,
+            peppered: false,
+            kdf: KdfParams::CURRENT,
+            cipher: CipherId::CURRENT,
+            reprompt: false,
+        };
+        let input_2 = AddItemInput {
+            uid: Null,
+            label: &Label::new("a completely different title")?,
+            account: Some(&account_2),
+            last_modified_at: Utc::now(),
+            encrypted_secret: b"$#an0ther-c1pherteXt-of_diff3rent^LENGTH%",
+            kdf_salt: salt,
+            auth_nonce: nonce_2,
+            peppered: false,
+            kdf: KdfParams::CURRENT,
+            cipher: CipherId::CURRENT,
+            reprompt: false,
+        };
+
+        // We should be able to add the first item sucessfully.
+        let item = db.add_item(input_1)?;
+        assert_eq!(db.item_by_id(item.uid)?, item);
+
+        // The second item has an identical salt, so insertion must fail
+        // due to the violation of the UNIQUE constraint.
+        let error = db.add_item(input_2).expect_err("item with duplicate salt added");
+        let Error::Db(NanosqlError::Sqlite(SqliteError::SqliteFailure(error, _))) = error else {
+            panic!("unexpected error: {}", error);
+        };
+
+        assert_eq!(error.code, ErrorCode::ConstraintViolation);
+
+        Ok(())
+    }
+
+    #[test]
+    fn nonce_uniqueness_is_enforced() -> Result<()> {
+        let db = Database::open(":memory:", &DatabaseOptions::default())?;
+        let salt_1: [u8; RECOMMENDED_SALT_LEN] = *b"NdBIIex0BLnkThWH";
+        let salt_2: [u8; RECOMMENDED_SALT_LEN] = *b"xS8HYP2XAjgSnEOJ";
+        let nonce: [u8; NONCE_LEN] = *b"vb4yngPRSgEOrBLNGw8YcGpG";
+        let account_1 = AccountName::new("foo@bar.qux")?;
+        let account_2 = AccountName::new("lol@wut.gov")?;
+
+        let input_1 = AddItemInput {
+            uid: Null,
+            label: &Label::new("Not a useful label")?,
+            account: Some(&account_1),
+            last_modified_at: Utc::now(),
+            encrypted_secret: b"more stuff, I've run out of ideas",
+            kdf_salt: salt_1,
+            auth_nonce: nonce,
+            peppered: false,
+            kdf: KdfParams::CURRENT,
+            cipher: CipherId::CURRENT,
+            reprompt: false,
+        };
+        let input_2 = AddItemInput {
+            uid: Null,
+            label: &Label::new("...but neither is this!")?,
+            account: Some(&account_2),
+            last_modified_at: Utc::now(),
+            encrypted_secret: b"some different blob",
+            kdf_salt: salt_2,
+            auth_nonce: nonce,
+            peppered: false,
+            kdf: KdfParams::CURRENT,
+            cipher: CipherId::CURRENT,
+            reprompt: false,
+        };
+
+        // We should be able to add the first item sucessfully.
+        let item = db.add_item(input_1)?;
+        assert_eq!(db.item_by_id(item.uid)?, item);
+
+        // The second item has an identical nonce, so insertion must fail
+        // due to the violation of the UNIQUE constraint.
+        let error = db.add_item(input_2).expect_err("item with duplicate nonce added");
+        let Error::Db(NanosqlError::Sqlite(SqliteError::SqliteFailure(error, _))) = error else {
+            panic!("unexpected error: {}", error);
+        };
+
+        assert_eq!(error.code, ErrorCode::ConstraintViolation);
+
+        Ok(())
+    }
+
+    #[test]
+    fn search_text_is_matched_literally() -> Result<()> {
+        let db = Database::open(":memory:", &DatabaseOptions::default())?;
+
+        for (label, salt, nonce) in [
+            ("50% off", *b"c2VhcmNoX3NhbHRf", *b"c2VhcmNoX25vbmNlXzEyMzQ1"),
+            ("500 points", *b"c2VhcmNoX3NhbHQy", *b"c2VhcmNoX25vbmNlXzY3ODkw"),
+            ("user_name", *b"c2VhcmNoX3NhbHQz", *b"c2VhcmNoX25vbmNlX2FiY2Rl"),
+        ] {
+            db.add_item(AddItemInput {
+                uid: Null,
+                label: &Label::new(label)?,
+                account: None,
+                last_modified_at: Utc::now(),
+                encrypted_secret: b"ciphertext",
+                kdf_salt: salt,
+                auth_nonce: nonce,
+                peppered: false,
+                kdf: KdfParams::CURRENT,
+                cipher: CipherId::CURRENT,
+                reprompt: false,
+            })?;
+        }
+
+        let labels = |text| -> Result<Vec<String>> {
+            let items = db.list_items_for_display(Some(text))?;
+            Ok(items.into_iter().map(|item| item.label).collect())
+        };
+
+        assert_eq!(labels("50%")?, ["50% off"]);
+        assert_eq!(labels("R_N")?, ["user_name"]);
+        assert_eq!(labels("0 P")?, ["500 points"]);
+        assert!(labels("\\")?.is_empty());
+        assert_eq!(db.list_items_for_display(None)?.len(), 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn stats_reflect_items() -> Result<()> {
+        let db = Database::open(":memory:", &DatabaseOptions::default())?;
+
+        // Aggregates must be well-defined for an empty table, too.
+        let stats = db.stats(Utc::now())?;
+        assert_eq!(stats.item_count, 0);
+        assert_eq!(stats.items_without_account, 0);
+        assert_eq!(stats.expired_count, 0);
+        assert_eq!(stats.rotation_overdue_count, 0);
+        assert!(stats.tags.is_empty());
+        assert_eq!(stats.oldest_modified_at, None);
+        assert_eq!(stats.newest_modified_at, None);
+        assert_eq!(stats.file_size, None);
+
+        let t1 = Utc::now().checked_sub_days(Days::new(7)).unwrap();
+        let t2 = Utc::now();
+
+        let old_item = db.add_item(AddItemInput {
+            uid: Null,
+            label: &Label::new("old item")?,
+            account: None,
+            last_modified_at: t1,
+            encrypted_secret: b"ciphertext #1",
+            kdf_salt: *b"tEoDuAnrYaP5QiDr",
+            auth_nonce: *b"7bSm4sV0JeKxqSgQGRzXEuUm",
+            peppered: false,
+            kdf: KdfParams::CURRENT,
+            cipher: CipherId::CURRENT,
+            reprompt: false,
+        })?;
+        let new_item = db.add_item(AddItemInput {
+            uid: Null,
+            label: &Label::new("new item")?,
+            account: Some(&AccountName::new("user@example.com")?),
+            last_modified_at: t2,
+            encrypted_secret: b"ciphertext #2",
+            kdf_salt: *b"NwpVaeF1mH3XlLkT",
+            auth_nonce: *b"bqKzmA8WDh6yeEwQ0cFrJ2Lo",
+            peppered: false,
+            kdf: KdfParams::CURRENT,
+            cipher: CipherId::CURRENT,
+            reprompt: false,
+        })?;
+
+        db.set_expiry(old_item.uid, Some(t1))?;
+        db.set_expiry(new_item.uid, Some(t2 + Days::new(30)))?;
+        db.set_rotation(old_item.uid, Some(1))?;
+        db.set_rotation(new_item.uid, Some(1))?;
+        db.tag_item(old_item.uid, "work")?;
+        db.tag_item(new_item.uid, "work")?;
+        db.tag_item(new_item.uid, "bank")?;
+
+        let stats = db.stats(t2)?;
+        assert_eq!(stats.item_count, 2);
+        assert_eq!(stats.items_without_account, 1);
+        assert_eq!(stats.expired_count, 1);
+        assert_eq!(stats.rotation_overdue_count, 1);
+        assert_eq!(stats.tags, [
+            TagCount { tag: String::from("bank"), item_count: 1 },
+            TagCount { tag: String::from("work"), item_count: 2 },
+        ]);
+        assert_eq!(stats.oldest_modified_at, Some(t1));
+        assert_eq!(stats.newest_modified_at, Some(t2));
+
+        Ok(())
+    }
+
+    #[test]
+    fn access_log_is_recorded_per_item() -> Result<()> {
+        let db = Database::open(":memory:", &DatabaseOptions::default())?;
+        let item = db.add_item(AddItemInput {
+            uid: Null,
+            label: &Label::new("accessed item")?,
+            account: None,
+            last_modified_at: Utc::now(),
+            encrypted_secret: b"ciphertext",
+            kdf_salt: *b"c2JkZ3VhbmZvcXBl",
+            auth_nonce: *b"Zm9vYmFyYmF6cXV4bG9sd3V0",
+            peppered: false,
+            kdf: KdfParams::CURRENT,
+            cipher: CipherId::CURRENT,
+            reprompt: false,
+        })?;
+
+        assert!(db.access_history(item.uid)?.is_empty());
+
+        db.log_access(item.uid)?;
+        db.log_access(item.uid)?;
+
+        let history = db.access_history(item.uid)?;
+        assert_eq!(history.len(), 2);
+        assert!(history.iter().all(|entry| entry.item_uid == item.uid));
+        assert!(history[0].accessed_at >= history[1].accessed_at);
+
+        // Accesses can't be logged for non-existent items.
+        assert!(db.log_access(item.uid + 1).is_err());
+        assert!(db.access_history(item.uid + 1)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn tags_can_be_renamed_merged_and_deleted() -> Result<()> {
+        let db = &mut Database::open(":memory:", &DatabaseOptions::default())?;
+        let first = db.add_item(AddItemInput {
+            uid: Null,
+            label: &Label::new("first")?,
+            account: None,
+            last_modified_at: Utc::now(),
+            encrypted_secret: b"ciphertext",
+            kdf_salt: *b"dGFnc19hcmVfZnVu",
+            auth_nonce: *b"dGFnc19hcmVfZnVuX3JlYWxs",
+            peppered: false,
+            kdf: KdfParams::CURRENT,
+            cipher: CipherId::CURRENT,
+            reprompt: false,
+        })?;
+        let second = db.add_item(AddItemInput {
+            uid: Null,
+            label: &Label::new("second")?,
+            account: None,
+            last_modified_at: Utc::now(),
+            encrypted_secret: b"ciphertext",
+            kdf_salt: *b"bW9yZV90YWdzX2Z1",
+            auth_nonce: *b"bW9yZV90YWdzX2Z1bl9yZWFs",
+            peppered: false,
+            kdf: KdfParams::CURRENT,
+            cipher: CipherId::CURRENT,
+            reprompt: false,
+        })?;
+        let counts = |db: &Database| -> Result<Vec<(String, u64)>> {
+            Ok(db.tag_counts()?.into_iter().map(|tc| (tc.tag, tc.item_count)).collect())
+        };
+
+        db.tag_item(first.uid, "work")?;
+        db.tag_item(first.uid, " work ")?; // already tagged
+        db.tag_item(first.uid, "banking")?;
+        db.tag_item(second.uid, "job")?;
+        db.tag_item(second.uid, "work")?;
+        assert!(matches!(db.tag_item(second.uid, "  "), Err(Error::TagRequired)));
+
+        assert_eq!(counts(db)?, [("banking".into(), 1), ("job".into(), 1), ("work".into(), 2)]);
+        assert_eq!(db.item_tags(first.uid)?, ["banking", "work"]);
+
+        // renaming to an existing tag merges them, without duplicates
+        db.rename_tag("job", "work")?;
+        assert_eq!(counts(db)?, [("banking".into(), 1), ("work".into(), 2)]);
+        assert_eq!(db.item_tags(second.uid)?, ["work"]);
+
+        db.rename_tag("banking", "finance")?;
+        db.delete_tag("work")?;
+        db.untag_item(first.uid, "finance")?;
+        assert!(counts(db)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn duplicate_label_is_reported() -> Result<()> {
+        let db = Database::open(":memory:", &DatabaseOptions::default())?;
+        let input = AddItemInput {
+            uid: Null,
+            label: &Label::new("my bank")?,
+            account: None,
+            last_modified_at: Utc::now(),
+            encrypted_secret: b"ciphertext",
+            kdf_salt: *b"9Q8iU7yT6rE5wQ4a",
+            auth_nonce: *b"zX1cV2bN3mL4kJ5hG6fD7sA8",
+            peppered: false,
+            kdf: KdfParams::CURRENT,
+            cipher: CipherId::CURRENT,
+            reprompt: false,
+        };
+
+        db.add_item(input.clone())?;
+        assert!(db.label_exists("my bank")?);
+        assert!(!db.label_exists("my other bank")?);
+
+        let result = db.add_item(AddItemInput {
+            kdf_salt: *b"0P9o8I7u6Y5t4R3e",
+            auth_nonce: *b"qW1eR2tY3uI4oP5aS6dF7gH8",
+            ..input
+        });
+        assert!(matches!(result, Err(Error::DuplicateLabel)), "unexpected result: {:#?}", result);
+
+        Ok(())
+    }
+
+    #[test]
+    fn read_only_database_can_be_read_but_not_changed() -> Result<()> {
+        let db = Database::open(":memory:", &DatabaseOptions::default())?;
+        let input = AddItemInput {
+            uid: Null,
+            label: &Label::new("my bank")?,
+            account: None,
+            last_modified_at: Utc::now(),
+            encrypted_secret: b"ciphertext",
+            kdf_salt: *b"9Q8iU7yT6rE5wQ4a",
+            auth_nonce: *b"zX1cV2bN3mL4kJ5hG6fD7sA8",
+            peppered: false,
+            kdf: KdfParams::CURRENT,
+            cipher: CipherId::CURRENT,
+            reprompt: false,
+        };
+        db.add_item(input.clone())?;
+
+        db.set_read_only()?;
+        assert!(db.label_exists("my bank")?);
+
+        let result = db.add_item(AddItemInput {
+            label: &Label::new("my other bank")?,
+            ..input
+        });
+        assert!(result.is_err(), "unexpected result: {:#?}", result);
+        assert!(!db.label_exists("my other bank")?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn vault_settings_can_be_set_and_unset() -> Result<()> {
+        let db = Database::open(":memory:", &DatabaseOptions::default())?;
+
+        assert_eq!(db.setting(VaultSetting::ACCESS_LOG)?, None);
+        assert_eq!(db.setting(VaultSetting::EXPIRY_WARNING_DAYS)?, None);
+
+        db.set_setting(VaultSetting::ACCESS_LOG, Some(&true))?;
+        db.set_setting(VaultSetting::EXPIRY_WARNING_DAYS, Some(&30))?;
+        assert_eq!(db.setting(VaultSetting::ACCESS_LOG)?, Some(true));
+        assert_eq!(db.setting(VaultSetting::EXPIRY_WARNING_DAYS)?, Some(30));
+
+        // settings are independent of each other and of other metadata
+        db.set_setting(VaultSetting::ACCESS_LOG, None)?;
+        assert_eq!(db.setting(VaultSetting::ACCESS_LOG)?, None);
+        assert_eq!(db.setting(VaultSetting::EXPIRY_WARNING_DAYS)?, Some(30));
+        assert_eq!(db.setting(VaultSetting::PERSIST_FAILED_ATTEMPTS)?, None);
+        assert_eq!(db.failed_attempts()?, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn encryption_can_be_updated() -> Result<()> {
+        let db = &mut Database::open(":memory:", &DatabaseOptions::default())?;
+        let outdated_kdf = KdfParams::Argon2id { version: 0x10, m_cost: 1024, t_cost: 1, p_cost: 1 };
+        let item = db.add_item(AddItemInput {
+            uid: Null,
+            label: &Label::new("outdated item")?,
+            account: Some(&AccountName::new("user@example.com")?),
+            last_modified_at: Utc::now(),
+            encrypted_secret: b"old ciphertext",
+            kdf_salt: *b"b2xkX3NhbHRfMTIz",
+            auth_nonce: *b"b2xkX25vbmNlXzEyMzQ1Njc4",
+            peppered: false,
+            kdf: outdated_kdf,
+            cipher: CipherId::CURRENT,
+            reprompt: false,
+        })?;
+
+        assert!(item.needs_crypto_upgrade());
+
+        db.update_encryption([UpdateEncryptionInput {
+            uid: item.uid,
+            encrypted_secret: b"new ciphertext",
+            kdf_salt: *b"bmV3X3NhbHRfMTIz",
+            auth_nonce: *b"bmV3X25vbmNlXzEyMzQ1Njc4",
+            peppered: true,
+            kdf: KdfParams::CURRENT,
+            cipher: CipherId::CURRENT,
+        }])?;
+
+        let updated = db.item_by_id(item.uid)?;
+        assert!(!updated.needs_crypto_upgrade());
+        assert_eq!(updated.encrypted_secret, b"new ciphertext");
+        assert_eq!(&updated.kdf_salt, b"bmV3X3NhbHRfMTIz");
+        assert!(updated.peppered);
+
+        // the rest of the item is unchanged
+        assert_eq!(updated.label, item.label);
+        assert_eq!(updated.account, item.account);
+        assert_eq!(updated.last_modified_at, item.last_modified_at);
+
+        Ok(())
+    }
+
+    #[test]
+    fn compaction_shrinks_the_file() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("steelsafe-compact-test-{}", std::process::id()));
+        let db = Database::open(&path, &DatabaseOptions::default())?;
+        let padding = vec![0xa5_u8; 4096];
+
+        for index in 0..64_u32 {
+            db.add_item(AddItemInput {
+                uid: Null,
+                label: &Label::new(&format!("item {index}"))?,
+                account: None,
+                last_modified_at: Utc::now(),
+                encrypted_secret: &padding,
+                kdf_salt: format!("{index:016}").into_bytes().try_into().unwrap(),
+                auth_nonce: format!("{index:024}").into_bytes().try_into().unwrap(),
+                peppered: false,
+                kdf: KdfParams::CURRENT,
+                cipher: CipherId::CURRENT,
+                reprompt: false,
+            })?;
+        }
+
+        // the write-ahead log holds the latest changes, so it counts, too
+        let wal_size = std::fs::metadata(format!("{}-wal", path.display()))?.len();
+        assert!(wal_size > 0);
+        assert_eq!(db.stats(Utc::now())?.file_size, Some(std::fs::metadata(&path)?.len() + wal_size));
+
+        db.connection.execute_batch(r#"
+            DELETE FROM "item_identity";
+            DELETE FROM "item";
+            PRAGMA wal_checkpoint(TRUNCATE);
+        "#).map_err(nanosql::Error::from)?;
+
+        let compaction = db.compact()?;
+        drop(db);
+        std::fs::remove_file(&path)?;
+
+        let (Some(before), Some(after)) = (compaction.size_before, compaction.size_after) else {
+            panic!("file sizes are unknown: {compaction:?}");
+        };
+        // the empty tables and their indexes still take up a page each, but the secrets are gone
+        let freed = u64::try_from(64 * padding.len()).unwrap();
+        assert!(after + freed <= before, "{after} bytes after compaction, {before} bytes before");
+
+        // databases that aren't stored in a file can be compacted, too
+        let compaction = Database::open(":memory:", &DatabaseOptions::default())?.compact()?;
+        assert!(compaction.size_before.is_none() && compaction.size_after.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn replaced_secret_is_authenticated_with_the_new_date() -> Result<()> {
+        let db = &mut Database::open(":memory:", &DatabaseOptions::default())?;
+        let item = db.add_item(AddItemInput {
+            uid: Null,
+            label: &Label::new("Router")?,
+            account: Some(&AccountName::new("admin")?),
+            last_modified_at: Utc::now() - Days::new(400),
+            encrypted_secret: b"old ciphertext",
+            kdf_salt: *b"cm90YXRlZF9zYWx0",
+            auth_nonce: *b"cm90YXRlZF9ub25jZV8xMjM0",
+            peppered: false,
+            kdf: KdfParams::CURRENT,
+            cipher: CipherId::CURRENT,
+            reprompt: false,
+        })?;
+        let modified_at = Utc::now();
+        let secret = PlaintextSecret::new(b"new secret");
+        let output = item.encrypt_new_secret(&secret, &MasterPassword::new("hunter2")?, None, modified_at)?;
+
+        db.replace_secret(item.uid, modified_at, &output)?;
+
+        let rotated = db.item_by_id(item.uid)?;
+        assert_eq!(rotated.last_modified_at, modified_at);
+        assert_eq!(rotated.decryption_input(None).decrypt_and_verify(b"hunter2")?.as_slice(), b"new secret");
+
+        Ok(())
+    }
+
+    #[test]
+    fn renamed_item_is_authenticated_with_the_new_label() -> Result<()> {
+        let db = &mut Database::open(":memory:", &DatabaseOptions::default())?;
+        let modified_at = Utc::now() - Days::new(30);
+        let password = MasterPassword::new("hunter2")?;
+        let account = AccountName::new("me@example.com")?;
+        let add = |label: &str| -> Result<_> {
+            let label = Label::new(label)?;
+            let output = EncryptionInput {
+                plaintext_secret: &PlaintextSecret::new(b"correct horse"),
+                label: &label,
+                account: Some(&account),
+                last_modified_at: modified_at,
+                pepper: None,
+            }.encrypt_and_authenticate(&password)?;
+
+            db.add_item(AddItemInput::new(&label, Some(&account), modified_at, &output))
+        };
+        let item = add("Mail")?;
+        add("Bank")?;
+
+        let renamed_at = Utc::now();
+        let secret = item.decryption_input(None).decrypt_and_verify(password.as_bytes())?;
+        let new_label = Label::new("Email")?;
+        let renamed = item.encrypt_renamed(&PlaintextSecret::from(secret), &password, None, &new_label, renamed_at)?;
+
+        // the new label must not exist yet, and nothing changes if it does
+        let taken = db.rename_item(item.uid, "Bank", renamed_at, &renamed);
+        assert!(matches!(taken, Err(Error::DuplicateLabel)));
+        assert_eq!(db.item_by_id(item.uid)?, item);
+
+        db.rename_item(item.uid, "Email", renamed_at, &renamed)?;
+
+        let stored = db.item_by_id(item.uid)?;
+        assert_eq!(stored.label, "Email");
+        assert_eq!(stored.last_modified_at, renamed_at);
+        assert_eq!(stored.decryption_input(None).decrypt_and_verify(b"hunter2")?.as_slice(), b"correct horse");
+
+        Ok(())
+    }
+
+    #[test]
+    fn quarantined_items_are_moved_out_with_their_metadata() -> Result<()> {
+        let db = &mut Database::open(":memory:", &DatabaseOptions::default())?;
+        let now = Utc::now();
+        let tampered = db.add_item(AddItemInput {
+            uid: Null,
+            label: &Label::new("Tampered")?,
+            account: Some(&AccountName::new("mallory")?),
+            last_modified_at: now - Days::new(3),
+            encrypted_secret: b"ciphertext",
+            kdf_salt: *b"cXVhcmFudGluZV8x",
+            auth_nonce: *b"cXVhcmFudGluZV9ub25jZV8x",
+            peppered: false,
+            kdf: KdfParams::CURRENT,
+            cipher: CipherId::CURRENT,
+            reprompt: false,
+        })?;
+        let intact = db.add_item(AddItemInput {
+            uid: Null,
+            label: &Label::new("Intact")?,
+            account: None,
+            last_modified_at: now,
+            encrypted_secret: b"other ciphertext",
+            kdf_salt: *b"cXVhcmFudGluZV8y",
+            auth_nonce: *b"cXVhcmFudGluZV9ub25jZV8y",
+            peppered: false,
+            kdf: KdfParams::CURRENT,
+            cipher: CipherId::CURRENT,
+            reprompt: false,
+        })?;
+
+        db.tag_item(tampered.uid, "work")?;
+        db.set_expiry(tampered.uid, Some(now + Days::new(10)))?;
+        db.log_access(tampered.uid)?;
+        db.tag_item(intact.uid, "work")?;
+
+        assert_eq!(db.quarantine_items([tampered.uid], now)?, 1);
+
+        let items = db.all_items()?;
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].uid, intact.uid);
+        assert_eq!(db.item_tags(intact.uid)?, ["work"]);
+        assert!(db.access_history(tampered.uid)?.is_empty());
+
+        let quarantined = db.quarantined_items()?;
+        assert_eq!(quarantined.len(), 1);
+        assert_eq!(quarantined[0].item_uid, tampered.uid);
+        assert_eq!(quarantined[0].label, "Tampered");
+        assert_eq!(quarantined[0].account.as_deref(), Some("mallory"));
+        assert_eq!(quarantined[0].encrypted_secret, b"ciphertext");
+        assert_eq!(quarantined[0].kdf_salt, tampered.kdf_salt);
+        assert_eq!(quarantined[0].quarantined_at, now);
+
+        // the label is free again
+        db.add_item(AddItemInput {
+            uid: Null,
+            label: &Label::new("Tampered")?,
+            account: None,
+            last_modified_at: now,
+            encrypted_secret: b"re-created",
+            kdf_salt: *b"cXVhcmFudGluZV8z",
+            auth_nonce: *b"cXVhcmFudGluZV9ub25jZV8z",
+            peppered: false,
+            kdf: KdfParams::CURRENT,
+            cipher: CipherId::CURRENT,
+            reprompt: false,
+        })?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn deleted_item_takes_its_metadata_along() -> Result<()> {
+        let db = &mut Database::open(":memory:", &DatabaseOptions::default())?;
+        let item = db.add_item(AddItemInput {
+            uid: Null,
+            label: &Label::new("Old forum")?,
+            account: Some(&AccountName::new("anon")?),
+            last_modified_at: Utc::now(),
+            encrypted_secret: b"ciphertext",
+            kdf_salt: *b"ZGVsZXRlZF9zYWx0",
+            auth_nonce: *b"ZGVsZXRlZF9ub25jZV8xMjM0",
+            peppered: false,
+            kdf: KdfParams::CURRENT,
+            cipher: CipherId::CURRENT,
+            reprompt: false,
+        })?;
+
+        db.tag_item(item.uid, "stale")?;
+        db.set_expiry(item.uid, Some(Utc::now()))?;
+        db.log_access(item.uid)?;
+
+        // the foreign keys would make this fail if anything referring to the item remained
+        db.delete_item(item.uid)?;
+
+        assert!(db.all_items()?.is_empty());
+        assert!(db.tag_counts()?.is_empty());
+        assert!(db.expiries()?.is_empty());
+        assert!(!db.label_exists("Old forum")?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn copied_items_keep_their_metadata() -> Result<()> {
+        let source = &mut Database::open(":memory:", &DatabaseOptions::default())?;
+        let target = &mut Database::open(":memory:", &DatabaseOptions::default())?;
+        let expires_at = Utc::now() + Days::new(30);
+        let item = source.add_item(AddItemInput {
+            uid: Null,
+            label: &Label::new("VPN")?,
+            account: Some(&AccountName::new("alice")?),
+            last_modified_at: Utc::now(),
+            encrypted_secret: b"ciphertext",
+            kdf_salt: *b"Y29waWVkX3NhbHRf",
+            auth_nonce: *b"Y29waWVkX25vbmNlXzEyMzQ1",
+            peppered: false,
+            kdf: KdfParams::CURRENT,
+            cipher: CipherId::CURRENT,
+            reprompt: false,
+        })?;
+
+        source.tag_item(item.uid, "work")?;
+        source.set_expiry(item.uid, Some(expires_at))?;
+        source.set_url(item.uid, Some("https://vpn.example.com/"))?;
+        source.set_reprompt(item.uid, true)?;
+
+        let copies = target.copy_items_from(source, source.all_items()?)?;
+        assert_eq!(copies.len(), 1);
+
+        let copy = target.item_by_id(copies[0].uid)?;
+        assert_eq!(copy.label, "VPN");
+        assert_eq!(copy.encrypted_secret, item.encrypted_secret);
+        assert_eq!(copy.kdf_salt, item.kdf_salt);
+        assert!(copy.reprompt);
+        assert_eq!(target.item_tags(copy.uid)?, ["work"]);
+        assert_eq!(target.expiries()?.get(&copy.uid), Some(&expires_at));
+        assert_eq!(target.urls()?.get(&copy.uid).map(String::as_str), Some("https://vpn.example.com/"));
+
+        // copying again fails as a whole, because the label is taken
+        let result = target.copy_items_from(source, source.all_items()?);
+        assert!(matches!(result, Err(Error::DuplicateLabel)));
+        assert_eq!(target.all_items()?.len(), 1);
+
+        assert_eq!(source.delete_items([item.uid])?, 1);
+        assert!(source.all_items()?.is_empty());
+        assert!(source.tag_counts()?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn reprompt_flag_can_be_set() -> Result<()> {
+        let db = Database::open(":memory:", &DatabaseOptions::default())?;
+        let item = db.add_item(AddItemInput {
+            uid: Null,
+            label: &Label::new("Bank")?,
+            account: None,
+            last_modified_at: Utc::now(),
+            encrypted_secret: b"pin code",
+            kdf_salt: *b"cmVwcm9tcHRfc2Fs",
+            auth_nonce: *b"cmVwcm9tcHRfbm9uY2VfMTIz",
+            peppered: false,
+            kdf: KdfParams::CURRENT,
+            cipher: CipherId::CURRENT,
+            reprompt: false,
+        })?;
+        assert!(!item.reprompt);
+
+        db.set_reprompt(item.uid, true)?;
+        assert!(db.item_by_id(item.uid)?.reprompt);
+        assert!(db.item_by_label("Bank")?.is_some_and(|item| item.reprompt));
+
+        db.set_reprompt(item.uid, false)?;
+        assert!(!db.all_items()?[0].reprompt);
+
+        Ok(())
+    }
+
+    #[test]
+    fn pasted_labels_are_normalized() -> Result<()> {
+        let db = Database::open(":memory:", &DatabaseOptions::default())?;
+        let new = db.add_item(AddItemInput {
+            uid: Null,
+            label: &Label::new("Cafe\u{301}\n")?,
+            account: None,
+            last_modified_at: Utc::now(),
+            encrypted_secret: b"espresso",
+            kdf_salt: *b"bm9ybWFsaXplZF9z",
+            auth_nonce: *b"bm9ybWFsaXplZF9ub25jZV8x",
+            peppered: false,
+            kdf: KdfParams::CURRENT,
+            cipher: CipherId::CURRENT,
+            reprompt: false,
+        })?;
+        // stored before labels were normalized, so it must be found as it is
+        let old = db.add_item(AddItemInput {
+            uid: Null,
+            label: &Label::verbatim("Jose\u{301}"),
+            account: None,
+            last_modified_at: Utc::now(),
+            encrypted_secret: b"tequila",
+            kdf_salt: *b"bm9ybWFsaXplZF9v",
+            auth_nonce: *b"bm9ybWFsaXplZF9ub25jZV8y",
+            peppered: false,
+            kdf: KdfParams::CURRENT,
+            cipher: CipherId::CURRENT,
+            reprompt: false,
+        })?;
+
+        assert_eq!(new.label, "Caf\u{e9}");
+        assert!(db.label_exists("Caf\u{e9}")?);
+        assert_eq!(db.item_by_label("Cafe\u{301}")?.map(|item| item.uid), Some(new.uid));
+        assert_eq!(db.item_by_label(" Caf\u{e9}\n")?.map(|item| item.uid), Some(new.uid));
+        assert_eq!(db.item_by_label("Jose\u{301}")?.map(|item| item.uid), Some(old.uid));
+        assert!(db.item_by_label("Jos\u{e9}")?.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn smart_filters_find_untidy_items() -> Result<()> {
+        let db = Database::open(":memory:", &DatabaseOptions::default())?;
+        let now = Utc::now();
+        let old = db.add_item(AddItemInput {
+            uid: Null,
+            label: &Label::new("Old router")?,
+            account: None,
+            last_modified_at: now - Days::new(800),
+            encrypted_secret: b"old ciphertext",
+            kdf_salt: *b"b2xkX3JvdXRlcl9z",
+            auth_nonce: *b"b2xkX3JvdXRlcl9ub25jZV8x",
+            peppered: false,
+            kdf: KdfParams::CURRENT,
+            cipher: CipherId::CURRENT,
+            reprompt: false,
+        })?;
+        let new = db.add_item(AddItemInput {
+            uid: Null,
+            label: &Label::new("Bank")?,
+            account: Some(&AccountName::new("alice")?),
+            last_modified_at: now - Days::new(2),
+            encrypted_secret: b"new ciphertext",
+            kdf_salt: *b"bmV3X2Jhbmtfc2Fs",
+            auth_nonce: *b"bmV3X2Jhbmtfbm9uY2VfMTIz",
+            peppered: false,
+            kdf: KdfParams::CURRENT,
+            cipher: CipherId::CURRENT,
+            reprompt: false,
+        })?;
+        db.tag_item(new.uid, "finance")?;
+        db.log_access(new.uid)?;
+
+        let uids = |filter| -> Result<Vec<u64>> {
+            Ok(db.items_by_filter(filter, now)?.into_iter().map(|item| item.uid).collect())
+        };
+
+        assert_eq!(uids(SmartFilter::NoAccount)?, [old.uid]);
+        assert_eq!(uids(SmartFilter::NoTags)?, [old.uid]);
+        assert_eq!(uids(SmartFilter::NotAccessedForAYear)?, [old.uid]);
+        assert_eq!(uids(SmartFilter::ChangedThisWeek)?, [new.uid]);
+        assert!(uids(SmartFilter::RotationOverdue)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn changing_the_secret_resets_the_rotation() -> Result<()> {
+        let mut db = Database::open(":memory:", &DatabaseOptions::default())?;
+        let now = Utc::now();
+        let item = db.add_item(AddItemInput {
+            uid: Null,
+            label: &Label::new("VPN")?,
+            account: None,
+            last_modified_at: now - Days::new(100),
+            encrypted_secret: b"old ciphertext",
+            kdf_salt: *b"dnBuX3NhbHRfc2Fs",
+            auth_nonce: *b"dnBuX25vbmNlX25vbmNlXzEy",
+            peppered: false,
+            kdf: KdfParams::CURRENT,
+            cipher: CipherId::CURRENT,
+            reprompt: false,
+        })?;
+        let overdue = |db: &Database| -> Result<Vec<u64>> {
+            let items = db.items_by_filter(SmartFilter::RotationOverdue, now)?;
+            Ok(items.into_iter().map(|item| item.uid).collect())
+        };
+
+        db.set_rotation(item.uid, Some(90))?;
+        assert_eq!(overdue(&db)?, [item.uid]);
+
+        // changing the interval keeps the date of the last rotation
+        db.set_rotation(item.uid, Some(120))?;
+        let rotation = db.rotations()?[&item.uid];
+        assert_eq!(rotation.interval_days, 120);
+        assert_eq!(rotation.last_rotated_at, item.last_modified_at);
+        assert!(overdue(&db)?.is_empty());
+
+        db.set_rotation(item.uid, Some(30))?;
+        let output = EncryptionOutput {
+            encrypted_secret: b"new ciphertext".to_vec(),
+            kdf_salt: *b"dnBuX25ld19zYWx0",
+            auth_nonce: *b"dnBuX25ld19ub25jZV9ub25j",
+            peppered: false,
+            kdf: KdfParams::CURRENT,
+            cipher: CipherId::CURRENT,
+        };
+        db.replace_secret(item.uid, now, &output)?;
+        assert_eq!(db.rotations()?[&item.uid].last_rotated_at, now);
+        assert!(overdue(&db)?.is_empty());
+
+        db.set_rotation(item.uid, None)?;
+        assert!(db.rotations()?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn note_labels_are_unique() -> Result<()> {
+        let db = Database::open(":memory:", &DatabaseOptions::default())?;
+        let created_at = NaiveDate::from_ymd_opt(2024, 6, 30).unwrap().and_hms_opt(14, 3, 59).unwrap();
+
+        let label = db.unused_note_label(created_at)?;
+        assert_eq!(label, "Note 2024-06-30 14:03:59");
+
+        db.add_item(AddItemInput {
+            uid: Null,
+            label: &Label::new(&label)?,
+            account: None,
+            last_modified_at: Utc::now(),
+            encrypted_secret: b"recovery codes",
+            kdf_salt: *b"bm90ZV9zYWx0XzEy",
+            auth_nonce: *b"bm90ZV9ub25jZV8xMjM0NTY3",
+            peppered: false,
+            kdf: KdfParams::CURRENT,
+            cipher: CipherId::CURRENT,
+            reprompt: false,
+        })?;
+
+        assert_eq!(db.unused_note_label(created_at)?, "Note 2024-06-30 14:03:59 (2)");
 
         Ok(())
     }
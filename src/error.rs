@@ -5,13 +5,20 @@ use std::io::Error as IoError;
 use std::str::Utf8Error;
 use std::error::Error as StdError;
 use thiserror::Error;
+use serde::Serialize;
 use serde_json::Error as JsonError;
 use argon2::Error as Argon2Error;
 use chacha20poly1305::Error as XChaCha20Poly1305Error;
 use block_padding::UnpadError;
 use crypto_common::InvalidLength;
 use arboard::Error as ClipboardError;
+use qrcode::types::QrError;
+use image::ImageError;
+use rqrr::DeQRError;
+use age::{DecryptError as AgeDecryptError, EncryptError as AgeEncryptError};
 use nanosql::Error as SqlError;
+use crate::i18n;
+use crate::schema::Diagnostics;
 
 
 #[derive(Error)]
@@ -34,9 +41,113 @@ pub enum Error {
     #[error("Passwords do not match")]
     ConfirmPasswordMismatch,
 
+    #[error("An item with this label already exists")]
+    DuplicateLabel,
+
+    #[error("{0} incoming items have the label of a local item; pass `--on-conflict` to decide without asking")]
+    UnresolvedConflicts(usize),
+
+    #[error("Invalid otpauth:// URI: {0}")]
+    InvalidOtpAuthUri(String),
+
+    #[error("Tag name is required and must be a single line")]
+    TagRequired,
+
+    #[error("Input is too large: {size} bytes, but at most {limit} bytes are allowed (see `limits.max_secret_bytes`)")]
+    InputTooLarge {
+        size: usize,
+        limit: usize,
+    },
+
+    #[error("File is too large to import: {size} bytes, but at most {limit} bytes are allowed (see `limits.max_import_bytes`)")]
+    ImportTooLarge {
+        size: u64,
+        limit: u64,
+    },
+
+    #[error("Invalid .steelsaferc:\n{0}")]
+    InvalidConfig(Diagnostics),
+
+    #[error("Invalid `timestamps.format` in .steelsaferc: {0:?} contains an unknown or incomplete `%` specifier")]
+    InvalidTimeFormat(String),
+
+    #[error("Invalid query: {0}")]
+    InvalidQuery(String),
+
+    #[error("Invalid date {0:?}; expected e.g. 2024-12-31, or `never`")]
+    InvalidDate(String),
+
+    #[error("Invalid rotation interval {0:?}; expected a number of days, e.g. 90, or `never`")]
+    InvalidRotationInterval(String),
+
+    #[error("Invalid inventory: {0}")]
+    InvalidInventory(String),
+
+    #[error("Invalid Bitwarden export: {0}")]
+    InvalidBitwardenExport(String),
+
+    #[error("Invalid browser export: {0}")]
+    InvalidBrowserExport(String),
+
+    #[error("The password doesn't decrypt any item in the vault")]
+    NoItemDecrypted,
+
+    #[error("Invalid key share: {0}")]
+    InvalidKeyShare(String),
+
+    #[error("The threshold must be at least 2, and at most the number of shares; got {threshold} of {count}")]
+    KeyShareThreshold {
+        threshold: u8,
+        count: u8,
+    },
+
+    #[error("Can't find the password store; specify its directory")]
+    PassStoreRequired,
+
+    #[error("No key to encrypt the password store for; pass `--recipient`, or run `pass init`")]
+    PassRecipientRequired,
+
+    #[error("gpg failed: {0}")]
+    Gpg(String),
+
+    #[error("Item {0:?} is not a placeholder; its secret has already been filled in")]
+    NotPlaceholder(String),
+
+    #[error("{0}; run `steelsafe --fix-perms` to make it private")]
+    InsecurePermissions(String),
+
+    #[error("Can't make {0} private; its file system (e.g. FAT, or a network share) may not support permissions")]
+    PermissionsUnchangeable(String),
+
+    #[error("Password command failed: {0}")]
+    PasswordCommand(String),
+
+    #[error("{0}; type `help` for the list of commands")]
+    InvalidCommand(String),
+
+    #[error("Operation cancelled")]
+    Cancelled,
+
+    #[error("{skipped} items could not be decrypted with this password, so they still use outdated algorithms")]
+    UpgradeIncomplete {
+        skipped: usize,
+    },
+
+    #[error("Items were copied, but not deleted from the source vault, so these are in both: {}: {source}", .labels.join(", "))]
+    TransferIncomplete {
+        labels: Vec<String>,
+        source: Box<Error>,
+    },
+
     #[error("Account name must be a single line if specified")]
     AccountNameSingleLine,
 
+    #[error("At least one recovery code is required")]
+    RecoveryCodesRequired,
+
+    #[error("Every recovery code has been used")]
+    NoRecoveryCodesLeft,
+
     #[error("No item is currently selected")]
     SelectionRequired,
 
@@ -58,6 +169,133 @@ pub enum Error {
         actual: i64,
     },
 
+    #[error("Not a steelsafe export bundle, or the bundle is corrupt")]
+    InvalidBundle,
+
+    #[error("Not a .ssitem file, or one written by a newer version of steelsafe")]
+    InvalidItemFile,
+
+    #[error("Export bundle format version {actual} is not supported (latest known version is {supported}); please upgrade steelsafe")]
+    BundleVersionTooNew {
+        supported: u32,
+        actual: u32,
+    },
+
+    #[error("Secrets in the export bundle use an unsupported key derivation function or cipher")]
+    UnsupportedEncryption,
+
+    #[error("Item was encrypted with a pepper, but no `pepper_file` is configured")]
+    PepperRequired,
+
+    #[error("Pepper file is corrupt: it must contain exactly 32 bytes")]
+    InvalidPepperFile,
+
+    #[error("No identity file to decrypt shared items with; set `identity_file` in .steelsaferc")]
+    IdentityRequired,
+
+    #[error("The identity file doesn't contain an age X25519 secret key (AGE-SECRET-KEY-1...)")]
+    InvalidIdentity,
+
+    #[error("Invalid public key {0:?}; expected an age X25519 recipient (age1...)")]
+    InvalidRecipient(String),
+
+    #[error("The team already has a member named {0:?}, or one with the same public key")]
+    DuplicateRecipient(String),
+
+    #[error("The team has no member named {0:?}")]
+    RecipientNotFound(String),
+
+    #[error("The team has no members; add one with `steelsafe team add-member`")]
+    NoRecipients,
+
+    #[error("Can't remove the last member of the team while items are shared; unshare them first")]
+    LastRecipient,
+
+    #[error("Item {0:?} is not shared with the team")]
+    NotShared(String),
+
+    #[error("The envelope of the item is not encrypted to public keys")]
+    InvalidEnvelope,
+
+    #[error("Can't open the envelope of the item: {0}")]
+    Envelope(#[from] AgeDecryptError),
+
+    #[error("Can't seal the item for the team: {0}")]
+    Seal(#[from] AgeEncryptError),
+
+    #[error("Invalid audit report: {0}")]
+    InvalidReport(String),
+
+    #[error("The signature of the audit report doesn't match its contents; it has been altered")]
+    ReportSignatureMismatch,
+
+    #[error("The audit report was signed with a different key than the one given")]
+    ReportKeyMismatch,
+
+    #[error("Wordlist {0} must contain at least two distinct words")]
+    InvalidWordlist(String),
+
+    #[error("Vault synchronization is not configured; set `git_sync` in .steelsaferc")]
+    SyncNotConfigured,
+
+    #[error("The TUI can't ask for the sync password; set `git_sync.password_command` in .steelsaferc")]
+    SyncPasswordCommandRequired,
+
+    #[error("Synchronized, but these labels belong to different items on another device: {0}")]
+    SyncConflicts(String),
+
+    #[error("No item is labelled {0:?}")]
+    ItemNotFound(String),
+
+    #[error("The agent is not running; start it with `steelsafe agent`")]
+    AgentNotRunning,
+
+    #[error("Another agent is already running")]
+    AgentAlreadyRunning,
+
+    #[error("The agent can't fulfill the request: {0}")]
+    Agent(String),
+
+    #[cfg(feature = "remote")]
+    #[error("Remote backup is not configured; set `remote_backup` in .steelsaferc")]
+    BackupNotConfigured,
+
+    #[cfg(feature = "remote")]
+    #[error("Remote backup credentials item {0:?} does not exist")]
+    MissingCredentials(String),
+
+    #[error("Network access is disabled by `network: false` in .steelsaferc")]
+    NetworkDisabled,
+
+    #[error("git {command} failed: {message}")]
+    Git {
+        command: String,
+        message: String,
+    },
+
+    #[error("Invalid remote vault location {0:?}; expected `[user@]host:path`")]
+    InvalidRemote(String),
+
+    #[error("ssh {host} failed: {message}")]
+    Ssh {
+        host: String,
+        message: String,
+    },
+
+    #[error("The remote vault was changed since it was opened; your copy was kept at {0}")]
+    RemoteVaultChanged(String),
+
+    #[error("Both vaults are the same replica; merge into a new, empty vault instead of copying it")]
+    SameReplica,
+
+    #[cfg(feature = "serve")]
+    #[error("Refusing to serve the API on {0}: only loopback addresses are allowed")]
+    NonLoopbackListen(String),
+
+    #[cfg(feature = "serve")]
+    #[error("Invalid API request: {0}")]
+    InvalidApiRequest(String),
+
     #[error("Password hashing error: {0}")]
     Argon2(#[from] Argon2Error),
 
@@ -73,6 +311,30 @@ pub enum Error {
     #[error(transparent)]
     Clipboard(#[from] ClipboardError),
 
+    #[error("{program} exited unsuccessfully ({status})")]
+    ClipboardProgram {
+        program: String,
+        status: String,
+    },
+
+    #[error("Can't copy to the clipboard; tried {0}")]
+    ClipboardUnavailable(String),
+
+    #[error("The primary selection is not supported")]
+    PrimarySelectionUnsupported,
+
+    #[error("Secret can't be displayed as a QR code: {0}")]
+    QrCode(#[from] QrError),
+
+    #[error("Can't read image: {0}")]
+    QrImage(#[from] ImageError),
+
+    #[error("No QR code found in the image")]
+    QrCodeNotFound,
+
+    #[error("Can't decode QR code: {0}")]
+    QrDecode(#[from] DeQRError),
+
     #[error("{message}: {source}")]
     Context {
         message: String,
@@ -82,6 +344,122 @@ pub enum Error {
 }
 
 impl Error {
+    /// The message of the error in the language of the user interface.
+    /// Messages missing from the catalog (e.g. those coming from
+    /// underlying libraries) are shown in English.
+    pub fn localized(&self) -> String {
+        let message = self.to_string();
+        i18n::translate(&message).map_or(message, String::from)
+    }
+
+    /// The exit status of the process if this error ends it. Every variant is
+    /// listed explicitly, so that new ones have to be categorized, too.
+    pub const fn exit_status(&self) -> ExitStatus {
+        match self {
+            Error::ItemNotFound(_)
+            | Error::RecipientNotFound(_)
+            | Error::NotShared(_) => ExitStatus::NotFound,
+            #[cfg(feature = "remote")]
+            Error::MissingCredentials(_) => ExitStatus::NotFound,
+
+            Error::XChaCha20Poly1305(_)
+            | Error::Unpad(_)
+            | Error::NoItemDecrypted
+            | Error::Envelope(_)
+            | Error::ReportSignatureMismatch
+            | Error::ReportKeyMismatch => ExitStatus::AuthenticationFailed,
+
+            Error::Io(_)
+            | Error::Context { .. }
+            | Error::Clipboard(_)
+            | Error::ClipboardProgram { .. }
+            | Error::ClipboardUnavailable(_)
+            | Error::PrimarySelectionUnsupported
+            | Error::PasswordCommand(_)
+            | Error::PermissionsUnchangeable(_)
+            | Error::Git { .. }
+            | Error::Gpg(_)
+            | Error::Ssh { .. } => ExitStatus::Io,
+
+            Error::LabelRequired
+            | Error::SecretRequired
+            | Error::EncryptionPasswordRequired
+            | Error::ConfirmPasswordMismatch
+            | Error::DuplicateLabel
+            | Error::UnresolvedConflicts(_)
+            | Error::SyncConflicts(_)
+            | Error::InvalidOtpAuthUri(_)
+            | Error::TagRequired
+            | Error::InputTooLarge { .. }
+            | Error::ImportTooLarge { .. }
+            | Error::InvalidQuery(_)
+            | Error::InvalidDate(_)
+            | Error::InvalidRotationInterval(_)
+            | Error::InvalidInventory(_)
+            | Error::InvalidBitwardenExport(_)
+            | Error::InvalidBrowserExport(_)
+            | Error::InvalidKeyShare(_)
+            | Error::KeyShareThreshold { .. }
+            | Error::PassStoreRequired
+            | Error::PassRecipientRequired
+            | Error::NotPlaceholder(_)
+            | Error::InvalidCommand(_)
+            | Error::AccountNameSingleLine
+            | Error::RecoveryCodesRequired
+            | Error::SelectionRequired
+            | Error::InvalidBundle
+            | Error::InvalidRemote(_)
+            | Error::SameReplica
+            | Error::InvalidRecipient(_)
+            | Error::DuplicateRecipient(_)
+            | Error::NoRecipients
+            | Error::LastRecipient
+            | Error::InvalidEnvelope
+            | Error::InvalidReport(_)
+            | Error::InvalidItemFile
+            | Error::BundleVersionTooNew { .. }
+            | Error::UnsupportedEncryption
+            | Error::Utf8(_)
+            | Error::QrCode(_)
+            | Error::QrImage(_)
+            | Error::QrCodeNotFound
+            | Error::QrDecode(_) => ExitStatus::InvalidInput,
+            #[cfg(feature = "serve")]
+            Error::NonLoopbackListen(_) | Error::InvalidApiRequest(_) => ExitStatus::InvalidInput,
+
+            Error::MissingDatabaseDir
+            | Error::InvalidConfig(_)
+            | Error::InvalidTimeFormat(_)
+            | Error::PepperRequired
+            | Error::InvalidPepperFile
+            | Error::IdentityRequired
+            | Error::InvalidIdentity
+            | Error::InvalidWordlist(_)
+            | Error::InsecurePermissions(_)
+            | Error::SyncNotConfigured
+            | Error::SyncPasswordCommandRequired
+            | Error::NetworkDisabled => ExitStatus::Config,
+            #[cfg(feature = "remote")]
+            Error::BackupNotConfigured => ExitStatus::Config,
+
+            Error::ScreenAlreadyOpen
+            | Error::Cancelled
+            | Error::NoRecoveryCodesLeft
+            | Error::UpgradeIncomplete { .. }
+            | Error::TransferIncomplete { .. }
+            | Error::Json(_)
+            | Error::Db(_)
+            | Error::SchemaVersionMismatch { .. }
+            | Error::AgentNotRunning
+            | Error::AgentAlreadyRunning
+            | Error::Agent(_)
+            | Error::RemoteVaultChanged(_)
+            | Error::Seal(_)
+            | Error::Argon2(_)
+            | Error::InvalidLength(_) => ExitStatus::Failure,
+        }
+    }
+
     pub fn context<E, M>(source: E, message: M) -> Self
     where
         E: StdError + Send + Sync + 'static,
@@ -102,6 +480,68 @@ impl Debug for Error {
 
 pub type Result<T, E = Error> = core::result::Result<T, E>;
 
+/// The exit status of the process upon failure, so that scripts can
+/// tell the causes apart without parsing the error message.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ExitStatus {
+    /// Any error not covered by a more specific status.
+    Failure = 1,
+    /// The requested item does not exist.
+    NotFound = 2,
+    /// The password is wrong, or the data has been tampered with.
+    AuthenticationFailed = 3,
+    /// Reading or writing a file, the clipboard, or an external program failed.
+    Io = 4,
+    /// The input (e.g. a label, a query, or a bundle) is invalid.
+    InvalidInput = 5,
+    /// The configuration is invalid or incomplete.
+    Config = 6,
+    /// The command line arguments are invalid.
+    Usage = 64,
+}
+
+impl ExitStatus {
+    /// A stable identifier of the status, used in machine-readable error output.
+    pub const fn name(self) -> &'static str {
+        match self {
+            ExitStatus::Failure => "failure",
+            ExitStatus::NotFound => "not_found",
+            ExitStatus::AuthenticationFailed => "authentication_failed",
+            ExitStatus::Io => "io",
+            ExitStatus::InvalidInput => "invalid_input",
+            ExitStatus::Config => "config",
+            ExitStatus::Usage => "usage",
+        }
+    }
+}
+
+/// An error as printed by `--json-errors`, on a single line of the standard error.
+#[derive(Clone, Debug, Serialize)]
+pub struct ErrorReport {
+    /// The exit status of the process.
+    pub status: u8,
+    /// The name of the exit status, e.g. `not_found`.
+    pub kind: &'static str,
+    /// The human-readable description of the error, in English.
+    pub message: String,
+}
+
+impl ErrorReport {
+    pub fn new(status: ExitStatus, message: String) -> Self {
+        ErrorReport {
+            status: status as u8,
+            kind: status.name(),
+            message,
+        }
+    }
+}
+
+impl From<ExitStatus> for std::process::ExitCode {
+    fn from(status: ExitStatus) -> Self {
+        std::process::ExitCode::from(status as u8)
+    }
+}
+
 pub trait ResultExt<T> {
     fn context<M>(self, message: M) -> Result<T>
     where
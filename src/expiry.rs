@@ -0,0 +1,162 @@
+//! Reminders about items that have expired, or are about to expire.
+//!
+//! Expiry dates are set with `steelsafe expire`. The TUI shows a banner upon
+//! startup if any item is due, and `steelsafe status` prints the same summary.
+
+use chrono::{DateTime, Utc, Duration};
+use crate::{
+    db::ExpiringItem,
+    vault::Vault,
+    i18n::tr,
+    error::Result,
+};
+
+
+/// Whether an expiry date is due, by the same rules as `ExpiryReport`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ExpiryStatus {
+    /// Neither expired nor expiring within the warning period.
+    Valid,
+    /// Expires within the warning period.
+    Expiring,
+    /// The expiry date has passed.
+    Expired,
+}
+
+impl ExpiryStatus {
+    pub fn of(expires_at: DateTime<Utc>, now: DateTime<Utc>, warning_days: u32) -> Self {
+        if expires_at <= now {
+            ExpiryStatus::Expired
+        } else if expires_at <= now + Duration::days(warning_days.into()) {
+            ExpiryStatus::Expiring
+        } else {
+            ExpiryStatus::Valid
+        }
+    }
+}
+
+/// The items that have expired, or will expire within the warning period.
+#[derive(Clone, Debug)]
+pub struct ExpiryReport {
+    /// Items whose expiry date has passed, the longest expired first.
+    pub expired: Vec<ExpiringItem>,
+    /// Items expiring within the warning period, the soonest first.
+    pub expiring: Vec<ExpiringItem>,
+    /// The length of the warning period.
+    pub warning_days: u32,
+    /// The end of the warning period.
+    pub until: DateTime<Utc>,
+}
+
+impl ExpiryReport {
+    /// Collects the items expiring before `now + warning_days`.
+    pub fn new(db: &dyn Vault, now: DateTime<Utc>, warning_days: u32) -> Result<Self> {
+        let until = now + Duration::days(warning_days.into());
+        let (expired, expiring) = db
+            .expiring_items(until)?
+            .into_iter()
+            .partition(|item| item.expires_at <= now);
+
+        Ok(ExpiryReport { expired, expiring, warning_days, until })
+    }
+
+    /// Returns `true` if no item is due, so there's nothing to report.
+    pub fn is_empty(&self) -> bool {
+        self.expired.is_empty() && self.expiring.is_empty()
+    }
+
+    /// E.g. "2 expired, 1 expiring within 14 days".
+    pub fn summary(&self) -> String {
+        let mut parts = Vec::new();
+
+        if !self.expired.is_empty() {
+            parts.push(tr("{} expired").replacen("{}", &self.expired.len().to_string(), 1));
+        }
+        if !self.expiring.is_empty() {
+            parts.push(
+                tr("{} expiring within {} days")
+                    .replacen("{}", &self.expiring.len().to_string(), 1)
+                    .replacen("{}", &self.warning_days.to_string(), 1)
+            );
+        }
+
+        parts.join(", ")
+    }
+
+    /// A search query matching the reported items. Queries compare calendar
+    /// days, so it also matches items expiring later on the last day.
+    pub fn query(&self) -> String {
+        format!("expires:<={}", self.until.format("%Y-%m-%d"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc, Duration};
+    use nanosql::Null;
+    use crate::config::DatabaseOptions;
+    use crate::crypto::{KdfParams, CipherId};
+    use crate::db::{Database, AddItemInput};
+    use crate::fields::Label;
+    use crate::error::Result;
+    use super::{ExpiryReport, ExpiryStatus};
+
+
+    #[test]
+    fn expired_and_expiring_items_are_reported() -> Result<()> {
+        let now = Utc.with_ymd_and_hms(2024, 6, 30, 12, 0, 0).unwrap();
+        let db = Database::open(":memory:", &DatabaseOptions::default())?;
+        let salts = [*b"ZXhwaXJ5X3NhbHQx", *b"ZXhwaXJ5X3NhbHQy", *b"ZXhwaXJ5X3NhbHQz"];
+        let nonces = [
+            *b"ZXhwaXJ5X25vbmNlXzFfXzFf",
+            *b"ZXhwaXJ5X25vbmNlXzJfXzJf",
+            *b"ZXhwaXJ5X25vbmNlXzNfXzNf",
+        ];
+        let expiries = [Duration::days(-1), Duration::days(3), Duration::days(30)];
+
+        for (i, expires_in) in expiries.into_iter().enumerate() {
+            let item = db.add_item(AddItemInput {
+                uid: Null,
+                label: &Label::new(&format!("item {i}"))?,
+                account: None,
+                last_modified_at: now,
+                encrypted_secret: b"ciphertext",
+                kdf_salt: salts[i],
+                auth_nonce: nonces[i],
+                peppered: false,
+                kdf: KdfParams::CURRENT,
+                cipher: CipherId::CURRENT,
+                reprompt: false,
+            })?;
+            db.set_expiry(item.uid, Some(now + expires_in))?;
+        }
+
+        let report = ExpiryReport::new(&db, now, 14)?;
+        assert_eq!(report.expired.len(), 1);
+        assert_eq!(report.expired[0].label, "item 0");
+        assert_eq!(report.expiring.len(), 1);
+        assert_eq!(report.expiring[0].label, "item 1");
+        assert_eq!(report.summary(), "1 expired, 1 expiring within 14 days");
+        assert_eq!(report.query(), "expires:<=2024-07-14");
+
+        // removing the expiry date removes the reminder
+        db.set_expiry(report.expired[0].uid, None)?;
+        db.set_expiry(report.expiring[0].uid, None)?;
+        assert!(ExpiryReport::new(&db, now, 14)?.is_empty());
+        assert_eq!(db.expiries()?.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn status_agrees_with_report() {
+        let now = Utc.with_ymd_and_hms(2024, 6, 30, 12, 0, 0).unwrap();
+        let status = |days| ExpiryStatus::of(now + Duration::days(days), now, 14);
+
+        assert_eq!(status(-1), ExpiryStatus::Expired);
+        assert_eq!(status(0), ExpiryStatus::Expired);
+        assert_eq!(status(3), ExpiryStatus::Expiring);
+        assert_eq!(status(14), ExpiryStatus::Expiring);
+        assert_eq!(status(30), ExpiryStatus::Valid);
+    }
+}
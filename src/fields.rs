@@ -0,0 +1,248 @@
+//! The fields of an item as types of their own, so that they can't be mixed
+//! up: a label can't be passed where the account name is expected, and the
+//! secret and the master password can't end up in a log message or on the
+//! clipboard by accident.
+//!
+//! Labels and account names are normalized and validated upon construction,
+//! by the same rules everywhere (the TUI, the CLI, and imports). Since they
+//! are authenticated byte by byte, a pasted value with a trailing newline or
+//! decomposed accents must end up the same as the typed one, both in the
+//! database and in the additional data of the secret. The secret and the master
+//! password are zeroized when dropped, their `Debug` impls only show their
+//! length, and they implement neither `Display` nor `Deref<Target = str>`:
+//! their contents must be asked for explicitly, as bytes.
+
+use std::ops::Deref;
+use std::fmt::{self, Display, Formatter};
+use serde::{Serialize, Deserialize};
+use unicode_normalization::UnicodeNormalization;
+use nanosql::{AsSqlTy, ToSql, SqlTy, TyPrim, ToSqlOutput};
+use nanosql::rusqlite::Result as SqliteResult;
+use crate::{
+    memlock::{SecretBytes, SecretString},
+    error::{Error, Result},
+};
+
+
+/// The label of an item: not blank, and a single line.
+///
+/// Deserialized as-is, like `Label::verbatim()`: labels in export bundles
+/// are authenticated along with the secret.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Label(String);
+
+impl Label {
+    /// Normalizes the label, which must then be neither blank nor multi-line.
+    pub fn new(label: &str) -> Result<Self> {
+        let label = normalize(label);
+
+        if label.is_empty() || is_multi_line(&label) {
+            return Err(Error::LabelRequired);
+        }
+
+        Ok(Label(label))
+    }
+
+    /// Wraps the label of an item that is already stored, without validation.
+    /// It's authenticated along with the secret, so it must be used verbatim.
+    pub fn verbatim(label: &str) -> Self {
+        Label(label.to_owned())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Deref for Label {
+    type Target = str;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl Display for Label {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        formatter.write_str(&self.0)
+    }
+}
+
+/// The account name of an item, e.g. a username or an email address: a single line.
+/// Deserialized as-is, like a `Label`.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct AccountName(String);
+
+impl AccountName {
+    /// Normalizes the account name, which must then be a single line.
+    pub fn new(account: &str) -> Result<Self> {
+        let account = normalize(account);
+
+        if is_multi_line(&account) {
+            return Err(Error::AccountNameSingleLine);
+        }
+
+        Ok(AccountName(account))
+    }
+
+    /// Like `new()`, but a blank account name means that there is none.
+    pub fn optional(account: &str) -> Result<Option<Self>> {
+        let account = AccountName::new(account)?;
+        Ok(if account.0.is_empty() { None } else { Some(account) })
+    }
+
+    /// Like `Label::verbatim()`, for the account name of a stored item.
+    pub fn verbatim(account: &str) -> Self {
+        AccountName(account.to_owned())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Deref for AccountName {
+    type Target = str;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl Display for AccountName {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        formatter.write_str(&self.0)
+    }
+}
+
+/// Trims surrounding whitespace (including the newline of a pasted line),
+/// and converts the text to Unicode Normalization Form C.
+pub fn normalize(text: &str) -> String {
+    text.trim().nfc().collect()
+}
+
+fn is_multi_line(text: &str) -> bool {
+    text.contains(['\n', '\r'])
+}
+
+/// The secret of an item before encryption, or after decryption. It may be
+/// empty, e.g. for a placeholder.
+#[derive(Debug)]
+pub struct PlaintextSecret(SecretBytes);
+
+impl PlaintextSecret {
+    /// Copies the secret into a buffer of its own.
+    pub fn new(secret: &[u8]) -> Self {
+        let mut bytes = SecretBytes::with_capacity(secret.len());
+        bytes.as_mut_vec().extend_from_slice(secret);
+        PlaintextSecret(bytes)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<SecretBytes> for PlaintextSecret {
+    fn from(bytes: SecretBytes) -> Self {
+        PlaintextSecret(bytes)
+    }
+}
+
+/// The password that the encryption key of an item is derived from: not empty.
+#[derive(Debug)]
+pub struct MasterPassword(SecretString);
+
+impl MasterPassword {
+    /// Copies the password into a buffer of its own.
+    pub fn new(password: &str) -> Result<Self> {
+        if password.is_empty() {
+            return Err(Error::EncryptionPasswordRequired);
+        }
+
+        Ok(MasterPassword(SecretString::from(password)))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        self.0.as_bytes()
+    }
+}
+
+/// Labels and account names are stored as plain text; they are
+/// only validated when an item is added.
+impl AsSqlTy for Label {
+    const SQL_TY: SqlTy = SqlTy::new(TyPrim::Text);
+
+    type Borrowed<'p> = &'p str;
+}
+
+impl ToSql for Label {
+    fn to_sql(&self) -> SqliteResult<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl AsSqlTy for AccountName {
+    const SQL_TY: SqlTy = SqlTy::new(TyPrim::Text);
+
+    type Borrowed<'p> = &'p str;
+}
+
+impl ToSql for AccountName {
+    fn to_sql(&self) -> SqliteResult<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::{Error, Result};
+    use super::{Label, AccountName, PlaintextSecret, MasterPassword};
+
+
+    #[test]
+    fn labels_and_account_names_are_validated() -> Result<()> {
+        assert_eq!(Label::new(" Bank ")?.as_str(), "Bank");
+        assert!(matches!(Label::new(" \t"), Err(Error::LabelRequired)));
+        assert!(matches!(Label::new("two\nlines"), Err(Error::LabelRequired)));
+        assert!(matches!(Label::new("two\rlines"), Err(Error::LabelRequired)));
+        assert_eq!(Label::verbatim(" ").as_str(), " ");
+
+        assert_eq!(AccountName::new("")?.as_str(), "");
+        assert_eq!(AccountName::new("me@example.com")?.to_string(), "me@example.com");
+        assert!(matches!(AccountName::new("me\nyou"), Err(Error::AccountNameSingleLine)));
+        assert_eq!(AccountName::optional(" \n")?, None);
+        assert_eq!(AccountName::optional("me\n")?, Some(AccountName::verbatim("me")));
+
+        Ok(())
+    }
+
+    #[test]
+    fn pasted_and_typed_values_are_the_same() -> Result<()> {
+        let typed = Label::new("Caf\u{e9}")?;
+        let pasted = Label::new("Cafe\u{301}\r\n")?;
+
+        assert_eq!(pasted, typed);
+        assert_eq!(pasted.as_bytes(), "Caf\u{e9}".as_bytes());
+        assert_eq!(AccountName::new("\tJos\u{e9}\n")?, AccountName::new("Jose\u{301}")?);
+        assert_eq!(super::normalize(" A\u{30a} \n"), "\u{c5}");
+
+        Ok(())
+    }
+
+    #[test]
+    fn secrets_and_passwords_are_not_printed() -> Result<()> {
+        let secret = PlaintextSecret::new(b"correct horse");
+        let password = MasterPassword::new("hunter2")?;
+
+        assert_eq!(secret.as_bytes(), b"correct horse");
+        assert_eq!(password.as_bytes(), b"hunter2");
+        assert!(!format!("{secret:?}").contains("horse"));
+        assert!(!format!("{password:?}").contains("hunter2"));
+        assert!(matches!(MasterPassword::new(""), Err(Error::EncryptionPasswordRequired)));
+
+        Ok(())
+    }
+}
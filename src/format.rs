@@ -0,0 +1,485 @@
+//! The versioned container format of export bundles.
+//!
+//! An export bundle is laid out as follows (all integers are little-endian):
+//!
+//! | Offset  | Size | Contents                                                   |
+//! |---------|------|------------------------------------------------------------|
+//! | 0       | 8    | Magic bytes: `STLSAFE` followed by `0x1a` (ASCII `SUB`)    |
+//! | 8       | 4    | Format version, `u32`                                      |
+//! | 12      | 4    | Length of the header in bytes, `u32`, denoted by `N` below |
+//! | 16      | N    | Header: the UTF-8 JSON serialization of [`BundleHeader`]   |
+//! | 16 + N  | rest | Payload: a UTF-8 JSON array of [`ExportedItem`]s           |
+//!
+//! If the header contains a `payload_encryption` field, then the payload is further
+//! encrypted as a whole with a separate password, using [`crypto::encrypt_stream()`],
+//! with the raw bytes of the header as associated data. This hides the labels and
+//! account names as well, which are otherwise stored in plain text. The field records
+//! the KDF, the cipher and the chunk length, so that readers decrypt bundles written
+//! with older settings, too; only unknown algorithms are rejected.
+//!
+//! Each item describes the key derivation function and the cipher that were used
+//! for encrypting its secret. Secrets are exported exactly as they are stored in
+//! the database (i.e., still encrypted), so that exporting never requires any
+//! passwords, and the bundle is as safe to store as the database itself. Secrets
+//! of peppered items can only be decrypted using the same pepper file that was
+//! used for encrypting them.
+//!
+//! Versioning rules: a reader **must** reject bundles with a version newer than the
+//! latest version it knows about, because newer versions may change the meaning of
+//! existing fields. Conversely, every reader **must** keep supporting all previous
+//! versions, so that old backups remain importable indefinitely. The only version
+//! so far is 1.
+//!
+//! The sync file of git synchronization (see `sync`) uses the same layout, with
+//! its own magic, version, header and payload; [`Container`] reads and writes both.
+
+use std::io::{Read, Write, ErrorKind};
+use serde::{Serialize, Deserialize, de::DeserializeOwned};
+use chrono::{DateTime, Utc};
+use zeroize::Zeroizing;
+use crate::crypto::{
+    self, RECOMMENDED_SALT_LEN, NONCE_LEN, STREAM_CHUNK_LEN,
+    KdfParams, CipherId, DecryptionInput, Pepper,
+};
+use crate::db::{Item, AddItemInput};
+use crate::fields::{Label, AccountName};
+use crate::error::{Error, Result};
+
+
+/// Identifies a steelsafe export bundle. The trailing `0x1a` byte (`^Z`) stops
+/// the accidental printing of binary data when `type`-ing the file on Windows.
+pub const MAGIC: [u8; 8] = *b"STLSAFE\x1a";
+
+/// The version of the format written by this version of steelsafe.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Upper limit on the length of the header, so that a corrupt length
+/// field can't cause an absurdly large allocation.
+const MAX_HEADER_LEN: u32 = 1 << 20;
+
+/// Metadata describing the contents of a bundle.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct BundleHeader {
+    /// The date of the creation of the bundle.
+    pub created_at: DateTime<Utc>,
+    /// The number of items in the payload. Used for detecting truncation.
+    pub item_count: u64,
+    /// If present, the payload is encrypted with a password.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payload_encryption: Option<PayloadEncryption>,
+}
+
+/// Describes how the payload as a whole was encrypted.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct PayloadEncryption {
+    /// The key derivation function that was used for the payload key.
+    pub kdf: KdfParams,
+    /// The cipher that was used in the STREAM construction.
+    pub cipher: CipherId,
+    /// The length of the plaintext chunks, in bytes.
+    pub chunk_len: u64,
+}
+
+impl PayloadEncryption {
+    /// The settings used by [`crypto::encrypt_stream()`].
+    pub const CURRENT: Self = PayloadEncryption {
+        kdf: KdfParams::CURRENT,
+        cipher: CipherId::CURRENT,
+        chunk_len: STREAM_CHUNK_LEN as u64,
+    };
+
+    /// Decrypts a payload encrypted with these settings, which needn't be the
+    /// current ones. Unknown algorithms are already rejected by deserialization.
+    pub fn decrypt<R: Read, W: Write>(
+        &self,
+        password: &[u8],
+        associated_data: &[u8],
+        reader: R,
+        writer: W,
+    ) -> Result<()> {
+        let chunk_len = usize::try_from(self.chunk_len).map_err(|_| Error::UnsupportedEncryption)?;
+        crypto::decrypt_stream(password, associated_data, self.kdf, self.cipher, chunk_len, reader, writer)
+    }
+}
+
+/// The representation of an item in the payload of the bundle.
+/// This is the same as a row of the `Item` table, except for
+/// the unique ID, which is local to the database.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct ExportedItem {
+    pub label: Label,
+    pub account: Option<AccountName>,
+    pub last_modified_at: DateTime<Utc>,
+    pub encrypted_secret: Vec<u8>,
+    pub kdf_salt: [u8; RECOMMENDED_SALT_LEN],
+    pub auth_nonce: [u8; NONCE_LEN],
+    pub peppered: bool,
+    pub kdf: KdfParams,
+    pub cipher: CipherId,
+}
+
+impl ExportedItem {
+    /// Borrows the item as an insertion request, with a fresh unique ID.
+    pub fn as_add_item_input(&self) -> AddItemInput<'_> {
+        AddItemInput {
+            uid: nanosql::Null,
+            label: &self.label,
+            account: self.account.as_ref(),
+            last_modified_at: self.last_modified_at,
+            encrypted_secret: self.encrypted_secret.as_slice(),
+            kdf_salt: self.kdf_salt,
+            auth_nonce: self.auth_nonce,
+            peppered: self.peppered,
+            kdf: self.kdf,
+            cipher: self.cipher,
+            reprompt: false,
+        }
+    }
+
+    /// Borrows the fields needed for decrypting the secret.
+    pub fn decryption_input<'a>(&'a self, pepper: Option<&'a Pepper>) -> DecryptionInput<'a> {
+        DecryptionInput {
+            encrypted_secret: &self.encrypted_secret,
+            kdf_salt: self.kdf_salt,
+            auth_nonce: self.auth_nonce,
+            label: self.label.as_str(),
+            account: self.account.as_deref(),
+            last_modified_at: self.last_modified_at,
+            peppered: self.peppered,
+            kdf: self.kdf,
+            cipher: self.cipher,
+            pepper,
+        }
+    }
+}
+
+impl From<Item> for ExportedItem {
+    fn from(item: Item) -> Self {
+        ExportedItem {
+            label: Label::verbatim(&item.label),
+            account: item.account.as_deref().map(AccountName::verbatim),
+            last_modified_at: item.last_modified_at,
+            encrypted_secret: item.encrypted_secret,
+            kdf_salt: item.kdf_salt,
+            auth_nonce: item.auth_nonce,
+            peppered: item.peppered,
+            kdf: item.kdf,
+            cipher: item.cipher,
+        }
+    }
+}
+
+/// A complete export bundle: the header and the items.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Bundle {
+    pub header: BundleHeader,
+    pub items: Vec<ExportedItem>,
+}
+
+impl Bundle {
+    /// Creates a bundle of the given items, with an unencrypted payload.
+    pub fn new(items: Vec<ExportedItem>) -> Self {
+        Bundle {
+            header: BundleHeader {
+                created_at: Utc::now(),
+                item_count: items.len() as u64,
+                payload_encryption: None,
+            },
+            items,
+        }
+    }
+
+    /// Serializes the bundle using the latest version of the format.
+    ///
+    /// If a `payload_password` is given, the payload is encrypted with it, and the
+    /// `payload_encryption` field of the written header is filled in accordingly.
+    pub fn write_to<W: Write>(&self, writer: W, payload_password: Option<&[u8]>) -> Result<()> {
+        let header = BundleHeader {
+            payload_encryption: payload_password.map(|_| PayloadEncryption::CURRENT),
+            ..self.header.clone()
+        };
+
+        Container::BUNDLE.write(writer, &header, |header, writer| {
+            if let Some(password) = payload_password {
+                let payload = Zeroizing::new(serde_json::to_vec(&self.items)?);
+                crypto::encrypt_stream(password, header, payload.as_slice(), writer)
+            } else {
+                serde_json::to_writer(writer, &self.items).map_err(Into::into)
+            }
+        })
+    }
+
+    /// Deserializes a bundle of any supported version. Returns an error if the
+    /// bundle is of a newer version, or if its secrets can't be decrypted by
+    /// this version of steelsafe.
+    ///
+    /// The `payload_password` callback is only invoked if the payload is encrypted.
+    pub fn read_from<R, F>(reader: R, payload_password: F) -> Result<Self>
+    where
+        R: Read,
+        F: FnOnce() -> Result<Zeroizing<String>>,
+    {
+        let (header, items) = Container::BUNDLE.read(reader, |header: &BundleHeader, raw_header, reader| {
+            let items: Vec<ExportedItem> = match header.payload_encryption {
+                None => serde_json::from_reader(reader)?,
+                Some(encryption) => {
+                    let password = payload_password()?;
+                    let mut payload = Zeroizing::new(Vec::new());
+                    encryption.decrypt(password.as_bytes(), raw_header, reader, &mut *payload)?;
+                    serde_json::from_slice(&payload)?
+                }
+            };
+
+            if items.len() as u64 != header.item_count {
+                return Err(Error::InvalidBundle);
+            }
+
+            Ok(items)
+        })?;
+
+        Ok(Bundle { header, items })
+    }
+}
+
+/// Identifies a file format laid out like export bundles: magic bytes, version,
+/// length-prefixed JSON header, and a payload that is up to the format.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Container {
+    /// The magic bytes at the beginning of the file.
+    pub magic: [u8; 8],
+    /// The version of the format written by this version of steelsafe.
+    /// Files of a newer version are rejected.
+    pub version: u32,
+}
+
+impl Container {
+    /// The container of export bundles.
+    pub const BUNDLE: Self = Container { magic: MAGIC, version: FORMAT_VERSION };
+
+    /// Writes the magic, the version and the header, then lets `write_payload`
+    /// write the payload. It's passed the raw bytes of the header, which are
+    /// the associated data of an encrypted payload.
+    pub fn write<W, H, F>(&self, mut writer: W, header: &H, write_payload: F) -> Result<()>
+    where
+        W: Write,
+        H: Serialize,
+        F: FnOnce(&[u8], &mut W) -> Result<()>,
+    {
+        let header = serde_json::to_vec(header)?;
+        let header_len = u32::try_from(header.len())
+            .ok()
+            .filter(|&len| len <= MAX_HEADER_LEN)
+            .ok_or(Error::InvalidBundle)?;
+
+        writer.write_all(&self.magic)?;
+        writer.write_all(&self.version.to_le_bytes())?;
+        writer.write_all(&header_len.to_le_bytes())?;
+        writer.write_all(&header)?;
+
+        write_payload(&header, &mut writer)?;
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    /// Checks the magic and the version, reads the header, then lets `read_payload`
+    /// read the rest of the file, given the header and its raw bytes.
+    pub fn read<R, H, T, F>(&self, mut reader: R, read_payload: F) -> Result<(H, T)>
+    where
+        R: Read,
+        H: DeserializeOwned,
+        F: FnOnce(&H, &[u8], R) -> Result<T>,
+    {
+        let magic: [u8; 8] = read_array(&mut reader)?;
+
+        if magic != self.magic {
+            return Err(Error::InvalidBundle);
+        }
+
+        let version = u32::from_le_bytes(read_array(&mut reader)?);
+
+        if version == 0 {
+            return Err(Error::InvalidBundle);
+        }
+        if version > self.version {
+            return Err(Error::BundleVersionTooNew {
+                supported: self.version,
+                actual: version,
+            });
+        }
+
+        let header_len = u32::from_le_bytes(read_array(&mut reader)?);
+
+        if header_len > MAX_HEADER_LEN {
+            return Err(Error::InvalidBundle);
+        }
+
+        let mut header_buf = vec![0_u8; header_len as usize];
+        reader.read_exact(&mut header_buf).map_err(map_eof)?;
+
+        let header: H = serde_json::from_slice(&header_buf)?;
+        let payload = read_payload(&header, &header_buf, reader)?;
+
+        Ok((header, payload))
+    }
+}
+
+/// Reads a fixed number of bytes, treating premature EOF as a corrupt file.
+fn read_array<R: Read, const N: usize>(reader: &mut R) -> Result<[u8; N]> {
+    let mut buf = [0_u8; N];
+    reader.read_exact(&mut buf).map_err(map_eof)?;
+    Ok(buf)
+}
+
+fn map_eof(error: std::io::Error) -> Error {
+    if error.kind() == ErrorKind::UnexpectedEof {
+        Error::InvalidBundle
+    } else {
+        error.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use zeroize::Zeroizing;
+    use crate::error::{Error, Result};
+    use super::{Bundle, BundleHeader, ExportedItem, PayloadEncryption, KdfParams, CipherId, Label, AccountName};
+    use super::{MAGIC, FORMAT_VERSION};
+
+
+    fn no_password() -> Result<Zeroizing<String>> {
+        panic!("password requested for unencrypted payload");
+    }
+
+    fn sample_bundle() -> Bundle {
+        Bundle::new(vec![
+            ExportedItem {
+                label: Label::verbatim("first label"),
+                account: Some(AccountName::verbatim("me@example.com")),
+                last_modified_at: Utc::now(),
+                encrypted_secret: b"not really encrypted".to_vec(),
+                kdf_salt: *b"A1s2D3f4G5h6J7k8",
+                auth_nonce: *b"q9W8e7R6t5Y4u3I2o1PzXcVb",
+                peppered: false,
+                kdf: KdfParams::CURRENT,
+                cipher: CipherId::CURRENT,
+            },
+            ExportedItem {
+                label: Label::verbatim("second label"),
+                account: None,
+                last_modified_at: Utc::now(),
+                encrypted_secret: Vec::new(),
+                kdf_salt: *b"m0N9b8V7c6X5z4L3",
+                auth_nonce: *b"kJhGfDsA0987654321qWeRtY",
+                peppered: true,
+                kdf: KdfParams::CURRENT,
+                cipher: CipherId::CURRENT,
+            },
+        ])
+    }
+
+    #[test]
+    fn bundle_round_trip_is_lossless() -> Result<()> {
+        let bundle = sample_bundle();
+        let mut buf = Vec::new();
+
+        bundle.write_to(&mut buf, None)?;
+        assert!(buf.starts_with(&MAGIC));
+
+        let read_back = Bundle::read_from(buf.as_slice(), no_password)?;
+        assert_eq!(read_back, bundle);
+
+        Ok(())
+    }
+
+    #[test]
+    fn encrypted_payload_round_trip_succeeds() -> Result<()> {
+        let bundle = sample_bundle();
+        let password = "payload password";
+        let mut buf = Vec::new();
+
+        bundle.write_to(&mut buf, Some(password.as_bytes()))?;
+
+        // labels must not appear in plain text
+        assert!(!buf.windows(b"first label".len()).any(|w| w == b"first label"));
+
+        let read_back = Bundle::read_from(buf.as_slice(), || Ok(Zeroizing::new(password.into())))?;
+        assert_eq!(read_back.header.payload_encryption, Some(PayloadEncryption::CURRENT));
+        assert_eq!(read_back.items, bundle.items);
+
+        let result = Bundle::read_from(buf.as_slice(), || Ok(Zeroizing::new("wrong".into())));
+        assert!(
+            matches!(result, Err(Error::XChaCha20Poly1305(chacha20poly1305::Error))),
+            "unexpected result: {:#?}",
+            result,
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn newer_version_is_rejected() -> Result<()> {
+        let mut buf = Vec::new();
+        sample_bundle().write_to(&mut buf, None)?;
+
+        let newer_version = FORMAT_VERSION + 1;
+        buf[MAGIC.len()..MAGIC.len() + 4].copy_from_slice(&newer_version.to_le_bytes());
+
+        let result = Bundle::read_from(buf.as_slice(), no_password);
+        assert!(
+            matches!(
+                result,
+                Err(Error::BundleVersionTooNew { supported, actual })
+                    if supported == FORMAT_VERSION && actual == newer_version
+            ),
+            "unexpected result: {:#?}",
+            result,
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn corrupt_bundle_is_rejected() -> Result<()> {
+        let mut buf = Vec::new();
+        sample_bundle().write_to(&mut buf, None)?;
+
+        // wrong magic
+        let mut bad_magic = buf.clone();
+        bad_magic[0] ^= 0xff;
+        let result = Bundle::read_from(bad_magic.as_slice(), no_password);
+        assert!(matches!(result, Err(Error::InvalidBundle)), "unexpected result: {:#?}", result);
+
+        // truncated in the middle of the fixed-size prefix
+        let result = Bundle::read_from(&buf[..10], no_password);
+        assert!(matches!(result, Err(Error::InvalidBundle)), "unexpected result: {:#?}", result);
+
+        Ok(())
+    }
+
+    #[test]
+    fn oversized_kdf_parameters_are_rejected() -> Result<()> {
+        let header = BundleHeader {
+            created_at: Utc::now(),
+            item_count: 0,
+            payload_encryption: Some(PayloadEncryption {
+                kdf: KdfParams::Argon2id { version: 0x13, m_cost: u32::MAX, t_cost: u32::MAX, p_cost: 1 },
+                ..PayloadEncryption::CURRENT
+            }),
+        };
+        let header = serde_json::to_vec(&header)?;
+
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&MAGIC);
+        buf.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        buf.extend_from_slice(&(header.len() as u32).to_le_bytes());
+        buf.extend_from_slice(&header);
+        buf.extend_from_slice(&[0_u8; 64]);
+
+        let result = Bundle::read_from(buf.as_slice(), || Ok(Zeroizing::new("password".into())));
+        assert!(matches!(result, Err(Error::UnsupportedEncryption)), "unexpected result: {:#?}", result);
+
+        Ok(())
+    }
+}
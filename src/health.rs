@@ -0,0 +1,155 @@
+//! Pre-flight checks upon startup: problems that don't prevent using the vault,
+//! but that are better found out about before they bite, such as a clipboard
+//! that can't work, or a backup that is long overdue. The findings are shown
+//! in a panel when the interface is first drawn, until dismissed.
+
+use std::path::PathBuf;
+use chrono::{DateTime, Utc, TimeDelta};
+use crate::{
+    config::Config,
+    db::Database,
+    clipboard,
+    perms::{self, Exposure},
+    i18n::tr,
+    error::Result,
+};
+
+
+/// The smallest terminal in which every dialog fits.
+pub const MIN_TERMINAL_SIZE: (u16, u16) = (80, 24);
+
+/// A problem found by the pre-flight checks.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Finding {
+    /// None of the clipboard backends can work in this session.
+    NoClipboard,
+    /// The terminal is smaller than `MIN_TERMINAL_SIZE`.
+    SmallTerminal { width: u16, height: u16 },
+    /// The terminal can't show colors, so highlights are hard to tell apart.
+    NoColors,
+    /// The database, its journal, or its directory is accessible to other users.
+    Exposed(Exposure),
+    /// A lock file left behind by a program that has exited, e.g. the index
+    /// lock of git, which makes every subsequent synchronization fail.
+    StaleLock(PathBuf),
+    /// The last remote backup is older than the configured interval.
+    BackupOverdue { last: Option<DateTime<Utc>> },
+}
+
+impl Finding {
+    /// What is wrong, and what to do about it, in the current locale.
+    pub fn message(&self) -> String {
+        match self {
+            Finding::NoClipboard => {
+                tr("No clipboard is available; install `wl-copy` or `xclip`, or set `clipboard` in .steelsaferc").into()
+            }
+            Finding::SmallTerminal { width, height } => {
+                tr("The terminal is {} columns by {} lines; some dialogs need {} by {}")
+                    .replacen("{}", &width.to_string(), 1)
+                    .replacen("{}", &height.to_string(), 1)
+                    .replacen("{}", &MIN_TERMINAL_SIZE.0.to_string(), 1)
+                    .replacen("{}", &MIN_TERMINAL_SIZE.1.to_string(), 1)
+            }
+            Finding::NoColors => {
+                tr("The terminal doesn't support colors; the selection may be hard to see").into()
+            }
+            Finding::Exposed(exposure) => {
+                tr("{}; run `steelsafe --fix-perms` to make it private").replacen("{}", &exposure.to_string(), 1)
+            }
+            Finding::StaleLock(path) => {
+                tr("Stale lock file {}; remove it unless git is running").replacen("{}", &path.display().to_string(), 1)
+            }
+            Finding::BackupOverdue { last: None } => {
+                tr("No remote backup has been made yet; run `steelsafe backup`").into()
+            }
+            Finding::BackupOverdue { last: Some(last) } => {
+                tr("The last remote backup is {} days old; run `steelsafe backup`")
+                    .replacen("{}", &(Utc::now() - *last).num_days().to_string(), 1)
+            }
+        }
+    }
+}
+
+/// Runs every check. `terminal` is the size of the terminal and the number of
+/// colors it supports, if the full-screen interface is about to be shown.
+pub fn run(db: &Database, config: &Config, terminal: Option<(u16, u16, u16)>) -> Result<Vec<Finding>> {
+    let mut findings = Vec::new();
+
+    if !clipboard::is_probably_available(&config.clipboard) {
+        findings.push(Finding::NoClipboard);
+    }
+
+    if let Some((width, height, colors)) = terminal {
+        findings.extend(check_terminal(width, height, colors));
+    }
+
+    findings.extend(perms::check(&config.db_file_path()?)?.into_iter().map(Finding::Exposed));
+
+    if let Some(git_sync) = config.git_sync.as_ref() {
+        let lock = git_sync.repo.join(".git").join("index.lock");
+
+        if lock.exists() {
+            findings.push(Finding::StaleLock(lock));
+        }
+    }
+
+    if let Some(remote_backup) = config.remote_backup.as_ref() {
+        let interval_days = remote_backup.interval_days.unwrap_or(1);
+        findings.extend(check_backup(db.last_remote_backup()?, interval_days, Utc::now()));
+    }
+
+    Ok(findings)
+}
+
+fn check_terminal(width: u16, height: u16, colors: u16) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let (min_width, min_height) = MIN_TERMINAL_SIZE;
+
+    if width < min_width || height < min_height {
+        findings.push(Finding::SmallTerminal { width, height });
+    }
+    // the themes use the 8 basic colors and their bright variants
+    if colors < 8 {
+        findings.push(Finding::NoColors);
+    }
+
+    findings
+}
+
+/// A backup is overdue once the interval has passed for the second time, so
+/// that running `steelsafe backup --if-due` daily doesn't trigger the warning.
+fn check_backup(last: Option<DateTime<Utc>>, interval_days: u32, now: DateTime<Utc>) -> Option<Finding> {
+    let overdue = match last {
+        Some(last) => now - last > TimeDelta::days(2 * i64::from(interval_days)),
+        None => true,
+    };
+
+    overdue.then_some(Finding::BackupOverdue { last })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use super::{Finding, check_terminal, check_backup};
+
+
+    #[test]
+    fn small_and_colorless_terminals_are_reported() {
+        assert!(check_terminal(120, 40, 256).is_empty());
+        assert!(check_terminal(80, 24, 8).is_empty());
+        assert_eq!(
+            check_terminal(79, 40, 2),
+            [Finding::SmallTerminal { width: 79, height: 40 }, Finding::NoColors],
+        );
+    }
+
+    #[test]
+    fn backups_are_overdue_after_twice_the_interval() {
+        let last = Utc.with_ymd_and_hms(2024, 3, 1, 12, 0, 0).unwrap();
+        let now = Utc.with_ymd_and_hms(2024, 3, 8, 12, 0, 0).unwrap();
+
+        assert_eq!(check_backup(Some(last), 7, now), None);
+        assert_eq!(check_backup(Some(last), 3, now), Some(Finding::BackupOverdue { last: Some(last) }));
+        assert_eq!(check_backup(None, 7, now), Some(Finding::BackupOverdue { last: None }));
+    }
+}
@@ -0,0 +1,485 @@
+//! Translations of the user interface.
+//!
+//! Messages are looked up gettext-style: the English text is the key, and it
+//! is also what's shown if the catalog of the current locale doesn't contain it.
+//! Messages with a parameter contain a `{}` placeholder, which must be kept in
+//! the translation, and filled in by the caller using `str::replacen()`.
+//!
+//! The locale is selected once, at startup, by the `locale` setting in the
+//! config, or else by the usual `LC_ALL`, `LC_MESSAGES`, and `LANG` variables.
+//! Until then (e.g. in tests), English is used.
+
+use std::env;
+use std::sync::OnceLock;
+use serde::Deserialize;
+
+
+/// A supported language of the user interface.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Locale {
+    #[default]
+    En,
+    De,
+}
+
+impl Locale {
+    /// Selects the locale based on the environment, falling back to English
+    /// if the language is not specified or it is not supported.
+    fn from_env() -> Self {
+        let lang = ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .into_iter()
+            .filter_map(|name| env::var(name).ok())
+            .find(|value| !value.is_empty())
+            .unwrap_or_default();
+
+        Self::from_lang(&lang)
+    }
+
+    /// Parses a POSIX locale name such as `de_AT.UTF-8`.
+    fn from_lang(lang: &str) -> Self {
+        let language = lang.split(['_', '.', '@', '-']).next().unwrap_or_default();
+
+        match language.to_ascii_lowercase().as_str() {
+            "de" => Locale::De,
+            _ => Locale::En,
+        }
+    }
+
+    fn catalog(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Locale::En => &[],
+            Locale::De => GERMAN,
+        }
+    }
+}
+
+static LOCALE: OnceLock<Locale> = OnceLock::new();
+
+/// Selects the locale of the process. `configured` takes precedence over
+/// the environment. Only the first call has an effect.
+pub fn init(configured: Option<Locale>) {
+    let _ = LOCALE.set(configured.unwrap_or_else(Locale::from_env));
+}
+
+/// The locale selected by `init()`, or English if it hasn't been called.
+pub fn locale() -> Locale {
+    LOCALE.get().copied().unwrap_or_default()
+}
+
+/// Translates a message into the current locale.
+pub fn tr(msgid: &'static str) -> &'static str {
+    translate(msgid).unwrap_or(msgid)
+}
+
+/// Translates a message not known at compile time (e.g. the display of an
+/// error). Returns `None` if the current catalog doesn't contain it.
+pub fn translate(message: &str) -> Option<&'static str> {
+    locale()
+        .catalog()
+        .iter()
+        .find_map(|&(msgid, msgstr)| (msgid == message).then_some(msgstr))
+}
+
+const GERMAN: &[(&str, &str)] = &[
+    // main table
+    ("[C]opy secret", "[C] Geheimnis kopieren"),
+    ("[P]rimary selection", "[P] Primäre Auswahl"),
+    ("[F]ind", "[F] Suchen"),
+    ("[1] First", "[1] Erster"),
+    ("[0] Last", "[0] Letzter"),
+    ("['] Jump to label", "['] Zum Titel springen"),
+    ("Jump to", "Springen zu"),
+    ("[N]ew item", "[N] Neuer Eintrag"),
+    ("<Shift+N> Duplicate", "<Umschalt+N> Duplizieren"),
+    ("[M]emo", "[M] Notiz"),
+    ("[B]ackup codes", "[B] Wiederherstellungscodes"),
+    ("[I]nfo", "[I] Info"),
+    ("[.] Details", "[.] Details"),
+    ("[A]ccess log", "[A] Zugriffsprotokoll"),
+    ("Q[R] code", "[R] QR-Code"),
+    ("[T]ags", "[T] Tags"),
+    ("[W]rite to file", "[W] In Datei schreiben"),
+    ("<Space> Select", "<Leertaste> Auswählen"),
+    ("{} selected", "{} ausgewählt"),
+    ("[W]rite bundle", "[W] Paket schreiben"),
+    ("<Esc> Clear", "<Esc> Auswahl aufheben"),
+    ("[V]iew columns", "[V] Spalten"),
+    ("Fi[L]ters", "[L] Filter"),
+    ("[H]ide names", "[H] Namen verbergen"),
+    ("Privacy mode", "Privatsphäre-Modus"),
+    ("Locked", "Gesperrt"),
+    ("Press any key to unlock", "Zum Entsperren eine beliebige Taste drücken"),
+    ("Press any key, then enter the password", "Eine beliebige Taste drücken, dann das Passwort eingeben"),
+    ("[U]pgrade crypto", "[U] Verschlüsselung erneuern"),
+    ("[Q]uit", "[Q] Beenden"),
+    ("<^C> Quit", "<^C> Beenden"),
+    ("Title", "Titel"),
+    ("Username or account", "Benutzername oder Konto"),
+    ("Modified at", "Geändert am"),
+    ("Tags", "Tags"),
+    ("Codes left", "Codes übrig"),
+    ("Expires", "Läuft ab"),
+    ("expired", "abgelaufen"),
+    ("expiring", "läuft ab"),
+    ("Recovery codes", "Wiederherstellungscodes"),
+    ("{} of {} left", "{} von {} übrig"),
+    ("[U]se next code", "[U] Nächsten Code verwenden"),
+    ("Always ask for password", "Passwort immer abfragen"),
+    ("[P]assword always", "[P] Passwort immer"),
+    ("[O]pen note", "[O] Notiz öffnen"),
+    ("[R]ename", "[R] Umbenennen"),
+    ("New title", "Neuer Titel"),
+    ("Note", "Notiz"),
+    ("yes", "ja"),
+    ("no", "nein"),
+    ("local", "lokal"),
+
+    // relative timestamps
+    ("just now", "gerade eben"),
+    ("1 minute ago", "vor 1 Minute"),
+    ("{} minutes ago", "vor {} Minuten"),
+    ("1 hour ago", "vor 1 Stunde"),
+    ("{} hours ago", "vor {} Stunden"),
+    ("1 day ago", "vor 1 Tag"),
+    ("{} days ago", "vor {} Tagen"),
+
+    // dialogs
+    ("Error", "Fehler"),
+    ("<Esc> Close", "<Esc> Schließen"),
+    ("<Esc> Cancel", "<Esc> Abbrechen"),
+    ("Vault statistics", "Tresorstatistik"),
+    ("Number of items", "Anzahl der Einträge"),
+    ("Items without account", "Einträge ohne Konto"),
+    ("Oldest modification", "Älteste Änderung"),
+    ("Newest modification", "Neueste Änderung"),
+    ("Database file size", "Größe der Datenbank"),
+    ("Size before compaction", "Größe vor Komprimierung"),
+    ("[K] Compact", "[K] Komprimieren"),
+    ("Schema version", "Schemaversion"),
+    ("Expired items", "Abgelaufene Einträge"),
+    ("Rotation overdue", "Wechsel überfällig"),
+    ("Weak secrets", "Schwache Geheimnisse"),
+    ("Reused secrets", "Doppelte Geheimnisse"),
+    ("Secrets not decrypted", "Nicht entschlüsselt"),
+    ("Items per tag", "Einträge nach Tags"),
+    ("[U] Check secrets", "[U] Geheimnisse prüfen"),
+    ("Warnings", "Warnungen"),
+    ("<Esc> Dismiss", "<Esc> Ausblenden"),
+    ("Item exported", "Eintrag exportiert"),
+    ("Items exported", "Einträge exportiert"),
+    ("Exported to {}", "Exportiert nach {}"),
+    (
+        "No clipboard is available; install `wl-copy` or `xclip`, or set `clipboard` in .steelsaferc",
+        "Keine Zwischenablage verfügbar; `wl-copy` oder `xclip` installieren oder `clipboard` in .steelsaferc setzen",
+    ),
+    (
+        "The terminal is {} columns by {} lines; some dialogs need {} by {}",
+        "Das Terminal hat {} Spalten und {} Zeilen; manche Dialoge brauchen {} mal {}",
+    ),
+    (
+        "The terminal doesn't support colors; the selection may be hard to see",
+        "Das Terminal unterstützt keine Farben; die Auswahl ist eventuell schwer zu erkennen",
+    ),
+    (
+        "{}; run `steelsafe --fix-perms` to make it private",
+        "{}; mit `steelsafe --fix-perms` den Zugriff beschränken",
+    ),
+    (
+        "Stale lock file {}; remove it unless git is running",
+        "Verwaiste Sperrdatei {}; löschen, sofern git nicht läuft",
+    ),
+    (
+        "No remote backup has been made yet; run `steelsafe backup`",
+        "Noch kein Remote-Backup erstellt; `steelsafe backup` ausführen",
+    ),
+    (
+        "The last remote backup is {} days old; run `steelsafe backup`",
+        "Das letzte Remote-Backup ist {} Tage alt; `steelsafe backup` ausführen",
+    ),
+    ("Discard changes?", "Änderungen verwerfen?"),
+    ("[Y]es", "[Y] Ja"),
+    ("[N]o", "[N] Nein"),
+    ("The new item has unsaved input.", "Der neue Eintrag ist nicht gespeichert."),
+    ("The new item has unsaved input. Quit anyway?", "Der neue Eintrag ist nicht gespeichert. Trotzdem beenden?"),
+    ("Access log: {}", "Zugriffsprotokoll: {}"),
+    ("Access logging is disabled.", "Das Zugriffsprotokoll ist deaktiviert."),
+    ("Set `access_log` in the config to enable it.", "Aktivierbar mit `access_log` in der Konfig."),
+    ("Last accessed", "Letzter Zugriff"),
+    ("Number of accesses", "Anzahl der Zugriffe"),
+    ("never", "nie"),
+    ("Details", "Details"),
+    ("Columns", "Spalten"),
+    ("<Space> Show/hide", "<Leertaste> Ein-/ausblenden"),
+    ("Filters", "Filter"),
+    ("Commands", "Befehle"),
+    ("Type to search commands", "Tippen, um Befehle zu suchen"),
+    ("<Enter> Run", "<Enter> Ausführen"),
+    ("No matching commands", "Keine passenden Befehle"),
+    ("Copy secret", "Geheimnis kopieren"),
+    ("Copy to primary selection", "In die primäre Auswahl kopieren"),
+    ("Show QR code", "QR-Code anzeigen"),
+    ("Find items", "Einträge suchen"),
+    ("Select first item", "Ersten Eintrag auswählen"),
+    ("Select last item", "Letzten Eintrag auswählen"),
+    ("Jump to item by label", "Per Titel zu Eintrag springen"),
+    ("New item", "Neuer Eintrag"),
+    ("Duplicate item", "Eintrag duplizieren"),
+    ("New quick note", "Neue Notiz"),
+    ("Add recovery codes", "Wiederherstellungscodes hinzufügen"),
+    ("Show item details", "Details des Eintrags anzeigen"),
+    ("Show access log", "Zugriffsprotokoll anzeigen"),
+    ("Manage tags", "Tags verwalten"),
+    ("Export item to file", "Eintrag in Datei exportieren"),
+    ("Select item for export", "Eintrag für den Export auswählen"),
+    ("Show or hide columns", "Spalten ein- oder ausblenden"),
+    ("Filter items", "Einträge filtern"),
+    ("Toggle privacy mode", "Privatsphäre-Modus umschalten"),
+    ("Upgrade encryption", "Verschlüsselung aktualisieren"),
+    ("Quit", "Beenden"),
+    ("<Enter> Apply", "<Enter> Anwenden"),
+    ("All items", "Alle Einträge"),
+    ("No account", "Ohne Konto"),
+    ("No tags", "Ohne Tags"),
+    ("Not used for a year", "Seit einem Jahr nicht verwendet"),
+    ("Added or changed this week", "Diese Woche hinzugefügt oder geändert"),
+    ("Overdue for rotation", "Überfällig für Passwortwechsel"),
+    ("No tags yet.", "Noch keine Tags."),
+    ("Use `steelsafe tag` to tag items.", "Tags vergeben mit `steelsafe tag`."),
+    ("Items", "Einträge"),
+    ("Remove {} from all items? [Y]es / [N]o", "{} von allen Einträgen entfernen? [Y] Ja / [N] Nein"),
+    ("<Enter> Rename", "<Enter> Umbenennen"),
+    ("[R]ename or merge", "[R] Umbenennen oder zusammenführen"),
+    ("[D]elete", "[D] Löschen"),
+    ("New name (an existing name merges)", "Neuer Name (bestehende werden zusammengeführt)"),
+    ("QR code: {}", "QR-Code: {}"),
+    ("{} expired", "{} abgelaufen"),
+    ("{} expiring within {} days", "{} laufen in {} Tagen ab"),
+    ("[E] Show", "[E] Anzeigen"),
+    ("[X] Dismiss", "[X] Ausblenden"),
+    ("Upgrading encryption", "Verschlüsselung wird erneuert"),
+    ("Checking secrets", "Geheimnisse werden geprüft"),
+    ("Synchronizing", "Wird synchronisiert"),
+    ("Cancelling…", "Wird abgebrochen…"),
+    ("Decrypting…", "Wird entschlüsselt…"),
+    ("Encrypting…", "Wird verschlüsselt…"),
+
+    // new item
+    ("New secret item", "Neuer geheimer Eintrag"),
+    ("New note", "Neue Notiz"),
+    ("New recovery codes", "Neue Wiederherstellungscodes"),
+    ("Recovery codes (one per line)", "Wiederherstellungscodes (einer pro Zeile)"),
+    ("<Enter> Save", "<Enter> Speichern"),
+    ("<^G> Generate", "<^G> Generieren"),
+    ("<^O> Read QR image", "<^O> QR-Bild lesen"),
+    ("<^H> Show secret", "<^H> Geheimnis zeigen"),
+    ("<^H> Hide secret", "<^H> Geheimnis verbergen"),
+    ("<^E> Show encr passwd", "<^E> Passwort zeigen"),
+    ("<^E> Hide encr passwd", "<^E> Passwort verbergen"),
+    ("Title or label", "Titel oder Bezeichnung"),
+    ("Secret (to be stored)", "Geheimnis (wird gespeichert)"),
+    ("Encryption (master) password", "Verschlüsselungs-(Master-)Passwort"),
+    ("Confirm master password", "Master-Passwort bestätigen"),
+    ("Required", "Erforderlich"),
+    ("Optional", "Optional"),
+    ("Generate", "Generieren"),
+    ("<Enter> Insert", "<Enter> Einfügen"),
+    ("{} random characters", "{} zufällige Zeichen"),
+    ("{} long words", "{} lange Wörter"),
+    ("{} short words", "{} kurze Wörter"),
+    ("{} pronounceable characters", "{} aussprechbare Zeichen"),
+    ("{} bits", "{} Bit"),
+    ("Policy: {}", "Richtlinie: {}"),
+    ("Shorter than {} characters", "Kürzer als {} Zeichen"),
+    ("No lowercase letter", "Kein Kleinbuchstabe"),
+    ("No uppercase letter", "Kein Großbuchstabe"),
+    ("No digit", "Keine Ziffer"),
+    ("No symbol", "Kein Sonderzeichen"),
+    ("Banned characters: {}", "Verbotene Zeichen: {}"),
+
+    // password entry
+    ("Enter decryption (master) password", "Entschlüsselungs-(Master-)Passwort eingeben"),
+    ("Enter new bundle password", "Neues Paketpasswort eingeben"),
+    ("Confirm bundle password", "Paketpasswort bestätigen"),
+    ("<^H> Show password", "<^H> Passwort zeigen"),
+    ("<^H> Hide password", "<^H> Passwort verbergen"),
+    ("Too many failed attempts; retry in {}s", "Zu viele Fehlversuche; erneut in {} s"),
+    ("Wrong password (attempt {})", "Falsches Passwort (Versuch {})"),
+    ("<Enter> OK", "<Enter> OK"),
+    ("Caps Lock is on", "Feststelltaste ist aktiv"),
+    ("Passwords can't have line breaks", "Passwörter können keine Zeilenumbrüche enthalten"),
+    ("Very weak password", "Sehr schwaches Passwort"),
+    ("Weak password", "Schwaches Passwort"),
+    ("Fair password", "Mittelstarkes Passwort"),
+    ("Strong password", "Starkes Passwort"),
+
+    // conflicts
+    ("Conflict {} of {}", "Konflikt {} von {}"),
+    ("Local item", "Lokaler Eintrag"),
+    ("Incoming item", "Eingehender Eintrag"),
+    ("Result", "Ergebnis"),
+    ("Encrypted with pepper", "Mit Pepper verschlüsselt"),
+    ("<↑↓> Select", "<↑↓> Auswählen"),
+    ("[L] Keep local", "[L] Lokalen behalten"),
+    ("[I] Take incoming", "[I] Eingehenden übernehmen"),
+    ("[B] Keep both", "[B] Beide behalten"),
+    ("<Shift> For all", "<Umschalt> Für alle"),
+    ("Keep local item", "Lokalen Eintrag behalten"),
+    ("Take incoming item", "Eingehenden Eintrag übernehmen"),
+    ("Keep both items", "Beide Einträge behalten"),
+    ("The local item stays as it is.", "Der lokale Eintrag bleibt unverändert."),
+    ("The incoming item is not imported.", "Der eingehende Eintrag wird nicht importiert."),
+    (
+        "The incoming change is undone in the other vault.",
+        "Die eingehende Änderung wird im anderen Tresor rückgängig gemacht.",
+    ),
+    ("The incoming item replaces the local one.", "Der eingehende Eintrag ersetzt den lokalen."),
+    (
+        "The tags, expiry date and URL of the local item are kept.",
+        "Tags, Ablaufdatum und URL des lokalen Eintrags bleiben erhalten.",
+    ),
+    (
+        "The incoming item is added under a new title:",
+        "Der eingehende Eintrag wird unter einem neuen Titel hinzugefügt:",
+    ),
+    ("Its password is asked for once you press Enter.", "Sein Passwort wird nach Drücken von Enter abgefragt."),
+
+    // find
+    ("Search term", "Suchbegriff"),
+    ("<Enter> Focus secrets", "<Enter> Zur Liste"),
+    ("<Esc> Exit search", "<Esc> Suche beenden"),
+    ("1 match", "1 Treffer"),
+    ("{} matches", "{} Treffer"),
+    ("No items match", "Keine passenden Einträge"),
+    ("e.g. bank account:alice tag:work modified:>2024-01-01", "z. B. bank account:alice tag:work modified:>2024-01-01"),
+
+    // errors
+    ("Can't find database directory", "Datenbankverzeichnis nicht gefunden"),
+    ("Label is required and must be a single line", "Titel ist erforderlich und muss einzeilig sein"),
+    ("Secret is required", "Geheimnis ist erforderlich"),
+    (
+        "Encryption (master) password is required and must be a single line",
+        "Verschlüsselungs-(Master-)Passwort ist erforderlich und muss einzeilig sein",
+    ),
+    ("Passwords do not match", "Passwörter stimmen nicht überein"),
+    ("An item with this label already exists", "Ein Eintrag mit diesem Titel existiert bereits"),
+    ("Tag name is required and must be a single line", "Tag-Name ist erforderlich und muss einzeilig sein"),
+    ("Account name must be a single line if specified", "Kontoname muss einzeilig sein"),
+    ("At least one recovery code is required", "Mindestens ein Wiederherstellungscode ist erforderlich"),
+    ("Every recovery code has been used", "Alle Wiederherstellungscodes wurden verwendet"),
+    ("No item is currently selected", "Kein Eintrag ausgewählt"),
+    ("Operation cancelled", "Vorgang abgebrochen"),
+    ("No QR code found in the image", "Kein QR-Code im Bild gefunden"),
+    (
+        "Not a steelsafe export bundle, or the bundle is corrupt",
+        "Kein Steelsafe-Exportpaket, oder das Paket ist beschädigt",
+    ),
+    (
+        "Not a .ssitem file, or one written by a newer version of steelsafe",
+        "Keine .ssitem-Datei, oder eine von einer neueren Steelsafe-Version",
+    ),
+    (
+        "Item was encrypted with a pepper, but no `pepper_file` is configured",
+        "Eintrag wurde mit Pepper verschlüsselt, aber `pepper_file` ist nicht konfiguriert",
+    ),
+    (
+        "Pepper file is corrupt: it must contain exactly 32 bytes",
+        "Pepper-Datei ist beschädigt: sie muss genau 32 Bytes enthalten",
+    ),
+    (
+        "Vault synchronization is not configured; set `git_sync` in .steelsaferc",
+        "Synchronisierung ist nicht konfiguriert; `git_sync` in .steelsaferc setzen",
+    ),
+    (
+        "The TUI can't ask for the sync password; set `git_sync.password_command` in .steelsaferc",
+        "Die TUI kann nicht nach dem Synchronisierungspasswort fragen; `git_sync.password_command` in .steelsaferc setzen",
+    ),
+    (
+        "The agent is not running; start it with `steelsafe agent`",
+        "Der Agent läuft nicht; starten mit `steelsafe agent`",
+    ),
+    ("Another agent is already running", "Ein anderer Agent läuft bereits"),
+    (
+        "Remote backup is not configured; set `remote_backup` in .steelsaferc",
+        "Remote-Backup ist nicht konfiguriert; `remote_backup` in .steelsaferc setzen",
+    ),
+    (
+        "Can't find the password store; specify its directory",
+        "Passwortspeicher nicht gefunden; sein Verzeichnis angeben",
+    ),
+    (
+        "No key to encrypt the password store for; pass `--recipient`, or run `pass init`",
+        "Kein Schlüssel zum Verschlüsseln des Passwortspeichers; `--recipient` angeben oder `pass init` ausführen",
+    ),
+    (
+        "The password doesn't decrypt any item in the vault",
+        "Das Passwort entschlüsselt keinen Eintrag im Tresor",
+    ),
+    (
+        "No identity file to decrypt shared items with; set `identity_file` in .steelsaferc",
+        "Keine Identitätsdatei zum Entschlüsseln geteilter Einträge; `identity_file` in .steelsaferc setzen",
+    ),
+    (
+        "The identity file doesn't contain an age X25519 secret key (AGE-SECRET-KEY-1...)",
+        "Die Identitätsdatei enthält keinen geheimen age-X25519-Schlüssel (AGE-SECRET-KEY-1...)",
+    ),
+    (
+        "The team has no members; add one with `steelsafe team add-member`",
+        "Das Team hat keine Mitglieder; mit `steelsafe team add-member` eines hinzufügen",
+    ),
+    (
+        "Can't remove the last member of the team while items are shared; unshare them first",
+        "Das letzte Teammitglied kann nicht entfernt werden, solange Einträge geteilt sind; zuerst die Freigabe aufheben",
+    ),
+    (
+        "The envelope of the item is not encrypted to public keys",
+        "Der Umschlag des Eintrags ist nicht mit öffentlichen Schlüsseln verschlüsselt",
+    ),
+    (
+        "The signature of the audit report doesn't match its contents; it has been altered",
+        "Die Signatur des Prüfberichts passt nicht zu seinem Inhalt; er wurde verändert",
+    ),
+    (
+        "The audit report was signed with a different key than the one given",
+        "Der Prüfbericht wurde mit einem anderen als dem angegebenen Schlüssel signiert",
+    ),
+    (
+        "Both vaults are the same replica; merge into a new, empty vault instead of copying it",
+        "Beide Tresore sind dasselbe Replikat; in einen neuen, leeren Tresor zusammenführen, statt zu kopieren",
+    ),
+    (
+        "Network access is disabled by `network: false` in .steelsaferc",
+        "Netzwerkzugriff ist durch `network: false` in .steelsaferc deaktiviert",
+    ),
+    (
+        "Encryption, decryption, or authentication error",
+        "Fehler bei Verschlüsselung, Entschlüsselung oder Authentifizierung",
+    ),
+    ("Invalid padding in decrypted secret", "Ungültiges Padding im entschlüsselten Geheimnis"),
+];
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use super::{Locale, GERMAN};
+
+
+    #[test]
+    fn catalog_is_consistent() {
+        let mut msgids = HashSet::new();
+
+        for &(msgid, msgstr) in GERMAN {
+            assert!(msgids.insert(msgid), "duplicate message {msgid:?}");
+            assert_eq!(
+                msgid.matches("{}").count(),
+                msgstr.matches("{}").count(),
+                "placeholders of {msgid:?} don't match",
+            );
+        }
+
+        assert_eq!(Locale::from_lang("de_AT.UTF-8"), Locale::De);
+        assert_eq!(Locale::from_lang("en_US.UTF-8"), Locale::En);
+        assert_eq!(Locale::from_lang("C"), Locale::En);
+        assert_eq!(Locale::from_lang(""), Locale::En);
+    }
+}
@@ -0,0 +1,79 @@
+//! Where the user interface gets its events from.
+//!
+//! The TUI doesn't read the terminal directly, but asks an `EventSource`
+//! for the next event instead, so that tests can drive it with scripted
+//! events, without a terminal.
+
+use std::time::Duration;
+use ratatui::crossterm::event::{self, Event};
+use crate::error::Result;
+
+
+/// Supplies terminal (keyboard, mouse, paste, resize) events.
+pub trait EventSource {
+    /// Waits at most `timeout` for the next event.
+    /// Returns `None` if there was no event in the meantime.
+    fn next_event(&mut self, timeout: Duration) -> Result<Option<Event>>;
+}
+
+/// The events of the actual terminal, as reported by `crossterm`.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct TerminalEvents;
+
+impl EventSource for TerminalEvents {
+    fn next_event(&mut self, timeout: Duration) -> Result<Option<Event>> {
+        if event::poll(timeout)? {
+            Ok(Some(event::read()?))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+/// Hands out pre-recorded events one by one, without ever waiting.
+/// Once they run out, it behaves as if the user were idle.
+#[cfg(test)]
+#[derive(Clone, Default, Debug)]
+pub struct ScriptedEvents {
+    events: std::collections::VecDeque<Event>,
+}
+
+#[cfg(test)]
+impl ScriptedEvents {
+    /// Appends an event to the script.
+    pub fn push(&mut self, event: Event) -> &mut Self {
+        self.events.push_back(event);
+        self
+    }
+
+    /// Appends a key press without modifiers.
+    pub fn key(&mut self, code: event::KeyCode) -> &mut Self {
+        self.push(Event::Key(event::KeyEvent::from(code)))
+    }
+
+    /// Appends a key press with the Control key held down.
+    pub fn ctrl(&mut self, c: char) -> &mut Self {
+        let key = event::KeyEvent::new(event::KeyCode::Char(c), event::KeyModifiers::CONTROL);
+        self.push(Event::Key(key))
+    }
+
+    /// Appends a key press for each character of `text`.
+    pub fn type_text(&mut self, text: &str) -> &mut Self {
+        for c in text.chars() {
+            self.key(event::KeyCode::Char(c));
+        }
+        self
+    }
+
+    /// Returns `true` once every event has been handed out.
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+}
+
+#[cfg(test)]
+impl EventSource for ScriptedEvents {
+    fn next_event(&mut self, _timeout: Duration) -> Result<Option<Event>> {
+        Ok(self.events.pop_front())
+    }
+}
@@ -0,0 +1,36 @@
+//! Soft handling of Ctrl+C (`SIGINT`) in the command-line interface.
+//!
+//! The first interrupt only raises a flag, which commands check between steps,
+//! so that they return `Error::Cancelled` and unwind normally, zeroizing every
+//! password and secret on the way. Blocking reads (e.g. a password prompt) are
+//! not interrupted by the flag, so a second interrupt exits immediately.
+//!
+//! The TUI doesn't need this: in raw mode, Ctrl+C is an ordinary key press.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use crate::error::{Error, Result, ResultExt};
+
+
+/// The exit status of a process killed by `SIGINT`, by shell convention.
+const INTERRUPTED_EXIT_STATUS: i32 = 128 + 2;
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Replaces the default handler of Ctrl+C, which terminates the process at once.
+pub fn install_handler() -> Result<()> {
+    ctrlc::set_handler(|| {
+        if INTERRUPTED.swap(true, Ordering::SeqCst) {
+            std::process::exit(INTERRUPTED_EXIT_STATUS);
+        }
+        eprintln!("\nInterrupted; press Ctrl+C again to quit immediately");
+    }).context("Can't install a handler for Ctrl+C")
+}
+
+/// Returns `Err(Error::Cancelled)` if Ctrl+C has been pressed.
+pub fn check() -> Result<()> {
+    if INTERRUPTED.load(Ordering::SeqCst) {
+        Err(Error::Cancelled)
+    } else {
+        Ok(())
+    }
+}
@@ -0,0 +1,203 @@
+//! Inventories: the titles, account names, and tags of items, without their
+//! secrets, for answering "which accounts do we have?", e.g. in a spreadsheet.
+//!
+//! An inventory can be imported into another vault, where every item becomes a
+//! placeholder with an empty secret and the `placeholder` tag, until its secret
+//! is filled in with `steelsafe fill`.
+//!
+//! In the CSV format, the first line names the columns (`label`, `account`,
+//! and `tags`, in any order), and the tags of an item are separated by line
+//! breaks within their field, since tags themselves can't contain any.
+
+use std::mem;
+use serde::{Serialize, Deserialize};
+use crate::error::{Error, Result};
+
+
+/// The columns of the CSV format, in the order they are written.
+const CSV_HEADER: [&str; 3] = ["label", "account", "tags"];
+
+/// An item listed in an inventory.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct InventoryItem {
+    pub label: String,
+    #[serde(default)]
+    pub account: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// Renders the items as a pretty-printed JSON array.
+pub fn to_json(items: &[InventoryItem]) -> Result<String> {
+    let mut json = serde_json::to_string_pretty(items)?;
+    json.push('\n');
+    Ok(json)
+}
+
+/// Parses a JSON array of items, as written by `to_json()`.
+pub fn from_json(text: &str) -> Result<Vec<InventoryItem>> {
+    serde_json::from_str(text).map_err(|error| Error::InvalidInventory(error.to_string()))
+}
+
+/// Renders the items as CSV, with a header line.
+pub fn to_csv(items: &[InventoryItem]) -> String {
+    let mut csv = String::new();
+    write_record(&mut csv, &CSV_HEADER);
+
+    for item in items {
+        let account = item.account.as_deref().unwrap_or_default();
+        let tags = item.tags.join("\n");
+        write_record(&mut csv, &[&item.label, account, &tags]);
+    }
+
+    csv
+}
+
+/// Parses CSV with a header line. Only the `label` column is required, and
+/// unknown columns are ignored, so that spreadsheets may add their own.
+pub fn from_csv(text: &str) -> Result<Vec<InventoryItem>> {
+    let mut records = parse_records(text)?.into_iter();
+    let header = records.next().unwrap_or_default();
+    let column = |name: &str| header.iter().position(|title| title.trim().eq_ignore_ascii_case(name));
+
+    let label_column = column("label").ok_or_else(|| {
+        Error::InvalidInventory(String::from("the first line must name a `label` column"))
+    })?;
+    let account_column = column("account");
+    let tags_column = column("tags");
+
+    let items = records.map(|mut record| {
+        let mut take = |index: Option<usize>| {
+            index.and_then(|index| record.get_mut(index)).map(mem::take).unwrap_or_default()
+        };
+        let label = take(Some(label_column));
+        let account = take(account_column);
+        let tags = take(tags_column);
+
+        InventoryItem {
+            label,
+            account: if account.is_empty() { None } else { Some(account) },
+            tags: tags.lines().map(str::trim).filter(|tag| !tag.is_empty()).map(String::from).collect(),
+        }
+    });
+
+    Ok(items.collect())
+}
+
+fn write_record(csv: &mut String, fields: &[&str]) {
+    for (index, field) in fields.iter().enumerate() {
+        if index > 0 {
+            csv.push(',');
+        }
+
+        if field.contains([',', '"', '\n', '\r']) {
+            csv.push('"');
+            csv.push_str(&field.replace('"', "\"\""));
+            csv.push('"');
+        } else {
+            csv.push_str(field);
+        }
+    }
+
+    csv.push('\n');
+}
+
+/// Splits CSV into records and fields, as described by RFC 4180. Blank lines
+/// are skipped, and both `\n` and `\r\n` line endings are accepted.
+pub fn parse_records(text: &str) -> Result<Vec<Vec<String>>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if quoted {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' => quoted = false,
+                _ => field.push(c),
+            }
+            continue;
+        }
+
+        match c {
+            '"' if field.is_empty() => quoted = true,
+            '"' => {
+                return Err(Error::InvalidInventory(String::from("quote in the middle of an unquoted field")));
+            }
+            ',' => record.push(mem::take(&mut field)),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                record.push(mem::take(&mut field));
+                records.push(mem::take(&mut record));
+            }
+            _ => field.push(c),
+        }
+    }
+
+    if quoted {
+        return Err(Error::InvalidInventory(String::from("unterminated quoted field")));
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+
+    records.retain(|record| record.iter().any(|field| !field.is_empty()));
+
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::{Error, Result};
+    use super::{InventoryItem, to_csv, from_csv, to_json, from_json};
+
+
+    fn items() -> Vec<InventoryItem> {
+        vec![
+            InventoryItem {
+                label: String::from("Bank, \"private\""),
+                account: Some(String::from("alice")),
+                tags: vec![String::from("finance"), String::from("home, sweet home")],
+            },
+            InventoryItem {
+                label: String::from("Router"),
+                account: None,
+                tags: Vec::new(),
+            },
+        ]
+    }
+
+    #[test]
+    fn csv_round_trips() -> Result<()> {
+        let csv = to_csv(&items());
+
+        assert_eq!(
+            csv,
+            "label,account,tags\n\
+             \"Bank, \"\"private\"\"\",alice,\"finance\nhome, sweet home\"\n\
+             Router,,\n"
+        );
+        assert_eq!(from_csv(&csv)?, items());
+        assert_eq!(from_json(&to_json(&items())?)?, items());
+
+        Ok(())
+    }
+
+    #[test]
+    fn csv_columns_are_found_by_name() -> Result<()> {
+        let csv = "Notes,Label\r\nmodem in the hall,Router\r\n\r\n,Printer";
+        let labels: Vec<_> = from_csv(csv)?.into_iter().map(|item| item.label).collect();
+
+        assert_eq!(labels, ["Router", "Printer"]);
+        assert!(matches!(from_csv("account\nalice\n"), Err(Error::InvalidInventory(_))));
+        assert!(matches!(from_csv("label\n\"Router\n"), Err(Error::InvalidInventory(_))));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,268 @@
+//! The emergency kit: a sheet to print and keep in a physical safe.
+//!
+//! It tells whoever reads it (possibly yourself, after losing your computer)
+//! where the vault and its backups are, and how to recover it. Optionally, it
+//! also contains the secrets of selected items, in plain text and as QR codes,
+//! e.g. the password of the email account needed for recovering everything else.
+
+use std::fmt::{self, Debug, Formatter, Write as _};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use chrono::{DateTime, Utc};
+use zeroize::Zeroizing;
+use crate::{
+    qr::QrMatrix,
+    error::{Result, ResultExt},
+};
+
+
+/// The width of the light border around printed QR codes, in modules. Paper
+/// has no border of its own, so this is the full width the standard requires.
+const QUIET_ZONE: usize = 4;
+
+/// The size of a single module of QR codes in the HTML format, in CSS pixels.
+const MODULE_SIZE: usize = 4;
+
+/// The steps of restoring the vault, in order.
+const INSTRUCTIONS: &[&str] = &[
+    "Install steelsafe (https://github.com/H2CO3/steelsafe).",
+    "Copy the database file to the location above, or restore it from one of the backups.",
+    "If a pepper file is listed, restore it too: items created while it was configured \
+     can't be decrypted without it.",
+    "Start steelsafe, select an item, and press C to copy its secret. You will be asked \
+     for its encryption password.",
+];
+
+/// A secret to be printed on the emergency kit.
+pub struct KitSecret {
+    pub label: String,
+    pub account: Option<String>,
+    pub secret: Zeroizing<String>,
+}
+
+impl Debug for KitSecret {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        formatter
+            .debug_struct("KitSecret")
+            .field("label", &self.label)
+            .field("account", &self.account)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The contents of an emergency kit.
+#[derive(Debug)]
+pub struct EmergencyKit {
+    pub created_at: DateTime<Utc>,
+    /// What is where, e.g. `("Database", "/home/me/.local/share/steelsafe/secrets.sqlite3")`.
+    pub locations: Vec<(&'static str, String)>,
+    pub item_count: u64,
+    pub secrets: Vec<KitSecret>,
+}
+
+impl EmergencyKit {
+    /// Renders the kit as plain text. QR codes are drawn with Unicode block
+    /// characters, so they only print correctly with a monospace font.
+    pub fn to_text(&self) -> Result<Zeroizing<String>> {
+        let qr_codes = self.qr_codes()?;
+        let mut text = Zeroizing::new(String::new());
+
+        // writing into a `String` never fails
+        let _ = self.write_text(&mut text, &qr_codes);
+
+        Ok(text)
+    }
+
+    /// Renders the kit as a standalone HTML page. QR codes are inline SVG images.
+    pub fn to_html(&self) -> Result<Zeroizing<String>> {
+        let qr_codes = self.qr_codes()?;
+        let mut html = Zeroizing::new(String::new());
+
+        // writing into a `String` never fails
+        let _ = self.write_html(&mut html, &qr_codes);
+
+        Ok(html)
+    }
+
+    fn qr_codes(&self) -> Result<Vec<QrMatrix>> {
+        self.secrets
+            .iter()
+            .map(|secret| QrMatrix::new(secret.secret.as_bytes(), QUIET_ZONE))
+            .collect()
+    }
+
+    fn write_text(&self, text: &mut String, qr_codes: &[QrMatrix]) -> fmt::Result {
+        writeln!(text, "STEELSAFE EMERGENCY KIT")?;
+        writeln!(text, "Created at {}", self.created_at.format("%F %T UTC"))?;
+        writeln!(text)?;
+        writeln!(text, "{}", self.warning())?;
+        writeln!(text)?;
+
+        writeln!(text, "WHERE THE VAULT IS")?;
+        let width = self.locations.iter().map(|(name, _)| name.len() + 1).max().unwrap_or(0);
+        for (name, location) in &self.locations {
+            writeln!(text, "  {:width$}  {}", format!("{name}:"), location)?;
+        }
+        writeln!(text, "  The vault contains {} items.", self.item_count)?;
+        writeln!(text)?;
+
+        writeln!(text, "HOW TO RECOVER IT")?;
+        for (number, step) in (1..).zip(INSTRUCTIONS) {
+            writeln!(text, "  {number}. {step}")?;
+        }
+        writeln!(text)?;
+        writeln!(text, "  Encryption password (write it down by hand):")?;
+        writeln!(text)?;
+        writeln!(text, "  {}", "_".repeat(48))?;
+
+        for (secret, qr_code) in self.secrets.iter().zip(qr_codes) {
+            writeln!(text)?;
+            writeln!(text, "SECRET: {}", describe(secret))?;
+            writeln!(text)?;
+            writeln!(text, "{}", secret.secret.as_str())?;
+            writeln!(text)?;
+
+            for line in qr_code.half_blocks().iter() {
+                writeln!(text, "{line}")?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write_html(&self, html: &mut String, qr_codes: &[QrMatrix]) -> fmt::Result {
+        writeln!(html, "<!DOCTYPE html>")?;
+        writeln!(html, r#"<html lang="en"><head><meta charset="utf-8">"#)?;
+        writeln!(html, "<title>Steelsafe emergency kit</title>")?;
+        writeln!(html, "<style>")?;
+        writeln!(html, "body {{ font-family: sans-serif; max-width: 48em; margin: auto; }}")?;
+        writeln!(html, ".secret {{ break-inside: avoid; }} .secret pre {{ font-size: 1.2em; white-space: pre-wrap; }}")?;
+        writeln!(html, ".blank {{ border-bottom: 1px solid; height: 3em; }}")?;
+        writeln!(html, "</style></head><body>")?;
+
+        writeln!(html, "<h1>Steelsafe emergency kit</h1>")?;
+        writeln!(html, "<p>Created at {}</p>", self.created_at.format("%F %T UTC"))?;
+        writeln!(html, "<p><strong>{}</strong></p>", escape_html(&self.warning()).as_str())?;
+
+        writeln!(html, "<h2>Where the vault is</h2><dl>")?;
+        for (name, location) in &self.locations {
+            let name = escape_html(name);
+            let location = escape_html(location);
+            writeln!(html, "<dt>{}</dt><dd><code>{}</code></dd>", name.as_str(), location.as_str())?;
+        }
+        writeln!(html, "</dl><p>The vault contains {} items.</p>", self.item_count)?;
+
+        writeln!(html, "<h2>How to recover it</h2><ol>")?;
+        for step in INSTRUCTIONS {
+            writeln!(html, "<li>{}</li>", escape_html(step).as_str())?;
+        }
+        writeln!(html, "</ol><p>Encryption password (write it down by hand):</p>")?;
+        writeln!(html, r#"<div class="blank"></div>"#)?;
+
+        for (secret, qr_code) in self.secrets.iter().zip(qr_codes) {
+            writeln!(html, r#"<div class="secret"><h2>{}</h2>"#, escape_html(&describe(secret)).as_str())?;
+            writeln!(html, "<pre>{}</pre>", escape_html(&secret.secret).as_str())?;
+            writeln!(html, "{}</div>", qr_code.svg(MODULE_SIZE).as_str())?;
+        }
+
+        writeln!(html, "</body></html>")
+    }
+
+    fn warning(&self) -> String {
+        match self.secrets.len() {
+            0 => String::from("Keep this sheet in a safe place."),
+            1 => String::from(
+                "Keep this sheet in a safe place. It contains a secret in plain text: \
+                 anyone who reads it can use it."
+            ),
+            n => format!(
+                "Keep this sheet in a safe place. It contains {n} secrets in plain text: \
+                 anyone who reads it can use them."
+            ),
+        }
+    }
+}
+
+/// Writes the rendered kit into a new file, which only the owner can read.
+/// The file must not exist yet.
+pub fn write_kit_file(path: &Path, contents: &str) -> Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    let mut file = options.open(path).context("Can't create emergency kit")?;
+    file.write_all(contents.as_bytes())?;
+    file.sync_all()?;
+
+    Ok(())
+}
+
+fn describe(secret: &KitSecret) -> String {
+    match secret.account.as_deref() {
+        Some(account) => format!("{} ({})", secret.label, account),
+        None => secret.label.clone(),
+    }
+}
+
+fn escape_html(text: &str) -> Zeroizing<String> {
+    let mut escaped = Zeroizing::new(String::with_capacity(text.len()));
+
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use zeroize::Zeroizing;
+    use super::{EmergencyKit, KitSecret};
+
+
+    fn kit() -> EmergencyKit {
+        EmergencyKit {
+            created_at: Utc.with_ymd_and_hms(2024, 6, 30, 12, 0, 0).unwrap(),
+            locations: vec![("Database", "/home/me/<vault>/secrets.sqlite3".into())],
+            item_count: 42,
+            secrets: vec![KitSecret {
+                label: "Mail & calendar".into(),
+                account: Some("me@example.com".into()),
+                secret: Zeroizing::new("<correct horse>".into()),
+            }],
+        }
+    }
+
+    #[test]
+    fn text_contains_locations_and_secrets() {
+        let text = kit().to_text().unwrap();
+
+        assert!(text.contains("Created at 2024-06-30 12:00:00 UTC"));
+        assert!(text.contains("Database:  /home/me/<vault>/secrets.sqlite3"));
+        assert!(text.contains("The vault contains 42 items."));
+        assert!(text.contains("SECRET: Mail & calendar (me@example.com)\n\n<correct horse>\n"));
+        assert!(text.contains('█'));
+    }
+
+    #[test]
+    fn html_is_escaped() {
+        let html = kit().to_html().unwrap();
+
+        assert!(html.contains("<code>/home/me/&lt;vault&gt;/secrets.sqlite3</code>"));
+        assert!(html.contains("<h2>Mail &amp; calendar (me@example.com)</h2>"));
+        assert!(html.contains("<pre>&lt;correct horse&gt;</pre>"));
+        assert!(!html.contains("<correct horse>"));
+        assert!(html.contains("<svg "));
+    }
+}
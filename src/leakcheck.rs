@@ -0,0 +1,64 @@
+//! A global allocator for tests, which finds copies of a secret left behind
+//! in freed memory, e.g. by a `String` that grew, or by a buffer that was
+//! dropped without being zeroized.
+//!
+//! Every block is searched for the secret just before it's freed, but only on
+//! the thread that is checking, so that tests running in parallel don't see
+//! each other's secrets. Re-allocation frees the old block, so it's checked, too.
+//! This is the only place where `unsafe` is allowed, and only in tests.
+
+#![allow(unsafe_code)]
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::slice;
+
+
+#[global_allocator]
+static ALLOCATOR: LeakCheck = LeakCheck;
+
+thread_local! {
+    /// The secret being looked for on this thread, if any.
+    static SECRET: Cell<Option<&'static [u8]>> = const { Cell::new(None) };
+    /// The number of freed blocks that contained the secret.
+    static LEAKS: Cell<usize> = const { Cell::new(0) };
+}
+
+struct LeakCheck;
+
+// SAFETY: every call is forwarded to the system allocator unchanged; the
+// freed block is only read, while it's still allocated.
+unsafe impl GlobalAlloc for LeakCheck {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // `try_with()` fails while the thread-locals themselves are destroyed
+        let secret = SECRET.try_with(Cell::get).ok().flatten();
+
+        if let Some(secret) = secret.filter(|secret| !secret.is_empty()) {
+            // SAFETY: the caller guarantees that the block is allocated with this layout
+            let block = unsafe { slice::from_raw_parts(ptr, layout.size()) };
+
+            if block.windows(secret.len()).any(|window| window == secret) {
+                let _ = LEAKS.try_with(|leaks| leaks.set(leaks.get() + 1));
+            }
+        }
+
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+/// Runs `f`, and returns the number of blocks freed in the meantime (on the
+/// current thread) that still contained `secret`. The secret itself must not
+/// be on the heap, and it should be long enough not to occur by chance.
+pub fn leaked_copies(secret: &'static str, f: impl FnOnce()) -> usize {
+    LEAKS.with(|leaks| leaks.set(0));
+    SECRET.with(|cell| cell.set(Some(secret.as_bytes())));
+
+    f();
+
+    SECRET.with(|cell| cell.set(None));
+    LEAKS.with(Cell::get)
+}
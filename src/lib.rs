@@ -0,0 +1,19 @@
+//! The cryptographic core of `steelsafe`: encryption of secrets, the field
+//! types that hold them, and the errors these can produce.
+//!
+//! The `steelsafe` binary is built on top of this library. It is published
+//! separately so that the fuzz targets (see `fuzz/`) can link against exactly
+//! the code the binary runs; it is not meant to be a stable API.
+
+// Tests use a custom global allocator (see `leakcheck`), which needs `unsafe`.
+#![cfg_attr(not(test), forbid(unsafe_code))]
+#![cfg_attr(test, deny(unsafe_code))]
+
+pub mod crypto;
+pub mod error;
+pub mod fields;
+pub mod i18n;
+pub mod memlock;
+pub mod schema;
+#[cfg(test)]
+mod leakcheck;
@@ -0,0 +1,98 @@
+//! Rate limiting of decryption attempts.
+
+use std::time::{Duration, Instant};
+
+
+/// The number of consecutive failed attempts that are tolerated without any delay.
+pub const FREE_ATTEMPTS: u32 = 3;
+
+/// The upper limit of the delay imposed after too many failed attempts.
+pub const MAX_COOLDOWN: Duration = Duration::from_secs(5 * 60);
+
+/// Keeps track of consecutive failed decryption (master password) attempts.
+///
+/// After `FREE_ATTEMPTS` consecutive failures, every further failure imposes
+/// a cooldown before the next attempt, which doubles with each failure (1, 2,
+/// 4, 8, ... seconds), up to `MAX_COOLDOWN`. A successful attempt resets it.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct FailedAttempts {
+    count: u32,
+    locked_until: Option<Instant>,
+}
+
+impl FailedAttempts {
+    /// Restores the state after `count` consecutive failures (e.g., persisted by
+    /// a previous session). The corresponding cooldown is counted from `now`.
+    pub fn new(count: u32, now: Instant) -> Self {
+        let mut attempts = FailedAttempts { count, locked_until: None };
+        attempts.locked_until = attempts.cooldown().map(|cooldown| now + cooldown);
+        attempts
+    }
+
+    /// The number of consecutive failed attempts so far.
+    pub const fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// Registers a failed attempt, possibly starting a cooldown period.
+    pub fn record_failure(&mut self, now: Instant) {
+        *self = FailedAttempts::new(self.count.saturating_add(1), now);
+    }
+
+    /// Registers a successful attempt, resetting the counter.
+    pub fn record_success(&mut self) {
+        *self = FailedAttempts::default();
+    }
+
+    /// Returns the time remaining until the next attempt is allowed,
+    /// or `None` if an attempt can be made right now.
+    pub fn remaining_cooldown(&self, now: Instant) -> Option<Duration> {
+        self.locked_until
+            .and_then(|locked_until| locked_until.checked_duration_since(now))
+            .filter(|remaining| !remaining.is_zero())
+    }
+
+    /// The full length of the cooldown imposed after the current number of failures.
+    fn cooldown(&self) -> Option<Duration> {
+        let excess = self.count.checked_sub(FREE_ATTEMPTS)?;
+        let secs = 1_u64.checked_shl(excess).unwrap_or(u64::MAX);
+
+        Some(Duration::from_secs(secs).min(MAX_COOLDOWN))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+    use super::{FailedAttempts, FREE_ATTEMPTS, MAX_COOLDOWN};
+
+
+    #[test]
+    fn cooldown_grows_and_resets() {
+        let now = Instant::now();
+        let mut attempts = FailedAttempts::default();
+
+        for _ in 0..FREE_ATTEMPTS - 1 {
+            attempts.record_failure(now);
+            assert_eq!(attempts.remaining_cooldown(now), None);
+        }
+
+        attempts.record_failure(now);
+        assert_eq!(attempts.remaining_cooldown(now), Some(Duration::from_secs(1)));
+
+        attempts.record_failure(now);
+        assert_eq!(attempts.remaining_cooldown(now), Some(Duration::from_secs(2)));
+
+        // the cooldown expires eventually
+        assert_eq!(attempts.remaining_cooldown(now + Duration::from_secs(2)), None);
+
+        // the cooldown is bounded, even after an absurd number of failures
+        let attempts = FailedAttempts::new(u32::MAX, now);
+        assert_eq!(attempts.remaining_cooldown(now), Some(MAX_COOLDOWN));
+
+        let mut attempts = FailedAttempts::new(FREE_ATTEMPTS + 5, now);
+        attempts.record_success();
+        assert_eq!(attempts.count(), 0);
+        assert_eq!(attempts.remaining_cooldown(now), None);
+    }
+}
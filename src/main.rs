@@ -1,52 +1,264 @@
 #![doc = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/", "README.md"))]
-#![forbid(unsafe_code)]
+// Tests use a custom global allocator (see `leakcheck`), which needs `unsafe`.
+#![cfg_attr(not(test), forbid(unsafe_code))]
+#![cfg_attr(test, deny(unsafe_code))]
 
+use std::process::ExitCode;
+use std::path::Path;
+#[cfg(unix)]
+use std::sync::{Arc, atomic::{AtomicBool, Ordering}};
+use clap::Parser;
+use ratatui::crossterm::{terminal, style::available_color_count};
+#[cfg(unix)]
+use signal_hook::consts::SIGTSTP;
 use crate::{
+    cli::{Cli, Command},
     config::Config,
+    crypto::Pepper,
     db::Database,
+    net::Network,
+    ssh::RemoteVault,
     tui::State,
+    input::TerminalEvents,
     screen::ScreenGuard,
-    error::Result,
+    error::{Error, Result, ExitStatus, ErrorReport},
 };
+// The modules shared with the fuzz targets live in the library crate.
+use steelsafe::{crypto, error, fields, i18n, memlock, schema};
 
 mod db;
-mod crypto;
+mod format;
+mod lockout;
+mod otp;
+mod percent;
+mod recovery;
+mod wordlist;
+mod passgen;
+mod policy;
+mod timestamp;
+mod text;
+mod clipboard;
+mod progress;
+mod query;
+mod expiry;
 mod config;
-mod error;
+mod askpass;
+mod interrupt;
+mod sync;
+mod oplog;
+mod conflict;
+mod ssh;
+mod share;
+mod shamir;
+mod org;
+mod report;
+mod ssitem;
+mod qr;
+mod kit;
+mod inventory;
+mod migrate;
+mod bitwarden;
+mod pass;
+mod browser;
+mod perms;
+mod health;
+mod palette;
+mod secret_input;
+mod ui;
+mod markdown;
+mod net;
+mod vault;
+#[cfg(unix)]
+mod agent;
+#[cfg(feature = "remote")]
+mod remote;
+#[cfg(feature = "serve")]
+mod serve;
+mod cli;
+mod plain;
 mod screen;
+mod input;
 mod tui;
+#[cfg(test)]
+mod leakcheck;
 
 
 #[derive(Debug)]
 struct App {
     screen: ScreenGuard,
     state: State,
+    /// Set when another process sends `SIGTSTP`, e.g. `kill -TSTP`.
+    #[cfg(unix)]
+    stop_signal: Arc<AtomicBool>,
 }
 
 impl App {
     fn new(state: State) -> Result<Self> {
+        let screen = ScreenGuard::open()?;
+
+        // The default action would stop the process without restoring the terminal.
+        #[cfg(unix)]
+        let stop_signal = Arc::new(AtomicBool::new(false));
+        #[cfg(unix)]
+        signal_hook::flag::register(SIGTSTP, Arc::clone(&stop_signal))?;
+
         Ok(App {
-            screen: ScreenGuard::open()?,
+            screen,
             state,
+            #[cfg(unix)]
+            stop_signal,
         })
     }
 
     /// The main run loop.
     fn run(mut self) -> Result<()> {
         while self.state.is_running() {
-            self.screen.draw(|frame| self.state.draw(frame))?;
-            self.state.handle_events();
+            if self.state.needs_redraw() {
+                self.screen.draw(|frame| self.state.draw(frame))?;
+            }
+
+            self.state.handle_events(&mut TerminalEvents);
+
+            #[cfg(unix)]
+            {
+                let requested = self.state.take_suspend_request();
+                let signalled = self.stop_signal.swap(false, Ordering::SeqCst);
+
+                if requested || signalled {
+                    self.screen.suspend()?;
+                    self.state.request_redraw();
+                }
+            }
         }
 
         Ok(())
     }
 }
 
-fn main() -> Result<()> {
+fn main() -> ExitCode {
+    // clap would exit with status 2 upon invalid arguments, which means "not found" here
+    let cli = match Cli::try_parse() {
+        Ok(cli) => cli,
+        Err(error) if error.use_stderr() => {
+            // the arguments couldn't be parsed, so look for the flag by hand
+            if std::env::args_os().any(|arg| arg == "--json-errors") {
+                let message = error.kind().as_str().unwrap_or("invalid arguments");
+                report_error(true, ExitStatus::Usage, message.to_owned());
+            } else {
+                let _ = error.print();
+            }
+            return ExitStatus::Usage.into();
+        }
+        Err(help_or_version) => {
+            let _ = help_or_version.print();
+            return ExitCode::SUCCESS;
+        }
+    };
+    let json_errors = cli.json_errors;
+
+    match run(cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            let status = error.exit_status();
+            report_error(json_errors, status, error.to_string());
+            status.into()
+        }
+    }
+}
+
+/// Prints the error to the standard error, either as a JSON object or as text.
+fn report_error(json: bool, status: ExitStatus, message: String) {
+    if json {
+        let report = ErrorReport::new(status, message);
+        let line = serde_json::to_string(&report).unwrap_or_else(|_| report.message.clone());
+        eprintln!("{line}");
+    } else {
+        eprintln!("Error: {message}");
+    }
+}
+
+fn run(cli: Cli) -> Result<()> {
+    // a broken config must not prevent checking or replacing it
+    match &cli.command {
+        Some(Command::CheckConfig { path }) => return cli::check_config(path.as_deref()),
+        Some(Command::InitConfig { force }) => return cli::init_config(*force),
+        _ => {}
+    }
+
     let config = Config::from_rc_file()?;
-    let db_path = config.ensure_db_dir()?.join("secrets.sqlite3");
-    let db = Database::open(db_path)?;
-    let state = State::new(db, config.theme)?;
+    i18n::init(config.locale);
+
+    if let Some(location) = cli.remote.clone() {
+        let json_errors = cli.json_errors;
+        let remote = RemoteVault::fetch(location.parse()?, cli.writable, &Network::new(&config))?;
+        let writable = remote.is_writable();
+        // changes are uploaded even if steelsafe fails afterwards, since they are already stored
+        let result = run_with_database(cli, config, &remote.local_path(), writable);
+
+        return match (result, remote.push()) {
+            (result, Ok(uploaded)) => {
+                if uploaded {
+                    eprintln!("Uploaded changes to {location}");
+                }
+                result
+            }
+            (Ok(()), Err(push_error)) => Err(push_error),
+            (Err(error), Err(push_error)) => {
+                // neither error may go unnoticed; the exit status is that of the command
+                report_error(json_errors, push_error.exit_status(), push_error.to_string());
+                Err(error)
+            }
+        };
+    }
+
+    let db_path = config.db_file_path()?;
+
+    if cli.fix_perms {
+        return cli::fix_perms(&db_path);
+    }
+
+    run_with_database(cli, config, &db_path, true)
+}
+
+/// Opens the database at `db_path`, then runs the subcommand, or the user interface.
+fn run_with_database(cli: Cli, mut config: Config, db_path: &Path, writable: bool) -> Result<()> {
+    perms::create_private_file(db_path)?;
+
+    let mut db = Database::open(db_path, &config.db_options)?;
+    config.apply_vault_settings(&db)?;
+
+    if !writable {
+        db.set_read_only()?;
+        // reading secrets must not fail because it can't be recorded
+        config.access_log = false;
+        config.persist_failed_attempts = false;
+    }
+
+    if let Some(command) = cli.command {
+        for exposure in perms::check(db_path)? {
+            eprintln!("Warning: {}", Error::InsecurePermissions(exposure.to_string()));
+        }
+
+        interrupt::install_handler()?;
+        return command.run(&mut db, &config);
+    }
+
+    let pepper = config.pepper_file.as_deref().map(Pepper::load_or_create).transpose()?;
+
+    if cli.no_tui {
+        for finding in health::run(&db, &config, None)? {
+            eprintln!("Warning: {}", finding.message());
+        }
+
+        interrupt::install_handler()?;
+        return plain::run(&db, pepper, &config);
+    }
+
+    let terminal = terminal::size().ok().map(|(width, height)| (width, height, available_color_count()));
+    let findings = health::run(&db, &config, terminal)?;
+    let mut state = State::new(Box::new(db), pepper, &config)?;
+    state.show_findings(findings);
+
     let app = App::new(state)?;
 
     app.run()
@@ -0,0 +1,219 @@
+//! Rendering notes written in Markdown as styled lines of the TUI.
+//!
+//! Only the subset that's useful for runbooks and recovery instructions is
+//! supported: headings, bulleted, numbered and task lists, fenced code blocks,
+//! and `**bold**`, `*italic*` and `` `code` `` spans. Anything else, e.g. links
+//! or tables, is shown verbatim, which is still readable. Each line of the
+//! source is rendered on its own (soft line breaks are kept), and lines that
+//! don't fit are wrapped at spaces, indented under their list marker.
+
+use std::mem;
+use ratatui::{style::{Style, Modifier}, text::{Line, Span}};
+use crate::text::display_width;
+
+
+/// A piece of a line, styled uniformly.
+type Segment = (String, Style);
+
+/// Renders `text` in lines of at most `width` cells (except for words that are
+/// longer than that). `code` is the style of code spans and code blocks.
+pub fn render(text: &str, width: usize, code: Style) -> Vec<Line<'static>> {
+    let mut lines = Vec::new();
+    let mut in_code_block = false;
+
+    for source in text.lines() {
+        let trimmed = source.trim_start();
+
+        if trimmed.starts_with("```") {
+            in_code_block = !in_code_block;
+            continue;
+        }
+        if in_code_block {
+            // code is wrapped anywhere, since its spaces may matter
+            let code_lines = crate::text::wrap(source, width.saturating_sub(2));
+            lines.extend(code_lines.into_iter().map(|line| {
+                Line::from(vec![Span::raw("  "), Span::styled(line, code)])
+            }));
+            continue;
+        }
+        if trimmed.is_empty() {
+            lines.push(Line::default());
+            continue;
+        }
+
+        let indent = &source[..source.len() - trimmed.len()];
+        let (marker, content, style) = split_block(trimmed);
+        let prefix = format!("{indent}{marker}");
+        let segments = parse_inline(content, style, code);
+
+        lines.extend(wrap(&prefix, segments, width));
+    }
+
+    lines
+}
+
+/// Splits off the marker of a heading or list item, and returns the prefix it's
+/// rendered as, the rest of the line, and the style of the whole line.
+fn split_block(line: &str) -> (String, &str, Style) {
+    let hashes = line.bytes().take_while(|&b| b == b'#').count();
+
+    if (1..=6).contains(&hashes) && line[hashes..].starts_with(' ') {
+        let style = Style::new().add_modifier(Modifier::BOLD | Modifier::UNDERLINED);
+        return (String::new(), line[hashes..].trim(), style);
+    }
+
+    for (task, checkbox) in [("- [ ] ", "☐ "), ("- [x] ", "☑ "), ("- [X] ", "☑ ")] {
+        if let Some(rest) = line.strip_prefix(task) {
+            return (checkbox.into(), rest, Style::new());
+        }
+    }
+
+    for bullet in ["- ", "* ", "+ "] {
+        if let Some(rest) = line.strip_prefix(bullet) {
+            return ("• ".into(), rest, Style::new());
+        }
+    }
+
+    let digits = line.bytes().take_while(u8::is_ascii_digit).count();
+
+    if digits > 0 {
+        if let Some(rest) = line[digits..].strip_prefix(". ") {
+            return (format!("{}. ", &line[..digits]), rest, Style::new());
+        }
+    }
+
+    (String::new(), line, Style::new())
+}
+
+/// Splits the text into segments at emphasis and code markers. A marker only
+/// counts if it's closed later on the line, so a lone `*` is kept as is.
+fn parse_inline(text: &str, base: Style, code: Style) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    let mut current = String::new();
+    let mut bold = false;
+    let mut italic = false;
+    let mut rest = text;
+
+    let style = |bold: bool, italic: bool| {
+        let mut style = base;
+        if bold {
+            style = style.add_modifier(Modifier::BOLD);
+        }
+        if italic {
+            style = style.add_modifier(Modifier::ITALIC);
+        }
+        style
+    };
+
+    while let Some(c) = rest.chars().next() {
+        let after = &rest[c.len_utf8()..];
+
+        if c == '`' {
+            if let Some(end) = after.find('`') {
+                segments.push((mem::take(&mut current), style(bold, italic)));
+                segments.push((after[..end].to_owned(), code));
+                rest = &after[end + 1..];
+                continue;
+            }
+        }
+
+        if let Some(marker) = ["**", "__"].into_iter().find(|marker| rest.starts_with(marker)) {
+            if bold || rest[2..].contains(marker) {
+                segments.push((mem::take(&mut current), style(bold, italic)));
+                bold = !bold;
+                rest = &rest[2..];
+                continue;
+            }
+        }
+
+        // `_` within a word (e.g. `snake_case`) is not a marker
+        let at_boundary = c == '*' || current.is_empty() || current.ends_with(' ') || italic;
+
+        if (c == '*' || c == '_') && at_boundary && (italic || after.contains(c)) {
+            segments.push((mem::take(&mut current), style(bold, italic)));
+            italic = !italic;
+            rest = after;
+            continue;
+        }
+
+        current.push(c);
+        rest = after;
+    }
+
+    segments.push((current, style(bold, italic)));
+    segments.retain(|(text, _)| !text.is_empty());
+    segments
+}
+
+/// Breaks the segments into lines at spaces. The first line starts with
+/// `prefix`, and the others are indented by the same width.
+fn wrap(prefix: &str, segments: Vec<Segment>, width: usize) -> Vec<Line<'static>> {
+    let indent = " ".repeat(display_width(prefix));
+    let mut lines = Vec::new();
+    let mut spans = Vec::new();
+    let mut line_width = indent.len();
+
+    if !prefix.is_empty() {
+        spans.push(Span::raw(prefix.to_owned()));
+    }
+
+    for (text, style) in segments {
+        for word in text.split_inclusive(' ') {
+            let word_width = display_width(word.trim_end());
+
+            if line_width + word_width > width && line_width > indent.len() {
+                lines.push(Line::from(mem::take(&mut spans)));
+                spans.push(Span::raw(indent.clone()));
+                line_width = indent.len();
+            }
+
+            line_width += display_width(word);
+            spans.push(Span::styled(word.to_owned(), style));
+        }
+    }
+
+    lines.push(Line::from(spans));
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::{style::{Style, Modifier}, text::Line};
+    use super::render;
+
+
+    fn plain(lines: &[Line<'_>]) -> Vec<String> {
+        lines.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn blocks_are_rendered() {
+        let note = "# Restore\n\n1. Boot from USB\n- [x] wipe\n- [ ] reinstall\n  * nested\n```\nsudo dd\n```";
+        let lines = render(note, 40, Style::new());
+
+        assert_eq!(
+            plain(&lines),
+            ["Restore", "", "1. Boot from USB", "☑ wipe", "☐ reinstall", "  • nested", "  sudo dd"],
+        );
+        assert!(lines[0].spans.iter().all(|span| span.style.add_modifier.contains(Modifier::BOLD)));
+    }
+
+    #[test]
+    fn inline_styles_and_wrapping() {
+        let code = Style::new().add_modifier(Modifier::REVERSED);
+        let lines = render("- call **the bank** and say `pin` or my_pet_name *now*", 20, code);
+
+        assert_eq!(plain(&lines), ["• call the bank and ", "  say pin or ", "  my_pet_name now"]);
+
+        let styled = |text: &str| {
+            lines.iter().flat_map(|line| &line.spans).find(|span| span.content.trim() == text).map(|span| span.style)
+        };
+        assert_eq!(styled("bank"), Some(Style::new().add_modifier(Modifier::BOLD)));
+        assert_eq!(styled("pin"), Some(code));
+        assert_eq!(styled("now"), Some(Style::new().add_modifier(Modifier::ITALIC)));
+        assert_eq!(styled("my_pet_name"), Some(Style::new()));
+
+        // unclosed markers are kept
+        assert_eq!(plain(&render("2 * 3 = 6", 20, code)), ["2 * 3 = 6"]);
+    }
+}
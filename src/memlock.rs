@@ -0,0 +1,291 @@
+//! Buffers for key material and decrypted secrets, optionally locked into RAM.
+//!
+//! [`SecretBytes`] holds arbitrary bytes, and [`SecretString`] holds text, e.g.
+//! passwords typed into the TUI or generated ones. Neither leaves copies of its
+//! contents behind in freed memory: `SecretBytes` never re-allocates, and
+//! `SecretString` grows by moving its contents into a new `SecretBytes`, so
+//! that the old buffer is zeroized, too. Text removed from a `SecretString` is
+//! zeroized right away.
+//!
+//! With the `memlock` feature enabled, the memory backing a [`SecretBytes`] buffer
+//! is locked (`mlock()` on Unix, `VirtualLock()` on Windows), so that it can't be
+//! swapped out to disk. Locking may fail, e.g. if `RLIMIT_MEMLOCK` is too low; this
+//! is not treated as an error, since the buffer is still zeroized upon drop, which
+//! is the most important line of defense.
+
+use std::ops::{Deref, Range};
+use std::str;
+use std::fmt::{self, Debug, Formatter};
+use zeroize::{Zeroize, Zeroizing};
+
+
+/// A heap-allocated buffer of secret bytes with a fixed capacity.
+/// Its contents are zeroized when it is dropped.
+///
+/// The buffer never re-allocates: it can only be mutated through
+/// [`SecretBytes::as_mut_vec()`], the callers of which must ensure that the
+/// length never exceeds the capacity requested upon creation. (Re-allocation
+/// would leave an unlocked, non-zeroized copy of the secret behind.)
+pub struct SecretBytes {
+    // Fields are dropped in declaration order, so the buffer is
+    // zeroized first, and only then is the memory unlocked.
+    buf: Zeroizing<Vec<u8>>,
+    _lock: MemoryLock,
+}
+
+impl SecretBytes {
+    /// Creates an empty buffer that can hold up to `capacity` bytes.
+    pub fn with_capacity(capacity: usize) -> Self {
+        let buf = Zeroizing::new(Vec::with_capacity(capacity));
+        let lock = MemoryLock::new(buf.as_ptr(), buf.capacity());
+
+        SecretBytes { buf, _lock: lock }
+    }
+
+    /// Creates a buffer of `len` zero bytes.
+    pub fn zeroed(len: usize) -> Self {
+        let mut bytes = Self::with_capacity(len);
+        bytes.buf.resize(len, 0);
+        bytes
+    }
+
+    /// Mutable access to the underlying buffer. **Never** make it grow beyond
+    /// the capacity it was created with.
+    pub fn as_mut_vec(&mut self) -> &mut Vec<u8> {
+        &mut self.buf
+    }
+}
+
+impl Deref for SecretBytes {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.buf
+    }
+}
+
+impl Debug for SecretBytes {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        formatter
+            .debug_struct("SecretBytes")
+            .field("len", &self.buf.len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// A UTF-8 string whose contents are zeroized when it's dropped, and also
+/// when it grows, unlike a `Zeroizing<String>`, which only zeroizes its last
+/// allocation. Text is only ever inserted and removed at `char` boundaries,
+/// so the buffer is always valid UTF-8.
+pub struct SecretString(SecretBytes);
+
+impl SecretString {
+    /// Creates an empty string that can hold `capacity` bytes without growing.
+    pub fn with_capacity(capacity: usize) -> Self {
+        SecretString(SecretBytes::with_capacity(capacity))
+    }
+
+    /// Joins the lines (e.g. of a text area) with newlines, allocating exactly once.
+    pub fn from_lines(lines: &[String]) -> Self {
+        let len = lines.iter().map(String::len).sum::<usize>() + lines.len().saturating_sub(1);
+        let mut string = Self::with_capacity(len);
+
+        for (index, line) in lines.iter().enumerate() {
+            if index > 0 {
+                string.push('\n');
+            }
+            string.push_str(line);
+        }
+
+        string
+    }
+
+    pub fn push_str(&mut self, text: &str) {
+        let len = self.0.len() + text.len();
+        let capacity = self.0.capacity();
+
+        if len > capacity {
+            let mut grown = SecretBytes::with_capacity(len.max(capacity * 2));
+            grown.as_mut_vec().extend_from_slice(&self.0);
+            self.0 = grown;
+        }
+
+        self.0.as_mut_vec().extend_from_slice(text.as_bytes());
+    }
+
+    pub fn push(&mut self, c: char) {
+        self.push_str(c.encode_utf8(&mut [0; 4]));
+    }
+
+    /// Inserts the text at the byte index `at`, which must be a `char` boundary.
+    pub fn insert_str(&mut self, at: usize, text: &str) {
+        assert!(self.is_char_boundary(at), "byte index {at} is not a char boundary");
+
+        self.push_str(text);
+        self.0.as_mut_vec()[at..].rotate_right(text.len());
+    }
+
+    /// Removes the bytes in `range`, the ends of which must be `char` boundaries,
+    /// and zeroizes them, along with the bytes moved towards the beginning.
+    pub fn remove_range(&mut self, range: Range<usize>) {
+        assert!(
+            range.start <= range.end
+                && self.is_char_boundary(range.start)
+                && self.is_char_boundary(range.end),
+            "byte range {range:?} doesn't lie on char boundaries",
+        );
+
+        let len = self.0.len() - range.len();
+        let buf = self.0.as_mut_vec();
+
+        buf[range.start..].rotate_left(range.len());
+        buf[len..].zeroize();
+        buf.truncate(len);
+    }
+
+    /// Zeroizes the contents, keeping the buffer for reuse.
+    pub fn clear(&mut self) {
+        let len = self.0.len();
+        self.remove_range(0..len);
+    }
+
+    pub fn as_str(&self) -> &str {
+        // only ever edited at char boundaries, with `&str`s, so this can't fail
+        str::from_utf8(&self.0).unwrap_or_default()
+    }
+}
+
+impl From<&str> for SecretString {
+    fn from(text: &str) -> Self {
+        let mut string = Self::with_capacity(text.len());
+        string.push_str(text);
+        string
+    }
+}
+
+impl Deref for SecretString {
+    type Target = str;
+
+    fn deref(&self) -> &Self::Target {
+        self.as_str()
+    }
+}
+
+impl PartialEq for SecretString {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for SecretString {}
+
+impl Debug for SecretString {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        formatter
+            .debug_struct("SecretString")
+            .field("len", &self.len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Keeps a memory region locked in RAM while alive. This is a no-op if the
+/// `memlock` feature is disabled, if the region is empty, or if locking failed.
+///
+/// Note that the OS locks and unlocks whole pages, without reference counting,
+/// so unlocking one buffer may also unlock others sharing a page with it.
+struct MemoryLock {
+    #[cfg(feature = "memlock")]
+    _guard: Option<region::LockGuard>,
+}
+
+impl MemoryLock {
+    #[cfg(feature = "memlock")]
+    fn new(address: *const u8, size: usize) -> Self {
+        let guard = if size == 0 {
+            None
+        } else {
+            region::lock(address, size).ok()
+        };
+
+        MemoryLock { _guard: guard }
+    }
+
+    #[cfg(not(feature = "memlock"))]
+    fn new(_address: *const u8, _size: usize) -> Self {
+        MemoryLock {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use zeroize::Zeroizing;
+    use crate::leakcheck::leaked_copies;
+    use super::{SecretBytes, SecretString};
+
+
+    #[test]
+    fn secret_bytes_never_reallocate() {
+        let mut bytes = SecretBytes::with_capacity(64);
+        let address = bytes.as_ptr();
+
+        bytes.as_mut_vec().extend_from_slice(&[0xa5; 64]);
+        bytes.as_mut_vec().truncate(17);
+
+        assert_eq!(bytes.as_ptr(), address);
+        assert_eq!(bytes.as_slice(), &[0xa5; 17]);
+        assert_eq!(format!("{:?}", bytes), "SecretBytes { len: 17, .. }");
+
+        let zeroed = SecretBytes::zeroed(32);
+        assert_eq!(zeroed.as_slice(), &[0; 32]);
+    }
+
+    #[test]
+    fn secret_strings_leave_no_copies_when_growing() {
+        const SECRET: &str = "correct horse battery staple";
+
+        let leaks = leaked_copies(SECRET, || {
+            let mut string = SecretString::with_capacity(4);
+
+            string.push_str(SECRET);
+            for _ in 0..100 {
+                string.push('!');
+            }
+
+            assert!(string.starts_with(SECRET));
+            assert_eq!(string.len(), SECRET.len() + 100);
+            assert_eq!(format!("{string:?}"), "SecretString { len: 128, .. }");
+        });
+        assert_eq!(leaks, 0);
+
+        // the check works: a growing `Zeroizing<String>` leaves its old buffer behind
+        let leaks = leaked_copies(SECRET, || {
+            let mut string = Zeroizing::new(String::with_capacity(4));
+
+            string.push_str(SECRET);
+            string.push_str(&"!".repeat(100));
+        });
+        assert_eq!(leaks, 1);
+
+        // nor when editing in the middle
+        let leaks = leaked_copies(SECRET, || {
+            let mut string = SecretString::from("correct battery staple");
+
+            string.insert_str(8, " ");
+            string.insert_str(8, "hors");
+            string.insert_str(12, "e");
+            assert_eq!(string.as_str(), SECRET);
+
+            string.remove_range(0..8);
+            string.insert_str(0, "ö");
+            assert_eq!(string.as_str(), "öhorse battery staple");
+
+            string.clear();
+            assert!(string.is_empty());
+        });
+        assert_eq!(leaks, 0);
+
+        let lines = [String::from("first"), String::new(), String::from("third")];
+        assert_eq!(SecretString::from_lines(&lines).as_str(), "first\n\nthird");
+        assert_eq!(SecretString::from_lines(&[]).as_str(), "");
+    }
+}
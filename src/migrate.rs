@@ -0,0 +1,41 @@
+//! Items converted from the formats of other password managers, before they
+//! are encrypted and added to the vault (see e.g. the `bitwarden` module).
+
+use zeroize::Zeroizing;
+
+
+/// What kind of item a record was converted into, for the summary.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ItemKind {
+    Password,
+    Totp,
+    Note,
+}
+
+/// An item to be added, with its secret in plain text.
+#[derive(Debug)]
+pub struct ConvertedItem {
+    pub kind: ItemKind,
+    pub label: String,
+    pub account: Option<String>,
+    pub secret: Zeroizing<String>,
+    pub tags: Vec<String>,
+    /// The address of the website, if the record has one.
+    pub url: Option<String>,
+}
+
+/// A record that couldn't be converted, and the reason why.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct SkippedRecord {
+    pub name: String,
+    pub reason: String,
+}
+
+/// The result of converting the records of another password manager.
+#[derive(Debug, Default)]
+pub struct Conversion {
+    pub items: Vec<ConvertedItem>,
+    pub skipped: Vec<SkippedRecord>,
+    /// The number of records left out because they repeat an earlier one.
+    pub merged: usize,
+}
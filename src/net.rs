@@ -0,0 +1,187 @@
+//! The single choke point of network access, so that it's easy to audit what
+//! steelsafe sends where, and to turn it off entirely.
+//!
+//! With `"network": false` in .steelsaferc, nothing is sent anywhere: HTTP
+//! requests fail with `Error::NetworkDisabled`, and git synchronization only
+//! makes local commits. Every HTTP request (remote backups and update checks)
+//! is made through `Network`, which checks the switch first, and `GitSync`
+//! asks `Network::ensure_enabled()` before running `git fetch` or `git push`,
+//! just like `RemoteVault` does before running `ssh`.
+//!
+//! Requests are blocking, but they time out instead of hanging. Downloaded
+//! data is cached, so that it's still available when offline, and so that
+//! repeated checks don't contact the server every time.
+
+#[cfg(feature = "remote")]
+use std::{fs, io::Read, path::PathBuf, time::Duration};
+#[cfg(feature = "remote")]
+use chrono::{DateTime, Utc};
+use crate::{
+    config::Config,
+    error::{Error, Result},
+};
+#[cfg(feature = "remote")]
+use crate::error::ResultExt;
+
+
+/// How long to wait for a connection to be established.
+#[cfg(feature = "remote")]
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a whole request (including uploading or downloading the body) may take.
+#[cfg(feature = "remote")]
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Responses larger than this are truncated when downloaded.
+#[cfg(feature = "remote")]
+const MAX_RESPONSE_BYTES: u64 = 1 << 20;
+
+/// Permission to access the network, and the means of doing so.
+#[derive(Clone, Debug)]
+pub struct Network {
+    enabled: bool,
+    /// Where downloaded responses are cached, if the platform has a cache directory.
+    #[cfg(feature = "remote")]
+    cache_dir: Option<PathBuf>,
+}
+
+/// A downloaded response body, possibly from the cache.
+#[cfg(feature = "remote")]
+#[derive(Clone, Debug)]
+pub struct Cached {
+    pub body: Vec<u8>,
+    pub fetched_at: DateTime<Utc>,
+    /// Whether the cached copy is older than requested, because the
+    /// network is disabled or the server couldn't be reached.
+    pub stale: bool,
+}
+
+impl Network {
+    pub fn new(config: &Config) -> Self {
+        Network {
+            enabled: config.network != Some(false),
+            #[cfg(feature = "remote")]
+            cache_dir: Config::cache_dir().ok().map(|dir| dir.join("http")),
+        }
+    }
+
+    /// Fails if network access is disabled. Everything that goes online
+    /// must call this first (the methods of `Network` do so themselves).
+    pub fn ensure_enabled(&self) -> Result<()> {
+        if self.enabled {
+            Ok(())
+        } else {
+            Err(Error::NetworkDisabled)
+        }
+    }
+
+    /// Uploads `body` to `url` using a `PUT` request with the given headers.
+    /// `what` describes the upload in the error message if it fails.
+    #[cfg(feature = "remote")]
+    pub fn put(&self, url: &str, headers: &[(&str, &str)], body: &[u8], what: &str) -> Result<()> {
+        self.ensure_enabled()?;
+
+        headers
+            .iter()
+            .fold(agent().put(url), |req, (name, value)| req.set(name, value))
+            .send_bytes(body)
+            .context(format!("Can't upload {what}"))?;
+
+        Ok(())
+    }
+
+    /// Downloads `url`, unless it has been downloaded within `max_age`. If the
+    /// network is disabled or the request fails, an older copy is returned if
+    /// there is one.
+    #[cfg(feature = "remote")]
+    pub fn get_cached(&self, url: &str, max_age: Duration) -> Result<Cached> {
+        let cache_path = self.cache_path(url);
+        let cached = cache_path.as_deref().and_then(|path| {
+            let fetched_at = fs::metadata(path).and_then(|metadata| metadata.modified()).ok()?;
+            let body = fs::read(path).ok()?;
+            Some((body, DateTime::<Utc>::from(fetched_at)))
+        });
+
+        if let Some((body, fetched_at)) = cached.as_ref() {
+            if (Utc::now() - *fetched_at).to_std().is_ok_and(|age| age < max_age) {
+                return Ok(Cached { body: body.clone(), fetched_at: *fetched_at, stale: false });
+            }
+        }
+
+        let fetched = self.ensure_enabled().and_then(|()| {
+            let mut body = Vec::new();
+            agent()
+                .get(url)
+                .call()
+                .context(format!("Can't download {url}"))?
+                .into_reader()
+                .take(MAX_RESPONSE_BYTES)
+                .read_to_end(&mut body)?;
+            Ok(body)
+        });
+
+        match (fetched, cached) {
+            (Ok(body), _) => {
+                // failing to cache is not a reason to throw away the response
+                if let Some(path) = cache_path.as_deref() {
+                    let _ = path.parent().map(crate::perms::create_private_dir);
+                    let _ = fs::write(path, &body);
+                }
+                Ok(Cached { body, fetched_at: Utc::now(), stale: false })
+            }
+            (Err(_), Some((body, fetched_at))) => Ok(Cached { body, fetched_at, stale: true }),
+            (Err(error), None) => Err(error),
+        }
+    }
+
+    /// Cached responses are named after the hash of their URL.
+    #[cfg(feature = "remote")]
+    fn cache_path(&self, url: &str) -> Option<PathBuf> {
+        use sha2::{Sha256, Digest};
+
+        let name = crate::remote::hex(&Sha256::digest(url.as_bytes()));
+        self.cache_dir.as_ref().map(|dir| dir.join(name))
+    }
+}
+
+#[cfg(feature = "remote")]
+fn agent() -> ureq::Agent {
+    ureq::AgentBuilder::new()
+        .timeout_connect(CONNECT_TIMEOUT)
+        .timeout(REQUEST_TIMEOUT)
+        .user_agent(concat!("steelsafe/", env!("CARGO_PKG_VERSION")))
+        .build()
+}
+
+#[cfg(all(test, feature = "remote"))]
+mod tests {
+    use std::fs;
+    use std::time::Duration;
+    use crate::error::{Error, Result};
+    use super::Network;
+
+
+    #[test]
+    fn disabled_network_serves_only_the_cache() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("steelsafe-net-test-{}", std::process::id()));
+        let network = Network { enabled: false, cache_dir: Some(dir.clone()) };
+        let url = "https://example.com/never-requested";
+
+        assert!(matches!(network.put(url, &[], b"data", "test data"), Err(Error::NetworkDisabled)));
+        assert!(matches!(network.get_cached(url, Duration::ZERO), Err(Error::NetworkDisabled)));
+
+        // a cached response is returned even if it's too old
+        fs::create_dir_all(&dir)?;
+        fs::write(network.cache_path(url).unwrap(), b"cached body")?;
+
+        let fresh = network.get_cached(url, Duration::from_secs(3600))?;
+        assert_eq!((fresh.body.as_slice(), fresh.stale), (&b"cached body"[..], false));
+
+        let stale = network.get_cached(url, Duration::ZERO)?;
+        assert_eq!((stale.body.as_slice(), stale.stale), (&b"cached body"[..], true));
+
+        fs::remove_dir_all(&dir)?;
+
+        Ok(())
+    }
+}
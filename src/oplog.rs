@@ -0,0 +1,431 @@
+//! Conflict-free replication of the vault, based on an append-only operation log.
+//!
+//! Every change of an item (adding, updating, or deleting it) is appended to the
+//! `operation` table of the database, along with a Lamport timestamp: a counter
+//! that is greater than that of every operation the vault has seen so far, and
+//! the ID of the replica (i.e. the copy of the vault) where the change was made,
+//! which breaks ties. Items are identified by a random ID, which is the same in
+//! every replica, unlike their unique ID (the primary key), which is local.
+//!
+//! The state of an item is the one recorded by its operation with the greatest
+//! timestamp (last writer wins), and a deletion leaves a tombstone behind, so
+//! that the item isn't resurrected by an older operation. Since timestamps are
+//! totally ordered, reconciling two vaults gives the same result regardless of
+//! the order in which operations arrive, or of how many times they do. Thus,
+//! devices can exchange changes directly, in any order, without a central server.
+//!
+//! Labels must remain unique, so an item can't be added or renamed if another
+//! item already has its label. `conflicts()` finds such items beforehand, so
+//! that the user can decide which one to keep (see `conflict`); otherwise, the
+//! operations are skipped and reported as conflicts, until one of the items is
+//! renamed. Only the contents of the
+//! `item` table are replicated; tags, expiry dates, recovery code counts, and
+//! the access log are local to each replica for now.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use serde::{Serialize, Deserialize};
+use nanosql::{AsSqlTy, ToSql, FromSql};
+use crate::{
+    db::{Database, OperationEntry},
+    vault::Vault,
+    format::ExportedItem,
+    conflict::{self, Conflict},
+    error::{Error, Result},
+};
+
+
+/// What happened to an item.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[derive(Serialize, Deserialize, AsSqlTy, ToSql, FromSql)]
+#[serde(rename_all = "snake_case")]
+#[nanosql(rename_all = "lower_snake_case")]
+pub enum OpKind {
+    Add,
+    Update,
+    Delete,
+}
+
+/// A Lamport timestamp. Timestamps are ordered by their clock first, then by
+/// the ID of the replica, so no two operations of different replicas are equal.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Serialize, Deserialize)]
+pub struct Stamp {
+    pub clock: u64,
+    pub replica: String,
+}
+
+/// An operation on an item, as exchanged between replicas.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Operation {
+    /// The replica-independent ID of the item.
+    pub item_id: String,
+    pub kind: OpKind,
+    pub stamp: Stamp,
+    /// The item as of this operation, or `None` for a deletion. Operations read
+    /// from the log have no item until they are included in a changeset.
+    pub item: Option<ExportedItem>,
+}
+
+impl From<OperationEntry> for Operation {
+    fn from(entry: OperationEntry) -> Self {
+        Operation {
+            item_id: entry.item_id,
+            kind: entry.kind,
+            stamp: Stamp {
+                clock: entry.clock,
+                replica: entry.replica,
+            },
+            item: None,
+        }
+    }
+}
+
+/// The outcome of synchronizing two vaults.
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct SyncReport {
+    /// The number of operations applied to the local vault.
+    pub received: usize,
+    /// The number of operations applied to the other vault.
+    pub sent: usize,
+    /// The labels that couldn't be added or given to an item in either vault,
+    /// because another item already has them.
+    pub conflicts: Vec<String>,
+}
+
+/// Keeps the latest operation of each item, keyed by the ID of the item.
+/// The result doesn't depend on the order of the operations, nor on duplicates.
+pub fn reconcile<I>(operations: I) -> BTreeMap<String, Operation>
+where
+    I: IntoIterator<Item = Operation>,
+{
+    let mut latest = BTreeMap::<String, Operation>::new();
+
+    for operation in operations {
+        match latest.get(&operation.item_id) {
+            Some(existing) if existing.stamp >= operation.stamp => {}
+            _ => {
+                latest.insert(operation.item_id.clone(), operation);
+            }
+        }
+    }
+
+    latest
+}
+
+/// The latest operation of every item of the vault, including tombstones,
+/// along with the current state of the items that still exist.
+pub fn changeset(db: &dyn Vault) -> Result<Vec<Operation>> {
+    let item_uids = db.item_ids()?;
+    let mut operations: Vec<Operation> = reconcile(db.operations()?.into_iter().map(Operation::from))
+        .into_values()
+        .collect();
+
+    for operation in &mut operations {
+        if operation.kind == OpKind::Delete {
+            continue;
+        }
+
+        let &item_uid = item_uids.get(&operation.item_id).ok_or(Error::InvalidBundle)?;
+        operation.item = Some(db.item_by_id(item_uid)?.into());
+    }
+
+    Ok(operations)
+}
+
+/// The items of `other` that `sync()` couldn't add to the local vault, or
+/// rename there, because a different local item has their label, along with
+/// that item. Local items that `other` renames or deletes don't conflict.
+pub fn conflicts(local: &Database, other: &Database) -> Result<Vec<Conflict>> {
+    let known = reconcile(local.operations()?.into_iter().map(Operation::from));
+    let item_uids = local.item_ids()?;
+    let item_ids: HashMap<u64, &str> = item_uids
+        .iter()
+        .map(|(item_id, &item_uid)| (item_uid, item_id.as_str()))
+        .collect();
+    let is_newer = |operation: &Operation| {
+        known.get(&operation.item_id).is_none_or(|existing| existing.stamp < operation.stamp)
+    };
+    let incoming = reconcile(changeset(other)?.into_iter().filter(is_newer));
+    let mut taken: HashSet<String> = incoming
+        .values()
+        .filter_map(|operation| operation.item.as_ref())
+        .map(|item| item.label.to_string())
+        .collect();
+    let mut conflicts = Vec::new();
+
+    for operation in incoming.values() {
+        let Some(item) = operation.item.as_ref() else {
+            continue;
+        };
+
+        if !local.label_exists(&item.label)? {
+            continue;
+        }
+
+        let owner = local.item_by_label(&item.label)?.ok_or(Error::DuplicateLabel)?;
+        let owner_id = item_ids.get(&owner.uid).copied().unwrap_or_default();
+
+        if owner_id == operation.item_id {
+            continue;
+        }
+
+        // the owner gives up the label anyway
+        let is_moving = incoming.get(owner_id).is_some_and(|change| {
+            change.item.as_ref().is_none_or(|changed| changed.label != item.label)
+        });
+
+        if is_moving {
+            continue;
+        }
+
+        let renamed = conflict::unused_label(local, &item.label, &mut taken)?;
+        conflicts.push(Conflict::new(owner, item.clone(), Some(operation.clone()), renamed));
+    }
+
+    Ok(conflicts)
+}
+
+/// Applies the operations of another replica that are newer than the local
+/// ones of the same item. Returns the number of operations applied, and the
+/// labels that conflict with those of other items.
+pub fn apply(db: &mut dyn Vault, operations: Vec<Operation>) -> Result<(usize, Vec<String>)> {
+    let local = reconcile(db.operations()?.into_iter().map(Operation::from));
+    let newer: Vec<Operation> = reconcile(operations)
+        .into_values()
+        .filter(|operation| {
+            local
+                .get(&operation.item_id)
+                .is_none_or(|existing| existing.stamp < operation.stamp)
+        })
+        .collect();
+
+    let conflicts = db.apply_operations(&newer)?;
+
+    Ok((newer.len() - conflicts.len(), conflicts))
+}
+
+/// Synchronizes two vaults in both directions, so that both end up with the
+/// same items (except for conflicting labels). The two vaults must be distinct
+/// replicas: a copy of a database file has the same replica ID as the original.
+pub fn sync(local: &mut Database, other: &mut Database) -> Result<SyncReport> {
+    if local.replica_id()? == other.replica_id()? {
+        return Err(Error::SameReplica);
+    }
+
+    let to_local = changeset(other)?;
+    let to_other = changeset(local)?;
+    let (received, mut conflicts) = apply(local, to_local)?;
+    let (sent, other_conflicts) = apply(other, to_other)?;
+
+    conflicts.extend(other_conflicts);
+    conflicts.sort();
+    conflicts.dedup();
+
+    Ok(SyncReport { received, sent, conflicts })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+    use std::collections::HashMap;
+    use chrono::Utc;
+    use nanosql::Null;
+    use crate::config::DatabaseOptions;
+    use crate::crypto::{KdfParams, CipherId, EncryptionOutput};
+    use crate::db::{Database, AddItemInput, Item};
+    use crate::fields::Label;
+    use crate::vault::Vault;
+    use crate::conflict::Resolution;
+    use crate::error::{Error, Result};
+    use super::{OpKind, Operation, Stamp, reconcile, sync, conflicts};
+
+
+    /// Maps the labels of the items to their encrypted secrets, for comparing vaults.
+    fn contents(db: &Database) -> Result<HashMap<String, Vec<u8>>> {
+        let items = db.all_items()?;
+        Ok(items.into_iter().map(|item| (item.label, item.encrypted_secret)).collect())
+    }
+
+    fn add(db: &Database, label: &str, salt: u8) -> Result<Item> {
+        db.add_item(AddItemInput {
+            uid: Null,
+            label: &Label::new(label)?,
+            account: None,
+            last_modified_at: Utc::now(),
+            encrypted_secret: label.as_bytes(),
+            kdf_salt: [salt; 16],
+            auth_nonce: [salt; 24],
+            peppered: false,
+            kdf: KdfParams::CURRENT,
+            cipher: CipherId::CURRENT,
+            reprompt: false,
+        })
+    }
+
+    fn replace(db: &mut Database, item: &Item, secret: &[u8], salt: u8) -> Result<()> {
+        let output = EncryptionOutput {
+            encrypted_secret: secret.to_vec(),
+            kdf_salt: [salt; 16],
+            auth_nonce: [salt; 24],
+            peppered: false,
+            kdf: KdfParams::CURRENT,
+            cipher: CipherId::CURRENT,
+        };
+        db.replace_secret(item.uid, Utc::now(), &output)
+    }
+
+    fn operation(item_id: &str, clock: u64, replica: &str) -> Operation {
+        Operation {
+            item_id: item_id.into(),
+            kind: OpKind::Update,
+            stamp: Stamp { clock, replica: replica.into() },
+            item: None,
+        }
+    }
+
+    #[test]
+    fn reconciliation_does_not_depend_on_order() {
+        let operations = [
+            operation("a", 1, "x"),
+            operation("a", 2, "x"),
+            operation("a", 2, "y"),
+            operation("b", 5, "x"),
+            operation("b", 3, "y"),
+        ];
+        let expected = reconcile(operations.clone());
+
+        assert_eq!(expected["a"].stamp, Stamp { clock: 2, replica: "y".into() });
+        assert_eq!(expected["b"].stamp, Stamp { clock: 5, replica: "x".into() });
+
+        let mut reversed = operations.to_vec();
+        reversed.reverse();
+        assert_eq!(reconcile(reversed), expected);
+
+        let duplicated = operations.iter().chain(&operations).cloned();
+        assert_eq!(reconcile(duplicated), expected);
+    }
+
+    #[test]
+    fn vaults_converge_after_sync() -> Result<()> {
+        let mut laptop = Database::open(":memory:", &DatabaseOptions::default())?;
+        let mut phone = Database::open(":memory:", &DatabaseOptions::default())?;
+
+        let bank = add(&laptop, "bank", 1)?;
+        let mail = add(&laptop, "mail", 2)?;
+        add(&phone, "shop", 3)?;
+
+        let report = sync(&mut laptop, &mut phone)?;
+        assert_eq!((report.received, report.sent), (1, 2));
+        assert!(report.conflicts.is_empty());
+        assert_eq!(contents(&laptop)?, contents(&phone)?);
+        assert_eq!(contents(&phone)?.len(), 3);
+
+        // the later change wins on both sides, even though the other one
+        // was made on the phone, which is synchronized into the laptop first
+        let phone_bank = phone.item_by_label("bank")?.expect("bank was synchronized");
+        replace(&mut phone, &phone_bank, b"changed on the phone", 4)?;
+        replace(&mut laptop, &bank, b"changed on the laptop", 5)?;
+        laptop.delete_item(mail.uid)?;
+
+        let report = sync(&mut laptop, &mut phone)?;
+        assert!(report.conflicts.is_empty());
+        assert_eq!(contents(&laptop)?, contents(&phone)?);
+        assert!(!phone.label_exists("mail")?);
+
+        let winner = laptop.item_by_label("bank")?.expect("bank still exists");
+        let expected: &[u8] = if laptop.replica_id()? > phone.replica_id()? {
+            b"changed on the laptop"
+        } else {
+            b"changed on the phone"
+        };
+        assert_eq!(winner.encrypted_secret, expected);
+
+        // synchronizing again changes nothing
+        let report = sync(&mut laptop, &mut phone)?;
+        assert_eq!((report.received, report.sent), (0, 0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn conflicting_labels_are_reported() -> Result<()> {
+        let mut laptop = Database::open(":memory:", &DatabaseOptions::default())?;
+        let mut phone = Database::open(":memory:", &DatabaseOptions::default())?;
+
+        add(&laptop, "bank", 1)?;
+        add(&phone, "bank", 2)?;
+
+        let report = sync(&mut laptop, &mut phone)?;
+        assert_eq!((report.received, report.sent), (0, 0));
+        assert_eq!(report.conflicts, ["bank"]);
+        assert_eq!(laptop.item_by_label("bank")?.map(|item| item.kdf_salt), Some([1; 16]));
+        assert_eq!(phone.item_by_label("bank")?.map(|item| item.kdf_salt), Some([2; 16]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn resolved_conflicts_prevail_in_both_vaults() -> Result<()> {
+        let cases = [
+            (Resolution::KeepLocal, &[("bank", 1)][..]),
+            (Resolution::TakeIncoming, &[("bank", 2)][..]),
+            (Resolution::KeepBoth, &[("bank", 1), ("bank (2)", 2)][..]),
+        ];
+
+        for (resolution, expected) in cases {
+            let mut laptop = Database::open(":memory:", &DatabaseOptions::default())?;
+            let mut phone = Database::open(":memory:", &DatabaseOptions::default())?;
+
+            add(&laptop, "bank", 1)?;
+            add(&phone, "bank", 2)?;
+            add(&phone, "mail", 3)?;
+
+            let mut found = conflicts(&laptop, &phone)?;
+            assert_eq!(found.len(), 1);
+
+            for conflict in &mut found {
+                conflict.resolution = resolution;
+
+                // stands in for `Conflict::rename_incoming()`, since the secrets aren't encrypted
+                if resolution == Resolution::KeepBoth {
+                    conflict.incoming.label = conflict.renamed.clone();
+                }
+            }
+
+            laptop.resolve_conflicts(&found)?;
+
+            let report = sync(&mut laptop, &mut phone)?;
+            assert!(report.conflicts.is_empty(), "{resolution:?}: {:?}", report.conflicts);
+            assert_eq!(contents(&laptop)?, contents(&phone)?);
+            assert_eq!(contents(&phone)?.len(), expected.len() + 1);
+
+            for &(label, salt) in expected {
+                let item = phone.item_by_label(label)?.expect("item was synchronized");
+                assert_eq!(item.kdf_salt, [salt; 16], "{resolution:?}: {label}");
+            }
+
+            assert!(conflicts(&laptop, &phone)?.is_empty());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn copies_of_a_vault_are_not_synchronized() -> Result<()> {
+        let path = env::temp_dir().join(format!("steelsafe-oplog-test-{}", process::id()));
+        let copy = path.with_extension("copy");
+
+        drop(Database::open(&path, &DatabaseOptions::default())?);
+        fs::copy(&path, &copy)?;
+
+        let result = sync(
+            &mut Database::open(&path, &DatabaseOptions::default())?,
+            &mut Database::open(&copy, &DatabaseOptions::default())?,
+        );
+        fs::remove_file(&path)?;
+        fs::remove_file(&copy)?;
+
+        assert!(matches!(result, Err(Error::SameReplica)), "unexpected result: {result:?}");
+
+        Ok(())
+    }
+}
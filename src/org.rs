@@ -0,0 +1,109 @@
+//! Organization mode: sharing items with the members of a small team, each of
+//! whom decrypts them with their own key, rather than with the item password.
+//!
+//! Every member has an [age](https://age-encryption.org) X25519 key pair, e.g.
+//! made by `age-keygen`. The public keys are stored in the `recipient` table of
+//! the vault, and each member sets `identity_file` in their `.steelsaferc` to
+//! the file holding their own secret key.
+//!
+//! The secret of a shared item is additionally encrypted into an age file, the
+//! envelope of the item: a random key, unique to the envelope, encrypts the
+//! secret, and that key is wrapped to the public key of every member. Adding or
+//! removing a member opens every envelope with the identity of the member doing
+//! it, and re-wraps it for the new set of members. A removed member can't open
+//! the new envelopes, but may remember the secrets; rotate them if that matters.
+
+use std::iter;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::Path;
+use age::{Encryptor, Decryptor, x25519};
+use zeroize::Zeroizing;
+use crate::{
+    memlock::SecretBytes,
+    error::{Error, Result, ResultExt},
+};
+
+
+/// The prefix of age X25519 secret keys in identity files.
+const SECRET_KEY_PREFIX: &str = "AGE-SECRET-KEY-1";
+
+/// Parses the public key of a member, in its `age1...` form.
+pub fn parse_recipient(public_key: &str) -> Result<x25519::Recipient> {
+    public_key
+        .trim()
+        .parse()
+        .map_err(|_| Error::InvalidRecipient(public_key.into()))
+}
+
+/// Loads the first secret key from an identity file as written by `age-keygen`.
+/// Other lines, e.g. comments, are ignored.
+pub fn load_identity(path: &Path) -> Result<x25519::Identity> {
+    let text = Zeroizing::new(fs::read_to_string(path).context("Can't read identity file")?);
+    let line = text
+        .lines()
+        .map(str::trim)
+        .find(|line| line.starts_with(SECRET_KEY_PREFIX))
+        .ok_or(Error::InvalidIdentity)?;
+
+    line.parse().map_err(|_| Error::InvalidIdentity)
+}
+
+/// Encrypts the secret into an envelope that any of the recipients can open.
+pub fn seal(secret: &[u8], recipients: &[x25519::Recipient]) -> Result<Vec<u8>> {
+    let recipients: Vec<Box<dyn age::Recipient + Send>> = recipients
+        .iter()
+        .map(|recipient| Box::new(recipient.clone()) as Box<dyn age::Recipient + Send>)
+        .collect();
+    let encryptor = Encryptor::with_recipients(recipients).ok_or(Error::NoRecipients)?;
+    let mut envelope = Vec::new();
+    let mut writer = encryptor.wrap_output(&mut envelope)?;
+
+    writer.write_all(secret)?;
+    writer.finish()?;
+
+    Ok(envelope)
+}
+
+/// Decrypts the secret in the envelope with the identity of a member.
+pub fn open(envelope: &[u8], identity: &x25519::Identity) -> Result<SecretBytes> {
+    let Decryptor::Recipients(decryptor) = Decryptor::new(envelope)? else {
+        return Err(Error::InvalidEnvelope);
+    };
+    let mut reader = decryptor.decrypt(iter::once(identity as &dyn age::Identity))?;
+    // the plaintext is shorter than the envelope, so the buffer is never reallocated
+    let mut secret = SecretBytes::with_capacity(envelope.len());
+
+    reader.read_to_end(secret.as_mut_vec())?;
+
+    Ok(secret)
+}
+
+#[cfg(test)]
+mod tests {
+    use age::x25519::Identity;
+    use crate::error::{Error, Result};
+    use super::{parse_recipient, seal, open};
+
+
+    #[test]
+    fn every_recipient_opens_the_envelope() -> Result<()> {
+        let alice = Identity::generate();
+        let bob = Identity::generate();
+        let mallory = Identity::generate();
+        let recipients = [
+            parse_recipient(&alice.to_public().to_string())?,
+            parse_recipient(&bob.to_public().to_string())?,
+        ];
+        let envelope = seal(b"correct horse battery staple", &recipients)?;
+
+        assert_eq!(&*open(&envelope, &alice)?, b"correct horse battery staple");
+        assert_eq!(&*open(&envelope, &bob)?, b"correct horse battery staple");
+        assert!(matches!(open(&envelope, &mallory), Err(Error::Envelope(_))));
+
+        assert!(matches!(seal(b"secret", &[]), Err(Error::NoRecipients)));
+        assert!(matches!(parse_recipient("ssh-ed25519 AAAA"), Err(Error::InvalidRecipient(_))));
+
+        Ok(())
+    }
+}
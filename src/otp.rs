@@ -0,0 +1,283 @@
+//! Parsing `otpauth://` URIs, the de facto standard format for provisioning
+//! TOTP/HOTP seeds (usually encoded in the QR codes shown by websites).
+//!
+//! See <https://github.com/google/google-authenticator/wiki/Key-Uri-Format>.
+
+use crate::percent;
+use crate::error::{Error, Result};
+
+
+/// The URI scheme of one-time password provisioning URIs.
+pub const SCHEME: &str = "otpauth://";
+
+/// Whether the one-time passwords are time-based or counter-based.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum OtpKind {
+    Totp,
+    Hotp,
+}
+
+/// The hash function used for computing one-time passwords.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum OtpAlgorithm {
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+/// The parsed contents of an `otpauth://` URI.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct OtpAuthUri {
+    pub kind: OtpKind,
+    /// The provider or service the account belongs to, if specified.
+    pub issuer: Option<String>,
+    /// The account name, e.g. an email address.
+    pub account: String,
+    /// The shared secret, Base32-encoded, upper case, without padding.
+    pub secret: String,
+    pub algorithm: OtpAlgorithm,
+    /// The number of digits in a one-time password.
+    pub digits: u32,
+    /// The validity period of a TOTP code, in seconds.
+    pub period: u64,
+}
+
+impl OtpAuthUri {
+    /// A short, human-readable description of the parameters, excluding the secret.
+    pub fn describe(&self) -> String {
+        let kind = match self.kind {
+            OtpKind::Totp => "TOTP",
+            OtpKind::Hotp => "HOTP",
+        };
+        let algorithm = match self.algorithm {
+            OtpAlgorithm::Sha1 => "SHA-1",
+            OtpAlgorithm::Sha256 => "SHA-256",
+            OtpAlgorithm::Sha512 => "SHA-512",
+        };
+        let key_bits = self.secret.len() * 5;
+
+        match self.kind {
+            OtpKind::Totp => format!(
+                "{kind}, {algorithm}, {} digits every {} s, {key_bits}-bit key",
+                self.digits,
+                self.period,
+            ),
+            OtpKind::Hotp => format!("{kind}, {algorithm}, {} digits, {key_bits}-bit key", self.digits),
+        }
+    }
+
+    /// A TOTP URI with the usual parameters (SHA-1, 6 digits every 30 s) for a bare
+    /// Base32 seed, as shown by setup pages for typing it in instead of scanning it.
+    pub fn totp(issuer: &str, account: &str, seed: &str) -> Result<Self> {
+        let secret = normalize_base32(seed.trim())?;
+
+        if secret.is_empty() {
+            return Err(invalid("missing secret"));
+        }
+
+        Ok(OtpAuthUri {
+            kind: OtpKind::Totp,
+            issuer: (!issuer.is_empty()).then(|| issuer.into()),
+            account: account.into(),
+            secret,
+            algorithm: OtpAlgorithm::Sha1,
+            digits: 6,
+            period: 30,
+        })
+    }
+
+    /// Formats the parameters as a URI, e.g. for displaying it as a QR code.
+    /// The HOTP counter isn't parsed, so it's not included either.
+    pub fn to_uri(&self) -> String {
+        let kind = match self.kind {
+            OtpKind::Totp => "totp",
+            OtpKind::Hotp => "hotp",
+        };
+        let algorithm = match self.algorithm {
+            OtpAlgorithm::Sha1 => "SHA1",
+            OtpAlgorithm::Sha256 => "SHA256",
+            OtpAlgorithm::Sha512 => "SHA512",
+        };
+        let label = match self.issuer.as_deref() {
+            Some(issuer) => format!("{}:{}", percent::encode(issuer), percent::encode(&self.account)),
+            None => percent::encode(&self.account),
+        };
+        let mut uri = format!("{SCHEME}{kind}/{label}?secret={}", self.secret);
+
+        if let Some(issuer) = self.issuer.as_deref() {
+            uri.push_str(&format!("&issuer={}", percent::encode(issuer)));
+        }
+        uri.push_str(&format!("&algorithm={algorithm}&digits={}", self.digits));
+
+        if self.kind == OtpKind::Totp {
+            uri.push_str(&format!("&period={}", self.period));
+        }
+
+        uri
+    }
+
+    /// Returns `true` if the string looks like it's meant to be an `otpauth://` URI,
+    /// regardless of whether it's valid.
+    pub fn is_otpauth_uri(s: &str) -> bool {
+        s.get(..SCHEME.len()).is_some_and(|prefix| prefix.eq_ignore_ascii_case(SCHEME))
+    }
+
+    /// Parses and validates an `otpauth://` URI.
+    pub fn parse(uri: &str) -> Result<Self> {
+        let rest = uri
+            .trim()
+            .get(SCHEME.len()..)
+            .filter(|_| Self::is_otpauth_uri(uri.trim()))
+            .ok_or_else(|| invalid("scheme must be otpauth://"))?;
+        let (kind, rest) = rest.split_once('/').ok_or_else(|| invalid("missing label"))?;
+        let kind = match kind.to_ascii_lowercase().as_str() {
+            "totp" => OtpKind::Totp,
+            "hotp" => OtpKind::Hotp,
+            _ => return Err(invalid("type must be totp or hotp")),
+        };
+        let (label, query) = rest.split_once('?').unwrap_or((rest, ""));
+        let label = percent_decode(label)?;
+        let (label_issuer, account) = match label.split_once(':') {
+            Some((issuer, account)) => (Some(issuer.trim().to_owned()), account.trim().to_owned()),
+            None => (None, label.trim().to_owned()),
+        };
+
+        let mut parsed = OtpAuthUri {
+            kind,
+            issuer: label_issuer,
+            account,
+            secret: String::new(),
+            algorithm: OtpAlgorithm::Sha1,
+            digits: 6,
+            period: 30,
+        };
+
+        for param in query.split('&').filter(|param| !param.is_empty()) {
+            let (key, value) = param.split_once('=').unwrap_or((param, ""));
+            let value = percent_decode(value)?;
+
+            match key {
+                "secret" => parsed.secret = normalize_base32(&value)?,
+                // the parameter takes precedence over the label prefix
+                "issuer" => parsed.issuer = Some(value),
+                "algorithm" => {
+                    parsed.algorithm = match value.to_ascii_uppercase().as_str() {
+                        "SHA1" => OtpAlgorithm::Sha1,
+                        "SHA256" => OtpAlgorithm::Sha256,
+                        "SHA512" => OtpAlgorithm::Sha512,
+                        _ => return Err(invalid("unsupported algorithm")),
+                    };
+                }
+                "digits" => {
+                    parsed.digits = value
+                        .parse()
+                        .ok()
+                        .filter(|digits| (6..=10).contains(digits))
+                        .ok_or_else(|| invalid("digits must be between 6 and 10"))?;
+                }
+                "period" => {
+                    parsed.period = value
+                        .parse()
+                        .ok()
+                        .filter(|&period| period > 0)
+                        .ok_or_else(|| invalid("period must be a positive integer"))?;
+                }
+                // e.g. `counter` for HOTP, or vendor-specific extensions
+                _ => {}
+            }
+        }
+
+        if parsed.secret.is_empty() {
+            return Err(invalid("missing secret"));
+        }
+
+        Ok(parsed)
+    }
+}
+
+fn invalid(reason: &str) -> Error {
+    Error::InvalidOtpAuthUri(reason.into())
+}
+
+/// Checks that the secret is valid Base32 (RFC 4648), and converts
+/// it to upper case, removing padding and any embedded spaces.
+fn normalize_base32(secret: &str) -> Result<String> {
+    let normalized: String = secret
+        .chars()
+        .filter(|&c| c != ' ' && c != '=')
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+
+    if normalized.chars().all(|c| matches!(c, 'A'..='Z' | '2'..='7')) {
+        Ok(normalized)
+    } else {
+        Err(invalid("secret must be Base32-encoded"))
+    }
+}
+
+/// Labels and query values may also encode spaces as `+`.
+fn percent_decode(s: &str) -> Result<String> {
+    percent::decode(&s.replace('+', " ")).ok_or_else(|| invalid("malformed percent-encoding"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Result;
+    use super::{OtpAuthUri, OtpKind, OtpAlgorithm};
+
+
+    #[test]
+    fn parses_full_uri() -> Result<()> {
+        let uri = "otpauth://totp/ACME%20Co:john.doe@email.com?secret=hxdmvjecjjwsrb3hwizr4ifugftmxboz\
+                   &issuer=ACME%20Co&algorithm=SHA256&digits=8&period=60";
+        let parsed = OtpAuthUri::parse(uri)?;
+
+        assert_eq!(parsed, OtpAuthUri {
+            kind: OtpKind::Totp,
+            issuer: Some("ACME Co".into()),
+            account: "john.doe@email.com".into(),
+            secret: "HXDMVJECJJWSRB3HWIZR4IFUGFTMXBOZ".into(),
+            algorithm: OtpAlgorithm::Sha256,
+            digits: 8,
+            period: 60,
+        });
+
+        Ok(())
+    }
+
+    #[test]
+    fn applies_defaults_and_rejects_garbage() -> Result<()> {
+        let parsed = OtpAuthUri::parse("OTPAUTH://totp/alice?secret=JBSWY3DPEHPK3PXP")?;
+
+        assert_eq!(parsed.issuer, None);
+        assert_eq!(parsed.account, "alice");
+        assert_eq!(parsed.algorithm, OtpAlgorithm::Sha1);
+        assert_eq!((parsed.digits, parsed.period), (6, 30));
+
+        assert!(OtpAuthUri::parse("https://example.com/?secret=JBSWY3DPEHPK3PXP").is_err());
+        assert!(OtpAuthUri::parse("otpauth://totp/alice").is_err());
+        assert!(OtpAuthUri::parse("otpauth://totp/alice?secret=not-base32!").is_err());
+        assert!(OtpAuthUri::parse("otpauth://motp/alice?secret=JBSWY3DPEHPK3PXP").is_err());
+        assert!(OtpAuthUri::parse("otpauth://totp/alice?secret=JBSWY3DPEHPK3PXP&digits=3").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn bare_seed_round_trips_through_uri() -> Result<()> {
+        let built = OtpAuthUri::totp("ACME Co", "john.doe@email.com", " jbsw y3dp ehpk 3pxp ")?;
+        let uri = built.to_uri();
+
+        assert_eq!(
+            uri,
+            "otpauth://totp/ACME%20Co:john.doe%40email.com?secret=JBSWY3DPEHPK3PXP\
+             &issuer=ACME%20Co&algorithm=SHA1&digits=6&period=30",
+        );
+        assert_eq!(OtpAuthUri::parse(&uri)?, built);
+
+        assert!(OtpAuthUri::totp("ACME Co", "john", "hunter2!").is_err());
+        assert!(OtpAuthUri::totp("ACME Co", "john", "  ").is_err());
+
+        Ok(())
+    }
+}
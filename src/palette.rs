@@ -0,0 +1,99 @@
+//! The command palette: every action of the main table, found by typing a few
+//! letters of its name, instead of having to remember its key.
+//!
+//! Matching is fuzzy: the letters of the query must occur in the title of the
+//! command in the same order, but not necessarily next to each other, e.g.
+//! `dup` and `dpl` both find "Duplicate item". Commands with the letters
+//! closer together, and at the beginning of words, are listed first.
+
+use crate::i18n::tr;
+
+
+/// An action, and the key that performs it in the main table.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Command {
+    pub title: &'static str,
+    pub key: char,
+}
+
+/// Every command, in the order they are listed for an empty query.
+pub const COMMANDS: &[Command] = &[
+    Command { title: "Copy secret", key: 'c' },
+    Command { title: "Copy to primary selection", key: 'p' },
+    Command { title: "Show QR code", key: 'r' },
+    Command { title: "Find items", key: 'f' },
+    Command { title: "Select first item", key: '1' },
+    Command { title: "Select last item", key: '0' },
+    Command { title: "Jump to item by label", key: '\'' },
+    Command { title: "New item", key: 'n' },
+    Command { title: "Duplicate item", key: 'N' },
+    Command { title: "New quick note", key: 'm' },
+    Command { title: "Add recovery codes", key: 'b' },
+    Command { title: "Show item details", key: '.' },
+    Command { title: "Show access log", key: 'a' },
+    Command { title: "Vault statistics", key: 'i' },
+    Command { title: "Manage tags", key: 't' },
+    Command { title: "Export item to file", key: 'w' },
+    Command { title: "Select item for export", key: ' ' },
+    Command { title: "Show or hide columns", key: 'v' },
+    Command { title: "Filter items", key: 'l' },
+    Command { title: "Toggle privacy mode", key: 'h' },
+    Command { title: "Upgrade encryption", key: 'u' },
+    Command { title: "Quit", key: 'q' },
+];
+
+/// The commands whose title (in the current locale) matches the query,
+/// best match first. Every command matches an empty query.
+pub fn search(query: &str) -> Vec<Command> {
+    let mut scored: Vec<_> = COMMANDS
+        .iter()
+        .filter_map(|command| Some((score(tr(command.title), query)?, *command)))
+        .collect();
+
+    // the sort is stable, so equally good matches remain in the original order
+    scored.sort_by_key(|&(score, _)| score);
+    scored.into_iter().map(|(_, command)| command).collect()
+}
+
+/// Returns `None` if the characters of `query` don't all occur in `title`, in
+/// order, ignoring case and whitespace. Otherwise, lower is better: skipping
+/// characters costs 1 each, and so does matching in the middle of a word.
+fn score(title: &str, query: &str) -> Option<usize> {
+    let title: Vec<char> = title.chars().flat_map(char::to_lowercase).collect();
+    let mut position = 0;
+    let mut score = 0;
+
+    for wanted in query.chars().filter(|c| !c.is_whitespace()).flat_map(char::to_lowercase) {
+        let offset = title[position..].iter().position(|&c| c == wanted)?;
+        let index = position + offset;
+        let word_start = index == 0 || !title[index - 1].is_alphanumeric();
+
+        score += offset + usize::from(!word_start);
+        position = index + 1;
+    }
+
+    Some(score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{COMMANDS, search};
+
+
+    #[test]
+    fn fuzzy_matches_are_ranked() {
+        let keys = |query| search(query).into_iter().map(|command| command.key).collect::<String>();
+
+        assert_eq!(search("").len(), COMMANDS.len());
+        assert!(search("xyzzy").is_empty());
+
+        // letters may be skipped, but their order matters
+        assert_eq!(keys("dpl"), "N");
+        assert_eq!(keys("ldp"), "");
+
+        // word beginnings beat letters in the middle of words
+        assert!(keys("qr").starts_with('r'));
+        assert!(keys("stats").starts_with('i'));
+        assert_eq!(keys("NEW").get(..2), Some("nm"));
+    }
+}
@@ -0,0 +1,366 @@
+//! Conversion to and from `pass`, the standard Unix password manager, which
+//! keeps every entry in a separate file encrypted with GnuPG, in a directory
+//! tree (`~/.password-store` by default, or `$PASSWORD_STORE_DIR`).
+//!
+//! The first line of an entry is the password, and the rest is free-form, but
+//! by convention, a `login:` (or `username:` or `user:`) line holds the user
+//! name, and pass-otp adds an `otpauth://` line. When importing, the path of
+//! an entry within the store (e.g. `work/github.com`) becomes the label, and
+//! its directories become tags; any other lines become a separate note, like
+//! the notes of Bitwarden logins. Decryption is left to `gpg`, and thus to
+//! `gpg-agent`, which asks for the passphrase of the key as usual.
+//!
+//! When exporting, entries are encrypted for the keys listed in `.gpg-id` at
+//! the root of the store (keys of subdirectories are not supported), and
+//! labels containing slashes become directories. Existing entries are never
+//! overwritten.
+
+use std::env;
+use std::ffi::OsStr;
+use std::fs;
+use std::io::{Write, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use directories::UserDirs;
+use zeroize::Zeroizing;
+use crate::{
+    db::NOTE_TAG,
+    otp::OtpAuthUri,
+    migrate::{ItemKind, ConvertedItem, SkippedRecord, Conversion},
+    interrupt,
+    error::{Error, Result, ResultExt},
+};
+
+
+/// The extension of entry files.
+const ENTRY_EXTENSION: &str = "gpg";
+
+/// The file listing the keys the store is encrypted for, one per line.
+const GPG_ID_FILE: &str = ".gpg-id";
+
+/// The field names of the user name, as used by pass extensions and browser plugins.
+const ACCOUNT_FIELDS: [&str; 3] = ["login", "username", "user"];
+
+/// The store `pass` uses: `$PASSWORD_STORE_DIR`, or `~/.password-store`.
+pub fn default_store_dir() -> Option<PathBuf> {
+    env::var_os("PASSWORD_STORE_DIR")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| UserDirs::new().map(|dirs| dirs.home_dir().join(".password-store")))
+}
+
+/// Decrypts and converts every entry of the store. Entries that can't be
+/// decrypted or converted are collected in `Conversion::skipped`.
+pub fn import(store: &Path) -> Result<Conversion> {
+    let mut paths = Vec::new();
+    find_entries(store, &mut paths).context("Can't read password store")?;
+    paths.sort();
+
+    let mut conversion = Conversion::default();
+
+    for path in &paths {
+        interrupt::check()?;
+
+        let Some(name) = entry_name(store, path) else {
+            conversion.skipped.push(SkippedRecord {
+                name: path.display().to_string(),
+                reason: String::from("its path is not valid UTF-8"),
+            });
+            continue;
+        };
+
+        let converted = gpg(&[OsStr::new("--decrypt"), path.as_os_str()], None)
+            .map_err(|error| error.to_string())
+            .and_then(|plaintext| {
+                let contents = std::str::from_utf8(&plaintext).map_err(|_| String::from("it's not valid UTF-8"))?;
+                convert_entry(&name, contents)
+            });
+
+        match converted {
+            Ok(items) => conversion.items.extend(items),
+            Err(reason) => conversion.skipped.push(SkippedRecord { name, reason }),
+        }
+    }
+
+    Ok(conversion)
+}
+
+/// The keys to encrypt entries for. If `given` is not empty, those are used,
+/// and written to `.gpg-id` if the store doesn't have one yet (creating the
+/// store if necessary). Otherwise, the keys are read from `.gpg-id`.
+pub fn recipients(store: &Path, given: &[String]) -> Result<Vec<String>> {
+    let gpg_id_path = store.join(GPG_ID_FILE);
+
+    if !given.is_empty() {
+        if !gpg_id_path.exists() {
+            fs::create_dir_all(store).context("Can't create password store")?;
+            fs::write(&gpg_id_path, given.join("\n") + "\n").context("Can't write .gpg-id")?;
+        }
+        return Ok(given.to_vec());
+    }
+
+    let text = match fs::read_to_string(&gpg_id_path) {
+        Ok(text) => text,
+        Err(error) if error.kind() == ErrorKind::NotFound => String::new(),
+        Err(error) => return Err(Error::context(error, "Can't read .gpg-id")),
+    };
+    let recipients: Vec<String> = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect();
+
+    if recipients.is_empty() {
+        return Err(Error::PassRecipientRequired);
+    }
+
+    Ok(recipients)
+}
+
+/// The path of the entry file of an item. Slashes in the label separate
+/// directories; components that would escape the store or be hidden (and
+/// thus ignored by `pass`) are made harmless.
+pub fn entry_path(store: &Path, label: &str) -> PathBuf {
+    let mut path = store.to_owned();
+
+    for component in label.split('/') {
+        let component = component.trim().replace('\\', "_");
+        let component = if component.is_empty() || component.starts_with('.') {
+            format!("_{component}")
+        } else {
+            component
+        };
+        path.push(component);
+    }
+
+    let mut file_name = path.file_name().unwrap_or_default().to_owned();
+    file_name.push(".");
+    file_name.push(ENTRY_EXTENSION);
+    path.set_file_name(file_name);
+
+    path
+}
+
+/// Encrypts an item into a new entry file at `path`.
+pub fn write_entry(path: &Path, recipients: &[String], secret: &str, account: Option<&str>) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).context("Can't create directory in password store")?;
+    }
+
+    let mut contents = Zeroizing::new(format!("{secret}\n"));
+
+    if let Some(account) = account {
+        contents.push_str(&format!("login: {account}\n"));
+    }
+
+    let mut args = vec![OsStr::new("--encrypt"), OsStr::new("--no-encrypt-to")];
+
+    for recipient in recipients {
+        args.extend([OsStr::new("--recipient"), OsStr::new(recipient)]);
+    }
+    // gpg refuses to overwrite an existing file in batch mode
+    args.extend([OsStr::new("--output"), path.as_os_str()]);
+
+    gpg(&args, Some(contents.as_bytes()))?;
+
+    Ok(())
+}
+
+/// Collects the paths of the entry files in `dir`, recursively. Hidden files
+/// and directories (e.g. `.git` and `.extensions`) are skipped.
+fn find_entries(dir: &Path, paths: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+
+        if entry.file_type()?.is_dir() {
+            find_entries(&path, paths)?;
+        } else if path.extension().is_some_and(|ext| ext == ENTRY_EXTENSION) {
+            paths.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// The name of the entry, as `pass` shows it, e.g. `work/github.com`.
+fn entry_name(store: &Path, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(store).ok()?.with_extension("");
+    let components = relative
+        .components()
+        .map(|component| component.as_os_str().to_str())
+        .collect::<Option<Vec<_>>>()?;
+
+    Some(components.join("/"))
+}
+
+/// Converts the decrypted contents of an entry into items.
+fn convert_entry(name: &str, contents: &str) -> Result<Vec<ConvertedItem>, String> {
+    let tags: Vec<String> = name.rsplit_once('/').map_or(Vec::new(), |(dirs, _)| {
+        dirs.split('/').filter(|dir| !dir.trim().is_empty()).map(String::from).collect()
+    });
+    let mut lines = contents.lines();
+    let password = lines.next().unwrap_or_default();
+    let mut account = None;
+    let mut otp_uri = None;
+    let mut notes = Vec::new();
+
+    for line in lines {
+        let trimmed = line.trim();
+        let field = trimmed
+            .split_once(':')
+            .filter(|(key, value)| {
+                ACCOUNT_FIELDS.iter().any(|field| key.trim().eq_ignore_ascii_case(field)) && !value.trim().is_empty()
+            })
+            .map(|(_, value)| value.trim());
+
+        match field {
+            Some(value) if account.is_none() => account = Some(value),
+            _ if otp_uri.is_none() && OtpAuthUri::is_otpauth_uri(trimmed) => otp_uri = Some(trimmed),
+            _ => notes.push(line),
+        }
+    }
+
+    let notes = notes.join("\n");
+    let notes = notes.trim();
+    let note_tags = || {
+        let mut tags = tags.clone();
+        tags.push(NOTE_TAG.into());
+        tags
+    };
+
+    // an entry without a password on its first line is just a note
+    if password.trim().is_empty() && otp_uri.is_none() {
+        let text = contents.trim();
+
+        if text.is_empty() {
+            return Err(String::from("the entry is empty"));
+        }
+
+        return Ok(vec![ConvertedItem {
+            kind: ItemKind::Note,
+            label: name.into(),
+            account: None,
+            secret: Zeroizing::new(text.into()),
+            tags: note_tags(),
+            url: None,
+        }]);
+    }
+
+    let mut items = Vec::new();
+
+    if !password.trim().is_empty() {
+        items.push(ConvertedItem {
+            kind: ItemKind::Password,
+            label: name.into(),
+            account: account.map(String::from),
+            secret: Zeroizing::new(password.into()),
+            tags: tags.clone(),
+            url: None,
+        });
+    }
+
+    if let Some(uri) = otp_uri {
+        OtpAuthUri::parse(uri).map_err(|error| error.to_string())?;
+
+        items.push(ConvertedItem {
+            kind: ItemKind::Totp,
+            label: format!("{name} (TOTP)"),
+            account: account.map(String::from),
+            secret: Zeroizing::new(uri.into()),
+            tags: tags.clone(),
+            url: None,
+        });
+    }
+
+    if !notes.is_empty() {
+        items.push(ConvertedItem {
+            kind: ItemKind::Note,
+            label: format!("{name} (notes)"),
+            account: None,
+            secret: Zeroizing::new(notes.into()),
+            tags: note_tags(),
+            url: None,
+        });
+    }
+
+    Ok(items)
+}
+
+/// Runs `gpg` non-interactively (passphrases are asked for by `gpg-agent`),
+/// feeding it `input` (if any), and returns its standard output.
+fn gpg(args: &[&OsStr], input: Option<&[u8]>) -> Result<Zeroizing<Vec<u8>>> {
+    let mut child = Command::new("gpg")
+        .args(["--quiet", "--batch"])
+        .args(args)
+        .stdin(if input.is_some() { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Can't run gpg")?;
+
+    if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+        stdin.write_all(input).context("Can't write to gpg")?;
+    }
+
+    let output = child.wait_with_output().context("Can't run gpg")?;
+    let stdout = Zeroizing::new(output.stdout);
+
+    if output.status.success() {
+        Ok(stdout)
+    } else {
+        Err(Error::Gpg(String::from_utf8_lossy(&output.stderr).trim().into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use crate::migrate::ItemKind;
+    use super::{convert_entry, entry_name, entry_path};
+
+
+    #[test]
+    fn entries_are_split_into_items() -> Result<(), String> {
+        let contents = "hunter2\nlogin: octocat\notpauth://totp/GitHub:octocat?secret=JBSWY3DPEHPK3PXP\n\
+                        url: https://github.com\nSecurity question: blue\n";
+        let items = convert_entry("work/dev/github.com", contents)?;
+        let kinds: Vec<ItemKind> = items.iter().map(|item| item.kind).collect();
+
+        assert_eq!(kinds, [ItemKind::Password, ItemKind::Totp, ItemKind::Note]);
+        assert_eq!(items[0].label, "work/dev/github.com");
+        assert_eq!(items[0].account.as_deref(), Some("octocat"));
+        assert_eq!(items[0].secret.as_str(), "hunter2");
+        assert_eq!(items[0].tags, ["work", "dev"]);
+        assert_eq!(items[1].label, "work/dev/github.com (TOTP)");
+        assert_eq!(items[2].secret.as_str(), "url: https://github.com\nSecurity question: blue");
+        assert_eq!(items[2].tags, ["work", "dev", "note"]);
+
+        let items = convert_entry("wifi", "\nSSID: home\npassword: correct horse\n")?;
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].kind, ItemKind::Note);
+        assert_eq!(items[0].secret.as_str(), "SSID: home\npassword: correct horse");
+
+        assert!(convert_entry("empty", "\n\n").is_err());
+        assert!(convert_entry("bad-otp", "pw\notpauth://totp/x?secret=not-base32!").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn labels_map_to_paths_within_the_store() {
+        let store = Path::new("store");
+
+        assert_eq!(entry_path(store, "work/github.com"), Path::new("store/work/github.com.gpg"));
+        assert_eq!(entry_path(store, "../../etc/passwd"), Path::new("store/_../_../etc/passwd.gpg"));
+        assert_eq!(entry_path(store, ".hidden//x"), Path::new("store/_.hidden/_/x.gpg"));
+
+        let path = entry_path(store, "work/github.com");
+        assert_eq!(entry_name(store, &path).as_deref(), Some("work/github.com"));
+    }
+}
@@ -0,0 +1,303 @@
+//! The generators of new secrets offered by the New item dialog: random
+//! characters, diceware passphrases, i.e. words drawn uniformly at random
+//! from a wordlist, and pronounceable passwords.
+//!
+//! Wordlists are text files with one word per line, such as the long and
+//! short lists of the EFF. A leading dice roll (e.g. `11111 abacus`), as in the
+//! EFF lists, is ignored. Duplicate words are dropped, since they would only
+//! make the passphrase seem stronger than it is.
+//!
+//! Pronounceable passwords are syllables of a consonant and a vowel each, e.g.
+//! `tobarika`, which are easy to read out over the phone, with digits and
+//! symbols inserted between the syllables, if configured. Letters that sound
+//! ambiguous when spelled out or read aloud (`c`, `q`, `w`, `x`, `y`) are not used.
+//!
+//! Every generator knows its entropy, so that the configurations can be
+//! compared: the base 2 logarithm of the number of equally likely outputs.
+
+use std::fs;
+use std::path::Path;
+use std::collections::BTreeSet;
+use rand::{Rng, seq::SliceRandom};
+use zeroize::Zeroizing;
+use crate::{
+    config::{GeneratorConfig, PronounceableConfig},
+    policy::Policy,
+    memlock::SecretString,
+    i18n::tr,
+    error::{Error, Result, ResultExt},
+};
+
+
+/// The consonants and vowels of pronounceable passwords.
+const CONSONANTS: &[u8] = b"bdfghjklmnprstvz";
+const VOWELS: &[u8] = b"aeiou";
+
+/// The digits and symbols inserted into pronounceable passwords. The symbols
+/// have short, unambiguous names.
+const DIGITS: &[u8] = b"0123456789";
+const SYMBOLS: &[u8] = b"!#%+=?@";
+
+/// The words of a diceware wordlist, without duplicates.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Wordlist {
+    words: Vec<String>,
+}
+
+impl Wordlist {
+    /// Reads and parses the wordlist file at `path`.
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path).context("Can't read wordlist")?;
+        Self::parse(&text).ok_or_else(|| Error::InvalidWordlist(path.display().to_string()))
+    }
+
+    /// Returns `None` unless there are at least two distinct words.
+    fn parse(text: &str) -> Option<Self> {
+        let words: BTreeSet<&str> = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(|line| match line.split_once(char::is_whitespace) {
+                Some((roll, word)) if roll.bytes().all(|b| b.is_ascii_digit()) => word.trim(),
+                _ => line,
+            })
+            .collect();
+
+        if words.len() < 2 {
+            return None;
+        }
+
+        Some(Wordlist {
+            words: words.into_iter().map(String::from).collect(),
+        })
+    }
+
+    fn len(&self) -> usize {
+        self.words.len()
+    }
+}
+
+/// Which wordlist a passphrase generator uses; only affects its description.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WordlistKind {
+    Long,
+    Short,
+}
+
+/// A way of generating a new secret.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Generator {
+    /// `PASSWORD_LEN` characters from `PASSWORD_CHARSET`, or as many and
+    /// such characters as the password policy of the item demands.
+    Characters,
+    /// `words` words from the wordlist, joined by `separator`.
+    Passphrase {
+        kind: WordlistKind,
+        wordlist: Wordlist,
+        words: usize,
+        separator: String,
+    },
+    /// `letters` alternating consonants and vowels, plus `digits` digits and
+    /// `symbols` symbols at random syllable boundaries.
+    Pronounceable {
+        letters: usize,
+        digits: usize,
+        symbols: usize,
+    },
+}
+
+impl Generator {
+    /// Random characters, followed by a passphrase generator for each
+    /// configured wordlist, then pronounceable passwords.
+    pub fn from_config(config: &GeneratorConfig) -> Result<Vec<Self>> {
+        let wordlists = [
+            (WordlistKind::Long, config.long_wordlist.as_deref()),
+            (WordlistKind::Short, config.short_wordlist.as_deref()),
+        ];
+        let mut generators = vec![Generator::Characters];
+
+        for (kind, path) in wordlists {
+            let Some(path) = path else {
+                continue;
+            };
+
+            generators.push(Generator::Passphrase {
+                kind,
+                wordlist: Wordlist::load(path)?,
+                words: config.words,
+                separator: config.separator.clone(),
+            });
+        }
+
+        generators.push(Generator::pronounceable(&config.pronounceable));
+
+        Ok(generators)
+    }
+
+    fn pronounceable(config: &PronounceableConfig) -> Self {
+        Generator::Pronounceable {
+            letters: config.length,
+            digits: config.digits,
+            symbols: config.symbols,
+        }
+    }
+
+    /// A short description of the configuration, in the current locale.
+    pub fn description(&self, policy: &Policy) -> String {
+        let (message, count) = match *self {
+            Generator::Characters => ("{} random characters", policy.password_len()),
+            Generator::Passphrase { kind: WordlistKind::Long, words, .. } => ("{} long words", words),
+            Generator::Passphrase { kind: WordlistKind::Short, words, .. } => ("{} short words", words),
+            Generator::Pronounceable { letters, digits, symbols } => {
+                ("{} pronounceable characters", letters + digits + symbols)
+            }
+        };
+
+        tr(message).replacen("{}", &count.to_string(), 1)
+    }
+
+    /// The entropy of the generated secrets, in bits. For pronounceable
+    /// passwords, the positions of digits and symbols are not counted, and
+    /// for random characters, neither are the passwords rejected by the
+    /// policy, so it's an approximation.
+    pub fn entropy_bits(&self, policy: &Policy) -> f64 {
+        let bits = |count: usize, choices: usize| count as f64 * (choices as f64).log2();
+
+        match *self {
+            Generator::Characters => bits(policy.password_len(), policy.charset().len()),
+            Generator::Passphrase { ref wordlist, words, .. } => bits(words, wordlist.len()),
+            Generator::Pronounceable { letters, digits, symbols } => {
+                bits(letters.div_ceil(2), CONSONANTS.len())
+                    + bits(letters / 2, VOWELS.len())
+                    + bits(digits, DIGITS.len())
+                    + bits(symbols, SYMBOLS.len())
+            }
+        }
+    }
+
+    /// Generates a secret. Only random characters are guaranteed to comply
+    /// with the policy; the output of the others has to be checked.
+    pub fn generate(&self, policy: &Policy) -> SecretString {
+        match *self {
+            Generator::Characters => policy.generate_password(),
+            Generator::Passphrase { ref wordlist, words, ref separator, .. } => {
+                generate_passphrase(wordlist, words, separator)
+            }
+            Generator::Pronounceable { letters, digits, symbols } => {
+                generate_pronounceable(letters, digits, symbols)
+            }
+        }
+    }
+}
+
+fn generate_passphrase(wordlist: &Wordlist, words: usize, separator: &str) -> SecretString {
+    // `thread_rng()` returns a CSPRNG.
+    let mut rng = rand::thread_rng();
+    let mut passphrase = SecretString::with_capacity(0);
+
+    for index in 0..words {
+        if index > 0 {
+            passphrase.push_str(separator);
+        }
+        if let Some(word) = wordlist.words.choose(&mut rng) {
+            passphrase.push_str(word);
+        }
+    }
+
+    passphrase
+}
+
+fn generate_pronounceable(letters: usize, digits: usize, symbols: usize) -> SecretString {
+    let mut rng = rand::thread_rng();
+    let syllables = letters.div_ceil(2);
+
+    // the inserted characters, in random order, and the syllable each one precedes
+    // (`syllables` itself meaning the end); both are part of the secret
+    let mut extras: Zeroizing<Vec<u8>> = Zeroizing::new(Vec::with_capacity(digits + symbols));
+    extras.extend((0..digits).filter_map(|_| DIGITS.choose(&mut rng)));
+    extras.extend((0..symbols).filter_map(|_| SYMBOLS.choose(&mut rng)));
+    extras.shuffle(&mut rng);
+
+    let mut slots: Zeroizing<Vec<usize>> = Zeroizing::new(Vec::with_capacity(extras.len()));
+    slots.extend((0..extras.len()).map(|_| rng.gen_range(0..=syllables)));
+    slots.sort_unstable();
+
+    let mut password = SecretString::with_capacity(letters + extras.len());
+    let mut next_extra = 0;
+
+    for syllable in 0..=syllables {
+        while slots.get(next_extra) == Some(&syllable) {
+            password.push(char::from(extras[next_extra]));
+            next_extra += 1;
+        }
+
+        for index in 2 * syllable..(2 * syllable + 2).min(letters) {
+            let choices = if index % 2 == 0 { CONSONANTS } else { VOWELS };
+
+            if let Some(&letter) = choices.choose(&mut rng) {
+                password.push(char::from(letter));
+            }
+        }
+    }
+
+    password
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::policy::Policy;
+    use super::{Wordlist, WordlistKind, Generator, CONSONANTS, VOWELS, DIGITS, SYMBOLS};
+
+
+    #[test]
+    fn wordlists_ignore_dice_rolls_and_duplicates() {
+        let wordlist = Wordlist::parse("11111\tabacus\n11112\tabdomen\n\n11113 abacus\nzebra\r\n").unwrap();
+        assert_eq!(wordlist.words, ["abacus", "abdomen", "zebra"]);
+
+        assert_eq!(Wordlist::parse("11111\tabacus\n11112\tabacus\n"), None);
+        assert_eq!(Wordlist::parse(""), None);
+    }
+
+    #[test]
+    fn passphrases_have_the_configured_shape() {
+        let generator = Generator::Passphrase {
+            kind: WordlistKind::Long,
+            wordlist: Wordlist::parse("alpha\nbravo\ncharlie\ndelta").unwrap(),
+            words: 5,
+            separator: String::from(" + "),
+        };
+        let passphrase = generator.generate(&Policy::default());
+        let words: Vec<_> = passphrase.split(" + ").collect();
+
+        assert_eq!(words.len(), 5);
+        assert!(words.iter().all(|word| ["alpha", "bravo", "charlie", "delta"].contains(word)));
+        assert_eq!(generator.entropy_bits(&Policy::default()), 10.0);
+        assert!(Generator::Characters.entropy_bits(&Policy::default()) > 257.0);
+    }
+
+    #[test]
+    fn pronounceable_passwords_alternate_consonants_and_vowels() {
+        let generator = Generator::Pronounceable { letters: 9, digits: 2, symbols: 1 };
+        let password = generator.generate(&Policy::default());
+        let letters: Vec<u8> = password.bytes().filter(u8::is_ascii_lowercase).collect();
+
+        assert_eq!(password.len(), 12);
+        assert_eq!(password.bytes().filter(|b| DIGITS.contains(b)).count(), 2);
+        assert_eq!(password.bytes().filter(|b| SYMBOLS.contains(b)).count(), 1);
+        assert_eq!(letters.len(), 9);
+
+        for (index, letter) in letters.iter().enumerate() {
+            let expected = if index % 2 == 0 { CONSONANTS } else { VOWELS };
+            assert!(expected.contains(letter), "unexpected letter in {}", password.as_str());
+        }
+
+        // digits and symbols never split a syllable
+        for (index, byte) in password.bytes().enumerate().skip(1) {
+            if VOWELS.contains(&byte) {
+                assert!(CONSONANTS.contains(&password.as_bytes()[index - 1]));
+            }
+        }
+
+        assert_eq!(generator.description(&Policy::default()), "12 pronounceable characters");
+    }
+}
@@ -0,0 +1,62 @@
+//! Percent-encoding (RFC 3986), as used in `otpauth://` URIs and in the requests
+//! of the HTTP API.
+
+/// Encodes every byte except the unreserved characters of RFC 3986.
+pub fn encode(text: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+
+    for byte in text.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+            encoded.push(char::from(byte));
+        } else {
+            encoded.push_str(&format!("%{byte:02X}"));
+        }
+    }
+
+    encoded
+}
+
+/// Decodes `%XX` escapes. Returns `None` if an escape is malformed, or if the
+/// result isn't valid UTF-8. A `+` is left alone: whether it stands for a space
+/// depends on where the text comes from.
+pub fn decode(text: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut iter = text.bytes();
+
+    while let Some(byte) = iter.next() {
+        if byte == b'%' {
+            let hex = [iter.next()?, iter.next()?];
+            let hex = std::str::from_utf8(&hex).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+        } else {
+            bytes.push(byte);
+        }
+    }
+
+    String::from_utf8(bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{encode, decode};
+
+
+    #[test]
+    fn only_unreserved_characters_are_kept() {
+        assert_eq!(encode("Example-Co_1.0~"), "Example-Co_1.0~");
+        assert_eq!(encode("alice@example.com"), "alice%40example.com");
+        assert_eq!(encode("my bank/savings"), "my%20bank%2Fsavings");
+        assert_eq!(encode("étel"), "%C3%A9tel");
+    }
+
+    #[test]
+    fn escapes_are_decoded() {
+        assert_eq!(decode("bank").as_deref(), Some("bank"));
+        assert_eq!(decode("my%20bank%2Fsavings").as_deref(), Some("my bank/savings"));
+        assert_eq!(decode("%C3%A9tel").as_deref(), Some("étel"));
+        assert_eq!(decode("a+b").as_deref(), Some("a+b"));
+        assert_eq!(decode("50%"), None);
+        assert_eq!(decode("%zz"), None);
+        assert_eq!(decode("%FF"), None);
+    }
+}
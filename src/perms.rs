@@ -0,0 +1,292 @@
+//! Keeping the database private to its owner.
+//!
+//! Other users can't decrypt the secrets, but they could read the labels and
+//! account names, or copy the file for guessing passwords offline. Therefore,
+//! the database is created readable only by its owner, in a directory that
+//! only its owner can enter. On Unix, this is done using the permission bits;
+//! on Windows, by removing inherited permissions, and granting access to the
+//! current user only (using `icacls`).
+//!
+//! Upon startup, the database is checked for being accessible to others anyway
+//! (e.g. after being copied around, or when stored on a file system that doesn't
+//! support permissions, such as FAT), and `steelsafe --fix-perms` repairs that.
+//! The check is only implemented on Unix.
+
+use std::fmt::{self, Display, Formatter};
+use std::fs::{self, OpenOptions};
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use rand::Rng;
+use crate::error::{Error, Result};
+
+
+/// A file or directory related to the database that other users can access.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Exposure {
+    pub path: PathBuf,
+    /// The permission bits, e.g. `0o644`.
+    pub mode: u32,
+}
+
+impl Display for Exposure {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        write!(formatter, "{} is accessible to other users (mode {:03o})", self.path.display(), self.mode)
+    }
+}
+
+/// Creates a directory (and its missing parents) that only its owner can enter.
+/// Existing directories are left alone.
+pub fn create_private_dir(path: &Path) -> io::Result<()> {
+    if path.is_dir() {
+        return Ok(());
+    }
+
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+
+    builder.create(path)?;
+
+    #[cfg(windows)]
+    restrict(path, true)?;
+
+    Ok(())
+}
+
+/// Creates a new directory with a random name starting with `prefix` in the
+/// temporary directory, that only its owner can enter. Unlike `create_private_dir`,
+/// this never uses a directory that already exists, because other users could have
+/// created it (or a symlink) at a predictable path, and they would have access to it.
+pub fn create_temp_dir(prefix: &str) -> io::Result<PathBuf> {
+    create_temp_dir_in(&std::env::temp_dir(), prefix)
+}
+
+/// Like `create_temp_dir`, but creates the directory in `parent`, e.g. so that
+/// files prepared in it can be renamed into `parent` without crossing file systems.
+pub fn create_temp_dir_in(parent: &Path, prefix: &str) -> io::Result<PathBuf> {
+    const MAX_ATTEMPTS: usize = 16;
+
+    let mut rng = rand::thread_rng();
+
+    for _ in 0..MAX_ATTEMPTS {
+        let path = parent.join(format!("{prefix}-{:016x}", rng.gen::<u64>()));
+        let mut builder = fs::DirBuilder::new();
+
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+
+        match builder.create(&path) {
+            Ok(()) => {}
+            Err(error) if error.kind() == ErrorKind::AlreadyExists => continue,
+            Err(error) => return Err(error),
+        }
+
+        #[cfg(windows)]
+        restrict(&path, true)?;
+
+        return Ok(path);
+    }
+
+    Err(io::Error::new(ErrorKind::AlreadyExists, "can't find an unused temporary directory name"))
+}
+
+/// Creates an empty file, readable and writable only by its owner, if it
+/// doesn't exist yet, so that SQLite finds it instead of creating it with
+/// the default permissions. (SQLite gives the journal the same permissions.)
+pub fn create_private_file(path: &Path) -> io::Result<()> {
+    match create_new_private_file(path) {
+        Err(error) if error.kind() == ErrorKind::AlreadyExists => Ok(()),
+        result => result,
+    }
+}
+
+/// Creates an empty file, readable and writable only by its owner.
+/// Fails if the file (or a symlink at its path) already exists.
+pub fn create_new_private_file(path: &Path) -> io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    options.open(path)?;
+
+    #[cfg(windows)]
+    restrict(path, false)?;
+
+    Ok(())
+}
+
+/// Returns the database file, its journal, and its directory if other users
+/// can access them.
+pub fn check(db_path: &Path) -> Result<Vec<Exposure>> {
+    let mut exposures = Vec::new();
+
+    for path in related_paths(db_path) {
+        if let Some(mode) = exposed_mode(&path)? {
+            exposures.push(Exposure { path, mode });
+        }
+    }
+
+    Ok(exposures)
+}
+
+/// Revokes the access of other users to the database file, its journal, and its
+/// directory. Returns the paths that have been changed. Fails if the permissions
+/// can't be changed, e.g. because the file system doesn't support them.
+pub fn fix(db_path: &Path) -> Result<Vec<PathBuf>> {
+    let mut fixed = Vec::new();
+
+    for path in related_paths(db_path) {
+        // permissions are only checked on Unix, so elsewhere, they are always set
+        if cfg!(unix) && exposed_mode(&path)?.is_none() {
+            continue;
+        }
+        if !path.exists() {
+            continue;
+        }
+
+        restrict(&path, path.is_dir())?;
+
+        if exposed_mode(&path)?.is_some() {
+            return Err(Error::PermissionsUnchangeable(path.display().to_string()));
+        }
+
+        fixed.push(path);
+    }
+
+    Ok(fixed)
+}
+
+/// The directory of the database, the database file, and the files of the journal.
+fn related_paths(db_path: &Path) -> Vec<PathBuf> {
+    let mut paths = Vec::with_capacity(4);
+
+    if let Some(dir) = db_path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        paths.push(dir.to_owned());
+    }
+
+    paths.push(db_path.to_owned());
+
+    for suffix in ["-wal", "-shm"] {
+        let mut path = db_path.as_os_str().to_owned();
+        path.push(suffix);
+        paths.push(path.into());
+    }
+
+    paths
+}
+
+/// The permission bits of the file if it exists, and anyone but its owner
+/// has any kind of access to it.
+#[cfg(unix)]
+fn exposed_mode(path: &Path) -> Result<Option<u32>> {
+    use std::os::unix::fs::PermissionsExt;
+
+    match fs::metadata(path) {
+        Ok(metadata) => {
+            let mode = metadata.permissions().mode() & 0o777;
+            Ok(Some(mode).filter(|mode| mode & 0o077 != 0))
+        }
+        Err(error) if error.kind() == ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(error.into()),
+    }
+}
+
+#[cfg(not(unix))]
+fn exposed_mode(_path: &Path) -> Result<Option<u32>> {
+    Ok(None)
+}
+
+/// Keeps the owner's permissions, and removes everyone else's.
+#[cfg(unix)]
+fn restrict(path: &Path, _is_dir: bool) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = fs::metadata(path)?.permissions().mode() & 0o700;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+}
+
+/// Removes inherited permissions, and grants full control to the current user.
+#[cfg(windows)]
+fn restrict(path: &Path, is_dir: bool) -> io::Result<()> {
+    use std::process::{Command, Stdio};
+
+    let user = std::env::var("USERNAME").map_err(|error| io::Error::new(ErrorKind::NotFound, error))?;
+    // `(OI)(CI)` makes files and subdirectories created later inherit the grant
+    let grant = if is_dir { format!("{user}:(OI)(CI)F") } else { format!("{user}:F") };
+    let status = Command::new("icacls")
+        .arg(path)
+        .args(["/inheritance:r", "/grant:r", &grant])
+        .stdout(Stdio::null())
+        .status()?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!("icacls exited unsuccessfully ({status})")))
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn restrict(_path: &Path, _is_dir: bool) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::fs;
+    use std::io::ErrorKind;
+    use std::os::unix::fs::PermissionsExt;
+    use crate::error::Result;
+    use super::{check, fix, create_private_dir, create_private_file, create_temp_dir, create_new_private_file};
+
+
+    #[test]
+    fn exposed_files_are_found_and_fixed() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("steelsafe-perms-test-{}", std::process::id()));
+        let db_path = dir.join("secrets.sqlite3");
+
+        create_private_dir(&dir)?;
+        create_private_file(&db_path)?;
+        assert!(check(&db_path)?.is_empty());
+        assert_eq!(fs::metadata(&db_path)?.permissions().mode() & 0o777, 0o600);
+
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o755))?;
+        fs::set_permissions(&db_path, fs::Permissions::from_mode(0o644))?;
+
+        let exposed: Vec<_> = check(&db_path)?.into_iter().map(|exposure| exposure.mode).collect();
+        assert_eq!(exposed, [0o755, 0o644]);
+
+        assert_eq!(fix(&db_path)?, [dir.clone(), db_path.clone()]);
+        assert!(check(&db_path)?.is_empty());
+        assert_eq!(fs::metadata(&db_path)?.permissions().mode() & 0o777, 0o600);
+
+        fs::remove_dir_all(&dir)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn temp_dirs_and_new_files_are_never_reused() -> Result<()> {
+        let first = create_temp_dir("steelsafe-perms-test")?;
+        let second = create_temp_dir("steelsafe-perms-test")?;
+
+        assert_ne!(first, second);
+        assert_eq!(fs::metadata(&first)?.permissions().mode() & 0o777, 0o700);
+
+        let path = first.join("vault.sqlite3");
+        create_new_private_file(&path)?;
+        assert_eq!(fs::metadata(&path)?.permissions().mode() & 0o777, 0o600);
+
+        let error = create_new_private_file(&path).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::AlreadyExists);
+
+        fs::remove_dir_all(&first)?;
+        fs::remove_dir_all(&second)?;
+
+        Ok(())
+    }
+}
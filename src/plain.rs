@@ -0,0 +1,252 @@
+//! A line-oriented alternative to the TUI, for use with screen readers.
+//!
+//! It never switches to the alternate screen, never moves the cursor, and never
+//! uses colors: it reads one command per line from the standard input, and
+//! answers with plain lines of text. Secrets and passwords are read without echo.
+
+use std::io::{self, BufRead, Write};
+use chrono::Utc;
+use zeroize::Zeroizing;
+use crate::{
+    cli,
+    interrupt,
+    i18n::tr,
+    clipboard::{Clipboard, CopyTarget},
+    config::{Config, SizeLimits},
+    crypto::{EncryptionInput, Pepper},
+    fields::{Label, AccountName, PlaintextSecret, MasterPassword},
+    db::{Database, DisplayItem, AddItemInput},
+    query::Query,
+    error::{Error, Result},
+};
+
+
+const HELP: &str = "\
+Commands:
+  list              List every item
+  search QUERY      List the items matching the query, e.g. `search bank tag:work`
+  add               Add a new item; you will be asked for its fields one by one
+  copy TITLE        Copy the secret of the item with this exact title to the clipboard
+  primary TITLE     Copy the secret to the primary selection (middle-click paste)
+  help              Show this list
+  quit              Exit (so does end of input, e.g. Ctrl+D)";
+
+/// A command entered by the user.
+#[derive(Clone, PartialEq, Eq, Debug)]
+enum PlainCommand {
+    List(Option<String>),
+    Add,
+    Copy(String, Option<CopyTarget>),
+    Help,
+    Quit,
+}
+
+impl PlainCommand {
+    /// Parses a line of input. Returns `None` for a blank line.
+    fn parse(line: &str) -> Result<Option<Self>> {
+        let line = line.trim();
+        let (name, argument) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let argument = argument.trim();
+
+        let command = match name.to_lowercase().as_str() {
+            "" => return Ok(None),
+            "list" | "ls" => PlainCommand::List(None),
+            "search" | "find" if !argument.is_empty() => PlainCommand::List(Some(argument.into())),
+            "add" | "new" => PlainCommand::Add,
+            "copy" | "cp" if !argument.is_empty() => PlainCommand::Copy(argument.into(), None),
+            "primary" if !argument.is_empty() => {
+                PlainCommand::Copy(argument.into(), Some(CopyTarget::Primary))
+            }
+            "help" | "?" => PlainCommand::Help,
+            "quit" | "exit" | "q" => PlainCommand::Quit,
+            "search" | "find" | "copy" | "cp" | "primary" => {
+                return Err(Error::InvalidCommand(format!("`{name}` requires an argument")));
+            }
+            _ => return Err(Error::InvalidCommand(format!("unknown command `{name}`"))),
+        };
+
+        Ok(Some(command))
+    }
+}
+
+/// Runs the line-oriented interface until the user quits or the input ends.
+pub fn run(db: &Database, pepper: Option<Pepper>, config: &Config) -> Result<()> {
+    let mut clipboard = Clipboard::new(&config.clipboard);
+    let stdin = io::stdin();
+    let mut line = String::new();
+
+    println!("Steelsafe v{}. Type `help` for the list of commands.", env!("CARGO_PKG_VERSION"));
+
+    loop {
+        print!("steelsafe> ");
+        io::stdout().flush()?;
+
+        line.clear();
+        if stdin.lock().read_line(&mut line)? == 0 {
+            println!();
+            return Ok(());
+        }
+
+        interrupt::check()?;
+
+        let command = match PlainCommand::parse(&line) {
+            Ok(Some(command)) => command,
+            Ok(None) => continue,
+            Err(error) => {
+                println!("{}: {}", tr("Error"), error.localized());
+                continue;
+            }
+        };
+
+        let result = match command {
+            PlainCommand::List(query) => list(db, query.as_deref()),
+            PlainCommand::Add => add(db, pepper.as_ref(), &config.limits),
+            PlainCommand::Copy(label, target) => {
+                let target = target.unwrap_or(config.copy_to);
+                copy(db, config, &mut clipboard, &label, target)
+            }
+            PlainCommand::Help => {
+                println!("{HELP}");
+                Ok(())
+            }
+            PlainCommand::Quit => return Ok(()),
+        };
+
+        if let Err(error) = result {
+            println!("{}: {}", tr("Error"), error.localized());
+        }
+    }
+}
+
+fn list(db: &Database, query: Option<&str>) -> Result<()> {
+    let items = Query::parse(query.unwrap_or_default())?.find_items(db)?;
+
+    match items.len() {
+        0 => println!("No items found."),
+        1 => println!("1 item:"),
+        n => println!("{n} items:"),
+    }
+
+    for item in &items {
+        println!("{}", describe(item));
+    }
+
+    Ok(())
+}
+
+/// A single sentence describing the item, meant to be read out loud.
+fn describe(item: &DisplayItem) -> String {
+    let modified = item.last_modified_at.format("%F %T");
+
+    match item.account.as_deref() {
+        Some(account) => format!("{}, account {}, modified {} UTC", item.label, account, modified),
+        None => format!("{}, no account, modified {} UTC", item.label, modified),
+    }
+}
+
+fn add(db: &Database, pepper: Option<&Pepper>, limits: &SizeLimits) -> Result<()> {
+    let label = Label::new(&prompt("Title: ")?)?;
+    let account = AccountName::optional(&prompt("Account (optional, press Enter to skip): ")?)?;
+
+    let secret = Zeroizing::new(rpassword::prompt_password("Secret (not echoed): ")?);
+
+    if secret.is_empty() {
+        return Err(Error::SecretRequired);
+    }
+
+    limits.check_secret_len(secret.len())?;
+
+    let enc_pass = Zeroizing::new(rpassword::prompt_password("Encryption password: ")?);
+    let master_password = MasterPassword::new(&enc_pass)?;
+    let confirm = Zeroizing::new(rpassword::prompt_password("Confirm encryption password: ")?);
+
+    if enc_pass != confirm {
+        return Err(Error::ConfirmPasswordMismatch);
+    }
+
+    let encryption_input = EncryptionInput {
+        plaintext_secret: &PlaintextSecret::new(secret.as_bytes()),
+        label: &label,
+        account: account.as_ref(),
+        last_modified_at: Utc::now(),
+        pepper,
+    };
+    let encryption_output = encryption_input.encrypt_and_authenticate(&master_password)?;
+
+    db.add_item(AddItemInput::new(
+        &label,
+        account.as_ref(),
+        encryption_input.last_modified_at,
+        &encryption_output,
+    ))?;
+
+    println!("Added {label}.");
+
+    Ok(())
+}
+
+fn copy(
+    db: &Database,
+    config: &Config,
+    clipboard: &mut Clipboard,
+    label: &str,
+    target: CopyTarget,
+) -> Result<()> {
+    let item = db.item_by_label(label)?.ok_or_else(|| Error::ItemNotFound(label.into()))?;
+    let secret = cli::decrypt_item(&item, config)?;
+
+    clipboard.set_text(secret.as_str(), target)?;
+
+    if config.access_log {
+        db.log_access(item.uid)?;
+    }
+
+    let destination = match target {
+        CopyTarget::Clipboard => "the clipboard",
+        CopyTarget::Primary => "the primary selection",
+        CopyTarget::Both => "the clipboard and the primary selection",
+    };
+
+    println!("Copied the secret of {} to {}.", item.label, destination);
+
+    Ok(())
+}
+
+/// Reads a line of visible (echoed) input.
+fn prompt(message: &str) -> Result<String> {
+    print!("{message}");
+    io::stdout().flush()?;
+
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line)?;
+
+    Ok(line)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{clipboard::CopyTarget, error::Result};
+    use super::PlainCommand;
+
+
+    #[test]
+    fn parses_commands() -> Result<()> {
+        assert_eq!(PlainCommand::parse("  \n")?, None);
+        assert_eq!(PlainCommand::parse("list\n")?, Some(PlainCommand::List(None)));
+        assert_eq!(
+            PlainCommand::parse("search  bank tag:work\n")?,
+            Some(PlainCommand::List(Some("bank tag:work".into()))),
+        );
+        assert_eq!(PlainCommand::parse("COPY My Bank")?, Some(PlainCommand::Copy("My Bank".into(), None)));
+        assert_eq!(
+            PlainCommand::parse("primary My Bank")?,
+            Some(PlainCommand::Copy("My Bank".into(), Some(CopyTarget::Primary))),
+        );
+        assert_eq!(PlainCommand::parse("exit")?, Some(PlainCommand::Quit));
+
+        assert!(PlainCommand::parse("copy").is_err());
+        assert!(PlainCommand::parse("frobnicate").is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,243 @@
+//! Password policies: requirements on the secrets of some items, e.g. a
+//! minimum length, or characters that a legacy system doesn't accept.
+//!
+//! Policies are defined in `.steelsaferc`, and each one applies to the items
+//! with a given tag, or of a given type, or both. Every policy that applies
+//! to an item is combined: the longest minimum length wins, and the required
+//! character classes and the banned characters add up. Secrets made by someone
+//! else, i.e. OTP seeds and recovery codes, are never checked.
+//!
+//! The generator of random characters only produces compliant passwords.
+//! Secrets entered by hand (or made by another generator) are checked, and
+//! violations are reported as warnings, but they are still saved; the policy
+//! is a reminder, not a lock.
+
+use std::collections::BTreeSet;
+use serde::Deserialize;
+use crate::{
+    crypto::{self, PASSWORD_CHARSET, PASSWORD_LEN},
+    memlock::SecretString,
+    i18n::tr,
+};
+
+
+/// The definition of a policy in the config.
+#[derive(Clone, Default, Debug, Deserialize)]
+pub struct PasswordPolicy {
+    /// The policy applies to items with this tag, or to every item if not given.
+    #[serde(default)]
+    pub tag: Option<String>,
+    /// The policy applies to items of this type, or to every type if not given.
+    #[serde(default)]
+    pub item_type: Option<ItemType>,
+    /// The minimum number of characters.
+    #[serde(default)]
+    pub min_length: usize,
+    /// Each of these must occur at least once.
+    #[serde(default)]
+    pub require: Vec<CharClass>,
+    /// Characters that must not occur.
+    #[serde(default)]
+    pub banned_chars: String,
+}
+
+impl PasswordPolicy {
+    fn applies_to(&self, item_type: ItemType, tags: &[String]) -> bool {
+        self.item_type.is_none_or(|t| t == item_type)
+            && self.tag.as_ref().is_none_or(|tag| tags.contains(tag))
+    }
+}
+
+/// The kind of an item, as far as policies are concerned.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ItemType {
+    /// An ordinary secret, e.g. a password.
+    Secret,
+    /// A note, i.e. an item tagged `note`.
+    Note,
+}
+
+/// A class of characters that a policy may require.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CharClass {
+    Lowercase,
+    Uppercase,
+    Digit,
+    /// Anything printable that is not a letter or a digit.
+    Symbol,
+}
+
+impl CharClass {
+    fn contains(self, c: char) -> bool {
+        match self {
+            CharClass::Lowercase => c.is_lowercase(),
+            CharClass::Uppercase => c.is_uppercase(),
+            CharClass::Digit => c.is_numeric(),
+            CharClass::Symbol => !c.is_alphanumeric() && !c.is_whitespace() && !c.is_control(),
+        }
+    }
+
+    const fn missing_message(self) -> &'static str {
+        match self {
+            CharClass::Lowercase => "No lowercase letter",
+            CharClass::Uppercase => "No uppercase letter",
+            CharClass::Digit => "No digit",
+            CharClass::Symbol => "No symbol",
+        }
+    }
+}
+
+/// Every policy that applies to an item, combined. The default has no requirements.
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct Policy {
+    min_length: usize,
+    require: BTreeSet<CharClass>,
+    banned: BTreeSet<char>,
+}
+
+impl Policy {
+    /// Combines the policies that apply to an item of the given type and tags.
+    pub fn for_item(policies: &[PasswordPolicy], item_type: ItemType, tags: &[String]) -> Self {
+        policies
+            .iter()
+            .filter(|policy| policy.applies_to(item_type, tags))
+            .fold(Policy::default(), |mut combined, policy| {
+                combined.min_length = combined.min_length.max(policy.min_length);
+                combined.require.extend(&policy.require);
+                combined.banned.extend(policy.banned_chars.chars());
+                combined
+            })
+    }
+
+    /// Describes how the secret violates the policy, in the current locale.
+    /// Empty if the secret complies.
+    pub fn violations(&self, secret: &str) -> Vec<String> {
+        let mut violations = Vec::new();
+
+        if secret.chars().count() < self.min_length {
+            violations.push(tr("Shorter than {} characters").replacen("{}", &self.min_length.to_string(), 1));
+        }
+
+        for class in &self.require {
+            if !secret.chars().any(|c| class.contains(c)) {
+                violations.push(tr(class.missing_message()).to_owned());
+            }
+        }
+
+        let banned: String = self.banned.iter().filter(|&&c| secret.contains(c)).collect();
+
+        if !banned.is_empty() {
+            violations.push(tr("Banned characters: {}").replacen("{}", &banned, 1));
+        }
+
+        violations
+    }
+
+    /// The length of generated passwords: the default, or the minimum length if greater.
+    pub fn password_len(&self) -> usize {
+        PASSWORD_LEN.max(self.min_length)
+    }
+
+    /// The characters generated passwords are made of: the default ones, except the banned ones.
+    pub fn charset(&self) -> Vec<u8> {
+        PASSWORD_CHARSET
+            .iter()
+            .copied()
+            .filter(|&b| !self.banned.contains(&char::from(b)))
+            .collect()
+    }
+
+    /// A random password that complies with the policy, unless it's impossible
+    /// (e.g. a required class is banned entirely), in which case the
+    /// requirements that can't be met are ignored.
+    pub fn generate_password(&self) -> SecretString {
+        let charset = self.charset();
+        let required: Vec<CharClass> = self.require
+            .iter()
+            .copied()
+            .filter(|class| charset.iter().any(|&b| class.contains(char::from(b))))
+            .collect();
+
+        // Rejecting non-compliant passwords keeps the compliant ones equally likely.
+        // With the default length, the first try almost always complies.
+        loop {
+            let password = crypto::generate_password_from(&charset, self.password_len());
+
+            if required.iter().all(|class| password.chars().any(|c| class.contains(c))) {
+                return password;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PasswordPolicy, Policy, ItemType, CharClass};
+
+
+    fn policies() -> Vec<PasswordPolicy> {
+        vec![
+            PasswordPolicy {
+                tag: Some(String::from("bank")),
+                min_length: 8,
+                require: vec![CharClass::Digit],
+                banned_chars: String::from("\"'"),
+                ..PasswordPolicy::default()
+            },
+            PasswordPolicy {
+                item_type: Some(ItemType::Secret),
+                min_length: 12,
+                require: vec![CharClass::Uppercase, CharClass::Digit],
+                ..PasswordPolicy::default()
+            },
+        ]
+    }
+
+    #[test]
+    fn applicable_policies_are_combined() {
+        let tags = [String::from("bank")];
+
+        let policy = Policy::for_item(&policies(), ItemType::Secret, &tags);
+        assert!(policy.violations("Pa55word1234").is_empty());
+        assert_eq!(policy.violations("it's short"), [
+            "Shorter than 12 characters",
+            "No uppercase letter",
+            "No digit",
+            "Banned characters: '",
+        ]);
+
+        // only the tagged policy applies to notes
+        let policy = Policy::for_item(&policies(), ItemType::Note, &tags);
+        assert_eq!(policy.violations("no digits here"), ["No digit"]);
+
+        let policy = Policy::for_item(&policies(), ItemType::Note, &[]);
+        assert_eq!(policy, Policy::default());
+        assert!(policy.violations("").is_empty());
+    }
+
+    #[test]
+    fn generated_passwords_comply() {
+        let policy = Policy::for_item(&policies(), ItemType::Secret, &[String::from("bank")]);
+
+        for _ in 0..100 {
+            assert!(policy.violations(&policy.generate_password()).is_empty());
+        }
+
+        // a required class that is banned entirely is not an infinite loop
+        let impossible = Policy::for_item(
+            &[PasswordPolicy {
+                min_length: 50,
+                require: vec![CharClass::Digit],
+                banned_chars: String::from("0123456789"),
+                ..PasswordPolicy::default()
+            }],
+            ItemType::Secret,
+            &[],
+        );
+        let password = impossible.generate_password();
+        assert_eq!(password.len(), 50);
+        assert_eq!(impossible.violations(&password), ["No digit"]);
+    }
+}
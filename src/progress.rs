@@ -0,0 +1,245 @@
+//! Long-running operations on a worker thread, and a dialog showing their progress.
+//!
+//! The worker reports its progress through a channel, and checks a shared flag
+//! for cancellation between steps. The UI polls the task on every tick of the
+//! event loop, so it keeps redrawing and handling input in the meantime.
+
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Sender, Receiver};
+use ratatui::{
+    buffer::Buffer,
+    layout::{Rect, Constraint, Layout},
+    style::Modifier,
+    widgets::{Widget, Gauge, Paragraph, block::{Block, BorderType}},
+};
+use crate::{
+    config::Theme,
+    text::ellipsize_middle,
+    i18n::tr,
+    error::{Error, Result},
+};
+
+
+/// How far a task has got.
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct Progress {
+    /// The number of steps already finished.
+    pub done: usize,
+    /// The total number of steps.
+    pub total: usize,
+    /// Describes the step in progress, e.g. the label of the item being processed.
+    pub current: String,
+}
+
+impl Progress {
+    /// The finished fraction of the work, between 0 and 1.
+    pub fn ratio(&self) -> f64 {
+        if self.total == 0 {
+            0.0
+        } else {
+            (self.done as f64 / self.total as f64).clamp(0.0, 1.0)
+        }
+    }
+}
+
+/// Passed to the worker, for reporting progress and checking for cancellation.
+#[derive(Debug)]
+pub struct Reporter {
+    sender: Sender<Progress>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Reporter {
+    /// Reports that `done` out of `total` steps are finished, and that
+    /// `current` is the next one. Never fails, even if the UI has gone away.
+    pub fn report(&self, done: usize, total: usize, current: &str) {
+        let _ = self.sender.send(Progress { done, total, current: current.into() });
+    }
+
+    /// Returns `Err(Error::Cancelled)` if the user asked to cancel the task.
+    /// The worker should call this between steps, and stop if it fails.
+    pub fn check_cancelled(&self) -> Result<()> {
+        if self.cancelled.load(Ordering::Relaxed) {
+            Err(Error::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// A long-running operation on a worker thread, producing a `T` when done.
+#[derive(Debug)]
+pub struct Task<T> {
+    title: &'static str,
+    started_at: Instant,
+    progress: Progress,
+    receiver: Receiver<Progress>,
+    cancelled: Arc<AtomicBool>,
+    handle: Option<JoinHandle<Result<T>>>,
+}
+
+impl<T: Send + 'static> Task<T> {
+    /// Runs `work` on a new thread. `title` is a message ID, translated for display.
+    pub fn spawn<F>(title: &'static str, work: F) -> Self
+    where
+        F: FnOnce(&Reporter) -> Result<T> + Send + 'static
+    {
+        let (sender, receiver) = mpsc::channel();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let reporter = Reporter { sender, cancelled: Arc::clone(&cancelled) };
+        let handle = thread::spawn(move || work(&reporter));
+
+        Task {
+            title,
+            started_at: Instant::now(),
+            progress: Progress::default(),
+            receiver,
+            cancelled,
+            handle: Some(handle),
+        }
+    }
+
+    /// The message ID passed to `spawn()`.
+    pub fn title(&self) -> &'static str {
+        self.title
+    }
+
+    /// The time since the task was started, e.g. for animating a spinner.
+    pub fn elapsed(&self) -> Duration {
+        self.started_at.elapsed()
+    }
+
+    /// Asks the worker to stop as soon as possible. The task is still
+    /// running until `poll()` returns its (likely `Error::Cancelled`) result.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Applies the progress reported since the last call, without blocking.
+    /// Returns the result of the worker once it has finished, exactly once.
+    pub fn poll(&mut self) -> Option<Result<T>> {
+        if let Some(progress) = self.receiver.try_iter().last() {
+            self.progress = progress;
+        }
+
+        if !self.handle.as_ref().is_some_and(JoinHandle::is_finished) {
+            return None;
+        }
+
+        let handle = self.handle.take()?;
+
+        match handle.join() {
+            Ok(result) => Some(result),
+            // a panic in the worker is a bug, so it is propagated to the UI thread
+            Err(payload) => std::panic::resume_unwind(payload),
+        }
+    }
+
+    /// The dialog showing the progress of the task.
+    pub fn dialog<'a>(&'a self, theme: &'a Theme) -> ProgressDialog<'a> {
+        ProgressDialog {
+            title: self.title,
+            progress: &self.progress,
+            cancelled: self.is_cancelled(),
+            theme,
+        }
+    }
+}
+
+/// A modal with the title of the task, the step in progress, and a gauge.
+#[derive(Clone, Copy, Debug)]
+pub struct ProgressDialog<'a> {
+    title: &'static str,
+    progress: &'a Progress,
+    cancelled: bool,
+    theme: &'a Theme,
+}
+
+impl ProgressDialog<'_> {
+    /// The height of the dialog, including its borders.
+    pub const HEIGHT: u16 = 4;
+}
+
+impl Widget for ProgressDialog<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let footer = if self.cancelled { tr("Cancelling…") } else { tr("<Esc> Cancel") };
+        let block = Block::bordered()
+            .title(format!(" {} ", tr(self.title)))
+            .title_bottom(format!(" {footer} "))
+            .border_type(BorderType::Rounded)
+            .border_style(self.theme.border().add_modifier(Modifier::BOLD));
+
+        let inner = block.inner(area);
+        block.render(area, buf);
+
+        let [current_area, gauge_area] = Layout::vertical([Constraint::Length(1); 2]).areas(inner);
+        let current = ellipsize_middle(&self.progress.current, usize::from(inner.width));
+
+        Paragraph::new(current)
+            .style(self.theme.default())
+            .render(current_area, buf);
+
+        Gauge::default()
+            .ratio(self.progress.ratio())
+            .label(format!("{}/{}", self.progress.done, self.progress.total))
+            .gauge_style(self.theme.highlight())
+            .use_unicode(true)
+            .render(gauge_area, buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+    use crate::error::{Error, Result};
+    use super::{Task, Progress};
+
+
+    /// Polls the task until it finishes, or panics after a while.
+    fn wait<T: Send + 'static>(task: &mut Task<T>) -> Result<T> {
+        let deadline = Instant::now() + Duration::from_secs(10);
+
+        loop {
+            if let Some(result) = task.poll() {
+                return result;
+            }
+            assert!(Instant::now() < deadline, "task did not finish");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn progress_is_reported_and_tasks_can_be_cancelled() -> Result<()> {
+        let mut task = Task::spawn("Test", |reporter| {
+            reporter.report(1, 2, "first");
+            reporter.report(2, 2, "second");
+            Ok(42)
+        });
+
+        assert_eq!(wait(&mut task)?, 42);
+        assert_eq!(task.progress, Progress { done: 2, total: 2, current: "second".into() });
+        assert_eq!(task.progress.ratio(), 1.0);
+        assert!(task.poll().is_none(), "result must only be returned once");
+
+        let (sender, receiver) = std::sync::mpsc::channel::<()>();
+        let mut task = Task::spawn("Test", move |reporter| {
+            // wait until the task has been cancelled
+            receiver.recv().ok();
+            reporter.check_cancelled()?;
+            Ok(())
+        });
+
+        task.cancel();
+        sender.send(()).ok();
+        assert!(matches!(wait(&mut task), Err(Error::Cancelled)));
+
+        Ok(())
+    }
+}
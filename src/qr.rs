@@ -0,0 +1,182 @@
+//! QR codes of secrets, for scanning them from the screen or from paper,
+//! and reading QR codes from images, e.g. TOTP seeds from screenshots.
+
+use std::fmt::Write;
+use std::path::Path;
+use zeroize::Zeroizing;
+use qrcode::{QrCode, Color};
+use rqrr::PreparedImage;
+use crate::error::{Error, Result};
+
+
+/// The modules (dots) of a QR code, surrounded by a light quiet zone.
+/// The modules encode the secret, so they are zeroized upon drop.
+pub struct QrMatrix {
+    modules: Zeroizing<Vec<bool>>,
+    width: usize,
+}
+
+impl QrMatrix {
+    /// Encodes `data`, with a quiet zone of `quiet_zone` modules on each side.
+    pub fn new(data: &[u8], quiet_zone: usize) -> Result<Self> {
+        let code = QrCode::new(data)?;
+        let code_width = code.width();
+        let width = code_width + 2 * quiet_zone;
+        let mut modules = Zeroizing::new(vec![false; width * width]);
+
+        for y in 0..code_width {
+            for x in 0..code_width {
+                let index = (y + quiet_zone) * width + x + quiet_zone;
+                modules[index] = code[(x, y)] == Color::Dark;
+            }
+        }
+
+        Ok(QrMatrix { modules, width })
+    }
+
+    /// Whether the module at the given position is dark. Positions outside
+    /// of the code are considered light.
+    pub fn is_dark(&self, x: usize, y: usize) -> bool {
+        x < self.width && y < self.width && self.modules[y * self.width + x]
+    }
+
+    /// Renders the code with Unicode half blocks, two rows of modules per line
+    /// of text. Dark modules are drawn with the foreground color.
+    pub fn half_blocks(&self) -> Zeroizing<Vec<String>> {
+        let lines = (0..self.width).step_by(2).map(|y| {
+            (0..self.width).map(|x| {
+                match (self.is_dark(x, y), self.is_dark(x, y + 1)) {
+                    (true, true) => '█',
+                    (true, false) => '▀',
+                    (false, true) => '▄',
+                    (false, false) => ' ',
+                }
+            }).collect()
+        }).collect();
+
+        Zeroizing::new(lines)
+    }
+
+    /// Renders the code as a standalone SVG image, black on white, with each
+    /// module being `module_size` pixels wide.
+    pub fn svg(&self, module_size: usize) -> Zeroizing<String> {
+        let size = self.width * module_size;
+        let mut svg = Zeroizing::new(String::new());
+        let _ = write!(
+            svg,
+            r##"<svg xmlns="http://www.w3.org/2000/svg" width="{size}" height="{size}" viewBox="0 0 {w} {w}" shape-rendering="crispEdges"><rect width="{w}" height="{w}" fill="#fff"/><path fill="#000" d=""##,
+            w = self.width,
+        );
+
+        for y in 0..self.width {
+            for x in 0..self.width {
+                if self.is_dark(x, y) {
+                    let _ = write!(svg, "M{x} {y}h1v1h-1z");
+                }
+            }
+        }
+
+        svg.push_str(r#""/></svg>"#);
+        svg
+    }
+}
+
+/// Formats the credentials of a Wi-Fi network as a `WIFI:` URI, which phones
+/// offer to join when scanning its QR code. WPA covers WPA2 and WPA3 as well;
+/// open networks have no password.
+pub fn wifi_uri(ssid: &str, password: &str) -> Zeroizing<String> {
+    fn escape(out: &mut String, value: &str) {
+        for c in value.chars() {
+            if matches!(c, '\\' | ';' | ',' | ':' | '"') {
+                out.push('\\');
+            }
+            out.push(c);
+        }
+    }
+
+    // reserved up front, so that growing doesn't leave copies of the password behind
+    let mut uri = Zeroizing::new(String::with_capacity(2 * (ssid.len() + password.len()) + 24));
+    uri.push_str("WIFI:S:");
+    escape(&mut uri, ssid);
+
+    if password.is_empty() {
+        uri.push_str(";T:nopass;;");
+    } else {
+        uri.push_str(";T:WPA;P:");
+        escape(&mut uri, password);
+        uri.push_str(";;");
+    }
+
+    uri
+}
+
+/// Decodes the first QR code found in the image (PNG or JPEG) at `path`,
+/// e.g. a screenshot of the code shown on a two-factor authentication setup page.
+pub fn decode_image(path: &Path) -> Result<Zeroizing<String>> {
+    let image = image::open(path)?.to_luma8();
+    let mut prepared = PreparedImage::prepare(image);
+    let grids = prepared.detect_grids();
+    let grid = grids.first().ok_or(Error::QrCodeNotFound)?;
+    let (_metadata, content) = grid.decode()?;
+
+    Ok(Zeroizing::new(content))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use image::{GrayImage, Luma};
+    use crate::error::Error;
+    use crate::perms;
+    use super::{QrMatrix, wifi_uri, decode_image};
+
+
+    #[test]
+    fn quiet_zone_is_light() {
+        let matrix = QrMatrix::new(b"correct horse battery staple", 2).unwrap();
+        let half_blocks = matrix.half_blocks();
+        let width = half_blocks[0].chars().count();
+
+        // version N of the code is 17 + 4N modules wide
+        assert!(width >= 21 + 2 * 2);
+        assert_eq!((width - 2 * 2 - 17) % 4, 0);
+
+        for i in 0..width {
+            for border in [0, 1, width - 2, width - 1] {
+                assert!(!matrix.is_dark(i, border) && !matrix.is_dark(border, i));
+            }
+        }
+
+        // the top left corner of the finder pattern
+        assert!(matrix.is_dark(2, 2));
+        assert!(!matrix.is_dark(width, 2));
+        assert_eq!(half_blocks.len(), width.div_ceil(2));
+        assert!(matrix.svg(4).contains(&format!(r#"width="{}""#, width * 4)));
+    }
+
+    #[test]
+    fn wifi_uri_escapes_special_characters() {
+        assert_eq!(*wifi_uri("Home", "correct horse"), "WIFI:S:Home;T:WPA;P:correct horse;;");
+        assert_eq!(
+            *wifi_uri(r#"Café "5G""#, r"a;b,c:d\e"),
+            r#"WIFI:S:Café \"5G\";T:WPA;P:a\;b\,c\:d\\e;;"#,
+        );
+        assert_eq!(*wifi_uri("Guest", ""), "WIFI:S:Guest;T:nopass;;");
+    }
+
+    #[test]
+    fn image_without_qr_code_is_rejected() {
+        let dir = perms::create_temp_dir("steelsafe-qr-test").unwrap();
+        let blank = dir.join("blank.png");
+        let not_an_image = dir.join("seed.txt");
+
+        GrayImage::from_pixel(64, 64, Luma([255])).save(&blank).unwrap();
+        fs::write(&not_an_image, "otpauth://totp/Example?secret=JBSWY3DPEHPK3PXP").unwrap();
+
+        assert!(matches!(decode_image(&blank), Err(Error::QrCodeNotFound)));
+        assert!(matches!(decode_image(&not_an_image), Err(Error::QrImage(_))));
+        assert!(matches!(decode_image(&dir.join("missing.png")), Err(Error::QrImage(_))));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,288 @@
+//! The query language of the Find panel and of `steelsafe list`.
+//!
+//! A query consists of whitespace-separated terms, all of which must match:
+//!
+//! * `foo` matches items whose label or account name contains `foo`;
+//! * `label:foo` and `account:foo` only look at the label or the account name;
+//! * `tag:foo` matches items tagged `foo`;
+//! * `modified:>2024-01-01` compares the date of the last modification,
+//!   using one of `<`, `<=`, `>`, `>=`, or `=` (the default);
+//! * `expires:<=2024-12-31` likewise compares the expiry date. Items
+//!   without an expiry date never match.
+//!
+//! Text comparisons are case-insensitive, and the text may contain the SQL `LIKE`
+//! wildcards `_` and `%`, matching any one and any number of characters,
+//! respectively. Values containing whitespace can be
+//! surrounded by double quotes, e.g. `label:"my bank"`. Words with a colon but
+//! an unknown key (e.g. `https://example.com`) are treated as plain text.
+//!
+//! The first plain ASCII text term without wildcards is passed to the vault,
+//! in order to narrow down the items loaded from it; all terms are then
+//! checked again in memory.
+
+use std::collections::HashMap;
+use chrono::{DateTime, Utc, NaiveDate};
+use crate::{
+    db::DisplayItem,
+    vault::Vault,
+    error::{Error, Result},
+};
+
+
+/// A parsed query.
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct Query {
+    terms: Vec<Term>,
+}
+
+/// A single filter predicate.
+#[derive(Clone, PartialEq, Eq, Debug)]
+enum Term {
+    /// Label or account name contains the text.
+    Text(String),
+    Label(String),
+    Account(String),
+    Tag(String),
+    Modified(Comparison, NaiveDate),
+    Expires(Comparison, NaiveDate),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Comparison {
+    Less,
+    LessOrEqual,
+    Equal,
+    GreaterOrEqual,
+    Greater,
+}
+
+impl Query {
+    /// Parses a query. An empty (or all-whitespace) query matches every item.
+    pub fn parse(query: &str) -> Result<Self> {
+        let mut terms = Vec::new();
+        let mut rest = query.trim_start();
+
+        while !rest.is_empty() {
+            let (word, remainder) = split_word(rest)?;
+            terms.push(Term::parse(&word)?);
+            rest = remainder.trim_start();
+        }
+
+        Ok(Query { terms })
+    }
+
+    /// Returns `true` if matching requires the tags of the items.
+    fn has_tags(&self) -> bool {
+        self.terms.iter().any(|term| matches!(term, Term::Tag(_)))
+    }
+
+    /// Returns `true` if matching requires the expiry dates of the items.
+    fn has_expiries(&self) -> bool {
+        self.terms.iter().any(|term| matches!(term, Term::Expires(..)))
+    }
+
+    /// Loads the matching items from the database.
+    pub fn find_items(&self, db: &dyn Vault) -> Result<Vec<DisplayItem>> {
+        let mut items = db.list_items_for_display(self.search_text())?;
+        let tags = if self.has_tags() { db.tags_by_item()? } else { HashMap::new() };
+        let expiries = if self.has_expiries() { db.expiries()? } else { HashMap::new() };
+
+        items.retain(|item| self.matches(item, &tags, &expiries));
+
+        Ok(items)
+    }
+
+    /// The text to be passed to `Vault::list_items_for_display()`. The vault
+    /// matches it literally and only ignores ASCII case, so it must not narrow
+    /// down the items by text with wildcards or with non-ASCII letters.
+    fn search_text(&self) -> Option<&str> {
+        self.terms.iter().find_map(|term| match term {
+            Term::Text(text) if text.is_ascii() && !text.contains(['%', '_']) => Some(text.as_str()),
+            _ => None,
+        })
+    }
+
+    /// Checks every term against the item. `tags` and `expiries` map the unique
+    /// IDs of items to their tags and expiry dates; they are only consulted if
+    /// the query has tag or expiry terms, respectively.
+    fn matches(
+        &self,
+        item: &DisplayItem,
+        tags: &HashMap<u64, Vec<String>>,
+        expiries: &HashMap<u64, DateTime<Utc>>,
+    ) -> bool {
+        let account = item.account.as_deref().unwrap_or_default();
+
+        self.terms.iter().all(|term| match term {
+            Term::Text(text) => contains(&item.label, text) || contains(account, text),
+            Term::Label(text) => contains(&item.label, text),
+            Term::Account(text) => contains(account, text),
+            Term::Tag(tag) => tags
+                .get(&item.uid)
+                .is_some_and(|item_tags| item_tags.iter().any(|t| t.eq_ignore_ascii_case(tag))),
+            Term::Modified(cmp, date) => cmp.holds(item.last_modified_at.date_naive(), *date),
+            Term::Expires(cmp, date) => expiries
+                .get(&item.uid)
+                .is_some_and(|expires_at| cmp.holds(expires_at.date_naive(), *date)),
+        })
+    }
+}
+
+impl Term {
+    fn parse(word: &str) -> Result<Self> {
+        let Some((key, value)) = word.split_once(':') else {
+            return Ok(Term::Text(word.into()));
+        };
+
+        let term = match key.to_ascii_lowercase().as_str() {
+            "label" => Term::Label(value.into()),
+            "account" => Term::Account(value.into()),
+            "tag" => Term::Tag(value.trim().into()),
+            "modified" => {
+                let (cmp, date) = Comparison::split(value);
+                Term::Modified(cmp, parse_date(key, date)?)
+            }
+            "expires" => {
+                let (cmp, date) = Comparison::split(value);
+                Term::Expires(cmp, parse_date(key, date)?)
+            }
+            _ => return Ok(Term::Text(word.into())),
+        };
+
+        if value.is_empty() {
+            return Err(invalid(format!("missing value after `{key}:`")));
+        }
+
+        Ok(term)
+    }
+}
+
+impl Comparison {
+    /// Splits the optional comparison operator off of the beginning of the value.
+    fn split(value: &str) -> (Self, &str) {
+        [
+            ("<=", Comparison::LessOrEqual),
+            (">=", Comparison::GreaterOrEqual),
+            ("<", Comparison::Less),
+            (">", Comparison::Greater),
+            ("=", Comparison::Equal),
+        ]
+        .into_iter()
+        .find_map(|(op, cmp)| value.strip_prefix(op).map(|rest| (cmp, rest)))
+        .unwrap_or((Comparison::Equal, value))
+    }
+
+    /// Compares `lhs` to `rhs`, e.g. `Less` means `lhs < rhs`.
+    fn holds(self, lhs: NaiveDate, rhs: NaiveDate) -> bool {
+        match self {
+            Comparison::Less => lhs < rhs,
+            Comparison::LessOrEqual => lhs <= rhs,
+            Comparison::Equal => lhs == rhs,
+            Comparison::GreaterOrEqual => lhs >= rhs,
+            Comparison::Greater => lhs > rhs,
+        }
+    }
+}
+
+fn parse_date(key: &str, date: &str) -> Result<NaiveDate> {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|_| {
+        invalid(format!("expected a date like 2024-01-31 after `{key}:`, got {date:?}"))
+    })
+}
+
+/// Splits off the first word, which ends at unquoted whitespace. Double quotes
+/// group characters (including whitespace) into the word, and are removed.
+fn split_word(s: &str) -> Result<(String, &str)> {
+    let mut word = String::new();
+    let mut in_quotes = false;
+
+    for (idx, c) in s.char_indices() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            c if c.is_whitespace() && !in_quotes => return Ok((word, &s[idx..])),
+            c => word.push(c),
+        }
+    }
+
+    if in_quotes {
+        Err(invalid("unterminated quote".into()))
+    } else {
+        Ok((word, ""))
+    }
+}
+
+/// Case-insensitive substring search, in the same manner as `haystack LIKE '%needle%'`.
+fn contains(haystack: &str, needle: &str) -> bool {
+    let haystack: Vec<char> = haystack.to_lowercase().chars().collect();
+    let pattern: Vec<char> = needle.to_lowercase().chars().collect();
+
+    (0..=haystack.len()).any(|start| matches_prefix(&haystack[start..], &pattern))
+}
+
+/// Returns `true` if a prefix of `haystack` matches the `LIKE` pattern.
+fn matches_prefix(haystack: &[char], pattern: &[char]) -> bool {
+    match pattern.split_first() {
+        None => true,
+        Some((&'%', rest)) => (0..=haystack.len()).any(|skip| matches_prefix(&haystack[skip..], rest)),
+        Some((&p, rest)) => haystack.split_first().is_some_and(|(&h, haystack)| {
+            (p == '_' || p == h) && matches_prefix(haystack, rest)
+        }),
+    }
+}
+
+fn invalid(message: String) -> Error {
+    Error::InvalidQuery(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use chrono::{TimeZone, Utc};
+    use crate::db::DisplayItem;
+    use crate::error::Result;
+    use super::Query;
+
+
+    #[test]
+    fn parses_and_matches_terms() -> Result<()> {
+        let item = DisplayItem {
+            uid: 1,
+            label: "My Bank".into(),
+            account: Some("alice@example.com".into()),
+            last_modified_at: Utc.with_ymd_and_hms(2024, 3, 15, 12, 0, 0).unwrap(),
+            reprompt: false,
+        };
+        let tags = HashMap::from([(1, vec!["Work".to_owned()])]);
+        let expiries = HashMap::from([(1, Utc.with_ymd_and_hms(2024, 12, 31, 0, 0, 0).unwrap())]);
+        let matches = |query: &str| {
+            Query::parse(query).map(|query| query.matches(&item, &tags, &expiries))
+        };
+
+        assert!(matches("")?);
+        assert!(matches("bank")?);
+        assert!(matches("ALICE label:\"my bank\"")?);
+        assert!(matches("account:example tag:work modified:>2024-01-01")?);
+        assert!(matches("modified:2024-03-15 modified:<=2024-03-15")?);
+        assert!(!matches("account:bank")?);
+        assert!(!matches("tag:personal")?);
+        assert!(!matches("bank modified:<2024-03-15")?);
+        assert!(matches("my%bank b_nk")?);
+        assert!(!matches("bank%my")?);
+        assert!(matches("expires:<=2024-12-31 expires:>2024-06-30")?);
+        assert!(!matches("expires:<2024-12-31")?);
+        assert!(!Query::parse("expires:>2000-01-01")?.matches(&item, &tags, &HashMap::new()));
+
+        // unknown keys are plain text
+        assert_eq!(Query::parse("https://example.com")?.search_text(), Some("https://example.com"));
+
+        // the vault can't narrow down by wildcards or non-ASCII case
+        assert_eq!(Query::parse("my%bank b_nk Bäck bank")?.search_text(), Some("bank"));
+        assert_eq!(Query::parse("b_nk")?.search_text(), None);
+
+        assert!(Query::parse("modified:yesterday").is_err());
+        assert!(Query::parse("tag:").is_err());
+        assert!(Query::parse("label:\"unterminated").is_err());
+
+        Ok(())
+    }
+}
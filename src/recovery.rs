@@ -0,0 +1,153 @@
+//! Recovery codes: one-time codes that let you into an account without the
+//! second factor, e.g. after losing your phone.
+//!
+//! They are stored as the secret of an ordinary item, as a Markdown task list,
+//! so that used codes can be ticked off, and copying the whole secret still
+//! gives a readable list:
+//!
+//! ```text
+//! - [x] 1234-5678
+//! - [ ] 8765-4321
+//! ```
+//!
+//! The number of codes left is also stored unencrypted, alongside the item,
+//! so that it can be displayed without asking for the password.
+
+use std::fmt::{self, Debug, Formatter};
+use zeroize::Zeroizing;
+use crate::{
+    memlock::SecretString,
+    error::{Error, Result},
+};
+
+
+/// The prefix of a code that has been used.
+const USED: &str = "- [x]";
+
+/// The prefix of a code that hasn't been used yet.
+const UNUSED: &str = "- [ ]";
+
+/// A list of recovery codes, some of which may have been used.
+pub struct RecoveryCodes {
+    codes: Vec<(Zeroizing<String>, bool)>,
+}
+
+impl RecoveryCodes {
+    /// Parses codes entered by the user, or stored in an item. Lines may be
+    /// ticked off (`- [x]`) or not (`- [ ]`), and plain lines are codes that
+    /// haven't been used. A single plain line is split at whitespace, because
+    /// websites often show the codes side by side.
+    pub fn parse(text: &str) -> Result<Self> {
+        let lines: Vec<&str> = text.lines().map(str::trim).filter(|line| !line.is_empty()).collect();
+
+        let codes: Vec<_> = match lines.as_slice() {
+            [line] if !line.starts_with(USED) && !line.starts_with(UNUSED) => {
+                line.split_whitespace().map(|code| (Zeroizing::new(code.to_owned()), false)).collect()
+            }
+            _ => lines.iter().map(|line| parse_line(line)).collect(),
+        };
+
+        if codes.is_empty() || codes.iter().any(|(code, _)| code.is_empty()) {
+            return Err(Error::RecoveryCodesRequired);
+        }
+
+        Ok(RecoveryCodes { codes })
+    }
+
+    /// The number of codes, including the used ones.
+    pub fn total(&self) -> u32 {
+        u32::try_from(self.codes.len()).unwrap_or(u32::MAX)
+    }
+
+    /// The number of codes that haven't been used yet.
+    pub fn remaining(&self) -> u32 {
+        let count = self.codes.iter().filter(|(_, used)| !used).count();
+        u32::try_from(count).unwrap_or(u32::MAX)
+    }
+
+    /// Ticks off the first code that hasn't been used yet, and returns it.
+    pub fn use_next(&mut self) -> Result<Zeroizing<String>> {
+        let (code, used) = self.codes
+            .iter_mut()
+            .find(|(_, used)| !used)
+            .ok_or(Error::NoRecoveryCodesLeft)?;
+
+        *used = true;
+
+        Ok(code.clone())
+    }
+
+    /// The list in the format stored as the secret of the item.
+    pub fn to_secret(&self) -> SecretString {
+        let mut secret = SecretString::with_capacity(0);
+
+        for (code, used) in &self.codes {
+            if !secret.is_empty() {
+                secret.push('\n');
+            }
+            secret.push_str(if *used { USED } else { UNUSED });
+            secret.push(' ');
+            secret.push_str(code);
+        }
+
+        secret
+    }
+}
+
+impl Debug for RecoveryCodes {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        formatter
+            .debug_struct("RecoveryCodes")
+            .field("total", &self.total())
+            .field("remaining", &self.remaining())
+            .finish_non_exhaustive()
+    }
+}
+
+fn parse_line(line: &str) -> (Zeroizing<String>, bool) {
+    let (code, used) = if let Some(code) = line.strip_prefix(USED) {
+        (code, true)
+    } else if let Some(code) = line.strip_prefix(UNUSED) {
+        (code, false)
+    } else {
+        (line, false)
+    };
+
+    (Zeroizing::new(code.trim().to_owned()), used)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::{Error, Result};
+    use super::RecoveryCodes;
+
+
+    #[test]
+    fn codes_are_ticked_off_in_order() -> Result<()> {
+        let mut codes = RecoveryCodes::parse("1234-5678 8765-4321\n")?;
+        assert_eq!((codes.remaining(), codes.total()), (2, 2));
+
+        assert_eq!(codes.use_next()?.as_str(), "1234-5678");
+        assert_eq!(codes.to_secret().as_str(), "- [x] 1234-5678\n- [ ] 8765-4321");
+
+        // the stored format round-trips
+        let mut codes = RecoveryCodes::parse(&codes.to_secret())?;
+        assert_eq!((codes.remaining(), codes.total()), (1, 2));
+        assert_eq!(codes.use_next()?.as_str(), "8765-4321");
+        assert!(matches!(codes.use_next(), Err(Error::NoRecoveryCodesLeft)));
+        assert_eq!(codes.remaining(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn codes_may_contain_spaces_if_one_per_line() -> Result<()> {
+        let codes = RecoveryCodes::parse("  abcd efgh \n\nijkl mnop\n")?;
+
+        assert_eq!(codes.to_secret().as_str(), "- [ ] abcd efgh\n- [ ] ijkl mnop");
+        assert!(matches!(RecoveryCodes::parse(" \n "), Err(Error::RecoveryCodesRequired)));
+        assert!(matches!(RecoveryCodes::parse("- [ ] \nabc"), Err(Error::RecoveryCodesRequired)));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,182 @@
+//! Audit reports: an inventory of the vault for compliance reviews, with the
+//! label, account, tags, modification and expiry dates, and audit flags of
+//! every item, but no secrets, signed so that it can be verified later.
+//!
+//! The report is JSON of the form `{ "report": {...}, "public_key": "...",
+//! "signature": "..." }`. The signature is Ed25519, over the compact JSON
+//! serialization of `report`, and both the public key and the signature are
+//! in Base64. The key pair is derived from the encryption password like the
+//! keys of items are, with the pepper (if any), and a random salt and KDF
+//! parameters stored in the vault (see `crypto::SigningKeyParams`), so every
+//! report of a vault is signed with the same key, which only someone knowing
+//! the password can use. Compliance teams record the public key once,
+//! then check each report against it with `steelsafe verify-report`.
+
+use serde::{Serialize, Deserialize};
+use chrono::{DateTime, Utc};
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use ed25519_dalek::{Signer, SigningKey, VerifyingKey, Signature, PUBLIC_KEY_LENGTH, SIGNATURE_LENGTH};
+use crate::error::{Error, Result};
+
+
+/// The version of the report format.
+pub const VERSION: u32 = 1;
+
+/// Something about an item that an auditor may want to follow up on.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Flag {
+    /// The expiry date of the item has passed.
+    Expired,
+    /// The secret is encrypted with outdated algorithms (see `upgrade-crypto`).
+    OutdatedEncryption,
+    /// The secret can only be decrypted with the pepper file.
+    Peppered,
+    /// The password is asked for every time, even if the agent is unlocked.
+    Reprompt,
+    /// The item was imported from an inventory, and its secret is still empty.
+    Placeholder,
+    /// Every recovery code of the item has been used.
+    NoRecoveryCodesLeft,
+    /// The item is shared with the members of the team (see `org`).
+    Shared,
+}
+
+/// An item as it appears in the report.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReportItem {
+    pub label: String,
+    pub account: Option<String>,
+    pub last_modified_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub tags: Vec<String>,
+    pub flags: Vec<Flag>,
+}
+
+/// The signed contents of the report.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Report {
+    pub version: u32,
+    pub created_at: DateTime<Utc>,
+    /// The ID of the replica of the vault the report was made of.
+    pub vault: String,
+    pub items: Vec<ReportItem>,
+}
+
+/// The report file. Unknown fields are rejected, so that nothing can be
+/// added to a report without invalidating its signature.
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SignedReport {
+    report: Report,
+    public_key: String,
+    signature: String,
+}
+
+impl Report {
+    /// Signs the report, and returns the contents of the report file.
+    pub fn sign(self, key: &SigningKey) -> Result<String> {
+        let signature = key.sign(&serde_json::to_vec(&self)?);
+        let signed = SignedReport {
+            report: self,
+            public_key: encode_public_key(&key.verifying_key()),
+            signature: BASE64.encode(signature.to_bytes()),
+        };
+        let mut json = serde_json::to_string_pretty(&signed)?;
+
+        json.push('\n');
+
+        Ok(json)
+    }
+}
+
+/// Checks the signature of a report file, and returns the report along with
+/// the public key it was signed with. Whether that key is the one of the
+/// vault is up to the caller to check.
+pub fn verify(text: &str) -> Result<(Report, VerifyingKey)> {
+    let signed: SignedReport = serde_json::from_str(text)
+        .map_err(|error| Error::InvalidReport(error.to_string()))?;
+
+    if signed.report.version > VERSION {
+        return Err(Error::InvalidReport(format!(
+            "version {} is newer than the supported version {}",
+            signed.report.version,
+            VERSION,
+        )));
+    }
+
+    let public_key = decode_public_key(&signed.public_key)?;
+    let signature = BASE64
+        .decode(&signed.signature)
+        .ok()
+        .and_then(|bytes| <[u8; SIGNATURE_LENGTH]>::try_from(bytes).ok())
+        .ok_or_else(|| Error::InvalidReport(String::from("the signature is malformed")))?;
+
+    public_key
+        .verify_strict(&serde_json::to_vec(&signed.report)?, &Signature::from_bytes(&signature))
+        .map_err(|_| Error::ReportSignatureMismatch)?;
+
+    Ok((signed.report, public_key))
+}
+
+/// The Base64 form of the public key, as it appears in reports.
+pub fn encode_public_key(key: &VerifyingKey) -> String {
+    BASE64.encode(key.as_bytes())
+}
+
+/// Parses the Base64 form of a public key.
+pub fn decode_public_key(text: &str) -> Result<VerifyingKey> {
+    BASE64
+        .decode(text.trim())
+        .ok()
+        .and_then(|bytes| <[u8; PUBLIC_KEY_LENGTH]>::try_from(bytes).ok())
+        .and_then(|bytes| VerifyingKey::from_bytes(&bytes).ok())
+        .ok_or_else(|| Error::InvalidReport(format!("invalid public key {text:?}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use crate::crypto::{KdfParams, SigningKeyParams};
+    use crate::error::{Error, Result};
+    use super::{Report, ReportItem, Flag, VERSION, verify};
+
+
+    #[test]
+    fn altered_reports_fail_verification() -> Result<()> {
+        let params = SigningKeyParams { salt: [7; 16], kdf: KdfParams::CURRENT, peppered: false };
+        let key = params.derive_key(b"correct horse battery staple", None)?;
+        let report = Report {
+            version: VERSION,
+            created_at: Utc::now(),
+            vault: String::from("0123456789abcdef"),
+            items: vec![ReportItem {
+                label: String::from("Bank"),
+                account: Some(String::from("alice")),
+                last_modified_at: Utc::now(),
+                expires_at: None,
+                tags: vec![String::from("finance")],
+                flags: vec![Flag::Expired, Flag::Reprompt],
+            }],
+        };
+        let text = report.clone().sign(&key)?;
+        let (verified, public_key) = verify(&text)?;
+
+        assert_eq!(verified, report);
+        assert_eq!(public_key, key.verifying_key());
+
+        // the same password and parameters always give the same key
+        let again = params.derive_key(b"correct horse battery staple", None)?;
+        assert_eq!(again.verifying_key(), public_key);
+
+        let altered = text.replace("\"expired\",", "");
+        assert!(matches!(verify(&altered), Err(Error::ReportSignatureMismatch)));
+
+        let extended = text.replacen("\"report\": {", "\"report\": {\n    \"note\": \"all good\",", 1);
+        assert!(matches!(verify(&extended), Err(Error::InvalidReport(_))));
+
+        Ok(())
+    }
+}
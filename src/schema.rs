@@ -0,0 +1,474 @@
+//! Validation of `.steelsaferc`, with a helpful message for every mistake.
+//!
+//! Deserialization stops at the first error, and it ignores unknown keys, so a
+//! misspelled setting would silently have no effect. Therefore, the JSON is first
+//! checked against the description of the settings below, which reports each
+//! unknown key (suggesting the most similar known one), invalid color or name,
+//! and out-of-range number, along with its path, e.g. `theme.border.fg`.
+//!
+//! The description **must** be kept in sync with the types in `config.rs`.
+
+use std::fmt::{self, Display, Formatter};
+use serde_json::{Value, Map};
+use ratatui::style::Color;
+
+
+/// A single mistake in the configuration.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Diagnostic {
+    /// Where the mistake is, e.g. `columns[1].kind`. Empty for the top level.
+    pub path: String,
+    pub message: String,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        if self.path.is_empty() {
+            formatter.write_str(&self.message)
+        } else {
+            write!(formatter, "`{}`: {}", self.path, self.message)
+        }
+    }
+}
+
+/// Every mistake found in the configuration, displayed one per line.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Diagnostics(pub Vec<Diagnostic>);
+
+impl Display for Diagnostics {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        for (index, diagnostic) in self.0.iter().enumerate() {
+            if index > 0 {
+                formatter.write_str("\n")?;
+            }
+            write!(formatter, "  {diagnostic}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks the contents of `.steelsaferc`, and returns every mistake found.
+pub fn check_config(value: &Value) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    CONFIG.check(value, "", &mut diagnostics);
+    diagnostics
+}
+
+/// The expected shape of a JSON value.
+#[derive(Clone, Copy, Debug)]
+enum Schema {
+    Bool,
+    String,
+    /// A non-negative integer between the bounds, inclusive.
+    Integer(u64, u64),
+    /// Anything that ratatui accepts as a color.
+    Color,
+    /// One of the listed strings, e.g. a unit variant of an enum.
+    Name(&'static [&'static str]),
+    /// `null`, or a value of the inner schema. Only `Option`s accept `null`.
+    Nullable(&'static Schema),
+    /// An object key that must be present.
+    Required(&'static Schema),
+    Array(&'static Schema),
+    Object(&'static [(&'static str, Schema)]),
+    /// An object with exactly one of the listed keys, e.g. `{ "min": 24 }`.
+    OneKeyOf(&'static [(&'static str, Schema)]),
+    /// An object whose key named by the first field selects the rest of the keys.
+    Tagged(&'static str, &'static [(&'static str, &'static [(&'static str, Schema)])]),
+}
+
+const COLOR_NAMES: &[&str] = &[
+    "reset", "black", "red", "green", "yellow", "blue", "magenta", "cyan", "gray", "dark_gray",
+    "light_red", "light_green", "light_yellow", "light_blue", "light_magenta", "light_cyan", "white",
+];
+
+const COLOR_PAIR: &[(&str, Schema)] = &[
+    ("bg", Schema::Nullable(&Schema::Color)),
+    ("fg", Schema::Nullable(&Schema::Color)),
+];
+
+const THEME: &[(&str, Schema)] = &[
+    ("default", Schema::Object(COLOR_PAIR)),
+    ("highlight", Schema::Object(COLOR_PAIR)),
+    ("border", Schema::Object(COLOR_PAIR)),
+    ("border_highlight", Schema::Object(COLOR_PAIR)),
+    ("error", Schema::Object(COLOR_PAIR)),
+];
+
+const DB_OPTIONS: &[(&str, Schema)] = &[
+    ("busy_timeout_ms", Schema::Nullable(&Schema::Integer(0, u64::MAX))),
+];
+
+const PASSWORD_COMMAND: &[(&str, Schema)] = &[
+    ("command", Schema::Required(&Schema::Array(&Schema::String))),
+    ("tui", Schema::Bool),
+];
+
+const GIT_SYNC: &[(&str, Schema)] = &[
+    ("repo", Schema::Required(&Schema::String)),
+    ("bundle_file_name", Schema::Nullable(&Schema::String)),
+    ("password_command", Schema::Nullable(&Schema::Object(PASSWORD_COMMAND))),
+];
+
+const WEBDAV: &[(&str, Schema)] = &[
+    ("url", Schema::Required(&Schema::String)),
+];
+
+const S3: &[(&str, Schema)] = &[
+    ("endpoint", Schema::Required(&Schema::String)),
+    ("region", Schema::Required(&Schema::String)),
+    ("bucket", Schema::Required(&Schema::String)),
+    ("prefix", Schema::Nullable(&Schema::String)),
+];
+
+const REMOTE_BACKUP: &[(&str, Schema)] = &[
+    ("target", Schema::Required(&Schema::Tagged("kind", &[("web_dav", WEBDAV), ("s3", S3)]))),
+    ("credentials_item", Schema::Required(&Schema::String)),
+    ("interval_days", Schema::Nullable(&Schema::Integer(0, u32::MAX as u64))),
+];
+
+const AGENT: &[(&str, Schema)] = &[
+    ("socket", Schema::Nullable(&Schema::String)),
+    ("ttl_secs", Schema::Nullable(&Schema::Integer(1, u64::MAX))),
+];
+
+const TIMESTAMPS: &[(&str, Schema)] = &[
+    ("local_time", Schema::Bool),
+    ("relative", Schema::Bool),
+    ("format", Schema::Nullable(&Schema::String)),
+];
+
+const COLUMN_WIDTH: &[(&str, Schema)] = &[
+    ("length", Schema::Integer(0, u16::MAX as u64)),
+    ("min", Schema::Integer(0, u16::MAX as u64)),
+    ("max", Schema::Integer(0, u16::MAX as u64)),
+    ("percentage", Schema::Integer(0, 100)),
+    ("fill", Schema::Integer(0, u16::MAX as u64)),
+];
+
+const COLUMN: &[(&str, Schema)] = &[
+    ("kind", Schema::Required(&Schema::Name(&[
+        "label", "account", "modified", "tags", "recovery_codes", "expiry",
+    ]))),
+    ("width", Schema::Nullable(&Schema::OneKeyOf(COLUMN_WIDTH))),
+];
+
+const SORT_KEY: Schema = Schema::Name(&["added", "label", "account", "modified"]);
+
+const SORT: &[(&str, Schema)] = &[
+    ("by", SORT_KEY),
+    ("then_by", SORT_KEY),
+];
+
+const PANEL: &[(&str, Schema)] = &[
+    ("position", Schema::Name(&["top", "bottom"])),
+    ("height", Schema::Integer(3, u16::MAX as u64)),
+];
+
+const LIMITS: &[(&str, Schema)] = &[
+    ("max_secret_bytes", Schema::Integer(1, usize::MAX as u64)),
+    ("max_import_bytes", Schema::Integer(1, u64::MAX)),
+];
+
+const EXPIRY: &[(&str, Schema)] = &[
+    ("warning_days", Schema::Integer(0, u32::MAX as u64)),
+];
+
+const PRONOUNCEABLE: &[(&str, Schema)] = &[
+    ("length", Schema::Integer(1, 128)),
+    ("digits", Schema::Integer(0, 32)),
+    ("symbols", Schema::Integer(0, 32)),
+];
+
+const GENERATOR: &[(&str, Schema)] = &[
+    ("long_wordlist", Schema::Nullable(&Schema::String)),
+    ("short_wordlist", Schema::Nullable(&Schema::String)),
+    ("words", Schema::Integer(1, 64)),
+    ("separator", Schema::String),
+    ("pronounceable", Schema::Object(PRONOUNCEABLE)),
+];
+
+const POLL: &[(&str, Schema)] = &[
+    ("active_ms", Schema::Nullable(&Schema::Integer(1, u64::MAX))),
+    ("idle_ms", Schema::Nullable(&Schema::Integer(1, u64::MAX))),
+    ("idle_after_ms", Schema::Nullable(&Schema::Integer(0, u64::MAX))),
+];
+
+const POLICY: &[(&str, Schema)] = &[
+    ("tag", Schema::Nullable(&Schema::String)),
+    ("item_type", Schema::Nullable(&Schema::Name(&["secret", "note"]))),
+    ("min_length", Schema::Integer(0, 1024)),
+    ("require", Schema::Array(&Schema::Name(&["lowercase", "uppercase", "digit", "symbol"]))),
+    ("banned_chars", Schema::String),
+];
+
+const CONFIG: Schema = Schema::Object(&[
+    ("theme", Schema::Object(THEME)),
+    ("database", Schema::Nullable(&Schema::String)),
+    ("db_options", Schema::Object(DB_OPTIONS)),
+    ("pepper_file", Schema::Nullable(&Schema::String)),
+    ("identity_file", Schema::Nullable(&Schema::String)),
+    ("persist_failed_attempts", Schema::Bool),
+    ("password_command", Schema::Nullable(&Schema::Object(PASSWORD_COMMAND))),
+    ("access_log", Schema::Bool),
+    ("git_sync", Schema::Nullable(&Schema::Object(GIT_SYNC))),
+    ("remote_backup", Schema::Nullable(&Schema::Object(REMOTE_BACKUP))),
+    ("agent", Schema::Object(AGENT)),
+    ("timestamps", Schema::Object(TIMESTAMPS)),
+    ("columns", Schema::Array(&Schema::Object(COLUMN))),
+    ("sort", Schema::Object(SORT)),
+    ("panel", Schema::Object(PANEL)),
+    ("limits", Schema::Object(LIMITS)),
+    ("expiry", Schema::Object(EXPIRY)),
+    ("locale", Schema::Nullable(&Schema::Name(&["en", "de"]))),
+    ("clipboard", Schema::Array(&Schema::Name(&["arboard", "wl_copy", "xclip", "tmux", "osc52"]))),
+    ("copy_to", Schema::Name(&["clipboard", "primary", "both"])),
+    ("network", Schema::Nullable(&Schema::Bool)),
+    ("lock_on_focus_loss", Schema::Name(&["off", "keypress", "password"])),
+    ("poll", Schema::Object(POLL)),
+    ("generator", Schema::Object(GENERATOR)),
+    ("policies", Schema::Array(&Schema::Object(POLICY))),
+]);
+
+impl Schema {
+    fn check(self, value: &Value, path: &str, diagnostics: &mut Vec<Diagnostic>) {
+        let mut report = |message: String| {
+            diagnostics.push(Diagnostic { path: path.to_owned(), message });
+        };
+
+        match (self, value) {
+            (Schema::Nullable(_), Value::Null) => {}
+            (Schema::Nullable(inner) | Schema::Required(inner), _) => inner.check(value, path, diagnostics),
+            (Schema::Bool, Value::Bool(_)) | (Schema::String, Value::String(_)) => {}
+            (Schema::Integer(min, max), Value::Number(number)) => {
+                if !number.as_u64().is_some_and(|n| (min..=max).contains(&n)) {
+                    report(format!("expected an integer between {min} and {max}, got {number}"));
+                }
+            }
+            (Schema::Color, _) => {
+                if serde_json::from_value::<Color>(value.clone()).is_err() {
+                    let mut message = format!(
+                        "invalid color {value}; expected a name such as \"light_red\", \
+                         an index such as \"42\", or a hex code such as \"#ff8000\""
+                    );
+                    if let Some(name) = value.as_str().and_then(|s| suggest(s, COLOR_NAMES.iter().copied())) {
+                        message.push_str(&format!("; did you mean \"{name}\"?"));
+                    }
+                    report(message);
+                }
+            }
+            (Schema::Name(names), Value::String(name)) => {
+                if !names.contains(&name.as_str()) {
+                    report(unknown(&format!("value {name:?}"), name, names.iter().copied()));
+                }
+            }
+            (Schema::Array(item), Value::Array(items)) => {
+                for (index, item_value) in items.iter().enumerate() {
+                    item.check(item_value, &format!("{path}[{index}]"), diagnostics);
+                }
+            }
+            (Schema::Object(fields), Value::Object(map)) => {
+                check_fields(fields, map, path, None, diagnostics);
+            }
+            (Schema::OneKeyOf(fields), Value::Object(map)) => {
+                if map.len() == 1 {
+                    check_fields(fields, map, path, None, diagnostics);
+                } else {
+                    report(format!("expected exactly one of the keys {}", list(fields.iter().map(|&(key, _)| key))));
+                }
+            }
+            (Schema::Tagged(tag, variants), Value::Object(map)) => {
+                let Some(kind) = map.get(tag) else {
+                    return report(format!("missing required key `{tag}`"));
+                };
+                let Some(kind) = kind.as_str() else {
+                    let tag_path = join(path, tag);
+                    return Schema::String.check(kind, &tag_path, diagnostics);
+                };
+
+                match variants.iter().find(|&&(name, _)| name == kind) {
+                    Some(&(_, fields)) => check_fields(fields, map, path, Some(tag), diagnostics),
+                    None => diagnostics.push(Diagnostic {
+                        path: join(path, tag),
+                        message: unknown(&format!("value {kind:?}"), kind, variants.iter().map(|&(name, _)| name)),
+                    }),
+                }
+            }
+            (_, _) => {
+                report(format!("expected {}, got {}", self.expected(), describe(value)));
+            }
+        }
+    }
+
+    /// Describes the expected value, for error messages.
+    fn expected(self) -> String {
+        match self {
+            Schema::Bool => "`true` or `false`".into(),
+            Schema::String => "a string".into(),
+            Schema::Integer(..) => "an integer".into(),
+            Schema::Color => "a color".into(),
+            Schema::Name(names) => format!("one of {}", list(names.iter().copied())),
+            Schema::Nullable(inner) => format!("{} or `null`", inner.expected()),
+            Schema::Required(inner) => inner.expected(),
+            Schema::Array(_) => "an array".into(),
+            Schema::Object(_) | Schema::OneKeyOf(_) | Schema::Tagged(..) => "an object".into(),
+        }
+    }
+}
+
+/// Checks the known keys of an object, and reports the unknown and the missing ones.
+/// The key `ignored` (the tag of a tagged object) has already been checked.
+fn check_fields(
+    fields: &[(&'static str, Schema)],
+    map: &Map<String, Value>,
+    path: &str,
+    ignored: Option<&str>,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    for (key, value) in map {
+        if Some(key.as_str()) == ignored {
+            continue;
+        }
+
+        let key_path = join(path, key);
+
+        match fields.iter().find(|&&(name, _)| name == key.as_str()) {
+            Some(&(_, schema)) => schema.check(value, &key_path, diagnostics),
+            None => diagnostics.push(Diagnostic {
+                path: key_path,
+                message: unknown("key", key, fields.iter().map(|&(name, _)| name)),
+            }),
+        }
+    }
+
+    for &(name, schema) in fields {
+        if matches!(schema, Schema::Required(_)) && !map.contains_key(name) {
+            diagnostics.push(Diagnostic {
+                path: path.to_owned(),
+                message: format!("missing required key `{name}`"),
+            });
+        }
+    }
+}
+
+/// "unknown key; did you mean `border_highlight`?"
+fn unknown<I>(what: &str, actual: &str, known: I) -> String
+where
+    I: IntoIterator<Item = &'static str> + Clone,
+{
+    match suggest(actual, known.clone()) {
+        Some(suggestion) => format!("unknown {what}; did you mean `{suggestion}`?"),
+        None => format!("unknown {what}; expected one of {}", list(known)),
+    }
+}
+
+/// Returns the known word most similar to `actual`, if any is similar enough,
+/// i.e. at most a third of its characters need to be changed, but at least one.
+fn suggest<I>(actual: &str, known: I) -> Option<&'static str>
+where
+    I: IntoIterator<Item = &'static str>,
+{
+    let actual = actual.to_lowercase();
+    let max_distance = (actual.chars().count() / 3).max(1);
+
+    known
+        .into_iter()
+        .map(|word| (edit_distance(&actual, word), word))
+        .filter(|&(distance, _)| distance <= max_distance)
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, word)| word)
+}
+
+/// The Levenshtein distance of two strings, counting in `char`s.
+fn edit_distance(lhs: &str, rhs: &str) -> usize {
+    let rhs: Vec<char> = rhs.chars().collect();
+    let mut previous: Vec<usize> = (0..=rhs.len()).collect();
+
+    for (i, l) in lhs.chars().enumerate() {
+        let mut current = vec![i + 1];
+
+        for (j, &r) in rhs.iter().enumerate() {
+            let substitution = previous[j] + usize::from(l != r);
+            let deletion = previous[j + 1] + 1;
+            let insertion = current[j] + 1;
+            current.push(substitution.min(deletion).min(insertion));
+        }
+
+        previous = current;
+    }
+
+    previous[rhs.len()]
+}
+
+fn join(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_owned()
+    } else {
+        format!("{path}.{key}")
+    }
+}
+
+fn list<I: IntoIterator<Item = &'static str>>(words: I) -> String {
+    words.into_iter().map(|word| format!("`{word}`")).collect::<Vec<_>>().join(", ")
+}
+
+fn describe(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "`null`",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+    use crate::error::Result;
+    use super::{check_config, Diagnostic};
+
+
+    #[test]
+    fn mistakes_are_reported_with_paths_and_suggestions() -> Result<()> {
+        let example: Value = serde_json::from_str(include_str!("../.steelsaferc"))?;
+        assert!(check_config(&example).is_empty());
+
+        let config = json!({
+            "theme": {
+                "border_higlight": { "fg": "cyan" },
+                "error": { "bg": "ligth_red", "fg": "#ff0000" },
+            },
+            "columns": [
+                { "kind": "label", "width": { "percentage": 140 } },
+                { "kind": "acount" },
+                { "width": { "min": 10, "max": 20 } },
+            ],
+            "git_sync": {},
+            "remote_backup": {
+                "target": { "kind": "s3", "endpoint": "https://s3.example.com", "bucket": "b" },
+                "credentials_item": "S3",
+            },
+            "locale": "fr",
+            "access_log": "yes",
+        });
+        let diagnostics: Vec<String> = check_config(&config).iter().map(Diagnostic::to_string).collect();
+
+        assert_eq!(diagnostics, [
+            "`access_log`: expected `true` or `false`, got a string",
+            "`columns[0].width.percentage`: expected an integer between 0 and 100, got 140",
+            "`columns[1].kind`: unknown value \"acount\"; did you mean `account`?",
+            "`columns[2].width`: expected exactly one of the keys `length`, `min`, `max`, `percentage`, `fill`",
+            "`columns[2]`: missing required key `kind`",
+            "`git_sync`: missing required key `repo`",
+            "`locale`: unknown value \"fr\"; expected one of `en`, `de`",
+            "`remote_backup.target`: missing required key `region`",
+            "`theme.border_higlight`: unknown key; did you mean `border_highlight`?",
+            "`theme.error.bg`: invalid color \"ligth_red\"; expected a name such as \"light_red\", \
+             an index such as \"42\", or a hex code such as \"#ff8000\"; did you mean \"light_red\"?",
+        ]);
+
+        Ok(())
+    }
+}
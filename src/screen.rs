@@ -9,10 +9,15 @@ use ratatui::{
     backend::CrosstermBackend,
     crossterm::{
         terminal::{self, EnterAlternateScreen, LeaveAlternateScreen},
-        event::{EnableMouseCapture, DisableMouseCapture},
+        event::{
+            EnableMouseCapture, DisableMouseCapture, EnableBracketedPaste, DisableBracketedPaste,
+            EnableFocusChange, DisableFocusChange,
+        },
         ExecutableCommand,
     },
 };
+#[cfg(unix)]
+use signal_hook::consts::SIGTSTP;
 use crate::error::{Error, Result};
 
 
@@ -34,18 +39,8 @@ impl ScreenGuard {
                 return None;
             }
 
-            if let Err(error) = terminal::enable_raw_mode() {
-                result = Err(error.into());
-                return None;
-            }
-
-            if let Err(error) = io::stdout().execute(EnterAlternateScreen) {
-                result = Err(error.into());
-                return None;
-            }
-
-            if let Err(error) = io::stdout().execute(EnableMouseCapture) {
-                result = Err(error.into());
+            if let Err(error) = enter_tui_mode() {
+                result = Err(error);
                 return None;
             }
 
@@ -70,10 +65,21 @@ impl ScreenGuard {
     }
     */
 
+    /// Stops the process like `Ctrl+Z` normally does, after restoring the
+    /// terminal for the shell. Once the process is continued (e.g. by `fg`),
+    /// the terminal is set up again, and the next frame is drawn in full.
+    #[cfg(unix)]
+    pub fn suspend(&mut self) -> Result<()> {
+        leave_tui_mode()?;
+        // returns only after SIGCONT
+        signal_hook::low_level::emulate_default_handler(SIGTSTP)?;
+        enter_tui_mode()?;
+        self.terminal.clear()?;
+        Ok(())
+    }
+
     fn finalize(&mut self) -> Result<()> {
-        terminal::disable_raw_mode()?;
-        io::stdout().execute(DisableMouseCapture)?;
-        io::stdout().execute(LeaveAlternateScreen)?;
+        leave_tui_mode()?;
         IS_OPEN.store(false, Ordering::SeqCst);
         Ok(())
     }
@@ -100,3 +106,25 @@ impl Drop for ScreenGuard {
         }
     }
 }
+
+/// Switches to raw mode and the alternate screen, and enables mouse events.
+fn enter_tui_mode() -> Result<()> {
+    terminal::enable_raw_mode()?;
+    io::stdout().execute(EnterAlternateScreen)?;
+    io::stdout().execute(EnableMouseCapture)?;
+    // Pasted text arrives as a single event instead of one per character.
+    io::stdout().execute(EnableBracketedPaste)?;
+    // Needed for `lock_on_focus_loss`; terminals that don't support it ignore it.
+    io::stdout().execute(EnableFocusChange)?;
+    Ok(())
+}
+
+/// Undoes `enter_tui_mode()`.
+fn leave_tui_mode() -> Result<()> {
+    terminal::disable_raw_mode()?;
+    io::stdout().execute(DisableFocusChange)?;
+    io::stdout().execute(DisableBracketedPaste)?;
+    io::stdout().execute(DisableMouseCapture)?;
+    io::stdout().execute(LeaveAlternateScreen)?;
+    Ok(())
+}
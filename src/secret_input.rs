@@ -0,0 +1,462 @@
+//! A single-line input field for passwords, used by every password prompt of the TUI.
+//!
+//! Unlike a `TextArea`, it keeps the password in a `SecretString`, so editing
+//! it anywhere leaves no copies behind, and it never holds a line break: Enter
+//! is left to the prompt, and pasted text spanning several lines is rejected.
+//! (A single trailing newline, as copied from a terminal, is dropped instead.)
+//!
+//! The password is masked unless revealed. If Caps Lock seems to be on, the user
+//! is warned. Terminals supporting the kitty keyboard protocol report its state
+//! with every key; elsewhere, it's inferred from letters typed without Shift
+//! coming out in upper case (or vice versa), which only works if the terminal
+//! reports Shift separately, like the Windows console does. Where that's not
+//! possible, no warning is shown.
+
+use std::cell::Cell;
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::{Style, Color, Modifier},
+    text::Line,
+    widgets::{Widget, block::Block},
+    crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind, KeyEventState, KeyModifiers},
+};
+use unicode_segmentation::UnicodeSegmentation;
+use zeroize::Zeroizing;
+use crate::{
+    memlock::SecretString,
+    text::display_width,
+    i18n::tr,
+};
+
+
+/// Shown in place of each character while the password is masked.
+const MASK: &str = "●";
+
+/// Shown in place of characters that can't be displayed, e.g. a pasted tab.
+const UNPRINTABLE: &str = "\u{fffd}";
+
+/// A single-line, masked text field holding a password.
+#[derive(Debug)]
+pub struct SecretInput {
+    text: SecretString,
+    /// The byte index of the cursor, always at a grapheme cluster boundary.
+    cursor: usize,
+    revealed: bool,
+    caps_lock: bool,
+    show_strength: bool,
+    /// Set when a paste was rejected, until the next edit.
+    rejected_paste: bool,
+    placeholder: String,
+    block: Option<Block<'static>>,
+    style: Style,
+    error_style: Style,
+    /// The first column shown if the password is wider than the field. It's
+    /// only known while drawing, which doesn't have mutable access to the field.
+    scroll: Cell<usize>,
+}
+
+impl SecretInput {
+    /// Creates an empty field that can hold `capacity` bytes before growing.
+    /// (Growing doesn't leave copies behind, but it takes time.)
+    pub fn new(capacity: usize) -> Self {
+        SecretInput {
+            text: SecretString::with_capacity(capacity),
+            cursor: 0,
+            revealed: false,
+            caps_lock: false,
+            show_strength: false,
+            rejected_paste: false,
+            placeholder: String::new(),
+            block: None,
+            style: Style::default(),
+            error_style: Style::default().fg(Color::Red),
+            scroll: Cell::new(0),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.text.as_str()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    pub fn set_block(&mut self, block: Block<'static>) {
+        self.block = Some(block);
+    }
+
+    pub fn set_style(&mut self, style: Style) {
+        self.style = style;
+    }
+
+    /// The style of the Caps Lock warning, of rejected pastes, and of weak passwords.
+    pub fn set_error_style(&mut self, style: Style) {
+        self.error_style = style;
+    }
+
+    pub fn set_placeholder_text(&mut self, placeholder: impl Into<String>) {
+        self.placeholder = placeholder.into();
+    }
+
+    pub fn set_revealed(&mut self, revealed: bool) {
+        self.revealed = revealed;
+    }
+
+    /// Shows how strong the password is, e.g. when choosing a new one.
+    pub fn set_show_strength(&mut self, show_strength: bool) {
+        self.show_strength = show_strength;
+    }
+
+    /// Zeroizes the password, e.g. after a wrong one was entered.
+    pub fn clear(&mut self) {
+        self.text.clear();
+        self.cursor = 0;
+        self.rejected_paste = false;
+    }
+
+    /// Inserts the character at the cursor, unless it's a line break.
+    pub fn insert_char(&mut self, c: char) {
+        if c != '\n' && c != '\r' {
+            self.insert_str(c.encode_utf8(&mut [0; 4]));
+        }
+    }
+
+    /// Inserts the text at the cursor, without its line breaks.
+    pub fn insert_str(&mut self, text: &str) {
+        for part in text.split(['\n', '\r']) {
+            self.text.insert_str(self.cursor, part);
+            self.cursor += part.len();
+        }
+        self.rejected_paste = false;
+    }
+
+    /// Handles a key press or pasted text. Enter, Esc and keys with Ctrl (except
+    /// Ctrl+U, which deletes everything before the cursor) are left to the caller.
+    /// Returns `true` if the password changed.
+    pub fn input(&mut self, event: Event) -> bool {
+        match event {
+            Event::Key(key) if key.kind != KeyEventKind::Release => self.input_key(key),
+            Event::Paste(text) => self.paste(&Zeroizing::new(text)),
+            _ => false,
+        }
+    }
+
+    fn input_key(&mut self, key: KeyEvent) -> bool {
+        self.update_caps_lock(&key);
+
+        let ctrl_or_alt = key.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT);
+
+        match key.code {
+            KeyCode::Char('u') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.text.remove_range(0..self.cursor);
+                self.cursor = 0;
+                true
+            }
+            KeyCode::Char(c) if !ctrl_or_alt => {
+                self.insert_char(c);
+                true
+            }
+            KeyCode::Backspace if self.cursor > 0 => {
+                let start = self.prev_boundary();
+                self.text.remove_range(start..self.cursor);
+                self.cursor = start;
+                true
+            }
+            KeyCode::Delete if self.cursor < self.text.len() => {
+                self.text.remove_range(self.cursor..self.next_boundary());
+                true
+            }
+            KeyCode::Left => {
+                self.cursor = self.prev_boundary();
+                false
+            }
+            KeyCode::Right => {
+                self.cursor = self.next_boundary();
+                false
+            }
+            KeyCode::Home => {
+                self.cursor = 0;
+                false
+            }
+            KeyCode::End => {
+                self.cursor = self.text.len();
+                false
+            }
+            _ => false,
+        }
+    }
+
+    /// Inserts the pasted text, unless it has line breaks other than a single
+    /// trailing one, since no password could have them.
+    fn paste(&mut self, text: &str) -> bool {
+        let text = text.strip_suffix('\n').map_or(text, |text| text.strip_suffix('\r').unwrap_or(text));
+
+        if text.contains(['\n', '\r']) {
+            self.rejected_paste = true;
+            return false;
+        }
+
+        self.insert_str(text);
+        !text.is_empty()
+    }
+
+    fn update_caps_lock(&mut self, key: &KeyEvent) {
+        if key.state.contains(KeyEventState::CAPS_LOCK) {
+            self.caps_lock = true;
+        } else if let KeyCode::Char(c) = key.code {
+            if c.is_uppercase() || c.is_lowercase() {
+                self.caps_lock = c.is_uppercase() != key.modifiers.contains(KeyModifiers::SHIFT);
+            }
+        }
+    }
+
+    fn prev_boundary(&self) -> usize {
+        self.text[..self.cursor].grapheme_indices(true).next_back().map_or(0, |(index, _)| index)
+    }
+
+    fn next_boundary(&self) -> usize {
+        self.cursor + self.text[self.cursor..].graphemes(true).next().map_or(0, str::len)
+    }
+
+    /// What each grapheme cluster of the password looks like on the screen.
+    fn symbols(&self) -> impl Iterator<Item = (usize, &str)> {
+        self.text.grapheme_indices(true).map(move |(index, grapheme)| {
+            let symbol = if !self.revealed {
+                MASK
+            } else if display_width(grapheme) == 0 || grapheme.contains(char::is_control) {
+                UNPRINTABLE
+            } else {
+                grapheme
+            };
+            (index, symbol)
+        })
+    }
+
+    /// Adds the warnings and the strength of the password to the bottom of the
+    /// block, e.g. of the field as drawn by a form.
+    pub fn decorate(&self, mut block: Block<'static>) -> Block<'static> {
+        let title = |msgid, style| Line::from(format!(" {} ", tr(msgid))).style(style);
+
+        if self.rejected_paste {
+            block = block.title_bottom(title("Passwords can't have line breaks", self.error_style));
+        }
+        if self.caps_lock {
+            block = block.title_bottom(title("Caps Lock is on", self.error_style));
+        }
+        if self.show_strength && !self.is_empty() {
+            let strength = Strength::estimate(self.as_str());
+            let style = if strength < Strength::Fair { self.error_style } else { Style::default() };
+
+            block = block.title_bottom(title(strength.label(), style).right_aligned());
+        }
+
+        block
+    }
+}
+
+impl Widget for &SecretInput {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let area = match self.block.clone().map(|block| self.decorate(block)) {
+            Some(block) => {
+                let inner = block.inner(area);
+                block.render(area, buf);
+                inner
+            }
+            None => area,
+        };
+
+        if area.is_empty() {
+            return;
+        }
+
+        let cursor_style = self.style.add_modifier(Modifier::REVERSED);
+
+        if self.is_empty() && !self.placeholder.is_empty() {
+            buf.set_style(area, Style::default().fg(Color::DarkGray));
+            buf.set_string(area.x, area.y, " ", cursor_style);
+            buf.set_stringn(area.x + 1, area.y, &self.placeholder, usize::from(area.width - 1), Style::new());
+            return;
+        }
+
+        buf.set_style(area, self.style);
+
+        // Scroll horizontally just enough for the cursor to be visible.
+        let width = usize::from(area.width);
+        let cursor_column: usize = self
+            .symbols()
+            .take_while(|&(index, _)| index < self.cursor)
+            .map(|(_, symbol)| display_width(symbol))
+            .sum();
+        let first = self.scroll.get().min(cursor_column).max((cursor_column + 1).saturating_sub(width));
+        let mut column = 0;
+
+        self.scroll.set(first);
+
+        for (index, symbol) in self.symbols() {
+            let symbol_width = display_width(symbol);
+
+            if column >= first && column + symbol_width <= first + width {
+                let x = area.x + (column - first) as u16;
+                let style = if index == self.cursor { cursor_style } else { self.style };
+                buf.set_stringn(x, area.y, symbol, symbol_width, style);
+            }
+            column += symbol_width;
+        }
+
+        if self.cursor == self.text.len() && column < first + width {
+            buf.set_string(area.x + (column - first) as u16, area.y, " ", cursor_style);
+        }
+    }
+}
+
+/// A rough estimate of how hard a password is to guess by brute force.
+///
+/// It's the number of distinct characters times the bits needed to pick one
+/// from the classes (lower and upper case letters, digits and the rest) it
+/// uses. It knows nothing about words or keyboard patterns, so it is only an
+/// upper bound. Only the passwords themselves are looked at: the hint must not
+/// leave copies of them behind, which dictionary-based estimators do.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Strength {
+    VeryWeak,
+    Weak,
+    Fair,
+    Strong,
+}
+
+impl Strength {
+    pub fn estimate(password: &str) -> Self {
+        let has = |predicate: fn(&char) -> bool| password.chars().any(|c| predicate(&c));
+        let pool: u32 = [
+            (has(char::is_ascii_lowercase), 26),
+            (has(char::is_ascii_uppercase), 26),
+            (has(char::is_ascii_digit), 10),
+            (has(|c| !c.is_ascii_alphanumeric()), 33),
+        ]
+        .into_iter()
+        .filter_map(|(is_used, size)| is_used.then_some(size))
+        .sum();
+
+        // there are at most as many `char`s as bytes, so this never re-allocates
+        let mut chars = Zeroizing::new(Vec::with_capacity(password.len()));
+
+        chars.extend(password.chars());
+        chars.sort_unstable();
+        chars.dedup();
+
+        let bits = chars.len() as f64 * f64::from(pool.max(1)).log2();
+
+        if bits < 40.0 {
+            Strength::VeryWeak
+        } else if bits < 60.0 {
+            Strength::Weak
+        } else if bits < 80.0 {
+            Strength::Fair
+        } else {
+            Strength::Strong
+        }
+    }
+
+    const fn label(self) -> &'static str {
+        match self {
+            Strength::VeryWeak => "Very weak password",
+            Strength::Weak => "Weak password",
+            Strength::Fair => "Fair password",
+            Strength::Strong => "Strong password",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::{
+        buffer::Buffer,
+        layout::Rect,
+        widgets::Widget,
+        crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers},
+    };
+    use super::{SecretInput, Strength};
+
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> Event {
+        Event::Key(KeyEvent::new(code, modifiers))
+    }
+
+    fn render(input: &SecretInput, width: u16) -> String {
+        let area = Rect::new(0, 0, width, 1);
+        let mut buf = Buffer::empty(area);
+
+        input.render(area, &mut buf);
+        buf.content().iter().map(|cell| cell.symbol()).collect()
+    }
+
+    #[test]
+    fn editing_pasting_and_scrolling() {
+        let mut input = SecretInput::new(4);
+
+        for c in "huntexr".chars() {
+            input.input(key(KeyCode::Char(c), KeyModifiers::NONE));
+        }
+        input.input(key(KeyCode::Left, KeyModifiers::NONE));
+        input.input(key(KeyCode::Backspace, KeyModifiers::NONE));
+        input.input(key(KeyCode::Home, KeyModifiers::NONE));
+        input.input(key(KeyCode::Delete, KeyModifiers::NONE));
+        assert_eq!(input.as_str(), "unter");
+
+        // a single trailing newline is dropped, but several lines are rejected
+        input.input(key(KeyCode::End, KeyModifiers::NONE));
+        assert!(input.input(Event::Paste(String::from("2ö\r\n"))));
+        assert!(!input.input(Event::Paste(String::from("first\nsecond"))));
+        assert!(input.rejected_paste);
+        assert_eq!(input.as_str(), "unter2ö");
+
+        // masked, and scrolled so that the cursor at the end is visible
+        assert_eq!(render(&input, 4), "●●● ");
+        input.set_revealed(true);
+        assert_eq!(render(&input, 4), "r2ö ");
+        input.input(key(KeyCode::Home, KeyModifiers::NONE));
+        assert_eq!(render(&input, 4), "unte");
+
+        input.input(key(KeyCode::End, KeyModifiers::NONE));
+        input.input(key(KeyCode::Left, KeyModifiers::NONE));
+        input.input(key(KeyCode::Char('u'), KeyModifiers::CONTROL));
+        assert_eq!(input.as_str(), "ö");
+
+        input.clear();
+        assert!(input.is_empty());
+    }
+
+    #[test]
+    fn caps_lock_is_inferred_from_shift() {
+        let mut input = SecretInput::new(16);
+
+        // how the Windows console reports letters typed with Caps Lock on
+        input.input(key(KeyCode::Char('A'), KeyModifiers::NONE));
+        assert!(input.caps_lock);
+        input.input(key(KeyCode::Char('b'), KeyModifiers::SHIFT));
+        assert!(input.caps_lock);
+        input.input(key(KeyCode::Char('1'), KeyModifiers::NONE));
+        assert!(input.caps_lock);
+
+        // and with Caps Lock off, like Unix terminals always report them
+        input.input(key(KeyCode::Char('C'), KeyModifiers::SHIFT));
+        assert!(!input.caps_lock);
+        input.input(key(KeyCode::Char('d'), KeyModifiers::NONE));
+        assert!(!input.caps_lock);
+    }
+
+    #[test]
+    fn strength_grows_with_length_and_variety() {
+        assert_eq!(Strength::estimate(""), Strength::VeryWeak);
+        assert_eq!(Strength::estimate("aaaaaaaaaaaaaaaaaaaa"), Strength::VeryWeak);
+        assert_eq!(Strength::estimate("hunter2"), Strength::VeryWeak);
+        assert_eq!(Strength::estimate("Tr0ub4dor"), Strength::Weak);
+        assert_eq!(Strength::estimate("password"), Strength::VeryWeak);
+        assert_eq!(Strength::estimate("P@ssw0rd"), Strength::Weak);
+        assert_eq!(Strength::estimate("Tr0ub4dor&3x"), Strength::Fair);
+        assert_eq!(Strength::estimate("correct horse battery staple"), Strength::Fair);
+        assert_eq!(Strength::estimate("q7#Vz!pK2@mW9$xL"), Strength::Strong);
+    }
+}
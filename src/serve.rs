@@ -0,0 +1,492 @@
+//! A minimal HTTP API (`steelsafe serve`), so that scripts and self-hosted
+//! dashboards on the same machine can use the vault programmatically. It's
+//! only built with the `serve` feature.
+//!
+//! The server only listens on loopback addresses, and every request must carry
+//! `Authorization: Bearer <token>`, where the token is generated anew each time
+//! the server starts, and written to a file that only its owner can read. It
+//! understands one request per connection, and always closes the connection
+//! after answering. Responses are JSON:
+//!
+//! * `GET /items?q=<query>` lists the items matching the query (same syntax as
+//!   the search field of the TUI), without their secrets;
+//! * `GET /items/<label>` returns an item, with its secret still encrypted, in
+//!   the format of export bundles;
+//! * `POST /items/<label>/decrypt` with `{"password": "..."}` returns the
+//!   decrypted secret as `{"secret": "..."}`. Failed attempts impose the same
+//!   cooldown as in the TUI.
+//!
+//! Labels in the path are percent-encoded. Errors are `{"error": "..."}`.
+
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write, ErrorKind};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+use serde::{Serialize, Deserialize};
+use zeroize::{Zeroize, Zeroizing};
+use rand::RngCore;
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD as BASE64};
+use crate::{
+    cli::ListedItem,
+    config::Config,
+    crypto::Pepper,
+    db::Database,
+    format::ExportedItem,
+    lockout::FailedAttempts,
+    query::Query,
+    percent,
+    interrupt,
+    perms,
+    error::{Error, ExitStatus, Result, ResultExt},
+};
+
+
+/// The address the server listens on if none is specified.
+pub const DEFAULT_LISTEN: &str = "127.0.0.1:7539";
+
+/// How often the server checks for new connections and for Ctrl+C.
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Clients that don't send a complete request in time are disconnected,
+/// so that they can't block the server indefinitely.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The maximal size of the request line and the headers, together.
+const MAX_HEAD_BYTES: u64 = 16 * 1024;
+
+/// The maximal size of the body of a request.
+const MAX_BODY_BYTES: usize = 64 * 1024;
+
+/// The number of random bytes in an access token.
+const TOKEN_BYTES: usize = 32;
+
+/// An HTTP request, as far as the API is concerned.
+#[derive(Debug)]
+struct Request {
+    method: String,
+    /// The path and the query string.
+    target: String,
+    authorization: Option<Zeroizing<String>>,
+    body: Zeroizing<Vec<u8>>,
+}
+
+/// An HTTP response with a JSON body.
+#[derive(Debug)]
+struct Response {
+    status: u16,
+    body: Zeroizing<Vec<u8>>,
+}
+
+impl Response {
+    fn json<T: Serialize>(status: u16, value: &T) -> Result<Self> {
+        let body = Zeroizing::new(serde_json::to_vec(value)?);
+        Ok(Response { status, body })
+    }
+
+    fn error(status: u16, message: &str) -> Self {
+        let body = serde_json::json!({ "error": message }).to_string();
+        Response { status, body: Zeroizing::new(body.into_bytes()) }
+    }
+
+    fn from_error(error: &Error) -> Self {
+        let status = match error.exit_status() {
+            ExitStatus::NotFound => 404,
+            ExitStatus::AuthenticationFailed => 403,
+            ExitStatus::InvalidInput => 400,
+            _ => 500,
+        };
+        Response::error(status, &error.to_string())
+    }
+
+    const fn reason(&self) -> &'static str {
+        match self.status {
+            200 => "OK",
+            400 => "Bad Request",
+            401 => "Unauthorized",
+            403 => "Forbidden",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            429 => "Too Many Requests",
+            _ => "Internal Server Error",
+        }
+    }
+}
+
+/// The body of a decryption request.
+#[derive(Deserialize)]
+struct DecryptRequest {
+    password: String,
+}
+
+impl Drop for DecryptRequest {
+    fn drop(&mut self) {
+        self.password.zeroize();
+    }
+}
+
+/// The body of the response to a decryption request.
+#[derive(Serialize)]
+struct DecryptResponse<'a> {
+    secret: &'a str,
+}
+
+/// The state of the server.
+pub struct Server<'a> {
+    db: &'a Database,
+    pepper: Option<Pepper>,
+    token: Zeroizing<String>,
+    access_log: bool,
+    persist_failed_attempts: bool,
+    failed_attempts: FailedAttempts,
+}
+
+impl<'a> Server<'a> {
+    pub fn new(db: &'a Database, config: &Config, pepper: Option<Pepper>, token: Zeroizing<String>) -> Result<Self> {
+        let failed_attempt_count = if config.persist_failed_attempts {
+            db.failed_attempts()?
+        } else {
+            0
+        };
+
+        Ok(Server {
+            db,
+            pepper,
+            token,
+            access_log: config.access_log,
+            persist_failed_attempts: config.persist_failed_attempts,
+            failed_attempts: FailedAttempts::new(failed_attempt_count, Instant::now()),
+        })
+    }
+
+    /// Serves clients until Ctrl+C is pressed.
+    pub fn run(&mut self, listener: &TcpListener) -> Result<()> {
+        loop {
+            interrupt::check()?;
+
+            match listener.accept() {
+                Ok((stream, _addr)) => {
+                    // a misbehaving client must not bring down the server
+                    let _ = self.serve_client(stream);
+                }
+                Err(error) if error.kind() == ErrorKind::WouldBlock => {
+                    thread::sleep(POLL_INTERVAL);
+                }
+                Err(error) => return Err(error.into()),
+            }
+        }
+    }
+
+    fn serve_client(&mut self, stream: TcpStream) -> Result<()> {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+        stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+
+        let response = match read_request(&stream) {
+            Ok(request) => self.handle(&request),
+            Err(error) => Response::from_error(&error),
+        };
+
+        write_response(&stream, &response)
+    }
+
+    fn handle(&mut self, request: &Request) -> Response {
+        if !self.is_authorized(request) {
+            return Response::error(401, "Missing or invalid access token");
+        }
+
+        self.route(request).unwrap_or_else(|error| Response::from_error(&error))
+    }
+
+    /// Compares the token in constant time, so that it can't be guessed byte by byte.
+    fn is_authorized(&self, request: &Request) -> bool {
+        let Some(token) = request
+            .authorization
+            .as_deref()
+            .and_then(|value| value.strip_prefix("Bearer "))
+        else {
+            return false;
+        };
+
+        token.len() == self.token.len()
+            && token.bytes().zip(self.token.bytes()).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+    }
+
+    fn route(&mut self, request: &Request) -> Result<Response> {
+        let (path, query) = request.target.split_once('?').unwrap_or((&request.target, ""));
+        let segments = path
+            .strip_prefix('/')
+            .unwrap_or(path)
+            .split('/')
+            .map(percent_decode)
+            .collect::<Result<Vec<_>>>()?;
+        let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+
+        match (request.method.as_str(), segments.as_slice()) {
+            ("GET", ["items"]) => self.list(query),
+            ("GET", ["items", label]) => self.get(label),
+            ("POST", ["items", label, "decrypt"]) => self.decrypt(label, &request.body),
+            (_, ["items"] | ["items", _] | ["items", _, "decrypt"]) => {
+                Ok(Response::error(405, "Method not allowed"))
+            }
+            _ => Ok(Response::error(404, "No such endpoint")),
+        }
+    }
+
+    fn list(&self, query: &str) -> Result<Response> {
+        let search = query
+            .split('&')
+            .filter_map(|pair| pair.strip_prefix("q="))
+            .map(|value| percent_decode(&value.replace('+', " ")))
+            .next()
+            .transpose()?
+            .unwrap_or_default();
+
+        let items = Query::parse(&search)?.find_items(self.db)?;
+        let listed: Vec<_> = items.iter().map(ListedItem::new).collect();
+
+        Response::json(200, &listed)
+    }
+
+    fn get(&self, label: &str) -> Result<Response> {
+        let item = self.db.item_by_label(label)?.ok_or_else(|| Error::ItemNotFound(label.into()))?;
+
+        Response::json(200, &ExportedItem::from(item))
+    }
+
+    fn decrypt(&mut self, label: &str, body: &[u8]) -> Result<Response> {
+        if let Some(remaining) = self.failed_attempts.remaining_cooldown(Instant::now()) {
+            let message = format!("Too many failed attempts; try again in {} seconds", remaining.as_secs() + 1);
+            return Ok(Response::error(429, &message));
+        }
+
+        let request: DecryptRequest = serde_json::from_slice(body)
+            .map_err(|error| Error::InvalidApiRequest(error.to_string()))?;
+        let item = self.db.item_by_label(label)?.ok_or_else(|| Error::ItemNotFound(label.into()))?;
+        let result = item
+            .decryption_input(self.pepper.as_ref())
+            .decrypt_and_verify(request.password.as_bytes());
+
+        // only a wrong password counts as a failed attempt, not e.g. a missing pepper
+        match &result {
+            Ok(_) => self.record_attempt(true)?,
+            Err(error) if error.exit_status() == ExitStatus::AuthenticationFailed => self.record_attempt(false)?,
+            Err(_) => {}
+        }
+
+        let secret = result?;
+
+        if self.access_log {
+            self.db.log_access(item.uid)?;
+        }
+
+        Response::json(200, &DecryptResponse { secret: std::str::from_utf8(&secret)? })
+    }
+
+    /// Updates the counter of consecutive failed decryption attempts,
+    /// and persists it if it has changed and it's configured to do so.
+    fn record_attempt(&mut self, success: bool) -> Result<()> {
+        let old_count = self.failed_attempts.count();
+
+        if success {
+            self.failed_attempts.record_success();
+        } else {
+            self.failed_attempts.record_failure(Instant::now());
+        }
+
+        if self.persist_failed_attempts && self.failed_attempts.count() != old_count {
+            self.db.set_failed_attempts(self.failed_attempts.count())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Starts listening on `addr`, which must be a loopback address: the API
+/// is meant for local clients only, and it doesn't support TLS.
+pub fn bind(addr: SocketAddr) -> Result<TcpListener> {
+    if !addr.ip().is_loopback() {
+        return Err(Error::NonLoopbackListen(addr.to_string()));
+    }
+
+    let listener = TcpListener::bind(addr).context("Can't listen for API requests")?;
+    listener.set_nonblocking(true)?;
+
+    Ok(listener)
+}
+
+/// Generates a new random access token, and writes it into a file at `path`,
+/// which is only accessible to its owner. A previous token file is replaced.
+pub fn create_token(path: &Path) -> Result<Zeroizing<String>> {
+    let mut bytes = Zeroizing::new([0_u8; TOKEN_BYTES]);
+    rand::thread_rng().fill_bytes(bytes.as_mut_slice());
+    let token = Zeroizing::new(BASE64.encode(bytes.as_slice()));
+
+    // the permissions of an existing file would be kept, so start afresh
+    match fs::remove_file(path) {
+        Ok(()) => {}
+        Err(error) if error.kind() == ErrorKind::NotFound => {}
+        Err(error) => return Err(Error::context(error, "Can't remove old API token file")),
+    }
+
+    perms::create_private_file(path).context("Can't create API token file")?;
+    fs::write(path, token.as_bytes()).context("Can't write API token file")?;
+
+    Ok(token)
+}
+
+fn read_request(stream: &TcpStream) -> Result<Request> {
+    let invalid = |message: &str| Error::InvalidApiRequest(message.into());
+    let mut reader = BufReader::new(stream.take(MAX_HEAD_BYTES));
+    let mut line = Zeroizing::new(String::new());
+
+    reader.read_line(&mut line)?;
+
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target), Some(_version)) = (parts.next(), parts.next(), parts.next()) else {
+        return Err(invalid("malformed request line"));
+    };
+    let method = method.to_owned();
+    let target = target.to_owned();
+    let mut content_length = 0;
+    let mut authorization = None;
+
+    loop {
+        line.clear();
+
+        if reader.read_line(&mut line)? == 0 {
+            return Err(invalid("incomplete headers"));
+        }
+
+        let header = line.trim_end();
+
+        if header.is_empty() {
+            break;
+        }
+
+        let (name, value) = header.split_once(':').ok_or_else(|| invalid("malformed header"))?;
+        let value = value.trim();
+
+        if name.eq_ignore_ascii_case("content-length") {
+            content_length = value.parse().map_err(|_| invalid("invalid Content-Length"))?;
+        } else if name.eq_ignore_ascii_case("authorization") {
+            authorization = Some(Zeroizing::new(value.to_owned()));
+        }
+    }
+
+    if content_length > MAX_BODY_BYTES {
+        return Err(invalid("body too large"));
+    }
+
+    let mut body = Zeroizing::new(vec![0; content_length]);
+    reader.get_mut().set_limit(content_length as u64);
+    reader.read_exact(&mut body)?;
+
+    Ok(Request { method, target, authorization, body })
+}
+
+fn write_response(mut stream: &TcpStream, response: &Response) -> Result<()> {
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n",
+        response.status,
+        response.reason(),
+        response.body.len(),
+    );
+
+    if response.status == 401 {
+        head.push_str("WWW-Authenticate: Bearer\r\n");
+    }
+
+    head.push_str("Cache-Control: no-store\r\nConnection: close\r\n\r\n");
+
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(&response.body)?;
+    stream.flush()?;
+
+    Ok(())
+}
+
+fn percent_decode(text: &str) -> Result<String> {
+    percent::decode(text)
+        .ok_or_else(|| Error::InvalidApiRequest(format!("invalid percent-encoding in {text:?}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use serde_json::Value;
+    use zeroize::Zeroizing;
+    use crate::config::{Config, DatabaseOptions};
+    use crate::crypto::EncryptionInput;
+    use crate::db::{Database, AddItemInput};
+    use crate::fields::{Label, PlaintextSecret, MasterPassword};
+    use crate::error::Result;
+    use super::{Server, Request, Response};
+
+
+    fn request(method: &str, target: &str, token: Option<&str>, body: &str) -> Request {
+        Request {
+            method: method.into(),
+            target: target.into(),
+            authorization: token.map(|token| Zeroizing::new(format!("Bearer {token}"))),
+            body: Zeroizing::new(body.as_bytes().to_vec()),
+        }
+    }
+
+    fn json(response: &Response) -> Value {
+        serde_json::from_slice(&response.body).expect("response is JSON")
+    }
+
+    #[test]
+    fn api_requires_token_and_decrypts_items() -> Result<()> {
+        let db = Database::open(":memory:", &DatabaseOptions::default())?;
+        let label = Label::new("my bank")?;
+        let input = EncryptionInput {
+            plaintext_secret: &PlaintextSecret::new(b"hunter2"),
+            label: &label,
+            account: None,
+            last_modified_at: Utc::now(),
+            pepper: None,
+        };
+        let output = input.encrypt_and_authenticate(&MasterPassword::new("correct horse")?)?;
+        db.add_item(AddItemInput::new(&label, None, input.last_modified_at, &output))?;
+
+        let token = "s3cr3t-t0k3n";
+        let mut server = Server::new(&db, &Config::default(), None, Zeroizing::new(token.into()))?;
+
+        for token in [None, Some("wrong-token"), Some("s3cr3t-t0k3")] {
+            let response = server.handle(&request("GET", "/items", token, ""));
+            assert_eq!(response.status, 401);
+        }
+
+        let response = server.handle(&request("GET", "/items?q=bank", Some(token), ""));
+        assert_eq!(response.status, 200);
+        assert_eq!(json(&response)[0]["label"], "my bank");
+
+        let response = server.handle(&request("GET", "/items/my%20bank", Some(token), ""));
+        assert_eq!(response.status, 200);
+        assert!(!String::from_utf8_lossy(&response.body).contains("hunter2"));
+
+        let response = server.handle(&request("GET", "/items/nothing", Some(token), ""));
+        assert_eq!(response.status, 404);
+
+        let response = server.handle(&request("DELETE", "/items/my%20bank", Some(token), ""));
+        assert_eq!(response.status, 405);
+
+        let decrypt = "/items/my%20bank/decrypt";
+        let response = server.handle(&request("POST", decrypt, Some(token), r#"{"password":"wrong"}"#));
+        assert_eq!(response.status, 403);
+        assert_eq!(server.failed_attempts.count(), 1);
+
+        let response = server.handle(&request("POST", decrypt, Some(token), "not json"));
+        assert_eq!(response.status, 400);
+
+        let response = server.handle(&request("POST", decrypt, Some(token), r#"{"password":"correct horse"}"#));
+        assert_eq!(response.status, 200);
+        assert_eq!(json(&response)["secret"], "hunter2");
+        assert_eq!(server.failed_attempts.count(), 0);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,463 @@
+//! Splitting the encryption password into key shares (Shamir's secret sharing),
+//! so that any `threshold` of them restore it, but fewer reveal nothing about it.
+//!
+//! The password is prefixed with its length and padded with zeros to a multiple
+//! of `PADDING` bytes, so that shares don't reveal its exact length either. Then
+//! each byte is the constant term of a random polynomial of degree `threshold - 1`
+//! over GF(2^8), and the share numbered `x` holds the values of the polynomials
+//! at `x`. Any `threshold` shares determine the polynomials, and thus the
+//! password, by Lagrange interpolation at 0.
+//!
+//! A share is laid out as follows, then written in Crockford's Base32, in
+//! groups of four characters, so that it can be read aloud and typed reliably:
+//!
+//! | Offset | Size | Contents                                              |
+//! |--------|------|-------------------------------------------------------|
+//! | 0      | 1    | Format version, currently 1                           |
+//! | 1      | 2    | Random ID of the set of shares made together          |
+//! | 3      | 1    | Threshold, i.e. the number of shares needed           |
+//! | 4      | 1    | The number of this share, i.e. `x`, from 1            |
+//! | 5      | N    | The values of the polynomials at `x`                  |
+//! | 5 + N  | 2    | Fletcher-16 checksum of the above, against typos      |
+
+use std::fmt::{self, Debug, Formatter, Write as _};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use chrono::{DateTime, Utc};
+use rand::{Rng, RngCore};
+use zeroize::Zeroizing;
+use crate::{
+    qr::QrMatrix,
+    error::{Error, Result, ResultExt},
+};
+
+
+/// The version of the share format.
+const VERSION: u8 = 1;
+
+/// The padded password is a multiple of this many bytes long.
+const PADDING: usize = 32;
+
+/// The length of everything but the values: version, set ID, threshold, `x`.
+const HEADER_LEN: usize = 5;
+
+/// The length of the checksum at the end.
+const CHECKSUM_LEN: usize = 2;
+
+/// Crockford's Base32 alphabet, without `I`, `L`, `O` and `U`.
+const ALPHABET: &[u8; 32] = b"0123456789abcdefghjkmnpqrstvwxyz";
+
+/// The number of characters between dashes in the text form of a share.
+const GROUP_LEN: usize = 4;
+
+/// The width of the light border around printed QR codes, in modules.
+const QUIET_ZONE: usize = 4;
+
+/// A single share of the password.
+#[derive(Clone, PartialEq, Eq)]
+pub struct KeyShare {
+    set_id: u16,
+    threshold: u8,
+    x: u8,
+    values: Zeroizing<Vec<u8>>,
+}
+
+impl KeyShare {
+    /// The number of this share, from 1.
+    pub const fn number(&self) -> u8 {
+        self.x
+    }
+
+    /// The number of shares needed for restoring the password.
+    pub const fn threshold(&self) -> u8 {
+        self.threshold
+    }
+
+    /// Parses the text form of a share. Case, whitespace and dashes don't
+    /// matter, and the letters `o`, `i` and `l` are read as digits.
+    pub fn parse(text: &str) -> Result<Self> {
+        let bytes = decode_base32(text)?;
+        let invalid = |reason: &str| Error::InvalidKeyShare(reason.into());
+
+        if bytes.len() < HEADER_LEN + PADDING + CHECKSUM_LEN {
+            return Err(invalid("it's too short; a part of it may be missing"));
+        }
+
+        let (body, checksum) = bytes.split_at(bytes.len() - CHECKSUM_LEN);
+
+        if checksum != fletcher16(body).to_be_bytes() {
+            return Err(invalid("the checksum doesn't match; check it for typos"));
+        }
+        if body[0] != VERSION {
+            return Err(invalid("it was made by a newer version of steelsafe"));
+        }
+
+        let share = KeyShare {
+            set_id: u16::from_be_bytes([body[1], body[2]]),
+            threshold: body[3],
+            x: body[4],
+            values: Zeroizing::new(body[HEADER_LEN..].to_vec()),
+        };
+
+        if share.threshold < 2 || share.x == 0 || share.values.len() % PADDING != 0 {
+            return Err(invalid("it's malformed"));
+        }
+
+        Ok(share)
+    }
+
+    /// The text form of the share, e.g. `1a2b-3c4d-...`.
+    pub fn to_text(&self) -> Zeroizing<String> {
+        let mut bytes = Zeroizing::new(Vec::with_capacity(HEADER_LEN + self.values.len() + CHECKSUM_LEN));
+
+        bytes.push(VERSION);
+        bytes.extend_from_slice(&self.set_id.to_be_bytes());
+        bytes.push(self.threshold);
+        bytes.push(self.x);
+        bytes.extend_from_slice(&self.values);
+
+        let checksum = fletcher16(&bytes);
+        bytes.extend_from_slice(&checksum.to_be_bytes());
+
+        let encoded = encode_base32(&bytes);
+        let mut text = Zeroizing::new(String::with_capacity(encoded.len() * 5 / 4));
+
+        for (index, c) in encoded.chars().enumerate() {
+            if index > 0 && index % GROUP_LEN == 0 {
+                text.push('-');
+            }
+            text.push(c);
+        }
+
+        text
+    }
+}
+
+impl Debug for KeyShare {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        formatter
+            .debug_struct("KeyShare")
+            .field("set_id", &self.set_id)
+            .field("threshold", &self.threshold)
+            .field("x", &self.x)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Renders a share as a plain text sheet to print and hand over to its keeper.
+/// The QR code is drawn with Unicode block characters, like in the emergency kit.
+pub fn sheet(share: &KeyShare, count: u8, created_at: DateTime<Utc>) -> Result<Zeroizing<String>> {
+    let text = share.to_text();
+    // upper case fits the denser alphanumeric mode of QR codes
+    let mut qr_text = Zeroizing::new(text.as_str().to_owned());
+    qr_text.make_ascii_uppercase();
+    let qr_code = QrMatrix::new(qr_text.as_bytes(), QUIET_ZONE)?;
+    let mut sheet = Zeroizing::new(String::new());
+
+    // writing into a `String` never fails
+    let _ = write_sheet(&mut sheet, share, &text, &qr_code, count, created_at);
+
+    Ok(sheet)
+}
+
+fn write_sheet(
+    sheet: &mut String,
+    share: &KeyShare,
+    text: &str,
+    qr_code: &QrMatrix,
+    count: u8,
+    created_at: DateTime<Utc>,
+) -> fmt::Result {
+    writeln!(sheet, "STEELSAFE KEY SHARE {} OF {}", share.x, count)?;
+    writeln!(sheet, "Created at {}", created_at.format("%F %T UTC"))?;
+    writeln!(sheet)?;
+    writeln!(
+        sheet,
+        "Any {} of the {} shares restore the encryption password of the vault, but fewer \
+         reveal nothing about it. Keep this sheet in a safe place, apart from the others.",
+        share.threshold,
+        count,
+    )?;
+    writeln!(sheet, "To restore the password, run `steelsafe shares restore`, and type in the shares.")?;
+    writeln!(sheet)?;
+    writeln!(sheet, "{text}")?;
+    writeln!(sheet)?;
+
+    for line in qr_code.half_blocks().iter() {
+        writeln!(sheet, "{line}")?;
+    }
+
+    Ok(())
+}
+
+/// Writes a rendered sheet into a new file, which only the owner can read.
+/// The file must not exist yet.
+pub fn write_sheet_file(path: &Path, contents: &str) -> Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    let mut file = options.open(path).context("Can't create key share")?;
+    file.write_all(contents.as_bytes())?;
+    file.sync_all()?;
+
+    Ok(())
+}
+
+/// Splits the password into `count` shares, any `threshold` of which restore it.
+pub fn split(password: &str, threshold: u8, count: u8) -> Result<Vec<KeyShare>> {
+    if threshold < 2 || threshold > count {
+        return Err(Error::KeyShareThreshold { threshold, count });
+    }
+
+    let len = u8::try_from(password.len()).map_err(|_| Error::InvalidKeyShare(String::from(
+        "passwords longer than 255 bytes can't be split",
+    )))?;
+    let padded_len = (password.len() + 1).div_ceil(PADDING) * PADDING;
+    let mut secret = Zeroizing::new(vec![0_u8; padded_len]);
+
+    secret[0] = len;
+    secret[1..=password.len()].copy_from_slice(password.as_bytes());
+
+    // `thread_rng()` is a CSPRNG.
+    let mut rng = rand::thread_rng();
+    let set_id = rng.gen();
+    let mut shares: Vec<KeyShare> = (1..=count)
+        .map(|x| KeyShare {
+            set_id,
+            threshold,
+            x,
+            values: Zeroizing::new(Vec::with_capacity(padded_len)),
+        })
+        .collect();
+    let mut coefficients = Zeroizing::new(vec![0_u8; usize::from(threshold)]);
+
+    for &byte in secret.iter() {
+        coefficients[0] = byte;
+        rng.fill_bytes(&mut coefficients[1..]);
+
+        for share in &mut shares {
+            share.values.push(evaluate(&coefficients, share.x));
+        }
+    }
+
+    Ok(shares)
+}
+
+/// Restores the password from at least `threshold` shares of the same set.
+pub fn combine(shares: &[KeyShare]) -> Result<Zeroizing<String>> {
+    let invalid = |reason: &str| Error::InvalidKeyShare(reason.into());
+    let first = shares.first().ok_or_else(|| invalid("no shares were given"))?;
+
+    for (index, share) in shares.iter().enumerate() {
+        if share.set_id != first.set_id || share.threshold != first.threshold
+            || share.values.len() != first.values.len()
+        {
+            return Err(invalid("the shares weren't made together"));
+        }
+        if shares[..index].iter().any(|other| other.x == share.x) {
+            return Err(invalid("the same share was given twice"));
+        }
+    }
+
+    let needed = usize::from(first.threshold);
+
+    if shares.len() < needed {
+        return Err(Error::InvalidKeyShare(format!(
+            "{needed} shares are needed, but only {} were given",
+            shares.len(),
+        )));
+    }
+
+    // any `threshold` shares determine the polynomials; the rest are redundant
+    let shares = &shares[..needed];
+    let mut secret = Zeroizing::new(vec![0_u8; first.values.len()]);
+
+    for (index, share) in shares.iter().enumerate() {
+        // the Lagrange basis polynomial of this share, evaluated at 0
+        let mut basis = 1;
+
+        for (other_index, other) in shares.iter().enumerate() {
+            if other_index != index {
+                basis = mul(basis, div(other.x, other.x ^ share.x));
+            }
+        }
+
+        for (byte, &value) in secret.iter_mut().zip(share.values.iter()) {
+            *byte ^= mul(basis, value);
+        }
+    }
+
+    let len = usize::from(secret[0]);
+    let password = secret
+        .get(1..=len)
+        .filter(|_| secret[len + 1..].iter().all(|&byte| byte == 0))
+        .ok_or_else(|| invalid("the shares don't fit together"))?;
+    let password = std::str::from_utf8(password).map_err(|_| invalid("the shares don't fit together"))?;
+
+    Ok(Zeroizing::new(password.to_owned()))
+}
+
+/// Evaluates the polynomial with the given coefficients (constant term first)
+/// at `x`, using Horner's method.
+fn evaluate(coefficients: &[u8], x: u8) -> u8 {
+    coefficients.iter().rev().fold(0, |acc, &coefficient| mul(acc, x) ^ coefficient)
+}
+
+/// Multiplication in GF(2^8) with the polynomial of AES, `x^8 + x^4 + x^3 + x + 1`.
+/// It doesn't branch on the operands, which may be secret.
+fn mul(mut lhs: u8, mut rhs: u8) -> u8 {
+    let mut product = 0;
+
+    for _ in 0..8 {
+        product ^= lhs & 0_u8.wrapping_sub(rhs & 1);
+        let carry = 0_u8.wrapping_sub(lhs >> 7);
+        lhs = (lhs << 1) ^ (carry & 0x1b);
+        rhs >>= 1;
+    }
+
+    product
+}
+
+/// Division in GF(2^8): `lhs` times the inverse of `rhs`, which is `rhs^254`.
+/// The divisor is never 0, since it's the difference of distinct share numbers.
+fn div(lhs: u8, rhs: u8) -> u8 {
+    let mut inverse = 1;
+    let mut power = rhs;
+
+    // 254 = 0b1111_1110
+    for _ in 0..7 {
+        power = mul(power, power);
+        inverse = mul(inverse, power);
+    }
+
+    mul(lhs, inverse)
+}
+
+fn fletcher16(bytes: &[u8]) -> u16 {
+    let (sum1, sum2) = bytes.iter().fold((0_u16, 0_u16), |(sum1, sum2), &byte| {
+        let sum1 = (sum1 + u16::from(byte)) % 255;
+        (sum1, (sum2 + sum1) % 255)
+    });
+
+    (sum2 << 8) | sum1
+}
+
+fn encode_base32(bytes: &[u8]) -> Zeroizing<String> {
+    let mut encoded = Zeroizing::new(String::with_capacity(bytes.len().div_ceil(5) * 8));
+    let mut buffer = 0_u32;
+    let mut bits = 0;
+
+    for &byte in bytes {
+        buffer = (buffer << 8) | u32::from(byte);
+        bits += 8;
+
+        while bits >= 5 {
+            bits -= 5;
+            encoded.push(char::from(ALPHABET[(buffer >> bits) as usize & 31]));
+        }
+    }
+    if bits > 0 {
+        encoded.push(char::from(ALPHABET[(buffer << (5 - bits)) as usize & 31]));
+    }
+
+    encoded
+}
+
+fn decode_base32(text: &str) -> Result<Zeroizing<Vec<u8>>> {
+    let mut decoded = Zeroizing::new(Vec::with_capacity(text.len() * 5 / 8));
+    let mut buffer = 0_u32;
+    let mut bits = 0;
+
+    for c in text.chars().filter(|&c| c != '-' && !c.is_whitespace()) {
+        let c = match c.to_ascii_lowercase() {
+            'o' => '0',
+            'i' | 'l' => '1',
+            c => c,
+        };
+        let value = ALPHABET
+            .iter()
+            .position(|&letter| char::from(letter) == c)
+            .ok_or_else(|| Error::InvalidKeyShare(format!("{c:?} is not part of any share")))?;
+
+        buffer = (buffer << 5) | value as u32;
+        bits += 5;
+
+        if bits >= 8 {
+            bits -= 8;
+            decoded.push((buffer >> bits) as u8);
+        }
+    }
+
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::{Error, Result};
+    use super::{KeyShare, split, combine, mul, div};
+
+
+    #[test]
+    fn field_arithmetic_is_consistent() {
+        // the example of FIPS 197
+        assert_eq!(mul(0x57, 0x83), 0xc1);
+
+        for a in 0..=255 {
+            for b in 1..=255 {
+                assert_eq!(mul(div(a, b), b), a);
+            }
+        }
+    }
+
+    #[test]
+    fn any_threshold_shares_restore_the_password() -> Result<()> {
+        let password = "correct horse battery staple";
+        let shares = split(password, 3, 5)?;
+        assert_eq!(shares.len(), 5);
+
+        for subset in [[0, 1, 2], [4, 2, 0], [1, 3, 4]] {
+            let chosen: Vec<KeyShare> = subset.iter().map(|&index| shares[index].clone()).collect();
+            assert_eq!(combine(&chosen)?.as_str(), password);
+        }
+
+        // too few shares
+        assert!(matches!(combine(&shares[..2]), Err(Error::InvalidKeyShare(_))));
+        // the same share twice
+        let twice = [shares[0].clone(), shares[0].clone(), shares[1].clone()];
+        assert!(matches!(combine(&twice), Err(Error::InvalidKeyShare(_))));
+        // shares of another set
+        let other = split(password, 3, 5)?;
+        let mixed = [shares[0].clone(), shares[1].clone(), other[2].clone()];
+        assert!(matches!(combine(&mixed), Err(Error::InvalidKeyShare(_))));
+
+        assert!(matches!(split(password, 4, 3), Err(Error::KeyShareThreshold { .. })));
+        assert!(matches!(split(password, 1, 3), Err(Error::KeyShareThreshold { .. })));
+
+        Ok(())
+    }
+
+    #[test]
+    fn text_form_round_trips_and_catches_typos() -> Result<()> {
+        let shares = split("hunter2", 2, 3)?;
+        let text = shares[1].to_text();
+        assert!(text.split('-').all(|group| group.len() <= 4));
+
+        let parsed = KeyShare::parse(&text.to_uppercase().replace('-', " "))?;
+        assert_eq!(parsed, shares[1]);
+        assert_eq!(parsed.number(), 2);
+        assert_eq!(parsed.threshold(), 2);
+
+        // the last character may only hold padding bits, so change one in the middle
+        let mut typo = text.to_string();
+        let replacement = if &typo[10..11] == "0" { "1" } else { "0" };
+        typo.replace_range(10..11, replacement);
+        assert!(matches!(KeyShare::parse(&typo), Err(Error::InvalidKeyShare(_))));
+        assert!(matches!(KeyShare::parse("not a share!"), Err(Error::InvalidKeyShare(_))));
+
+        Ok(())
+    }
+}
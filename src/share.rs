@@ -0,0 +1,212 @@
+//! One-time sharing of a single secret with another person.
+//!
+//! A share file is an export bundle containing a single item, whose secret as
+//! well as the payload (i.e., the label and the account name) are encrypted with
+//! a freshly generated passphrase. The file can be sent over an insecure channel
+//! (e.g. email), while the passphrase is communicated out of band (e.g. over the
+//! phone). The recipient can print the secret using `steelsafe reveal`, or add
+//! it to their own vault using `steelsafe import`, in which case the passphrase,
+//! exactly as displayed, becomes the encryption password of the imported item.
+//!
+//! The passphrase consists of dash-separated groups of lowercase letters and
+//! digits, omitting easily confused characters, so that it can be dictated and
+//! typed reliably. Both the bundle and the secret are encrypted with it as
+//! displayed; `reveal` brings whatever the recipient types into that form.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufReader, BufWriter, Write};
+use std::iter;
+use std::path::Path;
+use chrono::Utc;
+use rand::seq::SliceRandom;
+use zeroize::Zeroizing;
+use crate::{
+    crypto::EncryptionInput,
+    fields::{Label, AccountName, PlaintextSecret, MasterPassword},
+    format::{Bundle, ExportedItem},
+    memlock::SecretBytes,
+    error::{Error, Result, ResultExt},
+};
+
+
+/// The characters a passphrase is made of: no `0`/`o`, `1`/`l`/`i`, or `5`/`s`.
+const PASSPHRASE_CHARSET: &[u8] = b"abcdefghjkmnpqrtuvwxyz2346789";
+
+/// The number of dash-separated groups in a passphrase.
+const PASSPHRASE_GROUPS: usize = 5;
+
+/// The number of characters in each group. With 29 possible characters,
+/// 5 groups of 5 characters amount to over 120 bits of entropy.
+const PASSPHRASE_GROUP_LEN: usize = 5;
+
+/// Writes the secret into a new share file at `path`, and returns the passphrase,
+/// formatted for display. The file must not exist yet.
+pub fn write_share_file(
+    path: &Path,
+    label: &Label,
+    account: Option<&AccountName>,
+    secret: &PlaintextSecret,
+) -> Result<Zeroizing<String>> {
+    let passphrase = generate_passphrase();
+    let last_modified_at = Utc::now();
+
+    // the passphrase, as displayed, becomes the encryption password of the item
+    let output = EncryptionInput {
+        plaintext_secret: secret,
+        label,
+        account,
+        last_modified_at,
+        // the recipient doesn't have our pepper
+        pepper: None,
+    }.encrypt_and_authenticate(&MasterPassword::new(&passphrase)?)?;
+
+    let item = ExportedItem {
+        label: label.clone(),
+        account: account.cloned(),
+        last_modified_at,
+        encrypted_secret: output.encrypted_secret,
+        kdf_salt: output.kdf_salt,
+        auth_nonce: output.auth_nonce,
+        peppered: false,
+        kdf: output.kdf,
+        cipher: output.cipher,
+    };
+
+    let file = File::create_new(path).context("Can't create share file")?;
+    Bundle::new(vec![item]).write_to(BufWriter::new(file), Some(passphrase.as_bytes()))?;
+
+    Ok(passphrase)
+}
+
+/// Reads a share file, and decrypts its secret using the passphrase, however
+/// it was typed in. Returns the shared item along with its decrypted secret.
+pub fn read_share_file(path: &Path, passphrase: &str) -> Result<(ExportedItem, SecretBytes)> {
+    let key = canonicalize_passphrase(passphrase);
+    let file = File::open(path).context("Can't open share file")?;
+    let bundle = Bundle::read_from(BufReader::new(file), || Ok(key.clone()))?;
+
+    let [item]: [ExportedItem; 1] = bundle.items.try_into().map_err(|_| Error::InvalidBundle)?;
+    let secret = item.decryption_input(None).decrypt_and_verify(key.as_bytes())?;
+
+    Ok((item, secret))
+}
+
+/// Overwrites the contents of the file with zeros before removing it.
+///
+/// This is best-effort: on copy-on-write or journaling file systems and on SSDs,
+/// the original blocks may well survive. The file is encrypted anyway, though,
+/// so this mainly guards against the passphrase being leaked later on.
+pub fn shred(path: &Path) -> Result<()> {
+    let mut file = OpenOptions::new().write(true).open(path).context("Can't open share file")?;
+    let len = file.metadata()?.len();
+
+    write_zeros(&mut file, len)?;
+    file.sync_all()?;
+    drop(file);
+
+    fs::remove_file(path).context("Can't remove share file")
+}
+
+fn write_zeros<W: Write>(writer: &mut W, len: u64) -> Result<()> {
+    let zeros = [0_u8; 4096];
+    let mut remaining = len;
+
+    while remaining > 0 {
+        let chunk_len = remaining.min(zeros.len() as u64) as usize;
+        writer.write_all(&zeros[..chunk_len])?;
+        remaining -= chunk_len as u64;
+    }
+
+    Ok(())
+}
+
+/// Randomly generates a passphrase, formatted as dash-separated groups.
+fn generate_passphrase() -> Zeroizing<String> {
+    // `thread_rng()` returns a CSPRNG.
+    let mut rng = rand::thread_rng();
+    let mut passphrase = Zeroizing::new(String::with_capacity(
+        PASSPHRASE_GROUPS * (PASSPHRASE_GROUP_LEN + 1)
+    ));
+
+    for group in 0..PASSPHRASE_GROUPS {
+        if group > 0 {
+            passphrase.push('-');
+        }
+        passphrase.extend(
+            iter::from_fn(|| PASSPHRASE_CHARSET.choose(&mut rng))
+                .copied()
+                .map(char::from)
+                .take(PASSPHRASE_GROUP_LEN)
+        );
+    }
+
+    passphrase
+}
+
+/// Brings the passphrase into the form it was displayed in, so that it's
+/// accepted no matter how it was typed in by the recipient: separators and
+/// whitespace are removed, and the rest is grouped again in lower case.
+fn canonicalize_passphrase(passphrase: &str) -> Zeroizing<String> {
+    let mut canonical = Zeroizing::new(String::with_capacity(
+        PASSPHRASE_GROUPS * (PASSPHRASE_GROUP_LEN + 1)
+    ));
+    let chars = passphrase
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase());
+
+    for (index, c) in chars.enumerate() {
+        if index > 0 && index % PASSPHRASE_GROUP_LEN == 0 {
+            canonical.push('-');
+        }
+        canonical.push(c);
+    }
+
+    canonical
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::File;
+    use std::io::BufReader;
+    use crate::format::Bundle;
+    use crate::fields::{Label, AccountName, PlaintextSecret};
+    use crate::error::Result;
+    use super::{write_share_file, read_share_file, shred};
+
+
+    #[test]
+    fn share_file_round_trip() -> Result<()> {
+        let path = env::temp_dir().join(format!("steelsafe-share-test-{}", std::process::id()));
+        let passphrase = write_share_file(
+            &path,
+            &Label::new("bank")?,
+            Some(&AccountName::new("alice")?),
+            &PlaintextSecret::new(b"hunter2"),
+        )?;
+
+        assert_eq!(passphrase.len(), 5 * 5 + 4);
+
+        // the passphrase is accepted regardless of separators and case
+        let sloppy = passphrase.replace('-', " ").to_uppercase();
+        let (item, secret) = read_share_file(&path, &sloppy)?;
+
+        assert_eq!(item.label.as_str(), "bank");
+        assert_eq!(item.account.as_deref(), Some("alice"));
+        assert_eq!(secret.as_slice(), b"hunter2");
+
+        assert!(read_share_file(&path, "wrong-passphrase").is_err());
+
+        // importing the file needs, and keeps, the passphrase exactly as displayed
+        let bundle = Bundle::read_from(BufReader::new(File::open(&path)?), || Ok(passphrase.clone()))?;
+        let secret = bundle.items[0].decryption_input(None).decrypt_and_verify(passphrase.as_bytes())?;
+
+        assert_eq!(secret.as_slice(), b"hunter2");
+
+        shred(&path)?;
+        assert!(!path.exists());
+
+        Ok(())
+    }
+}
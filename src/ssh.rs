@@ -0,0 +1,251 @@
+//! Opening a vault that is stored on another machine, over SSH.
+//!
+//! `steelsafe --remote user@host:/path/secrets.sqlite3` downloads the database
+//! into a private temporary directory, and opens that copy. Everything goes
+//! through the `ssh` program, so host aliases, keys and agents configured in
+//! `~/.ssh/config` apply, and the remote host only needs `cat` and `mv`.
+//!
+//! The copy is opened read-only, unless `--writable` is passed, too. In that
+//! case, it's uploaded when steelsafe exits, if it was changed: first next to
+//! the original, under a temporary name, then renamed over it, so that the
+//! remote database is replaced atomically, and is never seen half-written.
+//! If the remote database was changed in the meantime (e.g. from another
+//! device), it's not overwritten; the local copy is kept instead, so that
+//! no changes are lost. The same happens if uploading it fails.
+
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+use crate::{
+    net::Network,
+    perms,
+    error::{Error, Result, ResultExt},
+};
+
+
+/// The name of the downloaded copy within its temporary directory.
+const LOCAL_FILE_NAME: &str = "secrets.sqlite3";
+
+/// The suffix of the temporary remote file that is renamed over the database.
+const UPLOAD_SUFFIX: &str = ".steelsafe-upload";
+
+/// The location of a remote database, `[user@]host:path`, like `scp` expects it.
+/// A relative path is relative to the home directory on the remote host.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct RemoteLocation {
+    /// The destination passed to `ssh`, i.e. `[user@]host`.
+    pub host: String,
+    /// The path of the database on the remote host.
+    pub path: String,
+}
+
+impl FromStr for RemoteLocation {
+    type Err = Error;
+
+    fn from_str(spec: &str) -> Result<Self> {
+        let invalid = || Error::InvalidRemote(spec.into());
+        let (host, path) = spec.split_once(':').ok_or_else(invalid)?;
+
+        // a leading dash would be interpreted as an option by `ssh`
+        if host.is_empty() || host.starts_with('-') || host.contains(char::is_whitespace) || path.is_empty() {
+            return Err(invalid());
+        }
+
+        Ok(RemoteLocation {
+            host: host.into(),
+            path: path.into(),
+        })
+    }
+}
+
+/// A local copy of a remote database. The copy is deleted when this is dropped,
+/// unless it was changed, but couldn't be uploaded.
+#[derive(Debug)]
+pub struct RemoteVault {
+    location: RemoteLocation,
+    writable: bool,
+    local_dir: PathBuf,
+    /// The remote database as it was downloaded, for detecting changes made by others.
+    original: Vec<u8>,
+    keep_local: bool,
+}
+
+impl RemoteVault {
+    /// Downloads the remote database into a new, private temporary directory.
+    pub fn fetch(location: RemoteLocation, writable: bool, network: &Network) -> Result<Self> {
+        network.ensure_enabled()?;
+
+        let local_dir = perms::create_temp_dir("steelsafe-remote")
+            .context("Can't create directory for remote vault")?;
+
+        let mut vault = RemoteVault {
+            location,
+            writable,
+            local_dir,
+            original: Vec::new(),
+            keep_local: false,
+        };
+
+        vault.original = vault.download()?;
+
+        let local_path = vault.local_path();
+        perms::create_new_private_file(&local_path).context("Can't create local copy of remote vault")?;
+        fs::write(&local_path, &vault.original).context("Can't write local copy of remote vault")?;
+
+        Ok(vault)
+    }
+
+    /// The path of the local copy, to be opened as the database.
+    pub fn local_path(&self) -> PathBuf {
+        self.local_dir.join(LOCAL_FILE_NAME)
+    }
+
+    /// If changes are allowed, i.e. `--writable` was passed.
+    pub const fn is_writable(&self) -> bool {
+        self.writable
+    }
+
+    /// Uploads the local copy if it's writable and it was changed, replacing the
+    /// remote database atomically. The database must be closed by now, so that
+    /// everything is in the main file (and not in the write-ahead log).
+    /// Returns `true` if the remote database was replaced.
+    ///
+    /// The local copy is kept until the upload succeeds, and errors tell where it is.
+    pub fn push(mut self) -> Result<bool> {
+        if !self.writable {
+            return Ok(false);
+        }
+
+        // the copy may contain changes until proven otherwise
+        self.keep_local = true;
+
+        let local_path = self.local_path();
+        let kept = || format!("Can't upload remote vault; your copy was kept at {}", local_path.display());
+        let local = fs::read(&local_path).context(kept())?;
+
+        if local == self.original {
+            self.keep_local = false;
+            return Ok(false);
+        }
+
+        if self.download().context(kept())? != self.original {
+            return Err(Error::RemoteVaultChanged(local_path.display().to_string()));
+        }
+
+        let path = shell_quote(&self.location.path);
+        let upload_path = shell_quote(&format!("{}{UPLOAD_SUFFIX}", self.location.path));
+        let script = format!("umask 077 && cat > {upload_path} && mv -f -- {upload_path} {path}");
+
+        self.ssh(&script, Some(&local)).context(kept())?;
+        self.keep_local = false;
+
+        Ok(true)
+    }
+
+    /// The current contents of the remote database.
+    fn download(&self) -> Result<Vec<u8>> {
+        self.ssh(&format!("cat -- {}", shell_quote(&self.location.path)), None)
+    }
+
+    /// Runs a shell command on the remote host, feeding it `input` (if any),
+    /// and returns its standard output. Unlike git synchronization, `ssh` may
+    /// prompt for a password or passphrase: this only happens before the TUI
+    /// starts, or after it has exited.
+    fn ssh(&self, script: &str, input: Option<&[u8]>) -> Result<Vec<u8>> {
+        let mut child = Command::new("ssh")
+            .arg("--")
+            .arg(&self.location.host)
+            .arg(script)
+            .stdin(if input.is_some() { Stdio::piped() } else { Stdio::null() })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .context("Can't run ssh")?;
+
+        if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+            stdin.write_all(input).context("Can't upload remote vault")?;
+        }
+
+        let output = child.wait_with_output().context("Can't run ssh")?;
+
+        if output.status.success() {
+            Ok(output.stdout)
+        } else {
+            Err(Error::Ssh {
+                host: self.location.host.clone(),
+                message: String::from_utf8_lossy(&output.stderr).trim().into(),
+            })
+        }
+    }
+}
+
+impl Drop for RemoteVault {
+    fn drop(&mut self) {
+        if !self.keep_local {
+            let _ = fs::remove_dir_all(&self.local_dir);
+        }
+    }
+}
+
+/// Quotes `text` as a single word for a POSIX shell.
+fn shell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use super::{RemoteVault, RemoteLocation, LOCAL_FILE_NAME, shell_quote};
+    use crate::perms;
+    use crate::error::{Error, Result};
+
+
+    #[test]
+    fn remote_locations_are_parsed() -> Result<()> {
+        let location: RemoteLocation = "alice@example.com:/srv/steelsafe/secrets.sqlite3".parse()?;
+        assert_eq!(location.host, "alice@example.com");
+        assert_eq!(location.path, "/srv/steelsafe/secrets.sqlite3");
+
+        let location: RemoteLocation = "backup:vaults/secrets.sqlite3".parse()?;
+        assert_eq!(location.host, "backup");
+        assert_eq!(location.path, "vaults/secrets.sqlite3");
+
+        for spec in ["example.com", ":/path", "example.com:", "-oProxyCommand=evil:/path", "a b:/path"] {
+            assert!(
+                matches!(spec.parse::<RemoteLocation>(), Err(Error::InvalidRemote(_))),
+                "{spec:?} must be rejected",
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn remote_paths_are_quoted_for_the_shell() {
+        assert_eq!(shell_quote("/srv/secrets.sqlite3"), "'/srv/secrets.sqlite3'");
+        assert_eq!(shell_quote("it's $HOME; rm -rf"), r"'it'\''s $HOME; rm -rf'");
+    }
+
+    #[test]
+    fn changes_are_kept_if_the_upload_fails() -> Result<()> {
+        let local_dir = perms::create_temp_dir("steelsafe-remote-test")?;
+        let vault = RemoteVault {
+            // the `.invalid` top-level domain never resolves
+            location: "steelsafe.invalid:secrets.sqlite3".parse()?,
+            writable: true,
+            local_dir: local_dir.clone(),
+            original: b"as downloaded".to_vec(),
+            keep_local: false,
+        };
+        fs::write(vault.local_path(), b"changed")?;
+
+        let error = vault.push().expect_err("the host doesn't exist");
+        assert!(error.to_string().contains(&local_dir.display().to_string()), "unexpected error: {error}");
+        assert_eq!(fs::read(local_dir.join(LOCAL_FILE_NAME))?, b"changed");
+
+        fs::remove_dir_all(local_dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,140 @@
+//! The `.ssitem` format: a single item, for moving one credential between
+//! vaults without exporting (and importing) everything.
+//!
+//! The item is written exactly as it's stored in the database, i.e. with its
+//! secret still encrypted, along with its salt and nonce. The label, account
+//! name and modification date are the associated data of the encryption, so
+//! tampering with them makes the secret undecryptable. The layout is:
+//!
+//! | Offset | Size | Contents                                                 |
+//! |--------|------|----------------------------------------------------------|
+//! | 0      | 8    | Magic bytes: `STLITEM` followed by `0x1a` (ASCII `SUB`)  |
+//! | 8      | 4    | Format version, `u32`, little-endian                     |
+//! | 12     | rest | The UTF-8 JSON serialization of an [`ExportedItem`]      |
+//!
+//! The same versioning rules apply as to export bundles: newer versions
+//! are rejected, and every previous version remains readable.
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use crate::{
+    format::ExportedItem,
+    perms,
+    error::{Error, Result, ResultExt},
+};
+
+
+/// The file name extension of single-item files.
+pub const EXTENSION: &str = "ssitem";
+
+/// Identifies a single-item file; compare `format::MAGIC`.
+pub const MAGIC: [u8; 8] = *b"STLITEM\x1a";
+
+/// The version of the format written by this version of steelsafe.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Upper limit on the size of a file, so that reading garbage stops early.
+/// Secrets are limited to a few dozen kilobytes anyway.
+const MAX_FILE_LEN: u64 = 16 << 20;
+
+/// Writes the item into a new file, readable only by its owner (the label and
+/// account name are not encrypted). The file must not exist yet.
+pub fn write_item_file(path: &Path, item: &ExportedItem) -> Result<()> {
+    if path.try_exists()? {
+        let error = std::io::Error::from(std::io::ErrorKind::AlreadyExists);
+        return Err(Error::context(error, "Can't create item file"));
+    }
+
+    perms::create_private_file(path).context("Can't create item file")?;
+
+    let file = OpenOptions::new().write(true).open(path).context("Can't create item file")?;
+    let mut writer = BufWriter::new(file);
+
+    writer.write_all(&MAGIC)?;
+    writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+    serde_json::to_writer(&mut writer, item)?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Reads a single-item file of any supported version.
+pub fn read_item_file(path: &Path) -> Result<ExportedItem> {
+    let file = File::open(path).context("Can't open item file")?;
+    let mut reader = BufReader::new(file.take(MAX_FILE_LEN));
+    let mut prefix = [0_u8; MAGIC.len() + 4];
+
+    reader.read_exact(&mut prefix).map_err(|_| Error::InvalidItemFile)?;
+
+    let (magic, version) = prefix.split_at(MAGIC.len());
+    let version = u32::from_le_bytes(version.try_into().map_err(|_| Error::InvalidItemFile)?);
+
+    if magic != MAGIC || version == 0 || version > FORMAT_VERSION {
+        return Err(Error::InvalidItemFile);
+    }
+
+    serde_json::from_reader(reader).map_err(|_| Error::InvalidItemFile)
+}
+
+/// A file name for exporting the item, derived from its label: characters
+/// that are troublesome in file names (or in shells) are replaced by `_`.
+pub fn file_name(label: &str) -> String {
+    let stem: String = label
+        .chars()
+        .take(64)
+        .map(|c| if c.is_alphanumeric() || "-_.".contains(c) { c } else { '_' })
+        .collect();
+    let stem = stem.trim_start_matches('.');
+    let stem = if stem.is_empty() { "item" } else { stem };
+
+    format!("{stem}.{EXTENSION}")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+    use chrono::Utc;
+    use crate::crypto::{KdfParams, CipherId};
+    use crate::format::ExportedItem;
+    use crate::fields::{Label, AccountName};
+    use crate::error::{Error, Result};
+    use super::{write_item_file, read_item_file, file_name};
+
+
+    #[test]
+    fn item_file_round_trip() -> Result<()> {
+        let path = env::temp_dir().join(format!("steelsafe-ssitem-test-{}.ssitem", std::process::id()));
+        let item = ExportedItem {
+            label: Label::new("Bank")?,
+            account: Some(AccountName::new("alice")?),
+            last_modified_at: Utc::now(),
+            encrypted_secret: b"not really encrypted".to_vec(),
+            kdf_salt: [1; 16],
+            auth_nonce: [2; 24],
+            peppered: false,
+            kdf: KdfParams::CURRENT,
+            cipher: CipherId::CURRENT,
+        };
+
+        write_item_file(&path, &item)?;
+        assert_eq!(read_item_file(&path)?, item);
+
+        // existing files are never overwritten
+        assert!(write_item_file(&path, &item).is_err());
+
+        fs::write(&path, b"STLSAFE\x1a\x04\x00\x00\x00{}")?;
+        assert!(matches!(read_item_file(&path), Err(Error::InvalidItemFile)));
+
+        fs::remove_file(&path)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn file_names_are_safe() {
+        assert_eq!(file_name("Wi-Fi at the cottage"), "Wi-Fi_at_the_cottage.ssitem");
+        assert_eq!(file_name("../etc/passwd"), "_etc_passwd.ssitem");
+        assert_eq!(file_name("..."), "item.ssitem");
+    }
+}
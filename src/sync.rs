@@ -0,0 +1,388 @@
+//! Synchronization of the vault between devices through a git repository.
+//!
+//! The repository (a local clone, dedicated to steelsafe) contains a single
+//! sync file: the latest operation of every item (see `oplog`), including the
+//! tombstones of deleted items, encrypted with the sync password. Synchronizing
+//! consists of:
+//!
+//! 1. fetching the remote and resetting the working tree to the upstream branch,
+//!    then reading the sync file (`GitSync::fetch()`),
+//! 2. applying the operations of the sync file that are newer than the local ones
+//!    (`merge()`), exactly as `steelsafe merge` does with another vault,
+//! 3. writing the operations of both sides back to the sync file, and committing
+//!    and pushing it if it changed (`GitSync::push()`).
+//!
+//! Since the database is the merge point, local commits are never precious:
+//! anything that was committed but not pushed is re-created from the database.
+//! Items changed on both sides are merged by their latest change, and deletions
+//! are synchronized too. The first and the last steps may be slow, so the TUI
+//! runs them on a worker thread; only the merge itself needs the database.
+//!
+//! The sync file is laid out as follows (all integers are little-endian):
+//!
+//! | Offset  | Size | Contents                                                   |
+//! |---------|------|------------------------------------------------------------|
+//! | 0       | 8    | Magic bytes: `STLSYNC` followed by `0x1a` (ASCII `SUB`)    |
+//! | 8       | 4    | Format version, `u32`                                      |
+//! | 12      | 4    | Length of the header in bytes, `u32`, denoted by `N` below |
+//! | 16      | N    | Header: the UTF-8 JSON serialization of `SyncHeader`       |
+//! | 16 + N  | rest | Payload: a JSON array of `Operation`s, encrypted           |
+//!
+//! This is the container of export bundles (see `format::Container`), and the
+//! payload is encrypted using [`crypto::encrypt_stream()`], with the raw bytes of
+//! the header as associated data, in the same way as the payload of an export bundle.
+
+use std::fs::File;
+use std::io::{Write, BufReader, BufWriter, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use serde::{Serialize, Deserialize};
+use zeroize::Zeroizing;
+use crate::{
+    askpass::{self, PasswordCommand},
+    config::GitSyncConfig,
+    crypto,
+    vault::Vault,
+    net::Network,
+    oplog::{self, Operation, SyncReport},
+    format::{Container, PayloadEncryption},
+    error::{Error, Result, ResultExt},
+};
+
+
+/// The name of the sync file within the repository, unless configured otherwise.
+pub const DEFAULT_BUNDLE_FILE_NAME: &str = "vault.steelsafe";
+
+/// Identifies a sync file, and its version written by this version of steelsafe.
+/// The trailing `0x1a` byte of the magic is there for the same reason as in the
+/// magic of export bundles.
+const CONTAINER: Container = Container { magic: *b"STLSYNC\x1a", version: 1 };
+
+/// Shown by the terminal prompt, and passed to `password_command`.
+const PASSWORD_PROMPT: &str = "Sync password: ";
+
+/// Metadata describing the contents of a sync file.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+struct SyncHeader {
+    /// The number of operations in the payload. Used for detecting truncation.
+    operation_count: u64,
+    /// How the payload was encrypted.
+    payload_encryption: PayloadEncryption,
+}
+
+/// The repository after fetching the upstream branch, to be merged into the vault.
+#[derive(Debug)]
+pub struct Fetched {
+    has_upstream: bool,
+    /// The latest operation of every item, as written by `GitSync::push()`,
+    /// or `None` if the repository has no sync file yet.
+    operations: Option<Vec<Operation>>,
+}
+
+/// The operations to write to the sync file after merging it into the vault.
+#[derive(Debug)]
+pub struct Merged {
+    has_upstream: bool,
+    /// `None` if the sync file already contains exactly these operations.
+    operations: Option<Vec<Operation>>,
+    /// The number of operations received and sent, and the conflicting labels.
+    pub report: SyncReport,
+}
+
+/// Synchronizes the vault with a git repository and its upstream remote.
+#[derive(Clone, Debug)]
+pub struct GitSync {
+    repo: PathBuf,
+    bundle_file_name: String,
+    password_command: Option<PasswordCommand>,
+    network: Network,
+}
+
+impl GitSync {
+    pub fn new(config: &GitSyncConfig, network: Network) -> Self {
+        GitSync {
+            repo: config.repo.clone(),
+            network,
+            bundle_file_name: config
+                .bundle_file_name
+                .clone()
+                .unwrap_or_else(|| DEFAULT_BUNDLE_FILE_NAME.into()),
+            password_command: config.password_command.clone(),
+        }
+    }
+
+    /// Asks for the password of the sync file on the terminal, or runs
+    /// `git_sync.password_command` if it's configured.
+    pub fn ask_password(&self) -> Result<Zeroizing<String>> {
+        askpass::ask(self.password_command.as_ref(), PASSWORD_PROMPT, None)
+    }
+
+    /// Runs `git_sync.password_command` without access to the terminal, for the
+    /// TUI, which synchronizes by itself, and thus can't ask for the password.
+    pub fn run_password_command(&self) -> Result<Zeroizing<String>> {
+        self.password_command
+            .as_ref()
+            .ok_or(Error::SyncPasswordCommandRequired)?
+            .run(PASSWORD_PROMPT, None, false)
+    }
+
+    /// Pulls remote changes into the database, then commits and pushes local
+    /// changes, all on the current thread.
+    ///
+    /// If the current branch has no upstream, or network access is disabled,
+    /// only a local commit is made.
+    pub fn sync(&self, db: &mut dyn Vault, password: &[u8]) -> Result<SyncReport> {
+        let fetched = self.fetch(password)?;
+        let merged = merge(db, fetched)?;
+
+        self.push(&merged, password)?;
+
+        Ok(merged.report)
+    }
+
+    /// Resets the working tree to the upstream branch, if there is one and
+    /// network access is enabled, then reads and decrypts the sync file.
+    pub fn fetch(&self, password: &[u8]) -> Result<Fetched> {
+        let has_upstream = self.network.ensure_enabled().is_ok()
+            && self.git(&["rev-parse", "--abbrev-ref", "@{upstream}"]).is_ok();
+
+        if has_upstream {
+            self.git(&["fetch", "--quiet"])?;
+            self.git(&["reset", "--hard", "--quiet", "@{upstream}"])?;
+        }
+
+        let operations = read_sync_file(&self.bundle_path(), password)?;
+
+        Ok(Fetched { has_upstream, operations })
+    }
+
+    /// Writes the merged operations to the sync file, then commits and pushes it.
+    /// Does nothing if the sync file didn't need to change.
+    pub fn push(&self, merged: &Merged, password: &[u8]) -> Result<()> {
+        let Some(operations) = merged.operations.as_deref() else {
+            return Ok(());
+        };
+
+        let file = File::create(self.bundle_path()).context("Can't write sync file")?;
+        write_sync_file(BufWriter::new(file), operations, password)?;
+
+        self.git(&["add", "--", &self.bundle_file_name])?;
+        self.git(&["commit", "--quiet", "--message", "Update steelsafe vault"])?;
+
+        if merged.has_upstream {
+            self.git(&["push", "--quiet"])?;
+        }
+
+        Ok(())
+    }
+
+    fn bundle_path(&self) -> PathBuf {
+        self.repo.join(&self.bundle_file_name)
+    }
+
+    /// Runs a git command in the repository, and returns its standard output.
+    /// Git is never allowed to prompt for credentials, as that would mess up
+    /// the TUI; authentication must be non-interactive (e.g. an SSH agent).
+    fn git(&self, args: &[&str]) -> Result<String> {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(&self.repo)
+            .args(args)
+            .env("GIT_TERMINAL_PROMPT", "0")
+            .stdin(Stdio::null())
+            .output()
+            .context("Can't run git")?;
+
+        if output.status.success() {
+            Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+        } else {
+            Err(Error::Git {
+                command: args.first().copied().unwrap_or_default().into(),
+                message: String::from_utf8_lossy(&output.stderr).trim().into(),
+            })
+        }
+    }
+}
+
+/// Applies the fetched operations that are newer than the local ones, and
+/// collects the operations of both sides to be written back to the sync file.
+/// Operations that conflict with the label of a local item are kept in the sync
+/// file, so that they are applied once the conflict is resolved on either side.
+pub fn merge(db: &mut dyn Vault, fetched: Fetched) -> Result<Merged> {
+    let mut report = SyncReport::default();
+    let mut remote: &[Operation] = &[];
+
+    if let Some(operations) = fetched.operations.as_ref() {
+        (report.received, report.conflicts) = oplog::apply(db, operations.clone())?;
+        remote = operations;
+    }
+
+    let operations: Vec<Operation> = oplog::reconcile(remote.iter().cloned().chain(oplog::changeset(db)?))
+        .into_values()
+        .collect();
+
+    report.sent = operations.iter().filter(|operation| !remote.contains(operation)).count();
+
+    let is_unchanged = fetched.operations.is_some_and(|fetched| fetched == operations);
+
+    Ok(Merged {
+        has_upstream: fetched.has_upstream,
+        operations: if is_unchanged { None } else { Some(operations) },
+        report,
+    })
+}
+
+/// Reads the sync file in the repository, if it exists yet.
+fn read_sync_file(path: &Path, password: &[u8]) -> Result<Option<Vec<Operation>>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(error) if error.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(error) => return Err(Error::context(error, "Can't open sync file")),
+    };
+    let (_, operations) = CONTAINER.read(BufReader::new(file), |header: &SyncHeader, raw_header, reader| {
+        let mut payload = Zeroizing::new(Vec::new());
+        header.payload_encryption.decrypt(password, raw_header, reader, &mut *payload)?;
+
+        let operations: Vec<Operation> = serde_json::from_slice(&payload)?;
+
+        if operations.len() as u64 != header.operation_count {
+            return Err(Error::InvalidBundle);
+        }
+
+        Ok(operations)
+    })?;
+
+    Ok(Some(operations))
+}
+
+/// Writes the operations into a sync file, encrypted with `password`.
+fn write_sync_file<W: Write>(writer: W, operations: &[Operation], password: &[u8]) -> Result<()> {
+    let header = SyncHeader {
+        operation_count: operations.len() as u64,
+        payload_encryption: PayloadEncryption::CURRENT,
+    };
+
+    CONTAINER.write(writer, &header, |header, writer| {
+        let payload = Zeroizing::new(serde_json::to_vec(operations)?);
+        crypto::encrypt_stream(password, header, payload.as_slice(), writer)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use nanosql::Null;
+    use crate::config::DatabaseOptions;
+    use crate::crypto::{KdfParams, CipherId};
+    use crate::db::{Database, AddItemInput};
+    use crate::fields::Label;
+    use crate::oplog;
+    use crate::vault::Vault;
+    use crate::error::{Error, Result};
+    use super::{Fetched, merge, read_sync_file, write_sync_file};
+
+
+    fn add(db: &Database, label: &str, salt: u8) -> Result<()> {
+        db.add_item(AddItemInput {
+            uid: Null,
+            label: &Label::new(label)?,
+            account: None,
+            last_modified_at: Utc::now(),
+            encrypted_secret: b"not really encrypted",
+            kdf_salt: [salt; 16],
+            auth_nonce: [salt; 24],
+            peppered: false,
+            kdf: KdfParams::CURRENT,
+            cipher: CipherId::CURRENT,
+            reprompt: false,
+        })?;
+        Ok(())
+    }
+
+    /// Stands in for fetching the sync file that `other` would have pushed.
+    fn fetched_from(other: &Database) -> Result<Fetched> {
+        Ok(Fetched {
+            has_upstream: false,
+            operations: Some(oplog::changeset(other)?),
+        })
+    }
+
+    #[test]
+    fn merging_the_sync_file_propagates_deletions() -> Result<()> {
+        let mut laptop = Database::open(":memory:", &DatabaseOptions::default())?;
+        let mut phone = Database::open(":memory:", &DatabaseOptions::default())?;
+
+        add(&laptop, "bank", 1)?;
+        add(&laptop, "mail", 2)?;
+
+        let merged = merge(&mut phone, fetched_from(&laptop)?)?;
+        assert_eq!((merged.report.received, merged.report.sent), (2, 0));
+        assert!(merged.operations.is_none(), "nothing to push: {:?}", merged.operations);
+
+        let mail = phone.item_by_label("mail")?.expect("mail was synchronized");
+        phone.delete_item(mail.uid)?;
+        add(&phone, "shop", 3)?;
+
+        let merged = merge(&mut laptop, fetched_from(&phone)?)?;
+        assert_eq!((merged.report.received, merged.report.sent), (2, 0));
+        assert!(!laptop.label_exists("mail")?);
+        assert!(laptop.label_exists("shop")?);
+
+        // the tombstone is pushed along, so that the item isn't resurrected elsewhere
+        let merged = merge(&mut laptop, Fetched { has_upstream: false, operations: None })?;
+        let operations = merged.operations.expect("the sync file is written");
+        assert_eq!(operations.len(), 3);
+        assert_eq!(operations.iter().filter(|operation| operation.item.is_none()).count(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn conflicting_operations_stay_in_the_sync_file() -> Result<()> {
+        let mut laptop = Database::open(":memory:", &DatabaseOptions::default())?;
+        let phone = Database::open(":memory:", &DatabaseOptions::default())?;
+
+        add(&laptop, "bank", 1)?;
+        add(&phone, "bank", 2)?;
+
+        let merged = merge(&mut laptop, fetched_from(&phone)?)?;
+        assert_eq!(merged.report.conflicts, ["bank"]);
+        assert_eq!(laptop.item_by_label("bank")?.map(|item| item.kdf_salt), Some([1; 16]));
+
+        let operations = merged.operations.expect("the local item is pushed");
+        let salts: Vec<_> = operations
+            .iter()
+            .filter_map(|operation| operation.item.as_ref())
+            .map(|item| item.kdf_salt)
+            .collect();
+        assert_eq!(salts.len(), 2);
+        assert!(salts.contains(&[1; 16]) && salts.contains(&[2; 16]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn sync_file_is_encrypted() -> Result<()> {
+        let db = Database::open(":memory:", &DatabaseOptions::default())?;
+        add(&db, "a very conspicuous label", 1)?;
+
+        let operations = oplog::changeset(&db)?;
+        let mut file = Vec::new();
+        write_sync_file(&mut file, &operations, b"sync password")?;
+
+        let needle = b"conspicuous";
+        assert!(!file.windows(needle.len()).any(|window| window == needle));
+
+        let path = std::env::temp_dir().join(format!("steelsafe-sync-test-{}", std::process::id()));
+        std::fs::write(&path, &file)?;
+
+        let contents = read_sync_file(&path, b"sync password");
+        let wrong_password = read_sync_file(&path, b"wrong password");
+        std::fs::remove_file(&path)?;
+
+        assert_eq!(contents?, Some(operations));
+        assert!(matches!(wrong_password, Err(Error::XChaCha20Poly1305(_))), "{wrong_password:?}");
+
+        Ok(())
+    }
+}
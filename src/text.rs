@@ -0,0 +1,198 @@
+//! Width-aware text layout for the TUI.
+//!
+//! Terminal cells and `char`s don't correspond one-to-one: CJK characters and
+//! most emoji take up two cells, while combining marks take up none, and a
+//! single user-perceived character (grapheme cluster) may consist of several
+//! `char`s. Therefore, text is measured using its display width, and it's only
+//! ever split at grapheme cluster boundaries.
+
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+
+/// The number of terminal cells the text takes up.
+pub fn display_width(text: &str) -> usize {
+    text.width()
+}
+
+/// Shortens the text to at most `width` cells by replacing its middle with an
+/// ellipsis, so that both the beginning and the end (e.g. a domain name) remain visible.
+pub fn ellipsize_middle(text: &str, width: usize) -> String {
+    if display_width(text) <= width {
+        return text.to_owned();
+    }
+    if width == 0 {
+        return String::new();
+    }
+
+    // the ellipsis itself takes up one cell
+    let tail_width = (width - 1) / 2;
+    let head_width = width - 1 - tail_width;
+    let head = take_width(text.graphemes(true), head_width);
+    let mut tail = take_width(text.graphemes(true).rev(), tail_width);
+    tail.reverse();
+
+    head.into_iter()
+        .chain(["…"])
+        .chain(tail)
+        .collect()
+}
+
+/// Splits the text into lines of at most `width` cells. Returns a single
+/// empty line for empty text, so that the field is still shown.
+pub fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = vec![String::new()];
+    let mut line_width = 0;
+
+    for grapheme in text.graphemes(true) {
+        let grapheme_width = grapheme.width();
+
+        if line_width + grapheme_width > width && line_width > 0 {
+            lines.push(String::new());
+            line_width = 0;
+        }
+
+        lines.last_mut().expect("there is always at least one line").push_str(grapheme);
+        line_width += grapheme_width;
+    }
+
+    lines
+}
+
+/// Pads the text with spaces on the right, to be at least `width` cells wide.
+pub fn pad_end(text: &str, width: usize) -> String {
+    let padding = width.saturating_sub(display_width(text));
+    format!("{text}{:padding$}", "")
+}
+
+/// The number of `char`s in the grapheme cluster that ends before the `col`th
+/// `char` of the line, or 0 at the beginning of the line.
+pub fn grapheme_len_before(line: &str, col: usize) -> usize {
+    let (head, _) = line.split_at(char_offset(line, col));
+    head.graphemes(true).next_back().map_or(0, |grapheme| grapheme.chars().count())
+}
+
+/// The number of `char`s in the grapheme cluster that starts at the `col`th
+/// `char` of the line, or 0 at the end of the line.
+pub fn grapheme_len_after(line: &str, col: usize) -> usize {
+    let (_, tail) = line.split_at(char_offset(line, col));
+    tail.graphemes(true).next().map_or(0, |grapheme| grapheme.chars().count())
+}
+
+/// The byte offset of the `col`th `char`, or the length of the line past its end.
+fn char_offset(line: &str, col: usize) -> usize {
+    line.char_indices().nth(col).map_or(line.len(), |(offset, _)| offset)
+}
+
+/// Takes graphemes from the iterator for as long as they fit in `width` cells.
+fn take_width<'a, I>(graphemes: I, width: usize) -> Vec<&'a str>
+where
+    I: Iterator<Item = &'a str>,
+{
+    let mut total = 0;
+
+    graphemes
+        .take_while(|grapheme| {
+            total += grapheme.width();
+            total <= width
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::{
+        buffer::Buffer,
+        layout::{Rect, Constraint},
+        widgets::{Row, Table, Widget},
+        crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers},
+    };
+    use tui_textarea::TextArea;
+    use crate::ui::input_text;
+    use super::{display_width, ellipsize_middle, wrap, pad_end, grapheme_len_before, grapheme_len_after};
+
+
+    #[test]
+    fn ellipsis_respects_wide_characters_and_graphemes() {
+        assert_eq!(ellipsize_middle("example.com", 20), "example.com");
+        assert_eq!(ellipsize_middle("accounts.example.com", 9), "acco….com");
+        assert_eq!(ellipsize_middle("accounts.example.com", 9).chars().count(), 9);
+
+        // each of these takes up two cells
+        let cjk = "日本語のウェブサイト";
+        let shortened = ellipsize_middle(cjk, 9);
+        assert!(display_width(&shortened) <= 9);
+        assert_eq!(shortened, "日本…イト");
+
+        // a family emoji is a single grapheme made of several code points;
+        // it's either kept or dropped as a whole
+        let family = "👨‍👩‍👧";
+        let three = format!("{family}{family}{family}");
+        let shortened = ellipsize_middle(&three, 2 * display_width(family) + 1);
+        assert_eq!(shortened, format!("{family}…{family}"));
+
+        assert_eq!(ellipsize_middle("anything", 0), "");
+        assert_eq!(ellipsize_middle("anything", 1), "…");
+    }
+
+    #[test]
+    fn wrapping_and_padding_use_display_width() {
+        assert_eq!(wrap("", 10), [""]);
+        assert_eq!(wrap("abcdef", 4), ["abcd", "ef"]);
+        assert_eq!(wrap("日本語です", 5), ["日本", "語で", "す"]);
+        assert_eq!(wrap("e\u{301}e\u{301}e\u{301}", 2), ["e\u{301}e\u{301}", "e\u{301}"]);
+
+        assert_eq!(pad_end("日本", 6), "日本  ");
+        assert_eq!(pad_end("too long", 3), "too long");
+    }
+
+    #[test]
+    fn wide_labels_keep_table_columns_aligned() {
+        let area = Rect::new(0, 0, 17, 2);
+        let mut buf = Buffer::empty(area);
+        let rows = [
+            Row::new([ellipsize_middle("日本語のウェブサイト", 8), String::from("alice")]),
+            Row::new([ellipsize_middle("plain", 8), String::from("bob")]),
+        ];
+
+        Table::new(rows, [Constraint::Length(8), Constraint::Length(8)]).render(area, &mut buf);
+
+        // the second column starts at the same cell in both rows
+        assert_eq!(buf[(9, 0)].symbol(), "a");
+        assert_eq!(buf[(9, 1)].symbol(), "b");
+    }
+
+    #[test]
+    fn grapheme_lengths_around_the_cursor() {
+        let line = "ae\u{301}👍🏽";
+
+        assert_eq!(grapheme_len_before(line, 0), 0);
+        assert_eq!(grapheme_len_after(line, 0), 1);
+        assert_eq!(grapheme_len_before(line, 3), 2);
+        assert_eq!(grapheme_len_after(line, 1), 2);
+        assert_eq!(grapheme_len_after(line, 3), 2);
+        assert_eq!(grapheme_len_before(line, 5), 2);
+        assert_eq!(grapheme_len_after(line, 5), 0);
+        assert_eq!(grapheme_len_before(line, 99), 2);
+    }
+
+    #[test]
+    fn text_areas_edit_whole_graphemes() {
+        let key = |code| Event::Key(KeyEvent::new(code, KeyModifiers::NONE));
+        let mut text_area = TextArea::new(vec![String::from("xe\u{301}👍🏽y")]);
+
+        text_area.move_cursor(tui_textarea::CursorMove::End);
+        input_text(&mut text_area, key(KeyCode::Left));
+        input_text(&mut text_area, key(KeyCode::Left));
+        assert_eq!(text_area.cursor(), (0, 3));
+
+        assert!(input_text(&mut text_area, key(KeyCode::Backspace)));
+        assert_eq!(text_area.lines(), ["x👍🏽y"]);
+        assert!(input_text(&mut text_area, key(KeyCode::Delete)));
+        assert_eq!(text_area.lines(), ["xy"]);
+
+        input_text(&mut text_area, key(KeyCode::Right));
+        assert_eq!(text_area.cursor(), (0, 2));
+        assert!(!input_text(&mut text_area, key(KeyCode::Delete)));
+    }
+}
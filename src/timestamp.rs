@@ -0,0 +1,116 @@
+//! Formatting dates and times for display, in UTC or in the local
+//! time zone, and optionally relative to the current time.
+
+use chrono::{DateTime, Utc, Local};
+use chrono::format::{StrftimeItems, Item};
+use crate::{
+    config::TimestampConfig,
+    i18n::tr,
+    error::{Error, Result},
+};
+
+
+/// The format of absolute dates and times if none is configured.
+pub const DEFAULT_TIME_FORMAT: &str = "%F %T";
+
+/// Formats `time` according to the configuration.
+///
+/// Relative durations are only used for times in the past 30 days;
+/// older (and future) times are always shown as an absolute date.
+pub fn format_timestamp(time: DateTime<Utc>, config: &TimestampConfig, now: DateTime<Utc>) -> String {
+    if config.relative {
+        if let Some(relative) = format_relative(time, now) {
+            return relative;
+        }
+    }
+
+    if config.local_time {
+        time.with_timezone(&Local).format(config.format()).to_string()
+    } else {
+        time.format(config.format()).to_string()
+    }
+}
+
+/// Checks that a format string only contains specifiers known to `chrono`.
+/// Formatting with an invalid format string would panic.
+pub fn validate_time_format(format: &str) -> Result<()> {
+    if StrftimeItems::new(format).any(|item| item == Item::Error) {
+        Err(Error::InvalidTimeFormat(format.into()))
+    } else {
+        Ok(())
+    }
+}
+
+/// The time zone of absolute timestamps, to be shown in column titles.
+pub fn time_zone_name(config: &TimestampConfig) -> &'static str {
+    if config.local_time { tr("local") } else { "UTC" }
+}
+
+/// E.g. "just now", "1 minute ago", "3 days ago".
+fn format_relative(time: DateTime<Utc>, now: DateTime<Utc>) -> Option<String> {
+    let elapsed = now.signed_duration_since(time);
+
+    let (count, one, many) = if elapsed.num_seconds() < 0 {
+        return None;
+    } else if elapsed.num_minutes() < 1 {
+        return Some(String::from(tr("just now")));
+    } else if elapsed.num_hours() < 1 {
+        (elapsed.num_minutes(), tr("1 minute ago"), tr("{} minutes ago"))
+    } else if elapsed.num_days() < 1 {
+        (elapsed.num_hours(), tr("1 hour ago"), tr("{} hours ago"))
+    } else if elapsed.num_days() <= 30 {
+        (elapsed.num_days(), tr("1 day ago"), tr("{} days ago"))
+    } else {
+        return None;
+    };
+
+    if count == 1 {
+        Some(String::from(one))
+    } else {
+        Some(many.replacen("{}", &count.to_string(), 1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc, Duration};
+    use crate::config::TimestampConfig;
+    use super::{format_timestamp, validate_time_format};
+
+
+    #[test]
+    fn relative_timestamps() {
+        let now = Utc.with_ymd_and_hms(2024, 6, 30, 12, 0, 0).unwrap();
+        let config = TimestampConfig { local_time: false, relative: true, format: None };
+        let format = |elapsed: Duration| format_timestamp(now - elapsed, &config, now);
+
+        assert_eq!(format(Duration::seconds(59)), "just now");
+        assert_eq!(format(Duration::minutes(1)), "1 minute ago");
+        assert_eq!(format(Duration::minutes(59)), "59 minutes ago");
+        assert_eq!(format(Duration::hours(5)), "5 hours ago");
+        assert_eq!(format(Duration::days(3)), "3 days ago");
+        assert_eq!(format(Duration::days(31)), "2024-05-30 12:00:00");
+        assert_eq!(format(Duration::days(-1)), "2024-07-01 12:00:00");
+
+        let absolute = TimestampConfig { local_time: false, relative: false, format: None };
+        assert_eq!(format_timestamp(now, &absolute, now), "2024-06-30 12:00:00");
+    }
+
+    #[test]
+    fn custom_format_strings() {
+        let now = Utc.with_ymd_and_hms(2024, 6, 30, 12, 5, 0).unwrap();
+        let config = TimestampConfig {
+            local_time: false,
+            relative: true,
+            format: Some(String::from("%d.%m.%Y %H:%M")),
+        };
+
+        // relative times are not affected by the format string
+        assert_eq!(format_timestamp(now, &config, now), "just now");
+        assert_eq!(format_timestamp(now - Duration::days(60), &config, now), "01.05.2024 12:05");
+
+        assert!(validate_time_format("%d.%m.%Y %H:%M").is_ok());
+        assert!(validate_time_format("%Q").is_err());
+        assert!(validate_time_format("100%").is_err());
+    }
+}
@@ -1,28 +1,67 @@
 //! The bulk of the actual user interface logic.
 
-use std::mem;
-use std::ops::{ControlFlow, Deref, DerefMut};
-use std::time::Duration;
+use std::fs::File;
+use std::io::BufWriter;
+use std::sync::Arc;
+use std::collections::{HashMap, HashSet};
+use std::ops::ControlFlow;
+use std::time::{Duration, Instant};
 use std::fmt::{self, Debug, Formatter};
-use nanosql::Utc;
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+use nanosql::{DateTime, Utc};
+use chrono::Local;
 use zeroize::Zeroizing;
 use ratatui::{
     Frame,
-    layout::{Rect, Offset, Constraint, Margin},
-    text::Line,
-    style::Modifier,
-    widgets::{
-        Clear, Table, TableState, Row, Paragraph,
-        block::{Block, BorderType},
-    },
-    crossterm::event::{self, Event, KeyEventKind, KeyCode, KeyModifiers, MouseEventKind},
+    layout::{Rect, Position, Constraint, Margin, Layout, Flex},
+    text::{Line, Span},
+    style::{Style, Color, Modifier},
+    widgets::{Clear, Table, TableState, Row, Paragraph, ScrollbarState, block::{Block, BorderType}},
+    crossterm::event::{Event, KeyEvent, KeyEventKind, KeyCode, KeyModifiers, MouseEventKind, MouseButton},
 };
 use tui_textarea::TextArea;
-use arboard::Clipboard;
+#[cfg(unix)]
+use crate::agent;
 use crate::{
-    config::Theme,
-    crypto::{EncryptionInput, DecryptionInput},
-    db::{Database, Item, DisplayItem, AddItemInput},
+    config::{
+        Config, Theme, TimestampConfig, TableColumn, ColumnKind, SortOrder,
+        PanelConfig, PanelPosition, SizeLimits, FocusLock, PollConfig,
+    },
+    crypto::{EncryptionInput, EncryptionOutput, Pepper},
+    memlock::{SecretBytes, SecretString},
+    fields::{Label, AccountName, PlaintextSecret, MasterPassword},
+    qr::{self, QrMatrix},
+    lockout::FailedAttempts,
+    otp::OtpAuthUri,
+    timestamp::{format_timestamp, time_zone_name},
+    text::{self, ellipsize_middle},
+    query::Query,
+    expiry::{ExpiryReport, ExpiryStatus},
+    health::Finding,
+    palette::{self, Command},
+    secret_input::{SecretInput, Strength},
+    passgen::Generator,
+    policy::{Policy, PasswordPolicy, ItemType},
+    markdown,
+    progress::{Task, Reporter, ProgressDialog},
+    input::EventSource,
+    clipboard::{Clipboard, CopyTarget},
+    askpass::PasswordCommand,
+    sync::{self, GitSync, Fetched},
+    ssitem,
+    conflict,
+    format::{Bundle, ExportedItem},
+    net::Network,
+    db::{
+        Item, DisplayItem, AddItemInput, UpdateEncryptionInput,
+        AccessLogEntry, TagCount, VaultStats, Compaction, ItemRecoveryCodes, SmartFilter, NOTE_TAG,
+        WIFI_TAG, TOTP_TAG, normalize_tag,
+    },
+    recovery::RecoveryCodes,
+    vault::Vault,
+    ui::{hint, scrollbar, input_text, forms::{Form, Field, Input, Note, Placement}},
+    i18n::tr,
     error::{Error, Result},
 };
 
@@ -30,38 +69,242 @@ use crate::{
 /// The top-level UI state, the basis of rendering.
 #[derive(Debug)]
 pub struct State {
-    db: Database,
-    clipboard: ClipboardDebugWrapper,
+    db: Box<dyn Vault>,
+    /// Shared with worker threads.
+    pepper: Option<Arc<Pepper>>,
+    clipboard: Clipboard,
+    /// Where the "copy" action puts secrets.
+    copy_to: CopyTarget,
+    /// Supplies the password instead of the password entry panel, if set.
+    password_command: Option<PasswordCommand>,
     theme: Theme,
     is_running: bool,
+    failed_attempts: FailedAttempts,
+    persist_failed_attempts: bool,
+    access_log: bool,
+    timestamps: TimestampConfig,
+    limits: SizeLimits,
+    git_sync: Option<GitSync>,
+    #[cfg(unix)]
+    agent_socket: Option<PathBuf>,
     passwd_entry: Option<PasswordEntryState>,
     find: Option<FindItemState>,
     new_item: Option<NewItemState>,
+    /// Offered by the New item dialog; random characters come first.
+    generators: Vec<Generator>,
+    /// Combined into the policy of each new item, by its type and tags.
+    policies: Vec<PasswordPolicy>,
+    stats: Option<VaultStats>,
+    /// The result of compacting the database from the statistics modal.
+    compaction: Option<Compaction>,
+    /// Weak and reused secrets, once checked from the statistics modal.
+    secret_audit: Option<SecretAudit>,
+    /// Decrypting every secret to check it for the statistics modal.
+    auditing: Option<Task<SecretAudit>>,
+    access_history: Option<AccessHistory>,
+    qr_code: Option<QrCodeView>,
+    tag_manager: Option<TagManagerState>,
+    crypto_upgrade: Option<Task<CryptoUpgrade>>,
+    /// Fetching or pushing the git sync file; the vault is merged in between.
+    syncing: Option<Task<SyncStep>>,
+    /// The vault changed while it was being synchronized, so it's synchronized again.
+    sync_again: bool,
+    /// Key derivation takes a noticeable amount of time, so secrets are
+    /// decrypted and encrypted on a worker thread, while a spinner is shown.
+    decryption: Option<PendingDecryption>,
+    encryption: Option<PendingEncryption>,
+    /// The index of the selected entry while the column menu is open.
+    column_menu: Option<usize>,
+    /// The index of the selected entry while the filter menu is open;
+    /// 0 is "All items", followed by `SmartFilter::ALL`.
+    filter_menu: Option<usize>,
+    /// Restricts the table in addition to the Find panel, if set.
+    smart_filter: Option<SmartFilter>,
+    /// Account names are masked and labels are truncated in the table,
+    /// e.g. while sharing the screen.
+    privacy_mode: bool,
+    /// What it takes to unlock the screen once the terminal has lost focus.
+    focus_lock: FocusLock,
+    /// The terminal has lost focus, so nothing is shown until it's unlocked.
+    locked: bool,
+    /// How long to wait for input, depending on whether the user is idle.
+    poll: PollConfig,
+    /// When the last event (key press, resize, etc.) arrived.
+    last_input_at: Instant,
+    /// Something has changed since the screen was last drawn.
+    needs_redraw: bool,
+    /// When the screen last drawn becomes outdated by itself, if it does,
+    /// e.g. because of a spinner or a countdown. See `next_change_at()`.
+    redraw_at: Option<Instant>,
+    palette: Option<PaletteState>,
+    /// The item whose full metadata is shown.
+    details: Option<DisplayItem>,
+    /// The decrypted secret of the item in `details`, if it's a note,
+    /// shown below the metadata, with Markdown styling.
+    details_note: Option<Zeroizing<String>>,
+    /// The field for the new label, while the item in `details` is being renamed.
+    details_rename: Option<Form>,
+    /// The new label of the item, once entered. The item is re-encrypted with
+    /// it after asking for the password, since the label is authenticated.
+    rename_to: Option<Label>,
+    /// Items due to expire, shown in a banner above the table until dismissed.
+    expiry_report: Option<ExpiryReport>,
+    confirm_discard: Option<DiscardAction>,
+    /// Problems found by the pre-flight checks, shown upon startup until dismissed.
+    findings: Vec<Finding>,
+    /// Where `.ssitem` files are exported to: the working directory.
+    export_dir: PathBuf,
+    /// The file the selected item has just been exported to, shown until dismissed.
+    exported_to: Option<PathBuf>,
+    /// The number of items in `exported_to`.
+    exported_count: usize,
+    /// The unique IDs of the items marked with `Space` (multi-select mode, if
+    /// not empty). Writing to a file then exports these, instead of the selected one.
+    marked: HashSet<u64>,
+    /// The bundle password entered first while exporting the marked items,
+    /// until it's confirmed.
+    bundle_password: Option<SecretString>,
+    /// When Ctrl+C was last pressed; pressing it again soon after quits.
+    last_interrupt: Option<Instant>,
+    /// The beginning of a label typed after `'`, to jump to the item.
+    type_ahead: Option<TypeAhead>,
+    /// Ctrl+Z was pressed; the main loop suspends the process.
+    #[cfg(unix)]
+    suspend_requested: bool,
     popup_error: Option<Error>,
+    /// Every kind of column, in display order, and whether it's visible.
+    columns: Vec<(TableColumn, bool)>,
+    /// The order of `items`.
+    sort: SortOrder,
+    /// Where the search term and the password entry are shown.
+    panel: PanelConfig,
     items: Vec<DisplayItem>,
+    /// The number of rows the table showed when it was last drawn;
+    /// PageUp and PageDown move by this many.
+    table_page: usize,
+    /// Where the scrollbar of the table was last drawn, if the items didn't fit.
+    scrollbar_area: Option<Rect>,
+    /// The thumb of the scrollbar is being dragged with the mouse.
+    dragging_scrollbar: bool,
+    item_tags: HashMap<u64, Vec<String>>,
+    recovery_codes: HashMap<u64, ItemRecoveryCodes>,
+    urls: HashMap<u64, String>,
+    item_expiries: HashMap<u64, DateTime<Utc>>,
+    /// Items expiring within this many days are marked in the expiry column.
+    expiry_warning_days: u32,
     table_state: TableState,
 }
 
 impl State {
-    pub fn new(db: Database, theme: Theme) -> Result<Self> {
-        let items = db.list_items_for_display(None)?;
-        let clipboard = ClipboardDebugWrapper(Clipboard::new()?);
+    pub fn new(db: Box<dyn Vault>, pepper: Option<Pepper>, config: &Config) -> Result<Self> {
+        let git_sync = config.git_sync.as_ref().map(|sync| GitSync::new(sync, Network::new(config)));
+        let mut items = db.list_items_for_display(None)?;
+        let item_tags = db.tags_by_item()?;
+        let recovery_codes = db.recovery_codes()?;
+        let urls = db.urls()?;
+        let item_expiries = db.expiries()?;
+        let clipboard = Clipboard::new(&config.clipboard);
+        let expiry_report = ExpiryReport::new(&*db, Utc::now(), config.expiry.warning_days)?;
+        let failed_attempt_count = if config.persist_failed_attempts {
+            db.failed_attempts()?
+        } else {
+            0
+        };
+
+        items.sort_by(|lhs, rhs| config.sort.compare(lhs, rhs));
 
         let table_state = TableState::new()
             .with_selected(if items.is_empty() { None } else { Some(0) });
 
-        Ok(State {
+        let mut state = State {
             db,
+            pepper: pepper.map(Arc::new),
             clipboard,
-            theme,
+            copy_to: config.copy_to,
+            password_command: config.password_command.clone().filter(|command| command.tui),
+            theme: config.theme.clone(),
             is_running: true,
+            failed_attempts: FailedAttempts::new(failed_attempt_count, Instant::now()),
+            persist_failed_attempts: config.persist_failed_attempts,
+            access_log: config.access_log,
+            timestamps: config.timestamps.clone(),
+            limits: config.limits,
+            git_sync,
+            #[cfg(unix)]
+            agent_socket: config.agent_socket_path().ok(),
             passwd_entry: None,
             find: None,
             new_item: None,
+            generators: Generator::from_config(&config.generator)?,
+            policies: config.policies.clone(),
+            stats: None,
+            compaction: None,
+            secret_audit: None,
+            auditing: None,
+            access_history: None,
+            qr_code: None,
+            tag_manager: None,
+            crypto_upgrade: None,
+            syncing: None,
+            sync_again: false,
+            decryption: None,
+            encryption: None,
+            column_menu: None,
+            filter_menu: None,
+            smart_filter: None,
+            privacy_mode: false,
+            focus_lock: config.lock_on_focus_loss,
+            locked: false,
+            poll: config.poll,
+            last_input_at: Instant::now(),
+            needs_redraw: true,
+            redraw_at: None,
+            palette: None,
+            details: None,
+            details_note: None,
+            details_rename: None,
+            rename_to: None,
+            expiry_report: Some(expiry_report).filter(|report| !report.is_empty()),
+            confirm_discard: None,
+            findings: Vec::new(),
+            export_dir: PathBuf::from("."),
+            exported_to: None,
+            exported_count: 0,
+            marked: HashSet::new(),
+            bundle_password: None,
+            last_interrupt: None,
+            type_ahead: None,
+            #[cfg(unix)]
+            suspend_requested: false,
             popup_error: None,
+            columns: initial_columns(&config.columns),
+            sort: config.sort,
+            panel: config.panel,
             items,
+            table_page: 0,
+            scrollbar_area: None,
+            dragging_scrollbar: false,
+            item_tags,
+            recovery_codes,
+            urls,
+            item_expiries,
+            expiry_warning_days: config.expiry.warning_days,
             table_state,
-        })
+        };
+
+        // Being offline shouldn't prevent access to the local vault,
+        // so a failed synchronization is reported but not fatal.
+        if let Err(error) = state.sync_vault() {
+            state.popup_error = Some(error);
+        }
+
+        Ok(state)
+    }
+
+    /// Shows the problems found by the pre-flight checks in a panel.
+    pub fn show_findings(&mut self, findings: Vec<Finding>) {
+        self.findings = findings;
+        self.needs_redraw = true;
     }
 
     /// Returns `true` as long as the application should run.
@@ -70,47 +313,144 @@ impl State {
         self.is_running
     }
 
+    /// Returns `true` once after Ctrl+Z was pressed.
+    #[cfg(unix)]
+    pub fn take_suspend_request(&mut self) -> bool {
+        std::mem::take(&mut self.suspend_requested)
+    }
+
+    /// Returns `true` if the screen has to be drawn again, because something
+    /// has changed since `draw()` was last called.
+    pub const fn needs_redraw(&self) -> bool {
+        self.needs_redraw
+    }
+
+    /// Makes the next check of `needs_redraw()` return `true`, e.g. because
+    /// the terminal was cleared.
+    pub fn request_redraw(&mut self) {
+        self.needs_redraw = true;
+    }
+
     /// Top-level widget rendering.
     pub fn draw(&mut self, frame: &mut Frame) {
-        let bottom_input_height = 3;
-        let mut table_area = {
-            let mut area = frame.area();
-            area.height -= bottom_input_height;
-            area
-        };
-        let bottom_input_area = Rect {
-            x: table_area.x,
-            y: table_area.y + table_area.height,
-            width: table_area.width,
-            height: bottom_input_height,
-        };
-        let table = self.main_table();
+        self.needs_redraw = false;
+        self.redraw_at = self.next_change_at(Instant::now());
+
+        let (mut table_area, panel_area) = self.split_panel(frame.area());
 
         if let Some(passwd_entry) = self.passwd_entry.as_mut() {
-            frame.render_widget(&passwd_entry.enc_pass, bottom_input_area);
-        } else if let Some(find_state) = self.find.as_mut() {
-            frame.render_widget(&find_state.search_term, bottom_input_area);
+            passwd_entry.set_cooldown(self.failed_attempts.remaining_cooldown(Instant::now()));
+            frame.render_widget(&passwd_entry.enc_pass, panel_area);
+        } else if let Some(find_state) = self.find.as_mut().filter(|_| !self.locked) {
+            frame.render_widget(&find_state.search_term, panel_area);
         } else {
             table_area = frame.area();
         }
 
+        if let Some(report) = self.expiry_report.as_ref() {
+            let banner_area = Rect { height: table_area.height.min(1), ..table_area };
+            table_area.y += 1;
+            table_area.height = table_area.height.saturating_sub(1);
+            frame.render_widget(self.expiry_banner(report), banner_area);
+        }
+
+        // the height of the table, less the borders and the header row
+        let page_height = table_area.height.saturating_sub(3);
+        self.table_page = usize::from(page_height);
+
+        // the width of the table, less the borders
+        let table = self.main_table(table_area.width.saturating_sub(2));
         frame.render_stateful_widget(table, table_area, &mut self.table_state);
 
+        // on the right border, next to the rows, after rendering adjusted the offset
+        self.scrollbar_area = Some(Rect {
+            x: table_area.right().saturating_sub(1),
+            y: table_area.y + 2,
+            width: 1,
+            height: page_height,
+        }).filter(|_| page_height > 0 && self.items.len() > self.table_page);
+
+        if let Some(scrollbar_area) = self.scrollbar_area {
+            frame.render_stateful_widget(scrollbar(&self.theme), scrollbar_area, &mut self.scrollbar_state());
+        }
+
+        if self.items.is_empty() && (self.find.is_some() || self.smart_filter.is_some()) {
+            // below the top border and the header row
+            let placeholder_area = Rect {
+                y: table_area.y + 2,
+                height: table_area.height.saturating_sub(3).min(1),
+                ..table_area.inner(Margin { horizontal: 1, vertical: 0 })
+            };
+            let placeholder = Paragraph::new(hint("No items match"))
+                .centered()
+                .style(self.theme.border_highlight());
+
+            frame.render_widget(placeholder, placeholder_area);
+        }
+
+        if self.locked {
+            frame.render_widget(Clear, table_area);
+            frame.render_widget(self.lock_screen(), table_area);
+        }
+
         if let Some(error) = self.popup_error.as_ref() {
+            let lines = text::wrap(&error.localized(), ERROR_WIDTH - 2);
             let margin = Margin {
-                horizontal: table_area.width.saturating_sub(72 + 2) / 2,
-                vertical: table_area.height.saturating_sub(3 + 2) / 2,
+                horizontal: table_area.width.saturating_sub(ERROR_WIDTH as u16 + 2) / 2,
+                vertical: table_area.height.saturating_sub(lines.len() as u16 + 2 + 2) / 2,
+            };
+            let dialog_area = table_area.inner(margin);
+            let modal = self.error_modal(lines);
+
+            frame.render_widget(Clear, dialog_area);
+            frame.render_widget(modal, dialog_area);
+        } else if self.locked {
+            // only errors are shown over the lock screen, since they may explain why it's still locked
+        } else if !self.findings.is_empty() {
+            let lines = self.findings_lines();
+            let margin = Margin {
+                horizontal: table_area.width.saturating_sub(ERROR_WIDTH as u16 + 2) / 2,
+                vertical: table_area.height.saturating_sub(lines.len() as u16 + 2) / 2,
+            };
+            let dialog_area = table_area.inner(margin);
+            let modal = self.findings_modal(lines);
+
+            frame.render_widget(Clear, dialog_area);
+            frame.render_widget(modal, dialog_area);
+        } else if let Some(path) = self.exported_to.as_ref() {
+            let message = tr("Exported to {}").replacen("{}", &path.display().to_string(), 1);
+            let lines = text::wrap(&message, ERROR_WIDTH - 2);
+            let margin = Margin {
+                horizontal: table_area.width.saturating_sub(ERROR_WIDTH as u16 + 2) / 2,
+                vertical: table_area.height.saturating_sub(lines.len() as u16 + 2) / 2,
             };
             let dialog_area = table_area.inner(margin);
-            let modal = self.error_modal(error);
+            let modal = self.exported_modal(lines);
 
             frame.render_widget(Clear, dialog_area);
             frame.render_widget(modal, dialog_area);
+        } else if let Some(palette) = self.palette.as_ref() {
+            let commands = palette.commands();
+            let list_height = commands.len().clamp(1, PALETTE_HEIGHT) as u16;
+            let margin = Margin {
+                horizontal: table_area.width.saturating_sub(48 + 2) / 2,
+                vertical: table_area.height.saturating_sub(3 + list_height + 2) / 2,
+            };
+            let dialog_area = table_area.inner(margin);
+            let query_area = Rect { height: dialog_area.height.min(3), ..dialog_area };
+            let list_area = Rect {
+                y: dialog_area.y + 3,
+                height: dialog_area.height.saturating_sub(3),
+                ..dialog_area
+            };
+
+            frame.render_widget(Clear, dialog_area);
+            frame.render_widget(&palette.query, query_area);
+            frame.render_widget(self.palette_list(palette, &commands), list_area);
         } else if let Some(new_item) = self.new_item.as_ref() {
-            let inputs_total_height = new_item.text_areas().len() as u16 * 3;
             let margin = Margin {
                 horizontal: table_area.width.saturating_sub(72 + 2) / 2,
-                vertical: table_area.height.saturating_sub(inputs_total_height + 2) / 2,
+                vertical: table_area.height.saturating_sub(new_item.form.height() + 2) / 2,
             };
             let dialog_area = table_area.inner(margin);
             let outer = self.new_item_background(new_item);
@@ -118,729 +458,5213 @@ impl State {
             frame.render_widget(Clear, dialog_area);
             frame.render_widget(&outer, dialog_area);
 
-            let label_rect = Rect { height: 3, ..outer.inner(dialog_area) };
-            let desc_rect = label_rect.offset(Offset { x: 0, y: 3 });
-            let secret_rect = desc_rect.offset(Offset { x: 0, y: 3 });
-            let passwd_rect = secret_rect.offset(Offset { x: 0, y: 3 });
-            let confirm_rect = passwd_rect.offset(Offset { x: 0, y: 3 });
+            // The form extends over the right border, where its scrollbar goes
+            // on short terminals, on which not every field fits.
+            let inputs_area = outer.inner(dialog_area);
+            let form_area = Rect { width: inputs_area.width + 1, ..inputs_area }.intersection(dialog_area);
+
+            frame.render_widget(&new_item.form, form_area);
+
+            if let Some(selected) = new_item.generator_menu {
+                let margin = Margin {
+                    horizontal: dialog_area.width.saturating_sub(40 + 2) / 2,
+                    vertical: dialog_area.height.saturating_sub(self.generators.len() as u16 + 2) / 2,
+                };
+                let menu_area = dialog_area.inner(margin);
+
+                frame.render_widget(Clear, menu_area);
+                frame.render_widget(self.generator_menu_modal(selected, &new_item.policy), menu_area);
+            }
+        } else if let Some(stats) = self.stats.as_ref() {
+            let lines = self.stats_lines(stats);
+            let margin = Margin {
+                horizontal: table_area.width.saturating_sub(48 + 2) / 2,
+                vertical: table_area.height.saturating_sub(lines.len() as u16 + 2) / 2,
+            };
+            let dialog_area = table_area.inner(margin);
+            let modal = self.stats_modal(lines);
+
+            frame.render_widget(Clear, dialog_area);
+            frame.render_widget(modal, dialog_area);
+        } else if let Some(history) = self.access_history.as_ref() {
+            let lines = self.access_history_lines(history);
+            let margin = Margin {
+                horizontal: table_area.width.saturating_sub(48 + 2) / 2,
+                vertical: table_area.height.saturating_sub(lines.len() as u16 + 2) / 2,
+            };
+            let dialog_area = table_area.inner(margin);
+            let modal = self.access_history_modal(history, lines);
+
+            frame.render_widget(Clear, dialog_area);
+            frame.render_widget(modal, dialog_area);
+        } else if let Some(qr_code) = self.qr_code.as_ref() {
+            let margin = Margin {
+                horizontal: table_area.width.saturating_sub(qr_code.width() + 2) / 2,
+                vertical: table_area.height.saturating_sub(qr_code.height() + 2) / 2,
+            };
+            let dialog_area = table_area.inner(margin);
+            let modal = self.qr_code_modal(qr_code);
+
+            frame.render_widget(Clear, dialog_area);
+            frame.render_widget(modal, dialog_area);
+        } else if let Some(item) = self.details.as_ref() {
+            let lines = self.details_lines(item);
+            let input_height = self.details_rename.as_ref().map_or(0, Form::height);
+            let margin = Margin {
+                horizontal: table_area.width.saturating_sub(DETAILS_WIDTH as u16 + 2) / 2,
+                vertical: table_area.height.saturating_sub(lines.len() as u16 + input_height + 2) / 2,
+            };
+            let dialog_area = table_area.inner(margin);
+            let modal = self.details_modal(item, lines);
+
+            frame.render_widget(Clear, dialog_area);
+            frame.render_widget(modal, dialog_area);
+
+            if let Some(rename) = self.details_rename.as_ref() {
+                let input_area = Rect {
+                    x: dialog_area.x + 1,
+                    y: (dialog_area.y + dialog_area.height).saturating_sub(input_height + 1),
+                    width: dialog_area.width.saturating_sub(1),
+                    height: input_height,
+                };
+                frame.render_widget(rename, input_area);
+            }
+        } else if let Some(selected) = self.column_menu {
+            let margin = Margin {
+                horizontal: table_area.width.saturating_sub(32 + 2) / 2,
+                vertical: table_area.height.saturating_sub(self.columns.len() as u16 + 2) / 2,
+            };
+            let dialog_area = table_area.inner(margin);
+            let modal = self.column_menu_modal(selected);
+
+            frame.render_widget(Clear, dialog_area);
+            frame.render_widget(modal, dialog_area);
+        } else if let Some(selected) = self.filter_menu {
+            let margin = Margin {
+                horizontal: table_area.width.saturating_sub(32 + 2) / 2,
+                vertical: table_area.height.saturating_sub(SmartFilter::ALL.len() as u16 + 1 + 2) / 2,
+            };
+            let dialog_area = table_area.inner(margin);
+            let modal = self.filter_menu_modal(selected);
+
+            frame.render_widget(Clear, dialog_area);
+            frame.render_widget(modal, dialog_area);
+        } else if let Some(manager) = self.tag_manager.as_ref() {
+            let lines = self.tag_manager_lines(manager);
+            let input_height = manager.rename.as_ref().map_or(0, Form::height);
+            let margin = Margin {
+                horizontal: table_area.width.saturating_sub(48 + 2) / 2,
+                vertical: table_area.height.saturating_sub(lines.len() as u16 + input_height + 2) / 2,
+            };
+            let dialog_area = table_area.inner(margin);
+            let modal = self.tag_manager_modal(manager, lines);
+
+            frame.render_widget(Clear, dialog_area);
+            frame.render_widget(modal, dialog_area);
+
+            if let Some(rename) = manager.rename.as_ref() {
+                let input_area = Rect {
+                    x: dialog_area.x + 1,
+                    y: (dialog_area.y + dialog_area.height).saturating_sub(input_height + 1),
+                    width: dialog_area.width.saturating_sub(1),
+                    height: input_height,
+                };
+                frame.render_widget(rename, input_area);
+            }
+        }
+
+        // The confirmation is rendered on top of the dialog it refers to.
+        if let (None, Some(action)) = (self.popup_error.as_ref(), self.confirm_discard) {
+            let margin = Margin {
+                horizontal: table_area.width.saturating_sub(48 + 2) / 2,
+                vertical: table_area.height.saturating_sub(3 + 2) / 2,
+            };
+            let dialog_area = table_area.inner(margin);
+            let modal = self.confirm_discard_modal(action);
+
+            frame.render_widget(Clear, dialog_area);
+            frame.render_widget(modal, dialog_area);
+        }
+
+        // Long-running operations block every other dialog until they finish.
+        // The progress shows the labels of items, so it's hidden while locked.
+        let progress = self.crypto_upgrade
+            .as_ref()
+            .map(|task| task.dialog(&self.theme))
+            .or_else(|| self.auditing.as_ref().map(|task| task.dialog(&self.theme)))
+            .or_else(|| self.syncing.as_ref().map(|task| task.dialog(&self.theme)));
+
+        if let (None, Some(dialog), false) = (self.popup_error.as_ref(), progress, self.locked) {
+            let margin = Margin {
+                horizontal: table_area.width.saturating_sub(48 + 2) / 2,
+                vertical: table_area.height.saturating_sub(ProgressDialog::HEIGHT) / 2,
+            };
+            let dialog_area = table_area.inner(margin);
+
+            frame.render_widget(Clear, dialog_area);
+            frame.render_widget(dialog, dialog_area);
+        }
+
+        let waiting = match (self.decryption.as_ref(), self.encryption.as_ref()) {
+            (Some(pending), _) => Some((pending.task.title(), pending.task.elapsed())),
+            (None, Some(pending)) => Some((pending.task.title(), pending.task.elapsed())),
+            (None, None) => None,
+        };
+
+        if let (None, Some((message, elapsed))) = (self.popup_error.as_ref(), waiting) {
+            let margin = Margin {
+                horizontal: table_area.width.saturating_sub(32 + 2) / 2,
+                vertical: table_area.height.saturating_sub(1 + 2) / 2,
+            };
+            let dialog_area = table_area.inner(margin);
+            let modal = self.spinner_modal(message, elapsed);
+
+            frame.render_widget(Clear, dialog_area);
+            frame.render_widget(modal, dialog_area);
+        }
+    }
+
+    /// Formats a date and time as configured in `timestamps`.
+    fn format_time(&self, time: DateTime<Utc>) -> String {
+        format_timestamp(time, &self.timestamps, Utc::now())
+    }
+
+    fn visible_columns(&self) -> impl Iterator<Item = &TableColumn> {
+        self.columns.iter().filter(|(_, visible)| *visible).map(|(column, _)| column)
+    }
+
+    fn cell_text(&self, item: &DisplayItem, kind: ColumnKind) -> String {
+        match kind {
+            ColumnKind::Label if self.privacy_mode => private_label(&item.label),
+            ColumnKind::Label => item.label.clone(),
+            // the length of the name isn't shown either
+            ColumnKind::Account if self.privacy_mode => {
+                item.account.as_ref().map_or_else(String::new, |_| "•".repeat(PRIVATE_MASK_LEN))
+            }
+            ColumnKind::Account => item.account.clone().unwrap_or_default(),
+            ColumnKind::Modified => self.format_time(item.last_modified_at),
+            ColumnKind::Tags => self.item_tags.get(&item.uid).map_or_else(String::new, |tags| tags.join(", ")),
+            ColumnKind::RecoveryCodes => self.recovery_codes
+                .get(&item.uid)
+                .map_or_else(String::new, |codes| format!("{}/{}", codes.remaining, codes.total)),
+            ColumnKind::Expiry => self.item_expiries.get(&item.uid).map_or_else(String::new, |&expires_at| {
+                // the same calendar date as the expiry queries, not the local one
+                let date = expires_at.format("%Y-%m-%d");
+
+                match ExpiryStatus::of(expires_at, Utc::now(), self.expiry_warning_days) {
+                    ExpiryStatus::Valid => date.to_string(),
+                    ExpiryStatus::Expiring => format!("{date} [{}]", tr("expiring")),
+                    ExpiryStatus::Expired => format!("{date} [{}]", tr("expired")),
+                }
+            }),
+        }
+    }
+
+    /// Splits the screen into the table and the panel of the search term
+    /// and the password entry. The panel is never taller than the screen,
+    /// and the table gets whatever is left, which may be nothing at all.
+    fn split_panel(&self, area: Rect) -> (Rect, Rect) {
+        let panel = Constraint::Length(self.panel.height.min(area.height));
+        let table = Constraint::Fill(1);
 
-            frame.render_widget(&new_item.label, label_rect);
-            frame.render_widget(&new_item.account, desc_rect);
-            frame.render_widget(&new_item.secret, secret_rect);
-            frame.render_widget(&new_item.enc_pass, passwd_rect);
-            frame.render_widget(&new_item.confirm, confirm_rect);
+        match self.panel.position {
+            PanelPosition::Bottom => {
+                let [table_area, panel_area] = Layout::vertical([table, panel]).areas(area);
+                (table_area, panel_area)
+            }
+            PanelPosition::Top => {
+                let [panel_area, table_area] = Layout::vertical([panel, table]).areas(area);
+                (table_area, panel_area)
+            }
         }
     }
 
-    fn main_table(&self) -> Table<'static> {
+    /// Each position of the scrollbar is an offset of the table, so the
+    /// thumb is at the bottom exactly when the last item is on the screen.
+    fn scrollbar_state(&self) -> ScrollbarState {
+        ScrollbarState::new(self.items.len().saturating_sub(self.table_page) + 1)
+            .position(self.table_state.offset())
+            .viewport_content_length(self.table_page)
+    }
+
+    /// The table is laid out in the same way as `Table` itself does, so that
+    /// the contents of each cell can be shortened to fit in its column.
+    fn main_table(&self, width: u16) -> Table<'static> {
+        let constraints: Vec<_> = self.visible_columns().map(TableColumn::constraint).collect();
+        let column_widths: Vec<_> = Layout::horizontal(constraints.iter().copied())
+            .flex(Flex::Start)
+            .spacing(1)
+            .split(Rect::new(0, 0, width, 1))
+            .iter()
+            .map(|rect| usize::from(rect.width))
+            .collect();
+
+        let mut block = Block::bordered()
+            .title(format!(" SteelSafe v{} ", env!("CARGO_PKG_VERSION")))
+            .title_bottom(hint("[C]opy secret"))
+            .title_bottom(hint("[P]rimary selection"))
+            .title_bottom(hint("[F]ind"))
+            .title_bottom(hint("[1] First"))
+            .title_bottom(hint("[0] Last"))
+            .title_bottom(hint("['] Jump to label"))
+            .title_bottom(hint("[N]ew item"))
+            .title_bottom(hint("<Shift+N> Duplicate"))
+            .title_bottom(hint("[M]emo"))
+            .title_bottom(hint("[B]ackup codes"))
+            .title_bottom(hint("[I]nfo"))
+            .title_bottom(hint("[.] Details"))
+            .title_bottom(hint("[A]ccess log"))
+            .title_bottom(hint("Q[R] code"))
+            .title_bottom(hint("[T]ags"))
+            .title_bottom(hint("[W]rite to file"))
+            .title_bottom(hint("<Space> Select"))
+            .title_bottom(hint("[V]iew columns"))
+            .title_bottom(hint("Fi[L]ters"))
+            .title_bottom(hint("[H]ide names"))
+            .title_bottom(hint("[U]pgrade crypto"))
+            .title_bottom(hint("[Q]uit"))
+            .border_type(BorderType::Rounded)
+            .border_style(if self.main_table_has_focus() {
+                self.theme.border().add_modifier(Modifier::BOLD)
+            } else {
+                self.theme.border()
+            });
+
+        if self.privacy_mode {
+            block = block.title_top(hint("Privacy mode"));
+        }
+        if !self.marked.is_empty() {
+            let title = tr("{} selected").replacen("{}", &self.marked.len().to_string(), 1);
+            block = block
+                .title_top(format!(" {title} "))
+                .title_top(hint("[W]rite bundle"))
+                .title_top(hint("<Esc> Clear"));
+        }
+        if let Some(filter) = self.smart_filter {
+            block = block.title_top(Line::from(hint(filter.title())).centered());
+        }
+        if self.interrupt_pending() {
+            block = block.title_top(Line::from(hint("<^C> Quit")).right_aligned());
+        } else if let Some(type_ahead) = self.type_ahead.as_ref().filter(|type_ahead| type_ahead.is_pending()) {
+            let title = format!(" {}: {} ", tr("Jump to"), type_ahead.text);
+            block = block.title_top(Line::from(title).right_aligned());
+        }
+
         Table::new(
             self.items.iter().map(|item| {
-                Row::new([
-                    item.label.clone(),
-                    item.account.clone().unwrap_or_default(),
-                    item.last_modified_at.format("%F %T").to_string(),
-                ])
+                let row = Row::new(self.visible_columns().zip(&column_widths).map(|(column, &width)| {
+                    ellipsize_middle(&self.cell_text(item, column.kind), width)
+                }));
+
+                if self.marked.contains(&item.uid) {
+                    row.style(self.theme.highlight())
+                } else {
+                    row
+                }
             }),
-            [Constraint::Percentage(40), Constraint::Percentage(40), Constraint::Min(24)]
+            constraints
         ).header(
-            Row::new(["Title", "Username or account", "Modified at (UTC)"])
+            Row::new(self.visible_columns().map(|column| match column.kind {
+                ColumnKind::Modified => format!("{} ({})", tr(column.kind.title()), time_zone_name(&self.timestamps)),
+                kind => String::from(tr(kind.title())),
+            }))
                 .style(self.theme.default().add_modifier(Modifier::BOLD))
         ).highlight_style(
             Modifier::REVERSED
         ).block(
-            Block::bordered()
-                .title(format!(" SteelSafe v{} ", env!("CARGO_PKG_VERSION")))
-                .title_bottom(" [C]opy secret ")
-                .title_bottom(" [F]ind ")
-                .title_bottom(" [1] First ")
-                .title_bottom(" [0] Last ")
-                .title_bottom(" [N]ew item ")
-                .title_bottom(" [Q]uit ")
-                .border_type(BorderType::Rounded)
-                .border_style(if self.main_table_has_focus() {
-                    self.theme.border().add_modifier(Modifier::BOLD)
-                } else {
-                    self.theme.border()
-                })
+            block
         ).style(
             self.theme.default()
         )
     }
 
-    fn error_modal(&self, error: &Error) -> Paragraph<'static> {
+    /// A single line above the table, e.g. "Items: 2 expired  [E] Show  [X] Dismiss".
+    fn expiry_banner(&self, report: &ExpiryReport) -> Paragraph<'static> {
+        let line = Line::from(vec![
+            Span::styled(format!(" {}: {} ", tr("Items"), report.summary()), self.theme.error()),
+            Span::styled(hint("[E] Show"), self.theme.default()),
+            Span::styled(hint("[X] Dismiss"), self.theme.default()),
+        ]);
+
+        Paragraph::new(line).style(Style::new().add_modifier(Modifier::BOLD))
+    }
+
+    fn error_modal(&self, lines: Vec<String>) -> Paragraph<'static> {
         let block = Block::bordered()
-            .title(" Error ")
-            .title_bottom(" <Esc> Close ")
+            .title(hint("Error"))
+            .title_bottom(hint("<Esc> Close"))
             .border_type(BorderType::Rounded)
             .border_style(self.theme.error().add_modifier(Modifier::BOLD));
 
-        Paragraph::new(format!("\n{error}\n"))
+        // pad with an empty line above and below the message
+        let lines: Vec<_> = std::iter::once(Line::default())
+            .chain(lines.into_iter().map(Line::from))
+            .chain(std::iter::once(Line::default()))
+            .collect();
+
+        Paragraph::new(lines)
             .centered()
             .block(block)
             .style(self.theme.error())
     }
 
-    fn new_item_background(&self, state: &NewItemState) -> Block<'static> {
-        Block::bordered()
-            .title(" New secret item ")
-            .title_top(Line::from(" <^G> Generate password ").right_aligned())
-            .title_bottom(" <Enter> Save ")
-            .title_bottom(" <Esc> Cancel ")
-            .title_bottom(format!(
-                " <^H> {} secret ",
-                if state.show_secret { "Hide" } else { "Show" }
-            ))
-            .title_bottom(format!(
-                " <^E> {} encr passwd ",
-                if state.show_enc_pass { "Hide" } else { "Show" }
-            ))
+    /// Each finding as a bulleted, wrapped paragraph, with an empty line between them.
+    fn findings_lines(&self) -> Vec<Line<'static>> {
+        let mut lines = Vec::new();
+
+        for finding in &self.findings {
+            lines.push(Line::default());
+
+            for (index, line) in text::wrap(&finding.message(), ERROR_WIDTH - 4).into_iter().enumerate() {
+                let bullet = if index == 0 { " • " } else { "   " };
+                lines.push(Line::from(format!("{bullet}{line}")));
+            }
+        }
+
+        lines.push(Line::default());
+        lines
+    }
+
+    fn findings_modal(&self, lines: Vec<Line<'static>>) -> Paragraph<'static> {
+        let block = Block::bordered()
+            .title(hint("Warnings"))
+            .title_bottom(hint("<Esc> Dismiss"))
             .border_type(BorderType::Rounded)
-            .style(self.theme.border_highlight())
-            .border_style(self.theme.border_highlight().add_modifier(Modifier::BOLD))
+            .border_style(self.theme.error().add_modifier(Modifier::BOLD));
+
+        Paragraph::new(lines)
+            .block(block)
+            .style(self.theme.default())
     }
 
-    /// Event polling and error handling.
-    pub fn handle_events(&mut self) {
-        if let Err(error) = self.handle_events_impl() {
-            self.popup_error = Some(error);
-        }
+    fn exported_modal(&self, lines: Vec<String>) -> Paragraph<'static> {
+        let title = if self.exported_count == 1 { "Item exported" } else { "Items exported" };
+        let block = Block::bordered()
+            .title(hint(title))
+            .title_bottom(hint("<Esc> Dismiss"))
+            .border_type(BorderType::Rounded)
+            .border_style(self.theme.border_highlight().add_modifier(Modifier::BOLD));
+
+        Paragraph::new(lines.into_iter().map(Line::from).collect::<Vec<_>>())
+            .centered()
+            .block(block)
+            .style(self.theme.default())
     }
 
-    /// The bulk of the actual event handling logic.
-    fn handle_events_impl(&mut self) -> Result<()> {
-        if !event::poll(Duration::from_millis(50))? {
-            return Ok(());
+    /// Fills the whole table area, so that nothing of the vault shows through.
+    fn lock_screen(&self) -> Paragraph<'static> {
+        let prompt = match self.focus_lock {
+            FocusLock::Password if !self.items.is_empty() => "Press any key, then enter the password",
+            _ => "Press any key to unlock",
+        };
+        let block = Block::bordered()
+            .title(format!(" SteelSafe v{} ", env!("CARGO_PKG_VERSION")))
+            .title_top(hint("Locked"))
+            .border_type(BorderType::Rounded)
+            .border_style(self.theme.border());
+
+        Paragraph::new(vec![Line::default(), Line::from(tr(prompt))])
+            .centered()
+            .block(block)
+            .style(self.theme.default())
+    }
+
+    fn spinner_modal(&self, message: &'static str, elapsed: Duration) -> Paragraph<'static> {
+        let step = elapsed.as_millis() / SPINNER_STEP.as_millis();
+        let symbol = SPINNER[(step % SPINNER.len() as u128) as usize];
+        let block = Block::bordered()
+            .border_type(BorderType::Rounded)
+            .border_style(self.theme.border().add_modifier(Modifier::BOLD));
+
+        Paragraph::new(format!("{symbol} {}", tr(message)))
+            .centered()
+            .block(block)
+            .style(self.theme.default())
+    }
+
+    fn stats_modal(&self, lines: Vec<Line<'static>>) -> Paragraph<'static> {
+        let mut block = Block::bordered()
+            .title(hint("Vault statistics"))
+            .title_bottom(hint("<Esc> Close"))
+            .title_bottom(hint("[K] Compact"))
+            .border_type(BorderType::Rounded)
+            .border_style(self.theme.border_highlight().add_modifier(Modifier::BOLD));
+
+        if self.secret_audit.is_none() {
+            block = block.title_bottom(hint("[U] Check secrets"));
         }
-        let event = event::read()?;
 
-        let event = match self.handle_error_input(event)? {
-            ControlFlow::Break(()) => return Ok(()),
-            ControlFlow::Continue(event) => event,
-        };
-        let event = match self.handle_passwd_entry_input(event)? {
-            ControlFlow::Break(()) => return Ok(()),
-            ControlFlow::Continue(event) => event,
-        };
-        let event = match self.handle_find_input(event)? {
-            ControlFlow::Break(()) => return Ok(()),
-            ControlFlow::Continue(event) => event,
+        Paragraph::new(lines)
+            .block(block)
+            .style(self.theme.border_highlight())
+    }
+
+    fn stats_lines(&self, stats: &VaultStats) -> Vec<Line<'static>> {
+        /// The modal can't be scrolled, so only the most common tags are shown.
+        const MAX_TAGS_SHOWN: usize = 8;
+
+        let format_date = |date: Option<DateTime<Utc>>| {
+            date.map_or_else(
+                || String::from("-"),
+                |date| self.format_time(date),
+            )
         };
-        let event = match self.handle_new_input(event)? {
-            ControlFlow::Break(()) => return Ok(()),
-            ControlFlow::Continue(event) => event,
+        let zone = time_zone_name(&self.timestamps);
+        let format_size = |size: Option<u64>| size.map_or_else(|| String::from("-"), format_file_size);
+        let compaction = self.compaction.map(|compaction| {
+            (String::from(tr("Size before compaction")), format_size(compaction.size_before))
+        });
+        // the secrets can only be checked once decrypted, which needs the password
+        let format_audit = |count: fn(&SecretAudit) -> usize| {
+            self.secret_audit.as_ref().map_or_else(|| String::from("-"), |audit| count(audit).to_string())
         };
+        let skipped = self.secret_audit.filter(|audit| audit.skipped > 0).map(|audit| {
+            (String::from(tr("Secrets not decrypted")), audit.skipped.to_string())
+        });
+        let rows = [
+            (String::from(tr("Number of items")), stats.item_count.to_string()),
+            (String::from(tr("Items without account")), stats.items_without_account.to_string()),
+            (String::from(tr("Expired items")), stats.expired_count.to_string()),
+            (String::from(tr("Rotation overdue")), stats.rotation_overdue_count.to_string()),
+            (String::from(tr("Weak secrets")), format_audit(|audit| audit.weak)),
+            (String::from(tr("Reused secrets")), format_audit(|audit| audit.reused)),
+        ];
+        let storage_rows = [
+            (format!("{} ({zone})", tr("Oldest modification")), format_date(stats.oldest_modified_at)),
+            (format!("{} ({zone})", tr("Newest modification")), format_date(stats.newest_modified_at)),
+            (String::from(tr("Database file size")), format_size(stats.file_size)),
+            (String::from(tr("Schema version")), stats.schema_version.to_string()),
+        ];
+        let mut lines: Vec<_> = rows
+            .into_iter()
+            .chain(skipped)
+            .chain(storage_rows)
+            .chain(compaction)
+            .map(|(name, value)| Line::from(format!(" {name:<26}{value:>19} ")))
+            .collect();
 
-        self.handle_main_table_event(event)
-    }
+        if !stats.tags.is_empty() {
+            let mut tags: Vec<&TagCount> = stats.tags.iter().collect();
 
-    /// Handles events when the main table has focus.
-    fn handle_main_table_event(&mut self, event: Event) -> Result<()> {
-        if let Event::Mouse(mouse) = event {
-            match mouse.kind {
-                MouseEventKind::ScrollDown => {
-                    self.table_state.select_next();
-                }
-                MouseEventKind::ScrollUp => {
-                    self.table_state.select_previous();
-                }
-                _ => {}
+            // most used first, then alphabetically
+            tags.sort_by(|lhs, rhs| rhs.item_count.cmp(&lhs.item_count).then_with(|| lhs.tag.cmp(&rhs.tag)));
+
+            lines.push(Line::default());
+            lines.push(Line::from(format!(" {:<45} ", tr("Items per tag"))));
+            lines.extend(tags.iter().take(MAX_TAGS_SHOWN).map(|tag_count| {
+                let tag = ellipsize_middle(&tag_count.tag, 34);
+                Line::from(format!("   {}{:>9} ", text::pad_end(&tag, 34), tag_count.item_count))
+            }));
+
+            if tags.len() > MAX_TAGS_SHOWN {
+                lines.push(Line::from(format!(" {:>45} ", "...")));
             }
-            return Ok(());
         }
 
-        let Event::Key(key) = event else {
-            return Ok(());
-        };
+        lines
+    }
 
-        if key.kind != KeyEventKind::Press {
-            return Ok(());
+    fn confirm_discard_modal(&self, action: DiscardAction) -> Paragraph<'static> {
+        let block = Block::bordered()
+            .title(hint("Discard changes?"))
+            .title_bottom(hint("[Y]es"))
+            .title_bottom(hint("[N]o"))
+            .border_type(BorderType::Rounded)
+            .border_style(self.theme.error().add_modifier(Modifier::BOLD));
+
+        let message = match action {
+            DiscardAction::CloseDialog => tr("The new item has unsaved input."),
+            DiscardAction::Quit => tr("The new item has unsaved input. Quit anyway?"),
         };
 
-        match key.code {
-            KeyCode::Up | KeyCode::Char('k' | 'K') => {
-                self.table_state.select_previous();
-            }
-            KeyCode::Down | KeyCode::Tab | KeyCode::Char('j' | 'J') => {
-                self.table_state.select_next();
-            }
-            KeyCode::Char('1') => {
-                self.table_state.select_first();
+        Paragraph::new(format!("\n{message}\n"))
+            .centered()
+            .block(block)
+            .style(self.theme.error())
+    }
+
+    fn access_history_lines(&self, history: &AccessHistory) -> Vec<Line<'static>> {
+        /// The modal can't be scrolled, so only the most recent accesses are shown.
+        const MAX_SHOWN: usize = 10;
+
+        if !self.access_log && history.entries.is_empty() {
+            return vec![
+                Line::from(hint("Access logging is disabled.")),
+                Line::from(hint("Set `access_log` in the config to enable it.")),
+            ];
+        }
+
+        let last_accessed = history.entries.first().map_or_else(
+            || String::from(tr("never")),
+            |entry| self.format_time(entry.accessed_at),
+        );
+        let last_accessed_title = format!("{} ({})", tr("Last accessed"), time_zone_name(&self.timestamps));
+        let mut lines = vec![
+            Line::from(format!(" {:<26}{:>19} ", last_accessed_title, last_accessed)),
+            Line::from(format!(" {:<26}{:>19} ", tr("Number of accesses"), history.entries.len())),
+        ];
+
+        if !history.entries.is_empty() {
+            lines.push(Line::default());
+            lines.extend(history.entries.iter().take(MAX_SHOWN).map(|entry| {
+                Line::from(format!(" {:>45} ", self.format_time(entry.accessed_at)))
+            }));
+        }
+
+        if history.entries.len() > MAX_SHOWN {
+            lines.push(Line::from(format!(" {:>45} ", "...")));
+        }
+
+        lines
+    }
+
+    fn access_history_modal(&self, history: &AccessHistory, lines: Vec<Line<'static>>) -> Paragraph<'static> {
+        let block = Block::bordered()
+            .title(format!(" {} ", tr("Access log: {}").replacen("{}", &history.label, 1)))
+            .title_bottom(hint("<Esc> Close"))
+            .border_type(BorderType::Rounded)
+            .border_style(self.theme.border_highlight().add_modifier(Modifier::BOLD));
+
+        Paragraph::new(lines)
+            .block(block)
+            .style(self.theme.border_highlight())
+    }
+
+    /// Every field of the item, wrapped instead of truncated.
+    fn details_lines(&self, item: &DisplayItem) -> Vec<Line<'static>> {
+        let tags = self.item_tags.get(&item.uid).map_or_else(String::new, |tags| tags.join(", "));
+        let fields = [
+            (String::from(tr("Title")), item.label.clone()),
+            (String::from(tr("Username or account")), item.account.clone().unwrap_or_default()),
+            (
+                format!("{} ({})", tr("Modified at"), time_zone_name(&self.timestamps)),
+                self.format_time(item.last_modified_at),
+            ),
+            (String::from(tr("Tags")), tags),
+            (
+                String::from(tr("Always ask for password")),
+                String::from(tr(if item.reprompt { "yes" } else { "no" })),
+            ),
+        ];
+        let codes = self.recovery_codes.get(&item.uid).map(|codes| {
+            let left = tr("{} of {} left")
+                .replacen("{}", &codes.remaining.to_string(), 1)
+                .replacen("{}", &codes.total.to_string(), 1);
+            (String::from(tr("Recovery codes")), left)
+        });
+        let url = self.urls.get(&item.uid).map(|url| (String::from("URL"), url.clone()));
+        let name_style = self.theme.border_highlight().add_modifier(Modifier::BOLD);
+        let mut lines = Vec::new();
+
+        for (name, value) in fields.into_iter().chain(url).chain(codes) {
+            if !lines.is_empty() {
+                lines.push(Line::default());
             }
-            KeyCode::Char('0') => {
-                self.table_state.select_last();
+            lines.push(Line::from(format!(" {name} ")).style(name_style));
+            lines.extend(
+                text::wrap(&value, DETAILS_WIDTH - 2)
+                    .into_iter()
+                    .map(|line| Line::from(format!(" {line} ")))
+            );
+        }
+
+        if let Some(note) = self.details_note.as_ref() {
+            lines.push(Line::default());
+            lines.push(Line::from(format!(" {} ", tr("Note"))).style(name_style));
+
+            for mut line in markdown::render(note, DETAILS_WIDTH - 2, self.theme.highlight()) {
+                line.spans.insert(0, Span::raw(" "));
+                lines.push(line);
             }
-            KeyCode::Char('c' | 'C') | KeyCode::Enter => {
-                self.passwd_entry = Some(PasswordEntryState::with_theme(self.theme.clone()));
+        }
+
+        lines
+    }
+
+    fn details_modal(&self, item: &DisplayItem, lines: Vec<Line<'static>>) -> Paragraph<'static> {
+        let mut block = Block::bordered()
+            .title(hint("Details"))
+            .title_bottom(hint("<Esc> Close"))
+            .border_type(BorderType::Rounded)
+            .border_style(self.theme.border_highlight().add_modifier(Modifier::BOLD));
+
+        block = block
+            .title_bottom(hint("[R]ename"))
+            .title_bottom(hint("[P]assword always"));
+
+        if self.is_note(item.uid) && self.details_note.is_none() {
+            block = block.title_bottom(hint("[O]pen note"));
+        }
+
+        if self.recovery_codes.contains_key(&item.uid) {
+            block = block.title_bottom(hint("[U]se next code"));
+        }
+
+        Paragraph::new(lines)
+            .block(block)
+            .style(self.theme.border_highlight())
+    }
+
+    fn column_menu_modal(&self, selected: usize) -> Paragraph<'static> {
+        let block = Block::bordered()
+            .title(hint("Columns"))
+            .title_bottom(hint("<Space> Show/hide"))
+            .title_bottom(hint("<Esc> Close"))
+            .border_type(BorderType::Rounded)
+            .border_style(self.theme.border_highlight().add_modifier(Modifier::BOLD));
+
+        let lines: Vec<_> = self.columns.iter().enumerate().map(|(idx, (column, visible))| {
+            let check = if *visible { 'x' } else { ' ' };
+            let line = Line::from(format!(" [{check}] {:<26} ", tr(column.kind.title())));
+
+            if idx == selected {
+                line.style(self.theme.highlight())
+            } else {
+                line
             }
-            KeyCode::Char('f' | 'F' | '/') => {
-                // if we are already in find mode, do NOT reset
-                // the search term, just give back focus.
-                if let Some(find_state) = self.find.as_mut() {
-                    find_state.set_focus(true);
-                } else {
-                    self.find = Some(FindItemState::with_theme(self.theme.clone()));
-                }
+        }).collect();
+
+        Paragraph::new(lines)
+            .block(block)
+            .style(self.theme.border_highlight())
+    }
+
+    /// The matching commands, scrolled so that the selected one is visible.
+    fn palette_list(&self, palette: &PaletteState, commands: &[Command]) -> Paragraph<'static> {
+        let block = Block::bordered()
+            .title_bottom(hint("<Enter> Run"))
+            .title_bottom(hint("<Esc> Close"))
+            .border_type(BorderType::Rounded)
+            .border_style(self.theme.border_highlight().add_modifier(Modifier::BOLD));
+
+        if commands.is_empty() {
+            return Paragraph::new(hint("No matching commands")).block(block).style(self.theme.border_highlight());
+        }
+
+        let first = palette.selected.saturating_sub(PALETTE_HEIGHT - 1);
+        let lines: Vec<_> = commands.iter().enumerate().skip(first).take(PALETTE_HEIGHT).map(|(idx, command)| {
+            // a blank between brackets would look like a missing key
+            let key = if command.key == ' ' { String::from("[␣]") } else { format!("[{}]", command.key) };
+            let line = Line::from(format!(" {:<40} {key:>3} ", tr(command.title)));
+
+            if idx == palette.selected {
+                line.style(self.theme.highlight())
+            } else {
+                line
             }
-            KeyCode::Char('n' | 'N') => {
-                self.new_item = Some(NewItemState::with_theme(self.theme.clone()));
+        }).collect();
+
+        Paragraph::new(lines)
+            .block(block)
+            .style(self.theme.border_highlight())
+    }
+
+    fn filter_menu_modal(&self, selected: usize) -> Paragraph<'static> {
+        let block = Block::bordered()
+            .title(hint("Filters"))
+            .title_bottom(hint("<Enter> Apply"))
+            .title_bottom(hint("<Esc> Close"))
+            .border_type(BorderType::Rounded)
+            .border_style(self.theme.border_highlight().add_modifier(Modifier::BOLD));
+
+        let titles = std::iter::once("All items").chain(SmartFilter::ALL.map(SmartFilter::title));
+        let active = self.smart_filter.map_or(0, |filter| {
+            1 + SmartFilter::ALL.iter().position(|&f| f == filter).unwrap_or_default()
+        });
+
+        let lines: Vec<_> = titles.enumerate().map(|(idx, title)| {
+            let check = if idx == active { '*' } else { ' ' };
+            let line = Line::from(format!(" ({check}) {:<26} ", tr(title)));
+
+            if idx == selected {
+                line.style(self.theme.highlight())
+            } else {
+                line
             }
-            KeyCode::Char('q' | 'Q') => {
-                self.is_running = false;
+        }).collect();
+
+        Paragraph::new(lines)
+            .block(block)
+            .style(self.theme.border_highlight())
+    }
+
+    fn generator_menu_modal(&self, selected: usize, policy: &Policy) -> Paragraph<'static> {
+        let block = Block::bordered()
+            .title(hint("Generate"))
+            .title_bottom(hint("<Enter> Insert"))
+            .title_bottom(hint("<Esc> Close"))
+            .border_type(BorderType::Rounded)
+            .border_style(self.theme.border_highlight().add_modifier(Modifier::BOLD));
+
+        let lines: Vec<_> = self.generators.iter().enumerate().map(|(idx, generator)| {
+            let bits = format!("{:.0}", generator.entropy_bits(policy));
+            let entropy = tr("{} bits").replacen("{}", &bits, 1);
+            let line = Line::from(format!(" {:<24} {:>12} ", generator.description(policy), entropy));
+
+            if idx == selected {
+                line.style(self.theme.highlight())
+            } else {
+                line
             }
-            _ => {}
-        }
+        }).collect();
 
-        Ok(())
+        Paragraph::new(lines)
+            .block(block)
+            .style(self.theme.border_highlight())
     }
 
-    /// Handles events when the error modal is open.
-    fn handle_error_input(&mut self, event: Event) -> Result<ControlFlow<(), Event>> {
-        if self.popup_error.is_none() {
-            return Ok(ControlFlow::Continue(event));
+    fn tag_manager_lines(&self, manager: &TagManagerState) -> Vec<Line<'static>> {
+        if manager.tags.is_empty() {
+            return vec![
+                Line::from(hint("No tags yet.")),
+                Line::from(hint("Use `steelsafe tag` to tag items.")),
+            ];
         }
 
-        if let Event::Key(evt) = event {
-            if evt.code == KeyCode::Esc {
-                self.popup_error = None;
+        manager.tags.iter().enumerate().map(|(idx, tag_count)| {
+            let tag = text::pad_end(&ellipsize_middle(&tag_count.tag, 36), 36);
+            let line = Line::from(format!(" {tag}{:>9} ", tag_count.item_count));
+
+            if idx == manager.selected {
+                line.style(self.theme.highlight())
+            } else {
+                line
             }
+        }).collect()
+    }
+
+    fn tag_manager_modal(&self, manager: &TagManagerState, mut lines: Vec<Line<'static>>) -> Paragraph<'static> {
+        let mut block = Block::bordered()
+            .title(hint("Tags"))
+            .title(Line::from(hint("Items")).right_aligned())
+            .border_type(BorderType::Rounded)
+            .border_style(self.theme.border_highlight().add_modifier(Modifier::BOLD));
+
+        if let (true, Some(selected)) = (manager.confirm_delete, manager.selected_tag()) {
+            block = block.title_bottom(
+                Line::from(format!(
+                    " {} ",
+                    tr("Remove {} from all items? [Y]es / [N]o").replacen("{}", &format!("{:?}", selected.tag), 1),
+                ))
+                    .style(self.theme.error())
+            );
+        } else if manager.rename.is_some() {
+            block = block
+                .title_bottom(hint("<Enter> Rename"))
+                .title_bottom(hint("<Esc> Cancel"));
+        } else {
+            block = block
+                .title_bottom(hint("[R]ename or merge"))
+                .title_bottom(hint("[D]elete"))
+                .title_bottom(hint("<Esc> Close"));
         }
 
-        Ok(ControlFlow::Break(()))
+        // leave room for the text field of the new name
+        if manager.rename.is_some() {
+            lines.extend([Line::default(), Line::default(), Line::default()]);
+        }
+
+        Paragraph::new(lines)
+            .block(block)
+            .style(self.theme.border_highlight())
     }
 
-    /// Handles events for the password entry panel before decrypting a secret.
-    fn handle_passwd_entry_input(&mut self, event: Event) -> Result<ControlFlow<(), Event>> {
-        let Some(passwd_entry) = self.passwd_entry.as_mut() else {
-            return Ok(ControlFlow::Continue(event));
+    fn qr_code_modal<'a>(&self, qr_code: &'a QrCodeView) -> Paragraph<'a> {
+        let block = Block::bordered()
+            .title(format!(" {} ", tr("QR code: {}").replacen("{}", &qr_code.label, 1)))
+            .title_bottom(hint("<Esc> Close"))
+            .border_type(BorderType::Rounded)
+            .border_style(self.theme.border_highlight().add_modifier(Modifier::BOLD));
+        let lines: Vec<_> = qr_code.lines.iter().map(|line| Line::from(line.as_str())).collect();
+
+        // Scanners need dark modules on a light background,
+        // regardless of the color theme.
+        Paragraph::new(lines)
+            .block(block)
+            .style(Style::default().fg(Color::Black).bg(Color::White))
+    }
+
+    fn new_item_background(&self, state: &NewItemState) -> Block<'static> {
+        // Saving is only possible once every field is valid.
+        let save_hint = if state.form.is_valid() {
+            Line::from(hint("<Enter> Save"))
+        } else {
+            Line::from(hint("<Enter> Save")).style(self.theme.border_highlight().add_modifier(Modifier::DIM))
         };
 
-        match event {
-            Event::Key(evt) => match evt.code {
-                KeyCode::Esc => {
-                    self.passwd_entry = None;
-                }
-                KeyCode::Enter => {
-                    let password = Zeroizing::new(passwd_entry.enc_pass.lines().join("\n"));
-                    self.passwd_entry = None;
-                    self.copy_secret_to_clipboard(&password)?;
-                }
-                KeyCode::Char('h' | 'H') if evt.modifiers.contains(KeyModifiers::CONTROL) => {
-                    passwd_entry.toggle_show_enc_pass();
-                }
-                _ => {
-                    passwd_entry.enc_pass.input(event);
-                }
-            },
-            _ => {
-                passwd_entry.enc_pass.input(event);
-            }
-        }
+        Block::bordered()
+            .title(hint(state.template.title()))
+            .title_top(Line::from(hint("<^O> Read QR image")).right_aligned())
+            .title_top(Line::from(hint("<^G> Generate")).right_aligned())
+            .title_bottom(save_hint)
+            .title_bottom(hint("<Esc> Cancel"))
+            .title_bottom(hint(
+                if state.show_secret { "<^H> Hide secret" } else { "<^H> Show secret" }
+            ))
+            .title_bottom(hint(
+                if state.show_enc_pass { "<^E> Hide encr passwd" } else { "<^E> Show encr passwd" }
+            ))
+            .border_type(BorderType::Rounded)
+            .style(self.theme.border_highlight())
+            .border_style(self.theme.border_highlight().add_modifier(Modifier::BOLD))
+    }
 
-        Ok(ControlFlow::Break(()))
+    /// Event polling and error handling.
+    pub fn handle_events(&mut self, events: &mut dyn EventSource) {
+        if let Err(error) = self.handle_events_impl(events) {
+            self.popup_error = Some(error);
+            self.needs_redraw = true;
+        }
     }
 
-    /// Handles events for the Find panel.
-    fn handle_find_input(&mut self, event: Event) -> Result<ControlFlow<(), Event>> {
-        let Some(find_state) = self.find.as_mut() else {
-            return Ok(ControlFlow::Continue(event));
-        };
+    /// Checks on the background tasks, then waits for the next event.
+    fn handle_events_impl(&mut self, events: &mut dyn EventSource) -> Result<()> {
+        let running_tasks = self.running_tasks();
 
-        match event {
-            Event::Key(evt) => match evt.code {
-                KeyCode::Esc => {
-                    self.find = None;
-                    self.sync_data(true)?;
-                    Ok(ControlFlow::Break(()))
-                }
-                KeyCode::Enter if find_state.has_focus => {
-                    find_state.set_focus(false);
-                    Ok(ControlFlow::Break(()))
-                }
-                _ if find_state.has_focus => {
-                    find_state.search_term.input(event);
-                    self.sync_data(true)?;
-                    Ok(ControlFlow::Break(()))
-                }
-                _ => Ok(ControlFlow::Continue(event))
+        self.poll_crypto_upgrade()?;
+        self.poll_secret_audit()?;
+        self.poll_sync()?;
+        self.poll_decryption()?;
+        self.poll_encryption()?;
+
+        // a finished task closes its spinner, and usually opens something else
+        if self.running_tasks() != running_tasks {
+            self.needs_redraw = true;
+        }
+
+        let now = Instant::now();
+
+        if self.redraw_at.is_some_and(|redraw_at| redraw_at <= now) {
+            self.needs_redraw = true;
+        }
+
+        // wake up in time for the next frame, however long the poll interval is
+        let timeout = self.redraw_at.map_or(Duration::MAX, |redraw_at| redraw_at.saturating_duration_since(now));
+        let timeout = timeout.min(self.poll_timeout(self.running_tasks() > 0));
+
+        match events.next_event(timeout)? {
+            Some(event) => {
+                self.last_input_at = Instant::now();
+                self.needs_redraw |= self.may_change_screen(&event);
+                self.handle_event(event)
             }
-            _ => Ok(ControlFlow::Continue(event))
+            None => Ok(()),
         }
     }
 
-    /// Handles events for the "New item" dialog.
-    fn handle_new_input(&mut self, event: Event) -> Result<ControlFlow<(), Event>> {
-        // if the input text area is not open, ignore the event and give it back right away
-        let Some(new_item) = self.new_item.as_mut() else {
-            return Ok(ControlFlow::Continue(event));
-        };
+    /// The number of tasks running on worker threads.
+    fn running_tasks(&self) -> usize {
+        usize::from(self.decryption.is_some())
+        + usize::from(self.encryption.is_some())
+        + usize::from(self.crypto_upgrade.is_some())
+        + usize::from(self.auditing.is_some())
+        + usize::from(self.syncing.is_some())
+    }
 
+    /// Whether handling `event` can change what's on the screen. Mouse
+    /// movements and clicks, key releases, and focus changes that don't
+    /// lock the screen are ignored, so they don't cause a redraw, which
+    /// is slow over e.g. SSH. Resizing the terminal always does.
+    fn may_change_screen(&self, event: &Event) -> bool {
         match event {
-            Event::Key(evt) => match evt.code {
-                KeyCode::Esc => {
-                    self.new_item = None;
-                }
-                KeyCode::Down | KeyCode::Tab => {
-                    new_item.cycle_forward();
-                }
-                KeyCode::Up => {
-                    new_item.cycle_back();
-                }
-                KeyCode::Enter => {
-                    // close dialog even if an error occurred
-                    let new_item = self.new_item.take().expect("just checked that new_item is Some");
-                    let added = new_item.add_item(&self.db)?;
-
-                    self.sync_data(false)?;
-
-                    if let Some((idx, _item)) = self.items
-                        .iter()
-                        .enumerate()
-                        .rev() // the new item will _usually_ be the last one
-                        .find(|(_idx, item)| item.uid == added.uid)
-                    {
-                        self.table_state.select(Some(idx));
-                    }
-                }
-                KeyCode::Char('h' | 'H') if evt.modifiers.contains(KeyModifiers::CONTROL) => {
-                    new_item.toggle_show_secret();
-                }
-                KeyCode::Char('e' | 'E') if evt.modifiers.contains(KeyModifiers::CONTROL) => {
-                    new_item.toggle_show_enc_pass();
-                }
-                KeyCode::Char('g' | 'G') if evt.modifiers.contains(KeyModifiers::CONTROL) => {
-                    new_item.generate_random_password();
-                }
-                _ => {
-                    new_item.focused_text_area().input(event);
-                }
+            Event::Key(key) => key.kind != KeyEventKind::Release,
+            Event::Mouse(mouse) => match mouse.kind {
+                MouseEventKind::ScrollDown | MouseEventKind::ScrollUp => true,
+                MouseEventKind::Down(MouseButton::Left) => self.is_on_scrollbar(mouse.column, mouse.row),
+                MouseEventKind::Drag(MouseButton::Left) => self.dragging_scrollbar,
+                _ => false,
             },
-            _ => {
-                new_item.focused_text_area().input(event);
-            }
+            Event::FocusLost => self.focus_lock != FocusLock::Off,
+            Event::FocusGained => false,
+            Event::Paste(_) | Event::Resize(..) => true,
+        }
+    }
+
+    /// When the screen changes by itself, if it does, as of `now`: the next
+    /// frame of a spinner, the next second of a countdown, the end of a key
+    /// press being pending (e.g. the second `Ctrl+C`), or the next refresh of
+    /// relative timestamps. Animations are timed by the clock, not by the
+    /// number of redraws, so they run at the same speed however often the
+    /// screen is drawn.
+    fn next_change_at(&self, now: Instant) -> Option<Instant> {
+        let spinners = [
+            self.decryption.as_ref().map(|pending| pending.task.elapsed()),
+            self.encryption.as_ref().map(|pending| pending.task.elapsed()),
+            self.crypto_upgrade.as_ref().map(Task::elapsed),
+            self.auditing.as_ref().map(Task::elapsed),
+            self.syncing.as_ref().map(Task::elapsed),
+        ];
+        let spinner = spinners
+            .into_iter()
+            .flatten()
+            .map(|elapsed| now + until_next_multiple(elapsed, SPINNER_STEP))
+            .min();
+        let countdown = self.failed_attempts.remaining_cooldown(now).map(|remaining| {
+            // the remaining seconds are rounded up
+            let whole_secs = Duration::from_secs(remaining.as_secs());
+            now + if remaining > whole_secs { remaining - whole_secs } else { Duration::from_secs(1) }
+        });
+        let interrupt = self.last_interrupt.map(|time| time + INTERRUPT_WINDOW);
+        let type_ahead = self.type_ahead.as_ref().map(|type_ahead| type_ahead.last_key_at + TYPE_AHEAD_TIMEOUT);
+        let timestamps = self.timestamps.relative.then(|| now + RELATIVE_TIMESTAMP_REFRESH);
+
+        [spinner, countdown, interrupt, type_ahead, timestamps]
+            .into_iter()
+            .flatten()
+            .filter(|&time| time > now)
+            .min()
+    }
+
+    /// Waits for input only briefly while the user is interacting, or a
+    /// task is running, and longer once idle, to save power.
+    fn poll_timeout(&self, busy: bool) -> Duration {
+        if busy || self.last_input_at.elapsed() < self.poll.idle_after() {
+            self.poll.active_interval()
+        } else {
+            self.poll.idle_interval()
         }
+    }
+
+    /// The bulk of the actual event handling logic. Each modal and panel
+    /// gets a chance to consume the event, from the topmost one down.
+    fn handle_event(&mut self, event: Event) -> Result<()> {
+        let event = match self.handle_focus_change(event) {
+            ControlFlow::Break(()) => return Ok(()),
+            ControlFlow::Continue(event) => event,
+        };
+        let event = match self.handle_interrupt(event) {
+            ControlFlow::Break(()) => return Ok(()),
+            ControlFlow::Continue(event) => event,
+        };
+        #[cfg(unix)]
+        let event = match self.handle_suspend_shortcut(event) {
+            ControlFlow::Break(()) => return Ok(()),
+            ControlFlow::Continue(event) => event,
+        };
+        let event = match self.handle_error_input(event)? {
+            ControlFlow::Break(()) => return Ok(()),
+            ControlFlow::Continue(event) => event,
+        };
+        let event = match self.handle_lock_input(event)? {
+            ControlFlow::Break(()) => return Ok(()),
+            ControlFlow::Continue(event) => event,
+        };
+        let event = match self.handle_findings_input(event) {
+            ControlFlow::Break(()) => return Ok(()),
+            ControlFlow::Continue(event) => event,
+        };
+        let event = match self.handle_exported_input(event) {
+            ControlFlow::Break(()) => return Ok(()),
+            ControlFlow::Continue(event) => event,
+        };
+        let event = match self.handle_progress_input(event)? {
+            ControlFlow::Break(()) => return Ok(()),
+            ControlFlow::Continue(event) => event,
+        };
+        let event = match self.handle_confirm_discard_input(event)? {
+            ControlFlow::Break(()) => return Ok(()),
+            ControlFlow::Continue(event) => event,
+        };
+        let event = match self.handle_quit_shortcut(event)? {
+            ControlFlow::Break(()) => return Ok(()),
+            ControlFlow::Continue(event) => event,
+        };
+        let event = match self.handle_palette_input(event)? {
+            ControlFlow::Break(()) => return Ok(()),
+            ControlFlow::Continue(event) => event,
+        };
+        let event = match self.handle_passwd_entry_input(event)? {
+            ControlFlow::Break(()) => return Ok(()),
+            ControlFlow::Continue(event) => event,
+        };
+        let event = match self.handle_find_input(event)? {
+            ControlFlow::Break(()) => return Ok(()),
+            ControlFlow::Continue(event) => event,
+        };
+        let event = match self.handle_new_input(event)? {
+            ControlFlow::Break(()) => return Ok(()),
+            ControlFlow::Continue(event) => event,
+        };
+        let event = match self.handle_stats_input(event)? {
+            ControlFlow::Break(()) => return Ok(()),
+            ControlFlow::Continue(event) => event,
+        };
+        let event = match self.handle_access_history_input(event)? {
+            ControlFlow::Break(()) => return Ok(()),
+            ControlFlow::Continue(event) => event,
+        };
+        let event = match self.handle_qr_code_input(event)? {
+            ControlFlow::Break(()) => return Ok(()),
+            ControlFlow::Continue(event) => event,
+        };
+        let event = match self.handle_tag_manager_input(event)? {
+            ControlFlow::Break(()) => return Ok(()),
+            ControlFlow::Continue(event) => event,
+        };
+        let event = match self.handle_column_menu_input(event) {
+            ControlFlow::Break(()) => return Ok(()),
+            ControlFlow::Continue(event) => event,
+        };
+        let event = match self.handle_filter_menu_input(event)? {
+            ControlFlow::Break(()) => return Ok(()),
+            ControlFlow::Continue(event) => event,
+        };
+        let event = match self.handle_details_input(event)? {
+            ControlFlow::Break(()) => return Ok(()),
+            ControlFlow::Continue(event) => event,
+        };
+        let event = match self.handle_type_ahead(event) {
+            ControlFlow::Break(()) => return Ok(()),
+            ControlFlow::Continue(event) => event,
+        };
+
+        self.handle_main_table_event(event)
+    }
+
+    /// Pressing `'` in the table starts jumping to items by typing the beginning
+    /// of their label, like in file managers. Every letter is a shortcut of the
+    /// table otherwise, so typing goes on until no key is pressed for
+    /// `TYPE_AHEAD_TIMEOUT`, or until a key other than a character is pressed.
+    fn handle_type_ahead(&mut self, event: Event) -> ControlFlow<(), Event> {
+        let Event::Key(evt) = event else {
+            return ControlFlow::Continue(event);
+        };
+
+        if evt.kind != KeyEventKind::Press || !self.main_table_has_focus() {
+            return ControlFlow::Continue(event);
+        }
+
+        let Some(type_ahead) = self.type_ahead.as_mut().filter(|type_ahead| type_ahead.is_pending()) else {
+            self.type_ahead = None;
+
+            if evt.code == KeyCode::Char('\'') {
+                self.type_ahead = Some(TypeAhead::default());
+                return ControlFlow::Break(());
+            }
+
+            return ControlFlow::Continue(event);
+        };
+
+        match evt.code {
+            KeyCode::Char(ch) if !evt.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) => {
+                type_ahead.push(ch);
+            }
+            KeyCode::Backspace => {
+                type_ahead.pop();
+            }
+            KeyCode::Esc => {
+                self.type_ahead = None;
+                return ControlFlow::Break(());
+            }
+            _ => {
+                self.type_ahead = None;
+                return ControlFlow::Continue(event);
+            }
+        }
+
+        let prefix = type_ahead.text.to_lowercase();
+
+        if let Some(index) = self.items.iter().position(|item| item.label.to_lowercase().starts_with(&prefix)) {
+            self.table_state.select(Some(index));
+        }
+
+        ControlFlow::Break(())
+    }
+
+    /// Whether the cell at `column` and `row` is part of the scrollbar of the table.
+    fn is_on_scrollbar(&self, column: u16, row: u16) -> bool {
+        self.scrollbar_area.is_some_and(|area| area.contains(Position::new(column, row)))
+    }
+
+    /// Selects the item as far down the table as `row` is down the scrollbar,
+    /// and scrolls the table so that the thumb ends up under the mouse.
+    fn scroll_to_scrollbar_row(&mut self, row: u16) {
+        let (Some(area), Some(last)) = (self.scrollbar_area, self.items.len().checked_sub(1)) else {
+            return;
+        };
+        let track_length = usize::from(area.height.saturating_sub(1)).max(1);
+        let track_position = usize::from(row.clamp(area.top(), area.bottom() - 1) - area.top());
+        let index = (track_position * last + track_length / 2) / track_length;
+        let max_offset = self.items.len().saturating_sub(self.table_page);
+
+        self.table_state.select(Some(index));
+        *self.table_state.offset_mut() = index * max_offset / last.max(1);
+    }
+
+    /// Moves the selection by `delta` rows (up if negative), and scrolls the
+    /// table by as many, so that the selected row stays in the same place on
+    /// the screen, like paging in `less`. Stops at the first and last item.
+    fn move_selection_by(&mut self, delta: isize) {
+        let Some(last) = self.items.len().checked_sub(1) else {
+            return;
+        };
+        let selected = self.table_state.selected().map_or(0, |idx| idx.min(last));
+        let max_offset = self.items.len().saturating_sub(self.table_page);
+        let offset = self.table_state.offset().saturating_add_signed(delta).min(max_offset);
+
+        self.table_state.select(Some(selected.saturating_add_signed(delta).min(last)));
+        *self.table_state.offset_mut() = offset;
+    }
+
+    /// Handles events when the main table has focus.
+    fn handle_main_table_event(&mut self, event: Event) -> Result<()> {
+        if let Event::Mouse(mouse) = event {
+            match mouse.kind {
+                MouseEventKind::ScrollDown => {
+                    self.table_state.select_next();
+                }
+                MouseEventKind::ScrollUp => {
+                    self.table_state.select_previous();
+                }
+                MouseEventKind::Down(MouseButton::Left) if self.is_on_scrollbar(mouse.column, mouse.row) => {
+                    self.dragging_scrollbar = true;
+                    self.scroll_to_scrollbar_row(mouse.row);
+                }
+                MouseEventKind::Drag(MouseButton::Left) if self.dragging_scrollbar => {
+                    self.scroll_to_scrollbar_row(mouse.row);
+                }
+                MouseEventKind::Up(MouseButton::Left) => {
+                    self.dragging_scrollbar = false;
+                }
+                _ => {}
+            }
+            return Ok(());
+        }
+
+        let Event::Key(key) = event else {
+            return Ok(());
+        };
+
+        if key.kind != KeyEventKind::Press {
+            return Ok(());
+        };
+
+        match key.code {
+            KeyCode::Up | KeyCode::Char('k' | 'K') => {
+                self.table_state.select_previous();
+            }
+            KeyCode::Down | KeyCode::Tab | KeyCode::Char('j' | 'J') => {
+                self.table_state.select_next();
+            }
+            KeyCode::PageDown => {
+                self.move_selection_by(self.table_page.max(1) as isize);
+            }
+            KeyCode::PageUp => {
+                self.move_selection_by(-(self.table_page.max(1) as isize));
+            }
+            KeyCode::Char('d' | 'D') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.move_selection_by((self.table_page / 2).max(1) as isize);
+            }
+            KeyCode::Char('u' | 'U') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.move_selection_by(-((self.table_page / 2).max(1) as isize));
+            }
+            KeyCode::Char('1' | 'g') | KeyCode::Home => {
+                self.table_state.select_first();
+            }
+            KeyCode::Char('0' | 'G') | KeyCode::End => {
+                // the exact index, so that the item can be used before the next redraw
+                self.table_state.select(self.items.len().checked_sub(1));
+            }
+            KeyCode::Char('c' | 'C') | KeyCode::Enter => {
+                self.request_secret(SecretAction::CopyToClipboard)?;
+            }
+            KeyCode::Char('p' | 'P') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.palette = Some(PaletteState::new(self.theme.clone()));
+            }
+            KeyCode::Char('p' | 'P') => {
+                self.request_secret(SecretAction::CopyToPrimary)?;
+            }
+            KeyCode::Char('r' | 'R') => {
+                self.request_secret(SecretAction::ShowQrCode)?;
+            }
+            KeyCode::Char('f' | 'F' | '/') => {
+                // if we are already in find mode, do NOT reset
+                // the search term, just give back focus.
+                if let Some(find_state) = self.find.as_mut() {
+                    find_state.set_focus(true);
+                } else {
+                    let mut find_state = FindItemState::with_theme(self.theme.clone());
+                    // the empty search term matches every item already shown
+                    find_state.set_match_count(self.items.len());
+                    self.find = Some(find_state);
+                }
+            }
+            KeyCode::Char('n') => {
+                let new_item = NewItemState::new(self.theme.clone(), self.limits);
+                self.open_new_item(new_item, &[])?;
+            }
+            KeyCode::Char('N') => {
+                self.request_secret(SecretAction::Duplicate)?;
+            }
+            KeyCode::Char('m' | 'M') => {
+                let label = self.db.unused_note_label(Local::now().naive_local())?;
+                let new_item = NewItemState::note(self.theme.clone(), self.limits, &label);
+                self.open_new_item(new_item, &[NOTE_TAG.to_owned()])?;
+            }
+            KeyCode::Char('b' | 'B') => {
+                let new_item = NewItemState::recovery_codes(self.theme.clone(), self.limits);
+                self.open_new_item(new_item, &[])?;
+            }
+            KeyCode::Char('i' | 'I') => {
+                self.stats = Some(self.db.stats(Utc::now())?);
+                self.compaction = None;
+                self.secret_audit = None;
+            }
+            KeyCode::Char('.') => {
+                let index = self.table_state.selected().ok_or(Error::SelectionRequired)?;
+                let item = self.items.get(index).ok_or(Error::SelectionRequired)?;
+
+                self.details = Some(item.clone());
+                self.details_note = None;
+            }
+            KeyCode::Char('t' | 'T') => {
+                self.tag_manager = Some(TagManagerState::new(self.db.tag_counts()?, self.theme.clone()));
+            }
+            KeyCode::Char('w' | 'W') if !self.marked.is_empty() => {
+                let action = PasswordAction::ExportBundle;
+                self.passwd_entry = Some(PasswordEntryState::new(action, self.theme.clone()));
+            }
+            KeyCode::Char('w' | 'W') => {
+                self.export_selected_item()?;
+            }
+            KeyCode::Char(' ') => {
+                let index = self.table_state.selected().ok_or(Error::SelectionRequired)?;
+                let uid = self.items.get(index).ok_or(Error::SelectionRequired)?.uid;
+
+                if !self.marked.remove(&uid) {
+                    self.marked.insert(uid);
+                }
+                self.table_state.select_next();
+            }
+            KeyCode::Esc => {
+                self.marked.clear();
+            }
+            KeyCode::Char('v' | 'V') => {
+                self.column_menu = Some(0);
+            }
+            KeyCode::Char('l' | 'L') => {
+                let active = self.smart_filter.and_then(|filter| {
+                    SmartFilter::ALL.iter().position(|&f| f == filter)
+                });
+                self.filter_menu = Some(active.map_or(0, |index| index + 1));
+            }
+            KeyCode::Char('h' | 'H') => {
+                self.privacy_mode = !self.privacy_mode;
+            }
+            KeyCode::Char('u' | 'U') => {
+                let action = PasswordAction::UpgradeCrypto;
+                self.passwd_entry = Some(PasswordEntryState::new(action, self.theme.clone()));
+            }
+            KeyCode::Char('a' | 'A') => {
+                let index = self.table_state.selected().ok_or(Error::SelectionRequired)?;
+                let item = &self.items[index];
+
+                self.access_history = Some(AccessHistory {
+                    label: item.label.clone(),
+                    entries: self.db.access_history(item.uid)?,
+                });
+            }
+            KeyCode::Char('e' | 'E') => {
+                if let Some(report) = self.expiry_report.take() {
+                    self.find = Some(FindItemState::with_query(self.theme.clone(), &report.query()));
+                    self.sync_data(true)?;
+                }
+            }
+            KeyCode::Char('x' | 'X') => {
+                self.expiry_report = None;
+            }
+            KeyCode::Char('q' | 'Q') => {
+                self.is_running = false;
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Locks the screen when the terminal loses focus, if configured.
+    /// Regaining focus doesn't unlock it; that takes a keypress.
+    fn handle_focus_change(&mut self, event: Event) -> ControlFlow<(), Event> {
+        match event {
+            Event::FocusLost => {
+                if self.focus_lock != FocusLock::Off {
+                    self.locked = true;
+                    // a half-typed password for something else would be confusing
+                    self.passwd_entry = None;
+                    self.bundle_password = None;
+                }
+                ControlFlow::Break(())
+            }
+            Event::FocusGained => ControlFlow::Break(()),
+            _ => ControlFlow::Continue(event),
+        }
+    }
+
+    /// While the screen is locked, key presses unlock it, or ask for the
+    /// password of the selected item, and every other event is ignored.
+    fn handle_lock_input(&mut self, event: Event) -> Result<ControlFlow<(), Event>> {
+        if !self.locked {
+            return Ok(ControlFlow::Continue(event));
+        }
+        if self.passwd_entry.is_some() {
+            // the password entry ignores e.g. mouse events, which mustn't reach the table
+            let _ = self.handle_passwd_entry_input(event)?;
+            return Ok(ControlFlow::Break(()));
+        }
+
+        let Event::Key(key) = event else {
+            return Ok(ControlFlow::Break(()));
+        };
+
+        // the result of decrypting arrives in `poll_decryption()`
+        if key.kind != KeyEventKind::Press || self.decryption.is_some() {
+            return Ok(ControlFlow::Break(()));
+        }
+
+        // The agent and the password command are not asked, since they
+        // would unlock the screen for anyone at the keyboard. An empty
+        // table has no item to check the password against.
+        if self.focus_lock == FocusLock::Password && !self.items.is_empty() {
+            if self.table_state.selected().is_none() {
+                self.table_state.select_first();
+            }
+            let action = PasswordAction::Secret(SecretAction::Unlock);
+            self.passwd_entry = Some(PasswordEntryState::new(action, self.theme.clone()));
+        } else {
+            self.locked = false;
+        }
+
+        Ok(ControlFlow::Break(()))
+    }
+
+    /// The first `Ctrl+C` acts like `Esc`, e.g. it closes the topmost dialog.
+    /// Pressing it again within `INTERRUPT_WINDOW` quits, even while a dialog
+    /// is open or a secret is being decrypted.
+    fn handle_interrupt(&mut self, event: Event) -> ControlFlow<(), Event> {
+        let Event::Key(evt) = event else {
+            return ControlFlow::Continue(event);
+        };
+
+        if evt.kind != KeyEventKind::Press
+            || !evt.modifiers.contains(KeyModifiers::CONTROL)
+            || !matches!(evt.code, KeyCode::Char('c' | 'C'))
+        {
+            return ControlFlow::Continue(event);
+        }
+
+        if self.interrupt_pending() {
+            self.is_running = false;
+            return ControlFlow::Break(());
+        }
+
+        self.last_interrupt = Some(Instant::now());
+
+        ControlFlow::Continue(Event::Key(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE)))
+    }
+
+    /// `Ctrl+Z` suspends the process from anywhere, as in other terminal programs.
+    /// In raw mode, the terminal doesn't send `SIGTSTP` by itself.
+    #[cfg(unix)]
+    fn handle_suspend_shortcut(&mut self, event: Event) -> ControlFlow<(), Event> {
+        let Event::Key(evt) = event else {
+            return ControlFlow::Continue(event);
+        };
+
+        if evt.kind != KeyEventKind::Press
+            || !evt.modifiers.contains(KeyModifiers::CONTROL)
+            || !matches!(evt.code, KeyCode::Char('z' | 'Z'))
+        {
+            return ControlFlow::Continue(event);
+        }
+
+        self.suspend_requested = true;
+
+        ControlFlow::Break(())
+    }
+
+    /// Handles events when the error modal is open.
+    fn handle_error_input(&mut self, event: Event) -> Result<ControlFlow<(), Event>> {
+        if self.popup_error.is_none() {
+            return Ok(ControlFlow::Continue(event));
+        }
+
+        if let Event::Key(evt) = event {
+            if evt.code == KeyCode::Esc {
+                self.popup_error = None;
+            }
+        }
+
+        Ok(ControlFlow::Break(()))
+    }
+
+    /// The warnings panel is dismissed by `Esc` or `Enter`, and blocks other input,
+    /// so that the first keypress isn't accidentally taken for something else.
+    fn handle_findings_input(&mut self, event: Event) -> ControlFlow<(), Event> {
+        if self.findings.is_empty() {
+            return ControlFlow::Continue(event);
+        }
+
+        if let Event::Key(evt) = event {
+            if matches!(evt.code, KeyCode::Esc | KeyCode::Enter) {
+                self.findings.clear();
+            }
+        }
+
+        ControlFlow::Break(())
+    }
+
+    /// The notice of a finished export is dismissed by `Esc` or `Enter`.
+    fn handle_exported_input(&mut self, event: Event) -> ControlFlow<(), Event> {
+        if self.exported_to.is_none() {
+            return ControlFlow::Continue(event);
+        }
+
+        if let Event::Key(evt) = event {
+            if matches!(evt.code, KeyCode::Esc | KeyCode::Enter) {
+                self.exported_to = None;
+            }
+        }
+
+        ControlFlow::Break(())
+    }
+
+    /// Handles events while a long-running operation is in progress.
+    /// Only cancellation is possible; every other input is ignored.
+    fn handle_progress_input(&mut self, event: Event) -> Result<ControlFlow<(), Event>> {
+        // Decryption and encryption can't be cancelled, but they are quick.
+        if self.decryption.is_some() || self.encryption.is_some() {
+            return Ok(ControlFlow::Break(()));
+        }
+
+        if self.crypto_upgrade.is_none() && self.auditing.is_none() && self.syncing.is_none() {
+            return Ok(ControlFlow::Continue(event));
+        }
+
+        if let Event::Key(evt) = event {
+            if evt.code == KeyCode::Esc {
+                if let Some(task) = self.crypto_upgrade.as_ref() {
+                    task.cancel();
+                }
+                if let Some(task) = self.auditing.as_ref() {
+                    task.cancel();
+                }
+                if let Some(task) = self.syncing.as_ref() {
+                    task.cancel();
+                }
+            }
+        }
+
+        Ok(ControlFlow::Break(()))
+    }
+
+    /// Handles events when the "Discard changes?" confirmation is open.
+    fn handle_confirm_discard_input(&mut self, event: Event) -> Result<ControlFlow<(), Event>> {
+        let Some(action) = self.confirm_discard else {
+            return Ok(ControlFlow::Continue(event));
+        };
+
+        if let Event::Key(evt) = event {
+            match evt.code {
+                KeyCode::Char('y' | 'Y') => {
+                    self.confirm_discard = None;
+                    self.new_item = None;
+
+                    if action == DiscardAction::Quit {
+                        self.is_running = false;
+                    }
+                }
+                KeyCode::Char('n' | 'N') | KeyCode::Esc => {
+                    self.confirm_discard = None;
+                }
+                _ => {}
+            }
+        }
+
+        Ok(ControlFlow::Break(()))
+    }
+
+    /// `Ctrl+Q` quits from anywhere, even while a dialog is open,
+    /// but asks for confirmation if there is unsaved input.
+    fn handle_quit_shortcut(&mut self, event: Event) -> Result<ControlFlow<(), Event>> {
+        let Event::Key(evt) = event else {
+            return Ok(ControlFlow::Continue(event));
+        };
+
+        if evt.kind != KeyEventKind::Press
+            || !evt.modifiers.contains(KeyModifiers::CONTROL)
+            || !matches!(evt.code, KeyCode::Char('q' | 'Q'))
+        {
+            return Ok(ControlFlow::Continue(event));
+        }
+
+        if self.new_item.as_ref().is_some_and(|new_item| new_item.form.is_dirty()) {
+            self.confirm_discard = Some(DiscardAction::Quit);
+        } else {
+            self.is_running = false;
+        }
+
+        Ok(ControlFlow::Break(()))
+    }
+
+    /// Handles events for the password entry panel before decrypting a secret.
+    fn handle_passwd_entry_input(&mut self, event: Event) -> Result<ControlFlow<(), Event>> {
+        let Some(passwd_entry) = self.passwd_entry.as_mut() else {
+            return Ok(ControlFlow::Continue(event));
+        };
+
+        match event {
+            Event::Key(evt) => match evt.code {
+                KeyCode::Esc => {
+                    self.passwd_entry = None;
+                    self.bundle_password = None;
+                }
+                KeyCode::Enter => {
+                    let action = passwd_entry.action;
+                    let is_bundle_password = matches!(
+                        action,
+                        PasswordAction::ExportBundle | PasswordAction::ConfirmExportBundle
+                    );
+
+                    // ignore attempts during the cooldown, but keep the typed password
+                    if !is_bundle_password && self.failed_attempts.remaining_cooldown(Instant::now()).is_some() {
+                        return Ok(ControlFlow::Break(()));
+                    }
+
+                    let password = SecretString::from(passwd_entry.enc_pass.as_str());
+
+                    // The panel stays open while decrypting, so that
+                    // another password can be tried if this one is wrong.
+                    match action {
+                        PasswordAction::Secret(action) => self.start_decryption(action, password)?,
+                        PasswordAction::UpgradeCrypto => {
+                            self.passwd_entry = None;
+                            self.start_crypto_upgrade(password)?;
+                        }
+                        PasswordAction::AuditSecrets => {
+                            self.passwd_entry = None;
+                            self.start_secret_audit(password)?;
+                        }
+                        PasswordAction::ExportBundle => {
+                            if password.is_empty() {
+                                return Err(Error::EncryptionPasswordRequired);
+                            }
+                            let action = PasswordAction::ConfirmExportBundle;
+                            self.passwd_entry = Some(PasswordEntryState::new(action, self.theme.clone()));
+                            self.bundle_password = Some(password);
+                        }
+                        PasswordAction::ConfirmExportBundle => {
+                            self.passwd_entry = None;
+                            let first = self.bundle_password.take();
+
+                            if first.as_ref() != Some(&password) {
+                                return Err(Error::ConfirmPasswordMismatch);
+                            }
+                            self.export_marked_items(&password)?;
+                        }
+                    }
+                }
+                KeyCode::Char('h' | 'H') if evt.modifiers.contains(KeyModifiers::CONTROL) => {
+                    passwd_entry.toggle_show_enc_pass();
+                }
+                _ => {
+                    passwd_entry.enc_pass.input(event);
+                }
+            },
+            _ => {
+                passwd_entry.enc_pass.input(event);
+            }
+        }
+
+        Ok(ControlFlow::Break(()))
+    }
+
+    /// Handles events for the Find panel.
+    fn handle_find_input(&mut self, event: Event) -> Result<ControlFlow<(), Event>> {
+        let Some(find_state) = self.find.as_mut() else {
+            return Ok(ControlFlow::Continue(event));
+        };
+
+        match event {
+            Event::Key(evt) => match evt.code {
+                KeyCode::Esc => {
+                    self.find = None;
+                    self.sync_data(true)?;
+                    Ok(ControlFlow::Break(()))
+                }
+                KeyCode::Enter if find_state.has_focus => {
+                    find_state.set_focus(false);
+                    Ok(ControlFlow::Break(()))
+                }
+                _ if find_state.has_focus => {
+                    input_text(&mut find_state.search_term, event);
+                    self.sync_data(true)?;
+                    Ok(ControlFlow::Break(()))
+                }
+                _ => Ok(ControlFlow::Continue(event))
+            }
+            Event::Paste(_) if find_state.has_focus => {
+                input_text(&mut find_state.search_term, event);
+                self.sync_data(true)?;
+                Ok(ControlFlow::Break(()))
+            }
+            _ => Ok(ControlFlow::Continue(event))
+        }
+    }
+
+    /// Handles events for the "New item" dialog.
+    fn handle_new_input(&mut self, event: Event) -> Result<ControlFlow<(), Event>> {
+        if self.new_item.as_ref().is_some_and(|new_item| new_item.generator_menu.is_some()) {
+            return self.handle_generator_menu_input(event);
+        }
+
+        // if the input text area is not open, ignore the event and give it back right away
+        let Some(new_item) = self.new_item.as_mut() else {
+            return Ok(ControlFlow::Continue(event));
+        };
+
+        match event {
+            Event::Key(evt) => match evt.code {
+                KeyCode::Esc => {
+                    if new_item.form.is_dirty() {
+                        self.confirm_discard = Some(DiscardAction::CloseDialog);
+                    } else {
+                        self.new_item = None;
+                    }
+                }
+                KeyCode::Enter => {
+                    // Reveal the messages of all fields, even the untouched ones,
+                    // and don't even try to save if any of them is invalid.
+                    new_item.validate(&*self.db)?;
+
+                    if !new_item.form.submit() {
+                        return Ok(ControlFlow::Break(()));
+                    }
+
+                    // The dialog stays open until the item has been added.
+                    match new_item.start_encryption(self.pepper.clone()) {
+                        Ok(pending) => self.encryption = Some(pending),
+                        Err(error) => self.show_new_item_error(error)?,
+                    }
+                }
+                KeyCode::Char('h' | 'H') if evt.modifiers.contains(KeyModifiers::CONTROL) => {
+                    new_item.toggle_show_secret();
+                }
+                KeyCode::Char('e' | 'E') if evt.modifiers.contains(KeyModifiers::CONTROL) => {
+                    new_item.toggle_show_enc_pass();
+                }
+                KeyCode::Char('g' | 'G') if evt.modifiers.contains(KeyModifiers::CONTROL) => {
+                    new_item.generator_menu = Some(0);
+                }
+                KeyCode::Char('o' | 'O') if evt.modifiers.contains(KeyModifiers::CONTROL) => {
+                    // a failure is shown below the secret, where validation would hide it
+                    if new_item.read_qr_image() {
+                        new_item.validate(&*self.db)?;
+                    }
+                }
+                _ => {
+                    if new_item.input(event) {
+                        new_item.validate(&*self.db)?;
+                    }
+                }
+            },
+            _ => {
+                if new_item.input(event) {
+                    new_item.validate(&*self.db)?;
+                }
+            }
+        }
+
+        Ok(ControlFlow::Break(()))
+    }
+
+    /// Handles events for the vault statistics modal.
+    fn handle_stats_input(&mut self, event: Event) -> Result<ControlFlow<(), Event>> {
+        if self.stats.is_none() {
+            return Ok(ControlFlow::Continue(event));
+        }
+
+        if let Event::Key(evt) = event {
+            match evt.code {
+                KeyCode::Esc | KeyCode::Enter | KeyCode::Char('i' | 'I') => {
+                    self.stats = None;
+                }
+                KeyCode::Char('k' | 'K') => {
+                    self.compaction = Some(self.db.compact()?);
+                    self.stats = Some(self.db.stats(Utc::now())?);
+                }
+                KeyCode::Char('u' | 'U') => {
+                    let action = PasswordAction::AuditSecrets;
+                    self.passwd_entry = Some(PasswordEntryState::new(action, self.theme.clone()));
+                }
+                _ => {}
+            }
+        }
+
+        Ok(ControlFlow::Break(()))
+    }
+
+    /// Handles events when the item details modal is open.
+    fn handle_details_input(&mut self, event: Event) -> Result<ControlFlow<(), Event>> {
+        let Some(uid) = self.details.as_ref().map(|item| item.uid) else {
+            return Ok(ControlFlow::Continue(event));
+        };
+
+        if self.details_rename.is_some() {
+            return self.handle_details_rename_input(event);
+        }
+
+        if let Event::Key(evt) = event {
+            match evt.code {
+                KeyCode::Esc | KeyCode::Enter | KeyCode::Char('.') => {
+                    self.details = None;
+                    self.details_note = None;
+                }
+                KeyCode::Char('r' | 'R') => {
+                    let label = self.details.as_ref().map_or("", |item| item.label.as_str());
+                    self.details_rename = Some(rename_form(&self.theme, label));
+                }
+                KeyCode::Char('o' | 'O') if self.is_note(uid) => {
+                    self.request_secret(SecretAction::ShowNote)?;
+                }
+                KeyCode::Char('u' | 'U') => {
+                    if let Some(codes) = self.recovery_codes.get(&uid) {
+                        if codes.remaining == 0 {
+                            return Err(Error::NoRecoveryCodesLeft);
+                        }
+                        self.request_secret(SecretAction::UseRecoveryCode)?;
+                    }
+                }
+                KeyCode::Char('p' | 'P') => {
+                    if self.details.as_ref().is_some_and(|item| item.reprompt) {
+                        self.request_secret(SecretAction::DisableReprompt)?;
+                    } else {
+                        self.set_reprompt(uid, true)?;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(ControlFlow::Break(()))
+    }
+
+    /// Handles events while the new label of the item in the details view is
+    /// being entered. Once it's confirmed, the password is asked for, and the
+    /// item is re-encrypted with the new label by `poll_decryption()`.
+    fn handle_details_rename_input(&mut self, event: Event) -> Result<ControlFlow<(), Event>> {
+        let (Some(rename), Some(item)) = (self.details_rename.as_mut(), self.details.as_ref()) else {
+            return Ok(ControlFlow::Continue(event));
+        };
+
+        match event {
+            Event::Key(evt) if evt.code == KeyCode::Esc => {
+                self.details_rename = None;
+            }
+            Event::Key(evt) if evt.code == KeyCode::Enter => {
+                if !rename.submit() {
+                    return Ok(ControlFlow::Break(()));
+                }
+
+                let label = Label::new(&rename.field(0).value())?;
+                let is_changed = label.as_str() != item.label;
+
+                // the field stays open, so that the title can be corrected
+                if is_changed && self.db.label_exists(&label)? {
+                    rename.show_error(0, &Error::DuplicateLabel);
+                    return Ok(ControlFlow::Break(()));
+                }
+
+                self.details_rename = None;
+
+                if is_changed {
+                    self.rename_to = Some(label);
+                    self.request_secret(SecretAction::Rename)?;
+                }
+            }
+            event => {
+                rename.input(event);
+            }
+        }
+
+        Ok(ControlFlow::Break(()))
+    }
+
+    /// Returns `true` if the item is a quick note, i.e. it has the note tag.
+    fn is_note(&self, uid: u64) -> bool {
+        self.has_tag(uid, NOTE_TAG)
+    }
+
+    fn has_tag(&self, uid: u64, tag: &str) -> bool {
+        self.item_tags.get(&uid).is_some_and(|tags| tags.iter().any(|t| t == tag))
+    }
+
+    /// What the QR code of an item encodes. The secret of an item tagged `wifi`
+    /// becomes a `WIFI:` URI, and that of an item tagged `totp` an `otpauth://`
+    /// URI, built from the label and the account, so that phones know what to do
+    /// with it. Other secrets, and secrets that already are URIs, are encoded as-is.
+    fn qr_payload(&self, uid: u64, label: &str, secret: &str) -> Result<Zeroizing<String>> {
+        if as_otpauth_uri(secret).is_some() || secret.starts_with("WIFI:") {
+            return Ok(Zeroizing::new(secret.to_owned()));
+        }
+
+        let account = self.items
+            .iter()
+            .find(|item| item.uid == uid)
+            .and_then(|item| item.account.as_deref())
+            .filter(|account| !account.is_empty());
+
+        if self.has_tag(uid, WIFI_TAG) {
+            Ok(qr::wifi_uri(account.unwrap_or(label), secret))
+        } else if self.has_tag(uid, TOTP_TAG) {
+            let uri = OtpAuthUri::totp(label, account.unwrap_or(label), secret)?;
+            Ok(Zeroizing::new(uri.to_uri()))
+        } else {
+            Ok(Zeroizing::new(secret.to_owned()))
+        }
+    }
+
+    /// Sets whether the password of the item must always be typed,
+    /// and updates the details view if it's open.
+    fn set_reprompt(&mut self, uid: u64, reprompt: bool) -> Result<()> {
+        self.db.set_reprompt(uid, reprompt)?;
+        self.sync_data(false)?;
+
+        if let Some(details) = self.details.as_mut().filter(|item| item.uid == uid) {
+            details.reprompt = reprompt;
+        }
+
+        Ok(())
+    }
+
+    /// Handles events when the access log modal is open.
+    fn handle_access_history_input(&mut self, event: Event) -> Result<ControlFlow<(), Event>> {
+        if self.access_history.is_none() {
+            return Ok(ControlFlow::Continue(event));
+        }
+
+        if let Event::Key(evt) = event {
+            if matches!(evt.code, KeyCode::Esc | KeyCode::Enter | KeyCode::Char('a' | 'A')) {
+                self.access_history = None;
+            }
+        }
+
+        Ok(ControlFlow::Break(()))
+    }
+
+    /// Handles events when the tag manager is open.
+    fn handle_tag_manager_input(&mut self, event: Event) -> Result<ControlFlow<(), Event>> {
+        let Some(manager) = self.tag_manager.as_mut() else {
+            return Ok(ControlFlow::Continue(event));
+        };
+        if let (true, Some(rename)) = (matches!(event, Event::Paste(_)), manager.rename.as_mut()) {
+            rename.input(event);
+            return Ok(ControlFlow::Break(()));
+        }
+        let Event::Key(evt) = event else {
+            return Ok(ControlFlow::Break(()));
+        };
+        if evt.kind != KeyEventKind::Press {
+            return Ok(ControlFlow::Break(()));
+        }
+        let selected = manager.selected_tag().map(|tag_count| tag_count.tag.clone());
+
+        if let Some(rename) = manager.rename.as_mut() {
+            match evt.code {
+                KeyCode::Esc => {
+                    manager.rename = None;
+                }
+                KeyCode::Enter => {
+                    if !rename.submit() {
+                        return Ok(ControlFlow::Break(()));
+                    }
+
+                    let new_name = rename.field(0).value();
+                    manager.rename = None;
+
+                    if let Some(old_name) = selected {
+                        self.db.rename_tag(&old_name, &new_name)?;
+                        self.reload_tags(new_name.trim())?;
+                    }
+                }
+                _ => {
+                    rename.input(event);
+                }
+            }
+            return Ok(ControlFlow::Break(()));
+        }
+
+        if manager.confirm_delete {
+            manager.confirm_delete = false;
+
+            if let (KeyCode::Char('y' | 'Y'), Some(tag)) = (evt.code, selected) {
+                self.db.delete_tag(&tag)?;
+                self.reload_tags(&tag)?;
+            }
+            return Ok(ControlFlow::Break(()));
+        }
+
+        match evt.code {
+            KeyCode::Esc | KeyCode::Char('t' | 'T') => {
+                self.tag_manager = None;
+            }
+            KeyCode::Up | KeyCode::Char('k' | 'K') => {
+                manager.selected = manager.selected.saturating_sub(1);
+            }
+            KeyCode::Down | KeyCode::Tab | KeyCode::Char('j' | 'J') => {
+                manager.selected = (manager.selected + 1).min(manager.tags.len().saturating_sub(1));
+            }
+            KeyCode::Char('r' | 'R') => {
+                if let Some(tag) = selected {
+                    manager.start_rename(&tag);
+                }
+            }
+            KeyCode::Char('d' | 'D') => {
+                manager.confirm_delete = selected.is_some();
+            }
+            _ => {}
+        }
+
+        Ok(ControlFlow::Break(()))
+    }
+
+    /// Handles events when the column menu is open.
+    fn handle_column_menu_input(&mut self, event: Event) -> ControlFlow<(), Event> {
+        let Some(selected) = self.column_menu.as_mut() else {
+            return ControlFlow::Continue(event);
+        };
+        let Event::Key(evt) = event else {
+            return ControlFlow::Break(());
+        };
+        if evt.kind != KeyEventKind::Press {
+            return ControlFlow::Break(());
+        }
+
+        match evt.code {
+            KeyCode::Esc | KeyCode::Char('v' | 'V') => {
+                self.column_menu = None;
+            }
+            KeyCode::Up | KeyCode::Char('k' | 'K') => {
+                *selected = selected.saturating_sub(1);
+            }
+            KeyCode::Down | KeyCode::Tab | KeyCode::Char('j' | 'J') => {
+                *selected = (*selected + 1).min(self.columns.len() - 1);
+            }
+            KeyCode::Char(' ') | KeyCode::Enter => {
+                let visible_count = self.columns.iter().filter(|(_, visible)| *visible).count();
+                let (_, visible) = &mut self.columns[*selected];
+
+                // at least one column must remain visible
+                if !*visible || visible_count > 1 {
+                    *visible = !*visible;
+                }
+            }
+            _ => {}
+        }
+
+        ControlFlow::Break(())
+    }
+
+    /// Handles events when the command palette is open. Running a command
+    /// closes the palette, then presses the key of the command in the table.
+    fn handle_palette_input(&mut self, event: Event) -> Result<ControlFlow<(), Event>> {
+        let Some(palette) = self.palette.as_mut() else {
+            return Ok(ControlFlow::Continue(event));
+        };
+
+        match event {
+            Event::Key(evt) => match evt.code {
+                KeyCode::Esc => {
+                    self.palette = None;
+                }
+                KeyCode::Up => {
+                    palette.selected = palette.selected.saturating_sub(1);
+                }
+                KeyCode::Down | KeyCode::Tab => {
+                    let count = palette.commands().len();
+                    palette.selected = (palette.selected + 1).min(count.saturating_sub(1));
+                }
+                KeyCode::Enter => {
+                    let command = palette.commands().get(palette.selected).copied();
+                    self.palette = None;
+
+                    if let Some(command) = command {
+                        let key = KeyEvent::new(KeyCode::Char(command.key), KeyModifiers::NONE);
+
+                        if let ControlFlow::Continue(event) = self.handle_type_ahead(Event::Key(key)) {
+                            self.handle_main_table_event(event)?;
+                        }
+                    }
+                }
+                _ => {
+                    input_text(&mut palette.query, event);
+                    palette.selected = 0;
+                }
+            }
+            Event::Paste(_) => {
+                input_text(&mut palette.query, event);
+                palette.selected = 0;
+            }
+            _ => {}
+        }
+
+        Ok(ControlFlow::Break(()))
+    }
+
+    /// Handles events when the generator menu of the New item dialog is open.
+    fn handle_generator_menu_input(&mut self, event: Event) -> Result<ControlFlow<(), Event>> {
+        let Some(new_item) = self.new_item.as_mut() else {
+            return Ok(ControlFlow::Continue(event));
+        };
+        let Some(selected) = new_item.generator_menu.as_mut() else {
+            return Ok(ControlFlow::Continue(event));
+        };
+        let Event::Key(evt) = event else {
+            return Ok(ControlFlow::Break(()));
+        };
+        if evt.kind != KeyEventKind::Press {
+            return Ok(ControlFlow::Break(()));
+        }
+
+        match evt.code {
+            KeyCode::Esc => {
+                new_item.generator_menu = None;
+            }
+            KeyCode::Up | KeyCode::Char('k' | 'K') => {
+                *selected = selected.saturating_sub(1);
+            }
+            KeyCode::Down | KeyCode::Tab | KeyCode::Char('j' | 'J') => {
+                *selected = (*selected + 1).min(self.generators.len().saturating_sub(1));
+            }
+            KeyCode::Char(' ') | KeyCode::Enter => {
+                if let Some(generator) = self.generators.get(*selected) {
+                    new_item.insert_secret(&generator.generate(&new_item.policy));
+                }
+                new_item.generator_menu = None;
+                new_item.validate(&*self.db)?;
+            }
+            _ => {}
+        }
+
+        Ok(ControlFlow::Break(()))
+    }
+
+    /// Handles events when the filter menu is open.
+    fn handle_filter_menu_input(&mut self, event: Event) -> Result<ControlFlow<(), Event>> {
+        let Some(selected) = self.filter_menu.as_mut() else {
+            return Ok(ControlFlow::Continue(event));
+        };
+        let Event::Key(evt) = event else {
+            return Ok(ControlFlow::Break(()));
+        };
+        if evt.kind != KeyEventKind::Press {
+            return Ok(ControlFlow::Break(()));
+        }
+
+        match evt.code {
+            KeyCode::Esc | KeyCode::Char('l' | 'L') => {
+                self.filter_menu = None;
+            }
+            KeyCode::Up | KeyCode::Char('k' | 'K') => {
+                *selected = selected.saturating_sub(1);
+            }
+            KeyCode::Down | KeyCode::Tab | KeyCode::Char('j' | 'J') => {
+                *selected = (*selected + 1).min(SmartFilter::ALL.len());
+            }
+            KeyCode::Char(' ') | KeyCode::Enter => {
+                self.smart_filter = selected.checked_sub(1).map(|index| SmartFilter::ALL[index]);
+                self.filter_menu = None;
+                self.sync_data(true)?;
+            }
+            _ => {}
+        }
+
+        Ok(ControlFlow::Break(()))
+    }
+
+    /// Re-reads the tags after a modification, and selects `tag`
+    /// (or the tag taking its place, if it no longer exists).
+    fn reload_tags(&mut self, tag: &str) -> Result<()> {
+        let tags = self.db.tag_counts()?;
+
+        if let Some(manager) = self.tag_manager.as_mut() {
+            manager.selected = tags
+                .iter()
+                .position(|tag_count| tag_count.tag.as_str() >= tag)
+                .unwrap_or(tags.len())
+                .min(tags.len().saturating_sub(1));
+            manager.tags = tags;
+        }
+
+        self.sync_data(false)
+    }
+
+    /// Returns the label for a copy of the item labelled `label`
+    /// that doesn't exist yet: "label (copy)", "label (copy 2)", etc.
+    fn unused_copy_label(&self, label: &str) -> Result<String> {
+        conflict::first_unused_label(
+            |n| if n == 1 { format!("{label} (copy)") } else { format!("{label} (copy {n})") },
+            |candidate| self.db.label_exists(candidate),
+        )
+    }
+
+    /// Handles events when the QR code modal is open.
+    fn handle_qr_code_input(&mut self, event: Event) -> Result<ControlFlow<(), Event>> {
+        if self.qr_code.is_none() {
+            return Ok(ControlFlow::Continue(event));
+        }
+
+        if let Event::Key(evt) = event {
+            if matches!(evt.code, KeyCode::Esc | KeyCode::Enter | KeyCode::Char('r' | 'R')) {
+                self.qr_code = None;
+            }
+        }
+
+        Ok(ControlFlow::Break(()))
+    }
+
+    /// Reloads the contents of the database from disk to memory.
+    /// The selected item remains selected wherever it ends up in the
+    /// table. If it's gone, and `adjust_selection` is set, the last
+    /// item of the table will be selected. This is useful after certain
+    /// operations that act destructively on the table state (e.g., search).
+    fn sync_data(&mut self, adjust_selection: bool) -> Result<()> {
+        let selected_uid = self.table_state
+            .selected()
+            .and_then(|idx| self.items.get(idx))
+            .map(|item| item.uid);
+
+        self.reload_items()?;
+
+        if let Some(idx) = selected_uid.and_then(|uid| self.display_index(uid)) {
+            self.table_state.select(Some(idx));
+        }
+
+        #[allow(unused_parens)]
+        if (
+            adjust_selection
+            &&
+            !self.items.is_empty()
+            &&
+            self.table_state.selected().is_none_or(|idx| idx >= self.items.len())
+        ) {
+            self.table_state.select_last();
+        }
+
+        Ok(())
+    }
+
+    /// Reloads the contents of the database after a change to the item
+    /// `uid` (e.g. adding or renaming it), selects the item, and returns
+    /// its index in the table. Returns `None`, and leaves the selection
+    /// alone, if the item isn't shown, e.g. because it doesn't match
+    /// the search term.
+    fn sync_data_selecting(&mut self, uid: u64) -> Result<Option<usize>> {
+        self.reload_items()?;
+
+        let index = self.display_index(uid);
+
+        if index.is_some() {
+            self.table_state.select(index);
+        }
+
+        Ok(index)
+    }
+
+    /// The index of the item in the table, if it's shown.
+    fn display_index(&self, uid: u64) -> Option<usize> {
+        self.items.iter().position(|item| item.uid == uid)
+    }
+
+    /// Re-reads the items matching the search and the smart filter, in
+    /// the configured order, along with the tags, codes, and URLs.
+    fn reload_items(&mut self) -> Result<()> {
+        let query = self.find.as_mut().map_or_else(Query::default, FindItemState::query);
+        self.items = query.find_items(&*self.db)?;
+
+        if let Some(filter) = self.smart_filter {
+            let matching: HashSet<u64> = self.db
+                .items_by_filter(filter, Utc::now())?
+                .into_iter()
+                .map(|item| item.uid)
+                .collect();
+
+            self.items.retain(|item| matching.contains(&item.uid));
+        }
+
+        let sort = self.sort;
+        self.items.sort_by(|lhs, rhs| sort.compare(lhs, rhs));
+
+        if let Some(find_state) = self.find.as_mut() {
+            find_state.set_match_count(self.items.len());
+        }
+
+        self.item_tags = self.db.tags_by_item()?;
+        self.recovery_codes = self.db.recovery_codes()?;
+        self.urls = self.db.urls()?;
+        self.item_expiries = self.db.expiries()?;
+        self.needs_redraw = true;
+
+        Ok(())
+    }
+
+    /// Commits and pushes local changes, and pulls remote ones,
+    /// if synchronization is configured.
+    /// Writes the selected item, still encrypted, into a new `.ssitem` file
+    /// named after its label. No password is needed.
+    fn export_selected_item(&mut self) -> Result<()> {
+        let index = self.table_state.selected().ok_or(Error::SelectionRequired)?;
+        let uid = self.items.get(index).ok_or(Error::SelectionRequired)?.uid;
+        let item = ExportedItem::from(self.db.item_by_id(uid)?);
+        let path = self.export_dir.join(ssitem::file_name(&item.label));
+
+        ssitem::write_item_file(&path, &item)?;
+        self.exported_to = Some(path);
+        self.exported_count = 1;
+
+        Ok(())
+    }
+
+    /// Writes the marked items into a new export bundle in `export_dir`, with
+    /// its payload encrypted using `password`, like `steelsafe export --encrypt`.
+    /// The secrets are exported as they are stored, so no other password is needed.
+    fn export_marked_items(&mut self, password: &SecretString) -> Result<()> {
+        let mut items = Vec::with_capacity(self.marked.len());
+
+        for &uid in &self.marked {
+            items.push(ExportedItem::from(self.db.item_by_id(uid)?));
+        }
+        items.sort_by(|lhs, rhs| lhs.label.as_str().cmp(rhs.label.as_str()));
+
+        let bundle = Bundle::new(items);
+        let file_name = format!("steelsafe-selection-{}.bundle", Local::now().format("%Y%m%d-%H%M%S"));
+        let path = self.export_dir.join(file_name);
+
+        // never silently overwrite an existing file, e.g. one exported a moment ago
+        let file = File::create_new(&path)?;
+        bundle.write_to(BufWriter::new(file), Some(password.as_bytes()))?;
+
+        self.exported_to = Some(path);
+        self.exported_count = bundle.items.len();
+        self.marked.clear();
+
+        Ok(())
+    }
+
+    /// Starts synchronizing the vault through git on a worker thread. If it's
+    /// already being synchronized, it's synchronized again once that's done.
+    fn sync_vault(&mut self) -> Result<()> {
+        let Some(git_sync) = self.git_sync.clone() else {
+            return Ok(());
+        };
+
+        if self.syncing.is_some() {
+            self.sync_again = true;
+            return Ok(());
+        }
+
+        self.syncing = Some(Task::spawn("Synchronizing", move |reporter| {
+            reporter.report(0, 2, "git fetch");
+
+            let password = git_sync.run_password_command()?;
+            let fetched = git_sync.fetch(password.as_bytes())?;
+
+            reporter.check_cancelled()?;
+
+            Ok(SyncStep::Fetched(fetched, password))
+        }));
+
+        Ok(())
+    }
+
+    /// Merges the fetched sync file into the vault, and starts pushing the
+    /// result. Nothing is merged if fetching was cancelled.
+    fn poll_sync(&mut self) -> Result<()> {
+        let Some(result) = self.syncing.as_mut().and_then(Task::poll) else {
+            return Ok(());
+        };
+        self.syncing = None;
+
+        let (fetched, password) = match result {
+            Ok(SyncStep::Fetched(fetched, password)) => (fetched, password),
+            Ok(SyncStep::Pushed) if std::mem::take(&mut self.sync_again) => return self.sync_vault(),
+            Ok(SyncStep::Pushed) => return Ok(()),
+            Err(error) => {
+                self.sync_again = false;
+                return match error {
+                    Error::Cancelled => Ok(()),
+                    error => Err(error),
+                };
+            }
+        };
+        let Some(git_sync) = self.git_sync.clone() else {
+            return Ok(());
+        };
+        let merged = sync::merge(&mut *self.db, fetched)?;
+        let conflicts = merged.report.conflicts.join(", ");
+
+        if merged.report.received > 0 {
+            self.sync_data(false)?;
+        }
+
+        self.syncing = Some(Task::spawn("Synchronizing", move |reporter| {
+            reporter.report(1, 2, "git push");
+            git_sync.push(&merged, password.as_bytes())?;
+            Ok(SyncStep::Pushed)
+        }));
+
+        if conflicts.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::SyncConflicts(conflicts))
+        }
+    }
+
+    /// Performs `action` on the secret of the selected item, using the agent
+    /// if possible, and otherwise asking for the password first.
+    fn request_secret(&mut self, action: SecretAction) -> Result<()> {
+        // Ticking off a recovery code or renaming re-encrypts the item, which needs the password.
+        let needs_password = matches!(action, SecretAction::UseRecoveryCode | SecretAction::Rename);
+
+        if !needs_password && self.reveal_secret_via_agent(action)? {
+            return Ok(());
+        }
+
+        // During the cooldown, the panel is shown instead, which displays the remaining time.
+        let cooldown = self.failed_attempts.remaining_cooldown(Instant::now());
+
+        if let (Some(command), None) = (self.password_command.clone(), cooldown) {
+            // The command may take a while (e.g. waiting for the user to touch
+            // a hardware token), so it runs on the worker thread, too.
+            return self.start_decryption_with(action, move |label| {
+                let password = command.run("Decryption password: ", Some(label), false)?;
+                Ok(SecretString::from(password.as_str()))
+            });
+        }
+
+        let action = PasswordAction::Secret(action);
+        self.passwd_entry = Some(PasswordEntryState::new(action, self.theme.clone()));
+
+        Ok(())
+    }
+
+    /// Starts decrypting the secret of the selected item on a worker thread.
+    /// Once done, `poll_decryption()` performs `action` on it.
+    fn start_decryption(&mut self, action: SecretAction, enc_pass: SecretString) -> Result<()> {
+        self.start_decryption_with(action, move |_| Ok(enc_pass))
+    }
+
+    /// Like `start_decryption()`, but the password is obtained on the worker
+    /// thread, by calling `password` with the label of the item.
+    fn start_decryption_with<F>(&mut self, action: SecretAction, password: F) -> Result<()>
+    where
+        F: FnOnce(&str) -> Result<SecretString> + Send + 'static
+    {
+        let index = self.table_state.selected().ok_or(Error::SelectionRequired)?;
+        let item = self.db.item_by_id(self.items[index].uid)?;
+        let uid = item.uid;
+        let label = item.label.clone();
+        let pepper = self.pepper.clone();
+        let new_label = if action == SecretAction::Rename { self.rename_to.clone() } else { None };
+
+        let task = Task::spawn("Decrypting…", move |_| {
+            let enc_pass = MasterPassword::new(&password(&item.label)?)?;
+            let secret = item.decryption_input(pepper.as_deref()).decrypt_and_verify(enc_pass.as_bytes())?;
+            let used_code = if action == SecretAction::UseRecoveryCode {
+                Some(UsedRecoveryCode::new(&item, &secret, &enc_pass, pepper.as_deref())?)
+            } else {
+                None
+            };
+            let renamed = new_label
+                .map(|label| RenamedItem::new(&item, &secret, &enc_pass, pepper.as_deref(), label))
+                .transpose()?;
+
+            Ok(DecryptedSecret { secret, used_code, renamed })
+        });
+
+        self.decryption = Some(PendingDecryption { action, uid, label, task });
+
+        Ok(())
+    }
+
+    /// Performs the requested action on the secret once it has been decrypted,
+    /// and counts the attempt as failed if the password was wrong.
+    fn poll_decryption(&mut self) -> Result<()> {
+        let Some(mut pending) = self.decryption.take() else {
+            return Ok(());
+        };
+        let Some(result) = pending.task.poll() else {
+            self.decryption = Some(pending);
+            return Ok(());
+        };
+
+        let DecryptedSecret { secret: plaintext_secret, used_code, renamed } = match result {
+            Ok(decrypted) => decrypted,
+            Err(error @ Error::XChaCha20Poly1305(_)) => {
+                self.record_attempt(false)?;
+
+                // let the user try again right away, instead of showing a modal
+                return match self.passwd_entry.as_mut() {
+                    Some(passwd_entry) => {
+                        passwd_entry.reject(self.failed_attempts.count());
+                        Ok(())
+                    }
+                    None => Err(error),
+                };
+            }
+            Err(error) => {
+                self.passwd_entry = None;
+                return Err(error);
+            }
+        };
+        self.record_attempt(true)?;
+        self.passwd_entry = None;
+
+        if let Some(renamed) = renamed {
+            self.rename_to = None;
+            self.db.rename_item(pending.uid, &renamed.label, renamed.modified_at, &renamed.reencrypted)?;
+            self.sync_data_selecting(pending.uid)?;
+            self.sync_vault()?;
+
+            if let Some(details) = self.details.as_mut().filter(|item| item.uid == pending.uid) {
+                details.label = renamed.label.to_string();
+                details.last_modified_at = renamed.modified_at;
+            }
+
+            return Ok(());
+        }
+
+        if let Some(used_code) = used_code {
+            self.db.update_encryption(vec![UpdateEncryptionInput::new(pending.uid, &used_code.reencrypted)])?;
+            self.db.set_recovery_codes(pending.uid, Some(&used_code.codes))?;
+            self.sync_data(false)?;
+            self.sync_vault()?;
+
+            return self.use_secret(pending.action, pending.uid, &pending.label, &used_code.code);
+        }
+
+        // we do NOT use `String::from_utf8()`, because that would copy the
+        // bytes, and complicate correct zeroization of the secret on error.
+        let secret_str = std::str::from_utf8(&plaintext_secret)?;
+
+        self.use_secret(pending.action, pending.uid, &pending.label, secret_str)
+    }
+
+    /// Adds the new item once its secret has been encrypted, and selects it.
+    fn poll_encryption(&mut self) -> Result<()> {
+        let Some(mut pending) = self.encryption.take() else {
+            return Ok(());
+        };
+        let Some(result) = pending.task.poll() else {
+            self.encryption = Some(pending);
+            return Ok(());
+        };
+
+        let added = result.and_then(|encryption_output| {
+            self.db.add_item(AddItemInput::new(
+                &pending.label,
+                pending.account.as_ref(),
+                pending.last_modified_at,
+                &encryption_output,
+            ))
+        });
+        let added = match added {
+            Ok(added) => added,
+            Err(error) => return self.show_new_item_error(error),
+        };
+
+        if pending.template == ItemTemplate::Note {
+            self.db.tag_item(added.uid, NOTE_TAG)?;
+        }
+        if let Some(codes) = pending.recovery_codes.as_ref() {
+            self.db.set_recovery_codes(added.uid, Some(codes))?;
+        }
+
+        self.new_item = None;
+        self.sync_data_selecting(added.uid)?;
+        self.sync_vault()?;
+
+        Ok(())
+    }
+
+    /// Opens the New item dialog, subject to the policies applicable to
+    /// an item of its type with the given tags.
+    fn open_new_item(&mut self, mut new_item: NewItemState, tags: &[String]) -> Result<()> {
+        new_item.policy = new_item.template
+            .item_type()
+            .map(|item_type| Policy::for_item(&self.policies, item_type, tags))
+            .unwrap_or_default();
+        new_item.validate(&*self.db)?;
+        self.new_item = Some(new_item);
+
+        Ok(())
+    }
+
+    /// Keeps the New item dialog open upon errors, so that the input isn't lost.
+    /// If the error can be attributed to a field, it's shown right there.
+    fn show_new_item_error(&mut self, error: Error) -> Result<()> {
+        let field = NewItemField::for_error(&error);
+
+        match (self.new_item.as_mut(), field) {
+            (Some(new_item), Some(field)) => {
+                new_item.form.show_error(field as usize, &error);
+                Ok(())
+            }
+            _ => Err(error),
+        }
+    }
+
+    /// Performs `action` on the secret of the selected item without asking
+    /// for its password, if the agent is running, unlocked, and able to decrypt
+    /// it. Returns `false` if the password needs to be asked for instead.
+    #[cfg(unix)]
+    fn reveal_secret_via_agent(&mut self, action: SecretAction) -> Result<bool> {
+        let Some(socket_path) = self.agent_socket.as_deref() else {
+            return Ok(false);
+        };
+        let index = self.table_state.selected().ok_or(Error::SelectionRequired)?;
+        let DisplayItem { uid, ref label, reprompt, .. } = self.items[index];
+        let label = label.clone();
+
+        if reprompt {
+            return Ok(false);
+        }
+
+        match agent::try_decrypt(socket_path, &label) {
+            Some(secret) => {
+                self.use_secret(action, uid, &label, &secret)?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn reveal_secret_via_agent(&mut self, _action: SecretAction) -> Result<bool> {
+        Ok(false)
+    }
+
+    /// Copies the plaintext secret to the clipboard, or displays it.
+    /// We can't zeroize the clipboard content, so we don't even bother.
+    fn use_secret(&mut self, action: SecretAction, uid: u64, label: &str, secret: &str) -> Result<()> {
+        match action {
+            SecretAction::CopyToClipboard => {
+                self.clipboard.set_text(secret, self.copy_to)?;
+            }
+            SecretAction::CopyToPrimary => {
+                self.clipboard.set_text(secret, CopyTarget::Primary)?;
+            }
+            SecretAction::UseRecoveryCode => {
+                self.clipboard.set_text(secret, self.copy_to)?;
+            }
+            SecretAction::ShowQrCode => {
+                let payload = self.qr_payload(uid, label, secret)?;
+                self.qr_code = Some(QrCodeView::new(label, &payload)?);
+            }
+            SecretAction::DisableReprompt => {
+                self.set_reprompt(uid, false)?;
+            }
+            SecretAction::ShowNote => {
+                if self.details.as_ref().is_some_and(|item| item.uid == uid) {
+                    self.details_note = Some(Zeroizing::new(secret.to_owned()));
+                }
+            }
+            SecretAction::Rename => {
+                // `poll_decryption()` stores the re-encrypted item instead
+            }
+            SecretAction::Unlock => {
+                self.locked = false;
+                // the secret wasn't used, so it's not an access
+                return Ok(());
+            }
+            SecretAction::Duplicate => {
+                let account = self.items
+                    .iter()
+                    .find(|item| item.uid == uid)
+                    .and_then(|item| item.account.clone());
+                let copy_label = self.unused_copy_label(label)?;
+                let new_item = NewItemState::prefilled(
+                    self.theme.clone(),
+                    self.limits,
+                    &copy_label,
+                    account.as_deref(),
+                    secret,
+                );
+                // the copy is likely used for the same purpose, so the same policies apply
+                let tags = self.item_tags.get(&uid).cloned().unwrap_or_default();
+                self.open_new_item(new_item, &tags)?;
+            }
+        }
+
+        if self.access_log {
+            self.db.log_access(uid)?;
+        }
+
+        Ok(())
+    }
+
+    /// Re-encrypts every item using outdated algorithms on a worker thread.
+    /// Items that can't be decrypted with `password` are skipped.
+    fn start_crypto_upgrade(&mut self, password: SecretString) -> Result<()> {
+        let items: Vec<Item> = self.db
+            .all_items()?
+            .into_iter()
+            .filter(Item::needs_crypto_upgrade)
+            .collect();
+        let password = MasterPassword::new(&password)?;
+        let pepper = self.pepper.clone();
+
+        self.crypto_upgrade = Some(Task::spawn("Upgrading encryption", move |reporter| {
+            CryptoUpgrade::run(&items, &password, pepper.as_deref(), reporter)
+        }));
+
+        Ok(())
+    }
+
+    /// Stores the re-encrypted items once the upgrade has finished.
+    /// Nothing is changed if it was cancelled.
+    fn poll_crypto_upgrade(&mut self) -> Result<()> {
+        let Some(result) = self.crypto_upgrade.as_mut().and_then(Task::poll) else {
+            return Ok(());
+        };
+        self.crypto_upgrade = None;
+
+        let upgrade = match result {
+            Ok(upgrade) => upgrade,
+            Err(Error::Cancelled) => return Ok(()),
+            Err(error) => return Err(error),
+        };
+        let updates = upgrade.upgraded
+            .iter()
+            .map(|(uid, output)| UpdateEncryptionInput::new(*uid, output))
+            .collect();
+
+        self.db.update_encryption(updates)?;
+
+        if upgrade.skipped > 0 {
+            return Err(Error::UpgradeIncomplete { skipped: upgrade.skipped });
+        }
+
+        Ok(())
+    }
+
+    /// Decrypts every secret on a worker thread, to count the weak and the
+    /// reused ones. Items that can't be decrypted with `password` are skipped.
+    fn start_secret_audit(&mut self, password: SecretString) -> Result<()> {
+        let items = self.db.all_items()?;
+        let password = MasterPassword::new(&password)?;
+        let pepper = self.pepper.clone();
+
+        self.auditing = Some(Task::spawn("Checking secrets", move |reporter| {
+            SecretAudit::run(&items, &password, pepper.as_deref(), reporter)
+        }));
+
+        Ok(())
+    }
+
+    /// Shows the result of checking the secrets in the statistics modal.
+    fn poll_secret_audit(&mut self) -> Result<()> {
+        let Some(result) = self.auditing.as_mut().and_then(Task::poll) else {
+            return Ok(());
+        };
+        self.auditing = None;
+
+        match result {
+            Ok(audit) => self.secret_audit = Some(audit),
+            Err(Error::Cancelled) => {}
+            Err(error) => return Err(error),
+        }
+
+        Ok(())
+    }
+
+    /// Updates the counter of consecutive failed decryption attempts,
+    /// and persists it if requested by the configuration.
+    fn record_attempt(&mut self, success: bool) -> Result<()> {
+        let old_count = self.failed_attempts.count();
+
+        if success {
+            self.failed_attempts.record_success();
+        } else {
+            self.failed_attempts.record_failure(Instant::now());
+        }
+
+        if self.persist_failed_attempts && self.failed_attempts.count() != old_count {
+            self.db.set_failed_attempts(self.failed_attempts.count())?;
+        }
+
+        Ok(())
+    }
+
+    /// Whether `Ctrl+C` has been pressed recently enough for pressing it again to quit.
+    fn interrupt_pending(&self) -> bool {
+        self.last_interrupt.is_some_and(|time| time.elapsed() < INTERRUPT_WINDOW)
+    }
+
+    /// The main table has focus when none of the other widgets do.
+    fn main_table_has_focus(&self) -> bool {
+        (
+            self.find.is_none()
+            ||
+            self.find.as_ref().is_some_and(|find| !find.has_focus)
+        )
+        && self.passwd_entry.is_none()
+        && self.new_item.is_none()
+        && self.stats.is_none()
+        && self.access_history.is_none()
+        && self.qr_code.is_none()
+        && self.tag_manager.is_none()
+        && self.crypto_upgrade.is_none()
+        && self.auditing.is_none()
+        && self.syncing.is_none()
+        && self.decryption.is_none()
+        && self.encryption.is_none()
+        && self.column_menu.is_none()
+        && self.filter_menu.is_none()
+        && self.palette.is_none()
+        && self.details.is_none()
+        && self.confirm_discard.is_none()
+        && self.popup_error.is_none()
+        && self.exported_to.is_none()
+        && !self.locked
+    }
+}
+
+/// The frames of the animation shown while waiting for a worker thread.
+const SPINNER: [&str; 10] = ["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+
+/// The duration of a single frame of the spinner animation.
+const SPINNER_STEP: Duration = Duration::from_millis(80);
+
+/// How soon `Ctrl+C` has to be pressed again for quitting.
+const INTERRUPT_WINDOW: Duration = Duration::from_secs(2);
+
+/// How often relative timestamps ("3 minutes ago") are refreshed while idle.
+const RELATIVE_TIMESTAMP_REFRESH: Duration = Duration::from_secs(10);
+
+/// How long the label typed after `'` is kept after the last key press.
+const TYPE_AHEAD_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The inner width of the error modal.
+const ERROR_WIDTH: usize = 72;
+
+/// The number of commands listed in the palette at once.
+const PALETTE_HEIGHT: usize = 10;
+
+/// The inner width of the item details modal.
+const DETAILS_WIDTH: usize = 60;
+
+/// The number of characters of labels shown in privacy mode.
+const PRIVATE_LABEL_CHARS: usize = 3;
+
+/// The number of bullets masking account names in privacy mode.
+const PRIVATE_MASK_LEN: usize = 6;
+
+/// The initial capacity of the fields for passwords and secrets. Typing beyond
+/// it makes the line of a text area grow, which leaves a copy of the beginning
+/// in freed memory, so it's generous for secrets. Password fields grow without
+/// leaving copies behind.
+const PASSWORD_FIELD_CAPACITY: usize = 256;
+const SECRET_FIELD_CAPACITY: usize = 4096;
+
+/// The beginning of the label, enough to tell items apart for the user,
+/// but (hopefully) not for someone watching the screen.
+fn private_label(label: &str) -> String {
+    let mut chars = label.chars();
+    let mut shown: String = chars.by_ref().take(PRIVATE_LABEL_CHARS).collect();
+
+    if chars.next().is_some() {
+        shown.push('…');
+    }
+
+    shown
+}
+
+/// The configured columns are visible, in the configured order,
+/// followed by the hidden ones, which can be shown at runtime.
+fn initial_columns(configured: &[TableColumn]) -> Vec<(TableColumn, bool)> {
+    let configured = if configured.is_empty() {
+        TableColumn::defaults()
+    } else {
+        configured.to_vec()
+    };
+    let hidden: Vec<_> = ColumnKind::ALL
+        .into_iter()
+        .filter(|&kind| configured.iter().all(|column| column.kind != kind))
+        .map(|kind| (TableColumn { kind, width: None }, false))
+        .collect();
+
+    configured
+        .into_iter()
+        .map(|column| (column, true))
+        .chain(hidden)
+        .collect()
+}
+
+/// The field for the new label of an item, pre-filled with the current one.
+fn rename_form(theme: &Theme, current_label: &str) -> Form {
+    let field = Field::new("New title", Input::text(current_label))
+        .required()
+        .validator(|text| Label::new(text).map(drop));
+
+    Form::new(vec![field], Placement::Embedded, theme.clone())
+}
+
+/// The secret as an `otpauth://` URI, if it looks like one. URIs are single-line.
+fn as_otpauth_uri(secret: &str) -> Option<&str> {
+    Some(secret).filter(|secret| !secret.contains('\n') && OtpAuthUri::is_otpauth_uri(secret))
+}
+
+/// The time from `elapsed` until the next multiple of `step`.
+fn until_next_multiple(elapsed: Duration, step: Duration) -> Duration {
+    let into_step = elapsed.as_nanos() % step.as_nanos();
+    step - Duration::from_nanos(into_step as u64)
+}
+
+/// Formats a byte count in human-readable binary units.
+pub fn format_file_size(size: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+
+    if size < 1024 {
+        return format!("{size} B");
+    }
+
+    let mut value = size as f64 / 1024.0;
+    let mut unit = UNITS[0];
+
+    for &next_unit in &UNITS[1..] {
+        if value < 1024.0 {
+            break;
+        }
+        value /= 1024.0;
+        unit = next_unit;
+    }
+
+    format!("{value:.1} {unit}")
+}
+
+/// What to do once the user confirms discarding unsaved input.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum DiscardAction {
+    /// Close the New item dialog.
+    CloseDialog,
+    /// Quit the application.
+    Quit,
+}
+
+/// The recorded accesses of an item, most recent first.
+#[derive(Debug)]
+struct AccessHistory {
+    label: String,
+    entries: Vec<AccessLogEntry>,
+}
+
+/// Every tag in use, for renaming, merging, and deleting them.
+/// The text field and the selection of the command palette.
+#[derive(Debug)]
+struct PaletteState {
+    query: TextArea<'static>,
+    /// The index of the selected command among the matching ones.
+    selected: usize,
+}
+
+impl PaletteState {
+    fn new(theme: Theme) -> Self {
+        let mut query = TextArea::default();
+
+        query.set_placeholder_text(tr("Type to search commands"));
+        query.set_cursor_line_style(Style::default());
+        query.set_style(theme.default().add_modifier(Modifier::BOLD));
+        query.set_block(
+            Block::bordered()
+                .title(hint("Commands"))
+                .border_type(BorderType::Rounded)
+                .border_style(theme.border_highlight().add_modifier(Modifier::BOLD))
+        );
+
+        PaletteState { query, selected: 0 }
+    }
+
+    fn commands(&self) -> Vec<Command> {
+        palette::search(self.query.lines().first().map_or("", String::as_str))
+    }
+}
+
+#[derive(Debug)]
+struct TagManagerState {
+    tags: Vec<TagCount>,
+    selected: usize,
+    /// The field for the new name, while the selected tag is being renamed.
+    rename: Option<Form>,
+    /// Whether the deletion of the selected tag is awaiting confirmation.
+    confirm_delete: bool,
+    theme: Theme,
+}
+
+impl TagManagerState {
+    fn new(tags: Vec<TagCount>, theme: Theme) -> Self {
+        TagManagerState {
+            tags,
+            selected: 0,
+            rename: None,
+            confirm_delete: false,
+            theme,
+        }
+    }
+
+    fn selected_tag(&self) -> Option<&TagCount> {
+        self.tags.get(self.selected)
+    }
+
+    /// Opens the field for the new name, pre-filled with the current one.
+    fn start_rename(&mut self, current_name: &str) {
+        let field = Field::new("New name (an existing name merges)", Input::text(current_name))
+            .required()
+            .validator(|text| normalize_tag(text).map(drop));
+
+        self.rename = Some(Form::new(vec![field], Placement::Embedded, self.theme.clone()));
+    }
+}
+
+/// What to do with a secret once it has been decrypted.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum SecretAction {
+    /// Copy it to the clipboard (or wherever `copy_to` says).
+    CopyToClipboard,
+    /// Copy it to the primary selection.
+    CopyToPrimary,
+    /// Display it as a QR code.
+    ShowQrCode,
+    /// Open the New item dialog, pre-filled with a copy of the item.
+    Duplicate,
+    /// Tick off the next recovery code stored in the item, and copy it.
+    UseRecoveryCode,
+    /// Let the agent decrypt the item again. Proving that the password is
+    /// known keeps others at the keyboard from turning the flag off.
+    DisableReprompt,
+    /// Show it below the metadata in the details view.
+    ShowNote,
+    /// Re-encrypt it with the label in `State::rename_to`, and rename the item.
+    Rename,
+    /// Nothing; decrypting it proves that the user may unlock the screen.
+    Unlock,
+}
+
+/// The secret of an item being decrypted on a worker thread.
+#[derive(Debug)]
+struct PendingDecryption {
+    /// What to do with the secret once it has been decrypted.
+    action: SecretAction,
+    uid: u64,
+    label: String,
+    task: Task<DecryptedSecret>,
+}
+
+/// The result of decrypting the secret of an item on a worker thread.
+#[derive(Debug)]
+struct DecryptedSecret {
+    secret: SecretBytes,
+    /// Only for `SecretAction::UseRecoveryCode`.
+    used_code: Option<UsedRecoveryCode>,
+    /// Only for `SecretAction::Rename`.
+    renamed: Option<RenamedItem>,
+}
+
+/// A recovery code that has just been ticked off. The item is re-encrypted
+/// right away, on the worker thread, because that needs the password.
+#[derive(Debug)]
+struct UsedRecoveryCode {
+    code: Zeroizing<String>,
+    codes: RecoveryCodes,
+    reencrypted: EncryptionOutput,
+}
+
+impl UsedRecoveryCode {
+    fn new(item: &Item, secret: &[u8], password: &MasterPassword, pepper: Option<&Pepper>) -> Result<Self> {
+        let mut codes = RecoveryCodes::parse(std::str::from_utf8(secret)?)?;
+        let code = codes.use_next()?;
+        let replacement = PlaintextSecret::new(codes.to_secret().as_bytes());
+        let reencrypted = item.encrypt_replacement(&replacement, password, pepper)?;
+
+        Ok(UsedRecoveryCode { code, codes, reencrypted })
+    }
+}
+
+/// The secret of an item re-encrypted with its new label on a worker thread,
+/// to be stored along with the label and the modification date.
+#[derive(Debug)]
+struct RenamedItem {
+    label: Label,
+    modified_at: DateTime<Utc>,
+    reencrypted: EncryptionOutput,
+}
+
+impl RenamedItem {
+    fn new(item: &Item, secret: &[u8], password: &MasterPassword, pepper: Option<&Pepper>, label: Label) -> Result<Self> {
+        let modified_at = Utc::now();
+        let secret = PlaintextSecret::new(secret);
+        let reencrypted = item.encrypt_renamed(&secret, password, pepper, &label, modified_at)?;
+
+        Ok(RenamedItem { label, modified_at, reencrypted })
+    }
+}
+
+/// The secret of a new item being encrypted on a worker thread. The rest of
+/// the item is kept here, so that it is added exactly as it was authenticated.
+#[derive(Debug)]
+struct PendingEncryption {
+    label: Label,
+    account: Option<AccountName>,
+    last_modified_at: DateTime<Utc>,
+    template: ItemTemplate,
+    /// The parsed codes, if the item holds recovery codes.
+    recovery_codes: Option<RecoveryCodes>,
+    task: Task<EncryptionOutput>,
+}
+
+/// What the password typed into the password entry panel is used for.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum PasswordAction {
+    /// Decrypting the secret of the selected item.
+    Secret(SecretAction),
+    /// Re-encrypting every item that uses outdated algorithms.
+    UpgradeCrypto,
+    /// Checking every secret for the statistics modal.
+    AuditSecrets,
+    /// Choosing a password for the bundle of the marked items.
+    ExportBundle,
+    /// Entering the same bundle password again, against typos.
+    ConfirmExportBundle,
+}
+
+/// The result of a step of synchronizing the vault through git on a worker thread.
+#[derive(Debug)]
+enum SyncStep {
+    /// The sync file has been fetched, and it can be merged into the vault,
+    /// after which it's pushed using the same password.
+    Fetched(Fetched, Zeroizing<String>),
+    /// The merged vault has been committed and pushed.
+    Pushed,
+}
+
+/// The result of re-encrypting items on a worker thread.
+#[derive(Debug, Default)]
+struct CryptoUpgrade {
+    /// The unique IDs of the re-encrypted items, and their new encryption data.
+    upgraded: Vec<(u64, EncryptionOutput)>,
+    /// The number of items that couldn't be decrypted with the password.
+    skipped: usize,
+}
+
+impl CryptoUpgrade {
+    fn run(items: &[Item], password: &MasterPassword, pepper: Option<&Pepper>, reporter: &Reporter) -> Result<Self> {
+        let mut upgrade = CryptoUpgrade::default();
+
+        for (index, item) in items.iter().enumerate() {
+            reporter.check_cancelled()?;
+            reporter.report(index, items.len(), &item.label);
+
+            match item.reencrypt(password, pepper) {
+                Ok(output) => upgrade.upgraded.push((item.uid, output)),
+                Err(Error::XChaCha20Poly1305(_)) => upgrade.skipped += 1,
+                Err(error) => return Err(error),
+            }
+        }
+
+        reporter.report(items.len(), items.len(), "");
+
+        Ok(upgrade)
+    }
+}
+
+/// The number of weak and reused secrets, for the statistics modal.
+#[derive(Clone, Copy, Debug, Default)]
+struct SecretAudit {
+    /// Secrets that are estimated to be weak (see `Strength`).
+    weak: usize,
+    /// Secrets that are the same as the secret of at least one other item.
+    reused: usize,
+    /// Items that couldn't be decrypted with the given password.
+    skipped: usize,
+}
+
+impl SecretAudit {
+    fn run(items: &[Item], password: &MasterPassword, pepper: Option<&Pepper>, reporter: &Reporter) -> Result<Self> {
+        let mut audit = SecretAudit::default();
+        let mut secrets = Vec::with_capacity(items.len());
+
+        for (index, item) in items.iter().enumerate() {
+            reporter.check_cancelled()?;
+            reporter.report(index, items.len(), &item.label);
+
+            match item.decryption_input(pepper).decrypt_and_verify(password.as_bytes()) {
+                // placeholders don't have a secret yet
+                Ok(secret) if secret.is_empty() => {}
+                Ok(secret) => secrets.push(secret),
+                Err(Error::XChaCha20Poly1305(_)) => audit.skipped += 1,
+                Err(error) => return Err(error),
+            }
+        }
+
+        reporter.report(items.len(), items.len(), "");
+
+        audit.weak = secrets
+            .iter()
+            .filter(|secret| {
+                std::str::from_utf8(secret).is_ok_and(|secret| Strength::estimate(secret) < Strength::Fair)
+            })
+            .count();
+
+        // equal secrets end up next to each other
+        secrets.sort_unstable_by(|lhs, rhs| lhs.as_slice().cmp(rhs.as_slice()));
+
+        for (index, secret) in secrets.iter().enumerate() {
+            let is_same = |other: &SecretBytes| other.as_slice() == secret.as_slice();
+            let previous = index.checked_sub(1).and_then(|index| secrets.get(index));
+
+            if previous.is_some_and(is_same) || secrets.get(index + 1).is_some_and(is_same) {
+                audit.reused += 1;
+            }
+        }
+
+        Ok(audit)
+    }
+}
+
+/// The beginning of a label typed into the table, and when it was last typed.
+#[derive(Debug)]
+struct TypeAhead {
+    text: String,
+    last_key_at: Instant,
+}
+
+impl Default for TypeAhead {
+    fn default() -> Self {
+        TypeAhead { text: String::new(), last_key_at: Instant::now() }
+    }
+}
+
+impl TypeAhead {
+    fn is_pending(&self) -> bool {
+        self.last_key_at.elapsed() < TYPE_AHEAD_TIMEOUT
+    }
+
+    fn push(&mut self, ch: char) {
+        self.text.push(ch);
+        self.last_key_at = Instant::now();
+    }
+
+    fn pop(&mut self) {
+        self.text.pop();
+        self.last_key_at = Instant::now();
+    }
+}
+
+/// A secret rendered as a QR code, made of Unicode half blocks,
+/// two rows of modules per line of text.
+struct QrCodeView {
+    label: String,
+    lines: Zeroizing<Vec<String>>,
+}
+
+impl QrCodeView {
+    /// The width of the light border around the code, in modules.
+    const QUIET_ZONE: usize = 2;
+
+    fn new(label: &str, secret: &str) -> Result<Self> {
+        let matrix = QrMatrix::new(secret.as_bytes(), Self::QUIET_ZONE)?;
+
+        Ok(QrCodeView {
+            label: label.into(),
+            lines: matrix.half_blocks(),
+        })
+    }
+
+    fn width(&self) -> u16 {
+        self.lines.first().map_or(0, |line| line.chars().count() as u16)
+    }
+
+    fn height(&self) -> u16 {
+        self.lines.len() as u16
+    }
+}
+
+impl Debug for QrCodeView {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        formatter
+            .debug_struct("QrCodeView")
+            .field("label", &self.label)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug)]
+struct PasswordEntryState {
+    action: PasswordAction,
+    is_visible: bool,
+    /// Remaining cooldown after too many failed attempts, in whole seconds.
+    cooldown_secs: Option<u64>,
+    /// The number of consecutive failed attempts, if the last one failed.
+    failed_attempts: Option<u32>,
+    enc_pass: SecretInput,
+    theme: Theme,
+}
+
+impl PasswordEntryState {
+    fn new(action: PasswordAction, theme: Theme) -> Self {
+        let mut enc_pass = SecretInput::new(PASSWORD_FIELD_CAPACITY);
+        enc_pass.set_style(theme.default());
+        enc_pass.set_error_style(theme.error());
+        // only a new password can be too weak
+        enc_pass.set_show_strength(action == PasswordAction::ExportBundle);
+
+        // set up text field style
+        let mut state = PasswordEntryState {
+            action,
+            is_visible: false,
+            cooldown_secs: None,
+            failed_attempts: None,
+            enc_pass,
+            theme,
+        };
+        state.set_visible(false);
+        state
+    }
+
+    fn toggle_show_enc_pass(&mut self) {
+        self.set_visible(!self.is_visible);
+    }
+
+    fn set_visible(&mut self, is_visible: bool) {
+        self.is_visible = is_visible;
+        self.enc_pass.set_revealed(is_visible);
+        self.update_block();
+    }
+
+    /// Zeroizes the typed password after a wrong one was entered, and shows the
+    /// number of consecutive failed attempts, while keeping the panel open.
+    fn reject(&mut self, failed_attempts: u32) {
+        self.enc_pass.clear();
+        self.failed_attempts = Some(failed_attempts);
+        self.update_block();
+    }
+
+    /// Sets the remaining cooldown, rounded up to whole seconds for display.
+    fn set_cooldown(&mut self, cooldown: Option<Duration>) {
+        let cooldown_secs = cooldown.map(|d| d.as_secs() + u64::from(d.subsec_nanos() > 0));
+
+        if cooldown_secs != self.cooldown_secs {
+            self.cooldown_secs = cooldown_secs;
+            self.update_block();
+        }
+    }
+
+    fn update_block(&mut self) {
+        let show_hide_title = hint(
+            if self.is_visible { "<^H> Hide password" } else { "<^H> Show password" }
+        );
+
+        let title = match self.action {
+            PasswordAction::ExportBundle => "Enter new bundle password",
+            PasswordAction::ConfirmExportBundle => "Confirm bundle password",
+            PasswordAction::Secret(_)
+            | PasswordAction::UpgradeCrypto
+            | PasswordAction::AuditSecrets => "Enter decryption (master) password",
+        };
+        let block = Block::bordered()
+            .title(hint(title))
+            .border_type(BorderType::Rounded)
+            .border_style(self.theme.border().add_modifier(Modifier::BOLD));
+
+        let block = if let Some(secs) = self.cooldown_secs {
+            block.title_bottom(
+                Line::from(format!(
+                    " {} ",
+                    tr("Too many failed attempts; retry in {}s").replacen("{}", &secs.to_string(), 1),
+                )).style(self.theme.error())
+            )
+        } else if let Some(count) = self.failed_attempts {
+            block
+                .title_bottom(
+                    Line::from(format!(
+                        " {} ",
+                        tr("Wrong password (attempt {})").replacen("{}", &count.to_string(), 1),
+                    )).style(self.theme.error())
+                )
+                .title_bottom(hint("<Enter> OK"))
+        } else {
+            block.title_bottom(hint("<Enter> OK"))
+        };
+
+        self.enc_pass.set_block(
+            block
+                .title_bottom(hint("<Esc> Cancel"))
+                .title_bottom(show_hide_title)
+        );
+    }
+}
+
+#[derive(Debug)]
+struct FindItemState {
+    search_term: TextArea<'static>,
+    has_focus: bool,
+    /// The syntax error in the search term, if any.
+    error: Option<String>,
+    /// The number of items in the table, once the search term is applied.
+    match_count: Option<usize>,
+    theme: Theme,
+}
+
+impl FindItemState {
+    fn with_theme(theme: Theme) -> Self {
+        let mut search_term = TextArea::default();
+
+        search_term.set_placeholder_text(tr("e.g. bank account:alice tag:work modified:>2024-01-01"));
+
+        let mut state = FindItemState {
+            search_term,
+            has_focus: true,
+            error: None,
+            match_count: None,
+            theme,
+        };
+        state.set_focus(true);
+        state
+    }
+
+    /// Opens the panel with a pre-filled search term, giving focus to the table.
+    fn with_query(theme: Theme, query: &str) -> Self {
+        let mut state = Self::with_theme(theme);
+        state.search_term.insert_str(query);
+        state.set_focus(false);
+        state
+    }
+
+    fn set_focus(&mut self, has_focus: bool) {
+        self.has_focus = has_focus;
+        self.update_block();
+    }
+
+    fn set_match_count(&mut self, match_count: usize) {
+        self.match_count = Some(match_count);
+        self.update_block();
+    }
+
+    /// Parses the search term. If it's invalid, the error is shown
+    /// in the border of the panel, and every item is matched.
+    fn query(&mut self) -> Query {
+        let search_term = self.search_term.lines().first().map_or("", String::as_str);
+
+        let query = match Query::parse(search_term) {
+            Ok(query) => {
+                self.error = None;
+                query
+            }
+            Err(error) => {
+                self.error = Some(error.localized());
+                Query::default()
+            }
+        };
+
+        self.update_block();
+        query
+    }
+
+    fn update_block(&mut self) {
+        let mut block = Block::bordered()
+            .title(hint("Search term"))
+            .border_type(BorderType::Rounded);
+
+        if let Some(count) = self.match_count {
+            let matches = if count == 1 {
+                String::from(tr("1 match"))
+            } else {
+                tr("{} matches").replacen("{}", &count.to_string(), 1)
+            };
+            block = block.title(Line::from(format!(" {matches} ")).right_aligned());
+        }
+
+        block = match self.error.as_deref() {
+            Some(error) => block.title_bottom(Line::from(format!(" {error} ")).style(self.theme.error())),
+            None => block
+                .title_bottom(hint("<Enter> Focus secrets"))
+                .title_bottom(hint("<Esc> Exit search")),
+        };
+
+        if self.has_focus {
+            self.search_term.set_style(self.theme.default().add_modifier(Modifier::BOLD));
+            self.search_term.set_block(
+                block.border_style(self.theme.border().add_modifier(Modifier::BOLD))
+            )
+        } else {
+            self.search_term.set_style(self.theme.default());
+            self.search_term.set_block(
+                block.border_style(self.theme.border())
+            )
+        }
+    }
+}
+
+#[derive(Debug)]
+struct NewItemState {
+    /// The fields, in the order of `NewItemField::ALL`.
+    form: Form,
+    show_secret: bool,
+    show_enc_pass: bool,
+    /// The index of the selected generator while the generator menu is open.
+    generator_menu: Option<usize>,
+    template: ItemTemplate,
+    /// The requirements on the secret, which are checked but not enforced.
+    policy: Policy,
+    limits: SizeLimits,
+}
+
+impl NewItemState {
+    fn new(theme: Theme, limits: SizeLimits) -> Self {
+        let mut enc_pass = SecretInput::new(PASSWORD_FIELD_CAPACITY);
+        enc_pass.set_show_strength(true);
+
+        // The secret, and whether the passwords match, are checked by `validate()`.
+        let fields = vec![
+            Field::new("Title or label", Input::text(""))
+                .required()
+                .validator(|text| Label::new(text).map(drop)),
+            Field::new("Username or account", Input::text(""))
+                .validator(|text| AccountName::optional(text).map(drop)),
+            Field::new("Secret (to be stored)", Input::secret_text(SECRET_FIELD_CAPACITY)).required(),
+            Field::new("Encryption (master) password", Input::Password(enc_pass))
+                .required()
+                .validator(|text| {
+                    if text.is_empty() {
+                        Err(Error::EncryptionPasswordRequired)
+                    } else {
+                        Ok(())
+                    }
+                }),
+            Field::new("Confirm master password", Input::password(PASSWORD_FIELD_CAPACITY)).required(),
+        ];
+        let mut state = NewItemState {
+            form: Form::new(fields, Placement::Dialog, theme),
+            show_secret: false,
+            show_enc_pass: false,
+            generator_menu: None,
+            template: ItemTemplate::Secret,
+            policy: Policy::default(),
+            limits,
+        };
+
+        // set initial masking
+        state.set_show_secret(false);
+        state.set_show_enc_pass(false);
+
+        state
+    }
+
+    /// Creates the state of the New item dialog for duplicating an item.
+    /// The encryption password is left empty, so it can be chosen anew.
+    fn prefilled(theme: Theme, limits: SizeLimits, label: &str, account: Option<&str>, secret: &str) -> Self {
+        let mut state = Self::new(theme, limits);
+
+        state.input_mut(NewItemField::Label).insert_str(label);
+        state.input_mut(NewItemField::Account).insert_str(account.unwrap_or_default());
+        state.input_mut(NewItemField::Secret).insert_str(secret);
+
+        state
+    }
+
+    /// Creates the state of the New item dialog for a quick note. The label
+    /// is already filled in, so typing starts in the secret, i.e. the note.
+    fn note(theme: Theme, limits: SizeLimits, label: &str) -> Self {
+        let mut state = Self::new(theme, limits);
+
+        state.input_mut(NewItemField::Label).insert_str(label);
+        state.template = ItemTemplate::Note;
+        state.form.focus(NewItemField::Secret as usize);
+
+        state
+    }
+
+    /// Creates the state of the New item dialog for a list of recovery codes.
+    fn recovery_codes(theme: Theme, limits: SizeLimits) -> Self {
+        let mut state = Self::new(theme, limits);
+
+        state.template = ItemTemplate::RecoveryCodes;
+        state.form.field_mut(NewItemField::Secret as usize).set_title("Recovery codes (one per line)");
+
+        state
+    }
+
+    fn input_mut(&mut self, field: NewItemField) -> &mut Input {
+        self.form.field_mut(field as usize).input_mut()
+    }
+
+    fn value(&self, field: NewItemField) -> SecretString {
+        self.form.field(field as usize).value()
+    }
+
+    fn focused(&self) -> NewItemField {
+        NewItemField::ALL[self.form.focused()]
+    }
+
+    /// Forwards the event to the form. Returns `true` if the contents of the focused field changed.
+    fn input(&mut self, event: Event) -> bool {
+        // Pasted text is checked before it's inserted, because the text area
+        // would take a very long time to process a huge paste.
+        if let Event::Paste(text) = &event {
+            if let Err(error) = self.limits.check_secret_len(text.len()) {
+                self.form.show_error(self.form.focused(), &error);
+                return false;
+            }
+        }
+
+        let modified = self.form.input(event);
+
+        if modified && self.focused() == NewItemField::Secret {
+            self.apply_otpauth_uri();
+        }
+
+        modified
+    }
+
+    /// If the secret is a valid `otpauth://` URI (e.g., pasted from an authenticator
+    /// setup page), fills in the label and the account from it, unless they have
+    /// already been entered. The URI itself is stored as the secret, so that no
+    /// parameter is lost.
+    fn apply_otpauth_uri(&mut self) {
+        let secret = self.value(NewItemField::Secret);
+        let Some(parsed) = as_otpauth_uri(&secret).and_then(|uri| OtpAuthUri::parse(uri).ok()) else {
+            return;
+        };
+        let label = parsed.issuer.as_deref().unwrap_or(&parsed.account);
+
+        for (field, value) in [(NewItemField::Label, label), (NewItemField::Account, parsed.account.as_str())] {
+            let field = self.form.field_mut(field as usize);
+
+            if field.input().is_empty() && !value.is_empty() {
+                field.input_mut().insert_str(value);
+                field.touch();
+            }
+        }
+    }
+
+    /// Treats the secret as the path of an image file (e.g. a screenshot of a
+    /// two-factor authentication setup page), and replaces it with the contents
+    /// of the QR code in the image, then applies it like a pasted `otpauth://`
+    /// URI. Returns `false` and shows the error below the secret if the image
+    /// can't be read, or contains no QR code.
+    fn read_qr_image(&mut self) -> bool {
+        let index = NewItemField::Secret as usize;
+        let path = self.value(NewItemField::Secret);
+
+        match qr::decode_image(Path::new(path.trim())) {
+            Ok(content) => {
+                let field = self.form.field_mut(index);
+                field.input_mut().replace(&content);
+                field.touch();
+                self.form.focus(index);
+                self.apply_otpauth_uri();
+                true
+            }
+            Err(error) => {
+                self.form.show_error(index, &error);
+                false
+            }
+        }
+    }
+
+    /// Checks every field, and updates the inline error messages accordingly.
+    /// The rules are the same as those enforced by `add_item()`, plus the
+    /// uniqueness of the label, which is checked against the database.
+    fn validate(&mut self, db: &dyn Vault) -> Result<()> {
+        self.form.validate();
+
+        if let Ok(label) = Label::new(&self.value(NewItemField::Label)) {
+            if db.label_exists(&label)? {
+                self.form.set_error(NewItemField::Label as usize, Some(&Error::DuplicateLabel));
+            }
+        }
+
+        let secret = self.value(NewItemField::Secret);
+        let otpauth_uri = as_otpauth_uri(&secret);
+        let secret_error = if secret.is_empty() {
+            Some(Error::SecretRequired)
+        } else if self.template == ItemTemplate::RecoveryCodes {
+            RecoveryCodes::parse(&secret)
+                .and_then(|codes| self.limits.check_secret_len(codes.to_secret().len()))
+                .err()
+        } else if let Some(uri) = otpauth_uri {
+            OtpAuthUri::parse(uri).err()
+        } else {
+            self.limits.check_secret_len(secret.len()).err()
+        };
+        // OTP seeds are made by the website, so there's nothing the user could
+        // change; the parameters of the URI are described instead.
+        let note = match otpauth_uri {
+            Some(uri) => OtpAuthUri::parse(uri).ok().map(|parsed| Note::Info(parsed.describe())),
+            None if secret_error.is_some() => None,
+            None => {
+                let violations = self.policy.violations(&secret);
+
+                if violations.is_empty() {
+                    None
+                } else {
+                    Some(Note::Warning(tr("Policy: {}").replacen("{}", &violations.join("; "), 1)))
+                }
+            }
+        };
+        let confirm_error = if self.value(NewItemField::EncPass) == self.value(NewItemField::Confirm) {
+            None
+        } else {
+            Some(Error::ConfirmPasswordMismatch)
+        };
+
+        self.form.set_error(NewItemField::Secret as usize, secret_error.as_ref());
+        self.form.field_mut(NewItemField::Secret as usize).set_note(note);
+        self.form.set_error(NewItemField::Confirm as usize, confirm_error.as_ref());
+
+        Ok(())
+    }
+
+    fn set_show_secret(&mut self, flag: bool) {
+        self.show_secret = flag;
+        self.input_mut(NewItemField::Secret).set_revealed(flag);
+    }
+
+    fn set_show_enc_pass(&mut self, flag: bool) {
+        self.show_enc_pass = flag;
+
+        for field in [NewItemField::EncPass, NewItemField::Confirm] {
+            self.input_mut(field).set_revealed(flag);
+        }
+    }
+
+    fn toggle_show_secret(&mut self) {
+        self.set_show_secret(!self.show_secret);
+    }
+
+    fn toggle_show_enc_pass(&mut self) {
+        self.set_show_enc_pass(!self.show_enc_pass);
+    }
+
+    /// Replaces the secret with a generated one.
+    fn insert_secret(&mut self, secret: &str) {
+        self.input_mut(NewItemField::Secret).replace(secret);
+    }
+
+    /// Validates the input, and starts encrypting the secret on a worker thread.
+    fn start_encryption(&self, pepper: Option<Arc<Pepper>>) -> Result<PendingEncryption> {
+        // A value pasted with a trailing newline spans two lines of the text area.
+        let label = Label::new(&self.value(NewItemField::Label))?;
+        let account = AccountName::optional(&self.value(NewItemField::Account))?;
+
+        // Copies of the secret and the password are `SecretString`s (and then
+        // `PlaintextSecret` and `MasterPassword`), so that they are cleared
+        // upon drop (even if an error occurs). The fields themselves are
+        // cleared when the dialog is dropped.
+        let secret = self.value(NewItemField::Secret);
+
+        if secret.is_empty() {
+            return Err(Error::SecretRequired);
+        }
+        let recovery_codes = match self.template {
+            ItemTemplate::RecoveryCodes => Some(RecoveryCodes::parse(&secret)?),
+            ItemTemplate::Secret | ItemTemplate::Note => None,
+        };
+        let secret = recovery_codes.as_ref().map_or(secret, RecoveryCodes::to_secret);
+        self.limits.check_secret_len(secret.len())?;
+
+        let enc_pass = MasterPassword::new(&self.value(NewItemField::EncPass))?;
+
+        if enc_pass.as_bytes() != self.value(NewItemField::Confirm).as_bytes() {
+            return Err(Error::ConfirmPasswordMismatch);
+        }
+
+        let secret = PlaintextSecret::new(secret.as_bytes());
+        let last_modified_at = Utc::now();
+
+        let task = {
+            let label = label.clone();
+            let account = account.clone();
+
+            Task::spawn("Encrypting…", move |_| {
+                let encryption_input = EncryptionInput {
+                    plaintext_secret: &secret,
+                    label: &label,
+                    account: account.as_ref(),
+                    last_modified_at,
+                    pepper: pepper.as_deref(),
+                };
+                encryption_input.encrypt_and_authenticate(&enc_pass)
+            })
+        };
+
+        Ok(PendingEncryption {
+            label,
+            account,
+            last_modified_at,
+            template: self.template,
+            recovery_codes,
+            task,
+        })
+    }
+}
+
+/// What kind of item the New item dialog creates.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum ItemTemplate {
+    /// An ordinary secret, e.g. a password.
+    Secret,
+    /// A quick note, tagged `NOTE_TAG`.
+    Note,
+    /// A list of recovery codes (see the `recovery` module).
+    RecoveryCodes,
+}
+
+impl ItemTemplate {
+    const fn title(self) -> &'static str {
+        match self {
+            ItemTemplate::Secret => "New secret item",
+            ItemTemplate::Note => "New note",
+            ItemTemplate::RecoveryCodes => "New recovery codes",
+        }
+    }
+
+    /// Recovery codes are made by the website, so no policy applies to them.
+    const fn item_type(self) -> Option<ItemType> {
+        match self {
+            ItemTemplate::Secret => Some(ItemType::Secret),
+            ItemTemplate::Note => Some(ItemType::Note),
+            ItemTemplate::RecoveryCodes => None,
+        }
+    }
+}
+
+/// A field of the New item dialog.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum NewItemField {
+    Label,
+    Account,
+    Secret,
+    EncPass,
+    Confirm,
+}
+
+impl NewItemField {
+    /// All fields, in the order they appear in the dialog.
+    const ALL: [Self; 5] = [
+        NewItemField::Label,
+        NewItemField::Account,
+        NewItemField::Secret,
+        NewItemField::EncPass,
+        NewItemField::Confirm,
+    ];
+
+    /// Returns the field that caused a validation error, if any.
+    fn for_error(error: &Error) -> Option<Self> {
+        match error {
+            Error::LabelRequired | Error::DuplicateLabel => Some(NewItemField::Label),
+            Error::AccountNameSingleLine => Some(NewItemField::Account),
+            Error::SecretRequired
+            | Error::RecoveryCodesRequired
+            | Error::InvalidOtpAuthUri(_)
+            | Error::InputTooLarge { .. } => Some(NewItemField::Secret),
+            Error::EncryptionPasswordRequired => Some(NewItemField::EncPass),
+            Error::ConfirmPasswordMismatch => Some(NewItemField::Confirm),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use chrono::{DateTime, TimeDelta, TimeZone, Utc};
+    use nanosql::Null;
+    use ratatui::{
+        Terminal, backend::TestBackend, buffer::Cell, style::{Color, Modifier},
+        crossterm::event::{Event, KeyCode, KeyModifiers, MouseEvent, MouseEventKind, MouseButton},
+    };
+    use crate::config::{
+        Config, DatabaseOptions, Theme, ColorPair, FocusLock, GeneratorConfig, SizeLimits, PollConfig,
+        SortOrder, SortKey, PanelConfig, PanelPosition, ColumnKind,
+    };
+    use crate::policy::{PasswordPolicy, CharClass};
+    use crate::clipboard::{Clipboard, RecordingBackend, Selection};
+    use crate::crypto::{KdfParams, CipherId};
+    use crate::input::ScriptedEvents;
+    use crate::db::{Database, AddItemInput, NOTE_TAG, WIFI_TAG, TOTP_TAG};
+    use crate::fields::{Label, AccountName};
+    use crate::health::Finding;
+    use crate::error::{Error, Result};
+    use crate::leakcheck::leaked_copies;
+    use super::{
+        State, NewItemState, NewItemField, PasswordEntryState, PasswordAction, SecretAction,
+        TYPE_AHEAD_TIMEOUT, INTERRUPT_WINDOW, SPINNER_STEP, until_next_multiple,
+    };
+
+
+    /// Drives the user interface like the main loop does, but with scripted
+    /// events, an in-memory database, and a terminal that only exists in memory.
+    struct Harness {
+        state: State,
+        terminal: Terminal<TestBackend>,
+        copied: RecordingBackend,
+    }
+
+    impl Harness {
+        fn new() -> Result<Self> {
+            Self::with_config(&Config::default(), 120, 40)
+        }
+
+        fn with_config(config: &Config, width: u16, height: u16) -> Result<Self> {
+            let db = Database::open(":memory:", &DatabaseOptions::default())?;
+            let mut state = State::new(Box::new(db), None, config)?;
+            let copied = RecordingBackend::default();
+
+            // neither the real clipboard nor a running agent may interfere
+            state.clipboard = Clipboard::with_backend("recording", copied.clone());
+            #[cfg(unix)]
+            {
+                state.agent_socket = None;
+            }
+
+            Ok(Harness {
+                state,
+                terminal: Terminal::new(TestBackend::new(width, height))?,
+                copied,
+            })
+        }
+
+        /// Handles every event of the script, then waits for the resulting
+        /// encryption or decryption to finish. Like the main loop, it redraws
+        /// the screen only if something has changed, so the screen is stale
+        /// if a change wasn't noticed.
+        fn run(&mut self, events: &mut ScriptedEvents) -> Result<()> {
+            while !events.is_empty() {
+                self.draw_if_needed()?;
+                self.state.handle_events(&mut *events);
+            }
+
+            let deadline = Instant::now() + Duration::from_secs(60);
+
+            while self.state.running_tasks() > 0 {
+                assert!(Instant::now() < deadline, "background task timed out");
+                thread::sleep(Duration::from_millis(10));
+                self.state.handle_events(&mut ScriptedEvents::default());
+            }
+
+            self.draw_if_needed()
+        }
+
+        fn draw_if_needed(&mut self) -> Result<()> {
+            if self.state.needs_redraw() {
+                self.terminal.draw(|frame| self.state.draw(frame))?;
+            }
+            Ok(())
+        }
+
+        /// Adds a few items with fixed dates, for rendering only: the secrets
+        /// aren't actually encrypted, which would also take a while.
+        fn seed(&mut self) -> Result<()> {
+            let items = [
+                ("My Bank", Some("alice@example.com")),
+                ("Webmail", Some("bob")),
+                ("Wi-Fi at the cottage", None),
+            ];
+
+            for (day, (label, account)) in (1..).zip(items) {
+                let account = account.map(AccountName::new).transpose()?;
+
+                self.state.db.add_item(AddItemInput {
+                    uid: Null,
+                    label: &Label::new(label)?,
+                    account: account.as_ref(),
+                    last_modified_at: Utc.with_ymd_and_hms(2024, 3, day, 12, 0, 0).unwrap(),
+                    encrypted_secret: b"not actually encrypted",
+                    kdf_salt: [day as u8; 16],
+                    auth_nonce: [day as u8; 24],
+                    peppered: false,
+                    kdf: KdfParams::CURRENT,
+                    cipher: CipherId::CURRENT,
+                    reprompt: false,
+                })?;
+            }
+
+            self.state.sync_data(true)
+        }
+
+        /// Adds `count` items labelled "Item 00", "Item 01", etc., like `seed()`.
+        fn seed_many(&mut self, count: u8) -> Result<()> {
+            for n in 0..count {
+                self.state.db.add_item(AddItemInput {
+                    uid: Null,
+                    label: &Label::new(&format!("Item {n:02}"))?,
+                    account: None,
+                    last_modified_at: Utc.with_ymd_and_hms(2024, 3, 1, 12, 0, 0).unwrap(),
+                    encrypted_secret: b"not actually encrypted",
+                    kdf_salt: [n; 16],
+                    auth_nonce: [n; 24],
+                    peppered: false,
+                    kdf: KdfParams::CURRENT,
+                    cipher: CipherId::CURRENT,
+                    reprompt: false,
+                })?;
+            }
+
+            self.state.sync_data(true)
+        }
+
+        /// Opens the New item dialog, fills it in, and saves the item.
+        fn add_item(&mut self, label: &str, account: &str, secret: &str, password: &str) -> Result<()> {
+            self.run(
+                ScriptedEvents::default()
+                    .type_text("n")
+                    .type_text(label)
+                    .key(KeyCode::Tab)
+                    .type_text(account)
+                    .key(KeyCode::Tab)
+                    .type_text(secret)
+                    .key(KeyCode::Tab)
+                    .type_text(password)
+                    .key(KeyCode::Tab)
+                    .type_text(password)
+                    .key(KeyCode::Enter)
+            )
+        }
+
+        /// The text on the screen, one line per row.
+        fn screen(&self) -> String {
+            let buffer = self.terminal.backend().buffer();
+
+            buffer.content
+                .chunks(usize::from(buffer.area.width))
+                .map(|row| row.iter().map(Cell::symbol).collect::<String>())
+                .collect::<Vec<_>>()
+                .join("\n")
+        }
+
+        fn labels(&self) -> Vec<&str> {
+            self.state.items.iter().map(|item| item.label.as_str()).collect()
+        }
+
+        fn copied(&self) -> Vec<(String, Selection)> {
+            self.copied.0.lock().expect("clipboard record poisoned").clone()
+        }
+
+        fn assert_no_error(&self) {
+            assert!(self.state.popup_error.is_none(), "unexpected error: {:#?}", self.state.popup_error);
+        }
+    }
+
+    #[test]
+    fn add_search_and_copy() -> Result<()> {
+        let mut harness = Harness::new()?;
+
+        harness.add_item("My Bank", "alice", "hunter2", "master password")?;
+        harness.assert_no_error();
+        assert!(harness.state.new_item.is_none());
+        assert_eq!(harness.labels(), ["My Bank"]);
+        assert!(harness.screen().contains("My Bank"));
+
+        harness.add_item("Webmail", "bob", "correct horse", "master password")?;
+        harness.assert_no_error();
+        assert_eq!(harness.labels(), ["My Bank", "Webmail"]);
+        assert_eq!(harness.state.table_state.selected(), Some(1));
+
+        // the new item dialog refuses duplicate labels, and stays open
+        harness.add_item("Webmail", "", "other", "x")?;
+        assert!(harness.state.new_item.is_some());
+        assert_eq!(harness.labels(), ["My Bank", "Webmail"]);
+        harness.run(ScriptedEvents::default().key(KeyCode::Esc).type_text("y"))?;
+        assert!(harness.state.new_item.is_none());
+
+        // searching narrows down the table, and Enter gives focus back to it
+        harness.run(ScriptedEvents::default().type_text("/bank").key(KeyCode::Enter))?;
+        harness.assert_no_error();
+        assert_eq!(harness.labels(), ["My Bank"]);
+        assert!(!harness.screen().contains("Webmail"));
+        assert!(harness.screen().contains("1 match"));
+
+        // copying asks for the password, then copies the selected secret
+        harness.run(ScriptedEvents::default().type_text("c"))?;
+        assert!(harness.state.passwd_entry.is_some());
+        harness.run(ScriptedEvents::default().type_text("master password").key(KeyCode::Enter))?;
+        harness.assert_no_error();
+        assert!(harness.state.passwd_entry.is_none());
+        assert_eq!(harness.copied(), [("hunter2".to_owned(), Selection::Clipboard)]);
+
+        // leaving the search shows every item again
+        harness.run(ScriptedEvents::default().key(KeyCode::Esc))?;
+        assert_eq!(harness.labels(), ["My Bank", "Webmail"]);
+        assert!(harness.screen().contains("Webmail"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn wrong_password_can_be_retried() -> Result<()> {
+        let mut harness = Harness::new()?;
+
+        harness.add_item("My Bank", "alice", "hunter2", "master password")?;
+        harness.run(ScriptedEvents::default().type_text("cwrong password").key(KeyCode::Enter))?;
+
+        // no modal error; the panel stays open for another attempt
+        harness.assert_no_error();
+        assert!(harness.state.passwd_entry.is_some());
+        assert_eq!(harness.state.failed_attempts.count(), 1);
+        assert!(harness.copied().is_empty());
+
+        harness.run(ScriptedEvents::default().type_text("master password").key(KeyCode::Enter))?;
+        harness.assert_no_error();
+        assert!(harness.state.passwd_entry.is_none());
+        assert_eq!(harness.state.failed_attempts.count(), 0);
+        assert_eq!(harness.copied(), [("hunter2".to_owned(), Selection::Clipboard)]);
+
+        // Ctrl+Q quits from anywhere
+        harness.run(ScriptedEvents::default().ctrl('q'))?;
+        assert!(!harness.state.is_running());
+
+        Ok(())
+    }
+
+    #[test]
+    fn memo_adds_a_tagged_note() -> Result<()> {
+        let mut harness = Harness::new()?;
+
+        // the label is filled in, and typing starts in the secret
+        harness.run(
+            ScriptedEvents::default()
+                .type_text("m")
+                .type_text("1234-5678 8765-4321")
+                .key(KeyCode::Tab)
+                .type_text("master password")
+                .key(KeyCode::Tab)
+                .type_text("master password")
+                .key(KeyCode::Enter)
+        )?;
+        harness.assert_no_error();
+        assert!(harness.state.new_item.is_none());
+
+        let [item] = harness.state.items.as_slice() else {
+            panic!("expected exactly one item, got {:?}", harness.labels());
+        };
+        assert!(item.label.starts_with("Note "));
+        assert_eq!(harness.state.db.tags_by_item()?[&item.uid], [NOTE_TAG]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn reprompt_needs_the_password_to_be_turned_off() -> Result<()> {
+        let mut harness = Harness::new()?;
+
+        harness.run(
+            ScriptedEvents::default()
+                .type_text("m")
+                .type_text("PIN: 1234")
+                .key(KeyCode::Tab)
+                .type_text("master password")
+                .key(KeyCode::Tab)
+                .type_text("master password")
+                .key(KeyCode::Enter)
+        )?;
+        harness.assert_no_error();
+        let uid = harness.state.items[0].uid;
+
+        // turning it on is immediate
+        harness.run(ScriptedEvents::default().type_text(".p"))?;
+        harness.assert_no_error();
+        assert!(harness.state.db.item_by_id(uid)?.reprompt);
+        assert!(harness.state.details.as_ref().is_some_and(|item| item.reprompt));
+
+        // turning it off asks for the password
+        harness.run(ScriptedEvents::default().type_text("p"))?;
+        assert!(harness.state.passwd_entry.is_some());
+        assert!(harness.state.db.item_by_id(uid)?.reprompt);
+
+        harness.run(ScriptedEvents::default().type_text("master password").key(KeyCode::Enter))?;
+        harness.assert_no_error();
+        assert!(!harness.state.db.item_by_id(uid)?.reprompt);
+        assert!(harness.state.details.as_ref().is_some_and(|item| !item.reprompt));
+
+        Ok(())
+    }
+
+    #[test]
+    fn notes_are_shown_with_markdown_styling() -> Result<()> {
+        let mut harness = Harness::new()?;
+
+        harness.run(
+            ScriptedEvents::default()
+                .type_text("m")
+                .type_text("**PIN**: `1234`")
+                .key(KeyCode::Tab)
+                .type_text("master password")
+                .key(KeyCode::Tab)
+                .type_text("master password")
+                .key(KeyCode::Enter)
+        )?;
+        harness.assert_no_error();
+
+        harness.run(ScriptedEvents::default().type_text(".o"))?;
+        assert!(harness.state.passwd_entry.is_some());
+
+        harness.run(ScriptedEvents::default().type_text("master password").key(KeyCode::Enter))?;
+        harness.assert_no_error();
+
+        let item = harness.state.details.clone().expect("details are still shown");
+        let lines = harness.state.details_lines(&item);
+        let note = lines.iter().find(|line| line.to_string() == " PIN: 1234").expect("note is rendered");
+        assert!(note.spans[1].style.add_modifier.contains(Modifier::BOLD));
+
+        // closing the details forgets the note
+        harness.run(ScriptedEvents::default().key(KeyCode::Esc))?;
+        assert!(harness.state.details_note.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn renamed_items_remain_decryptable() -> Result<()> {
+        let mut harness = Harness::new()?;
+
+        harness.run(
+            ScriptedEvents::default()
+                .type_text("m")
+                .type_text("PIN: 1234")
+                .key(KeyCode::Tab)
+                .type_text("master password")
+                .key(KeyCode::Tab)
+                .type_text("master password")
+                .key(KeyCode::Enter)
+        )?;
+        harness.assert_no_error();
+
+        let selected = harness.state.table_state.selected().expect("the new note is selected");
+        let label = harness.state.items[selected].label.clone();
+
+        // the field starts out with the current label
+        harness.run(ScriptedEvents::default().type_text(".r").type_text(" (old)").key(KeyCode::Enter))?;
+        harness.assert_no_error();
+        assert!(harness.state.details_rename.is_none());
+        assert!(harness.state.passwd_entry.is_some());
+
+        harness.run(ScriptedEvents::default().type_text("master password").key(KeyCode::Enter))?;
+        harness.assert_no_error();
+
+        let renamed = format!("{label} (old)");
+        assert_eq!(harness.state.details.as_ref().map(|item| item.label.as_str()), Some(renamed.as_str()));
+        assert!(harness.state.items.iter().any(|item| item.label == renamed));
+
+        // the label is authenticated along with the secret, which still decrypts
+        harness.run(ScriptedEvents::default().type_text("o").type_text("master password").key(KeyCode::Enter))?;
+        harness.assert_no_error();
+        assert_eq!(harness.state.details_note.as_deref().map(String::as_str), Some("PIN: 1234"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn rename_errors_are_shown_in_the_field() -> Result<()> {
+        let mut harness = Harness::new()?;
+
+        harness.add_item("GitHub", "octocat", "hunter2", "master password")?;
+        harness.add_item("GitLab", "octocat", "hunter3", "master password")?;
+
+        // the field stays open, so that the title can be corrected
+        harness.run(
+            ScriptedEvents::default()
+                .type_text(".r")
+                .ctrl('a')
+                .ctrl('k')
+                .type_text("GitHub")
+                .key(KeyCode::Enter)
+        )?;
+        harness.assert_no_error();
+        assert!(harness.state.details_rename.is_some());
+        assert!(harness.screen().contains("An item with this label already exists"));
+
+        harness.run(ScriptedEvents::default().ctrl('a').ctrl('k').key(KeyCode::Enter))?;
+        harness.assert_no_error();
+        assert!(harness.screen().contains("Label is required and must be a single line"));
+
+        harness.run(ScriptedEvents::default().key(KeyCode::Esc))?;
+        assert!(harness.state.details_rename.is_none());
+        assert!(harness.state.passwd_entry.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn focus_loss_locks_until_the_password_is_entered() -> Result<()> {
+        let config = Config { lock_on_focus_loss: FocusLock::Password, ..Config::default() };
+        let mut harness = Harness::with_config(&config, 120, 40)?;
+
+        harness.run(
+            ScriptedEvents::default()
+                .type_text("m")
+                .type_text("PIN: 1234")
+                .key(KeyCode::Tab)
+                .type_text("master password")
+                .key(KeyCode::Tab)
+                .type_text("master password")
+                .key(KeyCode::Enter)
+        )?;
+        harness.assert_no_error();
+        let label = harness.state.items[0].label.clone();
+        assert!(harness.screen().contains(&label));
+
+        harness.run(ScriptedEvents::default().push(Event::FocusLost).push(Event::FocusGained))?;
+        assert!(harness.state.locked);
+        assert!(!harness.screen().contains(&label));
+
+        // the first key only opens the password entry, and doesn't reach the table
+        harness.run(ScriptedEvents::default().type_text("n"))?;
+        assert!(harness.state.new_item.is_none());
+        assert!(harness.state.passwd_entry.is_some());
+
+        harness.run(ScriptedEvents::default().type_text("wrong").key(KeyCode::Enter))?;
+        assert!(harness.state.locked);
+        assert!(!harness.screen().contains(&label));
+
+        harness.run(ScriptedEvents::default().type_text("master password").key(KeyCode::Enter))?;
+        harness.assert_no_error();
+        assert!(!harness.state.locked);
+        assert!(harness.screen().contains(&label));
+        assert!(harness.copied().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn generator_menu_inserts_a_passphrase() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("steelsafe-tui-wordlist-{}.txt", std::process::id()));
+        std::fs::write(&path, "11111\tcorrect\n11112\thorse\n11113\tbattery\n11114\tstaple\n")?;
+
+        let generator = GeneratorConfig {
+            long_wordlist: Some(path.clone()),
+            words: 4,
+            separator: String::from("."),
+            ..GeneratorConfig::default()
+        };
+        let config = Config { generator, ..Config::default() };
+        let mut harness = Harness::with_config(&config, 120, 40)?;
+        std::fs::remove_file(&path)?;
+
+        // the entropy of each configuration is shown: 4 words out of 4 is 8 bits
+        harness.run(ScriptedEvents::default().type_text("n").ctrl('g'))?;
+        assert!(harness.screen().contains("4 long words"));
+        assert!(harness.screen().contains(" 8 bits"));
+
+        harness.run(ScriptedEvents::default().key(KeyCode::Down).key(KeyCode::Enter))?;
+        harness.assert_no_error();
+
+        let new_item = harness.state.new_item.as_ref().expect("dialog remains open");
+        let secret = new_item.value(NewItemField::Secret);
+        let words: Vec<_> = secret.split('.').collect();
+        assert!(new_item.generator_menu.is_none());
+        assert_eq!(words.len(), 4);
+        assert!(words.iter().all(|word| ["correct", "horse", "battery", "staple"].contains(word)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn policy_violations_are_warnings() -> Result<()> {
+        let policy = PasswordPolicy {
+            min_length: 48,
+            require: vec![CharClass::Digit],
+            banned_chars: String::from("!$"),
+            ..PasswordPolicy::default()
+        };
+        let config = Config { policies: vec![policy], ..Config::default() };
+        let mut harness = Harness::with_config(&config, 120, 40)?;
+
+        harness.run(
+            ScriptedEvents::default()
+                .type_text("n")
+                .type_text("Legacy")
+                .key(KeyCode::Tab)
+                .key(KeyCode::Tab)
+                .type_text("hunter2!")
+        )?;
+        assert!(harness.screen().contains("Policy: Shorter than 48 characters; Banned characters: !"));
+
+        // the random password complies
+        harness.run(ScriptedEvents::default().ctrl('g').key(KeyCode::Enter))?;
+        let new_item = harness.state.new_item.as_ref().expect("dialog remains open");
+        let secret = new_item.value(NewItemField::Secret);
+        assert!(new_item.form.field(NewItemField::Secret as usize).note().is_none());
+        assert_eq!(secret.len(), 48);
+        assert!(secret.chars().any(|c| c.is_ascii_digit()));
+        assert!(!secret.contains(['!', '$']));
+
+        // a violating secret can still be saved
+        harness.run(
+            ScriptedEvents::default()
+                .ctrl('a')
+                .ctrl('k')
+                .type_text("short")
+                .key(KeyCode::Tab)
+                .type_text("master password")
+                .key(KeyCode::Tab)
+                .type_text("master password")
+                .key(KeyCode::Enter)
+        )?;
+        harness.assert_no_error();
+        assert!(harness.state.new_item.is_none());
+        assert_eq!(harness.state.items.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn failed_save_leaves_no_copies_of_the_secret() -> Result<()> {
+        const SECRET: &str = "a secret typed key by key";
+        const PASSWORD: &str = "a master password typed key by key";
+
+        let db = Database::open(":memory:", &DatabaseOptions::default())?;
+        let mut result = Ok(());
+
+        let leaks = leaked_copies(SECRET, || {
+            let mut new_item = NewItemState::new(<Theme as Default>::default(), SizeLimits::default());
+
+            new_item.input_mut(NewItemField::Label).insert_str("Leaky");
+            SECRET.chars().for_each(|c| new_item.input_mut(NewItemField::Secret).insert_char(c));
+            PASSWORD.chars().for_each(|c| new_item.input_mut(NewItemField::EncPass).insert_char(c));
+            new_item.input_mut(NewItemField::Confirm).insert_str("a typo");
+
+            result = new_item.validate(&db);
+            assert!(matches!(new_item.start_encryption(None), Err(Error::ConfirmPasswordMismatch)));
+        });
+        result?;
+        assert_eq!(leaks, 0);
+
+        // nor does a wrong password in the password entry, or the retry
+        let leaks = leaked_copies(PASSWORD, || {
+            let action = PasswordAction::Secret(SecretAction::CopyToClipboard);
+            let mut passwd_entry = PasswordEntryState::new(action, <Theme as Default>::default());
+
+            PASSWORD.chars().for_each(|c| passwd_entry.enc_pass.insert_char(c));
+            passwd_entry.reject(1);
+            PASSWORD.chars().for_each(|c| passwd_entry.enc_pass.insert_char(c));
+        });
+        assert_eq!(leaks, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn qr_code_of_tagged_items_is_a_uri() -> Result<()> {
+        let mut harness = Harness::new()?;
+        harness.seed()?;
+
+        let find = |harness: &Harness, label: &str| {
+            harness.state.items.iter().find(|item| item.label == label).expect("item is seeded").uid
+        };
+        let cottage = find(&harness, "Wi-Fi at the cottage");
+        let webmail = find(&harness, "Webmail");
+
+        // untagged items are encoded as they are
+        assert_eq!(*harness.state.qr_payload(cottage, "Wi-Fi at the cottage", "hunter2")?, "hunter2");
+
+        harness.state.db.tag_item(cottage, WIFI_TAG)?;
+        harness.state.db.tag_item(webmail, TOTP_TAG)?;
+        harness.state.sync_data(true)?;
+
+        // without an account, the label is the network name
+        let state = &harness.state;
+        assert_eq!(
+            *state.qr_payload(cottage, "Wi-Fi at the cottage", "hunter2")?,
+            "WIFI:S:Wi-Fi at the cottage;T:WPA;P:hunter2;;",
+        );
+        assert_eq!(
+            *state.qr_payload(webmail, "Webmail", "JBSWY3DPEHPK3PXP")?,
+            "otpauth://totp/Webmail:bob?secret=JBSWY3DPEHPK3PXP\
+             &issuer=Webmail&algorithm=SHA1&digits=6&period=30",
+        );
+
+        // complete URIs are kept, and invalid seeds are rejected
+        let uri = "otpauth://totp/Other:carol?secret=JBSWY3DPEHPK3PXP";
+        assert_eq!(*state.qr_payload(webmail, "Webmail", uri)?, uri);
+        assert!(matches!(state.qr_payload(webmail, "Webmail", "hunter1!"), Err(Error::InvalidOtpAuthUri(_))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn qr_image_without_code_leaves_the_secret_alone() -> Result<()> {
+        let mut harness = Harness::new()?;
+        let dir = crate::perms::create_temp_dir("steelsafe-tui-qr")?;
+        let path = dir.join("screenshot.png");
+        image::GrayImage::from_pixel(64, 64, image::Luma([255])).save(&path)?;
+
+        harness.run(
+            ScriptedEvents::default()
+                .type_text("nWebmail")
+                .key(KeyCode::Tab)
+                .key(KeyCode::Tab)
+                .type_text(path.to_str().expect("temp path is UTF-8"))
+                .ctrl('o')
+        )?;
+
+        let new_item = harness.state.new_item.as_ref().expect("dialog stays open");
+        assert_eq!(new_item.focused(), NewItemField::Secret);
+        assert_eq!(&*new_item.value(NewItemField::Secret), path.to_str().unwrap());
+        assert!(harness.screen().contains("No QR code found in the image"));
+
+        std::fs::remove_dir_all(&dir)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn selected_item_is_written_to_ssitem_file() -> Result<()> {
+        let mut harness = Harness::new()?;
+        harness.seed()?;
+
+        let dir = std::env::temp_dir().join(format!("steelsafe-tui-export-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        harness.state.export_dir = dir.clone();
+
+        let selected = harness.state.items[0].clone();
+        harness.run(ScriptedEvents::default().type_text("1w"))?;
+        harness.assert_no_error();
+
+        let path = harness.state.exported_to.clone().expect("export is reported");
+        let item = crate::ssitem::read_item_file(&path)?;
+        assert_eq!(item.label.as_str(), selected.label);
+        assert_eq!(item.account.as_deref(), selected.account.as_deref());
+
+        // the notice blocks the table until dismissed
+        harness.run(ScriptedEvents::default().type_text("i"))?;
+        assert!(harness.state.stats.is_none());
+        harness.run(ScriptedEvents::default().key(KeyCode::Esc))?;
+        assert!(harness.state.exported_to.is_none());
+
+        // the existing file is not overwritten
+        harness.run(ScriptedEvents::default().type_text("w"))?;
+        assert!(harness.state.popup_error.is_some());
+
+        std::fs::remove_dir_all(&dir)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn marked_items_are_written_to_encrypted_bundle() -> Result<()> {
+        let mut harness = Harness::new()?;
+        harness.seed()?;
+
+        let dir = std::env::temp_dir().join(format!("steelsafe-tui-bundle-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        harness.state.export_dir = dir.clone();
+
+        // Space marks the item and moves on to the next one
+        harness.run(ScriptedEvents::default().type_text("1 ").key(KeyCode::Down).type_text(" "))?;
+        harness.assert_no_error();
+        assert_eq!(harness.state.marked.len(), 2);
+        assert!(harness.screen().contains("2 selected"));
+
+        // mistyping the confirmation exports nothing
+        harness.run(
+            ScriptedEvents::default()
+                .type_text("wsecret")
+                .key(KeyCode::Enter)
+                .type_text("typo")
+                .key(KeyCode::Enter)
+        )?;
+        assert!(matches!(harness.state.popup_error, Some(Error::ConfirmPasswordMismatch)));
+        assert!(harness.state.exported_to.is_none());
+        harness.run(ScriptedEvents::default().key(KeyCode::Esc))?;
+
+        harness.run(
+            ScriptedEvents::default()
+                .type_text("wsecret")
+                .key(KeyCode::Enter)
+                .type_text("secret")
+                .key(KeyCode::Enter)
+        )?;
+        harness.assert_no_error();
+        assert!(harness.state.marked.is_empty());
+        assert!(harness.screen().contains("Items exported"));
+
+        let path = harness.state.exported_to.clone().expect("export is reported");
+        let reader = std::io::BufReader::new(std::fs::File::open(&path)?);
+        let bundle = crate::format::Bundle::read_from(reader, || Ok("secret".to_owned().into()))?;
+        let labels: Vec<&str> = bundle.items.iter().map(|item| item.label.as_str()).collect();
+        assert_eq!(labels, ["My Bank", "Wi-Fi at the cottage"]);
+
+        std::fs::remove_dir_all(&dir)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn find_panel_counts_the_matches() -> Result<()> {
+        let mut harness = Harness::new()?;
+        harness.seed()?;
+
+        harness.run(ScriptedEvents::default().type_text("/"))?;
+        assert!(harness.screen().contains("3 matches"));
+        assert!(!harness.screen().contains("No items match"));
 
-        Ok(ControlFlow::Break(()))
+        harness.run(ScriptedEvents::default().type_text("xyzzy"))?;
+        harness.assert_no_error();
+        assert!(harness.labels().is_empty());
+        assert!(harness.screen().contains("0 matches"));
+        assert!(harness.screen().contains("No items match"));
+
+        // the placeholder goes away along with the search
+        harness.run(ScriptedEvents::default().key(KeyCode::Esc))?;
+        assert!(!harness.screen().contains("No items match"));
+
+        Ok(())
     }
 
-    /// Reloads the contents of the database from disk to memory.
-    /// If `adjust_selection` is set, the last item of the table
-    /// will be selected. This is useful after certain operations
-    /// that act destructively on the table state (e.g., search).
-    fn sync_data(&mut self, adjust_selection: bool) -> Result<()> {
-        let search_term = self.find.as_ref().and_then(|find_state| {
-            find_state
-                .search_term
-                .lines()
-                .first()
-                .map(|line| format!("%{}%", line.trim()))
-        });
-        self.items = self.db.list_items_for_display(search_term.as_deref())?;
+    #[test]
+    fn paging_moves_the_selection_and_the_viewport_together() -> Result<()> {
+        // 10 rows of items fit between the borders and the header
+        let mut harness = Harness::with_config(&Config::default(), 80, 13)?;
+        harness.seed_many(50)?;
+        harness.run(ScriptedEvents::default().key(KeyCode::Home))?;
+        assert_eq!(harness.state.table_state.selected(), Some(0));
 
-        #[allow(unused_parens)]
-        if (
-            adjust_selection
-            &&
-            !self.items.is_empty()
-            &&
-            !self.table_state.selected().is_some_and(|idx| idx < self.items.len())
-        ) {
-            self.table_state.select_last();
-        }
+        harness.run(ScriptedEvents::default().key(KeyCode::PageDown))?;
+        assert_eq!(harness.state.table_state.selected(), Some(10));
+        assert_eq!(harness.state.table_state.offset(), 10);
+        assert!(harness.screen().contains("Item 10") && !harness.screen().contains("Item 09"));
+
+        harness.run(ScriptedEvents::default().ctrl('d'))?;
+        assert_eq!(harness.state.table_state.selected(), Some(15));
+        assert_eq!(harness.state.table_state.offset(), 15);
+
+        harness.run(ScriptedEvents::default().ctrl('u').key(KeyCode::PageUp))?;
+        assert_eq!(harness.state.table_state.selected(), Some(0));
+        assert_eq!(harness.state.table_state.offset(), 0);
+
+        // the last page is full, and paging past the end stops at the last item
+        harness.run(ScriptedEvents::default().type_text("G").key(KeyCode::PageDown))?;
+        assert_eq!(harness.state.table_state.selected(), Some(49));
+        assert_eq!(harness.state.table_state.offset(), 40);
+        assert!(harness.screen().contains("Item 49"));
+
+        harness.run(ScriptedEvents::default().type_text("g"))?;
+        assert_eq!(harness.state.table_state.selected(), Some(0));
+        assert_eq!(harness.state.table_state.offset(), 0);
+
+        harness.run(ScriptedEvents::default().key(KeyCode::End))?;
+        assert_eq!(harness.state.table_state.selected(), Some(49));
 
         Ok(())
     }
 
-    /// Actually copy the decrypted plaintext secret to the clipboard.
-    /// We can't zeroize the clipboard content, so we don't even bother.
-    fn copy_secret_to_clipboard(&mut self, enc_pass: &str) -> Result<()> {
-        let index = self.table_state.selected().ok_or(Error::SelectionRequired)?;
-        let uid = self.items[index].uid;
-        let item = self.db.item_by_id(uid)?;
-
-        let input = DecryptionInput {
-            encrypted_secret: &item.encrypted_secret,
-            kdf_salt: item.kdf_salt,
-            auth_nonce: item.auth_nonce,
-            label: item.label.as_str(),
-            account: item.account.as_deref(),
-            last_modified_at: item.last_modified_at,
+    #[test]
+    fn the_scrollbar_shows_and_moves_the_viewport() -> Result<()> {
+        let mouse = |kind, column, row| {
+            Event::Mouse(MouseEvent { kind, column, row, modifiers: KeyModifiers::NONE })
+        };
+        let thumb_rows = |harness: &Harness| -> Vec<usize> {
+            harness.screen()
+                .lines()
+                .enumerate()
+                .filter(|(_, line)| line.ends_with('█'))
+                .map(|(row, _)| row)
+                .collect()
         };
-        let plaintext_secret = input.decrypt_and_verify(enc_pass.as_bytes())?;
 
-        // we do NOT use `String::from_utf8()`, because that would copy the
-        // bytes, and complicate correct zeroization of the secret on error.
-        let secret_str = std::str::from_utf8(&plaintext_secret)?;
+        // everything fits, so there is no scrollbar
+        let mut harness = Harness::with_config(&Config::default(), 80, 13)?;
+        harness.seed()?;
+        harness.run(&mut ScriptedEvents::default())?;
+        assert!(harness.state.scrollbar_area.is_none());
+        assert!(thumb_rows(&harness).is_empty());
 
-        self.clipboard.set_text(secret_str).map_err(Into::into)
-    }
+        // 10 of 50 items are shown, so the thumb is 2 rows long, next to the rows of items
+        let mut harness = Harness::with_config(&Config::default(), 80, 13)?;
+        harness.seed_many(50)?;
+        harness.run(ScriptedEvents::default().key(KeyCode::Home))?;
+        assert_eq!(thumb_rows(&harness), [2, 3]);
 
-    /// The main table has focus when none of the other widgets do.
-    fn main_table_has_focus(&self) -> bool {
-        (
-            self.find.is_none()
-            ||
-            self.find.as_ref().is_some_and(|find| !find.has_focus)
-        )
-        && self.passwd_entry.is_none()
-        && self.new_item.is_none()
-        && self.popup_error.is_none()
-    }
-}
+        harness.run(ScriptedEvents::default().key(KeyCode::End))?;
+        assert_eq!(thumb_rows(&harness), [10, 11]);
 
-#[derive(Debug)]
-struct PasswordEntryState {
-    is_visible: bool,
-    enc_pass: TextArea<'static>,
-    theme: Theme,
-}
+        // clicking elsewhere does nothing, not even a redraw
+        let click = mouse(MouseEventKind::Down(MouseButton::Left), 40, 6);
+        harness.state.handle_events(ScriptedEvents::default().push(click));
+        assert!(!harness.state.needs_redraw());
+        assert_eq!(harness.state.table_state.selected(), Some(49));
 
-impl PasswordEntryState {
-    fn with_theme(theme: Theme) -> Self {
-        let mut enc_pass = TextArea::default();
-        enc_pass.set_style(theme.default());
+        // clicking the top of the track scrolls to the top; dragging follows the mouse
+        harness.run(ScriptedEvents::default().push(mouse(MouseEventKind::Down(MouseButton::Left), 79, 2)))?;
+        assert_eq!(harness.state.table_state.selected(), Some(0));
+        assert_eq!(thumb_rows(&harness), [2, 3]);
 
-        // set up text field style
-        let mut state = PasswordEntryState {
-            is_visible: false,
-            enc_pass,
-            theme,
-        };
-        state.set_visible(false);
-        state
-    }
+        harness.run(ScriptedEvents::default().push(mouse(MouseEventKind::Drag(MouseButton::Left), 75, 6)))?;
+        assert_eq!(harness.state.table_state.selected(), Some(22));
+        assert_eq!(harness.state.table_state.offset(), 17);
+        assert!(harness.screen().contains("Item 22"));
 
-    fn toggle_show_enc_pass(&mut self) {
-        self.set_visible(!self.is_visible);
+        // dragging past the end stops at the last item
+        harness.run(ScriptedEvents::default().push(mouse(MouseEventKind::Drag(MouseButton::Left), 79, 12)))?;
+        assert_eq!(harness.state.table_state.selected(), Some(49));
+        assert_eq!(harness.state.table_state.offset(), 40);
+
+        // once the button is released, dragging doesn't scroll
+        harness.run(ScriptedEvents::default()
+            .push(mouse(MouseEventKind::Up(MouseButton::Left), 79, 12))
+            .push(mouse(MouseEventKind::Drag(MouseButton::Left), 79, 2)))?;
+        assert_eq!(harness.state.table_state.selected(), Some(49));
+
+        Ok(())
     }
 
-    fn set_visible(&mut self, is_visible: bool) {
-        self.is_visible = is_visible;
+    #[test]
+    fn the_panel_goes_where_configured_and_fits_any_terminal() -> Result<()> {
+        let config = Config {
+            panel: PanelConfig { position: PanelPosition::Top, height: 4 },
+            ..Config::default()
+        };
+        let mut harness = Harness::with_config(&config, 80, 13)?;
+        harness.seed()?;
+        harness.run(ScriptedEvents::default().type_text("fbank"))?;
 
-        if self.is_visible {
-            self.enc_pass.clear_mask_char();
-        } else {
-            self.enc_pass.set_mask_char('●');
+        let screen = harness.screen();
+        let lines: Vec<&str> = screen.lines().collect();
+        assert!(lines[1].contains("bank"));
+        assert!(lines[4].contains("SteelSafe"));
+        assert!(lines[6].contains("My Bank"));
+
+        // the panel takes up the screen if it's too short for both, and nothing breaks
+        for height in 0..6 {
+            let mut harness = Harness::with_config(&Config::default(), 80, height)?;
+            harness.seed()?;
+            harness.run(ScriptedEvents::default().type_text("fweb").key(KeyCode::Enter))?;
+            harness.run(ScriptedEvents::default().key(KeyCode::PageDown))?;
+            assert_eq!(harness.state.table_state.selected(), Some(0));
         }
 
-        let show_hide_title = format!(
-            " <^H> {} password ",
-            if self.is_visible { "Hide" } else { "Show" },
-        );
+        Ok(())
+    }
 
-        self.enc_pass.set_block(
-            Block::bordered()
-                .title(" Enter decryption (master) password ")
-                .title_bottom(" <Enter> OK ")
-                .title_bottom(" <Esc> Cancel ")
-                .title_bottom(show_hide_title)
-                .border_type(BorderType::Rounded)
-                .border_style(self.theme.border().add_modifier(Modifier::BOLD))
-        );
+    #[test]
+    fn the_new_item_dialog_scrolls_to_the_focused_field() -> Result<()> {
+        // 3 of the 5 fields fit
+        let mut harness = Harness::with_config(&Config::default(), 80, 12)?;
+        harness.run(ScriptedEvents::default().type_text("n"))?;
+
+        let screen = harness.screen();
+        assert!(screen.contains("Title or label") && screen.contains("Secret (to be stored)"));
+        assert!(!screen.contains("Encryption (master) password"));
+
+        // moving down scrolls as little as needed
+        harness.run(ScriptedEvents::default().key(KeyCode::Tab).key(KeyCode::Tab).key(KeyCode::Tab))?;
+        let screen = harness.screen();
+        assert!(!screen.contains("Title or label"));
+        assert!(screen.contains("Username or account") && screen.contains("Encryption (master) password"));
+
+        // moving back up doesn't scroll until the focus leaves the visible fields
+        harness.run(ScriptedEvents::default().key(KeyCode::Up).key(KeyCode::Up))?;
+        assert!(harness.screen().contains("Encryption (master) password"));
+        harness.run(ScriptedEvents::default().key(KeyCode::Up))?;
+        let screen = harness.screen();
+        assert!(screen.contains("Title or label") && !screen.contains("Encryption (master) password"));
+
+        // wrapping around to the last field scrolls to the bottom
+        harness.run(ScriptedEvents::default().key(KeyCode::Up))?;
+        assert!(harness.screen().contains("Confirm master password"));
+        assert_eq!(harness.state.new_item.as_ref().map(|new_item| new_item.form.first_visible()), Some(2));
+
+        Ok(())
     }
-}
 
-#[derive(Debug)]
-struct FindItemState {
-    search_term: TextArea<'static>,
-    has_focus: bool,
-    theme: Theme,
-}
+    #[test]
+    fn new_items_are_selected_where_the_sort_order_puts_them() -> Result<()> {
+        let config = Config {
+            sort: SortOrder { by: SortKey::Account, then_by: SortKey::Label },
+            ..Config::default()
+        };
+        let mut harness = Harness::with_config(&config, 120, 40)?;
+        harness.seed()?;
+        assert_eq!(harness.labels(), ["My Bank", "Webmail", "Wi-Fi at the cottage"]);
 
-impl FindItemState {
-    fn with_theme(theme: Theme) -> Self {
-        let mut search_term = TextArea::default();
+        // same account as "Webmail", but an earlier label
+        harness.add_item("archive", "bob", "hunter2", "master password")?;
+        harness.assert_no_error();
+        assert_eq!(harness.labels(), ["My Bank", "archive", "Webmail", "Wi-Fi at the cottage"]);
+        assert_eq!(harness.state.table_state.selected(), Some(1));
 
-        search_term.set_block(
-            Block::bordered()
-                .title(" Search term ")
-                .title_bottom(" <Enter> Focus secrets ")
-                .title_bottom(" <Esc> Exit search ")
-                .border_type(BorderType::Rounded)
+        // items without an account come last, then by label
+        harness.add_item("Vacation photos", "", "correct horse", "master password")?;
+        harness.assert_no_error();
+        assert_eq!(
+            harness.labels(),
+            ["My Bank", "archive", "Webmail", "Vacation photos", "Wi-Fi at the cottage"],
         );
+        assert_eq!(harness.state.table_state.selected(), Some(3));
 
-        let mut state = FindItemState {
-            search_term,
-            has_focus: true,
-            theme,
-        };
-        state.set_focus(true);
-        state
+        // reloading keeps the selected item selected, even if it has moved
+        harness.state.table_state.select(Some(4));
+        harness.state.sort = SortOrder { by: SortKey::Modified, then_by: SortKey::Label };
+        harness.state.sync_data(false)?;
+        assert_eq!(harness.labels()[2..], ["Wi-Fi at the cottage", "Webmail", "My Bank"]);
+        assert_eq!(harness.state.table_state.selected(), Some(2));
+
+        Ok(())
     }
 
-    fn set_focus(&mut self, has_focus: bool) {
-        self.has_focus = has_focus;
+    #[test]
+    fn typing_after_apostrophe_jumps_to_label() -> Result<()> {
+        let mut harness = Harness::new()?;
+        harness.seed()?;
+        assert_eq!(harness.labels(), ["My Bank", "Webmail", "Wi-Fi at the cottage"]);
 
-        let block = self.search_term.block().cloned().unwrap_or_default();
+        // the letters select items instead of performing their commands
+        harness.run(ScriptedEvents::default().type_text("1'w"))?;
+        assert_eq!(harness.state.table_state.selected(), Some(1));
+        harness.run(ScriptedEvents::default().type_text("I"))?;
+        assert_eq!(harness.state.table_state.selected(), Some(2));
+        assert!(harness.screen().contains("Jump to: wI"));
+        assert!(harness.state.exported_to.is_none());
+        assert!(harness.state.stats.is_none());
 
-        if self.has_focus {
-            self.search_term.set_style(self.theme.default().add_modifier(Modifier::BOLD));
-            self.search_term.set_block(
-                block.border_style(self.theme.border().add_modifier(Modifier::BOLD))
-            )
-        } else {
-            self.search_term.set_style(self.theme.default());
-            self.search_term.set_block(
-                block.border_style(self.theme.border())
-            )
-        }
+        harness.run(ScriptedEvents::default().key(KeyCode::Backspace))?;
+        assert_eq!(harness.state.table_state.selected(), Some(1));
+
+        // no match leaves the selection alone
+        harness.run(ScriptedEvents::default().type_text("xyz"))?;
+        assert_eq!(harness.state.table_state.selected(), Some(1));
+
+        // after the timeout, keys are commands again
+        harness.state.type_ahead.as_mut().expect("type-ahead is pending").last_key_at -= TYPE_AHEAD_TIMEOUT;
+        harness.run(ScriptedEvents::default().type_text("i"))?;
+        assert!(harness.state.type_ahead.is_none());
+        assert!(harness.state.stats.is_some());
+        assert!(!harness.screen().contains("Jump to:"));
+
+        Ok(())
     }
-}
 
-#[derive(Debug)]
-struct NewItemState {
-    label: TextArea<'static>,
-    account: TextArea<'static>,
-    secret: TextArea<'static>,
-    enc_pass: TextArea<'static>,
-    confirm: TextArea<'static>,
-    focused: FocusedTextArea,
-    show_secret: bool,
-    show_enc_pass: bool,
-    theme: Theme,
-}
+    #[test]
+    fn idle_screen_is_neither_redrawn_nor_polled_often() -> Result<()> {
+        let mut harness = Harness::new()?;
+        harness.seed()?;
+        harness.run(&mut ScriptedEvents::default())?;
 
-impl NewItemState {
-    fn with_theme(theme: Theme) -> Self {
-        let mut state = NewItemState {
-            label: TextArea::default(),
-            account: TextArea::default(),
-            secret: TextArea::default(),
-            enc_pass: TextArea::default(),
-            confirm: TextArea::default(),
-            focused: FocusedTextArea::default(),
-            show_secret: false,
-            show_enc_pass: false,
-            theme,
-        };
+        // no input, nothing in progress: nothing to draw
+        harness.state.handle_events(&mut ScriptedEvents::default());
+        assert!(!harness.state.needs_redraw());
+        assert_eq!(harness.state.poll_timeout(false), PollConfig::default().active_interval());
 
-        // set initial styles
-        state.set_show_secret(false);
-        state.set_show_enc_pass(false);
+        harness.state.last_input_at -= PollConfig::default().idle_after();
+        assert_eq!(harness.state.poll_timeout(false), PollConfig::default().idle_interval());
 
-        let props = [
-            ("Title or label",               true),
-            ("Username or account",          false),
-            ("Secret (to be stored)",        true),
-            ("Encryption (master) password", true),
-            ("Confirm master password",      true),
-        ];
-        let border_style = state.theme.border_highlight();
-
-        for (ta, (title, required)) in state.text_areas_mut().into_iter().zip(props) {
-            ta.set_block(
-                Block::bordered()
-                    .title(format!(" {title} "))
-                    .border_type(BorderType::Rounded)
-                    .border_style(border_style)
-            );
-            ta.set_placeholder_text(if required { "Required" } else { "Optional" });
-        }
+        // any input marks the screen as changed, and ends the idle period
+        harness.state.handle_events(ScriptedEvents::default().type_text("j"));
+        assert!(harness.state.needs_redraw());
+        assert_eq!(harness.state.poll_timeout(false), PollConfig::default().active_interval());
+        harness.run(&mut ScriptedEvents::default())?;
+        assert!(!harness.state.needs_redraw());
 
-        state.set_focused_text_area(FocusedTextArea::default());
-        state
-    }
+        // a running task keeps checking often
+        assert_eq!(harness.state.poll_timeout(true), PollConfig::default().active_interval());
 
-    fn text_areas(&self) -> Vec<&TextArea<'static>> {
-        vec![
-            &self.label,
-            &self.account,
-            &self.secret,
-            &self.enc_pass,
-            &self.confirm,
-        ]
+        Ok(())
     }
 
-    fn text_areas_mut(&mut self) -> Vec<&mut TextArea<'static>> {
-        vec![
-            &mut self.label,
-            &mut self.account,
-            &mut self.secret,
-            &mut self.enc_pass,
-            &mut self.confirm,
-        ]
-    }
+    #[test]
+    fn only_changes_are_redrawn() -> Result<()> {
+        let mut harness = Harness::new()?;
+        harness.seed()?;
+        harness.run(&mut ScriptedEvents::default())?;
 
-    fn focused_text_area(&mut self) -> &mut TextArea<'static> {
-        match self.focused {
-            FocusedTextArea::Label   => &mut self.label,
-            FocusedTextArea::Account => &mut self.account,
-            FocusedTextArea::Secret  => &mut self.secret,
-            FocusedTextArea::EncPass => &mut self.enc_pass,
-            FocusedTextArea::Confirm => &mut self.confirm,
+        // events that don't change anything on the screen
+        let moved = MouseEvent { kind: MouseEventKind::Moved, column: 3, row: 4, modifiers: KeyModifiers::NONE };
+        let mut ignored = ScriptedEvents::default();
+        ignored.push(Event::FocusGained).push(Event::FocusLost).push(Event::Mouse(moved));
+
+        while !ignored.is_empty() {
+            harness.state.handle_events(&mut ignored);
+            assert!(!harness.state.needs_redraw());
         }
-    }
 
-    fn set_focused_text_area(&mut self, which: FocusedTextArea) {
-        self.focused = which;
+        harness.state.handle_events(ScriptedEvents::default().push(Event::Resize(100, 30)));
+        assert!(harness.state.needs_redraw());
 
-        let highlight_style = self.theme.highlight();
+        // a pending Ctrl+C shows a hint, which disappears once it's no longer pending
+        harness.run(ScriptedEvents::default().ctrl('c'))?;
+        assert!(harness.screen().contains("<^C> Quit"));
+        let last_interrupt = harness.state.last_interrupt.expect("Ctrl+C is pending");
+        assert_eq!(harness.state.redraw_at, Some(last_interrupt + INTERRUPT_WINDOW));
 
-        for ta in self.text_areas_mut() {
-            if let Some(block) = ta.block() {
-                ta.set_block(block.clone().style(highlight_style));
-            }
-        }
+        harness.state.handle_events(&mut ScriptedEvents::default());
+        assert!(!harness.state.needs_redraw());
 
-        let ta = self.focused_text_area();
+        // as if the time had passed
+        harness.state.last_interrupt = Some(last_interrupt - INTERRUPT_WINDOW);
+        harness.state.redraw_at = Some(last_interrupt);
+        harness.state.handle_events(&mut ScriptedEvents::default());
+        assert!(harness.state.needs_redraw());
+        harness.draw_if_needed()?;
+        assert!(!harness.screen().contains("<^C> Quit"));
+        assert_eq!(harness.state.redraw_at, None);
 
-        if let Some(block) = ta.block() {
-            ta.set_block(block.clone().style(highlight_style.add_modifier(Modifier::BOLD)));
-        }
+        Ok(())
     }
 
-    fn cycle_forward(&mut self) {
-        self.set_focused_text_area(self.focused.next());
+    #[test]
+    fn spinner_frames_are_timed_by_the_clock() {
+        assert_eq!(until_next_multiple(Duration::ZERO, SPINNER_STEP), SPINNER_STEP);
+        assert_eq!(
+            until_next_multiple(SPINNER_STEP * 2 + Duration::from_millis(10), SPINNER_STEP),
+            SPINNER_STEP - Duration::from_millis(10),
+        );
+        assert_eq!(until_next_multiple(Duration::from_millis(2_500), Duration::from_secs(1)), Duration::from_millis(500));
     }
 
-    fn cycle_back(&mut self) {
-        self.set_focused_text_area(self.focused.prev());
-    }
+    #[test]
+    fn palette_runs_the_command_of_the_key() -> Result<()> {
+        let mut harness = Harness::new()?;
+        harness.seed()?;
 
-    fn set_show_secret(&mut self, flag: bool) {
-        self.show_secret = flag;
+        // typing into the palette doesn't trigger the keys of the table
+        harness.run(ScriptedEvents::default().ctrl('p').type_text("stats"))?;
+        assert!(harness.state.palette.is_some());
+        assert!(harness.state.stats.is_none());
 
-        if flag {
-            self.secret.clear_mask_char();
-        } else {
-            self.secret.set_mask_char('●');
-        }
+        harness.run(ScriptedEvents::default().key(KeyCode::Enter))?;
+        harness.assert_no_error();
+        assert!(harness.state.palette.is_none());
+        assert!(harness.state.stats.is_some());
+
+        // nothing happens if no command matches
+        harness.run(ScriptedEvents::default().key(KeyCode::Esc).ctrl('p').type_text("xyzzy").key(KeyCode::Enter))?;
+        harness.assert_no_error();
+        assert!(harness.state.palette.is_none());
+        assert!(harness.state.is_running());
+
+        Ok(())
     }
 
-    fn set_show_enc_pass(&mut self, flag: bool) {
-        self.show_enc_pass = flag;
+    #[test]
+    fn stats_count_weak_and_reused_secrets_once_unlocked() -> Result<()> {
+        let mut harness = Harness::new()?;
 
-        if flag {
-            self.enc_pass.clear_mask_char();
-            self.confirm.clear_mask_char();
-        } else {
-            self.enc_pass.set_mask_char('●');
-            self.confirm.set_mask_char('●');
-        }
+        harness.add_item("My Bank", "alice", "hunter2", "master password")?;
+        harness.add_item("Webmail", "bob", "hunter2", "master password")?;
+        harness.add_item("Cloud", "carol", "vU7#qP2!xK9@mW4$", "master password")?;
+        harness.add_item("Other vault", "dave", "hunter3", "another password")?;
+        harness.assert_no_error();
+
+        harness.run(ScriptedEvents::default().type_text("i"))?;
+        assert!(harness.screen().contains("[U] Check secrets"));
+        assert!(harness.state.secret_audit.is_none());
+
+        harness.run(ScriptedEvents::default().type_text("u"))?;
+        assert!(harness.state.passwd_entry.is_some());
+        assert!(harness.state.stats.is_some());
+
+        harness.run(ScriptedEvents::default().type_text("master password").key(KeyCode::Enter))?;
+        harness.assert_no_error();
+
+        let audit = harness.state.secret_audit.expect("secrets were checked");
+        assert_eq!((audit.weak, audit.reused, audit.skipped), (2, 2, 1));
+
+        let screen = harness.screen();
+        assert!(screen.contains("Secrets not decrypted"));
+        assert!(!screen.contains("[U] Check secrets"));
+
+        // the result is forgotten when the statistics are opened again
+        harness.run(ScriptedEvents::default().key(KeyCode::Esc).type_text("i"))?;
+        assert!(harness.state.secret_audit.is_none());
+
+        Ok(())
     }
 
-    fn toggle_show_secret(&mut self) {
-        self.set_show_secret(!self.show_secret);
+    #[test]
+    fn smart_filters_restrict_the_table() -> Result<()> {
+        let mut harness = Harness::new()?;
+        harness.seed()?;
+        let total = harness.state.items.len();
+        let without_account = harness.state.items.iter().filter(|item| item.account.is_none()).count();
+
+        // the first filter is "No account"
+        harness.run(ScriptedEvents::default().type_text("l").key(KeyCode::Down).key(KeyCode::Enter))?;
+        harness.assert_no_error();
+        assert!(harness.state.filter_menu.is_none());
+        assert_eq!(harness.state.items.len(), without_account);
+        assert!(harness.state.items.iter().all(|item| item.account.is_none()));
+
+        // the menu opens at the active filter, and "All items" is right above it
+        harness.run(ScriptedEvents::default().type_text("l").key(KeyCode::Up).key(KeyCode::Enter))?;
+        harness.assert_no_error();
+        assert_eq!(harness.state.smart_filter, None);
+        assert_eq!(harness.state.items.len(), total);
+
+        Ok(())
     }
 
-    fn toggle_show_enc_pass(&mut self) {
-        self.set_show_enc_pass(!self.show_enc_pass);
+    #[test]
+    fn privacy_mode_hides_names_in_the_table() -> Result<()> {
+        let mut harness = Harness::new()?;
+        harness.seed()?;
+        harness.run(ScriptedEvents::default().type_text("h"))?;
+        harness.assert_no_error();
+
+        let screen = harness.screen();
+        assert!(screen.contains("Privacy mode"));
+        assert!(screen.contains("My …") && screen.contains("Wi-…"));
+        assert!(!screen.contains("Webmail") && !screen.contains("alice") && !screen.contains("bob"));
+
+        harness.run(ScriptedEvents::default().type_text("h"))?;
+
+        let screen = harness.screen();
+        assert!(!screen.contains("Privacy mode"));
+        assert!(screen.contains("Webmail") && screen.contains("alice@example.com"));
+
+        Ok(())
     }
 
-    fn generate_random_password(&mut self) {
-        let password = crate::crypto::generate_password();
-        self.secret.select_all();
-        self.secret.insert_str(password.as_str());
+    #[test]
+    fn startup_warnings_block_input_until_dismissed() -> Result<()> {
+        let mut harness = Harness::new()?;
+        harness.state.show_findings(vec![Finding::NoColors, Finding::BackupOverdue { last: None }]);
+
+        // the keypress doesn't open the new item dialog
+        harness.run(ScriptedEvents::default().type_text("n"))?;
+        assert!(harness.state.new_item.is_none());
+        assert_eq!(harness.state.findings.len(), 2);
+
+        harness.run(ScriptedEvents::default().key(KeyCode::Esc).type_text("n"))?;
+        harness.assert_no_error();
+        assert!(harness.state.findings.is_empty());
+        assert!(harness.state.new_item.is_some());
+
+        Ok(())
     }
 
-    fn add_item(self, db: &Database) -> Result<Item> {
-        let label = match self.label.lines() {
-            [line] if !line.trim().is_empty() => line.trim(),
-            _ => return Err(Error::LabelRequired),
-        };
-        let account = match self.account.lines() {
-            [] => None,
-            [line] => if line.trim().is_empty() { None } else { Some(line.trim()) },
-            _ => return Err(Error::AccountNameSingleLine),
-        };
+    #[test]
+    fn recovery_codes_are_ticked_off() -> Result<()> {
+        let mut harness = Harness::new()?;
 
-        // Steal the contents of the secret and wrap it in a `Zeroizing`, so
-        // that it's cleared upon drop (even if an error occurs).
-        let secret_lines = Zeroizing::new(self.secret.into_lines());
-        let secret = match secret_lines.as_slice() {
-            [] => return Err(Error::SecretRequired),
-            [line] if line.is_empty() => return Err(Error::SecretRequired),
-            lines => Zeroizing::new(lines.join("\n")),
-        };
+        harness.run(
+            ScriptedEvents::default()
+                .type_text("bGitHub")
+                .key(KeyCode::Tab)
+                .key(KeyCode::Tab)
+                .type_text("aaaa-1111 bbbb-2222")
+                .key(KeyCode::Tab)
+                .type_text("master password")
+                .key(KeyCode::Tab)
+                .type_text("master password")
+                .key(KeyCode::Enter)
+        )?;
+        harness.assert_no_error();
 
-        // Do the same to the encryption password.
-        let mut enc_pass_lines = Zeroizing::new(self.enc_pass.into_lines());
-        let enc_pass = match enc_pass_lines.as_mut_slice() {
-            [line] if !line.is_empty() => Zeroizing::new(mem::take(line)),
-            _ => return Err(Error::EncryptionPasswordRequired),
+        let uid = harness.state.items[0].uid;
+        let counts = |harness: &Harness| {
+            let codes = harness.state.recovery_codes[&uid];
+            (codes.remaining, codes.total)
         };
+        assert_eq!(counts(&harness), (2, 2));
 
-        let confirm_pass_lines = Zeroizing::new(self.confirm.into_lines());
-        let confirm_pass = Zeroizing::new(confirm_pass_lines.join("\n"));
+        // codes are used up in order, from the details view
+        harness.run(ScriptedEvents::default().type_text(".u").type_text("master password").key(KeyCode::Enter))?;
+        harness.assert_no_error();
+        assert!(harness.state.details.is_some());
+        assert_eq!(counts(&harness), (1, 2));
+        assert!(harness.screen().contains("1 of 2 left"));
 
-        if enc_pass != confirm_pass {
-            return Err(Error::ConfirmPasswordMismatch);
-        }
+        harness.run(ScriptedEvents::default().type_text("u").type_text("master password").key(KeyCode::Enter))?;
+        assert_eq!(counts(&harness), (0, 2));
+        assert_eq!(harness.copied(), [
+            ("aaaa-1111".to_owned(), Selection::Clipboard),
+            ("bbbb-2222".to_owned(), Selection::Clipboard),
+        ]);
 
-        let encryption_input = EncryptionInput {
-            plaintext_secret: secret.as_bytes(),
-            label,
-            account,
-            last_modified_at: Utc::now(),
-        };
-        let encryption_output = encryption_input.encrypt_and_authenticate(enc_pass.as_bytes())?;
+        harness.run(ScriptedEvents::default().type_text("u"))?;
+        assert!(matches!(harness.state.popup_error, Some(Error::NoRecoveryCodesLeft)));
 
-        db.add_item(AddItemInput {
-            uid: nanosql::Null, // generate fresh unique ID
-            label,
-            account,
-            last_modified_at: encryption_input.last_modified_at,
-            encrypted_secret: encryption_output.encrypted_secret.as_slice(),
-            kdf_salt: encryption_output.kdf_salt,
-            auth_nonce: encryption_output.auth_nonce,
-        })
+        Ok(())
     }
-}
 
-#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
-enum FocusedTextArea {
-    #[default]
-    Label,
-    Account,
-    Secret,
-    EncPass,
-    Confirm,
-}
+    #[test]
+    fn expiry_column_marks_due_items() -> Result<()> {
+        let mut harness = Harness::new()?;
+        harness.seed()?;
 
-impl FocusedTextArea {
-    fn next(self) -> Self {
-        use FocusedTextArea::*;
+        let now = Utc::now();
+        let expiries = [("My Bank", now - TimeDelta::days(1)), ("Webmail", now + TimeDelta::days(3))];
 
-        match self {
-            Label   => Account,
-            Account => Secret,
-            Secret  => EncPass,
-            EncPass => Confirm,
-            Confirm => Label,
+        for (label, expires_at) in expiries {
+            let uid = harness.state.items.iter().find(|item| item.label == label).unwrap().uid;
+            harness.state.item_expiries.insert(uid, expires_at);
         }
+
+        let cells: Vec<_> = harness.state.items
+            .iter()
+            .map(|item| (item.label.as_str(), harness.state.cell_text(item, ColumnKind::Expiry)))
+            .collect();
+        let date = |time: DateTime<Utc>| time.format("%Y-%m-%d").to_string();
+
+        assert!(cells.contains(&("My Bank", format!("{} [expired]", date(expiries[0].1)))));
+        assert!(cells.contains(&("Webmail", format!("{} [expiring]", date(expiries[1].1)))));
+        assert!(cells.contains(&("Wi-Fi at the cottage", String::new())));
+
+        Ok(())
     }
 
-    fn prev(self) -> Self {
-        use FocusedTextArea::*;
+    #[cfg(unix)]
+    #[test]
+    fn ctrl_z_requests_suspension_once() -> Result<()> {
+        let mut harness = Harness::new()?;
 
-        match self {
-            Label   => Confirm,
-            Account => Label,
-            Secret  => Account,
-            EncPass => Secret,
-            Confirm => EncPass,
+        // the dialog is left alone, so it's still there after resuming
+        harness.run(ScriptedEvents::default().type_text("n").ctrl('z'))?;
+        assert!(harness.state.new_item.is_some());
+        assert!(harness.state.take_suspend_request());
+        assert!(!harness.state.take_suspend_request());
+        assert!(harness.state.is_running());
+
+        Ok(())
+    }
+
+    #[test]
+    fn ctrl_c_closes_dialogs_then_quits() -> Result<()> {
+        let mut harness = Harness::new()?;
+
+        // the first Ctrl+C acts like Esc, and the footer says how to quit
+        harness.run(ScriptedEvents::default().type_text("n").ctrl('c'))?;
+        assert!(harness.state.new_item.is_none());
+        assert!(harness.state.is_running());
+        assert!(harness.screen().contains("<^C> Quit"));
+
+        // once it's no longer pending, it acts like Esc again
+        harness.state.last_interrupt = Some(Instant::now() - Duration::from_secs(3));
+        harness.run(ScriptedEvents::default().type_text("n").ctrl('c'))?;
+        assert!(harness.state.new_item.is_none());
+        assert!(harness.state.is_running());
+
+        // pressing it twice in quick succession quits, even from a dialog
+        harness.state.last_interrupt = None;
+        harness.run(ScriptedEvents::default().type_text("n").ctrl('c').ctrl('c'))?;
+        assert!(!harness.state.is_running());
+
+        Ok(())
+    }
+
+    /// The views of the snapshot tests, each set up by a script.
+    fn views() -> Vec<(&'static str, ScriptedEvents)> {
+        let mut new_item = ScriptedEvents::default();
+        new_item.type_text("nWebmail").key(KeyCode::Tab).type_text("bob").key(KeyCode::Enter);
+
+        let mut find = ScriptedEvents::default();
+        find.type_text("/bank").key(KeyCode::Enter);
+
+        vec![
+            ("main_table", ScriptedEvents::default()),
+            ("new_item", new_item),
+            ("find", find),
+        ]
+    }
+
+    /// Catches layout regressions, e.g. dialogs overflowing narrow terminals.
+    /// After an intentional change, review the new snapshots with `cargo insta review`.
+    #[test]
+    fn views_at_various_sizes() -> Result<()> {
+        for (width, height) in [(120, 40), (80, 24), (72, 20)] {
+            for (view, mut events) in views() {
+                let mut harness = Harness::with_config(&Config::default(), width, height)?;
+                harness.seed()?;
+                harness.run(&mut events)?;
+                insta::assert_snapshot!(format!("{view}_{width}x{height}"), harness.terminal.backend());
+            }
+
+            let mut harness = Harness::with_config(&Config::default(), width, height)?;
+            harness.seed()?;
+            harness.state.popup_error = Some(Error::ItemNotFound("Webmail".into()));
+            harness.run(&mut ScriptedEvents::default())?;
+            insta::assert_snapshot!(format!("error_{width}x{height}"), harness.terminal.backend());
         }
+
+        Ok(())
     }
-}
 
-/// The sole purpose of this is to implement `Debug` so that it doesn't break literally everything.
-struct ClipboardDebugWrapper(Clipboard);
+    #[test]
+    fn errors_are_shown_over_the_lock_screen() -> Result<()> {
+        let config = Config { lock_on_focus_loss: FocusLock::Keypress, ..Config::default() };
+        let mut harness = Harness::with_config(&config, 80, 24)?;
+        harness.seed()?;
+        harness.run(ScriptedEvents::default().push(Event::FocusLost))?;
+        harness.state.popup_error = Some(Error::ItemNotFound("Webmail".into()));
+        harness.state.request_redraw();
+        harness.run(&mut ScriptedEvents::default())?;
 
-impl Debug for ClipboardDebugWrapper {
-    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
-        formatter.debug_struct("Clipboard").finish_non_exhaustive()
+        assert!(harness.screen().contains("No item is labelled"));
+        assert!(!harness.screen().contains("My Bank"));
+        insta::assert_snapshot!("locked_error_80x24", harness.terminal.backend());
+
+        Ok(())
     }
-}
 
-impl Deref for ClipboardDebugWrapper {
-    type Target = Clipboard;
+    /// Wide characters take up two cells and combining marks none, yet the
+    /// borders of the dialogs must stay in the same columns as usual.
+    #[test]
+    fn wide_characters_in_dialogs() -> Result<()> {
+        let label = "日本語のウェブサイト";
+        let (width, height) = (72, 20);
+        let borders_line_up = |harness: &Harness| {
+            let buffer = harness.terminal.backend().buffer();
+            (0..height).all(|y| {
+                "│╭╰".contains(buffer[(0, y)].symbol()) && "│╮╯".contains(buffer[(width - 1, y)].symbol())
+            })
+        };
+
+        let mut harness = Harness::with_config(&Config::default(), width, height)?;
+        harness.seed()?;
+        harness.run(
+            ScriptedEvents::default()
+                .type_text("n")
+                .type_text(label)
+                .key(KeyCode::Tab)
+                .type_text("cafe\u{301}")
+                .key(KeyCode::Enter)
+        )?;
+        insta::assert_snapshot!("new_item_wide_72x20", harness.terminal.backend());
+        assert!(borders_line_up(&harness));
+
+        let mut harness = Harness::with_config(&Config::default(), width, height)?;
+        harness.seed()?;
+        harness.state.popup_error = Some(Error::ItemNotFound(label.into()));
+        harness.run(&mut ScriptedEvents::default())?;
+        insta::assert_snapshot!("error_wide_72x20", harness.terminal.backend());
+        assert!(borders_line_up(&harness));
 
-    fn deref(&self) -> &Self::Target {
-        &self.0
+        Ok(())
     }
-}
 
-impl DerefMut for ClipboardDebugWrapper {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.0
+    /// The text looks the same with every theme, so these snapshots include the styles.
+    #[test]
+    fn views_with_custom_theme() -> Result<()> {
+        let pair = |bg, fg| ColorPair { bg: Some(bg), fg: Some(fg) };
+        let config = Config {
+            theme: Theme {
+                default: pair(Color::White, Color::Black),
+                highlight: pair(Color::Blue, Color::White),
+                border: pair(Color::White, Color::DarkGray),
+                border_highlight: pair(Color::Blue, Color::LightBlue),
+                error: pair(Color::White, Color::Red),
+            },
+            ..Config::default()
+        };
+
+        for (view, mut events) in views() {
+            let mut harness = Harness::with_config(&config, 80, 24)?;
+            harness.seed()?;
+            harness.run(&mut events)?;
+            insta::assert_debug_snapshot!(format!("{view}_custom_theme"), harness.terminal.backend().buffer());
+        }
+
+        Ok(())
     }
 }
@@ -0,0 +1,62 @@
+//! Building blocks shared by the views and dialogs of the TUI.
+
+pub mod forms;
+
+use ratatui::{
+    symbols,
+    widgets::{Scrollbar, ScrollbarOrientation},
+    crossterm::event::Event,
+};
+use tui_textarea::{TextArea, Input, Key};
+use crate::{
+    config::Theme,
+    i18n::tr,
+    text::{grapheme_len_before, grapheme_len_after},
+};
+
+
+/// Translates a title or a key hint, and pads it for display in a border.
+pub fn hint(msgid: &'static str) -> String {
+    format!(" {} ", tr(msgid))
+}
+
+/// Forwards the event to the text area, including pasted text, which arrives
+/// as a single event because bracketed paste is enabled. Returns `true` if the
+/// contents of the text area changed.
+///
+/// The text area moves the cursor and deletes by `char`, so Left, Right,
+/// Backspace and Delete are repeated for each `char` of the grapheme cluster
+/// next to the cursor, e.g. an accented letter or an emoji with a skin tone.
+pub fn input_text(text_area: &mut TextArea<'_>, event: Event) -> bool {
+    let input = match event {
+        Event::Paste(text) => return text_area.insert_str(text),
+        event => Input::from(event),
+    };
+    let count = {
+        let (row, col) = text_area.cursor();
+        let line = text_area.lines().get(row).map_or("", String::as_str);
+
+        match input {
+            Input { key: Key::Left | Key::Backspace, ctrl: false, alt: false, .. } => {
+                grapheme_len_before(line, col)
+            }
+            Input { key: Key::Right | Key::Delete, ctrl: false, alt: false, .. } => {
+                grapheme_len_after(line, col)
+            }
+            _ => 1,
+        }
+    };
+
+    // at either end of a line, the key joins or moves across lines, once
+    (0..count.max(1)).fold(false, |modified, _| text_area.input(input.clone()) | modified)
+}
+
+/// A scrollbar drawn over the right border of a bordered widget.
+pub fn scrollbar(theme: &Theme) -> Scrollbar<'static> {
+    Scrollbar::new(ScrollbarOrientation::VerticalRight)
+        .begin_symbol(None)
+        .end_symbol(None)
+        .track_symbol(Some(symbols::line::VERTICAL))
+        .track_style(theme.default())
+        .thumb_style(theme.border_highlight())
+}
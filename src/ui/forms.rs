@@ -0,0 +1,555 @@
+//! Forms: the dialogs (and parts of dialogs) in which values are typed, e.g.
+//! the New item dialog, or the new title of an item in the details view.
+//!
+//! A form is a column of bordered input fields, one of which has the focus.
+//! Each [`Field`] is described once, by its title, whether it's required, and
+//! its validator, and the [`Form`] takes care of the rest: Tab and the arrow
+//! keys move the focus around a ring, other events go to the focused field,
+//! the fields are styled according to the focus, and validation errors are
+//! shown right below the fields, but only once they have been edited, or once
+//! saving has been attempted, so that an empty form isn't covered in errors.
+//! If the fields don't all fit, they're scrolled so that the focused one is
+//! visible, with a scrollbar on the right border of the dialog.
+//!
+//! Validators check a field on its own. Rules that involve other fields, the
+//! database, or settings of the dialog are checked by the dialog itself, which
+//! reports violations with [`Form::set_error()`] after [`Form::validate()`].
+
+use std::mem;
+use std::cell::Cell;
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    text::Line,
+    style::{Style, Modifier},
+    widgets::{Widget, StatefulWidget, ScrollbarState, block::{Block, BorderType}},
+    crossterm::event::{Event, KeyCode},
+};
+use tui_textarea::TextArea;
+use zeroize::Zeroizing;
+use crate::{
+    config::Theme,
+    memlock::SecretString,
+    secret_input::SecretInput,
+    i18n::tr,
+    error::{Error, Result},
+};
+use super::{hint, scrollbar, input_text};
+
+
+/// The height of each field, including its borders.
+const FIELD_HEIGHT: u16 = 3;
+
+/// Checks the value of a field on its own.
+pub type Validator = fn(&str) -> Result<()>;
+
+/// Where the form is shown, which decides its colors.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Placement {
+    /// A dialog of its own, in the highlight colors, with the focused field in bold.
+    Dialog,
+    /// Below the contents of a modal, e.g. to rename what the modal shows, in its colors.
+    Embedded,
+}
+
+/// What is typed into a field.
+#[derive(Debug)]
+pub enum Input {
+    Text(TextArea<'static>),
+    Password(SecretInput),
+}
+
+impl Input {
+    /// A text area, pre-filled with `initial`.
+    pub fn text(initial: &str) -> Self {
+        let mut text_area = TextArea::default();
+        text_area.insert_str(initial);
+        Input::Text(text_area)
+    }
+
+    /// A text area for a secret, which doesn't keep copies of its contents
+    /// for undo. The lines are zeroized when the input is dropped. Only the
+    /// first line is preallocated; further lines, and the yank buffer of
+    /// Ctrl+K and friends, are beyond our control.
+    pub fn secret_text(capacity: usize) -> Self {
+        let mut text_area = TextArea::new(vec![String::with_capacity(capacity)]);
+        text_area.set_max_histories(0);
+        Input::Text(text_area)
+    }
+
+    /// A single-line, masked input for a password.
+    pub fn password(capacity: usize) -> Self {
+        Input::Password(SecretInput::new(capacity))
+    }
+
+    /// The value, with the lines of a text area joined by newlines.
+    pub fn value(&self) -> SecretString {
+        match self {
+            Input::Text(text_area) => SecretString::from_lines(text_area.lines()),
+            Input::Password(input) => SecretString::from(input.as_str()),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        match self {
+            Input::Text(text_area) => text_area.is_empty(),
+            Input::Password(input) => input.is_empty(),
+        }
+    }
+
+    pub fn insert_char(&mut self, c: char) {
+        match self {
+            Input::Text(text_area) => text_area.insert_char(c),
+            Input::Password(input) => input.insert_char(c),
+        }
+    }
+
+    pub fn insert_str(&mut self, text: &str) {
+        match self {
+            Input::Text(text_area) => {
+                text_area.insert_str(text);
+            }
+            Input::Password(input) => input.insert_str(text),
+        }
+    }
+
+    /// Replaces the whole value with `text`.
+    pub fn replace(&mut self, text: &str) {
+        match self {
+            Input::Text(text_area) => text_area.select_all(),
+            Input::Password(input) => input.clear(),
+        }
+        self.insert_str(text);
+    }
+
+    /// Shows the value, or masks it with bullets.
+    pub fn set_revealed(&mut self, revealed: bool) {
+        match self {
+            Input::Text(text_area) if revealed => text_area.clear_mask_char(),
+            Input::Text(text_area) => text_area.set_mask_char('●'),
+            Input::Password(input) => input.set_revealed(revealed),
+        }
+    }
+
+    /// Returns `true` if the value changed.
+    fn input(&mut self, event: Event) -> bool {
+        match self {
+            Input::Text(text_area) => input_text(text_area, event),
+            Input::Password(input) => input.input(event),
+        }
+    }
+
+    fn set_style(&mut self, style: Style, error_style: Style) {
+        match self {
+            Input::Text(text_area) => text_area.set_style(style),
+            Input::Password(input) => {
+                input.set_style(style);
+                input.set_error_style(error_style);
+            }
+        }
+    }
+
+    fn set_placeholder_text(&mut self, placeholder: &str) {
+        match self {
+            Input::Text(text_area) => text_area.set_placeholder_text(placeholder),
+            Input::Password(input) => input.set_placeholder_text(placeholder),
+        }
+    }
+}
+
+impl Drop for Input {
+    /// Steals the lines of text areas, and wraps them in a `Zeroizing`, so that
+    /// they are cleared however the form is closed. Passwords clear themselves.
+    fn drop(&mut self) {
+        if let Input::Text(text_area) = self {
+            drop(Zeroizing::new(mem::take(text_area).into_lines()));
+        }
+    }
+}
+
+/// A message shown below a field when it has no error.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Note {
+    /// E.g. what a pasted `otpauth://` URI is about.
+    Info(String),
+    /// Something to fix, but not necessarily, e.g. a violated password policy.
+    Warning(String),
+}
+
+/// A titled input field of a form.
+#[derive(Debug)]
+pub struct Field {
+    title: &'static str,
+    input: Input,
+    required: bool,
+    validator: Option<Validator>,
+    /// The localized message of the validation error, if any.
+    error: Option<String>,
+    note: Option<Note>,
+    /// Whether the field has been edited. Errors are only shown for edited
+    /// fields, or once saving has been attempted.
+    touched: bool,
+}
+
+impl Field {
+    /// An optional field without a validator. The title is a msgid.
+    pub fn new(title: &'static str, input: Input) -> Self {
+        Field {
+            title,
+            input,
+            required: false,
+            validator: None,
+            error: None,
+            note: None,
+            touched: false,
+        }
+    }
+
+    /// Marks the field as required in its placeholder. That it's not left
+    /// empty is still up to the validator to check, with a specific error.
+    pub fn required(mut self) -> Self {
+        self.required = true;
+        self
+    }
+
+    pub fn validator(mut self, validator: Validator) -> Self {
+        self.validator = Some(validator);
+        self
+    }
+
+    pub fn input(&self) -> &Input {
+        &self.input
+    }
+
+    /// Changes made directly to the input aren't validated until the next
+    /// call to `Form::validate()`.
+    pub fn input_mut(&mut self) -> &mut Input {
+        &mut self.input
+    }
+
+    pub fn value(&self) -> SecretString {
+        self.input.value()
+    }
+
+    pub fn set_title(&mut self, title: &'static str) {
+        self.title = title;
+    }
+
+    pub fn note(&self) -> Option<&Note> {
+        self.note.as_ref()
+    }
+
+    pub fn set_note(&mut self, note: Option<Note>) {
+        self.note = note;
+    }
+
+    /// Makes the error of the field visible, e.g. after filling it in on behalf of the user.
+    pub fn touch(&mut self) {
+        self.touched = true;
+    }
+
+    fn validate(&mut self) {
+        self.error = self
+            .validator
+            .and_then(|validator| validator(&self.value()).err())
+            .map(|error| error.localized());
+    }
+
+    fn render(&self, block: Block<'static>, area: Rect, buf: &mut Buffer) {
+        match &self.input {
+            Input::Text(text_area) => {
+                let inner = block.inner(area);
+                block.render(area, buf);
+                text_area.render(inner, buf);
+            }
+            Input::Password(input) => {
+                let block = input.decorate(block);
+                let inner = block.inner(area);
+                block.render(area, buf);
+                input.render(inner, buf);
+            }
+        }
+    }
+}
+
+/// A column of fields, one of which has the focus.
+#[derive(Debug)]
+pub struct Form {
+    fields: Vec<Field>,
+    focused: usize,
+    /// Whether saving has been attempted. Once it has, all errors are shown.
+    submitted: bool,
+    /// The first field shown, if they don't all fit. It's only known
+    /// while drawing, which doesn't have mutable access to the form.
+    first_visible: Cell<usize>,
+    placement: Placement,
+    theme: Theme,
+}
+
+impl Form {
+    /// Creates a form with the first field focused, and validates it.
+    pub fn new(mut fields: Vec<Field>, placement: Placement, theme: Theme) -> Self {
+        let style = match placement {
+            Placement::Dialog => Style::default(),
+            Placement::Embedded => theme.default(),
+        };
+
+        for field in &mut fields {
+            field.input.set_style(style, theme.error());
+            field.input.set_placeholder_text(tr(if field.required { "Required" } else { "Optional" }));
+        }
+
+        let mut form = Form {
+            fields,
+            focused: 0,
+            submitted: false,
+            first_visible: Cell::new(0),
+            placement,
+            theme,
+        };
+        form.validate();
+        form
+    }
+
+    /// The height of the form if every field is shown.
+    pub fn height(&self) -> u16 {
+        self.fields.len() as u16 * FIELD_HEIGHT
+    }
+
+    pub fn field(&self, index: usize) -> &Field {
+        &self.fields[index]
+    }
+
+    pub fn field_mut(&mut self, index: usize) -> &mut Field {
+        &mut self.fields[index]
+    }
+
+    pub const fn focused(&self) -> usize {
+        self.focused
+    }
+
+    pub fn focus(&mut self, index: usize) {
+        self.focused = index.min(self.fields.len().saturating_sub(1));
+    }
+
+    /// Moves the focus to the next field, wrapping around after the last one.
+    pub fn focus_next(&mut self) {
+        self.focus((self.focused + 1) % self.fields.len().max(1));
+    }
+
+    /// Moves the focus to the previous field, wrapping around before the first one.
+    pub fn focus_prev(&mut self) {
+        self.focus(self.focused.checked_sub(1).unwrap_or(self.fields.len().saturating_sub(1)));
+    }
+
+    /// The index of the first field shown when the form was last drawn.
+    pub fn first_visible(&self) -> usize {
+        self.first_visible.get()
+    }
+
+    /// Moves the focus upon Tab, Shift+Tab and the arrow keys, and forwards
+    /// other events to the focused field, which is validated if it changed.
+    /// Returns `true` if the value of the field changed.
+    pub fn input(&mut self, event: Event) -> bool {
+        match event {
+            Event::Key(key) if matches!(key.code, KeyCode::Down | KeyCode::Tab) => {
+                self.focus_next();
+                false
+            }
+            Event::Key(key) if matches!(key.code, KeyCode::Up | KeyCode::BackTab) => {
+                self.focus_prev();
+                false
+            }
+            event => {
+                let Some(field) = self.fields.get_mut(self.focused) else {
+                    return false;
+                };
+                let modified = field.input.input(event);
+
+                if modified {
+                    field.touched = true;
+                    field.validate();
+                }
+
+                modified
+            }
+        }
+    }
+
+    /// Runs the validator of every field. Errors set by `set_error()` are cleared.
+    pub fn validate(&mut self) {
+        self.fields.iter_mut().for_each(Field::validate);
+    }
+
+    /// Sets or clears the error of a field, e.g. one found by checking it against other fields.
+    pub fn set_error(&mut self, index: usize, error: Option<&Error>) {
+        self.fields[index].error = error.map(Error::localized);
+    }
+
+    /// Shows the error right below the offending field, and focuses it.
+    pub fn show_error(&mut self, index: usize, error: &Error) {
+        self.set_error(index, Some(error));
+        self.fields[index].touched = true;
+        self.focus(index);
+    }
+
+    /// Returns `true` if none of the fields has an error.
+    pub fn is_valid(&self) -> bool {
+        self.fields.iter().all(|field| field.error.is_none())
+    }
+
+    /// Returns `true` if anything has been typed into any of the fields.
+    pub fn is_dirty(&self) -> bool {
+        self.fields.iter().any(|field| !field.input.is_empty())
+    }
+
+    /// Reveals the errors of all fields, even the untouched ones, and focuses
+    /// the first invalid field, if any. Returns `true` if the form is valid.
+    pub fn submit(&mut self) -> bool {
+        self.submitted = true;
+
+        match self.fields.iter().position(|field| field.error.is_some()) {
+            Some(index) => {
+                self.focus(index);
+                false
+            }
+            None => true,
+        }
+    }
+
+    /// The border and the titles of a field, reflecting the focus,
+    /// and the error or the note below it, if any.
+    fn block(&self, index: usize) -> Block<'static> {
+        let field = &self.fields[index];
+        let (style, border_style, focused_modifier) = match self.placement {
+            Placement::Dialog => (self.theme.highlight(), self.theme.border_highlight(), Modifier::BOLD),
+            Placement::Embedded => (
+                Style::default(),
+                self.theme.border().add_modifier(Modifier::BOLD),
+                Modifier::empty(),
+            ),
+        };
+        let block = Block::bordered()
+            .title(hint(field.title))
+            .border_type(BorderType::Rounded)
+            .border_style(border_style)
+            .style(if index == self.focused { style.add_modifier(focused_modifier) } else { style });
+        let error = field.error.as_ref().filter(|_| self.submitted || field.touched);
+
+        match (error, field.note.as_ref()) {
+            (Some(message), _) | (None, Some(Note::Warning(message))) => {
+                block.title_bottom(Line::from(format!(" {message} ")).style(self.theme.error()))
+            }
+            (None, Some(Note::Info(message))) => block.title_bottom(format!(" {message} ")),
+            (None, None) => block,
+        }
+    }
+}
+
+/// The last column of the area is reserved for the scrollbar, so it should
+/// be the right border of the dialog. If the fields don't all fit, only as many
+/// as do are shown, scrolled so that the focused one is visible.
+impl Widget for &Form {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let count = self.fields.len();
+
+        if count == 0 {
+            return;
+        }
+
+        let fields_area = Rect { width: area.width.saturating_sub(1), ..area };
+        let visible = usize::from(area.height / FIELD_HEIGHT).clamp(1, count);
+        let first = scroll_into_view(self.first_visible.get(), self.focused, visible).min(count - visible);
+
+        self.first_visible.set(first);
+
+        for (row, index) in (0_u16..).zip(first..first + visible) {
+            let field_area = Rect { y: area.y + FIELD_HEIGHT * row, height: FIELD_HEIGHT, ..fields_area };
+            self.fields[index].render(self.block(index), field_area.intersection(fields_area), buf);
+        }
+
+        if visible < count {
+            let scrollbar_area = Rect {
+                x: area.right().saturating_sub(1),
+                width: area.width.min(1),
+                ..area
+            };
+            let mut scrollbar_state = ScrollbarState::new(count - visible + 1)
+                .position(first)
+                .viewport_content_length(visible);
+
+            scrollbar(&self.theme).render(scrollbar_area, buf, &mut scrollbar_state);
+        }
+    }
+}
+
+/// The first of the rows to show in a scrolled view, `visible` at a time,
+/// so that the row `focused` is shown, scrolling as little as possible
+/// from `first`, the first row shown previously.
+fn scroll_into_view(first: usize, focused: usize, visible: usize) -> usize {
+    if focused < first {
+        focused
+    } else if focused >= first + visible {
+        focused + 1 - visible
+    } else {
+        first
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+    use crate::config::Theme;
+    use crate::fields::Label;
+    use crate::error::Error;
+    use super::{Form, Field, Input, Placement};
+
+
+    fn key(code: KeyCode) -> Event {
+        Event::Key(KeyEvent::new(code, KeyModifiers::NONE))
+    }
+
+    #[test]
+    fn focus_wraps_around_and_errors_show_once_edited_or_submitted() {
+        let fields = vec![
+            Field::new("Title or label", Input::text(""))
+                .required()
+                .validator(|text| Label::new(text).map(drop)),
+            Field::new("Username or account", Input::text("")),
+            Field::new("Encryption (master) password", Input::password(16)).required(),
+        ];
+        let mut form = Form::new(fields, Placement::Dialog, <Theme as Default>::default());
+
+        assert!(!form.is_valid());
+        assert!(!form.is_dirty());
+
+        form.input(key(KeyCode::Up));
+        assert_eq!(form.focused(), 2);
+        form.input(key(KeyCode::Tab));
+        assert_eq!(form.focused(), 0);
+
+        // typing validates the field
+        assert!(form.input(key(KeyCode::Char('x'))));
+        assert!(form.is_valid());
+        assert!(form.is_dirty());
+        assert!(form.input(key(KeyCode::Backspace)));
+        assert!(!form.is_valid());
+        assert!(form.field(0).touched);
+
+        // rules involving several fields are up to the dialog
+        form.input(key(KeyCode::Char('x')));
+        form.set_error(1, Some(&Error::DuplicateLabel));
+        form.input(key(KeyCode::Down));
+        assert_eq!(form.focused(), 1);
+
+        // submitting reveals every error, and focuses the first invalid field
+        form.focus(2);
+        assert!(!form.submit());
+        assert_eq!(form.focused(), 1);
+        assert!(form.field(1).error.is_some());
+
+        form.validate();
+        assert!(form.submit());
+        assert_eq!(form.field(0).value().as_str(), "x");
+    }
+}
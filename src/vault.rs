@@ -0,0 +1,139 @@
+//! The storage of items, abstracted away from the user interface.
+//!
+//! `Vault` is everything the TUI needs from the storage: the items, with their
+//! encrypted secrets, and the metadata attached to them (tags, expiry dates,
+//! recovery codes, URLs, the access log). The helpers the TUI shares with the CLI,
+//! e.g. queries, expiry reports and git synchronization, depend only on it, too.
+//!
+//! The SQLite `Database` is the only implementation for now. Another backend,
+//! e.g. a single encrypted file or a remote server, has to implement this trait,
+//! and nothing else: secrets are encrypted and decrypted by the callers, so
+//! a backend only ever stores and returns ciphertext. Operations that are
+//! specific to a backend (e.g. per-vault settings) remain methods of `Database`,
+//! and are only used by the CLI. Statistics and compaction are shown in the TUI,
+//! so they are part of the trait, even if some backends have little to report.
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+use chrono::{DateTime, Utc, NaiveDateTime};
+use crate::{
+    db::{
+        Item, DisplayItem, AddItemInput, UpdateEncryptionInput, SmartFilter, AccessLogEntry,
+        TagCount, ExpiringItem, ItemRecoveryCodes, VaultStats, Compaction, OperationEntry,
+    },
+    crypto::EncryptionOutput,
+    oplog::Operation,
+    recovery::RecoveryCodes,
+    error::Result,
+};
+
+
+/// A storage backend of items. See the module documentation.
+///
+/// Adding an item whose label already exists must fail with `Error::DuplicateLabel`.
+/// Methods that change several rows must do so atomically: either every change is
+/// stored, or none of them.
+pub trait Vault: Debug {
+    /// The items whose label or account name contains the text, ignoring ASCII
+    /// case, or every item if the text is `None`. Every character of the text
+    /// is matched literally.
+    fn list_items_for_display(&self, search_text: Option<&str>) -> Result<Vec<DisplayItem>>;
+
+    /// The items matching one of the predefined filters, as of `now`.
+    fn items_by_filter(&self, filter: SmartFilter, now: DateTime<Utc>) -> Result<Vec<DisplayItem>>;
+
+    /// The item with the unique ID, including its encrypted secret.
+    fn item_by_id(&self, id: u64) -> Result<Item>;
+
+    /// Every item, including the encrypted secrets, in the order of their unique IDs.
+    fn all_items(&self) -> Result<Vec<Item>>;
+
+    /// Returns `true` if an item with exactly this label exists.
+    fn label_exists(&self, label: &str) -> Result<bool>;
+
+    /// A label for a quick note created at `created_at` (local time), that doesn't exist yet.
+    fn unused_note_label(&self, created_at: NaiveDateTime) -> Result<String>;
+
+    /// Stores a new item with an already-encrypted secret.
+    fn add_item(&self, input: AddItemInput<'_>) -> Result<Item>;
+
+    /// Replaces the encrypted secrets of several items, atomically.
+    fn update_encryption(&mut self, updates: Vec<UpdateEncryptionInput<'_>>) -> Result<()>;
+
+    /// Changes the label of the item, along with its secret re-encrypted with
+    /// the new label and modification date, atomically.
+    fn rename_item(
+        &mut self,
+        item_uid: u64,
+        label: &str,
+        modified_at: DateTime<Utc>,
+        output: &EncryptionOutput,
+    ) -> Result<()>;
+
+    /// Removes the item, along with its tags, expiry date, recovery codes, URL
+    /// and access log, atomically.
+    fn delete_item(&mut self, item_uid: u64) -> Result<()>;
+
+    /// Sets whether the password of the item must always be typed.
+    fn set_reprompt(&self, item_uid: u64, reprompt: bool) -> Result<()>;
+
+    /// The tags of every item that has any, keyed by the unique ID of the item.
+    fn tags_by_item(&self) -> Result<HashMap<u64, Vec<String>>>;
+
+    /// Every tag, along with the number of items it's attached to.
+    fn tag_counts(&self) -> Result<Vec<TagCount>>;
+
+    /// Attaches a tag to the item, unless it already has it.
+    fn tag_item(&self, item_uid: u64, tag: &str) -> Result<()>;
+
+    /// Renames a tag on every item, merging it with `new_name` if that exists.
+    fn rename_tag(&mut self, old_name: &str, new_name: &str) -> Result<()>;
+
+    /// Removes a tag from every item.
+    fn delete_tag(&self, name: &str) -> Result<()>;
+
+    /// The expiry date of every item that has one, keyed by its unique ID.
+    fn expiries(&self) -> Result<HashMap<u64, DateTime<Utc>>>;
+
+    /// The items expiring before `until`, soonest first.
+    fn expiring_items(&self, until: DateTime<Utc>) -> Result<Vec<ExpiringItem>>;
+
+    /// The recovery code counts of every item that has any, keyed by its unique ID.
+    fn recovery_codes(&self) -> Result<HashMap<u64, ItemRecoveryCodes>>;
+
+    /// Stores or (if `None`) removes the recovery code counts of the item.
+    fn set_recovery_codes(&self, item_uid: u64, codes: Option<&RecoveryCodes>) -> Result<()>;
+
+    /// The URL of every item that has one, keyed by its unique ID.
+    fn urls(&self) -> Result<HashMap<u64, String>>;
+
+    /// Records that the secret of the item was decrypted just now.
+    fn log_access(&self, item_uid: u64) -> Result<()>;
+
+    /// The recorded accesses of the item, most recent first.
+    fn access_history(&self, item_uid: u64) -> Result<Vec<AccessLogEntry>>;
+
+    /// The number of consecutive failed decryption attempts stored in the vault.
+    fn failed_attempts(&self) -> Result<u32>;
+
+    /// Stores the number of consecutive failed decryption attempts.
+    fn set_failed_attempts(&self, count: u32) -> Result<()>;
+
+    /// The operation log, in the order the operations were recorded. See `oplog`.
+    fn operations(&self) -> Result<Vec<OperationEntry>>;
+
+    /// Maps the replica-independent IDs of the items to their unique IDs.
+    fn item_ids(&self) -> Result<HashMap<String, u64>>;
+
+    /// Applies operations received from another replica, atomically, and records
+    /// them. Returns the labels of the items that couldn't be added or renamed,
+    /// because another item has their label.
+    fn apply_operations(&mut self, operations: &[Operation]) -> Result<Vec<String>>;
+
+    /// Statistics of the whole vault, with expiry and rotation as of `now`.
+    fn stats(&self, now: DateTime<Utc>) -> Result<VaultStats>;
+
+    /// Reclaims the space left behind by deleted and replaced data, if the
+    /// backend has such a thing. Otherwise, it's a no-op with unknown sizes.
+    fn compact(&self) -> Result<Compaction>;
+}
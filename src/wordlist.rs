@@ -0,0 +1,63 @@
+//! Checking secrets against a list of common or leaked passwords (e.g. the
+//! most frequent million of a breach corpus), entirely offline.
+//!
+//! The list is a text file with one password per line, and it may be huge,
+//! so it's streamed instead of being loaded into memory: only the secrets to
+//! be checked are kept, and each line of the list is looked up among them.
+
+use std::io::BufRead;
+use std::collections::{HashMap, BTreeSet};
+use zeroize::Zeroizing;
+use crate::error::Result;
+
+
+/// Returns the indices of the `secrets` that occur in the list read from
+/// `reader`, in increasing order. Lines are compared byte by byte, after
+/// removing the line break, so lists need not be valid UTF-8.
+pub fn find_listed<R: BufRead>(mut reader: R, secrets: &[&str]) -> Result<BTreeSet<usize>> {
+    let mut indices_by_secret: HashMap<&[u8], Vec<usize>> = HashMap::with_capacity(secrets.len());
+
+    for (index, secret) in secrets.iter().enumerate() {
+        indices_by_secret.entry(secret.as_bytes()).or_default().push(index);
+    }
+
+    // a line matching a secret _is_ the secret, so it must be wiped, too
+    let mut line = Zeroizing::new(Vec::new());
+    let mut listed = BTreeSet::new();
+
+    loop {
+        line.clear();
+
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+
+        let word = line.strip_suffix(b"\n").unwrap_or(&line);
+        let word = word.strip_suffix(b"\r").unwrap_or(word);
+
+        if let Some(indices) = indices_by_secret.get(word) {
+            listed.extend(indices);
+        }
+    }
+
+    Ok(listed)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use crate::error::Result;
+    use super::find_listed;
+
+
+    #[test]
+    fn finds_exact_matches_only() -> Result<()> {
+        let list = Cursor::new(b"123456\r\npassword\nhunter2\n\xff\xfe\nqwerty".as_slice());
+        let secrets = ["correct horse", "qwerty", "Password", "hunter2", "password1", "hunter2"];
+        let listed = find_listed(list, &secrets)?;
+
+        assert_eq!(listed.into_iter().collect::<Vec<_>>(), [1, 3, 5]);
+
+        Ok(())
+    }
+}